@@ -0,0 +1,117 @@
+#[macro_use]
+extern crate criterion;
+extern crate bytes;
+extern crate futures;
+extern crate tokio_core;
+
+extern crate tokio_kafka;
+
+use std::rc::Rc;
+use std::time::Duration;
+
+use bytes::{BigEndian, Bytes, BytesMut};
+use criterion::Criterion;
+use futures::{Future, Stream};
+use tokio_core::reactor::Core;
+
+use tokio_kafka::{Accumulator, ApiVersion, Compression, MessageSetEncoder, RecordAccumulator, Runtime, TopicPartition};
+
+/// The records pushed to the accumulator in each iteration.
+const RECORDS: usize = 1000;
+
+/// The size of the value of each record.
+const VALUE_SIZE: usize = 128;
+
+const COMPRESSIONS: &[Compression] = &[
+    Compression::None,
+    Compression::GZIP,
+    Compression::Snappy,
+    Compression::LZ4,
+];
+
+const BATCH_SIZES: &[usize] = &[4 * 1024, 16 * 1024, 64 * 1024, 256 * 1024];
+
+const API_VERSIONS: &[ApiVersion] = &[0, 1];
+
+fn records() -> Vec<Bytes> {
+    (0..RECORDS)
+        .map(|i| {
+            let mut value = format!("record #{} of the encoding benchmark, ", i).into_bytes();
+            let pattern = value.clone();
+
+            while value.len() < VALUE_SIZE {
+                value.extend_from_slice(&pattern);
+            }
+
+            value.truncate(VALUE_SIZE);
+
+            Bytes::from(value)
+        })
+        .collect()
+}
+
+/// Push the records to the accumulator, and encode all its batches as the produce request does.
+///
+/// Returns the encoded bytes of the batches.
+fn encode(api_version: ApiVersion, compression: Compression, batch_size: usize, records: &[Bytes]) -> usize {
+    let core = Core::new().unwrap();
+    let accumulator = RecordAccumulator::new(
+        batch_size,
+        compression,
+        Duration::from_millis(0),
+        RECORDS * VALUE_SIZE * 2,
+        Duration::from_millis(0),
+        Runtime::from(core.handle()),
+    );
+    let tp = TopicPartition {
+        topic_name: "topic".into(),
+        partition_id: 0,
+    };
+
+    for value in records {
+        drop(accumulator.push_record(tp.clone(), 0, None, Some(value.clone()), api_version));
+    }
+
+    let in_flight = accumulator.in_flight();
+    let encoder = MessageSetEncoder::new(api_version, None);
+    let mut buf = BytesMut::with_capacity(batch_size * 2);
+    let mut encoded = 0;
+
+    accumulator
+        .batches(true)
+        .for_each(|(tp, batch)| {
+            let (_, message_set) = batch.build()?;
+
+            buf.clear();
+            encoder.encode::<BigEndian>(&message_set, &mut buf)?;
+            encoded += buf.len();
+
+            in_flight.completed(&tp);
+
+            Ok(())
+        })
+        .wait()
+        .unwrap();
+
+    encoded
+}
+
+fn bench_encoding(c: &mut Criterion) {
+    let records = Rc::new(records());
+
+    for &api_version in API_VERSIONS {
+        for &compression in COMPRESSIONS {
+            for &batch_size in BATCH_SIZES {
+                let records = records.clone();
+
+                c.bench_function(
+                    &format!("encode v{} {:?} batch={}", api_version, compression, batch_size),
+                    move |b| b.iter(|| encode(api_version, compression, batch_size, &records)),
+                );
+            }
+        }
+    }
+}
+
+criterion_group!(benches, bench_encoding);
+criterion_main!(benches);
@@ -217,11 +217,11 @@ impl ProducerInterceptor for LogInterceptor {
         Ok(record)
     }
 
-    fn ack(&self, result: &tokio_kafka::Result<RecordMetadata>) {
-        debug!("acked {:?}", result);
+    fn on_acknowledgement(&self, md: &RecordMetadata, err: Option<&tokio_kafka::Error>) {
+        debug!("acked {:?}", md);
 
-        match *result {
-            Ok(ref md) => trace!(
+        match err {
+            None => trace!(
                 "sent to {} #{} @{}, ts={}, key_size={}, value_size={}",
                 md.topic_name,
                 md.partition_id,
@@ -230,7 +230,7 @@ impl ProducerInterceptor for LogInterceptor {
                 md.serialized_key_size,
                 md.serialized_value_size
             ),
-            Err(ref err) => warn!("fail to produce records, {}", err),
+            Some(err) => warn!("fail to produce records to {} #{}, {}", md.topic_name, md.partition_id, err),
         }
     }
 }
@@ -18,7 +18,8 @@ use getopts::Options;
 
 use futures::Future;
 use tokio_core::reactor::Core;
-use tokio_kafka::{Client, Cluster, FetchOffset, KafkaClient, KafkaVersion, ListedOffset, Metadata, TopicPartition};
+use tokio_kafka::{Client, Cluster, FetchOffset, KafkaClient, KafkaVersion, ListedOffset, Metadata, Offset,
+                  TopicPartition};
 
 const DEFAULT_BROKER: &'static str = "127.0.0.1:9092";
 
@@ -230,13 +231,18 @@ fn dump_metadata<'a>(
                             print!(" {1:0$}", host_width, format!("({}:{})", host, port));
                         }
 
-                        print!(" {:>12} {:>12}", offset.earliest().unwrap(), offset.latest().unwrap());
+                        // the partition may have no offset, e.g. it failed to be listed
+                        let show = |offset: Option<Offset>| offset.map_or_else(|| "-".to_owned(), |o| o.to_string());
+
+                        print!(" {:>12} {:>12}", show(offset.earliest()), show(offset.latest()));
 
                         if config.show_size {
-                            print!(
-                                " {:>12}",
-                                format!("({})", offset.latest().unwrap() - offset.earliest().unwrap())
-                            );
+                            let size = match (offset.earliest(), offset.latest()) {
+                                (Some(earliest), Some(latest)) => Some(latest - earliest),
+                                _ => None,
+                            };
+
+                            print!(" {:>12}", format!("({})", show(size)));
                         }
 
                         println!("")
@@ -0,0 +1,242 @@
+#[macro_use]
+extern crate error_chain;
+#[macro_use]
+extern crate log;
+extern crate futures;
+extern crate getopts;
+extern crate pretty_env_logger;
+
+extern crate tokio_kafka;
+
+use std::env;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process;
+use std::time::Duration;
+
+use futures::{Future, Stream};
+use getopts::Options;
+
+use tokio_kafka::{BlockingConsumer, Consumer, KafkaConsumer, KafkaVersion, OffsetResetStrategy, StringDeserializer};
+
+const DEFAULT_BROKER: &str = "127.0.0.1:9092";
+const DEFAULT_CLIENT_ID: &str = "console-consumer";
+const DEFAULT_TOPIC: &str = "my-topic";
+
+/// The milliseconds waiting for the records in each poll.
+const DEFAULT_POLL_TIMEOUT_MILLIS: u64 = 1000;
+
+error_chain!{
+    links {
+        KafkaError(tokio_kafka::Error, tokio_kafka::ErrorKind);
+    }
+    foreign_links {
+        IoError(::std::io::Error);
+        ArgError(::getopts::Fail);
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Config {
+    brokers: Vec<String>,
+    client_id: String,
+    api_version_request: bool,
+    broker_version: Option<KafkaVersion>,
+    topics: Vec<String>,
+    group_id: String,
+    from_beginning: bool,
+    no_commit: bool,
+    print_key: bool,
+    print_timestamp: bool,
+    max_messages: Option<usize>,
+    timeout: Option<Duration>,
+}
+
+impl Config {
+    fn parse_cmdline() -> Result<Self> {
+        let args: Vec<String> = env::args().collect();
+        let program = Path::new(&args[0]).file_name().unwrap().to_str().unwrap();
+        let mut opts = Options::new();
+
+        opts.optflag("h", "help", "print this help menu.");
+        opts.optopt(
+            "b",
+            "bootstrap-server",
+            "Bootstrap broker(s) (host[:port], comma separated).",
+            "HOSTS",
+        );
+        opts.optopt("", "client-id", "Specify the client id.", "ID");
+        opts.optopt(
+            "",
+            "broker-version",
+            "Specify broker versions [0.8.0, 0.8.1, 0.8.2, 0.9.0, auto].",
+            "VERSION",
+        );
+        opts.optopt("t", "topics", "The topics to consume on (comma separated).", "NAMES");
+        opts.optopt("g", "group-id", "Specify the consumer group.", "NAME");
+        opts.optflag(
+            "",
+            "from-beginning",
+            "Start with the earliest record if the group does not have a committed offset.",
+        );
+        opts.optflag("", "no-commit", "Do not commit group offsets.");
+        opts.optflag("", "print-key", "Print the key of records.");
+        opts.optflag("", "print-timestamp", "Print the timestamp of records.");
+        opts.optopt("n", "max-messages", "Exit after consuming N records.", "N");
+        opts.optopt("", "timeout", "Exit if no record is available for consumption in MS.", "MS");
+
+        let m = opts.parse(&args[1..])?;
+
+        if m.opt_present("h") {
+            let brief = format!("Usage: {} [options]", program);
+
+            print!("{}", opts.usage(&brief));
+
+            process::exit(0);
+        }
+
+        let (api_version_request, broker_version) = match m.opt_str("broker-version") {
+            Some(ref s) if s == "auto" => (true, None),
+            Some(s) => (false, Some(s.parse()?)),
+            None => (false, None),
+        };
+
+        Ok(Config {
+            brokers: m.opt_str("b").map_or_else(
+                || vec![DEFAULT_BROKER.to_owned()],
+                |s| s.split(',').map(|s| s.trim().to_owned()).collect(),
+            ),
+            client_id: m.opt_str("client-id").unwrap_or_else(|| DEFAULT_CLIENT_ID.to_owned()),
+            api_version_request,
+            broker_version,
+            topics: m.opt_str("t").map_or_else(
+                || vec![DEFAULT_TOPIC.to_owned()],
+                |s| s.split(',').map(|s| s.trim().to_owned()).collect(),
+            ),
+            group_id: m.opt_str("g")
+                .unwrap_or_else(|| format!("console-consumer-{}", process::id())),
+            from_beginning: m.opt_present("from-beginning"),
+            no_commit: m.opt_present("no-commit"),
+            print_key: m.opt_present("print-key"),
+            print_timestamp: m.opt_present("print-timestamp"),
+            max_messages: parse_opt(&m, "max-messages")?,
+            timeout: parse_opt(&m, "timeout")?.map(Duration::from_millis),
+        })
+    }
+}
+
+fn parse_opt<T: ::std::str::FromStr>(m: &getopts::Matches, name: &str) -> Result<Option<T>> {
+    match m.opt_str(name) {
+        Some(s) => s.parse()
+            .map(Some)
+            .map_err(|_| format!("invalid value of `--{}`: {}", name, s).into()),
+        None => Ok(None),
+    }
+}
+
+fn main() {
+    pretty_env_logger::init();
+
+    let config = match Config::parse_cmdline() {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}", err);
+
+            process::exit(2);
+        }
+    };
+
+    debug!("parsed config: {:?}", config);
+
+    if let Err(err) = run(config) {
+        eprintln!("fail to consume records, {}", err);
+
+        process::exit(1);
+    }
+}
+
+fn run(config: Config) -> Result<()> {
+    debug!("consume records from {:?}", config.brokers);
+
+    let mut consumer = {
+        let config = config.clone();
+
+        BlockingConsumer::spawn(move |handle| {
+            let mut builder = KafkaConsumer::with_bootstrap_servers(config.brokers, handle)
+                .with_client_id(config.client_id)
+                .with_group_id(config.group_id)
+                .with_auto_offset_reset(if config.from_beginning {
+                    OffsetResetStrategy::Earliest
+                } else {
+                    OffsetResetStrategy::Latest
+                })
+                .with_key_deserializer(StringDeserializer::default())
+                .with_value_deserializer(StringDeserializer::default());
+
+            if config.api_version_request {
+                builder = builder.with_api_version_request()
+            }
+            if let Some(version) = config.broker_version {
+                builder = builder.with_broker_version_fallback(version)
+            }
+            if config.no_commit {
+                builder = builder.without_auto_commit()
+            }
+
+            let mut consumer = builder.build()?;
+
+            Ok(consumer
+                .subscribe(config.topics)
+                .map(|topics| {
+                    topics.map(|record| {
+                        (
+                            record.timestamp.map(|ts| ts.to_string()).unwrap_or_default(),
+                            record.key.unwrap_or_default(),
+                            record.value.unwrap_or_default(),
+                        )
+                    })
+                })
+                .flatten_stream())
+        })
+    };
+
+    let poll_timeout = config
+        .timeout
+        .unwrap_or_else(|| Duration::from_millis(DEFAULT_POLL_TIMEOUT_MILLIS));
+    let stdout = io::stdout();
+    let mut consumed = 0;
+
+    while config.max_messages.map_or(true, |max| consumed < max) && !consumer.is_finished() {
+        let records = consumer.poll(poll_timeout)?;
+
+        if records.is_empty() && config.timeout.is_some() {
+            debug!("no record is available in {:?}", poll_timeout);
+
+            break;
+        }
+
+        let mut out = stdout.lock();
+
+        for (timestamp, key, value) in records {
+            if config.max_messages.map_or(false, |max| consumed >= max) {
+                break;
+            }
+
+            if config.print_timestamp {
+                write!(out, "{}\t", timestamp)?;
+            }
+            if config.print_key {
+                write!(out, "{}\t", key)?;
+            }
+            writeln!(out, "{}", value)?;
+
+            consumed += 1;
+        }
+
+        out.flush()?;
+    }
+
+    debug!("consumed {} records", consumed);
+
+    Ok(())
+}
@@ -0,0 +1,235 @@
+#[macro_use]
+extern crate error_chain;
+#[macro_use]
+extern crate log;
+extern crate futures;
+extern crate getopts;
+extern crate pretty_env_logger;
+
+extern crate tokio_kafka;
+
+use std::env;
+use std::io::{self, BufRead};
+use std::path::Path;
+use std::process;
+use std::time::Duration;
+
+use futures::{future, Future};
+use getopts::Options;
+
+use tokio_kafka::{BlockingProducer, Compression, KafkaProducer, KafkaVersion, ProducerRecord, RequiredAcks,
+                  SharedSendRecord, StringSerializer, DEFAULT_BATCH_SIZE, DEFAULT_DELIVERY_TIMEOUT_MILLIS,
+                  DEFAULT_LINGER_MILLIS};
+
+const DEFAULT_BROKER: &str = "127.0.0.1:9092";
+const DEFAULT_CLIENT_ID: &str = "console-producer";
+const DEFAULT_TOPIC: &str = "my-topic";
+
+/// The records sent without waiting for their acknowledgements.
+const MAX_PENDING_RECORDS: usize = 1024;
+
+error_chain!{
+    links {
+        KafkaError(tokio_kafka::Error, tokio_kafka::ErrorKind);
+    }
+    foreign_links {
+        IoError(::std::io::Error);
+        ArgError(::getopts::Fail);
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Config {
+    brokers: Vec<String>,
+    client_id: String,
+    api_version_request: bool,
+    broker_version: Option<KafkaVersion>,
+    topic_name: String,
+    key_separator: Option<String>,
+    batch_size: usize,
+    compression: Compression,
+    required_acks: RequiredAcks,
+    linger: Duration,
+    timeout: Duration,
+}
+
+impl Config {
+    fn parse_cmdline() -> Result<Self> {
+        let args: Vec<String> = env::args().collect();
+        let program = Path::new(&args[0]).file_name().unwrap().to_str().unwrap();
+        let mut opts = Options::new();
+
+        opts.optflag("h", "help", "print this help menu.");
+        opts.optopt(
+            "b",
+            "bootstrap-server",
+            "Bootstrap broker(s) (host[:port], comma separated).",
+            "HOSTS",
+        );
+        opts.optopt("", "client-id", "Specify the client id.", "ID");
+        opts.optopt(
+            "",
+            "broker-version",
+            "Specify broker versions [0.8.0, 0.8.1, 0.8.2, 0.9.0, auto].",
+            "VERSION",
+        );
+        opts.optopt("t", "topic", "Specify target topic.", "NAME");
+        opts.optopt(
+            "k",
+            "key-separator",
+            "Split each line into the key and value at the first separator.",
+            "SEP",
+        );
+        opts.optopt("n", "batch-size", "Send N bytes in one batch.", "N");
+        opts.optopt(
+            "c",
+            "compression",
+            "Compress messages [none, gzip, snappy, lz4].",
+            "TYPE",
+        );
+        opts.optopt(
+            "a",
+            "required-acks",
+            "Specify amount of required broker acknowledgments [none, one, all].",
+            "TYPE",
+        );
+        opts.optopt(
+            "",
+            "linger",
+            "The producer groups together any records in the linger timeout.",
+            "MS",
+        );
+        opts.optopt("", "timeout", "Specify time to wait for the pending records.", "MS");
+
+        let m = opts.parse(&args[1..])?;
+
+        if m.opt_present("h") {
+            let brief = format!("Usage: {} [options] < FILE", program);
+
+            print!("{}", opts.usage(&brief));
+
+            process::exit(0);
+        }
+
+        let (api_version_request, broker_version) = match m.opt_str("broker-version") {
+            Some(ref s) if s == "auto" => (true, None),
+            Some(s) => (false, Some(s.parse()?)),
+            None => (false, None),
+        };
+
+        Ok(Config {
+            brokers: m.opt_str("b").map_or_else(
+                || vec![DEFAULT_BROKER.to_owned()],
+                |s| s.split(',').map(|s| s.trim().to_owned()).collect(),
+            ),
+            client_id: m.opt_str("client-id").unwrap_or_else(|| DEFAULT_CLIENT_ID.to_owned()),
+            api_version_request,
+            broker_version,
+            topic_name: m.opt_str("topic").unwrap_or_else(|| DEFAULT_TOPIC.to_owned()),
+            key_separator: m.opt_str("key-separator"),
+            batch_size: parse_opt(&m, "batch-size", DEFAULT_BATCH_SIZE)?,
+            compression: m.opt_str("compression").map_or(Ok(Compression::default()), |s| s.parse())?,
+            required_acks: m.opt_str("required-acks").map_or(Ok(RequiredAcks::default()), |s| s.parse())?,
+            linger: Duration::from_millis(parse_opt(&m, "linger", DEFAULT_LINGER_MILLIS)?),
+            timeout: Duration::from_millis(parse_opt(&m, "timeout", DEFAULT_DELIVERY_TIMEOUT_MILLIS)?),
+        })
+    }
+}
+
+fn parse_opt<T: ::std::str::FromStr>(m: &getopts::Matches, name: &str, default: T) -> Result<T> {
+    match m.opt_str(name) {
+        Some(s) => s.parse().map_err(|_| format!("invalid value of `--{}`: {}", name, s).into()),
+        None => Ok(default),
+    }
+}
+
+fn main() {
+    pretty_env_logger::init();
+
+    let config = match Config::parse_cmdline() {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}", err);
+
+            process::exit(2);
+        }
+    };
+
+    debug!("parsed config: {:?}", config);
+
+    if let Err(err) = run(config) {
+        eprintln!("fail to produce records, {}", err);
+
+        process::exit(1);
+    }
+}
+
+fn run(config: Config) -> Result<()> {
+    debug!("produce records to {:?}", config.brokers);
+
+    let producer = {
+        let config = config.clone();
+
+        BlockingProducer::spawn(move |handle| {
+            let mut builder = KafkaProducer::with_bootstrap_servers(config.brokers, handle)
+                .with_client_id(config.client_id)
+                .with_required_acks(config.required_acks)
+                .with_compression(config.compression)
+                .with_batch_size(config.batch_size)
+                .with_linger(config.linger)
+                .with_key_serializer(StringSerializer::default())
+                .with_value_serializer(StringSerializer::default())
+                .with_default_partitioner();
+
+            if config.api_version_request {
+                builder = builder.with_api_version_request()
+            }
+            if let Some(version) = config.broker_version {
+                builder = builder.with_broker_version_fallback(version)
+            }
+
+            builder.build()
+        })?
+    };
+
+    let stdin = io::stdin();
+    let mut pending = Vec::new();
+
+    for line in stdin.lock().lines() {
+        let line = line?;
+        let line = line.trim();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        let record = match config
+            .key_separator
+            .as_ref()
+            .and_then(|sep| line.find(sep.as_str()).map(|off| (off, sep.len())))
+        {
+            Some((off, len)) => {
+                ProducerRecord::from_key_value(&config.topic_name, line[..off].to_owned(), line[off + len..].to_owned())
+            }
+            None => ProducerRecord::from_value(&config.topic_name, line.to_owned()),
+        };
+
+        pending.push(producer.shared().send(record));
+
+        if pending.len() >= MAX_PENDING_RECORDS {
+            wait_acked(&mut pending)?;
+        }
+    }
+
+    producer.flush_sync(config.timeout)?;
+
+    wait_acked(&mut pending)
+}
+
+fn wait_acked(pending: &mut Vec<SharedSendRecord>) -> Result<()> {
+    let acked = future::join_all(pending.drain(..).collect::<Vec<_>>()).wait()?;
+
+    trace!("{} records acked", acked.len());
+
+    Ok(())
+}
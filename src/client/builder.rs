@@ -1,11 +1,14 @@
 use std::marker::PhantomData;
 use std::ops::{Deref, DerefMut};
+use std::rc::Rc;
 use std::time::Duration;
 
 use tokio_core::reactor::Handle;
 
-use client::{ClientConfig, KafkaClient, KafkaVersion};
+use client::{report_metrics, ClientConfig, ClientListener, KafkaClient, KafkaVersion, MetricsReporter,
+             QueueFullBehavior, Runtime};
 use errors::{ErrorKind, Result};
+use network::{SaslMechanism, SharedTransport, Transport};
 use protocol::ToMilliseconds;
 
 /// A `KafkaClient` builder easing the process of setting up various
@@ -14,6 +17,8 @@ use protocol::ToMilliseconds;
 pub struct ClientBuilder<'a> {
     config: ClientConfig,
     handle: Option<Handle>,
+    runtime: Option<Runtime>,
+    reporter: Option<(Rc<MetricsReporter>, Duration)>,
     phantom: PhantomData<&'a u8>,
 }
 
@@ -37,6 +42,8 @@ impl<'a> ClientBuilder<'a> {
         ClientBuilder {
             config,
             handle: Some(handle),
+            runtime: None,
+            reporter: None,
             phantom: PhantomData,
         }
     }
@@ -54,6 +61,12 @@ impl<'a> ClientBuilder<'a> {
         self
     }
 
+    /// Sets the runtime to spawn the background futures and wait for the timeouts
+    pub fn with_runtime(mut self, runtime: Runtime) -> Self {
+        self.runtime = Some(runtime);
+        self
+    }
+
     /// Sets the id string to pass to the server when making requests.
     pub fn with_client_id(mut self, client_id: String) -> Self {
         self.config.client_id = Some(client_id);
@@ -66,6 +79,13 @@ impl<'a> ClientBuilder<'a> {
         self
     }
 
+    /// Sets the maximum number of unacknowledged requests the client will send on a single
+    /// connection before blocking.
+    pub fn with_max_in_flight_requests_per_connection(mut self, max_in_flight_requests: usize) -> Self {
+        self.config.max_in_flight_requests_per_connection = max_in_flight_requests;
+        self
+    }
+
     /// Sets the maximum amount of time the client will wait for the response
     /// of a request.
     pub fn with_request_timeout(mut self, request_timeout: Duration) -> Self {
@@ -93,11 +113,159 @@ impl<'a> ClientBuilder<'a> {
         self
     }
 
+    /// Sets the period of time in milliseconds after which the cached metadata of a topic is
+    /// considered stale
+    pub fn with_topic_metadata_refresh_interval(mut self, refresh_interval: Duration) -> Self {
+        self.config.topic_metadata_refresh_interval = refresh_interval.as_millis();
+        self
+    }
+
+    /// Sets the period of time after which the resolved addresses of a broker are resolved again
+    pub fn with_dns_refresh_interval(mut self, refresh_interval: Duration) -> Self {
+        self.config.dns_refresh_interval = refresh_interval.as_millis();
+        self
+    }
+
+    /// Sets the base amount of time to wait before attempting to reconnect to a broker
+    pub fn with_reconnect_backoff(mut self, backoff: Duration) -> Self {
+        self.config.reconnect_backoff = backoff.as_millis();
+        self
+    }
+
+    /// Sets the maximum amount of time to wait before attempting to reconnect to a broker
+    pub fn with_reconnect_backoff_max(mut self, backoff: Duration) -> Self {
+        self.config.reconnect_backoff_max = backoff.as_millis();
+        self
+    }
+
+    /// Sets whether to disable the Nagle's algorithm of the broker connections
+    pub fn with_socket_nodelay(mut self, nodelay: bool) -> Self {
+        self.config.socket_nodelay = nodelay;
+        self
+    }
+
+    /// Sets the size of the TCP send buffer of the broker connections
+    pub fn with_socket_send_buffer_bytes(mut self, bytes: usize) -> Self {
+        self.config.socket_send_buffer_bytes = Some(bytes);
+        self
+    }
+
+    /// Sets the size of the TCP receive buffer of the broker connections
+    pub fn with_socket_receive_buffer_bytes(mut self, bytes: usize) -> Self {
+        self.config.socket_receive_buffer_bytes = Some(bytes);
+        self
+    }
+
+    /// Sets the idle time before the TCP keepalive probes are sent on the broker connections
+    pub fn with_socket_keepalive(mut self, keepalive: Duration) -> Self {
+        self.config.socket_keepalive = Some(keepalive.as_millis());
+        self
+    }
+
+    /// Sets the maximum number of the pending requests to a single broker
+    pub fn with_queue_buffering_max_requests(mut self, max_requests: usize) -> Self {
+        self.config.queue_buffering_max_requests = max_requests;
+        self
+    }
+
+    /// Sets what to do with the new requests when the request queue of a broker is full
+    pub fn with_queue_full_behavior(mut self, behavior: QueueFullBehavior) -> Self {
+        self.config.queue_full_behavior = behavior;
+        self
+    }
+
     /// Sets to record metrics for client operations
     pub fn with_metrics(mut self) -> Self {
         self.config.metrics = true;
         self
     }
+
+    /// Sets to record metrics for client operations, and report them with the interval
+    pub fn with_metrics_reporter<R>(mut self, reporter: R, interval: Duration) -> Self
+    where
+        R: 'static + MetricsReporter,
+    {
+        self.config.metrics = true;
+        self.reporter = Some((Rc::new(reporter), interval));
+        self
+    }
+
+    /// Register a listener of the client events
+    pub fn with_listener<L>(mut self, listener: L) -> Self
+    where
+        L: 'static + ClientListener,
+    {
+        self.config.listeners.add(listener);
+        self
+    }
+
+    /// Sets the transport used to connect to the brokers instead of TCP
+    pub fn with_transport<T>(mut self, transport: T) -> Self
+    where
+        T: 'static + Transport,
+    {
+        self.config.transport = SharedTransport::new(transport);
+        self
+    }
+
+    /// Sets the topics whose leaders will be pre-connected at startup
+    pub fn with_warm_up_topics<I, S>(mut self, topic_names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.config.warm_up_topics = topic_names.into_iter().map(|s| s.into()).collect();
+        self
+    }
+
+    /// Sets the SASL/PLAIN credentials used to authenticate the connections
+    pub fn with_sasl_plain<S>(mut self, username: S, password: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.config.sasl_username = Some(username.into());
+        self.config.sasl_password = Some(password.into());
+        self
+    }
+
+    /// Sets the SASL mechanism to use for authentication
+    pub fn with_sasl_mechanism(mut self, mechanism: SaslMechanism) -> Self {
+        self.config.sasl_mechanism = mechanism;
+        self
+    }
+
+    /// Connect to the brokers with TLS/SSL
+    pub fn with_ssl(mut self) -> Self {
+        self.config.ssl_enabled = true;
+        self
+    }
+
+    /// Sets the CA certificate used to verify the brokers, and enable TLS/SSL
+    pub fn with_ssl_ca_location<S>(mut self, location: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.config.ssl_enabled = true;
+        self.config.ssl_ca_location = Some(location.into());
+        self
+    }
+
+    /// Sets the PKCS#12 archive with the client certificate and private key, and enable TLS/SSL
+    pub fn with_ssl_keystore<S>(mut self, location: S, password: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.config.ssl_enabled = true;
+        self.config.ssl_keystore_location = Some(location.into());
+        self.config.ssl_keystore_password = Some(password.into());
+        self
+    }
+
+    /// Skip verifying the broker hostname against its certificate
+    pub fn without_ssl_hostname_verification(mut self) -> Self {
+        self.config.ssl_hostname_verification = false;
+        self
+    }
 }
 
 impl<'a> ClientBuilder<'a>
@@ -107,6 +275,32 @@ where
     pub fn build(self) -> Result<KafkaClient<'a>> {
         let handle = self.handle.ok_or(ErrorKind::ConfigError("missed handle"))?;
 
-        Ok(KafkaClient::new(self.config, handle))
+        let runtime = self.runtime.unwrap_or_else(|| Runtime::from(handle.clone()));
+
+        let client = KafkaClient::new_with_runtime(self.config, handle, runtime.clone())?;
+
+        if let (Some((reporter, interval)), Some(metrics)) = (self.reporter, client.metrics()) {
+            runtime.spawn(report_metrics(runtime.clone(), Rc::downgrade(&metrics), reporter, interval));
+        }
+
+        Ok(client)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio_core::reactor::Core;
+
+    use super::*;
+
+    #[test]
+    fn test_build_with_invalid_ssl() {
+        let core = Core::new().unwrap();
+        let client = ClientBuilder::with_bootstrap_servers(vec!["127.0.0.1:9092".to_owned()], core.handle())
+            .with_ssl()
+            .with_ssl_ca_location("not-exists.pem")
+            .build();
+
+        assert!(client.is_err());
     }
 }
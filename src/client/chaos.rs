@@ -0,0 +1,239 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use std::ops::Deref;
+use std::rc::Rc;
+use std::time::Duration;
+
+use rand;
+
+use futures::{future, Future};
+use tokio_service::Service;
+
+use client::{AbortSignal, Abortable, Runtime, StaticBoxFuture, ToStaticBoxFuture};
+use errors::Error;
+use network::KafkaRequest;
+use protocol::{ApiKey, ApiKeys};
+
+/// The faults injected into the requests of an API.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Fault {
+    /// The additional latency before the response is returned.
+    pub latency: Option<Duration>,
+    /// The rate, between 0.0 and 1.0, of the requests that fail without being sent.
+    pub error_rate: f64,
+}
+
+impl Fault {
+    /// Inject the additional latency before the response is returned.
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = Some(latency);
+        self
+    }
+
+    /// Fail the requests at the rate, between 0.0 and 1.0.
+    pub fn with_error_rate(mut self, error_rate: f64) -> Self {
+        self.error_rate = error_rate;
+        self
+    }
+
+    fn should_fail(&self) -> bool {
+        self.error_rate > 0.0 && rand::random::<f64>() < self.error_rate
+    }
+}
+
+/// The faults injected into the requests per API key.
+///
+/// The faults could be changed on the fly, they are shared between the clones.
+#[derive(Clone, Debug, Default)]
+pub struct Chaos {
+    inner: Rc<RefCell<ChaosInner>>,
+}
+
+#[derive(Debug, Default)]
+struct ChaosInner {
+    faults: HashMap<ApiKey, Fault>,
+    all: Option<Fault>,
+}
+
+impl Chaos {
+    /// Inject the faults into the requests of the API.
+    pub fn inject(&self, api_key: ApiKeys, fault: Fault) {
+        self.inner.borrow_mut().faults.insert(api_key.key(), fault);
+    }
+
+    /// Inject the faults into the requests of all the APIs without a specified fault.
+    pub fn inject_all(&self, fault: Fault) {
+        self.inner.borrow_mut().all = Some(fault);
+    }
+
+    /// Stop injecting the faults into the requests of the API.
+    pub fn heal(&self, api_key: ApiKeys) {
+        self.inner.borrow_mut().faults.remove(&api_key.key());
+    }
+
+    /// Stop injecting any faults.
+    pub fn heal_all(&self) {
+        let mut inner = self.inner.borrow_mut();
+
+        inner.faults.clear();
+        inner.all = None;
+    }
+
+    /// Get the faults injected into the requests of the API.
+    pub fn fault(&self, api_key: ApiKey) -> Option<Fault> {
+        let inner = self.inner.borrow();
+
+        inner.faults.get(&api_key).or_else(|| inner.all.as_ref()).cloned()
+    }
+}
+
+/// Inject the latency and errors into the requests, for the resilience testing.
+pub struct ChaosMiddleware<S> {
+    upstream: S,
+    chaos: Chaos,
+    runtime: Runtime,
+}
+
+impl<S> ChaosMiddleware<S> {
+    pub fn new(upstream: S, chaos: Chaos, runtime: Runtime) -> ChaosMiddleware<S> {
+        ChaosMiddleware {
+            upstream,
+            chaos,
+            runtime,
+        }
+    }
+
+    pub fn chaos(&self) -> Chaos {
+        self.chaos.clone()
+    }
+}
+
+impl<S> Deref for ChaosMiddleware<S> {
+    type Target = S;
+
+    fn deref(&self) -> &Self::Target {
+        &self.upstream
+    }
+}
+
+impl<'a, S> Service for ChaosMiddleware<S>
+where
+    S: Service<Request = (SocketAddr, KafkaRequest<'a>), Error = Error>,
+    S::Response: 'static,
+    S::Future: 'static,
+{
+    type Request = S::Request;
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = StaticBoxFuture<S::Response, S::Error>;
+
+    fn call(&self, request: Self::Request) -> Self::Future {
+        self.inject(request, |request| self.upstream.call(request))
+    }
+}
+
+impl<'a, S> Abortable for ChaosMiddleware<S>
+where
+    S: Abortable<Request = (SocketAddr, KafkaRequest<'a>), Error = Error>,
+    S::Response: 'static,
+    S::Future: 'static,
+{
+    fn call_abortable(&self, request: Self::Request, abort: AbortSignal) -> Self::Future {
+        self.inject(request, |request| self.upstream.call_abortable(request, abort))
+    }
+}
+
+impl<'a, S> ChaosMiddleware<S>
+where
+    S: Service<Request = (SocketAddr, KafkaRequest<'a>), Error = Error>,
+    S::Response: 'static,
+    S::Future: 'static,
+{
+    /// Inject the fault of the API into the request, which is sent to the upstream with `send`.
+    fn inject<F>(&self, request: S::Request, send: F) -> StaticBoxFuture<S::Response, S::Error>
+    where
+        F: FnOnce(S::Request) -> S::Future,
+    {
+        let api_key = request.1.header().api_key;
+
+        let fault = match self.chaos.fault(api_key) {
+            Some(fault) => fault,
+            None => return send(request).static_boxed(),
+        };
+
+        if fault.should_fail() {
+            debug!("inject error into the request #{} to {}", api_key, request.0);
+
+            return future::err(io::Error::new(io::ErrorKind::Other, "injected fault").into()).static_boxed();
+        }
+
+        let response = send(request);
+
+        match fault.latency {
+            Some(latency) => {
+                let runtime = self.runtime.clone();
+
+                response
+                    .and_then(move |response| {
+                        trace!("inject {:?} latency into the response of request #{}", latency, api_key);
+
+                        runtime.sleep(latency).map(move |_| response)
+                    })
+                    .static_boxed()
+            }
+            None => response.static_boxed(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future::FutureResult;
+    use tokio_core::reactor::Core;
+
+    use super::*;
+
+    struct Echo;
+
+    impl Service for Echo {
+        type Request = (SocketAddr, KafkaRequest<'static>);
+        type Response = ();
+        type Error = Error;
+        type Future = FutureResult<(), Error>;
+
+        fn call(&self, _request: Self::Request) -> Self::Future {
+            future::ok(())
+        }
+    }
+
+    #[test]
+    fn test_inject_faults() {
+        let mut core = Core::new().unwrap();
+        let chaos = Chaos::default();
+        let middleware = ChaosMiddleware::new(Echo, chaos.clone(), Runtime::from(core.handle()));
+        let addr = "127.0.0.1:9092".parse().unwrap();
+        let request = || (addr, KafkaRequest::api_versions(0, 0, None));
+
+        assert!(middleware.call(request()).wait().is_ok());
+
+        chaos.inject(ApiKeys::ApiVersions, Fault::default().with_error_rate(1.0));
+
+        assert!(middleware.call(request()).wait().is_err());
+
+        chaos.inject(ApiKeys::ApiVersions, Fault::default().with_latency(Duration::from_millis(10)));
+
+        assert!(core.run(middleware.call(request())).is_ok());
+
+        chaos.heal(ApiKeys::ApiVersions);
+        chaos.inject_all(Fault::default().with_error_rate(1.0));
+
+        assert_eq!(chaos.fault(ApiKeys::Fetch.key()), Some(Fault::default().with_error_rate(1.0)));
+        assert!(middleware.call(request()).wait().is_err());
+
+        chaos.heal_all();
+
+        assert!(middleware.call(request()).wait().is_ok());
+    }
+}
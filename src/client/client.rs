@@ -2,13 +2,14 @@ use std::borrow::Cow;
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::error::Error as StdError;
+use std::io;
 use std::iter::{self, FromIterator};
 use std::mem;
 use std::cmp;
-use std::net::{SocketAddr, ToSocketAddrs};
+use std::net::SocketAddr;
 use std::ops::Deref;
 use std::rc::Rc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::usize;
 
 use bytes::Bytes;
@@ -16,28 +17,45 @@ use bytes::Bytes;
 use rand::{self, Rng};
 
 use futures::unsync::oneshot;
+use futures::future::{Either, Loop};
 use futures::{future, Async, Future, IntoFuture, Poll};
-use tokio_core::reactor::{Handle, Timeout};
+use tokio_core::reactor::Handle;
 use tokio_service::Service;
-use tokio_timer::Timer;
 use ns_router::{AutoName, Config as RouterConfig, Router, SubscribeExt};
 use ns_std_threaded::ThreadedResolver;
 use abstract_ns::HostResolve;
 
+#[cfg(feature = "chaos")]
+use client::chaos::{Chaos, ChaosMiddleware};
 use client::middleware::Timeout as TimeoutMiddleware;
-use client::{Broker, BrokerRef, ClientBuilder, ClientConfig, Cluster, FutureResponse, InFlightMiddleware,
-             KafkaService, Metadata, Metrics};
-use errors::{Error, Result};
+use client::{Broker, BrokerRef, ClientBuilder, ClientConfig, Cluster, ErrorClass, Feature, FetchQuotas,
+             FetchSessionRequest, FetchSessions, FutureResponse, Hostnames, InFlightMiddleware, KafkaService, Metadata,
+             Metrics, Runtime, ShutdownReport};
+use consumer::{Assignment, LeaderSelector, ReplicaSelector, CONSUMER_PROTOCOL};
+use errors::{Error, Result, ResultExt};
 use errors::ErrorKind::{self, *};
-use network::{KafkaRequest, KafkaResponse, OffsetAndMetadata, TopicPartition, DEFAULT_PORT};
-use protocol::{ApiKeys, ApiVersion, CorrelationId, ErrorCode, FetchOffset, FetchPartition, FetchTopic, FetchTopicData,
-               GenerationId, JoinGroupMember, JoinGroupProtocol, KafkaCode, Message, MessageSet, Offset, PartitionId,
-               RequiredAcks, SyncGroupAssignment, Timestamp, UsableApiVersions, DEFAULT_RESPONSE_MAX_BYTES};
+use monitoring::{fetch_group_lag, FetchLags};
+use network::{KafkaRequest, KafkaResponse, OffsetAndMetadata, OffsetAndTimestamp, TopicPartition, DEFAULT_PORT};
+use protocol::{ApiKeys, ApiVersion, ConfigSourceType, CorrelationId, CreateTopicsConfigEntry, CreateTopicsTopic,
+               ErrorCode, FetchOffset, FetchPartition, FetchTopic, FetchTopicData, GenerationId, JoinGroupMember,
+               JoinGroupProtocol, KafkaCode, LeaderEpoch, Message, MessageSet, NodeId, Offset, PartitionId,
+               RequiredAcks, ResourceType, Schema, SyncGroupAssignment, Timestamp, UsableApiVersions,
+               BROKER_RESOURCE_TYPE, DEFAULT_MAX_NUMBER_OF_OFFSETS, DEFAULT_RESPONSE_MAX_BYTES, FINAL_SESSION_EPOCH,
+               INVALID_SESSION_ID, TOPIC_RESOURCE_TYPE, UNDEFINED_EPOCH_OFFSET, UNDEFINED_LEADER_EPOCH};
+
+/// The first version of Fetch requests which could be sent within the fetch sessions (KIP-227).
+const INCREMENTAL_FETCH_API_VERSION: ApiVersion = 7;
+
+/// The first version of Fetch requests which could be served by the followers (KIP-392).
+const FETCH_FROM_FOLLOWER_API_VERSION: ApiVersion = 11;
 
 /// A trait for communicating with the Kafka cluster.
 pub trait Client<'a>: 'static {
     fn handle(&self) -> &Handle;
 
+    /// The runtime to spawn the background futures and wait for the timeouts
+    fn runtime(&self) -> &Runtime;
+
     fn metadata(&self) -> GetMetadata;
 
     /// The retry strategy when request failed
@@ -53,22 +71,92 @@ pub trait Client<'a>: 'static {
         records: Vec<Cow<'a, MessageSet>>,
     ) -> ProduceRecords;
 
+    /// Send the batches of the partitions led by the broker in a single produce request.
+    ///
+    /// The metadata is not refreshed when the partitions failed with the stale metadata,
+    /// the failed partitions could be sent again with `produce_records`.
+    fn produce_batches(
+        &self,
+        broker: BrokerRef,
+        acks: RequiredAcks,
+        timeout: Duration,
+        batches: Vec<(TopicPartition<'a>, Cow<'a, MessageSet>)>,
+    ) -> ProduceRecords;
+
     /// Fetch records of partitions for all nodes for which we have assigned
     /// partitions.
+    ///
+    /// The partitions are fetched from the replicas chosen by the selector.
     fn fetch_records(
         &self,
         fetch_max_wait: Duration,
         fetch_min_bytes: usize,
         fetch_max_bytes: usize,
+        replica_selector: Rc<ReplicaSelector>,
         partitions: Vec<(TopicPartition<'a>, PartitionData)>,
     ) -> FetchRecords;
 
+    /// Fetch the records of a partition from the offset, up to `max_bytes`, from the leader.
+    ///
+    /// It is a low-level API independent of the consumer, the returned future resolves without waiting
+    /// for new records, and fails with the partition error if any.
+    fn fetch_partition_records(
+        &self,
+        topic_partition: TopicPartition<'a>,
+        offset: Offset,
+        max_bytes: usize,
+    ) -> FetchPartitionRecords {
+        let topic_name = topic_partition.topic_name.to_string();
+        let partition_id = topic_partition.partition_id;
+        let partitions = vec![
+            (
+                topic_partition,
+                PartitionData {
+                    offset,
+                    max_bytes: Some(max_bytes as i32),
+                },
+            ),
+        ];
+
+        self.fetch_records(Duration::default(), 0, max_bytes, Rc::new(LeaderSelector), partitions)
+            .and_then(move |(_, mut records)| -> Result<FetchedRecords> {
+                let fetched = records
+                    .remove(&topic_name)
+                    .and_then(|records| records.into_iter().find(|record| record.partition_id == partition_id))
+                    .ok_or_else(|| Error::from(KafkaError(KafkaCode::UnknownTopicOrPartition)))?;
+
+                if fetched.error_code != KafkaCode::None {
+                    bail!(KafkaError(fetched.error_code))
+                }
+
+                Ok(fetched)
+            })
+            .static_boxed()
+    }
+
     /// Search the offsets by target times for the specified topics and return a future which
     /// will eventually contain the partition offset information.
     fn list_offsets<I>(&self, partitions: I) -> ListOffsets
     where
         I: 'static + IntoIterator<Item = (TopicPartition<'a>, FetchOffset)>;
 
+    /// Search up to `max_number_of_offsets` offsets before the target times for the specified topics,
+    /// e.g. the base offsets of the log segments, which are useful to find the retention boundaries.
+    ///
+    /// Only the `ListOffsets` v0 (before Kafka 0.10.1) returns the multiple offsets of a partition,
+    /// the later versions return at most one offset, and a partition may have no offset at all.
+    fn offsets_before<I>(&self, partitions: I, max_number_of_offsets: i32) -> ListOffsets
+    where
+        I: 'static + IntoIterator<Item = (TopicPartition<'a>, FetchOffset)>;
+
+    /// Look up the earliest offsets whose timestamps are greater than or equal to the given timestamps.
+    ///
+    /// The lookup requires the `ListOffsets` v1 (Kafka 0.10.1 or later),
+    /// the partitions without such a record are omitted from the result.
+    fn offsets_for_times<I>(&self, partitions: I) -> OffsetsForTimes<'a>
+    where
+        I: 'static + IntoIterator<Item = (TopicPartition<'a>, Timestamp)>;
+
     /// Load metadata of the Kafka cluster and return a future which will eventually contain
     /// the metadata information.
     fn load_metadata(&mut self) -> LoadMetadata<'a>;
@@ -119,6 +207,75 @@ pub trait Client<'a>: 'static {
         generation: Generation,
         group_assignment: Option<Vec<ConsumerGroupAssignment<'a>>>,
     ) -> SyncGroup;
+
+    /// Create a topic with the number of partitions, replication factor and topic level configuration.
+    ///
+    /// The broker default will be used if `num_partitions` or `replication_factor` is `None`.
+    fn create_topic(
+        &self,
+        topic_name: Cow<'a, str>,
+        num_partitions: Option<i32>,
+        replication_factor: Option<i16>,
+        configs: HashMap<String, String>,
+    ) -> CreateTopic;
+
+    /// Delete the topic, the topic will be marked for deletion if `delete.topic.enable` is disabled.
+    fn delete_topic(&self, topic_name: Cow<'a, str>) -> DeleteTopic;
+
+    /// Delete the records of partitions before the given offsets, -1 means the high watermark.
+    ///
+    /// The request is sent to the leaders of partitions,
+    /// and return a future which will eventually contain the new low watermarks.
+    fn delete_records<I>(&self, partitions: I) -> DeleteRecords
+    where
+        I: 'static + IntoIterator<Item = (TopicPartition<'a>, Offset)>;
+
+    /// Look up the end offsets of the leader epochs of partitions.
+    ///
+    /// The request is sent to the leaders of partitions,
+    /// and return a future which will eventually contain the end offsets used to detect the log truncation.
+    fn offsets_for_leader_epoch<I>(&self, partitions: I) -> OffsetsForLeaderEpoch
+    where
+        I: 'static + IntoIterator<Item = (TopicPartition<'a>, LeaderEpoch)>;
+
+    /// Describe all the configurations of the topics or brokers.
+    fn describe_configs<I>(&self, resources: I) -> DescribeConfigs
+    where
+        I: 'static + IntoIterator<Item = ConfigResource>;
+
+    /// Alter the configurations of the topics or brokers.
+    ///
+    /// The configurations of resource are replaced as a whole,
+    /// the configurations not specified will be reverted to the default.
+    fn alter_configs<I>(&self, changes: I) -> AlterConfigs
+    where
+        I: 'static + IntoIterator<Item = (ConfigResource, HashMap<String, String>)>;
+
+    /// Describe the cluster id, the controller and the brokers of the cluster.
+    fn describe_cluster(&self) -> DescribeCluster;
+
+    /// List the groups managed by all the brokers.
+    fn list_groups(&self) -> ListGroups;
+
+    /// Describe the state, members and assignments of the groups.
+    fn describe_groups<I>(&self, group_ids: I) -> DescribeGroups
+    where
+        I: 'static + IntoIterator<Item = String>;
+
+    /// Fetch the committed offsets of the group and the log end offsets of the topics,
+    /// and calculate the lag of each partition in one future.
+    fn fetch_group_lag<S, I>(&self, group_id: S, topic_names: I) -> FetchLags
+    where
+        Self: Clone,
+        S: Into<String>,
+        I: IntoIterator<Item = S>,
+    {
+        fetch_group_lag(
+            self.clone(),
+            group_id.into(),
+            topic_names.into_iter().map(|topic_name| topic_name.into()).collect(),
+        )
+    }
 }
 
 /// The future of producing records.
@@ -133,11 +290,16 @@ pub struct ProducedRecords {
     pub error_code: KafkaCode,
     /// The offset found in the partition
     pub base_offset: Offset,
+    /// The timestamp assigned by the broker if the topic uses `LogAppendTime`
+    pub log_append_time: Option<Timestamp>,
 }
 
 /// The future of fetch records of partitions.
 pub type FetchRecords = StaticBoxFuture<(Duration, HashMap<String, Vec<FetchedRecords>>)>;
 
+/// The future of fetch records of a partition.
+pub type FetchPartitionRecords = StaticBoxFuture<FetchedRecords>;
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct FetchedRecords {
     /// The partition id
@@ -148,6 +310,8 @@ pub struct FetchedRecords {
     pub fetch_offset: Offset,
     /// The offset at the end of the log for this partition.
     pub high_watermark: Offset,
+    /// The replica preferred by the broker to fetch this partition from (v11+).
+    pub preferred_read_replica: Option<NodeId>,
     /// The message data fetched from this partition, in the format described
     /// above.
     pub messages: Vec<Message>,
@@ -156,6 +320,9 @@ pub struct FetchedRecords {
 /// The future of partition offsets information.
 pub type ListOffsets = StaticBoxFuture<HashMap<String, Vec<ListedOffset>>>;
 
+/// The future of offsets looked up by timestamps.
+pub type OffsetsForTimes<'a> = StaticBoxFuture<HashMap<TopicPartition<'a>, OffsetAndTimestamp>>;
+
 /// The partition and offset
 #[derive(Clone, Debug, PartialEq)]
 pub struct ListedOffset {
@@ -227,6 +394,107 @@ pub type ConsumerGroupProtocol<'a> = JoinGroupProtocol<'a>;
 /// The future of heartbeat.
 pub type Heartbeat = StaticBoxFuture;
 
+/// The future of describe cluster.
+pub type DescribeCluster = StaticBoxFuture<ClusterDescription>;
+
+/// The description of cluster.
+#[derive(Clone, Debug)]
+pub struct ClusterDescription {
+    /// The cluster id, only provided by Metadata v2 or later.
+    pub cluster_id: Option<String>,
+    /// The broker which acts as the controller, only provided by Metadata v1 or later.
+    pub controller: Option<Broker>,
+    /// The brokers of the cluster.
+    pub brokers: Vec<Broker>,
+}
+
+impl<'m> From<&'m Metadata> for ClusterDescription {
+    fn from(metadata: &'m Metadata) -> Self {
+        ClusterDescription {
+            cluster_id: metadata.cluster_id().map(str::to_owned),
+            controller: metadata.controller().cloned(),
+            brokers: metadata.brokers().to_vec(),
+        }
+    }
+}
+
+/// The future of list groups.
+pub type ListGroups = StaticBoxFuture<Vec<GroupListing>>;
+
+/// The future of describe groups.
+pub type DescribeGroups = StaticBoxFuture<Vec<GroupDescription>>;
+
+/// The group managed by a broker.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GroupListing {
+    /// The group id.
+    pub group_id: String,
+    /// The protocol type of the group, e.g. `consumer`.
+    pub protocol_type: String,
+    /// The broker which coordinates the group.
+    pub coordinator: BrokerRef,
+}
+
+/// The state of group.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GroupState {
+    /// The group state is unknown.
+    Unknown,
+    /// The group has no members.
+    Empty,
+    /// The group is preparing to rebalance, and the members are rejoining.
+    PreparingRebalance,
+    /// The group is waiting for the assignments from the leader.
+    CompletingRebalance,
+    /// The group is stable.
+    Stable,
+    /// The group has no more members, and its metadata is being removed.
+    Dead,
+}
+
+impl<'s> From<&'s str> for GroupState {
+    fn from(state: &'s str) -> Self {
+        match state {
+            "Empty" => GroupState::Empty,
+            "PreparingRebalance" => GroupState::PreparingRebalance,
+            "AwaitingSync" | "CompletingRebalance" => GroupState::CompletingRebalance,
+            "Stable" => GroupState::Stable,
+            "Dead" => GroupState::Dead,
+            _ => GroupState::Unknown,
+        }
+    }
+}
+
+/// The description of group.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GroupDescription {
+    /// The group id.
+    pub group_id: String,
+    /// The current state of the group.
+    pub state: GroupState,
+    /// The protocol type of the group, empty if there is no active group.
+    pub protocol_type: String,
+    /// The group protocol, only provided if the group is stable.
+    pub protocol: String,
+    /// The broker which coordinates the group.
+    pub coordinator: BrokerRef,
+    /// The members of the group.
+    pub members: Vec<GroupMemberDescription>,
+}
+
+/// The description of group member.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GroupMemberDescription {
+    /// The member id assigned by the coordinator.
+    pub member_id: String,
+    /// The client id used in the latest join group request of the member.
+    pub client_id: String,
+    /// The client host of the member.
+    pub client_host: String,
+    /// The partitions assigned to the consumer, only provided if the group is stable.
+    pub assignment: Option<Assignment<'static>>,
+}
+
 /// The consumer group
 #[derive(Clone, Debug)]
 pub struct ConsumerGroup {
@@ -290,6 +558,137 @@ pub type ConsumerGroupAssignment<'a> = SyncGroupAssignment<'a>;
 /// The future of sync consumer group.
 pub type SyncGroup = StaticBoxFuture<Bytes>;
 
+/// The future of create topic.
+pub type CreateTopic = StaticBoxFuture;
+
+/// The future of delete topic.
+pub type DeleteTopic = StaticBoxFuture;
+
+/// The future of delete records.
+pub type DeleteRecords = StaticBoxFuture<HashMap<String, Vec<DeletedRecords>>>;
+
+/// The deleted records of partition.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeletedRecords {
+    /// The partition id
+    pub partition_id: PartitionId,
+    /// The error code
+    pub error_code: KafkaCode,
+    /// Smallest available offset of the partition after the deletion.
+    pub low_watermark: Offset,
+}
+
+/// The future of offsets for leader epoch.
+pub type OffsetsForLeaderEpoch = StaticBoxFuture<HashMap<String, Vec<EpochEndOffset>>>;
+
+/// The end offset of the leader epoch of partition.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EpochEndOffset {
+    /// The partition id
+    pub partition_id: PartitionId,
+    /// The error code
+    pub error_code: KafkaCode,
+    /// The largest leader epoch not greater than the requested one, only provided since v1.
+    pub leader_epoch: Option<LeaderEpoch>,
+    /// The end offset of the leader epoch, -1 if the leader epoch is unknown.
+    pub end_offset: Offset,
+}
+
+impl EpochEndOffset {
+    /// The end offset of the leader epoch, or `None` if the leader epoch is unknown.
+    pub fn end_offset(&self) -> Option<Offset> {
+        if self.end_offset == UNDEFINED_EPOCH_OFFSET {
+            None
+        } else {
+            Some(self.end_offset)
+        }
+    }
+}
+
+/// The future of describe configs.
+pub type DescribeConfigs = StaticBoxFuture<HashMap<ConfigResource, Vec<ConfigEntry>>>;
+
+/// The future of alter configs.
+pub type AlterConfigs = StaticBoxFuture;
+
+/// The resource which has configurations.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ConfigResource {
+    /// The configurations of topic
+    Topic(String),
+    /// The configurations of broker
+    Broker(NodeId),
+}
+
+impl ConfigResource {
+    fn resource_type(&self) -> ResourceType {
+        match *self {
+            ConfigResource::Topic(_) => TOPIC_RESOURCE_TYPE,
+            ConfigResource::Broker(_) => BROKER_RESOURCE_TYPE,
+        }
+    }
+
+    fn resource_name(&self) -> String {
+        match *self {
+            ConfigResource::Topic(ref topic_name) => topic_name.clone(),
+            ConfigResource::Broker(node_id) => node_id.to_string(),
+        }
+    }
+
+    fn from_resource(resource_type: ResourceType, resource_name: &str) -> Option<Self> {
+        match resource_type {
+            TOPIC_RESOURCE_TYPE => Some(ConfigResource::Topic(resource_name.to_owned())),
+            BROKER_RESOURCE_TYPE => resource_name.parse().ok().map(ConfigResource::Broker),
+            _ => None,
+        }
+    }
+}
+
+/// The source of configuration.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConfigSource {
+    /// The source of config is unknown
+    Unknown,
+    /// Dynamic topic config that is configured for a specific topic
+    DynamicTopicConfig,
+    /// Dynamic broker config that is configured for a specific broker
+    DynamicBrokerConfig,
+    /// Dynamic broker config that is configured as default for all brokers in the cluster
+    DynamicDefaultBrokerConfig,
+    /// Static broker config provided as broker properties at start up
+    StaticBrokerConfig,
+    /// Built-in default configuration for configs that have a default value
+    DefaultConfig,
+}
+
+impl From<ConfigSourceType> for ConfigSource {
+    fn from(source: ConfigSourceType) -> Self {
+        match source {
+            1 => ConfigSource::DynamicTopicConfig,
+            2 => ConfigSource::DynamicBrokerConfig,
+            3 => ConfigSource::DynamicDefaultBrokerConfig,
+            4 => ConfigSource::StaticBrokerConfig,
+            5 => ConfigSource::DefaultConfig,
+            _ => ConfigSource::Unknown,
+        }
+    }
+}
+
+/// The configuration of resource.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConfigEntry {
+    /// The name of configuration
+    pub name: String,
+    /// The value of configuration, `None` if it is sensitive
+    pub value: Option<String>,
+    /// The source of configuration
+    pub source: ConfigSource,
+    /// The configuration could not be altered
+    pub read_only: bool,
+    /// The configuration value should not be displayed
+    pub sensitive: bool,
+}
+
 /// A Kafka client that communicate with the Kafka cluster.
 #[derive(Clone)]
 pub struct KafkaClient<'a> {
@@ -299,22 +698,49 @@ pub struct KafkaClient<'a> {
 struct Inner<'a> {
     config: ClientConfig,
     handle: Handle,
-    service: Rc<InFlightMiddleware<TimeoutMiddleware<KafkaService<'a>>>>,
-    timer: Rc<Timer>,
+    runtime: Runtime,
+    service: Rc<InFlightMiddleware<TimeoutMiddleware<Upstream<'a>>>>,
+    hostnames: Hostnames,
+    addresses: Addresses,
+    fetch_quotas: FetchQuotas,
+    fetch_sessions: FetchSessions,
     router: Rc<Router>,
     metrics: Option<Rc<Metrics>>,
     state: Rc<RefCell<State>>,
+    shutdown_hooks: RefCell<Vec<ShutdownHook>>,
+}
+
+#[cfg(not(feature = "chaos"))]
+type Upstream<'a> = KafkaService<'a>;
+
+#[cfg(feature = "chaos")]
+type Upstream<'a> = ChaosMiddleware<KafkaService<'a>>;
+
+/// The resolved addresses of the brokers, keyed by the `host:port` of brokers.
+type Addresses = Rc<RefCell<HashMap<String, ResolvedAddrs>>>;
+
+struct ResolvedAddrs {
+    addrs: Vec<SocketAddr>,
+    resolved_at: Instant,
 }
 
+/// The hook to release the resources (e.g. flush producer, leave group) before shutdown.
+type ShutdownHook = Box<Fn() -> StaticBoxFuture<ShutdownReport>>;
+
 #[derive(Default)]
 struct State {
     correlation_id: CorrelationId,
     metadata_status: MetadataStatus,
+    topics_refreshed: HashMap<String, Instant>,
+    group_coordinators: HashMap<String, Broker>,
+    metadata_api_version: ApiVersion,
 }
 
 enum MetadataStatus {
-    Loading(RefCell<Vec<oneshot::Sender<Rc<Metadata>>>>),
+    Loading(RefCell<Vec<oneshot::Sender<Result<Rc<Metadata>>>>>),
     Loaded(Rc<Metadata>),
+    /// The initial metadata could not be loaded from the bootstrap servers.
+    Failed(String),
 }
 
 impl Default for MetadataStatus {
@@ -335,15 +761,25 @@ impl<'a> KafkaClient<'a>
 where
     Self: 'static,
 {
-    pub fn new(config: ClientConfig, handle: Handle) -> KafkaClient<'a> {
+    /// Construct a `KafkaClient`, or fail if the config is invalid, e.g. the TLS certificates could not be loaded.
+    pub fn new(config: ClientConfig, handle: Handle) -> Result<KafkaClient<'a>> {
+        let runtime = Runtime::from(handle.clone());
+
+        KafkaClient::new_with_runtime(config, handle, runtime)
+    }
+
+    /// Construct a `KafkaClient` which spawns the background futures and waits for the timeouts with the runtime.
+    ///
+    /// The `Handle` is still used to connect to the brokers.
+    pub fn new_with_runtime(config: ClientConfig, handle: Handle, runtime: Runtime) -> Result<KafkaClient<'a>> {
         trace!("create client from config: {:?}", config);
 
         let metrics = if config.metrics {
-            Some(Rc::new(Metrics::new().expect("fail to register metrics")))
+            Some(Rc::new(Metrics::new()?))
         } else {
             None
         };
-        let timer = Rc::new(config.timer());
+        let tls_settings = config.tls_settings()?;
         let router = Rc::new(Router::from_config(
             &RouterConfig::new()
                 .set_fallthrough(
@@ -354,31 +790,59 @@ where
                 .done(),
             &handle,
         ));
+        let hostnames = Hostnames::default();
+        let upstream = KafkaService::new(
+            handle.clone(),
+            router.clone(),
+            config.max_connection_idle(),
+            config.reconnect_backoff(),
+            config.reconnect_backoff_max(),
+            config.sasl_authenticator(),
+            tls_settings,
+            hostnames.clone(),
+            metrics.clone(),
+            config.listeners.clone(),
+            config.max_in_flight_requests_per_connection,
+            config.check_crcs,
+            runtime.clone(),
+            config.transport(),
+            config.request_queues(),
+        );
+        #[cfg(feature = "chaos")]
+        let upstream = ChaosMiddleware::new(upstream, Chaos::default(), runtime.clone());
         let service = Rc::new(InFlightMiddleware::new(TimeoutMiddleware::new(
-            KafkaService::new(
-                handle.clone(),
-                router.clone(),
-                config.max_connection_idle(),
-                metrics.clone(),
-            ),
-            config.timer(),
-            config.request_timeout(),
+            upstream,
+            runtime.clone(),
+            config.request_timeouts(),
         )));
         let inner = Rc::new(Inner {
             config,
             handle,
+            runtime,
             service,
-            timer,
+            hostnames,
+            addresses: Addresses::default(),
+            fetch_quotas: FetchQuotas::default(),
+            fetch_sessions: FetchSessions::default(),
             router,
             metrics,
             state: Rc::new(RefCell::new(State::default())),
+            shutdown_hooks: RefCell::new(Vec::new()),
         });
 
         let mut client = KafkaClient { inner };
 
-        client.refresh_metadata();
+        client.spawn_load_metadata();
+
+        if !client.inner.config.warm_up_topics.is_empty() {
+            let warm_up = client.warm_up(client.inner.config.warm_up_topics.clone());
+
+            client.inner.runtime.spawn(warm_up.map_err(|err| {
+                warn!("fail to warm up connections, {}", err);
+            }));
+        }
 
-        client
+        Ok(client)
     }
 
     /// Construct a `ClientBuilder` from ClientConfig
@@ -394,24 +858,336 @@ where
         ClientBuilder::with_bootstrap_servers(hosts, handle)
     }
 
-    pub fn timer(&self) -> Rc<Timer> {
-        self.inner.timer.clone()
+    /// Get the metadata which contains the given topics.
+    ///
+    /// The cached metadata will be used if the topics are fresh,
+    /// otherwise only the unknown or stale topics will be refreshed from the cluster.
+    pub fn topic_metadata<I, S>(&self, topic_names: I) -> TopicMetadata
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.topic_metadata_with_auto_creation(topic_names, true)
+    }
+
+    /// Get the metadata which contains the given topics,
+    /// and whether the brokers should create the unknown topics when refreshing them.
+    ///
+    /// The brokers only respect the flag since Metadata v4 (Kafka 0.11.0),
+    /// the older brokers create the unknown topics if `auto.create.topics.enable` is set.
+    pub fn topic_metadata_with_auto_creation<I, S>(
+        &self,
+        topic_names: I,
+        allow_auto_topic_creation: bool,
+    ) -> TopicMetadata
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let client = self.clone();
+        let topic_names = topic_names.into_iter().map(|s| s.into()).collect::<Vec<String>>();
+
+        self.metadata()
+            .and_then(move |metadata| {
+                let stale_topics = (*client.inner.state).borrow().stale_topics(
+                    &topic_names,
+                    client.inner.config.topic_metadata_refresh_interval(),
+                    client.inner.runtime.now(),
+                );
+
+                if stale_topics.is_empty() {
+                    Either::A(future::ok(metadata))
+                } else {
+                    Either::B(client.load_topic_metadata(stale_topics, allow_auto_topic_creation))
+                }
+            })
+            .static_boxed()
+    }
+
+    /// Get the cached metadata without waiting, if it has been loaded and the given topic is fresh.
+    ///
+    /// Returns `None` if the metadata is loading, or the topic is unknown or stale and should be refreshed
+    /// with `topic_metadata`.
+    pub fn cached_topic_metadata(&self, topic_name: &str) -> Option<Rc<Metadata>> {
+        let state = (*self.inner.state).borrow();
+
+        match state.metadata_status {
+            MetadataStatus::Loaded(ref metadata)
+                if state
+                    .stale_topics(
+                        &[topic_name.to_owned()],
+                        self.inner.config.topic_metadata_refresh_interval(),
+                        self.inner.runtime.now(),
+                    )
+                    .is_empty() =>
+            {
+                Some(metadata.clone())
+            }
+            _ => None,
+        }
+    }
+
+    /// Force to refresh the metadata of the given topics from the cluster, regardless of its age.
+    ///
+    /// The refreshed topics are merged into the cached metadata at once,
+    /// e.g. the producers could see the topics just created without waiting for `metadata.max.age.ms`.
+    /// The metadata of all the topics will be reloaded if no topic is given.
+    pub fn refresh_metadata<I, S>(&self, topic_names: I) -> TopicMetadata
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let topic_names = topic_names.into_iter().map(|s| s.into()).collect::<Vec<String>>();
+
+        if topic_names.is_empty() {
+            LoadMetadata::new(self.inner.clone()).static_boxed()
+        } else {
+            self.load_topic_metadata(topic_names, true)
+        }
+    }
+
+    /// Check whether the feature is supported by all the brokers,
+    /// based on the API versions negotiated with the brokers, or the `broker.version.fallback`.
+    pub fn supports(&self, feature: Feature) -> Supports {
+        self.metadata()
+            .map(move |metadata| metadata.supports(feature))
+            .static_boxed()
+    }
+
+    /// Pre-connect (and authenticate) to the leaders of the topics,
+    /// so the first requests don't pay the latency to establish the connections.
+    pub fn warm_up<I, S>(&self, topic_names: I) -> WarmUp
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let inner = self.inner.clone();
+        let topic_names = topic_names.into_iter().map(|s| s.into()).collect::<Vec<String>>();
+
+        self.topic_metadata(topic_names.clone())
+            .and_then(move |metadata| inner.warm_up(&metadata, &topic_names))
+            .static_boxed()
+    }
+
+    /// Register a hook which will be run when the client is shutting down.
+    ///
+    /// The producers and consumers built from the client register their hooks
+    /// to flush the pending records, commit the consumed offsets and leave the group,
+    /// and report what was drained in the `ShutdownReport`.
+    pub fn register_shutdown_hook<F>(&self, hook: F)
+    where
+        F: Fn() -> StaticBoxFuture<ShutdownReport> + 'static,
+    {
+        self.inner.shutdown_hooks.borrow_mut().push(Box::new(hook));
+    }
+
+    /// Gracefully shutdown the client once the signal (e.g. `SIGTERM`) resolved or failed.
+    ///
+    /// The returned future resolves to the `ShutdownReport` after all the shutdown hooks have been run.
+    pub fn on_shutdown<F>(&self, signal: F) -> Shutdown
+    where
+        F: IntoFuture + 'static,
+    {
+        let client = self.clone();
+
+        signal
+            .into_future()
+            .then(move |_| {
+                info!("received shutdown signal");
+
+                client.shutdown()
+            })
+            .static_boxed()
+    }
+
+    /// Gracefully shutdown the client, run the shutdown hooks in the order of registration,
+    /// then close the idle connections to the brokers.
+    ///
+    /// A failed hook will be logged and will not prevent the following hooks from running.
+    /// The returned `ShutdownReport` merges the reports of the hooks which have been run successfully.
+    pub fn shutdown(&self) -> Shutdown {
+        let inner = self.inner.clone();
+        let hooks = self.inner.shutdown_hooks.borrow().len();
+        let started = Instant::now();
+
+        future::loop_fn((0, ShutdownReport::default()), move |(index, mut report)| {
+            if index >= hooks {
+                report.connections_closed += inner.service.close_idle_connections();
+                report.duration = started.elapsed();
+
+                info!("client shutdown, {}", report);
+
+                return Either::A(future::ok(Loop::Break(report)));
+            }
+
+            let hook = (inner.shutdown_hooks.borrow()[index])();
+
+            Either::B(hook.then(move |res| {
+                match res {
+                    Ok(hook_report) => report.merge(&hook_report),
+                    Err(err) => warn!("fail to run shutdown hook #{}, {}", index, err),
+                }
+
+                Ok::<_, Error>(Loop::Continue((index + 1, report)))
+            }))
+        }).static_boxed()
+    }
+
+    fn load_topic_metadata(&self, topic_names: Vec<String>, allow_auto_topic_creation: bool) -> TopicMetadata {
+        let inner = self.inner.clone();
+
+        self.inner
+            // the producers rely on the brokers to create the unknown topics with `auto.create.topics.enable`,
+            // unless the producer disabled `allow.auto.create.topics`
+            .fetch_metadata(topic_names, allow_auto_topic_creation)
+            .join(self.metadata())
+            .and_then(move |(fetched, cached)| {
+                let metadata = cached.merge_topics(&fetched);
+
+                let metadata = if metadata
+                    .brokers()
+                    .iter()
+                    .all(|broker| broker.api_versions().is_some())
+                {
+                    future::ok(metadata).static_boxed()
+                } else if inner.config.api_version_request {
+                    inner
+                        .load_api_versions(&metadata)
+                        .map(move |api_versions| metadata.with_api_versions(&api_versions))
+                        .static_boxed()
+                } else {
+                    let fallback_api_versions = inner.config.broker_version_fallback.api_versions();
+
+                    future::ok(metadata.with_fallback_api_versions(fallback_api_versions)).static_boxed()
+                };
+
+                metadata.map(move |metadata| {
+                    let metadata = Rc::new(metadata);
+
+                    (*inner.state)
+                        .borrow_mut()
+                        .update_topic_metadata(&metadata, &fetched, inner.runtime.now());
+
+                    inner
+                        .config
+                        .listeners
+                        .notify(|listener| listener.on_metadata_refresh(&metadata));
+
+                    metadata
+                })
+            })
+            .static_boxed()
     }
 
     pub fn metrics(&self) -> Option<Rc<Metrics>> {
         self.inner.metrics.clone()
     }
 
-    pub fn refresh_metadata(&mut self) {
-        let handle = self.inner.handle.clone();
+    /// Close the idle connections to the brokers, returns the number of the closed connections.
+    pub fn close_idle_connections(&self) -> usize {
+        self.inner.service.close_idle_connections()
+    }
+
+    /// The faults injected into the requests, for the resilience testing.
+    #[cfg(feature = "chaos")]
+    pub fn chaos(&self) -> Chaos {
+        self.inner.service.chaos()
+    }
+
+    /// Send the request to the leaders found in the metadata.
+    ///
+    /// If the leadership has been changed or is not available, the metadata will be reloaded,
+    /// and the request will be retried against the new leaders after the retry backoff.
+    fn with_leaders<T, F, S>(&self, send: F, stale_partitions: S) -> StaticBoxFuture<T>
+    where
+        F: Fn(Rc<Inner<'a>>, Rc<Metadata>) -> StaticBoxFuture<T> + 'static,
+        S: Fn(&T) -> Option<KafkaCode> + 'static,
+        T: 'static,
+    {
+        let inner = self.inner.clone();
+        let send = Rc::new(send);
+        let stale_partitions = Rc::new(stale_partitions);
+        let backoffs = self.inner.config.retry_policies().backoffs(ErrorClass::RefreshMetadata).into_iter();
+
+        let send_request = future::loop_fn(backoffs, move |mut backoffs| {
+            let inner = inner.clone();
+            let send = send.clone();
+            let stale_partitions = stale_partitions.clone();
+
+            (*inner.state)
+                .borrow()
+                .metadata()
+                .and_then({
+                    let inner = inner.clone();
+
+                    move |metadata| send(inner, metadata)
+                })
+                .then(move |res| {
+                    let stale = match res {
+                        Ok(ref res) => stale_partitions(res),
+                        Err(Error(KafkaError(code), _)) if code.is_stale_metadata() => Some(code),
+                        Err(_) => None,
+                    };
+
+                    match (stale, backoffs.next()) {
+                        (Some(code), Some(backoff)) => {
+                            debug!("metadata is stale, {}, reload it and retry after {:?}", code, backoff);
+
+                            let runtime = inner.runtime.clone();
+
+                            Either::A(
+                                LoadMetadata::new(inner)
+                                    .and_then(move |_| runtime.sleep(backoff))
+                                    .map(move |_| Loop::Continue(backoffs)),
+                            )
+                        }
+                        _ => Either::B(future::result(res.map(Loop::Break))),
+                    }
+                })
+        });
+
+        send_request.static_boxed()
+    }
+
+    /// Load the initial metadata, and retry with an exponential backoff
+    /// if all the bootstrap servers are unavailable.
+    ///
+    /// The pending and following metadata requests fail once the retries are exhausted.
+    fn spawn_load_metadata(&mut self) {
+        let inner = self.inner.clone();
+        let state = self.inner.state.clone();
+        let backoffs = self.inner.config.metadata_bootstrap_backoffs().into_iter();
+
+        let load_metadata = future::loop_fn((self.load_metadata(), backoffs), move |(load_metadata, mut backoffs)| {
+            let inner = inner.clone();
+
+            load_metadata.then(move |res| match res {
+                Ok(metadata) => Either::A(future::ok(Loop::Break(metadata))),
+                Err(err) => match backoffs.next() {
+                    Some(backoff) => {
+                        debug!("fail to load bootstrap metadata, retry after {:?}, {}", backoff, err);
+
+                        Either::B(
+                            inner
+                                .runtime
+                                .sleep(backoff)
+                                .map(move |_| Loop::Continue((LoadMetadata::new(inner), backoffs))),
+                        )
+                    }
+                    None => Either::A(future::err(err)),
+                },
+            })
+        });
 
-        handle.spawn(
-            self.load_metadata()
+        self.inner.runtime.spawn(
+            load_metadata
                 .map(|metadata| {
                     trace!("auto loaded metadata, {:?}", metadata);
                 })
-                .map_err(|err| {
+                .map_err(move |err| {
                     warn!("fail to load metadata, {}", err);
+
+                    (*state).borrow_mut().fail_metadata(&err);
                 }),
         );
     }
@@ -419,7 +1195,8 @@ where
 
 pub enum GetMetadata {
     Loaded(Rc<Metadata>),
-    Loading(oneshot::Receiver<Rc<Metadata>>),
+    Loading(oneshot::Receiver<Result<Rc<Metadata>>>),
+    Failed(String),
 }
 
 impl Future for GetMetadata {
@@ -429,7 +1206,12 @@ impl Future for GetMetadata {
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
         match *self {
             GetMetadata::Loaded(ref meta) => Ok(Async::Ready(meta.clone())),
-            GetMetadata::Loading(ref mut inner) => inner.poll().map_err(|_| Canceled("load metadata canceled").into()),
+            GetMetadata::Loading(ref mut inner) => match inner.poll() {
+                Ok(Async::Ready(res)) => res.map(Async::Ready),
+                Ok(Async::NotReady) => Ok(Async::NotReady),
+                Err(_) => bail!(Canceled("load metadata canceled")),
+            },
+            GetMetadata::Failed(ref reason) => bail!(ErrorKind::MetadataError(reason.clone())),
         }
     }
 }
@@ -442,6 +1224,10 @@ where
         &self.inner.handle
     }
 
+    fn runtime(&self) -> &Runtime {
+        &self.inner.runtime
+    }
+
     fn metadata(&self) -> GetMetadata {
         (*self.inner.state).borrow().metadata()
     }
@@ -457,9 +1243,33 @@ where
         tp: TopicPartition<'a>,
         records: Vec<Cow<'a, MessageSet>>,
     ) -> ProduceRecords {
-        let inner = self.inner.clone();
-        self.metadata()
-            .and_then(move |metadata| inner.produce_records(&metadata, required_acks, timeout, &tp, records))
+        self.with_leaders(
+            move |inner, metadata| inner.produce_records(&metadata, required_acks, timeout, &tp, records.clone()),
+            |topics| {
+                stale_metadata(
+                    topics
+                        .values()
+                        .flat_map(|partitions| partitions.iter().map(|partition| partition.error_code)),
+                )
+            },
+        )
+    }
+
+    fn produce_batches(
+        &self,
+        broker: BrokerRef,
+        required_acks: RequiredAcks,
+        timeout: Duration,
+        batches: Vec<(TopicPartition<'a>, Cow<'a, MessageSet>)>,
+    ) -> ProduceRecords {
+        let inner = self.inner.clone();
+        self.metadata()
+            .and_then(move |metadata| {
+                metadata
+                    .find_broker(broker)
+                    .map(move |broker| inner.produce_batches(broker, required_acks, timeout, batches))
+                    .unwrap_or_else(|| BrokerNotFound(broker).into())
+            })
             .static_boxed()
     }
 
@@ -468,57 +1278,117 @@ where
         fetch_max_wait: Duration,
         fetch_min_bytes: usize,
         fetch_max_bytes: usize,
+        replica_selector: Rc<ReplicaSelector>,
         partitions: Vec<(TopicPartition<'a>, PartitionData)>,
     ) -> FetchRecords {
-        let inner = self.inner.clone();
-        self.metadata()
-            .and_then(move |metadata| {
+        let rack_id = replica_selector.rack().map(|rack| rack.to_owned());
+
+        self.with_leaders(
+            move |inner, metadata| {
+                let rack_id = rack_id.clone();
+
                 inner
-                    .topics_by_broker(ApiKeys::Fetch, &metadata, partitions)
+                    .topics_by_broker(
+                        ApiKeys::Fetch,
+                        &metadata,
+                        Some(&*replica_selector),
+                        partitions.clone(),
+                    )
                     .into_future()
                     .and_then(move |topics| {
-                        inner.fetch_records(fetch_max_wait, fetch_min_bytes, fetch_max_bytes, topics)
+                        inner.fetch_records(fetch_max_wait, fetch_min_bytes, fetch_max_bytes, rack_id, topics)
                     })
-            })
-            .static_boxed()
+                    .static_boxed()
+            },
+            |&(_, ref topics)| {
+                stale_metadata(
+                    topics
+                        .values()
+                        .flat_map(|partitions| partitions.iter().map(|partition| partition.error_code)),
+                )
+            },
+        )
     }
 
     fn list_offsets<I>(&self, partitions: I) -> ListOffsets
     where
         I: 'static + IntoIterator<Item = (TopicPartition<'a>, FetchOffset)>,
     {
-        let inner = self.inner.clone();
-        self.metadata()
-            .and_then(move |metadata| {
+        self.offsets_before(partitions, DEFAULT_MAX_NUMBER_OF_OFFSETS)
+    }
+
+    fn offsets_before<I>(&self, partitions: I, max_number_of_offsets: i32) -> ListOffsets
+    where
+        I: 'static + IntoIterator<Item = (TopicPartition<'a>, FetchOffset)>,
+    {
+        let partitions: Vec<_> = partitions.into_iter().collect();
+
+        self.with_leaders(
+            move |inner, metadata| {
                 inner
-                    .topics_by_broker(ApiKeys::ListOffsets, &metadata, partitions)
+                    .topics_by_broker(ApiKeys::ListOffsets, &metadata, None, partitions.clone())
                     .into_future()
-                    .and_then(move |topics| inner.list_offsets(topics))
-            })
-            .static_boxed()
+                    .and_then(move |topics| inner.list_offsets(topics, max_number_of_offsets))
+                    .static_boxed()
+            },
+            |topics| {
+                stale_metadata(
+                    topics
+                        .values()
+                        .flat_map(|partitions| partitions.iter().map(|partition| partition.error_code)),
+                )
+            },
+        )
     }
 
-    fn load_metadata(&mut self) -> LoadMetadata<'a> {
-        if self.inner.config.metadata_max_age > 0 {
-            let handle = self.inner.handle.clone();
+    fn offsets_for_times<I>(&self, partitions: I) -> OffsetsForTimes<'a>
+    where
+        I: 'static + IntoIterator<Item = (TopicPartition<'a>, Timestamp)>,
+    {
+        let partitions: Vec<_> = partitions
+            .into_iter()
+            .map(|(tp, timestamp)| (tp, FetchOffset::ByTime(timestamp)))
+            .collect();
 
-            let timeout = Timeout::new(self.inner.config.metadata_max_age(), &handle);
+        self.list_offsets(partitions)
+            .and_then(|topics| {
+                let mut offsets = HashMap::new();
 
-            match timeout {
-                Ok(timeout) => {
-                    let inner = self.inner.clone();
-                    let future = timeout
-                        .from_err()
-                        .and_then(move |_| LoadMetadata::new(inner.clone()))
-                        .map(|_| ())
-                        .map_err(|_| ());
+                for (topic_name, partitions) in topics {
+                    for listed in partitions {
+                        if listed.error_code != KafkaCode::None {
+                            bail!(KafkaError(listed.error_code))
+                        }
 
-                    handle.spawn(future);
-                }
-                Err(err) => {
-                    warn!("fail to create timeout, {}", err);
+                        // the broker returns -1 if no record has a timestamp greater than or equal to the target
+                        match listed.offset() {
+                            Some(offset) if offset >= 0 => {
+                                offsets.insert(
+                                    topic_partition!(topic_name.clone(), listed.partition_id),
+                                    OffsetAndTimestamp::with_timestamp(offset, listed.timestamp),
+                                );
+                            }
+                            _ => {}
+                        }
+                    }
                 }
-            }
+
+                Ok(offsets)
+            })
+            .static_boxed()
+    }
+
+    fn load_metadata(&mut self) -> LoadMetadata<'a> {
+        if self.inner.config.metadata_max_age > 0 {
+            let inner = self.inner.clone();
+            let future = self.inner
+                .runtime
+                .sleep(self.inner.config.metadata_max_age())
+                .and_then(move |_| LoadMetadata::new(inner.clone()))
+                .map(|_| ())
+                .map_err(|_| ());
+
+            self.inner.runtime.spawn(future);
         }
 
         LoadMetadata::new(self.inner.clone())
@@ -671,6 +1541,147 @@ where
             })
             .static_boxed()
     }
+
+    fn create_topic(
+        &self,
+        topic_name: Cow<'a, str>,
+        num_partitions: Option<i32>,
+        replication_factor: Option<i16>,
+        configs: HashMap<String, String>,
+    ) -> CreateTopic {
+        let inner = self.inner.clone();
+        let topic = CreateTopicsTopic {
+            topic_name,
+            num_partitions: num_partitions.unwrap_or(-1),
+            replication_factor: replication_factor.unwrap_or(-1),
+            replica_assignment: vec![],
+            configs: configs
+                .into_iter()
+                .map(|(name, value)| CreateTopicsConfigEntry {
+                    config_name: name.into(),
+                    config_value: Some(value.into()),
+                })
+                .collect(),
+        };
+        self.metadata()
+            .and_then(move |metadata| Inner::create_topic(inner, &metadata, topic))
+            .static_boxed()
+    }
+
+    fn delete_topic(&self, topic_name: Cow<'a, str>) -> DeleteTopic {
+        let inner = self.inner.clone();
+        self.metadata()
+            .and_then(move |metadata| Inner::delete_topic(inner, &metadata, topic_name))
+            .static_boxed()
+    }
+
+    fn delete_records<I>(&self, partitions: I) -> DeleteRecords
+    where
+        I: 'static + IntoIterator<Item = (TopicPartition<'a>, Offset)>,
+    {
+        let partitions: Vec<_> = partitions.into_iter().collect();
+
+        self.with_leaders(
+            move |inner, metadata| {
+                inner
+                    .topics_by_broker(ApiKeys::DeleteRecords, &metadata, None, partitions.clone())
+                    .into_future()
+                    .and_then(move |topics| inner.delete_records(topics))
+                    .static_boxed()
+            },
+            |topics| {
+                stale_metadata(
+                    topics
+                        .values()
+                        .flat_map(|partitions| partitions.iter().map(|partition| partition.error_code)),
+                )
+            },
+        )
+    }
+
+    fn describe_cluster(&self) -> DescribeCluster {
+        self.metadata()
+            .map(|metadata| ClusterDescription::from(&*metadata))
+            .static_boxed()
+    }
+
+    fn offsets_for_leader_epoch<I>(&self, partitions: I) -> OffsetsForLeaderEpoch
+    where
+        I: 'static + IntoIterator<Item = (TopicPartition<'a>, LeaderEpoch)>,
+    {
+        let partitions: Vec<_> = partitions.into_iter().collect();
+
+        self.with_leaders(
+            move |inner, metadata| {
+                inner
+                    .topics_by_broker(ApiKeys::OffsetForLeaderEpoch, &metadata, None, partitions.clone())
+                    .into_future()
+                    .and_then(move |topics| inner.offsets_for_leader_epoch(topics))
+                    .static_boxed()
+            },
+            |topics| {
+                stale_metadata(
+                    topics
+                        .values()
+                        .flat_map(|partitions| partitions.iter().map(|partition| partition.error_code)),
+                )
+            },
+        )
+    }
+
+    fn list_groups(&self) -> ListGroups {
+        let inner = self.inner.clone();
+
+        self.metadata()
+            .and_then(move |metadata| inner.list_groups(&metadata))
+            .static_boxed()
+    }
+
+    fn describe_groups<I>(&self, group_ids: I) -> DescribeGroups
+    where
+        I: 'static + IntoIterator<Item = String>,
+    {
+        let inner = self.inner.clone();
+
+        self.metadata()
+            .and_then(move |metadata| {
+                let groups = group_ids
+                    .into_iter()
+                    .map(|group_id| {
+                        let inner = inner.clone();
+
+                        inner
+                            .group_coordinator(&metadata, group_id.clone().into())
+                            .and_then(move |coordinator| inner.describe_group(&coordinator, group_id))
+                    })
+                    .collect::<Vec<_>>();
+
+                future::join_all(groups)
+            })
+            .static_boxed()
+    }
+
+    fn describe_configs<I>(&self, resources: I) -> DescribeConfigs
+    where
+        I: 'static + IntoIterator<Item = ConfigResource>,
+    {
+        let inner = self.inner.clone();
+
+        self.metadata()
+            .and_then(move |metadata| Inner::describe_configs(inner, &metadata, resources))
+            .static_boxed()
+    }
+
+    fn alter_configs<I>(&self, changes: I) -> AlterConfigs
+    where
+        I: 'static + IntoIterator<Item = (ConfigResource, HashMap<String, String>)>,
+    {
+        let inner = self.inner.clone();
+
+        self.metadata()
+            .and_then(move |metadata| Inner::alter_configs(inner, &metadata, changes))
+            .static_boxed()
+    }
 }
 
 impl<'a> Inner<'a>
@@ -693,19 +1704,166 @@ where
     where
         N: Into<AutoName<'n>>,
     {
+        let host = host.into();
+        let key = address_key(&host);
         let service = self.service.clone();
+        let addresses = self.addresses.clone();
+        let connect = {
+            let service = self.service.clone();
+            let runtime = self.runtime.clone();
+            let request_timeout = self.config.request_timeout();
+            let addresses = self.addresses.clone();
+            let key = key.clone();
+
+            move |addrs: Vec<SocketAddr>| {
+                if addrs.len() == 1 {
+                    Either::A(future::ok(addrs[0]))
+                } else {
+                    // try the addresses in order, and send the request once connected
+                    Either::B(connect_any(service, runtime, request_timeout, addresses, key, addrs))
+                }
+            }
+        };
+
+        let listeners = self.config.listeners.clone();
+        let api_key = ApiKeys::from(req.header().api_key);
+        let request_timeout = self.config.api_request_timeout(api_key);
+
+        self.resolve(host)
+            .and_then(connect)
+            .and_then(move |addr| {
+                service.call((addr, req)).map_err(move |err| {
+                    if let ErrorKind::TimeoutError(_) = *err.kind() {
+                        listeners.notify(|listener| listener.on_request_timeout(&addr, api_key, request_timeout));
+                    }
+
+                    err
+                })
+            })
+            .map_err(move |err| {
+                if let Some(key) = key {
+                    if is_connect_error(&err) && addresses.borrow_mut().remove(&key).is_some() {
+                        debug!("fail to connect {}, the addresses will be resolved again", key);
+                    }
+                }
+
+                err
+            })
+            .static_boxed()
+    }
+
+    /// Resolve the addresses of the broker, and remember its hostname to verify the certificate.
+    ///
+    /// The resolved addresses are cached until `dns.refresh.interval.ms` elapsed
+    /// or the broker could not be connected, the expired addresses are still used
+    /// if the broker could not be resolved again, e.g. the DNS is temporarily unavailable.
+    fn resolve<'n, N>(&self, host: N) -> StaticBoxFuture<Vec<SocketAddr>>
+    where
+        N: Into<AutoName<'n>>,
+    {
+        let host = host.into();
+        let key = address_key(&host);
+        let mut expired = None;
+
+        if let Some(ref key) = key {
+            if let Some(resolved) = self.addresses.borrow().get(key) {
+                if resolved.resolved_at.elapsed() < self.config.dns_refresh_interval() {
+                    return future::ok(resolved.addrs.clone()).static_boxed();
+                }
+
+                expired = Some((key.clone(), resolved.addrs.clone()));
+            }
+        }
+
+        let hostname = hostname(&host);
+        let hostnames = self.hostnames.clone();
+        let addresses = self.addresses.clone();
+
         self.router
             .resolve_auto(host, DEFAULT_PORT)
             .from_err()
-            .map(|addrs| addrs.pick_one().unwrap())
-            .and_then(move |addr| service.call((addr, req)))
+            .and_then(move |mut address| -> Result<Vec<SocketAddr>> {
+                let addrs = address
+                    .iter()
+                    .flat_map(|weighted_set| weighted_set.addresses().collect::<Vec<_>>())
+                    .collect::<Vec<_>>();
+
+                if addrs.is_empty() {
+                    bail!(ErrorKind::IoError(io::Error::new(
+                        io::ErrorKind::AddrNotAvailable,
+                        "no address resolved",
+                    )))
+                }
+
+                if let Some(hostname) = hostname {
+                    let mut hostnames = hostnames.borrow_mut();
+
+                    for addr in &addrs {
+                        hostnames.insert(*addr, hostname.clone());
+                    }
+                }
+
+                if let Some(key) = key {
+                    trace!("resolved {} to {:?}", key, addrs);
+
+                    addresses.borrow_mut().insert(
+                        key,
+                        ResolvedAddrs {
+                            addrs: addrs.clone(),
+                            resolved_at: Instant::now(),
+                        },
+                    );
+                }
+
+                Ok(addrs)
+            })
+            .or_else(move |err| match expired {
+                Some((key, addrs)) => {
+                    warn!("fail to resolve {}, fallback to the expired addresses {:?}, {}", key, addrs, err);
+
+                    Ok(addrs)
+                }
+                None => Err(err),
+            })
             .static_boxed()
     }
 
+    /// Establish the connections to the leaders of the topics in advance.
+    fn warm_up(&self, metadata: &Metadata, topic_names: &[String]) -> WarmUp {
+        let mut leaders = metadata
+            .topics()
+            .into_iter()
+            .filter(|&(topic_name, _)| topic_names.iter().any(|name| name.as_str() == topic_name))
+            .flat_map(|(_, partitions)| partitions.iter().flat_map(|partition| partition.leader))
+            .collect::<Vec<_>>();
+
+        leaders.sort_by_key(|leader| leader.index());
+        leaders.dedup();
+
+        let connections = leaders
+            .into_iter()
+            .flat_map(|leader| metadata.find_broker(leader))
+            .map(|broker| {
+                let service = self.service.clone();
+                let runtime = self.runtime.clone();
+                let request_timeout = self.config.request_timeout();
+                let addresses = self.addresses.clone();
+                let key = address_key(&AutoName::HostPort(broker.host(), broker.port()));
+
+                self.resolve(AutoName::HostPort(broker.host(), broker.port()))
+                    .and_then(move |addrs| connect_any(service, runtime, request_timeout, addresses, key, addrs))
+            })
+            .collect::<Vec<_>>();
+
+        future::join_all(connections).map(|_| ()).static_boxed()
+    }
+
     /// Choose the node with the fewest outstanding requests which is at least eligible for
     /// connection.
-    pub fn least_loaded_broker(&self, metadata: &Metadata) -> Result<(SocketAddr, BrokerRef)> {
-        let mut brokers = metadata.brokers().to_vec();
+    ///
+    /// Only the resolved addresses are checked, so it never blocks on the DNS resolution.
+    pub fn least_loaded_broker<'m>(&self, metadata: &'m Metadata) -> Result<&'m Broker> {
+        let mut brokers = metadata.brokers().iter().collect::<Vec<_>>();
 
         trace!("choose least broker from: {:?}", brokers);
 
@@ -715,7 +1873,13 @@ where
         let mut found = None;
 
         for broker in brokers {
-            for addr in broker.addr().to_socket_addrs()? {
+            let addrs = self.addresses
+                .borrow()
+                .get(&format!("{}:{}", broker.host(), broker.port()))
+                .map(|resolved| resolved.addrs.clone())
+                .unwrap_or_default();
+
+            for addr in addrs {
                 match self.service.in_flight_requests(&addr) {
                     Some(0) => {
                         trace!(
@@ -724,11 +1888,11 @@ where
                             addr
                         );
 
-                        return Ok((addr, broker.as_ref()));
+                        return Ok(broker);
                     }
                     Some(n) if n < in_flight_requests => {
                         in_flight_requests = n;
-                        found = Some((addr, broker.as_ref()));
+                        found = Some(broker);
                     }
                     _ => {}
                 }
@@ -736,27 +1900,20 @@ where
         }
 
         found
-            .map(|(addr, broker)| {
+            .map(|broker| {
                 trace!(
-                    "found least loaded broker #{} @ {} with {} in flight requests",
-                    broker.index(),
-                    addr,
-                    self.service.in_flight_requests(&addr).unwrap_or_default()
+                    "found least loaded broker #{} with {} in flight requests",
+                    broker.id(),
+                    in_flight_requests
                 );
 
-                (addr, broker)
+                broker
             })
             .or_else(|| {
                 metadata.brokers().first().map(|broker| {
-                    let addr = broker.addr().to_socket_addrs().unwrap().next().unwrap();
+                    trace!("not found any alive broker, use a random broker #{}", broker.id());
 
-                    trace!(
-                        "not found any alive broker, use a random broker # {} @ {}",
-                        broker.id(),
-                        addr
-                    );
-
-                    (addr, broker.as_ref())
+                    broker
                 })
             })
             .ok_or_else(|| {
@@ -767,10 +1924,10 @@ where
     }
 
     fn fetch_all_metadata(&self) -> FetchMetadata {
-        self.fetch_metadata(iter::empty::<String>())
+        self.fetch_metadata(iter::empty::<String>(), false)
     }
 
-    fn fetch_metadata<I, S>(&self, topic_names: I) -> FetchMetadata
+    fn fetch_metadata<I, S>(&self, topic_names: I, allow_auto_topic_creation: bool) -> FetchMetadata
     where
         I: IntoIterator<Item = S>,
         S: Into<String>,
@@ -783,15 +1940,19 @@ where
             info!("fetch metadata for toipcs: {:?}", topic_names);
         }
 
+        // the API versions of the bootstrap hosts are unknown before the metadata is loaded, v0 is used at first
+        let api_version = (*self.state).borrow().metadata_api_version;
+
         let responses = {
             let mut responses = Vec::new();
 
             for host in &self.config.hosts {
                 let request = KafkaRequest::fetch_metadata(
-                    0, // api_version
+                    api_version,
                     self.next_correlation_id(),
                     self.client_id(),
                     &topic_names,
+                    allow_auto_topic_creation,
                 );
 
                 let response = self.send_request(host.as_str(), request).and_then(|res| {
@@ -816,7 +1977,8 @@ where
     fn fetch_api_versions(&self, broker: &Broker) -> FetchApiVersions {
         debug!("fetch API versions for broker: {:?}", broker);
 
-        let request = KafkaRequest::api_versions(self.next_correlation_id(), self.client_id());
+        // the API versions request must be understood by the brokers of any version
+        let request = KafkaRequest::api_versions(0, self.next_correlation_id(), self.client_id());
 
         self.send_request(AutoName::HostPort(broker.host(), broker.port()), request)
             .and_then(|res| {
@@ -837,8 +1999,36 @@ where
 
             for broker in metadata.brokers() {
                 let broker_ref = broker.as_ref();
-                let response = self.fetch_api_versions(broker)
-                    .map(move |api_versions| (broker_ref, api_versions));
+                let addr = format!("{}:{}", broker.host(), broker.port());
+                let fallback = self.config.broker_version_fallback;
+                let metrics = self.metrics.clone();
+                let response = self.fetch_api_versions(broker).then(move |res| {
+                    let (api_versions, is_fallback) = match res {
+                        Ok(api_versions) => (api_versions, false),
+                        // the brokers before 0.10 close the connection on the unknown ApiVersions request
+                        Err(ref err) if is_connection_closed(err) => {
+                            warn!(
+                                "broker {} closed the connection when probing API versions, fall back to {:?}",
+                                addr, fallback
+                            );
+
+                            (fallback.api_versions().clone(), true)
+                        }
+                        Err(err) => return Err(err),
+                    };
+
+                    debug!(
+                        "use API versions {} for broker {}",
+                        if is_fallback { "of the fallback version" } else { "from the broker" },
+                        addr
+                    );
+
+                    if let Some(ref metrics) = metrics {
+                        metrics.api_versions_loaded(&addr, is_fallback);
+                    }
+
+                    Ok((broker_ref, api_versions))
+                });
 
                 responses.push(response);
             }
@@ -857,15 +2047,13 @@ where
         tp: &TopicPartition<'a>,
         records: Vec<Cow<'a, MessageSet>>,
     ) -> ProduceRecords {
-        let (api_version, addr) = metadata.leader_for(tp).map_or_else(
-            || (0, AutoName::Auto(self.config.hosts.first().unwrap())),
-            |broker| {
-                (
-                    broker.api_version(ApiKeys::Produce).unwrap_or_default(),
-                    AutoName::HostPort(broker.host(), broker.port()),
-                )
-            },
-        );
+        let (api_version, addr) = match metadata.leader_for(tp) {
+            Some(broker) => (
+                broker.api_version(ApiKeys::Produce).unwrap_or_default(),
+                AutoName::HostPort(broker.host(), broker.port()),
+            ),
+            None => return KafkaError(KafkaCode::LeaderNotAvailable).into(),
+        };
 
         let request = KafkaRequest::produce_records(
             api_version,
@@ -877,6 +2065,40 @@ where
             records,
         );
 
+        self.send_produce_request(addr, request)
+    }
+
+    fn produce_batches(
+        &self,
+        broker: &Broker,
+        required_acks: RequiredAcks,
+        timeout: Duration,
+        batches: Vec<(TopicPartition<'a>, Cow<'a, MessageSet>)>,
+    ) -> ProduceRecords {
+        let api_version = broker.api_version(ApiKeys::Produce).unwrap_or_default();
+        let addr = AutoName::HostPort(broker.host(), broker.port());
+        let mut topics = HashMap::new();
+
+        for (tp, message_set) in batches {
+            topics
+                .entry(tp.topic_name)
+                .or_insert_with(Vec::new)
+                .push((tp.partition_id, message_set));
+        }
+
+        let request = KafkaRequest::produce_batches(
+            api_version,
+            self.next_correlation_id(),
+            self.client_id(),
+            required_acks,
+            timeout,
+            topics,
+        );
+
+        self.send_produce_request(addr, request)
+    }
+
+    fn send_produce_request<'n>(&self, addr: AutoName<'n>, request: KafkaRequest<'a>) -> ProduceRecords {
         self.send_request(addr, request)
             .and_then(|res| {
                 if let KafkaResponse::Produce(res) = res {
@@ -898,6 +2120,11 @@ where
                                     partition_id: partition.partition_id,
                                     error_code: partition.error_code.into(),
                                     base_offset: partition.offset,
+                                    // the timestamp is -1 if the topic uses `CreateTime`
+                                    log_append_time: match partition.timestamp {
+                                        Some(timestamp) if timestamp >= 0 => Some(timestamp),
+                                        _ => None,
+                                    },
                                 })
                                 .collect(),
                         )
@@ -911,6 +2138,7 @@ where
         &self,
         api_key: ApiKeys,
         metadata: &Metadata,
+        replica_selector: Option<&ReplicaSelector>,
         partitions: I,
     ) -> Result<TopicsByBroker<'a, T>>
     where
@@ -919,9 +2147,12 @@ where
         let mut topics = HashMap::new();
 
         for (tp, value) in partitions {
-            let broker = metadata
+            let leader = metadata
                 .leader_for(&tp)
                 .ok_or_else(|| KafkaError(KafkaCode::NotLeaderForPartition))?;
+            let broker = replica_selector
+                .and_then(|selector| select_replica(selector, metadata, &tp, api_key))
+                .unwrap_or(leader);
             let api_version = broker.api_version(api_key).unwrap_or_default();
 
             topics
@@ -940,47 +2171,105 @@ where
         fetch_max_wait: Duration,
         fetch_min_bytes: usize,
         fetch_max_bytes: usize,
+        rack_id: Option<String>,
         topics: TopicsByBroker<'a, PartitionData>,
     ) -> FetchRecords {
+        let rack_id = rack_id.map_or(Cow::from(""), Cow::from);
         let requests = {
             let mut requests = Vec::new();
 
             for (((host, port), api_version), offsets_by_topic) in topics {
+                // the broker returns at least one message even exceed the max bytes since Fetch v3,
+                // so the partition max bytes could be reduced without stalling the consumer.
+                let scale_partition_bytes = api_version >= 3;
                 let fetch_topics = offsets_by_topic
                     .iter()
                     .map(|(topic_name, partitions)| FetchTopic {
                         topic_name: topic_name.to_owned(),
                         partitions: partitions
                             .iter()
-                            .map(|&(partition_id, ref fetch_data)| FetchPartition {
-                                partition_id,
-                                fetch_offset: fetch_data.offset,
-                                max_bytes: fetch_data.max_bytes.unwrap_or(DEFAULT_RESPONSE_MAX_BYTES),
+                            .map(|&(partition_id, ref fetch_data)| {
+                                let max_bytes = fetch_data.max_bytes.unwrap_or(DEFAULT_RESPONSE_MAX_BYTES);
+
+                                FetchPartition {
+                                    partition_id,
+                                    current_leader_epoch: UNDEFINED_LEADER_EPOCH,
+                                    fetch_offset: fetch_data.offset,
+                                    log_start_offset: -1,
+                                    max_bytes: if scale_partition_bytes {
+                                        self.fetch_quotas.scale(&host, port, max_bytes as usize) as i32
+                                    } else {
+                                        max_bytes
+                                    },
+                                }
                             })
                             .collect(),
                     })
                     .collect();
 
+                // only the changed partitions are sent within the fetch session of broker,
+                // the unchanged partitions are omitted from both the request and the response.
+                let use_session = api_version >= INCREMENTAL_FETCH_API_VERSION;
+                let session = if use_session {
+                    self.fetch_sessions.build(&host, port, fetch_topics)
+                } else {
+                    FetchSessionRequest {
+                        session_id: INVALID_SESSION_ID,
+                        session_epoch: FINAL_SESSION_EPOCH,
+                        topics: fetch_topics,
+                        forgotten_topics: vec![],
+                    }
+                };
+
                 let request = KafkaRequest::fetch_records(
                     api_version,
                     self.next_correlation_id(),
                     self.client_id(),
                     fetch_max_wait,
                     fetch_min_bytes as i32,
-                    fetch_max_bytes as i32,
-                    fetch_topics,
-                );
+                    self.fetch_quotas.scale(&host, port, fetch_max_bytes) as i32,
+                    session.session_id,
+                    session.session_epoch,
+                    session.topics,
+                    session.forgotten_topics,
+                ).with_rack_id(rack_id.clone());
+                let fetch_quotas = self.fetch_quotas.clone();
+                let fetch_sessions = self.fetch_sessions.clone();
+                let session_host = host.clone();
                 let request = self.send_request(AutoName::HostPort(&host, port), request)
-                    .and_then(|res| {
-                        if let KafkaResponse::Fetch(res) = res {
-                            Ok((res.throttle_time, res.topics))
-                        } else {
-                            bail!(UnexpectedResponse(res.api_key()))
+                    .then(move |res| -> Result<(Option<i32>, Vec<FetchTopicData>)> {
+                        let res = match res {
+                            Ok(KafkaResponse::Fetch(res)) => res,
+                            Ok(res) => bail!(UnexpectedResponse(res.api_key())),
+                            Err(err) => {
+                                if use_session {
+                                    fetch_sessions.reset(&session_host, port);
+                                }
+
+                                return Err(err);
+                            }
+                        };
+
+                        if use_session {
+                            let error_code = res.error_code.unwrap_or_default();
+                            let session_id = res.session_id.unwrap_or_default();
+
+                            fetch_sessions.update(&session_host, port, error_code, session_id);
+
+                            if error_code != KafkaCode::None as ErrorCode {
+                                bail!(ErrorKind::KafkaError(error_code.into()))
+                            }
                         }
+
+                        Ok((res.throttle_time, res.topics))
                     })
-                    .map(|(throttle_time, topics)| {
+                    .map(move |(throttle_time, topics)| {
+                        let throttle_time = Duration::from_millis(throttle_time.unwrap_or_default() as u64);
+
+                        fetch_quotas.update(&host, port, throttle_time);
+
                         (
-                            Duration::from_millis(throttle_time.unwrap_or_default() as u64),
+                            throttle_time,
                             Self::extract_fetched_records(offsets_by_topic, topics),
                         )
                     });
@@ -1051,6 +2340,7 @@ where
                                 error_code: data.error_code.into(),
                                 fetch_offset: fetch.offset,
                                 high_watermark: data.high_watermark,
+                                preferred_read_replica: data.preferred_read_replica.filter(|&replica| replica >= 0),
                                 messages: data.message_set.messages,
                             })
                         })
@@ -1062,15 +2352,20 @@ where
             .collect()
     }
 
-    fn list_offsets(&self, topics: TopicsByBroker<'a, FetchOffset>) -> ListOffsets {
+    fn list_offsets(&self, topics: TopicsByBroker<'a, FetchOffset>, max_number_of_offsets: i32) -> ListOffsets {
         debug!("list offsets of topics: {:?}", topics);
 
         let requests = {
             let mut requests = Vec::new();
 
             for (((host, port), api_version), topics) in topics {
-                let request =
-                    KafkaRequest::list_offsets(api_version, self.next_correlation_id(), self.client_id(), topics);
+                let request = KafkaRequest::list_offsets(
+                    api_version,
+                    self.next_correlation_id(),
+                    self.client_id(),
+                    topics,
+                    max_number_of_offsets,
+                );
                 let request = self.send_request(AutoName::HostPort(&host, port), request)
                     .and_then(|res| {
                         if let KafkaResponse::ListOffsets(res) = res {
@@ -1122,21 +2417,137 @@ where
             .static_boxed()
     }
 
-    fn offset_commit<I>(
-        &self,
-        coordinator: &Broker,
-        group_id: Option<Cow<'a, str>>,
-        group_generation_id: Option<GenerationId>,
-        member_id: Option<Cow<'a, str>>,
-        retention_time: Option<Duration>,
-        offsets: I,
-    ) -> OffsetCommit
-    where
-        I: IntoIterator<Item = (TopicPartition<'a>, OffsetAndMetadata)>,
-    {
-        debug!("commit offsets to the `{:?}` group", group_id);
+    fn delete_records(&self, topics: TopicsByBroker<'a, Offset>) -> DeleteRecords {
+        debug!("delete records of topics: {:?}", topics);
 
-        let addr = AutoName::HostPort(coordinator.host(), coordinator.port());
+        let requests = topics
+            .into_iter()
+            .map(|(((host, port), api_version), topics)| {
+                let request = KafkaRequest::delete_records(
+                    api_version,
+                    self.next_correlation_id(),
+                    self.client_id(),
+                    self.config.request_timeout(),
+                    topics,
+                );
+
+                self.send_request(AutoName::HostPort(&host, port), request)
+                    .and_then(|res| {
+                        if let KafkaResponse::DeleteRecords(res) = res {
+                            Ok(res.topics)
+                        } else {
+                            bail!(UnexpectedResponse(res.api_key()))
+                        }
+                    })
+                    .map(|topics| {
+                        topics
+                            .into_iter()
+                            .map(|topic| {
+                                let partitions = topic
+                                    .partitions
+                                    .into_iter()
+                                    .map(|partition| DeletedRecords {
+                                        partition_id: partition.partition_id,
+                                        error_code: partition.error_code.into(),
+                                        low_watermark: partition.low_watermark,
+                                    })
+                                    .collect::<Vec<_>>();
+
+                                (topic.topic_name, partitions)
+                            })
+                            .collect::<Vec<_>>()
+                    })
+            })
+            .collect::<Vec<_>>();
+
+        future::join_all(requests)
+            .map(|responses| {
+                responses.into_iter().fold(HashMap::new(), |mut deleted, response| {
+                    for (topic_name, mut partitions) in response {
+                        deleted
+                            .entry(topic_name)
+                            .or_insert_with(Vec::new)
+                            .append(&mut partitions)
+                    }
+                    deleted
+                })
+            })
+            .static_boxed()
+    }
+
+    fn offsets_for_leader_epoch(&self, topics: TopicsByBroker<'a, LeaderEpoch>) -> OffsetsForLeaderEpoch {
+        debug!("look up the end offsets of leader epochs: {:?}", topics);
+
+        let requests = topics
+            .into_iter()
+            .map(|(((host, port), api_version), topics)| {
+                let request = KafkaRequest::offsets_for_leader_epoch(
+                    api_version,
+                    self.next_correlation_id(),
+                    self.client_id(),
+                    topics,
+                );
+
+                self.send_request(AutoName::HostPort(&host, port), request)
+                    .and_then(|res| {
+                        if let KafkaResponse::OffsetsForLeaderEpoch(res) = res {
+                            Ok(res.topics)
+                        } else {
+                            bail!(UnexpectedResponse(res.api_key()))
+                        }
+                    })
+                    .map(|topics| {
+                        topics
+                            .into_iter()
+                            .map(|topic| {
+                                let partitions = topic
+                                    .partitions
+                                    .into_iter()
+                                    .map(|partition| EpochEndOffset {
+                                        partition_id: partition.partition_id,
+                                        error_code: partition.error_code.into(),
+                                        leader_epoch: partition.leader_epoch,
+                                        end_offset: partition.end_offset,
+                                    })
+                                    .collect::<Vec<_>>();
+
+                                (topic.topic_name, partitions)
+                            })
+                            .collect::<Vec<_>>()
+                    })
+            })
+            .collect::<Vec<_>>();
+
+        future::join_all(requests)
+            .map(|responses| {
+                responses.into_iter().fold(HashMap::new(), |mut offsets, response| {
+                    for (topic_name, mut partitions) in response {
+                        offsets
+                            .entry(topic_name)
+                            .or_insert_with(Vec::new)
+                            .append(&mut partitions)
+                    }
+                    offsets
+                })
+            })
+            .static_boxed()
+    }
+
+    fn offset_commit<I>(
+        &self,
+        coordinator: &Broker,
+        group_id: Option<Cow<'a, str>>,
+        group_generation_id: Option<GenerationId>,
+        member_id: Option<Cow<'a, str>>,
+        retention_time: Option<Duration>,
+        offsets: I,
+    ) -> OffsetCommit
+    where
+        I: IntoIterator<Item = (TopicPartition<'a>, OffsetAndMetadata)>,
+    {
+        debug!("commit offsets to the `{:?}` group", group_id);
+
+        let addr = AutoName::HostPort(coordinator.host(), coordinator.port());
 
         let api_version = coordinator.api_version(ApiKeys::OffsetCommit).unwrap_or_default();
 
@@ -1228,26 +2639,36 @@ where
     }
 
     fn group_coordinator(&self, metadata: &Metadata, group_id: Cow<'a, str>) -> GroupCoordinator {
+        if let Some(coordinator) = (*self.state).borrow().group_coordinator(&group_id) {
+            trace!("use cached coordinator of group `{}`, broker #{}", group_id, coordinator.id());
+
+            return future::ok(coordinator).static_boxed();
+        }
+
         debug!("disover group coordinator of group `{}`", group_id);
 
-        let addr = {
+        let broker = {
             match self.least_loaded_broker(metadata) {
-                Ok((addr, _)) => addr,
+                Ok(broker) => broker,
                 Err(err) => {
                     return err.into();
                 }
             }
         };
 
+        let api_version = broker.api_version(ApiKeys::GroupCoordinator).unwrap_or_default();
+
+        let state = self.state.clone();
+        let coordinated_group_id = group_id.to_string();
+
         let request = KafkaRequest::group_coordinator(
-            0, // api_version,
+            api_version,
             self.next_correlation_id(),
             self.client_id(),
             group_id,
         );
 
-        self.service
-            .call((addr, request))
+        self.send_request(AutoName::HostPort(broker.host(), broker.port()), request)
             .and_then(|res| {
                 if let KafkaResponse::GroupCoordinator(res) = res {
                     Ok(res)
@@ -1255,13 +2676,19 @@ where
                     bail!(UnexpectedResponse(res.api_key()))
                 }
             })
-            .and_then(|res| {
+            .and_then(move |res| {
                 if res.error_code == KafkaCode::None as ErrorCode {
-                    Ok(Broker::new(
+                    let coordinator = Broker::new(
                         res.coordinator_id,
                         &res.coordinator_host,
                         res.coordinator_port as u16,
-                    ))
+                    );
+
+                    (*state)
+                        .borrow_mut()
+                        .update_group_coordinator(coordinated_group_id, coordinator.clone());
+
+                    Ok(coordinator)
                 } else {
                     bail!(KafkaError(res.error_code.into()))
                 }
@@ -1290,6 +2717,7 @@ where
         let api_version = coordinator.api_version(ApiKeys::JoinGroup).unwrap_or_default();
 
         let joined_group_id: String = (*group_id).to_owned();
+        let coordinated_group_id = joined_group_id.clone();
 
         let request = KafkaRequest::join_group(
             api_version,
@@ -1303,7 +2731,7 @@ where
             group_protocols,
         );
 
-        self.send_request(addr, request)
+        let response = self.send_request(addr, request)
             .and_then(|res| {
                 if let KafkaResponse::JoinGroup(res) = res {
                     Ok(res)
@@ -1324,8 +2752,9 @@ where
                 } else {
                     bail!(KafkaError(res.error_code.into()))
                 }
-            })
-            .static_boxed()
+            });
+
+        self.check_group_coordinator(&coordinated_group_id, response)
     }
 
     fn heartbeat(
@@ -1339,7 +2768,12 @@ where
 
         let addr = AutoName::HostPort(coordinator.host(), coordinator.port());
 
+        let heartbeat_group_id: String = (*group_id).to_owned();
+
+        let api_version = coordinator.api_version(ApiKeys::Heartbeat).unwrap_or_default();
+
         let request = KafkaRequest::heartbeat(
+            api_version,
             self.next_correlation_id(),
             self.client_id(),
             group_id,
@@ -1347,7 +2781,7 @@ where
             member_id,
         );
 
-        self.send_request(addr, request)
+        let response = self.send_request(addr, request)
             .and_then(|res| {
                 if let KafkaResponse::Heartbeat(res) = res {
                     Ok(res.error_code)
@@ -1361,8 +2795,9 @@ where
                 } else {
                     bail!(KafkaError(error_code.into()))
                 }
-            })
-            .static_boxed()
+            });
+
+        self.check_group_coordinator(&heartbeat_group_id, response)
     }
 
     fn leave_group(&self, coordinator: &Broker, group_id: Cow<'a, str>, member_id: Cow<'a, str>) -> LeaveGroup {
@@ -1371,10 +2806,19 @@ where
         let addr = AutoName::HostPort(coordinator.host(), coordinator.port());
 
         let leaved_group_id: String = (*group_id).to_owned();
+        let coordinated_group_id = leaved_group_id.clone();
 
-        let request = KafkaRequest::leave_group(self.next_correlation_id(), self.client_id(), group_id, member_id);
+        let api_version = coordinator.api_version(ApiKeys::LeaveGroup).unwrap_or_default();
 
-        self.send_request(addr, request)
+        let request = KafkaRequest::leave_group(
+            api_version,
+            self.next_correlation_id(),
+            self.client_id(),
+            group_id,
+            member_id,
+        );
+
+        let response = self.send_request(addr, request)
             .and_then(|res| {
                 if let KafkaResponse::LeaveGroup(res) = res {
                     Ok(res.error_code)
@@ -1388,8 +2832,9 @@ where
                 } else {
                     bail!(KafkaError(error_code.into()))
                 }
-            })
-            .static_boxed()
+            });
+
+        self.check_group_coordinator(&coordinated_group_id, response)
     }
 
     fn sync_group(
@@ -1407,7 +2852,12 @@ where
 
         let addr = AutoName::HostPort(coordinator.host(), coordinator.port());
 
+        let synced_group_id: String = (*group_id).to_owned();
+
+        let api_version = coordinator.api_version(ApiKeys::SyncGroup).unwrap_or_default();
+
         let request = KafkaRequest::sync_group(
+            api_version,
             self.next_correlation_id(),
             self.client_id(),
             group_id,
@@ -1416,7 +2866,7 @@ where
             group_assignment.unwrap_or_default(),
         );
 
-        self.send_request(addr, request)
+        let response = self.send_request(addr, request)
             .and_then(|res| {
                 if let KafkaResponse::SyncGroup(res) = res {
                     Ok(res)
@@ -1430,15 +2880,593 @@ where
                 } else {
                     bail!(KafkaError(res.error_code.into()))
                 }
+            });
+
+        self.check_group_coordinator(&synced_group_id, response)
+    }
+
+    /// Forget the cached coordinator of the group when it has been moved or become unavailable.
+    fn list_groups(&self, metadata: &Metadata) -> ListGroups {
+        let requests = metadata
+            .brokers()
+            .iter()
+            .map(|broker| {
+                let coordinator = broker.as_ref();
+                let request = KafkaRequest::list_groups(
+                    broker.api_version(ApiKeys::ListGroups).unwrap_or_default(),
+                    self.next_correlation_id(),
+                    self.client_id(),
+                );
+
+                self.send_request(AutoName::HostPort(broker.host(), broker.port()), request)
+                    .and_then(move |res| {
+                        if let KafkaResponse::ListGroups(res) = res {
+                            if res.error_code != KafkaCode::None as ErrorCode {
+                                bail!(KafkaError(res.error_code.into()))
+                            }
+
+                            Ok(res.groups
+                                .into_iter()
+                                .map(|group| GroupListing {
+                                    group_id: group.group_id,
+                                    protocol_type: group.protocol_type,
+                                    coordinator,
+                                })
+                                .collect::<Vec<_>>())
+                        } else {
+                            bail!(UnexpectedResponse(res.api_key()))
+                        }
+                    })
+            })
+            .collect::<Vec<_>>();
+
+        future::join_all(requests)
+            .map(|groups| groups.into_iter().flat_map(|groups| groups).collect())
+            .static_boxed()
+    }
+
+    fn describe_group(&self, coordinator: &Broker, group_id: String) -> StaticBoxFuture<GroupDescription> {
+        debug!("describe the `{}` group", group_id);
+
+        let addr = AutoName::HostPort(coordinator.host(), coordinator.port());
+        let api_version = coordinator.api_version(ApiKeys::DescribeGroups).unwrap_or_default();
+        let coordinator = coordinator.as_ref();
+        let described_group_id = group_id.clone();
+
+        let request = KafkaRequest::describe_groups(
+            api_version,
+            self.next_correlation_id(),
+            self.client_id(),
+            vec![group_id.clone().into()],
+        );
+
+        let response = self.send_request(addr, request).and_then(move |res| {
+            let group = if let KafkaResponse::DescribeGroups(res) = res {
+                res.groups
+                    .into_iter()
+                    .find(|group| group.group_id == described_group_id)
+                    .ok_or_else(|| UnexpectedResponse(ApiKeys::DescribeGroups))?
+            } else {
+                bail!(UnexpectedResponse(res.api_key()))
+            };
+
+            if group.error_code != KafkaCode::None as ErrorCode {
+                bail!(KafkaError(group.error_code.into()))
+            }
+
+            let is_consumer = group.protocol_type == CONSUMER_PROTOCOL;
+            let members = group
+                .members
+                .into_iter()
+                .map(|member| {
+                    let assignment = if is_consumer && !member.member_assignment.is_empty() {
+                        Some(
+                            Schema::deserialize(&member.member_assignment[..])
+                                .chain_err(|| "fail to deserialize assignment")?,
+                        )
+                    } else {
+                        None
+                    };
+
+                    Ok(GroupMemberDescription {
+                        member_id: member.member_id,
+                        client_id: member.client_id,
+                        client_host: member.client_host,
+                        assignment,
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            Ok(GroupDescription {
+                group_id: group.group_id,
+                state: group.state.as_str().into(),
+                protocol_type: group.protocol_type,
+                protocol: group.protocol,
+                coordinator,
+                members,
+            })
+        });
+
+        self.check_group_coordinator(&group_id, response)
+    }
+
+    fn check_group_coordinator<F>(&self, group_id: &str, future: F) -> StaticBoxFuture<F::Item>
+    where
+        F: Future<Error = Error> + 'static,
+        F::Item: 'static,
+    {
+        let state = self.state.clone();
+        let group_id = group_id.to_owned();
+
+        future
+            .map_err(move |err| {
+                match *err.kind() {
+                    KafkaError(KafkaCode::NotCoordinator) | KafkaError(KafkaCode::CoordinatorNotAvailable) => {
+                        debug!("coordinator of group `{}` is outdated, {}", group_id, err);
+
+                        (*state).borrow_mut().invalidate_group_coordinator(&group_id);
+                    }
+                    _ => {}
+                }
+
+                err
             })
             .static_boxed()
     }
 }
 
+impl<'a> Inner<'a>
+where
+    Self: 'static,
+{
+    fn create_topic(inner: Rc<Inner<'a>>, metadata: &Metadata, topic: CreateTopicsTopic<'a>) -> CreateTopic {
+        debug!(
+            "create topic `{}` with {} partitions and {} replicas",
+            topic.topic_name, topic.num_partitions, topic.replication_factor
+        );
+
+        let topic_name = topic.topic_name.to_string();
+
+        let response = Inner::send_to_controller(inner, metadata, move |inner, broker| {
+            KafkaRequest::create_topics(
+                broker.api_version(ApiKeys::CreateTopics).unwrap_or_default(),
+                inner.next_correlation_id(),
+                inner.client_id(),
+                inner.config.request_timeout(),
+                vec![topic.clone()],
+            )
+        });
+
+        response
+            .and_then(move |res| {
+                if let KafkaResponse::CreateTopics(res) = res {
+                    topic_error(
+                        &topic_name,
+                        res.topics.iter().map(|status| (status.topic_name.as_str(), status.error_code)),
+                    )
+                } else {
+                    bail!(UnexpectedResponse(res.api_key()))
+                }
+            })
+            .static_boxed()
+    }
+
+    fn delete_topic(inner: Rc<Inner<'a>>, metadata: &Metadata, topic_name: Cow<'a, str>) -> DeleteTopic {
+        debug!("delete topic `{}`", topic_name);
+
+        let deleted_topic_name = topic_name.to_string();
+
+        let response = Inner::send_to_controller(inner, metadata, move |inner, broker| {
+            KafkaRequest::delete_topics(
+                broker.api_version(ApiKeys::DeleteTopics).unwrap_or_default(),
+                inner.next_correlation_id(),
+                inner.client_id(),
+                inner.config.request_timeout(),
+                vec![topic_name.clone()],
+            )
+        });
+
+        response
+            .and_then(move |res| {
+                if let KafkaResponse::DeleteTopics(res) = res {
+                    topic_error(
+                        &deleted_topic_name,
+                        res.topics.iter().map(|status| (status.topic_name.as_str(), status.error_code)),
+                    )
+                } else {
+                    bail!(UnexpectedResponse(res.api_key()))
+                }
+            })
+            .static_boxed()
+    }
+
+    fn describe_configs<I>(inner: Rc<Inner<'a>>, metadata: &Metadata, resources: I) -> DescribeConfigs
+    where
+        I: IntoIterator<Item = ConfigResource>,
+    {
+        let resources = resources.into_iter().map(|resource| (resource, ()));
+        let brokers = match inner.configs_by_broker(ApiKeys::DescribeConfigs, metadata, resources) {
+            Ok(brokers) => brokers,
+            Err(err) => return future::err(err).static_boxed(),
+        };
+
+        debug!("describe configs of resources: {:?}", brokers);
+
+        let requests = brokers
+            .into_iter()
+            .map(|(((host, port), api_version), resources)| {
+                let request = KafkaRequest::describe_configs(
+                    api_version,
+                    inner.next_correlation_id(),
+                    inner.client_id(),
+                    resources
+                        .into_iter()
+                        .map(|(resource, _)| (resource.resource_type(), resource.resource_name().into(), None))
+                        .collect(),
+                );
+
+                inner
+                    .send_request(AutoName::HostPort(&host, port), request)
+                    .and_then(|res| {
+                        if let KafkaResponse::DescribeConfigs(res) = res {
+                            res.resources
+                                .into_iter()
+                                .map(|status| {
+                                    resource_error(status.error_code, status.error_message.as_ref())?;
+
+                                    let resource = ConfigResource::from_resource(
+                                        status.resource_type,
+                                        &status.resource_name,
+                                    ).ok_or_else(|| UnexpectedResponse(ApiKeys::DescribeConfigs))?;
+                                    let entries = status
+                                        .config_entries
+                                        .into_iter()
+                                        .map(|entry| ConfigEntry {
+                                            name: entry.config_name,
+                                            value: entry.config_value,
+                                            source: entry.config_source.into(),
+                                            read_only: entry.read_only,
+                                            sensitive: entry.is_sensitive,
+                                        })
+                                        .collect();
+
+                                    Ok((resource, entries))
+                                })
+                                .collect::<Result<Vec<_>>>()
+                        } else {
+                            bail!(UnexpectedResponse(res.api_key()))
+                        }
+                    })
+            })
+            .collect::<Vec<_>>();
+
+        future::join_all(requests)
+            .map(|responses| responses.into_iter().flat_map(|resources| resources).collect())
+            .static_boxed()
+    }
+
+    fn alter_configs<I>(inner: Rc<Inner<'a>>, metadata: &Metadata, changes: I) -> AlterConfigs
+    where
+        I: IntoIterator<Item = (ConfigResource, HashMap<String, String>)>,
+    {
+        let brokers = match inner.configs_by_broker(ApiKeys::AlterConfigs, metadata, changes) {
+            Ok(brokers) => brokers,
+            Err(err) => return future::err(err).static_boxed(),
+        };
+
+        debug!("alter configs of resources: {:?}", brokers);
+
+        let requests = brokers
+            .into_iter()
+            .map(|(((host, port), api_version), changes)| {
+                let request = KafkaRequest::alter_configs(
+                    api_version,
+                    inner.next_correlation_id(),
+                    inner.client_id(),
+                    changes
+                        .into_iter()
+                        .map(|(resource, configs)| (resource.resource_type(), resource.resource_name().into(), configs))
+                        .collect(),
+                    false,
+                );
+
+                inner
+                    .send_request(AutoName::HostPort(&host, port), request)
+                    .and_then(|res| {
+                        if let KafkaResponse::AlterConfigs(res) = res {
+                            for status in res.resources {
+                                resource_error(status.error_code, status.error_message.as_ref())?;
+                            }
+
+                            Ok(())
+                        } else {
+                            bail!(UnexpectedResponse(res.api_key()))
+                        }
+                    })
+            })
+            .collect::<Vec<_>>();
+
+        future::join_all(requests).map(|_| ()).static_boxed()
+    }
+
+    /// Group the resources by the brokers which the requests should be sent to.
+    ///
+    /// The broker configurations must be sent to the broker itself,
+    /// and the topic configurations could be sent to any broker.
+    fn configs_by_broker<I, T>(
+        &self,
+        api_key: ApiKeys,
+        metadata: &Metadata,
+        resources: I,
+    ) -> Result<ConfigsByBroker<T>>
+    where
+        I: IntoIterator<Item = (ConfigResource, T)>,
+    {
+        let mut brokers = HashMap::new();
+
+        for (resource, value) in resources {
+            let broker = match resource {
+                ConfigResource::Broker(node_id) => metadata
+                    .brokers()
+                    .iter()
+                    .find(|broker| broker.id() == node_id)
+                    .ok_or_else(|| KafkaError(KafkaCode::BrokerNotAvailable))?,
+                ConfigResource::Topic(_) => self.least_loaded_broker(metadata)?,
+            };
+            let api_version = broker.api_version(api_key).unwrap_or_default();
+
+            brokers
+                .entry(((broker.host().to_owned(), broker.port()), api_version))
+                .or_insert_with(Vec::new)
+                .push((resource, value));
+        }
+
+        Ok(brokers)
+    }
+
+    /// Send the request to the controller of the cluster.
+    ///
+    /// The request is sent to the controller in the metadata first, if it is known (Metadata v1 or later),
+    /// and the other brokers will be tried in turn until one of them doesn't answer with `NotController`.
+    /// The metadata will be reloaded in the background if the controller has moved.
+    fn send_to_controller<F>(inner: Rc<Inner<'a>>, metadata: &Metadata, build_request: F) -> FutureResponse
+    where
+        F: Fn(&Inner<'a>, &Broker) -> KafkaRequest<'a> + 'static,
+    {
+        let controller = metadata.controller().map(|broker| broker.as_ref());
+        let mut brokers = metadata.brokers().to_vec();
+
+        if let Some(controller) = controller {
+            brokers.sort_by_key(|broker| broker.as_ref() != controller);
+        }
+
+        let send_request = future::loop_fn(brokers.into_iter(), move |mut brokers| match brokers.next() {
+            Some(broker) => {
+                let request = build_request(&inner, &broker);
+                let inner = inner.clone();
+
+                Either::A(
+                    inner
+                        .send_request(AutoName::HostPort(broker.host(), broker.port()), request)
+                        .map(move |res| {
+                            if is_not_controller(&res) {
+                                trace!("broker #{} is not the controller", broker.id());
+
+                                if controller == Some(broker.as_ref()) {
+                                    debug!("controller has moved from broker #{}, reload metadata", broker.id());
+
+                                    let reload = LoadMetadata::new(inner.clone()).map(|_| ()).map_err(|err| {
+                                        warn!("fail to reload metadata, {}", err);
+                                    });
+
+                                    inner.runtime.spawn(reload);
+                                }
+
+                                Loop::Continue(brokers)
+                            } else {
+                                Loop::Break(res)
+                            }
+                        }),
+                )
+            }
+            None => Either::B(future::err(KafkaError(KafkaCode::NotController).into())),
+        });
+
+        send_request.static_boxed()
+    }
+}
+
+/// Find the partition error caused by the stale metadata.
+fn stale_metadata<I>(mut error_codes: I) -> Option<KafkaCode>
+where
+    I: Iterator<Item = KafkaCode>,
+{
+    error_codes.find(|error_code| error_code.is_stale_metadata())
+}
+
+/// Select the replica to fetch the partition from, or `None` to fetch from the leader.
+///
+/// The brokers only serve the consumers from the followers since Fetch v11 (KIP-392),
+/// so the leader is used unless the version negotiated with the selected follower is new enough.
+fn select_replica<'b>(
+    selector: &ReplicaSelector,
+    metadata: &'b Metadata,
+    tp: &TopicPartition,
+    api_key: ApiKeys,
+) -> Option<&'b Broker> {
+    metadata
+        .find_partition(tp)
+        .and_then(|partition| {
+            selector
+                .select(tp, partition, metadata)
+                .map(|replica| (partition.leader, replica))
+        })
+        .and_then(|(leader, replica)| {
+            metadata.find_broker(replica).and_then(|broker| {
+                if leader == Some(replica)
+                    || broker.api_version(api_key).unwrap_or_default() >= FETCH_FROM_FOLLOWER_API_VERSION
+                {
+                    Some(broker)
+                } else {
+                    debug!(
+                        "fetch {} from the leader, broker #{} doesn't serve the fetches from followers",
+                        tp,
+                        replica.index()
+                    );
+
+                    None
+                }
+            })
+        })
+}
+
+fn is_not_controller(res: &KafkaResponse) -> bool {
+    let not_controller = KafkaCode::NotController as ErrorCode;
+
+    match *res {
+        KafkaResponse::CreateTopics(ref res) => res.topics
+            .iter()
+            .any(|status| status.error_code == not_controller),
+        KafkaResponse::DeleteTopics(ref res) => res.topics
+            .iter()
+            .any(|status| status.error_code == not_controller),
+        _ => false,
+    }
+}
+
+fn resource_error(error_code: ErrorCode, error_message: Option<&String>) -> Result<()> {
+    if error_code != KafkaCode::None as ErrorCode {
+        warn!("fail to access the configs, {}", error_message.map_or("", |s| s.as_str()));
+
+        bail!(KafkaError(error_code.into()))
+    }
+
+    Ok(())
+}
+
+fn topic_error<'t, I>(topic_name: &str, topics: I) -> Result<()>
+where
+    I: Iterator<Item = (&'t str, ErrorCode)>,
+{
+    for (name, error_code) in topics {
+        if name == topic_name && error_code != KafkaCode::None as ErrorCode {
+            bail!(KafkaError(error_code.into()))
+        }
+    }
+
+    Ok(())
+}
+
 pub type FetchMetadata = StaticBoxFuture<Rc<Metadata>>;
+pub type TopicMetadata = StaticBoxFuture<Rc<Metadata>>;
+pub type Supports = StaticBoxFuture<bool>;
+pub type WarmUp = StaticBoxFuture;
+pub type Shutdown = StaticBoxFuture<ShutdownReport>;
 pub type FetchApiVersions = StaticBoxFuture<UsableApiVersions>;
 pub type LoadApiVersions = StaticBoxFuture<HashMap<BrokerRef, UsableApiVersions>>;
 
+/// The hostname of the broker, used to verify the broker certificate.
+fn hostname(name: &AutoName) -> Option<String> {
+    match *name {
+        AutoName::HostPort(host, _) => Some(host.to_owned()),
+        AutoName::Auto(name) => name.rsplitn(2, ':').last().map(|host| host.to_owned()),
+        _ => None,
+    }
+}
+
+/// The key of the resolved addresses, or `None` if the name is an address.
+fn address_key(name: &AutoName) -> Option<String> {
+    match *name {
+        AutoName::HostPort(host, port) => Some(format!("{}:{}", host, port)),
+        AutoName::Auto(name) => Some(name.to_owned()),
+        _ => None,
+    }
+}
+
+/// The connection to the broker could not be established.
+fn is_connect_error(err: &Error) -> bool {
+    match *err.kind() {
+        IoError(ref err) => match err.kind() {
+            io::ErrorKind::NotConnected | io::ErrorKind::AddrNotAvailable | io::ErrorKind::ConnectionRefused => true,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Check whether the broker closed the connection instead of answering the request.
+fn is_connection_closed(err: &Error) -> bool {
+    match *err.kind() {
+        IoError(ref err) => match err.kind() {
+            io::ErrorKind::UnexpectedEof
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::BrokenPipe => true,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Connect to the resolved addresses in order, return the first connected address.
+///
+/// The connected address will be preferred by the following requests.
+fn connect_any<'a>(
+    service: Rc<InFlightMiddleware<TimeoutMiddleware<Upstream<'a>>>>,
+    runtime: Runtime,
+    timeout: Duration,
+    addresses: Addresses,
+    key: Option<String>,
+    addrs: Vec<SocketAddr>,
+) -> StaticBoxFuture<SocketAddr>
+where
+    KafkaService<'a>: 'static,
+{
+    let connected = future::loop_fn(addrs.into_iter(), move |mut addrs| {
+        let addr = match addrs.next() {
+            Some(addr) => addr,
+            None => {
+                return Either::A(future::err(
+                    IoError(io::Error::new(io::ErrorKind::NotConnected, "no more address")).into(),
+                ))
+            }
+        };
+
+        Either::B(
+            runtime
+                .timeout(service.warm_up(addr), timeout)
+                .then(move |res: Result<()>| -> Result<Loop<SocketAddr, _>> {
+                    match res {
+                        Ok(()) => Ok(Loop::Break(addr)),
+                        Err(err) => {
+                            warn!("fail to connect {}, {}", addr, err);
+
+                            Ok(Loop::Continue(addrs))
+                        }
+                    }
+                }),
+        )
+    });
+
+    connected
+        .map(move |addr| {
+            if let Some(key) = key {
+                if let Some(resolved) = addresses.borrow_mut().get_mut(&key) {
+                    if let Some(pos) = resolved.addrs.iter().position(|resolved| *resolved == addr) {
+                        let addr = resolved.addrs.remove(pos);
+
+                        resolved.addrs.insert(0, addr);
+                    }
+                }
+            }
+
+            addr
+        })
+        .static_boxed()
+}
+
+type ConfigsByBroker<T> = HashMap<((String, u16), ApiVersion), Vec<(ConfigResource, T)>>;
+
 type TopicsByBroker<'a, T> = HashMap<((String, u16), ApiVersion), HashMap<Cow<'a, str>, Vec<(PartitionId, T)>>>;
 
 impl State {
@@ -1455,24 +3483,100 @@ impl State {
                 GetMetadata::Loading(receiver)
             }
             MetadataStatus::Loaded(ref metadata) => GetMetadata::Loaded(metadata.clone()),
+            MetadataStatus::Failed(ref reason) => GetMetadata::Failed(reason.clone()),
         }
     }
 
     pub fn refresh_metadata(&mut self) {
-        if let MetadataStatus::Loaded(_) = self.metadata_status {
-            self.metadata_status = MetadataStatus::Loading(Default::default());
+        match self.metadata_status {
+            MetadataStatus::Loaded(_) | MetadataStatus::Failed(_) => {
+                self.metadata_status = MetadataStatus::Loading(Default::default());
+            }
+            MetadataStatus::Loading(_) => {}
+        }
+    }
+
+    /// Fail the pending metadata requests, until the metadata is loaded again.
+    pub fn fail_metadata(&mut self, err: &Error) {
+        if let MetadataStatus::Loading(_) = self.metadata_status {
+            let reason = err.to_string();
+            let status = mem::replace(&mut self.metadata_status, MetadataStatus::Failed(reason.clone()));
+
+            if let MetadataStatus::Loading(senders) = status {
+                for sender in senders.into_inner() {
+                    drop(sender.send(Err(ErrorKind::MetadataError(reason.clone()).into())));
+                }
+            }
         }
     }
 
-    pub fn update_metadata(&mut self, metadata: &Rc<Metadata>) {
+    pub fn group_coordinator(&self, group_id: &str) -> Option<Broker> {
+        self.group_coordinators.get(group_id).cloned()
+    }
+
+    pub fn update_group_coordinator(&mut self, group_id: String, coordinator: Broker) {
+        self.group_coordinators.insert(group_id, coordinator);
+    }
+
+    pub fn invalidate_group_coordinator(&mut self, group_id: &str) {
+        self.group_coordinators.remove(group_id);
+    }
+
+    pub fn update_metadata(&mut self, metadata: &Rc<Metadata>, now: Instant) {
+        self.topics_refreshed(metadata, now);
+        self.negotiate_metadata_api_version(metadata);
+
         let status = mem::replace(&mut self.metadata_status, MetadataStatus::Loaded(metadata.clone()));
 
         if let MetadataStatus::Loading(senders) = status {
             for sender in senders.into_inner() {
-                drop(sender.send(metadata.clone()));
+                drop(sender.send(Ok(metadata.clone())));
             }
         }
     }
+
+    /// Update the metadata merged with the fetched topics.
+    pub fn update_topic_metadata(&mut self, metadata: &Rc<Metadata>, fetched: &Metadata, now: Instant) {
+        self.topics_refreshed(fetched, now);
+        self.negotiate_metadata_api_version(metadata);
+
+        // the full refresh in progress will supersede the merged metadata
+        if let MetadataStatus::Loaded(_) = self.metadata_status {
+            self.metadata_status = MetadataStatus::Loaded(metadata.clone());
+        }
+    }
+
+    /// Negotiate the version of Metadata requests with all the brokers,
+    /// since the requests are sent to the bootstrap hosts which could be any of them.
+    fn negotiate_metadata_api_version(&mut self, metadata: &Metadata) {
+        self.metadata_api_version = metadata
+            .brokers()
+            .iter()
+            .map(|broker| broker.api_version(ApiKeys::Metadata).unwrap_or_default())
+            .min()
+            .unwrap_or_default();
+    }
+
+    fn topics_refreshed(&mut self, metadata: &Metadata, now: Instant) {
+        for (topic_name, partitions) in metadata.topics() {
+            if !partitions.is_empty() {
+                self.topics_refreshed.insert(topic_name.to_owned(), now);
+            }
+        }
+    }
+
+    /// The topics which are unknown or have not been refreshed in the `max_age`.
+    pub fn stale_topics(&self, topic_names: &[String], max_age: Duration, now: Instant) -> Vec<String> {
+        topic_names
+            .iter()
+            .filter(|topic_name| {
+                self.topics_refreshed
+                    .get(topic_name.as_str())
+                    .map_or(true, |refreshed| now.duration_since(*refreshed) >= max_age)
+            })
+            .cloned()
+            .collect()
+    }
 }
 
 /// The future of loaded metadata
@@ -1548,7 +3652,14 @@ where
                     Err(err) => return Err(err),
                 },
                 Loading::Finished(ref metadata) => {
-                    (*self.inner.state).borrow_mut().update_metadata(metadata);
+                    (*self.inner.state)
+                        .borrow_mut()
+                        .update_metadata(metadata, self.inner.runtime.now());
+
+                    self.inner
+                        .config
+                        .listeners
+                        .notify(|listener| listener.on_metadata_refresh(metadata));
 
                     return Ok(Async::Ready(metadata.clone()));
                 }
@@ -1621,3 +3732,54 @@ where
         StaticBoxFuture::new(self)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use client::PartitionInfo;
+    use consumer::RackAwareReplicaSelector;
+    use protocol::{UsableApiVersion, UsableApiVersions};
+
+    fn rack_metadata(fetch_version: ApiVersion) -> Metadata {
+        let api_versions = UsableApiVersions::new(vec![
+            UsableApiVersion {
+                api_key: ApiKeys::Fetch,
+                min_version: 0,
+                max_version: fetch_version,
+            },
+        ]);
+        let partition = PartitionInfo {
+            partition_id: 0,
+            leader: Some(BrokerRef::new(0)),
+            replicas: vec![BrokerRef::new(0), BrokerRef::new(1)],
+            in_sync_replicas: vec![BrokerRef::new(0), BrokerRef::new(1)],
+        };
+
+        Metadata::with_brokers(vec![
+            Broker::new(0, "localhost", 9092).with_rack(Some("a".to_owned())),
+            Broker::new(1, "localhost", 9093).with_rack(Some("b".to_owned())),
+        ]).merge_topics(&Metadata::with_topics(vec![("topic".to_owned(), vec![partition])]))
+            .with_api_versions(&HashMap::from_iter(vec![
+                (BrokerRef::new(0), api_versions.clone()),
+                (BrokerRef::new(1), api_versions),
+            ]))
+    }
+
+    #[test]
+    fn test_select_replica() {
+        let tp = topic_partition!("topic", 0);
+        let selector = RackAwareReplicaSelector::new("b");
+
+        let metadata = rack_metadata(11);
+        let broker = select_replica(&selector, &metadata, &tp, ApiKeys::Fetch).unwrap();
+
+        assert_eq!(broker.id(), 1);
+        assert_eq!(broker.api_version(ApiKeys::Fetch), Some(FETCH_FROM_FOLLOWER_API_VERSION));
+
+        // the follower doesn't serve the consumers before Fetch v11
+        let metadata = rack_metadata(10);
+
+        assert!(select_replica(&selector, &metadata, &tp, ApiKeys::Fetch).is_none());
+    }
+}
@@ -1,7 +1,8 @@
 use std::collections::HashMap;
 
+use client::Feature;
 use network::TopicPartition;
-use protocol::{ApiKeys, ApiVersion, NodeId, PartitionId, UsableApiVersions, SUPPORTED_API_VERSIONS};
+use protocol::{ApiKeys, ApiVersion, NodeId, PartitionId, UsableApiVersions};
 
 /// A trait for representation of a subset of the nodes, topics, and partitions in the Kafka
 /// cluster.
@@ -30,6 +31,10 @@ pub trait Cluster {
     /// topic exists)
     fn partitions_for_topic(&self, topic_name: &str) -> Option<Vec<TopicPartition>>;
 
+    /// Get the list of partitions for this topic which have a leader (return `None` if no such
+    /// topic exists)
+    fn available_partitions_for_topic(&self, topic_name: &str) -> Option<Vec<TopicPartition>>;
+
     /// Get the list of partitions whose leader is this node
     fn partitions_for_broker(&self, broker: BrokerRef) -> Vec<TopicPartition>;
 }
@@ -50,6 +55,9 @@ pub struct Broker {
 
     /// The version ranges of requests supported by the broker.
     api_versions: Option<UsableApiVersions>,
+
+    /// The rack of the broker, advertised since Metadata v1.
+    rack: Option<String>,
 }
 
 impl Broker {
@@ -59,6 +67,7 @@ impl Broker {
             host: host.to_owned(),
             port,
             api_versions: None,
+            rack: None,
         }
     }
 
@@ -85,25 +94,33 @@ impl Broker {
         (&self.host, self.port)
     }
 
-    fn api_versions(&self) -> Option<&UsableApiVersions> {
+    /// The rack of the broker, or `None` if the broker is not configured with `broker.rack`.
+    pub fn rack(&self) -> Option<&str> {
+        self.rack.as_ref().map(|rack| rack.as_str())
+    }
+
+    pub fn api_versions(&self) -> Option<&UsableApiVersions> {
         self.api_versions.as_ref()
     }
 
+    /// The API version negotiated with this broker, or `None` if the API versions are unknown.
     pub fn api_version(&self, api_key: ApiKeys) -> Option<ApiVersion> {
-        if api_key == ApiKeys::Fetch {
-            return Some(1);
-        }
-        let supported_version: i16 = SUPPORTED_API_VERSIONS
-            .find(api_key).map(|v| v.max_version).unwrap_or(0);
+        self.api_versions.as_ref().map(|api_versions| {
+            let api_version = api_versions.negotiate(api_key).unwrap_or_default();
+
+            trace!("use API version {} of {:?} for broker #{}", api_version, api_key, self.node_id);
+
+            api_version
+        })
+    }
+
+    /// Check whether the feature is supported by the API versions of this broker.
+    ///
+    /// The feature is considered unsupported if the API versions are unknown.
+    pub fn supports(&self, feature: Feature) -> bool {
         self.api_versions
             .as_ref()
-            .and_then(|api_versions|
-                     api_versions.find(api_key)
-                    .map(|api_version| {
-                        debug!("Api Key {:?}, current: {}, supported: {}", api_key, api_version.max_version, supported_version);
-                        ::std::cmp::min(api_version.max_version, supported_version)
-                    })
-            )
+            .map_or(false, |api_versions| feature.is_supported_by(api_versions))
     }
 
     pub fn with_api_versions(&self, api_versions: Option<UsableApiVersions>) -> Self {
@@ -112,6 +129,17 @@ impl Broker {
             host: self.host.clone(),
             port: self.port,
             api_versions,
+            rack: self.rack.clone(),
+        }
+    }
+
+    pub fn with_rack(&self, rack: Option<String>) -> Self {
+        Broker {
+            node_id: self.node_id,
+            host: self.host.clone(),
+            port: self.port,
+            api_versions: self.api_versions.clone(),
+            rack,
         }
     }
 }
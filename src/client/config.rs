@@ -1,9 +1,16 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
 use std::time::Duration;
 
-use tokio_retry::strategy::{jitter, ExponentialBackoff};
-use tokio_timer::{wheel, Timer};
+use native_tls::{Certificate, Pkcs12, TlsConnector};
 
-use client::KafkaVersion;
+use client::{ClientListeners, ErrorClass, KafkaVersion, QueueFullBehavior, RequestQueues, RequestTimeouts,
+             RetryPolicies, RetryPolicy};
+use errors::{ErrorKind, Result};
+use network::{SaslAuthenticator, SaslMechanism, SharedTransport, SocketOptions, TlsSettings};
+use protocol::{ApiKeys, CrcPolicy};
 
 /// The default milliseconds after which we close the idle connections.
 ///
@@ -11,6 +18,13 @@ use client::KafkaVersion;
 /// [`ClientConfig::max_connection_idle`](struct.ClientConfig.html#max_connection_idle.v)
 pub const DEFAULT_MAX_CONNECTION_IDLE_TIMEOUT_MILLIS: u64 = 5000;
 
+/// The default maximum number of unacknowledged requests the client will send on a single
+/// connection before blocking.
+///
+/// Defaults to 5, see
+/// [`ClientConfig::max_in_flight_requests_per_connection`](struct.ClientConfig.html#max_in_flight_requests_per_connection.v)
+pub const DEFAULT_MAX_IN_FLIGHT_REQUESTS_PER_CONNECTION: usize = 5;
+
 /// The default milliseconds the client will wait for the response of a request.
 ///
 /// Defaults to 30 seconds, see
@@ -23,10 +37,17 @@ pub const DEFAULT_REQUEST_TIMEOUT_MILLS: u64 = 30_000;
 /// [`ClientConfig::metadata_max_age`](struct.ClientConfig.html#metadata_max_age.v)
 pub const DEFAULT_METADATA_MAX_AGE_MILLS: u64 = 5 * 60 * 1000;
 
-/// The default milliseconds of the timer tick duration.
+/// The default number of times to retry loading the metadata from the bootstrap servers.
 ///
-/// Defaults to 100 ms
-pub const DEFAULT_TIMER_TICK_MILLS: u64 = 100;
+/// Defaults to 5, see
+/// [`ClientConfig::metadata_bootstrap_retries`](struct.ClientConfig.html#metadata_bootstrap_retries.v)
+pub const DEFAULT_METADATA_BOOTSTRAP_RETRIES: usize = 5;
+
+/// The default milliseconds after which the resolved addresses of a broker are resolved again.
+///
+/// Defaults to 1 minute, see
+/// [`ClientConfig::dns_refresh_interval`](struct.ClientConfig.html#dns_refresh_interval.v)
+pub const DEFAULT_DNS_REFRESH_INTERVAL_MILLIS: u64 = 60_000;
 
 /// The default time to wait before attempting to retry a failed request to a given topic partition.
 ///
@@ -34,8 +55,21 @@ pub const DEFAULT_TIMER_TICK_MILLS: u64 = 100;
 /// [`ClientConfig::retry_backoff`](struct.ClientConfig.html#retry_backoff.v)
 pub const DEFAULT_RETRY_BACKOFF_MILLIS: u64 = 100;
 
+/// The default time to wait before attempting to reconnect to a broker which failed to connect.
+///
+/// Defaults to 50 ms, see
+/// [`ClientConfig::reconnect_backoff`](struct.ClientConfig.html#reconnect_backoff.v)
+pub const DEFAULT_RECONNECT_BACKOFF_MILLIS: u64 = 50;
+
+/// The default maximum time to wait before attempting to reconnect to a broker
+/// which has repeatedly failed to connect.
+///
+/// Defaults to 1 second, see
+/// [`ClientConfig::reconnect_backoff_max`](struct.ClientConfig.html#reconnect_backoff_max.v)
+pub const DEFAULT_RECONNECT_BACKOFF_MAX_MILLIS: u64 = 1000;
+
 /// Configuration for the Kafka Client.
-#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 #[serde(default)]
 pub struct ClientConfig {
     /// A list of host/port pairs to use for establishing the initial connection to the Kafka
@@ -55,11 +89,26 @@ pub struct ClientConfig {
     #[serde(rename = "connection.max.idle.ms")]
     pub max_connection_idle: u64,
 
+    /// The maximum number of unacknowledged requests the client will send on a single connection
+    /// before blocking.
+    ///
+    /// Note that if this setting is set to be greater than 1 and there are failed sends,
+    /// there is a risk of message re-ordering due to retries.
+    #[serde(rename = "max.in.flight.requests.per.connection")]
+    pub max_in_flight_requests_per_connection: usize,
+
     /// The maximum amount of time the client will wait for the response of a
     /// request.
     #[serde(rename = "request.timeout.ms")]
     pub request_timeout: u64,
 
+    /// The maximum amount of time the client will wait for the response of the requests of an API,
+    /// keyed by the API name, e.g. `JoinGroup` or `Metadata`, which overrides the `request.timeout.ms`.
+    ///
+    /// The connection of a timed out request is closed, and its other in-flight requests are failed.
+    #[serde(rename = "request.timeouts.ms")]
+    pub request_timeouts: BTreeMap<String, u64>,
+
     /// Request broker's supported API versions to adjust functionality to available protocol
     /// features.
     #[serde(rename = "api.version.request")]
@@ -82,9 +131,72 @@ pub struct ClientConfig {
     #[serde(rename = "metadata.max.age.ms")]
     pub metadata_max_age: u64,
 
+    /// The number of times to retry loading the initial metadata when all the bootstrap servers
+    /// are unavailable, the backoff starts from `retry.backoff.ms` and increases exponentially.
+    ///
+    /// The metadata requests fail once the retries are exhausted.
+    #[serde(rename = "metadata.bootstrap.retries")]
+    pub metadata_bootstrap_retries: usize,
+
+    /// The period of time in milliseconds after which the cached metadata of a topic is
+    /// considered stale, and only the stale topics will be refreshed when they are used.
+    #[serde(rename = "topic.metadata.refresh.interval.ms")]
+    pub topic_metadata_refresh_interval: u64,
+
+    /// The period of time in milliseconds after which the resolved addresses of a broker
+    /// are resolved again, so the client could follow the IP changes of the brokers.
+    #[serde(rename = "dns.refresh.interval.ms")]
+    pub dns_refresh_interval: u64,
+
+    /// How to handle the fetched messages whose CRC mismatched, to fail the fetch request (default),
+    /// skip the corrupted messages with a warning, or ignore the CRCs without calculating them.
+    ///
+    /// Valid values are: fail, skip, ignore.
+    #[serde(rename = "check.crcs")]
+    pub check_crcs: CrcPolicy,
+
+    /// Disable the Nagle's algorithm (TCP_NODELAY) of the broker connections,
+    /// so the small requests are sent without the delay.
+    #[serde(rename = "socket.nodelay")]
+    pub socket_nodelay: bool,
+
+    /// The size of the TCP send buffer (SO_SNDBUF) of the broker connections,
+    /// the OS default will be used if not set.
+    #[serde(rename = "socket.send.buffer.bytes")]
+    pub socket_send_buffer_bytes: Option<usize>,
+
+    /// The size of the TCP receive buffer (SO_RCVBUF) of the broker connections,
+    /// the OS default will be used if not set.
+    #[serde(rename = "socket.receive.buffer.bytes")]
+    pub socket_receive_buffer_bytes: Option<usize>,
+
+    /// The idle time in milliseconds before the TCP keepalive probes are sent on the broker connections,
+    /// the keepalive is disabled if not set.
+    #[serde(rename = "socket.keepalive.ms")]
+    pub socket_keepalive: Option<u64>,
+
+    /// The maximum number of the pending requests to a single broker,
+    /// including the requests waiting for the connection and the in-flight requests.
+    ///
+    /// Zero means the pending requests are not bounded.
+    #[serde(rename = "queue.buffering.max.requests")]
+    pub queue_buffering_max_requests: usize,
+
+    /// What to do with the new requests when the request queue of a broker is full,
+    /// to fail them with `QueueFull` at once (default), or wait for the pending requests.
+    ///
+    /// Valid values are: fail, block.
+    #[serde(rename = "queue.full.behavior")]
+    pub queue_full_behavior: QueueFullBehavior,
+
     /// Record metrics for client operations
     pub metrics: bool,
 
+    /// Pre-connect (and authenticate) to the leaders of the topics at startup,
+    /// so the first requests don't pay the latency to establish the connections.
+    #[serde(rename = "warm.up.topics")]
+    pub warm_up_topics: Vec<String>,
+
     /// Setting a value greater than zero will cause the client to resend any record
     /// whose send fails with a potentially transient error.
     pub retries: usize,
@@ -94,6 +206,128 @@ pub struct ClientConfig {
     /// This avoids repeatedly sending requests in a tight loop under some failure scenarios.
     #[serde(rename = "retry.backoff.ms")]
     pub retry_backoff: u64,
+
+    /// The retry policies for each class of errors,
+    /// which overrides the `retries` and `retry.backoff.ms` for the configured error classes.
+    #[serde(rename = "retry.policies")]
+    pub retry_policies: RetryPolicies,
+
+    /// The base amount of time to wait before attempting to reconnect to a broker
+    /// which failed to connect.
+    ///
+    /// The backoff increases exponentially with a random jitter for each consecutive failure,
+    /// up to the `reconnect.backoff.max.ms`.
+    #[serde(rename = "reconnect.backoff.ms")]
+    pub reconnect_backoff: u64,
+
+    /// The maximum amount of time to wait before attempting to reconnect to a broker
+    /// which has repeatedly failed to connect.
+    #[serde(rename = "reconnect.backoff.max.ms")]
+    pub reconnect_backoff_max: u64,
+
+    /// SASL mechanism to use for authentication.
+    ///
+    /// Supported: PLAIN, SCRAM-SHA-256, SCRAM-SHA-512.
+    #[serde(rename = "sasl.mechanism")]
+    pub sasl_mechanism: SaslMechanism,
+
+    /// SASL username for use with the PLAIN and SASL-SCRAM-.. mechanisms.
+    ///
+    /// The connections will be authenticated with SASL before sending any request
+    /// when it has been set.
+    #[serde(rename = "sasl.username")]
+    pub sasl_username: Option<String>,
+
+    /// SASL password for use with the PLAIN and SASL-SCRAM-.. mechanisms.
+    ///
+    /// The password is redacted from the debug output, and it is not serialized.
+    #[serde(rename = "sasl.password", skip_serializing)]
+    pub sasl_password: Option<String>,
+
+    /// Connect to the brokers with TLS/SSL.
+    #[serde(rename = "ssl.enabled")]
+    pub ssl_enabled: bool,
+
+    /// File path to the CA certificate (PEM or DER format) for verifying the broker's key.
+    ///
+    /// The system trusted CA certificates will be used if not set.
+    #[serde(rename = "ssl.ca.location")]
+    pub ssl_ca_location: Option<String>,
+
+    /// File path to the PKCS#12 archive with the client's certificate and private key,
+    /// used for the client authentication.
+    #[serde(rename = "ssl.keystore.location")]
+    pub ssl_keystore_location: Option<String>,
+
+    /// The password of the PKCS#12 archive.
+    ///
+    /// The password is redacted from the debug output, and it is not serialized.
+    #[serde(rename = "ssl.keystore.password", skip_serializing)]
+    pub ssl_keystore_password: Option<String>,
+
+    /// Verify the broker hostname against its certificate.
+    #[serde(rename = "ssl.endpoint.identification.enabled")]
+    pub ssl_hostname_verification: bool,
+
+    /// The listeners of the client events, e.g. the broker connections and the metadata refreshes.
+    ///
+    /// The listeners could only be registered in code, they are not serialized.
+    #[serde(skip)]
+    pub listeners: ClientListeners,
+
+    /// The transport used to connect to the brokers, e.g. through a proxy, defaults to TCP.
+    ///
+    /// The transport could only be set in code, it is not serialized.
+    #[serde(skip)]
+    pub transport: SharedTransport,
+}
+
+impl fmt::Debug for ClientConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ClientConfig")
+            .field("hosts", &self.hosts)
+            .field("client_id", &self.client_id)
+            .field("max_connection_idle", &self.max_connection_idle)
+            .field("max_in_flight_requests_per_connection", &self.max_in_flight_requests_per_connection)
+            .field("request_timeout", &self.request_timeout)
+            .field("request_timeouts", &self.request_timeouts)
+            .field("api_version_request", &self.api_version_request)
+            .field("broker_version_fallback", &self.broker_version_fallback)
+            .field("metadata_max_age", &self.metadata_max_age)
+            .field("metadata_bootstrap_retries", &self.metadata_bootstrap_retries)
+            .field("topic_metadata_refresh_interval", &self.topic_metadata_refresh_interval)
+            .field("dns_refresh_interval", &self.dns_refresh_interval)
+            .field("check_crcs", &self.check_crcs)
+            .field("socket_nodelay", &self.socket_nodelay)
+            .field("socket_send_buffer_bytes", &self.socket_send_buffer_bytes)
+            .field("socket_receive_buffer_bytes", &self.socket_receive_buffer_bytes)
+            .field("socket_keepalive", &self.socket_keepalive)
+            .field("queue_buffering_max_requests", &self.queue_buffering_max_requests)
+            .field("queue_full_behavior", &self.queue_full_behavior)
+            .field("metrics", &self.metrics)
+            .field("warm_up_topics", &self.warm_up_topics)
+            .field("retries", &self.retries)
+            .field("retry_backoff", &self.retry_backoff)
+            .field("retry_policies", &self.retry_policies)
+            .field("reconnect_backoff", &self.reconnect_backoff)
+            .field("reconnect_backoff_max", &self.reconnect_backoff_max)
+            .field("sasl_mechanism", &self.sasl_mechanism)
+            .field("sasl_username", &self.sasl_username)
+            .field("sasl_password", &redacted(&self.sasl_password))
+            .field("ssl_enabled", &self.ssl_enabled)
+            .field("ssl_ca_location", &self.ssl_ca_location)
+            .field("ssl_keystore_location", &self.ssl_keystore_location)
+            .field("ssl_keystore_password", &redacted(&self.ssl_keystore_password))
+            .field("ssl_hostname_verification", &self.ssl_hostname_verification)
+            .field("listeners", &self.listeners)
+            .field("transport", &self.transport)
+            .finish()
+    }
+}
+
+/// Hide the password from the debug output.
+fn redacted(password: &Option<String>) -> Option<&'static str> {
+    password.as_ref().map(|_| "******")
 }
 
 impl Default for ClientConfig {
@@ -102,13 +336,39 @@ impl Default for ClientConfig {
             hosts: vec![],
             client_id: None,
             max_connection_idle: DEFAULT_MAX_CONNECTION_IDLE_TIMEOUT_MILLIS,
+            max_in_flight_requests_per_connection: DEFAULT_MAX_IN_FLIGHT_REQUESTS_PER_CONNECTION,
             request_timeout: DEFAULT_REQUEST_TIMEOUT_MILLS,
+            request_timeouts: BTreeMap::new(),
             api_version_request: false,
             broker_version_fallback: KafkaVersion::default(),
             metadata_max_age: DEFAULT_METADATA_MAX_AGE_MILLS,
+            metadata_bootstrap_retries: DEFAULT_METADATA_BOOTSTRAP_RETRIES,
+            topic_metadata_refresh_interval: DEFAULT_METADATA_MAX_AGE_MILLS,
+            dns_refresh_interval: DEFAULT_DNS_REFRESH_INTERVAL_MILLIS,
+            check_crcs: CrcPolicy::default(),
+            socket_nodelay: true,
+            socket_send_buffer_bytes: None,
+            socket_receive_buffer_bytes: None,
+            socket_keepalive: None,
+            queue_buffering_max_requests: 0,
+            queue_full_behavior: QueueFullBehavior::default(),
             metrics: false,
+            warm_up_topics: vec![],
             retries: 0,
             retry_backoff: DEFAULT_RETRY_BACKOFF_MILLIS,
+            retry_policies: RetryPolicies::default(),
+            reconnect_backoff: DEFAULT_RECONNECT_BACKOFF_MILLIS,
+            reconnect_backoff_max: DEFAULT_RECONNECT_BACKOFF_MAX_MILLIS,
+            sasl_mechanism: SaslMechanism::default(),
+            sasl_username: None,
+            sasl_password: None,
+            ssl_enabled: false,
+            ssl_ca_location: None,
+            ssl_keystore_location: None,
+            ssl_keystore_password: None,
+            ssl_hostname_verification: true,
+            listeners: ClientListeners::default(),
+            transport: SharedTransport::default(),
         }
     }
 }
@@ -137,6 +397,31 @@ impl ClientConfig {
         Duration::from_millis(self.request_timeout)
     }
 
+    /// The maximum amount of time the client will wait for the response of the requests of an API.
+    pub fn api_request_timeout(&self, api_key: ApiKeys) -> Duration {
+        Duration::from_millis(
+            self.request_timeouts
+                .get(api_key.name())
+                .cloned()
+                .unwrap_or(self.request_timeout),
+        )
+    }
+
+    /// The timeouts of requests for each API, the unknown API names are ignored.
+    pub fn request_timeouts(&self) -> RequestTimeouts {
+        self.request_timeouts.iter().fold(
+            RequestTimeouts::new(self.request_timeout()),
+            |timeouts, (name, &timeout)| match ApiKeys::from_name(name) {
+                Some(api_key) => timeouts.with_api(api_key, Duration::from_millis(timeout)),
+                None => {
+                    warn!("ignore the request timeout of unknown API `{}`", name);
+
+                    timeouts
+                }
+            },
+        )
+    }
+
     /// The period of time in milliseconds after which we force a refresh of metadata
     /// even if we haven't seen any partition leadership changes to proactively discover any
     /// new brokers or partitions.
@@ -144,12 +429,44 @@ impl ClientConfig {
         Duration::from_millis(self.metadata_max_age)
     }
 
-    /// Construct a `Timer`
-    pub fn timer(&self) -> Timer {
-        wheel()
-            .tick_duration(Duration::from_millis(DEFAULT_TIMER_TICK_MILLS))
-            .num_slots((self.request_timeout / DEFAULT_TIMER_TICK_MILLS).next_power_of_two() as usize)
-            .build()
+    /// The backoffs to retry loading the initial metadata from the bootstrap servers.
+    pub fn metadata_bootstrap_backoffs(&self) -> Vec<Duration> {
+        RetryPolicy {
+            max_retries: self.metadata_bootstrap_retries,
+            backoff: self.retry_backoff,
+            ..Default::default()
+        }.backoffs()
+    }
+
+    /// The period of time in milliseconds after which the cached metadata of a topic is
+    /// considered stale.
+    pub fn topic_metadata_refresh_interval(&self) -> Duration {
+        Duration::from_millis(self.topic_metadata_refresh_interval)
+    }
+
+    /// The period of time after which the resolved addresses of a broker are resolved again.
+    pub fn dns_refresh_interval(&self) -> Duration {
+        Duration::from_millis(self.dns_refresh_interval)
+    }
+
+    /// The options of the TCP sockets connected to the brokers.
+    pub fn socket_options(&self) -> SocketOptions {
+        SocketOptions {
+            nodelay: self.socket_nodelay,
+            send_buffer_size: self.socket_send_buffer_bytes,
+            recv_buffer_size: self.socket_receive_buffer_bytes,
+            keepalive: self.socket_keepalive.map(Duration::from_millis),
+        }
+    }
+
+    /// The transport used to connect to the brokers, a TCP transport with the socket options by default.
+    pub fn transport(&self) -> SharedTransport {
+        self.transport.or_tcp(self.socket_options())
+    }
+
+    /// The bounded request queues of the broker connections.
+    pub fn request_queues(&self) -> RequestQueues {
+        RequestQueues::new(self.queue_buffering_max_requests, self.queue_full_behavior)
     }
 
     /// The amount of time to wait before attempting to retry a failed request to a given topic
@@ -158,12 +475,89 @@ impl ClientConfig {
         Duration::from_millis(self.retry_backoff)
     }
 
-    /// The retry strategy when request failed
+    /// The base amount of time to wait before attempting to reconnect to a broker.
+    pub fn reconnect_backoff(&self) -> Duration {
+        Duration::from_millis(self.reconnect_backoff)
+    }
+
+    /// The maximum amount of time to wait before attempting to reconnect to a broker.
+    pub fn reconnect_backoff_max(&self) -> Duration {
+        Duration::from_millis(self.reconnect_backoff_max)
+    }
+
+    /// The retry strategy when request failed with a transient error
     pub fn retry_strategy(&self) -> Vec<Duration> {
-        ExponentialBackoff::from_millis(self.retry_backoff)
-            .map(jitter)
-            .take(self.retries)
-            .collect()
+        self.retry_policies().backoffs(ErrorClass::Retriable)
+    }
+
+    /// The retry policies for each class of errors,
+    /// the error classes not configured are retried with the `retries` and `retry.backoff.ms`.
+    pub fn retry_policies(&self) -> RetryPolicies {
+        let policy = RetryPolicy {
+            max_retries: self.retries,
+            backoff: self.retry_backoff,
+            ..Default::default()
+        };
+
+        self.retry_policies.clone().or(RetryPolicies {
+            retriable: Some(policy.clone()),
+            refresh_metadata: Some(policy),
+        })
+    }
+
+    /// The SASL authenticator used to authenticate the new connections
+    pub fn sasl_authenticator(&self) -> Option<SaslAuthenticator> {
+        self.sasl_username.as_ref().map(|username| {
+            SaslAuthenticator::new(
+                self.client_id.clone(),
+                self.sasl_mechanism,
+                username.as_str(),
+                self.sasl_password.as_ref().map_or("", |password| password.as_str()),
+            ).with_api_version_request(self.api_version_request)
+        })
+    }
+
+    /// The TLS settings used to wrap the new connections
+    pub fn tls_settings(&self) -> Result<Option<TlsSettings>> {
+        if !self.ssl_enabled {
+            return Ok(None);
+        }
+
+        let mut builder = TlsConnector::builder()?;
+
+        if let Some(ref path) = self.ssl_ca_location {
+            builder.add_root_certificate(load_certificate(path)?)?;
+        }
+
+        if let Some(ref path) = self.ssl_keystore_location {
+            let password = self.ssl_keystore_password.as_ref().map_or("", |password| password.as_str());
+
+            builder.identity(Pkcs12::from_der(&read_file(path)?, password)?)?;
+        }
+
+        Ok(Some(TlsSettings {
+            connector: builder.build()?,
+            hostname_verification: self.ssl_hostname_verification,
+        }))
+    }
+}
+
+fn read_file(path: &str) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+
+    File::open(path)?.read_to_end(&mut buf)?;
+
+    Ok(buf)
+}
+
+/// Load a DER or PEM encoded certificate.
+fn load_certificate(path: &str) -> Result<Certificate> {
+    let data = read_file(path)?;
+
+    if data.starts_with(b"-----BEGIN") {
+        Certificate::from_pem(&data).map_err(|_| ErrorKind::ConfigError("invalid PEM certificate").into())
+    } else {
+        Ok(Certificate::from_der(&data)?)
     }
 }
 
@@ -193,6 +587,83 @@ mod tests {
             Duration::from_millis(DEFAULT_METADATA_MAX_AGE_MILLS)
         );
         assert_eq!(config.retry_strategy().len(), 3);
+        assert_eq!(config.retry_policies().backoffs(ErrorClass::RefreshMetadata).len(), 3);
+        assert!(config.retry_policies().backoffs(ErrorClass::Fatal).is_empty());
+        assert_eq!(
+            config.metadata_bootstrap_backoffs().len(),
+            DEFAULT_METADATA_BOOTSTRAP_RETRIES
+        );
+        assert_eq!(config.sasl_authenticator(), None);
+
+        let config = ClientConfig {
+            sasl_username: Some("user".to_owned()),
+            sasl_password: Some("pass".to_owned()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            config.sasl_authenticator(),
+            Some(SaslAuthenticator::plain(None, "user", "pass"))
+        );
+
+        let config = ClientConfig {
+            sasl_mechanism: SaslMechanism::ScramSha256,
+            sasl_username: Some("user".to_owned()),
+            sasl_password: Some("pass".to_owned()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            config.sasl_authenticator().map(|authenticator| authenticator.mechanism()),
+            Some(SaslMechanism::ScramSha256)
+        );
+        assert!(config.tls_settings().unwrap().is_none());
+
+        let config = ClientConfig {
+            ssl_enabled: true,
+            ssl_ca_location: Some("not-exists.pem".to_owned()),
+            ..Default::default()
+        };
+
+        assert!(config.tls_settings().is_err());
+    }
+
+    #[test]
+    fn test_request_timeouts() {
+        let config = ClientConfig {
+            request_timeouts: vec![("JoinGroup".to_owned(), 300_000), ("Unknown".to_owned(), 1)]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        };
+
+        assert_eq!(config.api_request_timeout(ApiKeys::JoinGroup), Duration::from_secs(300));
+        assert_eq!(config.api_request_timeout(ApiKeys::Metadata), config.request_timeout());
+        assert_eq!(
+            config.request_timeouts(),
+            RequestTimeouts::new(config.request_timeout()).with_api(ApiKeys::JoinGroup, Duration::from_secs(300))
+        );
+    }
+
+    #[test]
+    fn test_redact_passwords() {
+        let config = ClientConfig {
+            sasl_username: Some("user".to_owned()),
+            sasl_password: Some("sasl-secret".to_owned()),
+            ssl_keystore_password: Some("keystore-secret".to_owned()),
+            ..Default::default()
+        };
+
+        let debug = format!("{:?}", config);
+
+        assert!(debug.contains("\"user\""));
+        assert!(!debug.contains("sasl-secret"));
+        assert!(!debug.contains("keystore-secret"));
+
+        let json = serde_json::to_string(&config).unwrap();
+
+        assert!(!json.contains("sasl-secret"));
+        assert!(!json.contains("keystore-secret"));
     }
 
     #[test]
@@ -208,13 +679,38 @@ mod tests {
   ],
   "client.id": "tokio-kafka",
   "connection.max.idle.ms": 5000,
+  "max.in.flight.requests.per.connection": 5,
   "request.timeout.ms": 30000,
+  "request.timeouts.ms": {},
   "api.version.request": false,
   "broker.version.fallback": "0.9.0",
   "metadata.max.age.ms": 300000,
+  "metadata.bootstrap.retries": 5,
+  "topic.metadata.refresh.interval.ms": 300000,
+  "dns.refresh.interval.ms": 60000,
+  "check.crcs": "fail",
+  "socket.nodelay": true,
+  "socket.send.buffer.bytes": null,
+  "socket.receive.buffer.bytes": null,
+  "socket.keepalive.ms": null,
+  "queue.buffering.max.requests": 0,
+  "queue.full.behavior": "fail",
   "metrics": false,
+  "warm.up.topics": [],
   "retries": 0,
-  "retry.backoff.ms": 100
+  "retry.backoff.ms": 100,
+  "retry.policies": {
+    "retriable": null,
+    "refresh.metadata": null
+  },
+  "reconnect.backoff.ms": 50,
+  "reconnect.backoff.max.ms": 1000,
+  "sasl.mechanism": "PLAIN",
+  "sasl.username": null,
+  "ssl.enabled": false,
+  "ssl.ca.location": null,
+  "ssl.keystore.location": null,
+  "ssl.endpoint.identification.enabled": true
 }"#;
 
         assert_eq!(serde_json::to_string_pretty(&config).unwrap(), json);
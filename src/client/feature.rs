@@ -0,0 +1,108 @@
+use std::fmt;
+
+use protocol::{ApiKeys, ApiVersion, UsableApiVersions};
+
+/// The protocol features which depend on the API versions supported by the brokers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Feature {
+    /// Look up the offsets by timestamp, since Kafka 0.10.1
+    OffsetsForTimes,
+    /// Create and delete the topics, since Kafka 0.10.1
+    AdminTopics,
+    /// Describe and alter the configurations, since Kafka 0.11.0
+    AdminConfigs,
+    /// The headers of records, since Kafka 0.11.0
+    Headers,
+    /// Delete the records before an offset, since Kafka 0.11.0
+    DeleteRecords,
+    /// The idempotent producer, since Kafka 0.11.0
+    Idempotence,
+    /// The transactional producer, since Kafka 0.11.0
+    Transactions,
+    /// The SASL authentication wrapped in the Kafka protocol, since Kafka 1.0.0
+    SaslAuthenticate,
+    /// The incremental fetch sessions, since Kafka 1.1.0
+    IncrementalFetch,
+    /// The Zstandard compression, since Kafka 2.1.0
+    Zstd,
+    /// Look up the end offsets of the leader epochs to detect the log truncation, since Kafka 2.1.0
+    OffsetsForLeaderEpoch,
+    /// Fetch from the closest replica instead of the leader, since Kafka 2.4.0
+    FetchFromFollower,
+}
+
+impl Feature {
+    /// The minimum versions of the APIs required by the feature.
+    pub fn requirements(&self) -> &'static [(ApiKeys, ApiVersion)] {
+        match *self {
+            Feature::OffsetsForTimes => &[(ApiKeys::ListOffsets, 1)],
+            Feature::AdminTopics => &[(ApiKeys::CreateTopics, 0), (ApiKeys::DeleteTopics, 0)],
+            Feature::AdminConfigs => &[(ApiKeys::DescribeConfigs, 0), (ApiKeys::AlterConfigs, 0)],
+            Feature::Headers => &[(ApiKeys::Produce, 3), (ApiKeys::Fetch, 4)],
+            Feature::DeleteRecords => &[(ApiKeys::DeleteRecords, 0)],
+            Feature::Idempotence => &[(ApiKeys::Produce, 3), (ApiKeys::InitProducerId, 0)],
+            Feature::Transactions => &[
+                (ApiKeys::InitProducerId, 0),
+                (ApiKeys::AddPartitionsToTxn, 0),
+                (ApiKeys::AddOffsetsToTxn, 0),
+                (ApiKeys::EndTxn, 0),
+                (ApiKeys::TxnOffsetCommit, 0),
+            ],
+            Feature::SaslAuthenticate => &[(ApiKeys::SaslAuthenticate, 0)],
+            Feature::IncrementalFetch => &[(ApiKeys::Fetch, 7)],
+            Feature::Zstd => &[(ApiKeys::Produce, 7), (ApiKeys::Fetch, 10)],
+            Feature::OffsetsForLeaderEpoch => &[(ApiKeys::OffsetForLeaderEpoch, 2)],
+            Feature::FetchFromFollower => &[(ApiKeys::Fetch, 11)],
+        }
+    }
+
+    /// Check whether the feature is supported by the API versions of a broker.
+    pub fn is_supported_by(&self, api_versions: &UsableApiVersions) -> bool {
+        self.requirements().iter().all(|&(api_key, version)| {
+            api_versions
+                .find(api_key)
+                .map_or(false, |api_version| api_version.max_version >= version)
+        })
+    }
+}
+
+impl fmt::Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use protocol::UsableApiVersion;
+
+    #[test]
+    fn test_supported_features() {
+        let api_versions = UsableApiVersions::new(vec![
+            UsableApiVersion {
+                api_key: ApiKeys::Produce,
+                min_version: 0,
+                max_version: 3,
+            },
+            UsableApiVersion {
+                api_key: ApiKeys::Fetch,
+                min_version: 0,
+                max_version: 5,
+            },
+            UsableApiVersion {
+                api_key: ApiKeys::ListOffsets,
+                min_version: 0,
+                max_version: 2,
+            },
+        ]);
+
+        assert!(Feature::OffsetsForTimes.is_supported_by(&api_versions));
+        assert!(Feature::Headers.is_supported_by(&api_versions));
+        assert!(!Feature::Idempotence.is_supported_by(&api_versions));
+        assert!(!Feature::IncrementalFetch.is_supported_by(&api_versions));
+        assert!(!Feature::Zstd.is_supported_by(&api_versions));
+        assert!(!Feature::OffsetsForLeaderEpoch.is_supported_by(&api_versions));
+        assert!(!Feature::FetchFromFollower.is_supported_by(&api_versions));
+    }
+}
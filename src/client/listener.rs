@@ -0,0 +1,119 @@
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::rc::Rc;
+use std::time::Duration;
+
+use client::Metadata;
+use protocol::ApiKeys;
+
+/// A listener of the client events, which could log or alert on the connectivity issues of the cluster.
+///
+/// All the callbacks do nothing by default, the listener only needs to implement the interested events.
+pub trait ClientListener {
+    /// A connection to the broker has been established.
+    fn on_connect(&self, _addr: &SocketAddr) {}
+
+    /// Fail to connect to the broker, it will be reconnected after the backoff.
+    fn on_connect_failed(&self, _addr: &SocketAddr, _err: &io::Error) {}
+
+    /// A connection to the broker has been closed by the client, e.g. it has been idle for too long.
+    fn on_disconnect(&self, _addr: &SocketAddr) {}
+
+    /// The metadata of the cluster has been refreshed.
+    fn on_metadata_refresh(&self, _metadata: &Metadata) {}
+
+    /// The broker did not respond the request within the `request.timeout.ms`.
+    fn on_request_timeout(&self, _addr: &SocketAddr, _api_key: ApiKeys, _timeout: Duration) {}
+
+    /// The request has been throttled by the broker for violating the quota.
+    fn on_throttle(&self, _addr: &SocketAddr, _api_key: ApiKeys, _throttle_time: Duration) {}
+}
+
+/// The listeners registered on the `ClientConfig`, which are notified in the registered order.
+#[derive(Clone, Default)]
+pub struct ClientListeners(Vec<Rc<ClientListener>>);
+
+impl fmt::Debug for ClientListeners {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ClientListeners({})", self.0.len())
+    }
+}
+
+impl PartialEq for ClientListeners {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.len() == other.0.len() && self.0.iter().zip(other.0.iter()).all(|(l, r)| Rc::ptr_eq(l, r))
+    }
+}
+
+impl ClientListeners {
+    /// Register a listener.
+    pub fn add<L>(&mut self, listener: L)
+    where
+        L: 'static + ClientListener,
+    {
+        self.0.push(Rc::new(listener))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Notify the listeners of an event.
+    pub fn notify<F>(&self, event: F)
+    where
+        F: Fn(&ClientListener),
+    {
+        for listener in &self.0 {
+            event(&**listener)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct Events(Rc<RefCell<Vec<String>>>);
+
+    impl ClientListener for Events {
+        fn on_connect(&self, addr: &SocketAddr) {
+            self.0.borrow_mut().push(format!("connect {}", addr))
+        }
+
+        fn on_throttle(&self, addr: &SocketAddr, api_key: ApiKeys, throttle_time: Duration) {
+            self.0
+                .borrow_mut()
+                .push(format!("throttle {:?} to {} for {} ms", api_key, addr, throttle_time.as_secs() * 1000))
+        }
+    }
+
+    #[test]
+    fn test_notify() {
+        let events = Events::default();
+        let mut listeners = ClientListeners::default();
+
+        assert!(listeners.is_empty());
+
+        listeners.add(events.clone());
+
+        let addr = "127.0.0.1:9092".parse().unwrap();
+
+        listeners.notify(|listener| listener.on_connect(&addr));
+        listeners.notify(|listener| listener.on_disconnect(&addr));
+        listeners.notify(|listener| listener.on_throttle(&addr, ApiKeys::Fetch, Duration::from_secs(1)));
+
+        assert_eq!(
+            *events.0.borrow(),
+            vec![
+                "connect 127.0.0.1:9092".to_owned(),
+                "throttle Fetch to 127.0.0.1:9092 for 1000 ms".to_owned(),
+            ]
+        );
+        assert_eq!(listeners.clone(), listeners);
+        assert_ne!(listeners, ClientListeners::default());
+    }
+}
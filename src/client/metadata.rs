@@ -1,8 +1,9 @@
+use std::collections::HashSet;
 use std::collections::hash_map::HashMap;
 use std::iter::FromIterator;
 use std::slice;
 
-use client::{Broker, BrokerRef, Cluster, PartitionInfo};
+use client::{Broker, BrokerRef, Cluster, Feature, PartitionInfo};
 use network::TopicPartition;
 use protocol::{MetadataResponse, PartitionId, UsableApiVersions};
 
@@ -22,6 +23,15 @@ pub struct Metadata {
 
     // ~ a mapping of groups to their coordinators
     group_coordinators: HashMap<String, BrokerRef>,
+
+    // ~ the controller broker, known since Metadata v1
+    controller: Option<BrokerRef>,
+
+    // ~ the cluster id, known since Metadata v2
+    cluster_id: Option<String>,
+
+    // ~ the Kafka internal topics, e.g. `__consumer_offsets`, known since Metadata v1
+    internal_topics: HashSet<String>,
 }
 
 impl Metadata {
@@ -29,8 +39,7 @@ impl Metadata {
     pub fn with_brokers(brokers: Vec<Broker>) -> Self {
         Metadata {
             brokers,
-            topic_partitions: HashMap::new(),
-            group_coordinators: HashMap::new(),
+            ..Default::default()
         }
     }
 
@@ -43,7 +52,7 @@ impl Metadata {
                     .into_iter()
                     .map(|(topic_name, partitions)| (topic_name, TopicPartitions { partitions })),
             ),
-            group_coordinators: HashMap::new(),
+            ..Default::default()
         }
     }
 
@@ -56,9 +65,34 @@ impl Metadata {
                 .collect(),
             topic_partitions: self.topic_partitions.clone(),
             group_coordinators: self.group_coordinators.clone(),
+            controller: self.controller,
+            cluster_id: self.cluster_id.clone(),
+            internal_topics: self.internal_topics.clone(),
         }
     }
 
+    /// The controller broker of the cluster, or `None` if unknown (before Metadata v1).
+    pub fn controller(&self) -> Option<&Broker> {
+        self.controller.and_then(|controller| self.find_broker(controller))
+    }
+
+    /// The id of the cluster, or `None` if unknown (before Metadata v2).
+    pub fn cluster_id(&self) -> Option<&str> {
+        self.cluster_id.as_ref().map(|cluster_id| cluster_id.as_str())
+    }
+
+    /// Check whether the topic is a Kafka internal topic, e.g. `__consumer_offsets`.
+    ///
+    /// Always returns `false` before Metadata v1.
+    pub fn is_internal_topic(&self, topic_name: &str) -> bool {
+        self.internal_topics.contains(topic_name)
+    }
+
+    /// Check whether the feature is supported by all the brokers.
+    pub fn supports(&self, feature: Feature) -> bool {
+        !self.brokers.is_empty() && self.brokers.iter().all(|broker| broker.supports(feature))
+    }
+
     /// Create a new Metadata with the given fallback API versions
     pub fn with_fallback_api_versions(&self, api_versions: &UsableApiVersions) -> Self {
         Metadata {
@@ -68,6 +102,49 @@ impl Metadata {
                 .collect(),
             topic_partitions: self.topic_partitions.clone(),
             group_coordinators: self.group_coordinators.clone(),
+            controller: self.controller,
+            cluster_id: self.cluster_id.clone(),
+            internal_topics: self.internal_topics.clone(),
+        }
+    }
+}
+
+impl Metadata {
+    /// Merge the metadata of the topics fetched from the cluster.
+    ///
+    /// The known brokers are preserved with their API versions,
+    /// and the topics without any partition (not exists or failed to fetch) are ignored.
+    pub fn merge_topics(&self, other: &Metadata) -> Self {
+        let mut brokers = self.brokers.clone();
+
+        for broker in &other.brokers {
+            if !brokers.iter().any(|known| known.id() == broker.id()) {
+                brokers.push(broker.clone());
+            }
+        }
+
+        let mut topic_partitions = self.topic_partitions.clone();
+        let mut internal_topics = self.internal_topics.clone();
+
+        for (topic_name, partitions) in &other.topic_partitions {
+            if !partitions.is_empty() {
+                topic_partitions.insert(topic_name.clone(), partitions.clone());
+
+                if other.internal_topics.contains(topic_name) {
+                    internal_topics.insert(topic_name.clone());
+                } else {
+                    internal_topics.remove(topic_name);
+                }
+            }
+        }
+
+        Metadata {
+            brokers,
+            topic_partitions,
+            group_coordinators: self.group_coordinators.clone(),
+            controller: other.controller.or(self.controller),
+            cluster_id: other.cluster_id.clone().or_else(|| self.cluster_id.clone()),
+            internal_topics,
         }
     }
 }
@@ -78,6 +155,9 @@ impl Default for Metadata {
             brokers: Vec::new(),
             topic_partitions: HashMap::new(),
             group_coordinators: HashMap::new(),
+            controller: None,
+            cluster_id: None,
+            internal_topics: HashSet::new(),
         }
     }
 }
@@ -136,6 +216,19 @@ impl Cluster for Metadata {
             })
     }
 
+    fn available_partitions_for_topic(&self, topic_name: &str) -> Option<Vec<TopicPartition>> {
+        self.topic_partitions
+            .iter()
+            .find(|&(topic, _)| topic.as_str() == topic_name)
+            .map(|(topic_name, partitions)| {
+                partitions
+                    .iter()
+                    .filter(|&(_, partition)| partition.leader.is_some())
+                    .map(|(partition_id, _)| topic_partition!(topic_name.as_str(), partition_id))
+                    .collect()
+            })
+    }
+
     fn partitions_for_broker(&self, leader: BrokerRef) -> Vec<TopicPartition> {
         self.topic_partitions
             .iter()
@@ -154,7 +247,9 @@ impl From<MetadataResponse> for Metadata {
         Metadata {
             brokers: md.brokers
                 .iter()
-                .map(|broker| Broker::new(broker.node_id, &broker.host, broker.port as u16))
+                .map(|broker| {
+                    Broker::new(broker.node_id, &broker.host, broker.port as u16).with_rack(broker.rack.clone())
+                })
                 .collect(),
             topic_partitions: HashMap::from_iter(md.topics.iter().map(|topic| {
                 (
@@ -174,6 +269,15 @@ impl From<MetadataResponse> for Metadata {
                 )
             })),
             group_coordinators: HashMap::new(),
+            // the controller id is -1 if there is no active controller
+            controller: md.controller_id
+                .and_then(|controller_id| if controller_id < 0 { None } else { Some(BrokerRef::new(controller_id)) }),
+            cluster_id: md.cluster_id.clone(),
+            internal_topics: md.topics
+                .iter()
+                .filter(|topic| topic.is_internal)
+                .map(|topic| topic.topic_name.clone())
+                .collect(),
         }
     }
 }
@@ -246,3 +350,87 @@ impl<'a> Iterator for PartitionInfoIter<'a> {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use client::ClusterDescription;
+    use protocol::{BrokerMetadata, ResponseHeader, TopicMetadata};
+
+    #[test]
+    fn test_merge_topics() {
+        let partition = |leader| PartitionInfo {
+            leader: Some(BrokerRef::new(leader)),
+            ..Default::default()
+        };
+
+        let cached = Metadata {
+            brokers: vec![Broker::new(0, "localhost", 9092)],
+            topic_partitions: HashMap::from_iter(vec![
+                ("foo".to_owned(), TopicPartitions { partitions: vec![partition(0)] }),
+                ("bar".to_owned(), TopicPartitions { partitions: vec![partition(0)] }),
+            ]),
+            ..Default::default()
+        };
+        let fetched = Metadata {
+            brokers: vec![Broker::new(0, "localhost", 9092), Broker::new(1, "localhost", 9093)],
+            topic_partitions: HashMap::from_iter(vec![
+                ("foo".to_owned(), TopicPartitions { partitions: vec![partition(1), partition(0)] }),
+                ("unknown".to_owned(), TopicPartitions { partitions: vec![] }),
+            ]),
+            ..Default::default()
+        };
+
+        let metadata = cached.merge_topics(&fetched);
+
+        assert_eq!(metadata.brokers().len(), 2);
+
+        let mut topic_names = metadata.topic_names();
+
+        topic_names.sort();
+
+        assert_eq!(topic_names, vec!["bar", "foo"]);
+        assert_eq!(metadata.topics()["foo"].len(), 2);
+        assert_eq!(
+            metadata.leader_for(&topic_partition!("foo", 0)).map(|broker| broker.id()),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn test_from_metadata_response() {
+        let res = MetadataResponse {
+            header: ResponseHeader { correlation_id: 123 },
+            throttle_time: None,
+            brokers: vec![BrokerMetadata {
+                node_id: 1,
+                host: "localhost".to_owned(),
+                port: 9092,
+                rack: Some("rack".to_owned()),
+            }],
+            cluster_id: Some("cluster".to_owned()),
+            controller_id: Some(1),
+            topics: vec![TopicMetadata {
+                error_code: 0,
+                topic_name: "__consumer_offsets".to_owned(),
+                is_internal: true,
+                partitions: vec![],
+            }],
+        };
+
+        let metadata = Metadata::from(res);
+
+        assert_eq!(metadata.brokers()[0].rack(), Some("rack"));
+        assert_eq!(metadata.controller().map(|broker| broker.id()), Some(1));
+        assert_eq!(metadata.cluster_id(), Some("cluster"));
+        assert!(metadata.is_internal_topic("__consumer_offsets"));
+        assert!(!metadata.is_internal_topic("foo"));
+
+        let description = ClusterDescription::from(&metadata);
+
+        assert_eq!(description.cluster_id, Some("cluster".to_owned()));
+        assert_eq!(description.controller.map(|broker| broker.id()), Some(1));
+        assert_eq!(description.brokers.len(), 1);
+    }
+}
@@ -1,14 +1,26 @@
+use std::cell::RefCell;
+use std::io::Write;
 use std::net::SocketAddr;
 use std::ops::{Deref, DerefMut};
+use std::rc::{Rc, Weak};
+use std::time::Duration;
 
-use prometheus::{CounterVec, GaugeVec, Registry};
+use futures::future::{self, Loop};
+use futures::Future;
 
+use prometheus::proto::MetricFamily;
+use prometheus::{exponential_buckets, CounterVec, Encoder, GaugeVec, HistogramOpts, HistogramVec, Registry,
+                 TextEncoder};
+
+use client::{Runtime, Spawned};
 use errors::Result;
 use network::{KafkaRequest, KafkaResponse};
-use protocol::ApiKeys;
+use protocol::{ApiKeys, KafkaCode, PartitionId};
 
 pub const NAMESPACE_KAFKA: &str = "kafka";
 pub const SUBSYSTEM_CLIENT: &str = "client";
+pub const SUBSYSTEM_CONSUMER: &str = "consumer";
+pub const SUBSYSTEM_PRODUCER: &str = "producer";
 
 pub struct Metrics {
     registry: Registry,
@@ -16,6 +28,29 @@ pub struct Metrics {
     send_requests: CounterVec,
     in_flight_requests: GaugeVec,
     received_responses: CounterVec,
+    request_latency: HistogramVec,
+    request_errors: CounterVec,
+    throttle_time: HistogramVec,
+    connections: CounterVec,
+    api_versions: CounterVec,
+
+    group_rebalances: CounterVec,
+    group_rebalance_latency: HistogramVec,
+    group_generation: GaugeVec,
+    group_generation_lifetime: HistogramVec,
+    assigned_partitions: GaugeVec,
+    consumer_lag: GaugeVec,
+    end_to_end_latency: HistogramVec,
+
+    produced_bytes: CounterVec,
+    produced_records: CounterVec,
+    batch_fill_ratio: HistogramVec,
+    batch_size: HistogramVec,
+    record_queue_time: HistogramVec,
+    compression_ratio: HistogramVec,
+    produce_errors: CounterVec,
+    encode_allocated_bytes: CounterVec,
+    encode_shared_bytes: CounterVec,
 }
 
 impl Deref for Metrics {
@@ -57,15 +92,211 @@ impl Metrics {
             &["broker", "api_key"],
         )?;
 
+        let request_latency = HistogramVec::new(
+            HistogramOpts::new("request_latency_seconds", "time from sending the request to receiving the response")
+                .namespace(NAMESPACE_KAFKA.to_owned())
+                .subsystem(SUBSYSTEM_CLIENT.to_owned()),
+            &["broker", "api_key"],
+        )?;
+
+        let request_errors = CounterVec::new(
+            opts!("request_errors", "failed API requests")
+                .namespace(NAMESPACE_KAFKA.to_owned())
+                .subsystem(SUBSYSTEM_CLIENT.to_owned()),
+            &["broker", "api_key"],
+        )?;
+
+        let throttle_time = HistogramVec::new(
+            HistogramOpts::new("throttle_time_seconds", "time the requests were throttled by the broker quotas")
+                .namespace(NAMESPACE_KAFKA.to_owned())
+                .subsystem(SUBSYSTEM_CLIENT.to_owned()),
+            &["broker", "api_key"],
+        )?;
+
+        let connections = CounterVec::new(
+            opts!("connections", "attempts to connect the brokers")
+                .namespace(NAMESPACE_KAFKA.to_owned())
+                .subsystem(SUBSYSTEM_CLIENT.to_owned()),
+            &["broker", "result"],
+        )?;
+
+        let api_versions = CounterVec::new(
+            opts!("api_versions", "API versions loaded from the brokers or the fallback version")
+                .namespace(NAMESPACE_KAFKA.to_owned())
+                .subsystem(SUBSYSTEM_CLIENT.to_owned()),
+            &["broker", "source"],
+        )?;
+
+        let group_rebalances = CounterVec::new(
+            opts!("group_rebalances", "completed group rebalances")
+                .namespace(NAMESPACE_KAFKA.to_owned())
+                .subsystem(SUBSYSTEM_CONSUMER.to_owned()),
+            &["group_id"],
+        )?;
+
+        let group_rebalance_latency = HistogramVec::new(
+            HistogramOpts::new("group_rebalance_latency_seconds", "time to join and sync up the group")
+                .namespace(NAMESPACE_KAFKA.to_owned())
+                .subsystem(SUBSYSTEM_CONSUMER.to_owned()),
+            &["group_id"],
+        )?;
+
+        let group_generation = GaugeVec::new(
+            opts!("group_generation", "current generation of the group")
+                .namespace(NAMESPACE_KAFKA.to_owned())
+                .subsystem(SUBSYSTEM_CONSUMER.to_owned()),
+            &["group_id"],
+        )?;
+
+        let group_generation_lifetime = HistogramVec::new(
+            HistogramOpts::new("group_generation_lifetime_seconds", "time the member stayed in a generation")
+                .namespace(NAMESPACE_KAFKA.to_owned())
+                .subsystem(SUBSYSTEM_CONSUMER.to_owned()),
+            &["group_id"],
+        )?;
+
+        let assigned_partitions = GaugeVec::new(
+            opts!("assigned_partitions", "partitions assigned to the member")
+                .namespace(NAMESPACE_KAFKA.to_owned())
+                .subsystem(SUBSYSTEM_CONSUMER.to_owned()),
+            &["group_id"],
+        )?;
+
+        let consumer_lag = GaugeVec::new(
+            opts!("lag", "records not yet committed by the group")
+                .namespace(NAMESPACE_KAFKA.to_owned())
+                .subsystem(SUBSYSTEM_CONSUMER.to_owned()),
+            &["group_id", "topic", "partition"],
+        )?;
+
+        let end_to_end_latency = HistogramVec::new(
+            HistogramOpts::new("end_to_end_latency_seconds", "time from the record timestamp to the consumption")
+                .namespace(NAMESPACE_KAFKA.to_owned())
+                .subsystem(SUBSYSTEM_CONSUMER.to_owned()),
+            &["topic"],
+        )?;
+
+        let produced_bytes = CounterVec::new(
+            opts!("produced_bytes", "bytes of the keys and values produced to the topic")
+                .namespace(NAMESPACE_KAFKA.to_owned())
+                .subsystem(SUBSYSTEM_PRODUCER.to_owned()),
+            &["topic"],
+        )?;
+
+        let produced_records = CounterVec::new(
+            opts!("produced_records", "records produced to the topic")
+                .namespace(NAMESPACE_KAFKA.to_owned())
+                .subsystem(SUBSYSTEM_PRODUCER.to_owned()),
+            &["topic"],
+        )?;
+
+        let batch_fill_ratio = HistogramVec::new(
+            HistogramOpts::new("batch_fill_ratio", "ratio of the batch size to the configured batch size")
+                .namespace(NAMESPACE_KAFKA.to_owned())
+                .subsystem(SUBSYSTEM_PRODUCER.to_owned())
+                .buckets(vec![0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8, 0.9, 1.0]),
+            &["topic"],
+        )?;
+
+        let batch_size = HistogramVec::new(
+            HistogramOpts::new("batch_size_bytes", "bytes of the keys and values in the sent batch")
+                .namespace(NAMESPACE_KAFKA.to_owned())
+                .subsystem(SUBSYSTEM_PRODUCER.to_owned())
+                .buckets(exponential_buckets(64.0, 4.0, 10)?),
+            &["topic"],
+        )?;
+
+        let record_queue_time = HistogramVec::new(
+            HistogramOpts::new("record_queue_time_seconds", "time the batch waited in the accumulator")
+                .namespace(NAMESPACE_KAFKA.to_owned())
+                .subsystem(SUBSYSTEM_PRODUCER.to_owned()),
+            &["topic"],
+        )?;
+
+        let compression_ratio = HistogramVec::new(
+            HistogramOpts::new("compression_ratio", "ratio of the compressed batch size to the uncompressed size")
+                .namespace(NAMESPACE_KAFKA.to_owned())
+                .subsystem(SUBSYSTEM_PRODUCER.to_owned())
+                .buckets(vec![0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8, 0.9, 1.0, 1.5]),
+            &["topic"],
+        )?;
+
+        let produce_errors = CounterVec::new(
+            opts!("produce_errors", "failed attempts to produce batches to the topic")
+                .namespace(NAMESPACE_KAFKA.to_owned())
+                .subsystem(SUBSYSTEM_PRODUCER.to_owned()),
+            &["topic", "error"],
+        )?;
+
+        let encode_allocated_bytes = CounterVec::new(
+            opts!(
+                "encode_allocated_bytes",
+                "bytes allocated to encode and compress the batches before writing the requests"
+            ).namespace(NAMESPACE_KAFKA.to_owned())
+                .subsystem(SUBSYSTEM_PRODUCER.to_owned()),
+            &["topic"],
+        )?;
+
+        let encode_shared_bytes = CounterVec::new(
+            opts!(
+                "encode_shared_bytes",
+                "bytes of the keys and values shared with the records without being copied before writing the requests"
+            ).namespace(NAMESPACE_KAFKA.to_owned())
+                .subsystem(SUBSYSTEM_PRODUCER.to_owned()),
+            &["topic"],
+        )?;
+
         registry.register(Box::new(send_requests.clone()))?;
         registry.register(Box::new(in_flight_requests.clone()))?;
         registry.register(Box::new(received_responses.clone()))?;
+        registry.register(Box::new(request_latency.clone()))?;
+        registry.register(Box::new(request_errors.clone()))?;
+        registry.register(Box::new(throttle_time.clone()))?;
+        registry.register(Box::new(connections.clone()))?;
+        registry.register(Box::new(api_versions.clone()))?;
+        registry.register(Box::new(group_rebalances.clone()))?;
+        registry.register(Box::new(group_rebalance_latency.clone()))?;
+        registry.register(Box::new(group_generation.clone()))?;
+        registry.register(Box::new(group_generation_lifetime.clone()))?;
+        registry.register(Box::new(assigned_partitions.clone()))?;
+        registry.register(Box::new(consumer_lag.clone()))?;
+        registry.register(Box::new(end_to_end_latency.clone()))?;
+        registry.register(Box::new(produced_bytes.clone()))?;
+        registry.register(Box::new(produced_records.clone()))?;
+        registry.register(Box::new(batch_fill_ratio.clone()))?;
+        registry.register(Box::new(batch_size.clone()))?;
+        registry.register(Box::new(record_queue_time.clone()))?;
+        registry.register(Box::new(compression_ratio.clone()))?;
+        registry.register(Box::new(produce_errors.clone()))?;
+        registry.register(Box::new(encode_allocated_bytes.clone()))?;
+        registry.register(Box::new(encode_shared_bytes.clone()))?;
 
         Ok(Metrics {
             registry,
             send_requests,
             in_flight_requests,
             received_responses,
+            request_latency,
+            request_errors,
+            throttle_time,
+            connections,
+            api_versions,
+            group_rebalances,
+            group_rebalance_latency,
+            group_generation,
+            group_generation_lifetime,
+            assigned_partitions,
+            consumer_lag,
+            end_to_end_latency,
+            produced_bytes,
+            produced_records,
+            batch_fill_ratio,
+            batch_size,
+            record_queue_time,
+            compression_ratio,
+            produce_errors,
+            encode_allocated_bytes,
+            encode_shared_bytes,
         })
     }
 
@@ -81,10 +312,240 @@ impl Metrics {
         self.in_flight_requests.with_label_values(&labels[..2]).inc();
     }
 
-    pub fn received_response(&self, addr: &SocketAddr, response: &KafkaResponse) {
+    pub fn received_response(&self, addr: &SocketAddr, response: &KafkaResponse, latency: Duration) {
         let labels = [&addr.to_string(), response.api_key().name()];
 
         self.received_responses.with_label_values(&labels).inc();
         self.in_flight_requests.with_label_values(&labels).dec();
+        self.request_latency
+            .with_label_values(&labels)
+            .observe(as_secs_f64(latency));
+    }
+
+    pub fn request_failed(&self, addr: &SocketAddr, api_key: ApiKeys) {
+        let labels = [&addr.to_string(), api_key.name()];
+
+        self.request_errors.with_label_values(&labels).inc();
+        self.in_flight_requests.with_label_values(&labels).dec();
+    }
+
+    pub fn throttled(&self, addr: &SocketAddr, api_key: ApiKeys, throttle_time: Duration) {
+        self.throttle_time
+            .with_label_values(&[&addr.to_string(), api_key.name()])
+            .observe(as_secs_f64(throttle_time));
+    }
+
+    pub fn connected(&self, addr: &SocketAddr, succeeded: bool) {
+        let result = if succeeded { "established" } else { "failed" };
+
+        self.connections
+            .with_label_values(&[&addr.to_string(), result])
+            .inc();
+    }
+
+    pub fn api_versions_loaded(&self, broker: &str, fallback: bool) {
+        let source = if fallback { "fallback" } else { "broker" };
+
+        self.api_versions.with_label_values(&[broker, source]).inc();
+    }
+
+    pub fn group_rebalanced(&self, group_id: &str, generation_id: i32, latency: Duration, assigned_partitions: usize) {
+        let labels = [group_id];
+
+        self.group_rebalances.with_label_values(&labels).inc();
+        self.group_rebalance_latency
+            .with_label_values(&labels)
+            .observe(as_secs_f64(latency));
+        self.group_generation
+            .with_label_values(&labels)
+            .set(f64::from(generation_id));
+        self.assigned_partitions
+            .with_label_values(&labels)
+            .set(assigned_partitions as f64);
+    }
+
+    pub fn group_generation_ended(&self, group_id: &str, lifetime: Duration) {
+        self.group_generation_lifetime
+            .with_label_values(&[group_id])
+            .observe(as_secs_f64(lifetime));
+    }
+
+    pub fn consumer_lag(&self, group_id: &str, topic_name: &str, partition_id: PartitionId, lag: i64) {
+        let labels = [group_id, topic_name, &partition_id.to_string()];
+
+        self.consumer_lag.with_label_values(&labels).set(lag as f64);
+    }
+
+    pub fn record_consumed(&self, topic_name: &str, latency: Duration) {
+        self.end_to_end_latency
+            .with_label_values(&[topic_name])
+            .observe(as_secs_f64(latency));
+    }
+
+    pub fn batch_produced(&self, topic_name: &str, records: usize, bytes: usize, fill_ratio: f64) {
+        let labels = [topic_name];
+
+        if let Err(err) = self.produced_records
+            .with_label_values(&labels)
+            .inc_by(records as f64)
+            .and_then(|_| self.produced_bytes.with_label_values(&labels).inc_by(bytes as f64))
+        {
+            warn!("fail to record produced batch, {}", err);
+        }
+
+        self.batch_fill_ratio
+            .with_label_values(&labels)
+            .observe(fill_ratio);
+        self.batch_size
+            .with_label_values(&labels)
+            .observe(bytes as f64);
+    }
+
+    pub fn batch_drained(&self, topic_name: &str, queue_time: Duration, compression_ratio: f64) {
+        let labels = [topic_name];
+
+        self.record_queue_time
+            .with_label_values(&labels)
+            .observe(as_secs_f64(queue_time));
+        self.compression_ratio
+            .with_label_values(&labels)
+            .observe(compression_ratio);
+    }
+
+    /// Record the bytes allocated or shared without being copied to encode a batch.
+    pub fn batch_encoded(&self, topic_name: &str, allocated_bytes: usize, shared_bytes: usize) {
+        let labels = [topic_name];
+
+        if let Err(err) = self.encode_allocated_bytes
+            .with_label_values(&labels)
+            .inc_by(allocated_bytes as f64)
+            .and_then(|_| {
+                self.encode_shared_bytes
+                    .with_label_values(&labels)
+                    .inc_by(shared_bytes as f64)
+            }) {
+            warn!("fail to record encoded batch, {}", err);
+        }
+    }
+
+    pub fn produce_failed(&self, topic_name: &str, error_code: KafkaCode) {
+        self.produce_errors
+            .with_label_values(&[topic_name, &format!("{:?}", error_code)])
+            .inc();
+    }
+}
+
+impl Metrics {
+    /// Report the current values of the metrics.
+    pub fn report(&self, reporter: &MetricsReporter) -> Result<()> {
+        reporter.report(&self.registry.gather())
+    }
+
+    /// Export the current values of the metrics in the Prometheus text format.
+    pub fn export(&self) -> Result<String> {
+        let mut buf = Vec::new();
+
+        TextEncoder::new().encode(&self.registry.gather(), &mut buf)?;
+
+        Ok(String::from_utf8_lossy(&buf).into_owned())
+    }
+}
+
+/// A reporter exporting the metrics to the monitoring system.
+pub trait MetricsReporter {
+    /// Report the gathered metric families.
+    fn report(&self, metrics: &[MetricFamily]) -> Result<()>;
+}
+
+/// A reporter writing the metrics in the Prometheus text format.
+pub struct PrometheusReporter<W> {
+    writer: RefCell<W>,
+}
+
+impl<W: Write> PrometheusReporter<W> {
+    pub fn new(writer: W) -> Self {
+        PrometheusReporter {
+            writer: RefCell::new(writer),
+        }
+    }
+
+    /// Consume the reporter and return the writer.
+    pub fn into_inner(self) -> W {
+        self.writer.into_inner()
+    }
+}
+
+impl<W: Write> MetricsReporter for PrometheusReporter<W> {
+    fn report(&self, metrics: &[MetricFamily]) -> Result<()> {
+        let mut writer = self.writer.borrow_mut();
+
+        TextEncoder::new().encode(metrics, &mut *writer)?;
+
+        Ok(writer.flush()?)
+    }
+}
+
+/// Report the metrics with the interval, until the metrics have been dropped with the client.
+pub fn report_metrics(
+    runtime: Runtime,
+    metrics: Weak<Metrics>,
+    reporter: Rc<MetricsReporter>,
+    interval: Duration,
+) -> Spawned {
+    Box::new(future::loop_fn((), move |_| {
+        let metrics = metrics.clone();
+        let reporter = reporter.clone();
+
+        runtime.sleep(interval).then(move |_| {
+            future::ok::<_, ()>(match metrics.upgrade() {
+                Some(metrics) => {
+                    if let Err(err) = metrics.report(&*reporter) {
+                        warn!("fail to report metrics, {}", err);
+                    }
+
+                    Loop::Continue(())
+                }
+                None => Loop::Break(()),
+            })
+        })
+    }))
+}
+
+fn as_secs_f64(d: Duration) -> f64 {
+    d.as_secs() as f64 + f64::from(d.subsec_nanos()) / 1_000_000_000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export() {
+        let metrics = Metrics::new().unwrap();
+        let addr = "127.0.0.1:9092".parse().unwrap();
+        let request = KafkaRequest::api_versions(0, 123, None);
+
+        metrics.send_request(&addr, &request);
+        metrics.request_failed(&addr, ApiKeys::ApiVersions);
+        metrics.connected(&addr, true);
+        metrics.throttled(&addr, ApiKeys::Produce, Duration::from_millis(250));
+        metrics.batch_produced("topic", 10, 1024, 0.5);
+        metrics.batch_drained("topic", Duration::from_millis(5), 0.3);
+
+        let text = metrics.export().unwrap();
+
+        assert!(text.contains("kafka_client_request_errors{api_key=\"ApiVersions\",broker=\"127.0.0.1:9092\"} 1"));
+        assert!(text.contains("kafka_client_connections{broker=\"127.0.0.1:9092\",result=\"established\"} 1"));
+        assert!(
+            text.contains("kafka_client_throttle_time_seconds_sum{api_key=\"Produce\",broker=\"127.0.0.1:9092\"} 0.25")
+        );
+        assert!(text.contains("kafka_producer_batch_size_bytes_count{topic=\"topic\"} 1"));
+        assert!(text.contains("kafka_producer_compression_ratio_sum{topic=\"topic\"} 0.3"));
+
+        let reporter = PrometheusReporter::new(Vec::new());
+
+        metrics.report(&reporter).unwrap();
+
+        assert_eq!(String::from_utf8(reporter.into_inner()).unwrap(), text);
     }
 }
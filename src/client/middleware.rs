@@ -1,15 +1,18 @@
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::error::Error as StdError;
 use std::net::SocketAddr;
+use std::ops::Deref;
 use std::rc::Rc;
 use std::time::Duration;
-use tokio_timer::{self as timer, Timer};
 
-use futures::Future;
+use futures::{Future, Poll};
 use tokio_service::Service;
 
-use client::{StaticBoxFuture, ToStaticBoxFuture};
+use client::{Runtime, StaticBoxFuture, ToStaticBoxFuture, WithTimeout};
+use errors::Error;
+use network::KafkaRequest;
+use protocol::ApiKeys;
 
 #[derive(Clone)]
 pub struct InFlightMiddleware<S> {
@@ -54,6 +57,14 @@ impl<S> InFlightMiddleware<S> {
     }
 }
 
+impl<S> Deref for InFlightMiddleware<S> {
+    type Target = S;
+
+    fn deref(&self) -> &Self::Target {
+        &self.upstream
+    }
+}
+
 impl<S> Service for InFlightMiddleware<S>
 where
     Self: 'static,
@@ -94,40 +105,210 @@ impl<T> WithAddr for (SocketAddr, T) {
     }
 }
 
+pub trait WithApiKey {
+    fn api_key(&self) -> ApiKeys;
+}
+
+impl<'a> WithApiKey for (SocketAddr, KafkaRequest<'a>) {
+    fn api_key(&self) -> ApiKeys {
+        ApiKeys::from(self.1.header().api_key)
+    }
+}
+
+/// The timeouts of requests, which could be overridden for each API.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RequestTimeouts {
+    default: Duration,
+    apis: HashMap<&'static str, Duration>,
+}
+
+impl RequestTimeouts {
+    /// Create a `RequestTimeouts` which limits all the requests to `default`.
+    pub fn new(default: Duration) -> Self {
+        RequestTimeouts {
+            default,
+            apis: HashMap::new(),
+        }
+    }
+
+    /// Override the timeout of the API.
+    pub fn with_api(mut self, api_key: ApiKeys, timeout: Duration) -> Self {
+        self.apis.insert(api_key.name(), timeout);
+        self
+    }
+
+    /// The timeout of the requests of the API.
+    pub fn timeout(&self, api_key: ApiKeys) -> Duration {
+        self.apis.get(api_key.name()).cloned().unwrap_or(self.default)
+    }
+}
+
+/// The signal fired by `Timeout` when the request timed out, to abort the connection of the request.
+///
+/// The connection is kept if the request was dropped before the signal has been fired,
+/// e.g. the caller gave up the request or another request of a `select` completed first.
+#[derive(Clone, Debug, Default)]
+pub struct AbortSignal(Rc<Cell<bool>>);
+
+impl AbortSignal {
+    /// Fire the signal to abort the connection.
+    pub fn abort(&self) {
+        self.0.set(true)
+    }
+
+    /// The signal has been fired.
+    pub fn is_aborted(&self) -> bool {
+        self.0.get()
+    }
+}
+
+/// The service which could abort the connection of a request once the signal has been fired.
+pub trait Abortable: Service {
+    /// Send the request, and abort its connection if the response is dropped after the `abort` signal fired.
+    fn call_abortable(&self, request: Self::Request, abort: AbortSignal) -> Self::Future;
+}
+
 /// Abort requests that are taking too long
 #[derive(Clone)]
 pub struct Timeout<S> {
     upstream: S,
-    timer: Timer,
-    duration: Duration,
+    runtime: Runtime,
+    timeouts: RequestTimeouts,
 }
 
 impl<S> Timeout<S> {
     /// Crate a new `Timeout` with the given `upstream` service.
     ///
-    /// Requests will be limited to `duration` and aborted once the limit has
-    /// been reached.
-    pub fn new(upstream: S, timer: Timer, duration: Duration) -> Timeout<S> {
+    /// Requests will be limited to the timeout of their API and aborted once the limit has
+    /// been reached, the response of an aborted request is dropped and its connection will be closed.
+    pub fn new(upstream: S, runtime: Runtime, timeouts: RequestTimeouts) -> Timeout<S> {
         Timeout {
             upstream,
-            duration,
-            timer,
+            timeouts,
+            runtime,
         }
     }
 }
 
+impl<S> Deref for Timeout<S> {
+    type Target = S;
+
+    fn deref(&self) -> &Self::Target {
+        &self.upstream
+    }
+}
+
 impl<S, E> Service for Timeout<S>
 where
-    S: Service<Error = E>,
-    E: From<timer::TimeoutError<S::Future>>,
+    S: Abortable<Error = E>,
+    S::Request: WithApiKey,
+    E: From<Error>,
 {
     type Request = S::Request;
     type Response = S::Response;
     type Error = S::Error;
-    type Future = timer::Timeout<S::Future>;
+    type Future = TimeoutResponse<S::Future>;
 
     fn call(&self, request: Self::Request) -> Self::Future {
-        let resp = self.upstream.call(request);
-        self.timer.timeout(resp, self.duration)
+        let duration = self.timeouts.timeout(request.api_key());
+        let abort = AbortSignal::default();
+        let resp = self.upstream.call_abortable(request, abort.clone());
+
+        TimeoutResponse {
+            response: self.runtime.timeout(resp, duration),
+            abort,
+        }
+    }
+}
+
+/// The response of a request limited by `Timeout`, which fires the abort signal when the timer fires.
+pub struct TimeoutResponse<F> {
+    response: WithTimeout<F>,
+    abort: AbortSignal,
+}
+
+impl<F, E> Future for TimeoutResponse<F>
+where
+    F: Future<Error = E>,
+    E: From<Error>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let res = self.response.poll();
+
+        // the timed out request is dropped with the error, after the signal has been fired
+        if self.response.is_elapsed() {
+            self.abort.abort();
+        }
+
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future;
+    use tokio_core::reactor::Core;
+
+    use super::*;
+
+    #[test]
+    fn test_request_timeouts() {
+        let timeouts =
+            RequestTimeouts::new(Duration::from_secs(30)).with_api(ApiKeys::JoinGroup, Duration::from_secs(300));
+
+        assert_eq!(timeouts.timeout(ApiKeys::JoinGroup), Duration::from_secs(300));
+        assert_eq!(timeouts.timeout(ApiKeys::Metadata), Duration::from_secs(30));
+    }
+
+    struct Pending {
+        signals: RefCell<Vec<AbortSignal>>,
+    }
+
+    impl Service for Pending {
+        type Request = (SocketAddr, KafkaRequest<'static>);
+        type Response = ();
+        type Error = Error;
+        type Future = future::Empty<(), Error>;
+
+        fn call(&self, request: Self::Request) -> Self::Future {
+            self.call_abortable(request, AbortSignal::default())
+        }
+    }
+
+    impl Abortable for Pending {
+        fn call_abortable(&self, _request: Self::Request, abort: AbortSignal) -> Self::Future {
+            self.signals.borrow_mut().push(abort);
+
+            future::empty()
+        }
+    }
+
+    #[test]
+    fn test_abort_on_timeout() {
+        let mut core = Core::new().unwrap();
+        let timeout = Timeout::new(
+            Pending {
+                signals: RefCell::new(vec![]),
+            },
+            Runtime::from(core.handle()),
+            RequestTimeouts::new(Duration::from_millis(50)),
+        );
+        let request = || {
+            (
+                "127.0.0.1:9092".parse().unwrap(),
+                KafkaRequest::fetch_metadata::<&str>(0, 0, None, &[], false),
+            )
+        };
+
+        // the request dropped before timed out keeps its connection
+        drop(timeout.call(request()));
+
+        assert!(!timeout.signals.borrow()[0].is_aborted());
+
+        assert!(core.run(timeout.call(request())).is_err());
+        assert!(timeout.signals.borrow()[1].is_aborted());
     }
 }
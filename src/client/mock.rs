@@ -10,20 +10,24 @@ use std::usize;
 use bytes::Bytes;
 use typemap::{Key, TypeMap};
 
+use futures::future;
 use tokio_core::reactor::Handle;
 
-use client::{Broker, BrokerRef, Client, Cluster, ConsumerGroup, ConsumerGroupAssignment, ConsumerGroupProtocol,
-             FetchRecords, Generation, GetMetadata, GroupCoordinator, Heartbeat, JoinGroup, LeaveGroup, ListOffsets,
-             LoadMetadata, Metadata, OffsetCommit, OffsetFetch, PartitionData, ProduceRecords, SyncGroup,
-             ToStaticBoxFuture};
-use consumer::Assignment;
+use client::{AlterConfigs, Broker, BrokerRef, Client, Cluster, ClusterDescription, CommittedOffset, ConfigResource,
+             ConsumerGroup, ConsumerGroupAssignment, ConsumerGroupProtocol, CreateTopic, DeleteRecords, DeleteTopic,
+             DescribeCluster, DescribeConfigs, DescribeGroups, FetchRecords, Generation, GetMetadata,
+             GroupCoordinator, Heartbeat, JoinGroup, LeaveGroup, ListGroups, ListOffsets, LoadMetadata, Metadata,
+             OffsetCommit, OffsetFetch, OffsetsForLeaderEpoch, OffsetsForTimes, PartitionData, ProduceRecords,
+             Runtime, SyncGroup, ToStaticBoxFuture};
+use consumer::{Assignment, ReplicaSelector};
 use errors::{ErrorKind, Result};
 use network::{OffsetAndMetadata, TopicPartition};
-use protocol::{FetchOffset, KafkaCode, MessageSet, RequiredAcks, Schema};
+use protocol::{FetchOffset, KafkaCode, LeaderEpoch, MessageSet, Offset, RequiredAcks, Schema, Timestamp};
 
 #[derive(Clone)]
 pub struct MockClient<'a> {
     handle: Option<Handle>,
+    runtime: Option<Runtime>,
     metadata: Rc<Metadata>,
     group_coordinators: HashMap<Cow<'a, str>, Broker>,
     consumer_groups: HashMap<Cow<'a, str>, ConsumerGroup>,
@@ -39,6 +43,7 @@ impl<'a> MockClient<'a> {
     pub fn with_metadata(metadata: Metadata) -> Self {
         MockClient {
             handle: None,
+            runtime: None,
             metadata: Rc::new(metadata),
             group_coordinators: HashMap::new(),
             consumer_groups: HashMap::new(),
@@ -48,10 +53,16 @@ impl<'a> MockClient<'a> {
     }
 
     pub fn with_handle(mut self, handle: Handle) -> Self {
+        self.runtime = Some(Runtime::from(handle.clone()));
         self.handle = Some(handle);
         self
     }
 
+    pub fn with_runtime(mut self, runtime: Runtime) -> Self {
+        self.runtime = Some(runtime);
+        self
+    }
+
     pub fn with_group_coordinator(mut self, group_id: Cow<'a, str>, broker: Broker) -> Self {
         self.group_coordinators.insert(group_id, broker);
         self
@@ -107,6 +118,12 @@ where
         &self.handle.as_ref().expect("should attach event loop with `with_core`")
     }
 
+    fn runtime(&self) -> &Runtime {
+        self.runtime
+            .as_ref()
+            .expect("should attach runtime with `with_handle` or `with_runtime`")
+    }
+
     fn metadata(&self) -> GetMetadata {
         GetMetadata::Loaded(self.metadata.clone())
     }
@@ -125,11 +142,22 @@ where
         unimplemented!()
     }
 
+    fn produce_batches(
+        &self,
+        broker: BrokerRef,
+        acks: RequiredAcks,
+        timeout: Duration,
+        batches: Vec<(TopicPartition<'a>, Cow<'a, MessageSet>)>,
+    ) -> ProduceRecords {
+        unimplemented!()
+    }
+
     fn fetch_records(
         &self,
         fetch_max_wait: Duration,
         fetch_min_bytes: usize,
         fetch_max_bytes: usize,
+        replica_selector: Rc<ReplicaSelector>,
         partitions: Vec<(TopicPartition<'a>, PartitionData)>,
     ) -> FetchRecords {
         unimplemented!()
@@ -142,6 +170,20 @@ where
         unimplemented!()
     }
 
+    fn offsets_before<I>(&self, partitions: I, max_number_of_offsets: i32) -> ListOffsets
+    where
+        I: IntoIterator<Item = (TopicPartition<'a>, FetchOffset)>,
+    {
+        unimplemented!()
+    }
+
+    fn offsets_for_times<I>(&self, partitions: I) -> OffsetsForTimes<'a>
+    where
+        I: IntoIterator<Item = (TopicPartition<'a>, Timestamp)>,
+    {
+        unimplemented!()
+    }
+
     fn load_metadata(&mut self) -> LoadMetadata<'a> {
         unimplemented!()
     }
@@ -156,7 +198,30 @@ where
     where
         I: IntoIterator<Item = (TopicPartition<'a>, OffsetAndMetadata)>,
     {
-        unimplemented!()
+        let error_code = match generation {
+            // the simple consumers commit without the generation of a group
+            Some(ref generation) if generation.generation_id < 0 && generation.member_id.is_empty() => KafkaCode::None,
+            Some(ref generation) => match self.consumer_groups.get(generation.group_id.as_str()) {
+                Some(group) if generation.member_id != group.member_id => KafkaCode::UnknownMemberId,
+                Some(group) if generation.generation_id != group.generation_id => KafkaCode::IllegalGeneration,
+                _ => KafkaCode::None,
+            },
+            None => KafkaCode::None,
+        };
+
+        let mut committed = HashMap::new();
+
+        for (tp, _) in offsets {
+            committed
+                .entry(String::from(tp.topic_name))
+                .or_insert_with(Vec::new)
+                .push(CommittedOffset {
+                    partition_id: tp.partition_id,
+                    error_code,
+                });
+        }
+
+        future::ok(committed).static_boxed()
     }
 
     fn offset_fetch<I>(&self, coordinator: BrokerRef, generation: Generation, partitions: I) -> OffsetFetch
@@ -237,4 +302,61 @@ where
             Err(ErrorKind::KafkaError(KafkaCode::NotCoordinator).into())
         }.static_boxed()
     }
+
+    fn create_topic(
+        &self,
+        topic_name: Cow<'a, str>,
+        num_partitions: Option<i32>,
+        replication_factor: Option<i16>,
+        configs: HashMap<String, String>,
+    ) -> CreateTopic {
+        unimplemented!()
+    }
+
+    fn delete_topic(&self, topic_name: Cow<'a, str>) -> DeleteTopic {
+        unimplemented!()
+    }
+
+    fn delete_records<I>(&self, partitions: I) -> DeleteRecords
+    where
+        I: IntoIterator<Item = (TopicPartition<'a>, Offset)>,
+    {
+        unimplemented!()
+    }
+
+    fn offsets_for_leader_epoch<I>(&self, partitions: I) -> OffsetsForLeaderEpoch
+    where
+        I: IntoIterator<Item = (TopicPartition<'a>, LeaderEpoch)>,
+    {
+        unimplemented!()
+    }
+
+    fn describe_configs<I>(&self, resources: I) -> DescribeConfigs
+    where
+        I: IntoIterator<Item = ConfigResource>,
+    {
+        unimplemented!()
+    }
+
+    fn alter_configs<I>(&self, changes: I) -> AlterConfigs
+    where
+        I: IntoIterator<Item = (ConfigResource, HashMap<String, String>)>,
+    {
+        unimplemented!()
+    }
+
+    fn describe_cluster(&self) -> DescribeCluster {
+        future::ok(ClusterDescription::from(&*self.metadata)).static_boxed()
+    }
+
+    fn list_groups(&self) -> ListGroups {
+        unimplemented!()
+    }
+
+    fn describe_groups<I>(&self, group_ids: I) -> DescribeGroups
+    where
+        I: IntoIterator<Item = String>,
+    {
+        unimplemented!()
+    }
 }
@@ -1,30 +1,63 @@
 mod builder;
+#[cfg(feature = "chaos")]
+mod chaos;
 mod client;
 mod cluster;
 mod config;
+mod feature;
+mod listener;
 mod metadata;
 mod metrics;
 mod middleware;
+mod queue;
+mod quota;
 mod record;
+mod retry;
+mod runtime;
 mod service;
+mod session;
+mod shutdown;
+#[cfg(feature = "simulation")]
+mod simulation;
+mod topic;
 mod version;
 
 #[cfg(test)]
 mod mock;
 
 pub use self::builder::ClientBuilder;
-pub use self::client::{Client, ConsumerGroup, ConsumerGroupAssignment, ConsumerGroupMember, ConsumerGroupProtocol,
-                       FetchRecords, FetchedRecords, Generation, GetMetadata, GroupCoordinator, Heartbeat, JoinGroup,
-                       KafkaClient, LeaveGroup, ListOffsets, ListedOffset, LoadMetadata, OffsetCommit, OffsetFetch,
-                       PartitionData, ProduceRecords, StaticBoxFuture, SyncGroup, ToStaticBoxFuture};
+#[cfg(feature = "chaos")]
+pub use self::chaos::{Chaos, ChaosMiddleware, Fault};
+pub use self::client::{AlterConfigs, Client, ClusterDescription, CommittedOffset, ConfigEntry, ConfigResource,
+                       ConfigSource, ConsumerGroup, ConsumerGroupAssignment, ConsumerGroupMember, ConsumerGroupProtocol,
+                       CreateTopic, DeleteRecords, DeleteTopic, DeletedRecords, DescribeCluster, DescribeConfigs,
+                       DescribeGroups, EpochEndOffset, FetchPartitionRecords, FetchRecords, FetchedRecords, Generation,
+                       GetMetadata, GroupCoordinator, GroupDescription, GroupListing, GroupMemberDescription,
+                       GroupState, Heartbeat, JoinGroup, KafkaClient, LeaveGroup, ListGroups, ListOffsets, ListedOffset,
+                       LoadMetadata, OffsetCommit, OffsetFetch, OffsetsForLeaderEpoch, OffsetsForTimes, PartitionData,
+                       ProduceRecords, ProducedRecords, Shutdown, StaticBoxFuture, Supports, SyncGroup,
+                       ToStaticBoxFuture, TopicMetadata, WarmUp};
 pub use self::cluster::{Broker, BrokerRef, Cluster, PartitionInfo};
-pub use self::config::{ClientConfig, DEFAULT_MAX_CONNECTION_IDLE_TIMEOUT_MILLIS, DEFAULT_METADATA_MAX_AGE_MILLS,
-                       DEFAULT_REQUEST_TIMEOUT_MILLS, DEFAULT_RETRY_BACKOFF_MILLIS};
+pub use self::config::{ClientConfig, DEFAULT_DNS_REFRESH_INTERVAL_MILLIS, DEFAULT_MAX_CONNECTION_IDLE_TIMEOUT_MILLIS,
+                       DEFAULT_MAX_IN_FLIGHT_REQUESTS_PER_CONNECTION, DEFAULT_METADATA_BOOTSTRAP_RETRIES,
+                       DEFAULT_METADATA_MAX_AGE_MILLS, DEFAULT_RECONNECT_BACKOFF_MAX_MILLIS,
+                       DEFAULT_RECONNECT_BACKOFF_MILLIS, DEFAULT_REQUEST_TIMEOUT_MILLS, DEFAULT_RETRY_BACKOFF_MILLIS};
+pub use self::feature::Feature;
+pub use self::listener::{ClientListener, ClientListeners};
 pub use self::metadata::{Metadata, TopicPartitions};
-pub use self::metrics::Metrics;
-pub use self::middleware::InFlightMiddleware;
+pub use self::metrics::{report_metrics, Metrics, MetricsReporter, PrometheusReporter};
+pub use self::middleware::{AbortSignal, Abortable, InFlightMiddleware, RequestTimeouts, TimeoutResponse};
+pub use self::queue::{AcquireSlot, QueueFullBehavior, QueueSlot, RequestQueues};
+pub use self::quota::FetchQuotas;
 pub use self::record::{PartitionRecord, TopicRecord};
-pub use self::service::{FutureResponse, KafkaService};
+pub use self::retry::{retry, ErrorClass, RetryPolicies, RetryPolicy, DEFAULT_RETRY_BACKOFF_MAX_MILLIS};
+pub use self::runtime::{Executor, Interval, Runtime, Sleep, Spawned, Timer, WithTimeout};
+pub use self::service::{FutureResponse, Hostnames, KafkaService};
+pub use self::session::{FetchSessionRequest, FetchSessions};
+pub use self::shutdown::ShutdownReport;
+#[cfg(feature = "simulation")]
+pub use self::simulation::{SimulatedListener, SimulatedStream, SimulatedTransport, Simulation};
+pub use self::topic::TypedTopic;
 pub use self::version::KafkaVersion;
 
 #[cfg(test)]
@@ -0,0 +1,225 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::rc::Rc;
+use std::str::FromStr;
+
+use futures::task::{self, Task};
+use futures::{Async, Future, Poll};
+
+use errors::{Error, ErrorKind, Result};
+
+/// What to do when the request queue of a broker is full.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QueueFullBehavior {
+    /// Fail the new requests with `QueueFull` at once.
+    Fail,
+    /// Wait until the pending requests of the broker have been completed.
+    Block,
+}
+
+impl Default for QueueFullBehavior {
+    fn default() -> Self {
+        QueueFullBehavior::Fail
+    }
+}
+
+impl FromStr for QueueFullBehavior {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "fail" => Ok(QueueFullBehavior::Fail),
+            "block" => Ok(QueueFullBehavior::Block),
+            _ => bail!(ErrorKind::ParseError(format!("unknown queue full behavior: {}", s))),
+        }
+    }
+}
+
+/// Bound the pending requests of each broker connection.
+///
+/// A request takes a slot of its broker until it is completed or dropped,
+/// the requests will not be bounded if the maximum number is zero.
+#[derive(Clone, Debug, Default)]
+pub struct RequestQueues {
+    max_requests: usize,
+    behavior: QueueFullBehavior,
+    inner: Rc<RefCell<HashMap<SocketAddr, Queue>>>,
+}
+
+#[derive(Debug, Default)]
+struct Queue {
+    pending: usize,
+    waiters: Vec<Task>,
+}
+
+impl RequestQueues {
+    pub fn new(max_requests: usize, behavior: QueueFullBehavior) -> Self {
+        RequestQueues {
+            max_requests,
+            behavior,
+            inner: Rc::new(RefCell::new(HashMap::new())),
+        }
+    }
+
+    /// The number of the pending requests of the broker.
+    pub fn pending(&self, addr: &SocketAddr) -> usize {
+        self.inner.borrow().get(addr).map_or(0, |queue| queue.pending)
+    }
+
+    /// Take a slot of the broker for a new request.
+    pub fn acquire(&self, addr: SocketAddr) -> AcquireSlot {
+        AcquireSlot {
+            queues: self.clone(),
+            addr,
+        }
+    }
+
+    fn try_acquire(&self, addr: SocketAddr) -> bool {
+        let mut inner = self.inner.borrow_mut();
+        let queue = inner.entry(addr).or_insert_with(Queue::default);
+
+        if self.max_requests > 0 && queue.pending >= self.max_requests {
+            false
+        } else {
+            queue.pending += 1;
+
+            true
+        }
+    }
+
+    fn release(&self, addr: &SocketAddr) {
+        let waiters = {
+            let mut inner = self.inner.borrow_mut();
+
+            match inner.get_mut(addr) {
+                Some(queue) => {
+                    queue.pending = queue.pending.saturating_sub(1);
+                    queue.waiters.drain(..).collect::<Vec<_>>()
+                }
+                None => vec![],
+            }
+        };
+
+        for waiter in waiters {
+            waiter.notify();
+        }
+    }
+}
+
+/// The future of taking a slot of the broker.
+pub struct AcquireSlot {
+    queues: RequestQueues,
+    addr: SocketAddr,
+}
+
+impl Future for AcquireSlot {
+    type Item = QueueSlot;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        if self.queues.try_acquire(self.addr) {
+            return Ok(Async::Ready(QueueSlot {
+                queues: self.queues.clone(),
+                addr: self.addr,
+            }));
+        }
+
+        match self.queues.behavior {
+            QueueFullBehavior::Fail => bail!(ErrorKind::QueueFull(self.addr, self.queues.max_requests)),
+            QueueFullBehavior::Block => {
+                trace!("request queue of {} is full, wait for the pending requests", self.addr);
+
+                if let Some(queue) = self.queues.inner.borrow_mut().get_mut(&self.addr) {
+                    queue.waiters.push(task::current());
+                }
+
+                Ok(Async::NotReady)
+            }
+        }
+    }
+}
+
+/// The slot taken by a pending request, which is released when dropped.
+pub struct QueueSlot {
+    queues: RequestQueues,
+    addr: SocketAddr,
+}
+
+impl Drop for QueueSlot {
+    fn drop(&mut self) {
+        self.queues.release(&self.addr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future;
+
+    use super::*;
+
+    #[test]
+    fn test_request_queues() {
+        let addr = "127.0.0.1:9092".parse().unwrap();
+        let queues = RequestQueues::new(2, QueueFullBehavior::Fail);
+
+        let first = queues.acquire(addr).wait().unwrap();
+        let second = queues.acquire(addr).wait().unwrap();
+
+        assert_eq!(queues.pending(&addr), 2);
+
+        match *queues.acquire(addr).wait().err().unwrap().kind() {
+            ErrorKind::QueueFull(full_addr, 2) => assert_eq!(full_addr, addr),
+            ref kind => panic!("unexpected error: {:?}", kind),
+        }
+
+        // the other brokers have their own queues
+        assert!(queues.acquire("127.0.0.1:9093".parse().unwrap()).wait().is_ok());
+
+        drop(first);
+
+        assert_eq!(queues.pending(&addr), 1);
+        assert!(queues.acquire(addr).wait().is_ok());
+
+        drop(second);
+
+        assert_eq!(queues.pending(&addr), 0);
+    }
+
+    #[test]
+    fn test_blocking_queue() {
+        let addr = "127.0.0.1:9092".parse().unwrap();
+        let queues = RequestQueues::new(1, QueueFullBehavior::Block);
+
+        let slot = queues.acquire(addr).wait().unwrap();
+        let mut blocked = queues.acquire(addr);
+
+        future::lazy(|| {
+            assert!(blocked.poll().unwrap().is_not_ready());
+
+            drop(slot);
+
+            assert!(blocked.poll().unwrap().is_ready());
+
+            Ok::<_, Error>(())
+        }).wait()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_unbounded_queue() {
+        let addr = "127.0.0.1:9092".parse().unwrap();
+        let queues = RequestQueues::default();
+
+        let slots = (0..100)
+            .map(|_| queues.acquire(addr).wait().unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(queues.pending(&addr), 100);
+
+        drop(slots);
+
+        assert_eq!(queues.pending(&addr), 0);
+    }
+}
@@ -0,0 +1,105 @@
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::Duration;
+
+/// The minimum ratio of the fetch size to the configured size.
+const MIN_FETCH_RATIO: f64 = 1.0 / 64.0;
+
+/// The ratio increased by each unthrottled fetch response.
+const FETCH_RATIO_STEP: f64 = 1.0 / 16.0;
+
+/// Adapt the fetch size of each broker to the quota of consumer.
+///
+/// The fetch size of a broker is halved when its fetch response was throttled,
+/// and slowly ramped back up to the configured size by the following unthrottled responses.
+#[derive(Clone, Debug, Default)]
+pub struct FetchQuotas {
+    ratios: Rc<RefCell<HashMap<(String, u16), f64>>>,
+}
+
+impl FetchQuotas {
+    /// The ratio of the fetch size to the configured size for the broker.
+    pub fn ratio(&self, host: &str, port: u16) -> f64 {
+        self.ratios
+            .borrow()
+            .get(&(host.to_owned(), port))
+            .cloned()
+            .unwrap_or(1.0)
+    }
+
+    /// Scale the configured fetch size for the broker.
+    pub fn scale(&self, host: &str, port: u16, size: usize) -> usize {
+        cmp::max(1, (size as f64 * self.ratio(host, port)) as usize)
+    }
+
+    /// Update the ratio of broker with the throttle time of its fetch response.
+    pub fn update(&self, host: &str, port: u16, throttle_time: Duration) {
+        let mut ratios = self.ratios.borrow_mut();
+        let key = (host.to_owned(), port);
+
+        if throttle_time > Duration::default() {
+            let ratio = ratios.entry(key).or_insert(1.0);
+
+            *ratio = (*ratio / 2.0).max(MIN_FETCH_RATIO);
+
+            debug!(
+                "fetch from {}:{} was throttled for {:?}, reduce fetch size to {:.1}%",
+                host,
+                port,
+                throttle_time,
+                *ratio * 100.0
+            );
+        } else {
+            let recovered = match ratios.get_mut(&key) {
+                Some(ratio) => {
+                    *ratio += FETCH_RATIO_STEP;
+                    *ratio >= 1.0
+                }
+                None => false,
+            };
+
+            if recovered {
+                trace!("fetch size of {}:{} was recovered", host, port);
+
+                ratios.remove(&key);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fetch_quotas() {
+        let quotas = FetchQuotas::default();
+
+        assert_eq!(quotas.scale("localhost", 9092, 1024), 1024);
+
+        quotas.update("localhost", 9092, Duration::from_millis(100));
+        quotas.update("localhost", 9092, Duration::from_millis(100));
+
+        assert_eq!(quotas.scale("localhost", 9092, 1024), 256);
+        assert_eq!(quotas.scale("localhost", 9093, 1024), 1024);
+
+        quotas.update("localhost", 9092, Duration::default());
+
+        assert_eq!(quotas.scale("localhost", 9092, 1024), 320);
+
+        for _ in 0..100 {
+            quotas.update("localhost", 9092, Duration::from_secs(1));
+        }
+
+        assert_eq!(quotas.scale("localhost", 9092, 6400), 100);
+
+        for _ in 0..16 {
+            quotas.update("localhost", 9092, Duration::default());
+        }
+
+        assert_eq!(quotas.scale("localhost", 9092, 1024), 1024);
+        assert!(quotas.ratios.borrow().is_empty());
+    }
+}
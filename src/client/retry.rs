@@ -0,0 +1,442 @@
+use std::cmp;
+use std::rc::Rc;
+use std::time::Duration;
+
+use abstract_ns::Error as ResolveError;
+use futures::future::{self, Either, Loop};
+use futures::Future;
+use tokio_retry::strategy::{jitter, ExponentialBackoff};
+
+use client::{Runtime, StaticBoxFuture, ToStaticBoxFuture, DEFAULT_RETRY_BACKOFF_MILLIS};
+use errors::{Error, ErrorKind};
+use protocol::KafkaCode;
+
+/// The default maximum time to wait before attempting to retry a failed request.
+///
+/// Defaults to 10 seconds, see [`RetryPolicy::max_backoff`](struct.RetryPolicy.html#max_backoff.v)
+pub const DEFAULT_RETRY_BACKOFF_MAX_MILLIS: u64 = 10_000;
+
+/// The class of the errors which decides how the failed request will be retried.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ErrorClass {
+    /// The transient error, the request could be retried after a backoff.
+    Retriable,
+    /// The error caused by the stale metadata, the metadata should be refreshed before retrying.
+    RefreshMetadata,
+    /// The error which would not be resolved by retrying.
+    Fatal,
+}
+
+impl ErrorClass {
+    /// Classify the error code returned by the server.
+    pub fn from_code(code: KafkaCode) -> Self {
+        if code.is_stale_metadata() {
+            ErrorClass::RefreshMetadata
+        } else if code.is_retriable() {
+            ErrorClass::Retriable
+        } else {
+            ErrorClass::Fatal
+        }
+    }
+
+    /// Classify the error of a failed request.
+    pub fn of(err: &Error) -> Self {
+        match *err.kind() {
+            ErrorKind::KafkaError(code) => Self::from_code(code),
+            ErrorKind::IoError(_)
+            | ErrorKind::TimeoutError(_)
+            | ErrorKind::TimerError(_)
+            | ErrorKind::QueueFull(..) => ErrorClass::Retriable,
+            ErrorKind::BrokerNotFound(_) | ErrorKind::TopicNotFound(_) => ErrorClass::RefreshMetadata,
+            ErrorKind::ResolveError(ref err) => match *err {
+                ResolveError::TemporaryError(_) => ErrorClass::Retriable,
+                // the hostname of the broker may have been changed
+                ResolveError::NameNotFound => ErrorClass::RefreshMetadata,
+                _ => ErrorClass::Fatal,
+            },
+            _ => ErrorClass::Fatal,
+        }
+    }
+}
+
+/// The policy to retry the requests failed with a class of errors.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// The maximum number of retries, the request will not be retried if it is zero.
+    #[serde(rename = "max.retries")]
+    pub max_retries: usize,
+
+    /// The base amount of time to wait before retrying,
+    /// increased exponentially for each consecutive failure.
+    #[serde(rename = "backoff.ms")]
+    pub backoff: u64,
+
+    /// The maximum amount of time to wait before retrying.
+    #[serde(rename = "backoff.max.ms")]
+    pub max_backoff: u64,
+
+    /// Randomize the backoff to avoid the retries of the clients are synchronized.
+    pub jitter: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 0,
+            backoff: DEFAULT_RETRY_BACKOFF_MILLIS,
+            max_backoff: DEFAULT_RETRY_BACKOFF_MAX_MILLIS,
+            jitter: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The maximum amount of time to wait before retrying.
+    pub fn max_backoff(&self) -> Duration {
+        Duration::from_millis(self.max_backoff)
+    }
+
+    /// The backoffs of the retries.
+    pub fn backoffs(&self) -> Vec<Duration> {
+        let max_backoff = self.max_backoff();
+        let randomize = self.jitter;
+
+        ExponentialBackoff::from_millis(self.backoff)
+            .map(|backoff| cmp::min(backoff, max_backoff))
+            .map(|backoff| if randomize { jitter(backoff) } else { backoff })
+            .take(self.max_retries)
+            .collect()
+    }
+}
+
+/// The retry policies for each class of errors.
+///
+/// The policy of an error class falls back to the `retries` and `retry.backoff.ms` if it is not configured,
+/// and the fatal errors are never retried.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicies {
+    /// The policy to retry the requests failed with a transient error.
+    pub retriable: Option<RetryPolicy>,
+
+    /// The policy to retry the requests failed with the stale metadata.
+    #[serde(rename = "refresh.metadata")]
+    pub refresh_metadata: Option<RetryPolicy>,
+}
+
+impl RetryPolicies {
+    /// The policy to retry the requests failed with the class of errors.
+    pub fn policy(&self, class: ErrorClass) -> Option<&RetryPolicy> {
+        match class {
+            ErrorClass::Retriable => self.retriable.as_ref(),
+            ErrorClass::RefreshMetadata => self.refresh_metadata.as_ref(),
+            ErrorClass::Fatal => None,
+        }
+    }
+
+    /// The backoffs to retry the requests failed with the class of errors.
+    pub fn backoffs(&self, class: ErrorClass) -> Vec<Duration> {
+        self.policy(class).map(RetryPolicy::backoffs).unwrap_or_default()
+    }
+
+    /// Use the policies of the `other` for the error classes not configured.
+    pub fn or(self, other: RetryPolicies) -> RetryPolicies {
+        RetryPolicies {
+            retriable: self.retriable.or(other.retriable),
+            refresh_metadata: self.refresh_metadata.or(other.refresh_metadata),
+        }
+    }
+}
+
+/// Run the action, and retry it with the policy of the error class when it failed.
+///
+/// The metadata is reloaded with `reload_metadata` before the backoff of the errors caused by the stale metadata,
+/// so the action should be a single attempt which doesn't refresh the metadata itself.
+pub fn retry<F, R, T, M>(
+    runtime: Runtime,
+    policies: &RetryPolicies,
+    reload_metadata: M,
+    action: F,
+) -> StaticBoxFuture<T>
+where
+    F: Fn() -> R + 'static,
+    R: Future<Item = T, Error = Error> + 'static,
+    T: 'static,
+    M: Fn() -> StaticBoxFuture + 'static,
+{
+    let reload_metadata = Rc::new(reload_metadata);
+    let backoffs = (
+        policies.backoffs(ErrorClass::Retriable).into_iter(),
+        policies.backoffs(ErrorClass::RefreshMetadata).into_iter(),
+    );
+
+    future::loop_fn(backoffs, move |(mut retriable, mut refresh_metadata)| {
+        let runtime = runtime.clone();
+        let reload_metadata = reload_metadata.clone();
+
+        action().then(move |res| match res {
+            Ok(res) => Either::A(future::ok(Loop::Break(res))),
+            Err(err) => {
+                let class = ErrorClass::of(&err);
+                let backoff = match class {
+                    ErrorClass::Retriable => retriable.next(),
+                    ErrorClass::RefreshMetadata => refresh_metadata.next(),
+                    ErrorClass::Fatal => None,
+                };
+
+                match backoff {
+                    Some(backoff) => {
+                        debug!("request failed with {:?} error, retry after {:?}, {}", class, backoff, err);
+
+                        let reload = if class == ErrorClass::RefreshMetadata {
+                            reload_metadata()
+                                .then(|res| {
+                                    if let Err(err) = res {
+                                        warn!("fail to reload metadata, {}", err);
+                                    }
+
+                                    Ok(())
+                                })
+                                .static_boxed()
+                        } else {
+                            future::ok(()).static_boxed()
+                        };
+
+                        Either::B(
+                            reload
+                                .and_then(move |_| runtime.sleep(backoff))
+                                .map(move |_| Loop::Continue((retriable, refresh_metadata))),
+                        )
+                    }
+                    None => Either::A(future::err(err)),
+                }
+            }
+        })
+    }).static_boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+    use std::io;
+
+    use futures::future;
+
+    use super::*;
+
+    use client::{Executor, Sleep, Spawned, Timer};
+
+    #[derive(Clone, Default)]
+    struct ManualTimer {
+        slept: Rc<RefCell<Vec<Duration>>>,
+    }
+
+    impl Executor for ManualTimer {
+        fn spawn(&self, _future: Spawned) {}
+    }
+
+    impl Timer for ManualTimer {
+        fn sleep(&self, duration: Duration) -> Sleep {
+            self.slept.borrow_mut().push(duration);
+
+            future::ok(()).static_boxed()
+        }
+    }
+
+    fn reload_nothing() -> StaticBoxFuture {
+        future::ok(()).static_boxed()
+    }
+
+    #[test]
+    fn test_error_class() {
+        assert_eq!(
+            ErrorClass::from_code(KafkaCode::NotLeaderForPartition),
+            ErrorClass::RefreshMetadata
+        );
+        assert_eq!(ErrorClass::from_code(KafkaCode::RequestTimedOut), ErrorClass::Retriable);
+        assert_eq!(ErrorClass::from_code(KafkaCode::MessageSizeTooLarge), ErrorClass::Fatal);
+
+        assert_eq!(
+            ErrorClass::of(&ErrorKind::TimeoutError("request".to_owned()).into()),
+            ErrorClass::Retriable
+        );
+        assert_eq!(
+            ErrorClass::of(&ErrorKind::QueueFull("127.0.0.1:9092".parse().unwrap(), 5).into()),
+            ErrorClass::Retriable
+        );
+        assert_eq!(
+            ErrorClass::of(&ErrorKind::ParseError("response".to_owned()).into()),
+            ErrorClass::Fatal
+        );
+
+        let err = ResolveError::TemporaryError(io::Error::new(io::ErrorKind::TimedOut, "dns timed out").into());
+
+        assert_eq!(ErrorClass::of(&ErrorKind::ResolveError(err).into()), ErrorClass::Retriable);
+        assert_eq!(
+            ErrorClass::of(&ErrorKind::ResolveError(ResolveError::NameNotFound).into()),
+            ErrorClass::RefreshMetadata
+        );
+    }
+
+    #[test]
+    fn test_backoffs() {
+        let policy = RetryPolicy {
+            max_retries: 3,
+            backoff: 100,
+            max_backoff: 1000,
+            jitter: false,
+        };
+
+        assert_eq!(
+            policy.backoffs(),
+            vec![
+                Duration::from_millis(100),
+                Duration::from_millis(1000),
+                Duration::from_millis(1000),
+            ]
+        );
+
+        let policies = RetryPolicies {
+            retriable: Some(policy.clone()),
+            ..Default::default()
+        };
+
+        assert_eq!(policies.backoffs(ErrorClass::Retriable).len(), 3);
+        assert!(policies.backoffs(ErrorClass::RefreshMetadata).is_empty());
+        assert!(policies.backoffs(ErrorClass::Fatal).is_empty());
+
+        let policies = policies.or(RetryPolicies {
+            retriable: Some(RetryPolicy::default()),
+            refresh_metadata: Some(RetryPolicy::default()),
+        });
+
+        assert_eq!(policies.retriable, Some(policy));
+        assert_eq!(policies.refresh_metadata, Some(RetryPolicy::default()));
+    }
+
+    #[test]
+    fn test_retry() {
+        let timer = ManualTimer::default();
+        let runtime = Runtime::new(timer.clone(), timer.clone());
+        let policies = RetryPolicies {
+            retriable: Some(RetryPolicy {
+                max_retries: 2,
+                jitter: false,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let attempts = Rc::new(Cell::new(0));
+
+        // retry the transient errors until the retries exhausted
+        let res = retry(runtime.clone(), &policies, reload_nothing, {
+            let attempts = attempts.clone();
+
+            move || {
+                attempts.set(attempts.get() + 1);
+
+                future::err::<(), _>(ErrorKind::KafkaError(KafkaCode::RequestTimedOut).into())
+            }
+        }).wait();
+
+        assert!(res.is_err());
+        assert_eq!(attempts.get(), 3);
+        assert_eq!(timer.slept.borrow().len(), 2);
+
+        // never retry the fatal errors
+        attempts.set(0);
+
+        let res = retry(runtime.clone(), &policies, reload_nothing, {
+            let attempts = attempts.clone();
+
+            move || {
+                attempts.set(attempts.get() + 1);
+
+                future::err::<(), _>(ErrorKind::KafkaError(KafkaCode::MessageSizeTooLarge).into())
+            }
+        }).wait();
+
+        assert!(res.is_err());
+        assert_eq!(attempts.get(), 1);
+    }
+
+    #[test]
+    fn test_retry_stale_metadata() {
+        let timer = ManualTimer::default();
+        let runtime = Runtime::new(timer.clone(), timer.clone());
+        let policies = RetryPolicies {
+            refresh_metadata: Some(RetryPolicy {
+                max_retries: 3,
+                jitter: false,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let attempts = Rc::new(Cell::new(0));
+        let reloads = Rc::new(Cell::new(0));
+
+        // reload the metadata once before each retry, and never retry the attempt twice
+        let res = retry(
+            runtime.clone(),
+            &policies,
+            {
+                let reloads = reloads.clone();
+
+                move || {
+                    reloads.set(reloads.get() + 1);
+
+                    future::ok(()).static_boxed()
+                }
+            },
+            {
+                let attempts = attempts.clone();
+
+                move || {
+                    attempts.set(attempts.get() + 1);
+
+                    future::err::<(), _>(ErrorKind::KafkaError(KafkaCode::NotLeaderForPartition).into())
+                }
+            },
+        ).wait();
+
+        assert!(res.is_err());
+        assert_eq!(attempts.get(), 4);
+        assert_eq!(reloads.get(), 3);
+        assert_eq!(timer.slept.borrow().len(), 3);
+
+        // the transient errors are retried without reloading the metadata
+        attempts.set(0);
+        reloads.set(0);
+
+        let res = retry(
+            runtime.clone(),
+            &RetryPolicies {
+                retriable: policies.refresh_metadata.clone(),
+                ..Default::default()
+            },
+            {
+                let reloads = reloads.clone();
+
+                move || {
+                    reloads.set(reloads.get() + 1);
+
+                    future::ok(()).static_boxed()
+                }
+            },
+            {
+                let attempts = attempts.clone();
+
+                move || {
+                    attempts.set(attempts.get() + 1);
+
+                    future::err::<(), _>(ErrorKind::KafkaError(KafkaCode::RequestTimedOut).into())
+                }
+            },
+        ).wait();
+
+        assert!(res.is_err());
+        assert_eq!(attempts.get(), 4);
+        assert_eq!(reloads.get(), 0);
+    }
+}
@@ -0,0 +1,274 @@
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use futures::{future, Async, Future, Poll, Stream};
+use tokio_core::reactor::{Handle, Timeout};
+
+use client::{StaticBoxFuture, ToStaticBoxFuture};
+use errors::{Error, ErrorKind};
+use protocol::ToMilliseconds;
+
+/// The future spawned to run in the background.
+pub type Spawned = Box<Future<Item = (), Error = ()>>;
+
+/// The future completes after a while.
+pub type Sleep = StaticBoxFuture;
+
+/// An executor runs the background futures of the client.
+pub trait Executor {
+    /// Spawn a future to run in the background.
+    fn spawn(&self, future: Spawned);
+}
+
+/// A timer creates the futures completing after a while.
+pub trait Timer {
+    /// Create a future which completes after the duration.
+    fn sleep(&self, duration: Duration) -> Sleep;
+
+    /// The current time of the timer, which the deadlines and the linger of the client are measured with.
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+impl Executor for Handle {
+    fn spawn(&self, future: Spawned) {
+        Handle::spawn(self, future)
+    }
+}
+
+impl Timer for Handle {
+    fn sleep(&self, duration: Duration) -> Sleep {
+        match Timeout::new(duration, self) {
+            Ok(timeout) => timeout.from_err().static_boxed(),
+            Err(err) => future::err(err.into()).static_boxed(),
+        }
+    }
+}
+
+/// The runtime of client, which spawns the background futures and drives the timeouts.
+///
+/// The `Runtime` is created from the `Handle` of reactor by default,
+/// it could be replaced to run the client on another reactor, or drive it manually in the tests.
+#[derive(Clone)]
+pub struct Runtime {
+    executor: Rc<Executor>,
+    timer: Rc<Timer>,
+}
+
+impl Runtime {
+    /// Construct a `Runtime` with the executor and timer
+    pub fn new<E, T>(executor: E, timer: T) -> Self
+    where
+        E: 'static + Executor,
+        T: 'static + Timer,
+    {
+        Runtime {
+            executor: Rc::new(executor),
+            timer: Rc::new(timer),
+        }
+    }
+
+    /// Spawn a future to run in the background.
+    pub fn spawn<F>(&self, future: F)
+    where
+        F: 'static + Future<Item = (), Error = ()>,
+    {
+        self.executor.spawn(Box::new(future))
+    }
+
+    /// Create a future which completes after the duration.
+    pub fn sleep(&self, duration: Duration) -> Sleep {
+        self.timer.sleep(duration)
+    }
+
+    /// The current time of the timer.
+    pub fn now(&self) -> Instant {
+        self.timer.now()
+    }
+
+    /// Wait for the future, or fail with `TimeoutError` once the duration elapsed.
+    pub fn timeout<F>(&self, future: F, duration: Duration) -> WithTimeout<F>
+    where
+        F: Future,
+        F::Error: From<Error>,
+    {
+        WithTimeout {
+            future,
+            sleep: self.sleep(duration),
+            duration,
+            elapsed: false,
+        }
+    }
+
+    /// Create a stream which yields after the delay, and then every period.
+    pub fn interval(&self, delay: Duration, period: Duration) -> Interval {
+        Interval {
+            runtime: self.clone(),
+            sleep: self.sleep(delay),
+            period,
+        }
+    }
+}
+
+impl From<Handle> for Runtime {
+    fn from(handle: Handle) -> Self {
+        Runtime::new(handle.clone(), handle)
+    }
+}
+
+/// The future waits for another future with a timeout.
+pub struct WithTimeout<F> {
+    future: F,
+    sleep: Sleep,
+    duration: Duration,
+    elapsed: bool,
+}
+
+impl<F> WithTimeout<F> {
+    /// Whether the future has been failed because the duration elapsed.
+    pub fn is_elapsed(&self) -> bool {
+        self.elapsed
+    }
+}
+
+impl<F> Future for WithTimeout<F>
+where
+    F: Future,
+    F::Error: From<Error>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        if let Async::Ready(item) = self.future.poll()? {
+            return Ok(Async::Ready(item));
+        }
+
+        match self.sleep.poll() {
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Ok(Async::Ready(())) => {
+                self.elapsed = true;
+
+                let reason = format!("timed out after {} ms", self.duration.as_millis());
+
+                Err(Error::from(ErrorKind::TimeoutError(reason)).into())
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+/// The stream yields after a delay, and then every period.
+pub struct Interval {
+    runtime: Runtime,
+    sleep: Sleep,
+    period: Duration,
+}
+
+impl Stream for Interval {
+    type Item = ();
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        try_ready!(self.sleep.poll());
+
+        self.sleep = self.runtime.sleep(self.period);
+
+        Ok(Async::Ready(Some(())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct ManualRuntime {
+        spawned: Rc<RefCell<Vec<Spawned>>>,
+        slept: Rc<RefCell<Vec<Duration>>>,
+    }
+
+    impl Executor for ManualRuntime {
+        fn spawn(&self, future: Spawned) {
+            self.spawned.borrow_mut().push(future)
+        }
+    }
+
+    impl Timer for ManualRuntime {
+        fn sleep(&self, duration: Duration) -> Sleep {
+            self.slept.borrow_mut().push(duration);
+
+            future::ok(()).static_boxed()
+        }
+    }
+
+    #[test]
+    fn test_manual_runtime() {
+        let manual = ManualRuntime::default();
+        let runtime = Runtime::new(manual.clone(), manual.clone());
+        let fired = Rc::new(Cell::new(false));
+
+        runtime.spawn({
+            let fired = fired.clone();
+
+            runtime
+                .sleep(Duration::from_millis(100))
+                .map(move |_| fired.set(true))
+                .map_err(|_| ())
+        });
+
+        assert!(!fired.get());
+        assert_eq!(manual.spawned.borrow().len(), 1);
+        assert_eq!(*manual.slept.borrow(), vec![Duration::from_millis(100)]);
+
+        for future in manual.spawned.borrow_mut().drain(..) {
+            future.wait().unwrap();
+        }
+
+        assert!(fired.get());
+    }
+
+    #[test]
+    fn test_timeout() {
+        let manual = ManualRuntime::default();
+        let runtime = Runtime::new(manual.clone(), manual.clone());
+
+        assert_eq!(runtime.timeout(future::ok::<_, Error>(123), Duration::from_secs(1)).wait().unwrap(), 123);
+
+        let mut timeout = runtime.timeout(future::empty::<(), Error>(), Duration::from_secs(1));
+
+        match timeout.poll() {
+            Err(Error(ErrorKind::TimeoutError(_), _)) => {}
+            res => panic!("unexpected result: {:?}", res),
+        }
+
+        assert!(timeout.is_elapsed());
+    }
+
+    #[test]
+    fn test_interval() {
+        let manual = ManualRuntime::default();
+        let runtime = Runtime::new(manual.clone(), manual.clone());
+
+        let ticks = runtime
+            .interval(Duration::from_millis(0), Duration::from_millis(100))
+            .take(3)
+            .collect()
+            .wait()
+            .unwrap();
+
+        assert_eq!(ticks.len(), 3);
+        assert_eq!(
+            *manual.slept.borrow(),
+            vec![
+                Duration::from_millis(0),
+                Duration::from_millis(100),
+                Duration::from_millis(100),
+                Duration::from_millis(100),
+            ]
+        );
+    }
+}
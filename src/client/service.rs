@@ -1,13 +1,14 @@
 use std::cell::RefCell;
+use std::collections::HashMap;
 use std::fmt::Debug;
 use std::io;
 use std::net::SocketAddr;
 use std::rc::Rc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use bytes::BytesMut;
 
-use futures::future::Future;
+use futures::future::{self, Either, Future, Loop};
 use futures::unsync::oneshot;
 use futures::{Async, Poll, Stream};
 use tokio_core::reactor::Handle;
@@ -19,13 +20,18 @@ use tokio_proto::util::client_proxy::ClientProxy;
 use tokio_service::Service;
 use ns_router::{AutoName, Router};
 
-use client::{Metrics, StaticBoxFuture, ToStaticBoxFuture};
+use client::{AbortSignal, Abortable, ClientListeners, Metrics, RequestQueues, Runtime, StaticBoxFuture,
+             ToStaticBoxFuture};
 use errors::Error;
-use network::{ConnectionId, KafkaCodec, KafkaConnection, KafkaConnector, KafkaRequest, KafkaResponse, Pool, Pooled};
+use network::{Clock, ConnectionId, KafkaCodec, KafkaConnection, KafkaConnector, KafkaRequest, KafkaResponse, KeepAlive,
+              Pool, Pooled, SaslAuthenticator, SaslChannel, SaslSession, SendRequest, SharedTransport, TlsSettings};
+use protocol::{ApiKeys, CrcPolicy};
 
 #[derive(Debug, Default)]
 struct State {
     connection_id: ConnectionId,
+    /// The brokers which throttled the previous requests, and the time until which the requests should be delayed.
+    throttled: HashMap<SocketAddr, Instant>,
 }
 
 impl State {
@@ -33,13 +39,46 @@ impl State {
         self.connection_id = self.connection_id.wrapping_add(1);
         self.connection_id - 1
     }
+
+    /// Delay the following requests to the broker, which has throttled the response for violating the quota.
+    pub fn throttle(&mut self, addr: SocketAddr, throttle_time: Duration, now: Instant) {
+        let until = self.throttled.entry(addr).or_insert(now);
+
+        if *until < now + throttle_time {
+            *until = now + throttle_time;
+        }
+    }
+
+    /// The remaining time to wait before sending the request to the broker.
+    pub fn throttle_delay(&mut self, addr: &SocketAddr, now: Instant) -> Option<Duration> {
+        let delay = self.throttled
+            .get(addr)
+            .and_then(|until| if *until > now { Some(*until - now) } else { None });
+
+        if delay.is_none() {
+            self.throttled.remove(addr);
+        }
+
+        delay
+    }
 }
 
+/// The hostnames of the resolved broker addresses, used to verify the broker certificates.
+pub type Hostnames = Rc<RefCell<HashMap<SocketAddr, String>>>;
+
 pub struct KafkaService<'a> {
     handle: Handle,
     pool: Pool<SocketAddr, TokioClient<'a>>,
     connector: KafkaConnector,
+    authenticator: Option<SaslAuthenticator>,
+    tls: Option<TlsSettings>,
+    hostnames: Hostnames,
     metrics: Option<Rc<Metrics>>,
+    listeners: ClientListeners,
+    max_in_flight_requests_per_connection: usize,
+    check_crcs: CrcPolicy,
+    runtime: Runtime,
+    queues: RequestQueues,
     state: Rc<RefCell<State>>,
 }
 
@@ -48,53 +87,179 @@ impl<'a> KafkaService<'a> {
         handle: Handle,
         router: Rc<Router>,
         max_connection_idle: Duration,
+        reconnect_backoff: Duration,
+        reconnect_backoff_max: Duration,
+        authenticator: Option<SaslAuthenticator>,
+        tls: Option<TlsSettings>,
+        hostnames: Hostnames,
         metrics: Option<Rc<Metrics>>,
+        listeners: ClientListeners,
+        max_in_flight_requests_per_connection: usize,
+        check_crcs: CrcPolicy,
+        runtime: Runtime,
+        transport: SharedTransport,
+        queues: RequestQueues,
     ) -> Self {
         KafkaService {
             handle: handle.clone(),
-            pool: Pool::new(max_connection_idle),
-            connector: KafkaConnector::new(handle, router),
+            pool: Pool::new(max_connection_idle, reconnect_backoff, reconnect_backoff_max).with_clock({
+                let runtime = runtime.clone();
+
+                Clock::new(move || runtime.now())
+            }),
+            connector: KafkaConnector::new(handle, router, transport),
+            authenticator,
+            tls,
+            hostnames,
             metrics,
+            listeners,
+            max_in_flight_requests_per_connection,
+            check_crcs,
+            runtime,
+            queues,
             state: Rc::new(RefCell::new(State::default())),
         }
     }
 }
 
-impl<'a> Service for KafkaService<'a>
+impl<'a> KafkaService<'a>
 where
     Self: 'static,
 {
-    type Request = (SocketAddr, KafkaRequest<'a>);
-    type Response = KafkaResponse;
-    type Error = Error;
-    type Future = FutureResponse;
+    /// Establish a connection to the broker in advance, and return it to the pool.
+    pub fn warm_up(&self, addr: SocketAddr) -> StaticBoxFuture {
+        self.connection(addr)
+            .map(move |mut client| {
+                debug!("warmed up connection to {}", addr);
 
-    fn call(&self, req: Self::Request) -> Self::Future {
-        let (addr, request) = req;
+                client.idle()
+            })
+            .from_err()
+            .static_boxed()
+    }
 
-        self.metrics
-            .as_ref()
-            .map(|metrics| metrics.send_request(&addr, &request));
+    /// Close the idle connections in the pool, returns the number of the closed connections.
+    pub fn close_idle_connections(&self) -> usize {
+        let closed = self.pool.clear();
+
+        self.disconnected(&closed);
+
+        closed.len()
+    }
+
+    fn disconnected(&self, addrs: &[SocketAddr]) {
+        for addr in addrs {
+            self.listeners.notify(|listener| listener.on_disconnect(addr));
+        }
+    }
+
+    /// Checkout an idle connection from the pool, or establish a new connection to the broker.
+    ///
+    /// The connections idle for longer than `connection.max.idle.ms` are closed before checking out,
+    /// and the broker which failed to connect will not be reconnected until the backoff elapsed.
+    fn connection(&self, addr: SocketAddr) -> Connection<'a> {
+        let expired = self.pool.reap();
+
+        self.disconnected(&expired);
 
         let checkout = self.pool.checkout(addr);
         let connect = {
             let handle = self.handle.clone();
+            let metrics = self.metrics.clone();
+            let listeners = self.listeners.clone();
+            let backoff_pool = self.pool.clone();
             let connection_id = self.state.borrow_mut().next_connection_id();
             let pool = self.pool.clone();
-
-            self.connector.tcp(AutoName::SocketAddr(addr)).map(move |io| {
-                let (tx, rx) = oneshot::channel();
-                let client = RemoteClient {
-                    connection_id,
-                    client_rx: RefCell::new(Some(rx)),
-                }.bind_client(&handle, io);
-                let pooled = pool.pooled(addr, client);
-                drop(tx.send(pooled.clone()));
-                pooled
-            })
+            let authenticator = self.authenticator.clone();
+            let reauthenticator = self.authenticator.clone();
+            let runtime = self.runtime.clone();
+            let max_in_flight = self.max_in_flight_requests_per_connection;
+            let check_crcs = self.check_crcs;
+
+            let connect = match self.tls {
+                Some(ref tls) if tls.hostname_verification => {
+                    let domain = self.hostnames
+                        .borrow()
+                        .get(&addr)
+                        .cloned()
+                        .unwrap_or_else(|| addr.ip().to_string());
+
+                    self.connector
+                        .tls(AutoName::SocketAddr(addr), tls.connector.clone(), domain)
+                }
+                Some(ref tls) => self.connector
+                    .tls_without_hostname_verification(AutoName::SocketAddr(addr), tls.connector.clone()),
+                None => self.connector.plain(AutoName::SocketAddr(addr)),
+            };
+
+            let backoff = match self.pool.reconnect_delay(&addr) {
+                Some(delay) => {
+                    debug!("reconnect to {} after {:?}", addr, delay);
+
+                    Either::A(
+                        self.runtime
+                            .sleep(delay)
+                            .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string())),
+                    )
+                }
+                None => Either::B(future::ok(())),
+            };
+
+            backoff
+                .and_then(move |_| connect)
+                .and_then(move |io| match authenticator {
+                    Some(ref authenticator) => Either::A(authenticator.authenticate(io)),
+                    None => Either::B(future::ok((io, SaslSession::default()))),
+                })
+                .then(move |res| {
+                    if let Some(ref metrics) = metrics {
+                        metrics.connected(&addr, res.is_ok());
+                    }
+
+                    match res {
+                        Ok(_) => listeners.notify(|listener| listener.on_connect(&addr)),
+                        Err(ref err) => listeners.notify(|listener| listener.on_connect_failed(&addr, err)),
+                    }
+
+                    match res {
+                        Ok(_) => backoff_pool.connected(&addr),
+                        Err(ref err) => {
+                            let backoff = backoff_pool.connect_failed(addr);
+
+                            warn!("fail to connect {}, {}, reconnect after {:?}", addr, err, backoff);
+                        }
+                    }
+
+                    res
+                })
+                .map(move |(io, session)| {
+                    let (tx, rx) = oneshot::channel();
+                    let client = RemoteClient {
+                        connection_id,
+                        max_in_flight,
+                        check_crcs,
+                        client_rx: RefCell::new(Some(rx)),
+                    }.bind_client(&handle, io);
+                    let pooled = pool.pooled(addr, client);
+                    drop(tx.send(pooled.clone()));
+
+                    if let Some(authenticator) = reauthenticator {
+                        if session.reauthenticate_after().is_some() {
+                            runtime.spawn(keep_authenticated(
+                                connection_id,
+                                runtime.clone(),
+                                authenticator,
+                                pooled.clone(),
+                                session,
+                            ));
+                        }
+                    }
+
+                    pooled
+                })
         };
 
-        let race = checkout
+        checkout
             .select(connect)
             .map(|(client, _work)| client)
             .map_err(|(err, _work)| {
@@ -105,30 +270,106 @@ where
                 // that if the connector is failing, it may be that we
                 // never had a pooled stream at all
                 err
-            });
+            })
+            .static_boxed()
+    }
+}
 
-        let metrics = self.metrics.clone();
+impl<'a> Service for KafkaService<'a>
+where
+    Self: 'static,
+{
+    type Request = (SocketAddr, KafkaRequest<'a>);
+    type Response = KafkaResponse;
+    type Error = Error;
+    type Future = FutureResponse;
 
-        race.and_then(move |client| client.call(Message::WithoutBody(request)))
-            .map(|msg| {
-                debug!("received message: {:?}", msg);
+    fn call(&self, req: Self::Request) -> Self::Future {
+        self.call_abortable(req, AbortSignal::default())
+    }
+}
 
-                match msg {
-                    Message::WithoutBody(res) | Message::WithBody(res, _) => res,
-                }
-            })
-            .map(move |response| {
-                metrics.map(|metrics| metrics.received_response(&addr, &response));
+impl<'a> Abortable for KafkaService<'a>
+where
+    Self: 'static,
+{
+    fn call_abortable(&self, req: Self::Request, abort: AbortSignal) -> Self::Future {
+        let (addr, request) = req;
 
-                response
+        self.metrics
+            .as_ref()
+            .map(|metrics| metrics.send_request(&addr, &request));
+
+        let connection = self.connection(addr);
+        let connection = match self.state.borrow_mut().throttle_delay(&addr, self.runtime.now()) {
+            Some(delay) => {
+                debug!("broker {} is throttled, delay the request for {:?}", addr, delay);
+
+                Either::A(
+                    self.runtime
+                        .sleep(delay)
+                        .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))
+                        .and_then(move |_| connection),
+                )
+            }
+            None => Either::B(connection),
+        };
+
+        let metrics = self.metrics.clone();
+        let listeners = self.listeners.clone();
+        let state = self.state.clone();
+        let runtime = self.runtime.clone();
+        let api_key = ApiKeys::from(request.header().api_key);
+        let sent = Instant::now();
+
+        self.queues
+            .acquire(addr)
+            .and_then(move |slot| {
+                connection
+                    .and_then(move |client| PendingResponse {
+                        response: client.call(Message::WithoutBody(request)),
+                        client: Some(client),
+                        abort,
+                    })
+                    .map(|msg| {
+                        debug!("received message: {:?}", msg);
+
+                        match msg {
+                            Message::WithoutBody(res) | Message::WithBody(res, _) => res,
+                        }
+                    })
+                    .then(move |res| {
+                        drop(slot);
+
+                        if let Some(ref metrics) = metrics {
+                            match res {
+                                Ok(ref response) => metrics.received_response(&addr, response, sent.elapsed()),
+                                Err(_) => metrics.request_failed(&addr, api_key),
+                            }
+                        }
+
+                        if let Some(throttle_time) = res.as_ref().ok().and_then(KafkaResponse::throttle_time) {
+                            state.borrow_mut().throttle(addr, throttle_time, runtime.now());
+
+                            if let Some(ref metrics) = metrics {
+                                metrics.throttled(&addr, api_key, throttle_time);
+                            }
+
+                            listeners.notify(|listener| listener.on_throttle(&addr, api_key, throttle_time));
+                        }
+
+                        res
+                    })
+                    .from_err()
             })
-            .from_err()
             .static_boxed()
     }
 }
 
 pub type FutureResponse = StaticBoxFuture<KafkaResponse>;
 
+type Connection<'a> = StaticBoxFuture<PooledClient<'a>, io::Error>;
+
 type TokioBody = Body<BytesMut, io::Error>;
 
 pub struct KafkaBody(TokioBody);
@@ -146,8 +387,132 @@ type TokioClient<'a> = ClientProxy<Message<KafkaRequest<'a>, KafkaBody>, Message
 
 type PooledClient<'a> = Pooled<SocketAddr, TokioClient<'a>>;
 
+/// The pending response of a request, which aborts its connection when dropped after the request timed out.
+///
+/// The response of a timed out request may never come, the connection is aborted to fail its other in-flight requests
+/// instead of keeping them waiting for the lost response. The request dropped by its caller for other reasons
+/// leaves the connection usable, the late response will be discarded.
+struct PendingResponse<F, T>
+where
+    T: Clone + Debug,
+{
+    response: F,
+    client: Option<Pooled<SocketAddr, T>>,
+    abort: AbortSignal,
+}
+
+impl<F, T> Future for PendingResponse<F, T>
+where
+    F: Future,
+    T: Clone + Debug,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let res = self.response.poll();
+
+        match res {
+            Ok(Async::NotReady) => {}
+            _ => self.client = None,
+        }
+
+        res
+    }
+}
+
+impl<F, T> Drop for PendingResponse<F, T>
+where
+    T: Clone + Debug,
+{
+    fn drop(&mut self) {
+        if let Some(client) = self.client.take() {
+            if self.abort.is_aborted() {
+                debug!("request timed out before received response, abort the connection, {:?}", client);
+
+                client.abort();
+            }
+        }
+    }
+}
+
+/// Re-authenticate the connection before the SASL session expires,
+/// until the connection has been closed or expired in the pool.
+fn keep_authenticated<'a>(
+    connection_id: ConnectionId,
+    runtime: Runtime,
+    authenticator: SaslAuthenticator,
+    client: PooledClient<'a>,
+    session: SaslSession,
+) -> Box<Future<Item = (), Error = ()> + 'a>
+where
+    PooledChannel<'a>: SaslChannel,
+{
+    Box::new(
+        future::loop_fn((client, session), move |(mut client, session)| {
+            let delay = match session.reauthenticate_after() {
+                Some(delay) => delay,
+                None => return Either::A(future::ok(Loop::Break(()))),
+            };
+
+            trace!("connection #{} will be re-authenticated after {:?}", connection_id, delay);
+
+            let authenticator = authenticator.clone();
+
+            Either::B(runtime.sleep(delay).and_then(move |_| {
+                if client.is_expired() {
+                    trace!("connection #{} expired, stop re-authenticating", connection_id);
+
+                    return Either::A(future::ok(Loop::Break(())));
+                }
+
+                client.authenticating();
+
+                Either::B(
+                    authenticator
+                        .reauthenticate(PooledChannel(client.clone()), &session)
+                        .then(move |res| match res {
+                            Ok((_, session)) => {
+                                debug!("connection #{} re-authenticated, {:?}", connection_id, session);
+
+                                client.authenticated();
+
+                                Ok(Loop::Continue((client, session)))
+                            }
+                            Err(err) => {
+                                warn!("fail to re-authenticate connection #{}, {}", connection_id, err);
+
+                                client.close();
+
+                                Ok(Loop::Break(()))
+                            }
+                        }),
+                )
+            }))
+        }).map_err(move |err: Error| warn!("fail to re-authenticate connection #{}, {}", connection_id, err)),
+    )
+}
+
+/// Send the SASL requests on a pooled connection which has been authenticated.
+struct PooledChannel<'a>(PooledClient<'a>);
+
+impl<'a> SaslChannel for PooledChannel<'a>
+where
+    Self: 'static,
+{
+    fn send_request(self, request: KafkaRequest<'static>) -> SendRequest<Self> {
+        let response = self.0.call(Message::WithoutBody(request));
+
+        Box::new(response.map(move |msg| match msg {
+            Message::WithoutBody(res) | Message::WithBody(res, _) => (self, res),
+        }))
+    }
+}
+
 struct RemoteClient<'a> {
     connection_id: u32,
+    max_in_flight: usize,
+    check_crcs: CrcPolicy,
     client_rx: RefCell<Option<oneshot::Receiver<PooledClient<'a>>>>,
 }
 
@@ -169,6 +534,8 @@ where
 
         BindingClient {
             connection_id: self.connection_id,
+            max_in_flight: self.max_in_flight,
+            check_crcs: self.check_crcs,
             rx: self.client_rx.borrow_mut().take().expect("client_rx was lost"),
             io: Some(io),
         }
@@ -177,6 +544,8 @@ where
 
 struct BindingClient<'a, T> {
     connection_id: u32,
+    max_in_flight: usize,
+    check_crcs: CrcPolicy,
     rx: oneshot::Receiver<PooledClient<'a>>,
     io: Option<T>,
 }
@@ -196,8 +565,9 @@ where
                 Ok(Async::Ready(KafkaConnection::new(
                     self.connection_id,
                     self.io.take().expect("binding client io lost"),
-                    KafkaCodec::new(),
+                    KafkaCodec::with_check_crcs(self.check_crcs),
                     client,
+                    self.max_in_flight,
                 )))
             }
             Ok(Async::NotReady) => Ok(Async::NotReady),
@@ -205,3 +575,62 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_throttle() {
+        let mut state = State::default();
+        let addr = "127.0.0.1:9092".parse().unwrap();
+        let now = Instant::now();
+
+        assert_eq!(state.throttle_delay(&addr, now), None);
+
+        state.throttle(addr, Duration::from_millis(500), now);
+
+        assert_eq!(state.throttle_delay(&addr, now), Some(Duration::from_millis(500)));
+
+        // a shorter throttle time never shortens the pending delay
+        state.throttle(addr, Duration::from_millis(100), now);
+
+        assert_eq!(
+            state.throttle_delay(&addr, now + Duration::from_millis(200)),
+            Some(Duration::from_millis(300))
+        );
+        assert_eq!(state.throttle_delay(&addr, now + Duration::from_secs(1)), None);
+        assert!(state.throttled.is_empty());
+    }
+
+    #[test]
+    fn test_pending_response() {
+        let pool: Pool<SocketAddr, ()> = Pool::new(
+            Duration::from_secs(5),
+            Duration::from_secs(10),
+            Duration::from_secs(60),
+        );
+        let client = pool.pooled("127.0.0.1:9092".parse().unwrap(), ());
+        let abort = AbortSignal::default();
+
+        // the request dropped by its caller leaves the connection usable
+        drop(PendingResponse {
+            response: future::empty::<(), ()>(),
+            client: Some(client.clone()),
+            abort: abort.clone(),
+        });
+
+        assert!(!client.is_expired());
+
+        // the timed out request aborts its connection
+        abort.abort();
+
+        drop(PendingResponse {
+            response: future::empty::<(), ()>(),
+            client: Some(client.clone()),
+            abort,
+        });
+
+        assert!(client.is_expired());
+    }
+}
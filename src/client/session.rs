@@ -0,0 +1,230 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use protocol::{ErrorCode, FetchPartition, FetchSessionEpoch, FetchSessionId, FetchTopic, ForgottenTopic, KafkaCode,
+               Offset, PartitionId, INITIAL_SESSION_EPOCH, INVALID_SESSION_ID};
+
+/// The fetch request built within the fetch session of a broker.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FetchSessionRequest<'a> {
+    /// The fetch session id, or `INVALID_SESSION_ID` to create a new session.
+    pub session_id: FetchSessionId,
+    /// The fetch session epoch, or `INITIAL_SESSION_EPOCH` to create a new session.
+    pub session_epoch: FetchSessionEpoch,
+    /// The partitions to be added to the session, or whose fetch position was changed.
+    pub topics: Vec<FetchTopic<'a>>,
+    /// The partitions to be removed from the session.
+    pub forgotten_topics: Vec<ForgottenTopic<'a>>,
+}
+
+#[derive(Clone, Debug, Default)]
+struct FetchSession {
+    session_id: FetchSessionId,
+    next_epoch: FetchSessionEpoch,
+    partitions: HashMap<(String, PartitionId), (Offset, i32)>,
+}
+
+/// Maintain the incremental fetch sessions (KIP-227) of the brokers.
+///
+/// The first fetch request to a broker carries all the partitions and creates a session,
+/// the following requests only carry the partitions whose fetch position was changed,
+/// and the broker only returns the partitions which have new records or errors.
+#[derive(Clone, Debug, Default)]
+pub struct FetchSessions {
+    sessions: Rc<RefCell<HashMap<(String, u16), FetchSession>>>,
+}
+
+impl FetchSessions {
+    /// Build the fetch request of the broker within its session.
+    pub fn build<'a>(&self, host: &str, port: u16, topics: Vec<FetchTopic<'a>>) -> FetchSessionRequest<'a> {
+        let mut sessions = self.sessions.borrow_mut();
+        let session = sessions
+            .entry((host.to_owned(), port))
+            .or_insert_with(FetchSession::default);
+
+        let partitions = topics
+            .iter()
+            .flat_map(|topic| {
+                topic.partitions.iter().map(move |partition| {
+                    (
+                        (topic.topic_name.to_string(), partition.partition_id),
+                        (partition.fetch_offset, partition.max_bytes),
+                    )
+                })
+            })
+            .collect::<HashMap<_, _>>();
+
+        if session.session_id == INVALID_SESSION_ID {
+            session.next_epoch = INITIAL_SESSION_EPOCH;
+            session.partitions = partitions;
+
+            return FetchSessionRequest {
+                session_id: INVALID_SESSION_ID,
+                session_epoch: INITIAL_SESSION_EPOCH,
+                topics,
+                forgotten_topics: vec![],
+            };
+        }
+
+        let session_epoch = session.next_epoch;
+
+        // the epoch wraps to 1 since 0 is used to create a new session
+        session.next_epoch = if session_epoch == i32::MAX { 1 } else { session_epoch + 1 };
+
+        let changed = topics
+            .into_iter()
+            .map(|topic| {
+                let partitions = topic
+                    .partitions
+                    .into_iter()
+                    .filter(|partition| {
+                        session
+                            .partitions
+                            .get(&(topic.topic_name.to_string(), partition.partition_id))
+                            .map_or(true, |&(fetch_offset, max_bytes)| {
+                                fetch_offset != partition.fetch_offset || max_bytes != partition.max_bytes
+                            })
+                    })
+                    .collect::<Vec<FetchPartition>>();
+
+                FetchTopic {
+                    topic_name: topic.topic_name,
+                    partitions,
+                }
+            })
+            .filter(|topic| !topic.partitions.is_empty())
+            .collect();
+
+        let mut forgotten = HashMap::new();
+
+        for &(ref topic_name, partition_id) in session.partitions.keys() {
+            if !partitions.contains_key(&(topic_name.clone(), partition_id)) {
+                forgotten
+                    .entry(topic_name.clone())
+                    .or_insert_with(HashSet::new)
+                    .insert(partition_id);
+            }
+        }
+
+        session.partitions = partitions;
+
+        FetchSessionRequest {
+            session_id: session.session_id,
+            session_epoch,
+            topics: changed,
+            forgotten_topics: forgotten
+                .into_iter()
+                .map(|(topic_name, partitions)| ForgottenTopic {
+                    topic_name: Cow::Owned(topic_name),
+                    partitions: partitions.into_iter().collect(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Update the session of broker with the fetch response.
+    pub fn update(&self, host: &str, port: u16, error_code: ErrorCode, session_id: FetchSessionId) {
+        let mut sessions = self.sessions.borrow_mut();
+        let key = (host.to_owned(), port);
+
+        if error_code != KafkaCode::None as ErrorCode {
+            debug!(
+                "fetch session of {}:{} was closed, {:?}",
+                host,
+                port,
+                KafkaCode::from(error_code)
+            );
+
+            sessions.remove(&key);
+        } else if let Some(session) = sessions.get_mut(&key) {
+            if session.session_id == INVALID_SESSION_ID && session_id != INVALID_SESSION_ID {
+                trace!("fetch session {} of {}:{} was created", session_id, host, port);
+
+                session.session_id = session_id;
+                session.next_epoch = INITIAL_SESSION_EPOCH + 1;
+            } else if session.session_id != session_id {
+                sessions.remove(&key);
+            }
+        }
+    }
+
+    /// Reset the session of broker, the next fetch request will create a new session.
+    pub fn reset(&self, host: &str, port: u16) {
+        self.sessions.borrow_mut().remove(&(host.to_owned(), port));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fetch_topic(topic_name: &'static str, partitions: &[(PartitionId, Offset)]) -> FetchTopic<'static> {
+        FetchTopic {
+            topic_name: topic_name.into(),
+            partitions: partitions
+                .iter()
+                .map(|&(partition_id, fetch_offset)| FetchPartition {
+                    partition_id,
+                    current_leader_epoch: -1,
+                    fetch_offset,
+                    log_start_offset: -1,
+                    max_bytes: 1024,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_fetch_sessions() {
+        let sessions = FetchSessions::default();
+
+        let req = sessions.build("localhost", 9092, vec![fetch_topic("topic", &[(0, 10), (1, 20)])]);
+
+        assert_eq!(req.session_id, INVALID_SESSION_ID);
+        assert_eq!(req.session_epoch, INITIAL_SESSION_EPOCH);
+        assert_eq!(req.topics, vec![fetch_topic("topic", &[(0, 10), (1, 20)])]);
+
+        sessions.update("localhost", 9092, KafkaCode::None as ErrorCode, 123);
+
+        // only the changed partitions are sent
+        let req = sessions.build("localhost", 9092, vec![fetch_topic("topic", &[(0, 15), (1, 20)])]);
+
+        assert_eq!(req.session_id, 123);
+        assert_eq!(req.session_epoch, 1);
+        assert_eq!(req.topics, vec![fetch_topic("topic", &[(0, 15)])]);
+        assert!(req.forgotten_topics.is_empty());
+
+        sessions.update("localhost", 9092, KafkaCode::None as ErrorCode, 123);
+
+        // the removed partitions are forgotten
+        let req = sessions.build("localhost", 9092, vec![fetch_topic("topic", &[(0, 15)])]);
+
+        assert_eq!(req.session_epoch, 2);
+        assert!(req.topics.is_empty());
+        assert_eq!(
+            req.forgotten_topics,
+            vec![
+                ForgottenTopic {
+                    topic_name: "topic".into(),
+                    partitions: vec![1],
+                },
+            ]
+        );
+
+        // the other broker has its own session
+        let req = sessions.build("localhost", 9093, vec![fetch_topic("topic", &[(1, 20)])]);
+
+        assert_eq!(req.session_id, INVALID_SESSION_ID);
+
+        // the session is closed by the error
+        sessions.update("localhost", 9092, KafkaCode::InvalidFetchSessionEpoch as ErrorCode, 0);
+
+        let req = sessions.build("localhost", 9092, vec![fetch_topic("topic", &[(0, 15)])]);
+
+        assert_eq!(req.session_id, INVALID_SESSION_ID);
+        assert_eq!(req.session_epoch, INITIAL_SESSION_EPOCH);
+        assert_eq!(req.topics, vec![fetch_topic("topic", &[(0, 15)])]);
+    }
+}
@@ -0,0 +1,82 @@
+use std::fmt;
+use std::time::Duration;
+
+use protocol::ToMilliseconds;
+
+/// The report of a graceful shutdown, what was or wasn't drained before closing.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ShutdownReport {
+    /// The number of the pending records which have been sent.
+    pub records_flushed: usize,
+    /// The number of the pending records which failed to be sent or have been aborted.
+    pub records_failed: usize,
+    /// The number of the consumed offsets which have been committed.
+    pub offsets_committed: usize,
+    /// The number of the connections to the brokers which have been closed.
+    pub connections_closed: usize,
+    /// The amount of time spent to shutdown.
+    pub duration: Duration,
+}
+
+impl ShutdownReport {
+    /// Merge the counters of the other report, e.g. the report of a shutdown hook.
+    ///
+    /// The duration is not merged, it should be measured by the caller.
+    pub fn merge(&mut self, other: &ShutdownReport) {
+        self.records_flushed += other.records_flushed;
+        self.records_failed += other.records_failed;
+        self.offsets_committed += other.offsets_committed;
+        self.connections_closed += other.connections_closed;
+    }
+}
+
+impl fmt::Display for ShutdownReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "flushed {} records, failed {} records, committed {} offsets, closed {} connections in {} ms",
+            self.records_flushed,
+            self.records_failed,
+            self.offsets_committed,
+            self.connections_closed,
+            self.duration.as_millis()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge() {
+        let mut report = ShutdownReport {
+            records_flushed: 1,
+            duration: Duration::from_millis(100),
+            ..Default::default()
+        };
+
+        report.merge(&ShutdownReport {
+            records_flushed: 2,
+            records_failed: 3,
+            offsets_committed: 4,
+            connections_closed: 5,
+            duration: Duration::from_secs(1),
+        });
+
+        assert_eq!(
+            report,
+            ShutdownReport {
+                records_flushed: 3,
+                records_failed: 3,
+                offsets_committed: 4,
+                connections_closed: 5,
+                duration: Duration::from_millis(100),
+            }
+        );
+        assert_eq!(
+            report.to_string(),
+            "flushed 3 records, failed 3 records, committed 4 offsets, closed 5 connections in 100 ms"
+        );
+    }
+}
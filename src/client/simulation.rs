@@ -0,0 +1,687 @@
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::mem;
+use std::net::SocketAddr;
+use std::rc::Rc;
+use std::result;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use futures::executor::{self, Notify, NotifyHandle, Spawn};
+use futures::task::{self, Task};
+use futures::unsync::{mpsc, oneshot};
+use futures::{future, Async, Future, Poll, Stream};
+use tokio_core::reactor::{Core, Handle};
+use tokio_io::{AsyncRead, AsyncWrite};
+
+use client::{Executor, Runtime, Sleep, Spawned, Timer, ToStaticBoxFuture};
+use errors::{Error, ErrorKind, Result};
+use network::{Transport, TransportConnect, TransportStream};
+
+/// The first port of the simulated client connections.
+const CLIENT_PORTS: u16 = 49152;
+
+/// A deterministic simulation of the time and the network I/O of the clients.
+///
+/// The futures spawned on the `Runtime` only run when the test drives them,
+/// the `Runtime::sleep` timers only fire and the `Runtime::now` only moves when the simulated time is advanced,
+/// and the brokers are connected through the in-memory streams of the `SimulatedTransport` instead of the sockets,
+/// so the linger, retries, session timeouts and rebalances are reproducible without real sleeps.
+///
+/// The connections are driven by an event loop owned by the simulation, which is never blocked on.
+/// The brokers should be addressed by their IP addresses, since the host names are still resolved by DNS,
+/// and the jitter of the retry backoffs should be disabled to get the same timeline on every run.
+/// The `TokenBucketRateLimiter` still refills its buckets on the wall clock.
+///
+/// The simulation is shared between the clones.
+#[derive(Clone)]
+pub struct Simulation {
+    inner: Rc<RefCell<SimulationInner>>,
+    core: Rc<RefCell<Core>>,
+    handle: Handle,
+    activity: Activity,
+}
+
+struct SimulationInner {
+    start: Instant,
+    now: Duration,
+    next_timer_id: u64,
+    timers: BTreeMap<(Duration, u64), oneshot::Sender<()>>,
+    tasks: Vec<Spawn<Spawned>>,
+    listeners: HashMap<SocketAddr, mpsc::UnboundedSender<(SimulatedStream, SocketAddr)>>,
+    next_port: u16,
+}
+
+/// The counter of the I/O, the notifications and the spawned or completed tasks,
+/// which tells whether the simulation has stalled.
+#[derive(Clone, Default)]
+struct Activity(Arc<AtomicUsize>);
+
+impl Activity {
+    fn record(&self) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn get(&self) -> usize {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+impl Notify for Activity {
+    fn notify(&self, _id: usize) {
+        self.record()
+    }
+}
+
+impl Simulation {
+    /// Construct a `Simulation` starting from the zero time.
+    pub fn new() -> Result<Self> {
+        let core = Core::new()?;
+        let handle = core.handle();
+
+        Ok(Simulation {
+            inner: Rc::new(RefCell::new(SimulationInner {
+                start: Instant::now(),
+                now: Duration::default(),
+                next_timer_id: 0,
+                timers: BTreeMap::new(),
+                tasks: Vec::new(),
+                listeners: HashMap::new(),
+                next_port: CLIENT_PORTS,
+            })),
+            core: Rc::new(RefCell::new(core)),
+            handle,
+            activity: Activity::default(),
+        })
+    }
+
+    /// The `Handle` of the event loop which drives the simulated connections.
+    pub fn handle(&self) -> Handle {
+        self.handle.clone()
+    }
+
+    /// The `Runtime` driven by the simulation.
+    pub fn runtime(&self) -> Runtime {
+        Runtime::new(self.clone(), self.clone())
+    }
+
+    /// The `Transport` which connects to the listeners of the simulation.
+    pub fn transport(&self) -> SimulatedTransport {
+        SimulatedTransport(self.clone())
+    }
+
+    /// The elapsed simulated time.
+    pub fn now(&self) -> Duration {
+        self.inner.borrow().now
+    }
+
+    /// The number of the spawned futures which are not completed.
+    pub fn pending_tasks(&self) -> usize {
+        self.inner.borrow().tasks.len()
+    }
+
+    /// The number of the timers which are not fired.
+    pub fn pending_timers(&self) -> usize {
+        self.inner.borrow().timers.len()
+    }
+
+    /// Listen for the simulated connections on the address.
+    pub fn listen(&self, addr: SocketAddr) -> io::Result<SimulatedListener> {
+        let mut inner = self.inner.borrow_mut();
+
+        if inner.listeners.contains_key(&addr) {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("address {} is in use", addr),
+            ));
+        }
+
+        let (sender, incoming) = mpsc::unbounded();
+
+        inner.listeners.insert(addr, sender);
+
+        Ok(SimulatedListener { addr, incoming })
+    }
+
+    /// Connect to the listener on the address, or fail with `ConnectionRefused` if nobody is listening.
+    pub fn connect(&self, addr: &SocketAddr) -> io::Result<SimulatedStream> {
+        let mut inner = self.inner.borrow_mut();
+        let local_addr = SocketAddr::from(([127, 0, 0, 1], inner.next_port));
+
+        inner.next_port = inner.next_port.checked_add(1).unwrap_or(CLIENT_PORTS);
+
+        let (client, server) = SimulatedStream::pair(local_addr, *addr, self.activity.clone());
+        let accepted = inner
+            .listeners
+            .get(addr)
+            .map_or(false, |listener| listener.unbounded_send((server, local_addr)).is_ok());
+
+        if accepted {
+            self.activity.record();
+
+            Ok(client)
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                format!("connection to {} refused", addr),
+            ))
+        }
+    }
+
+    /// Run the spawned futures and the connections until none of them could make progress.
+    pub fn run_until_stalled(&self) {
+        let notify = NotifyHandle::from(Arc::new(self.activity.clone()));
+        let mut quiet_rounds = 0;
+
+        // the tasks of the event loop are woken without being counted,
+        // so the simulation is stalled only if a whole round after the last activity is quiet too
+        while quiet_rounds < 2 {
+            let activity = self.activity.get();
+            let tasks = mem::replace(&mut self.inner.borrow_mut().tasks, Vec::new());
+            let mut pending = Vec::with_capacity(tasks.len());
+
+            for mut task in tasks {
+                match task.poll_future_notify(&notify, 0) {
+                    Ok(Async::NotReady) => pending.push(task),
+                    Ok(Async::Ready(())) | Err(()) => self.activity.record(),
+                }
+            }
+
+            {
+                let mut inner = self.inner.borrow_mut();
+
+                pending.extend(inner.tasks.drain(..));
+                inner.tasks = pending;
+            }
+
+            self.core.borrow_mut().turn(Some(Duration::from_millis(0)));
+
+            if self.activity.get() == activity {
+                quiet_rounds += 1;
+            } else {
+                quiet_rounds = 0;
+            }
+        }
+    }
+
+    /// Advance the simulated time, fire the expired timers in order and run until stalled after each of them.
+    pub fn advance(&self, duration: Duration) {
+        let deadline = self.now() + duration;
+
+        self.run_until_stalled();
+
+        while self.fire_next_timer(Some(deadline)) {
+            self.run_until_stalled();
+        }
+
+        self.inner.borrow_mut().now = deadline;
+    }
+
+    /// Run the future to completion, the simulated time is advanced to the next timer whenever it is stalled.
+    ///
+    /// Fails with `Canceled` if the simulation stalled without any pending timer.
+    pub fn run<F>(&self, future: F) -> result::Result<F::Item, F::Error>
+    where
+        F: Future,
+        F::Error: From<Error>,
+    {
+        let notify = NotifyHandle::from(Arc::new(self.activity.clone()));
+        let mut future = executor::spawn(future);
+
+        loop {
+            let activity = self.activity.get();
+
+            if let Async::Ready(item) = future.poll_future_notify(&notify, 0)? {
+                return Ok(item);
+            }
+
+            self.run_until_stalled();
+
+            if self.activity.get() == activity && !self.fire_next_timer(None) {
+                return Err(ErrorKind::Canceled("the simulation stalled").into());
+            }
+        }
+    }
+
+    /// Fire the next timer which expires before the deadline, and move the simulated time to it.
+    fn fire_next_timer(&self, deadline: Option<Duration>) -> bool {
+        let expired = {
+            let mut inner = self.inner.borrow_mut();
+            let key = inner.timers.keys().next().cloned();
+
+            match key {
+                Some(key) if deadline.map_or(true, |deadline| key.0 <= deadline) => {
+                    inner.now = cmp::max(inner.now, key.0);
+                    inner.timers.remove(&key)
+                }
+                _ => None,
+            }
+        };
+
+        match expired {
+            Some(sender) => {
+                drop(sender.send(()));
+
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl Executor for Simulation {
+    fn spawn(&self, future: Spawned) {
+        self.inner.borrow_mut().tasks.push(executor::spawn(future));
+        self.activity.record();
+    }
+}
+
+impl Timer for Simulation {
+    fn sleep(&self, duration: Duration) -> Sleep {
+        let (sender, receiver) = oneshot::channel();
+        let mut inner = self.inner.borrow_mut();
+        let key = (inner.now + duration, inner.next_timer_id);
+
+        inner.next_timer_id += 1;
+        inner.timers.insert(key, sender);
+
+        receiver
+            .map_err(|_| ErrorKind::Canceled("simulation dropped").into())
+            .static_boxed()
+    }
+
+    fn now(&self) -> Instant {
+        let inner = self.inner.borrow();
+
+        inner.start + inner.now
+    }
+}
+
+/// The transport which connects to the brokers listening in a `Simulation` with the in-memory streams.
+#[derive(Clone)]
+pub struct SimulatedTransport(Simulation);
+
+impl Transport for SimulatedTransport {
+    fn connect(&self, addr: &SocketAddr, _handle: &Handle) -> TransportConnect {
+        Box::new(future::result(self.0.connect(addr)).map(|stream| Box::new(stream) as Box<TransportStream>))
+    }
+}
+
+/// The stream of the simulated connections accepted on an address.
+pub struct SimulatedListener {
+    addr: SocketAddr,
+    incoming: mpsc::UnboundedReceiver<(SimulatedStream, SocketAddr)>,
+}
+
+impl SimulatedListener {
+    /// The address which the listener is listening on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl Stream for SimulatedListener {
+    type Item = (SimulatedStream, SocketAddr);
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        self.incoming
+            .poll()
+            .map_err(|_| io::Error::new(io::ErrorKind::Other, "simulation dropped"))
+    }
+}
+
+/// One direction of a simulated connection.
+#[derive(Default)]
+struct Pipe {
+    buf: VecDeque<u8>,
+    closed: bool,
+    reader: Option<Task>,
+}
+
+impl Pipe {
+    fn close(&mut self) {
+        self.closed = true;
+
+        if let Some(reader) = self.reader.take() {
+            reader.notify();
+        }
+    }
+}
+
+/// An end of a simulated connection, the bytes written to it are read from the other end.
+///
+/// The other end reads EOF once this end was shut down or dropped.
+pub struct SimulatedStream {
+    local_addr: SocketAddr,
+    peer_addr: SocketAddr,
+    incoming: Rc<RefCell<Pipe>>,
+    outgoing: Rc<RefCell<Pipe>>,
+    activity: Activity,
+}
+
+impl SimulatedStream {
+    fn pair(client_addr: SocketAddr, server_addr: SocketAddr, activity: Activity) -> (Self, Self) {
+        let upstream = Rc::new(RefCell::new(Pipe::default()));
+        let downstream = Rc::new(RefCell::new(Pipe::default()));
+
+        (
+            SimulatedStream {
+                local_addr: client_addr,
+                peer_addr: server_addr,
+                incoming: downstream.clone(),
+                outgoing: upstream.clone(),
+                activity: activity.clone(),
+            },
+            SimulatedStream {
+                local_addr: server_addr,
+                peer_addr: client_addr,
+                incoming: upstream,
+                outgoing: downstream,
+                activity,
+            },
+        )
+    }
+
+    /// The local address of this end.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// The address of the other end.
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.peer_addr
+    }
+}
+
+impl fmt::Debug for SimulatedStream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SimulatedStream")
+            .field("local_addr", &self.local_addr)
+            .field("peer_addr", &self.peer_addr)
+            .finish()
+    }
+}
+
+impl Read for SimulatedStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut pipe = self.incoming.borrow_mut();
+
+        if pipe.buf.is_empty() {
+            if pipe.closed {
+                return Ok(0);
+            }
+
+            pipe.reader = Some(task::current());
+
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+
+        let len = cmp::min(buf.len(), pipe.buf.len());
+
+        for (dst, src) in buf.iter_mut().zip(pipe.buf.drain(..len)) {
+            *dst = src;
+        }
+
+        self.activity.record();
+
+        Ok(len)
+    }
+}
+
+impl Write for SimulatedStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut pipe = self.outgoing.borrow_mut();
+
+        if pipe.closed {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+
+        pipe.buf.extend(buf.iter().cloned());
+
+        if let Some(reader) = pipe.reader.take() {
+            reader.notify();
+        }
+
+        self.activity.record();
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl AsyncRead for SimulatedStream {}
+
+impl AsyncWrite for SimulatedStream {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.outgoing.borrow_mut().close();
+        self.activity.record();
+
+        Ok(Async::Ready(()))
+    }
+}
+
+impl Drop for SimulatedStream {
+    fn drop(&mut self) {
+        self.incoming.borrow_mut().close();
+        self.outgoing.borrow_mut().close();
+        self.activity.record();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use tokio_io::io::{copy, read_to_end, shutdown, write_all};
+
+    use super::*;
+
+    #[test]
+    fn test_timers() {
+        let simulation = Simulation::new().unwrap();
+        let runtime = simulation.runtime();
+        let fired = Rc::new(RefCell::new(Vec::new()));
+
+        for &(name, delay) in &[("second", 200), ("first", 100)] {
+            let fired = fired.clone();
+            let simulation = simulation.clone();
+
+            runtime.spawn(
+                runtime
+                    .sleep(Duration::from_millis(delay))
+                    .map(move |_| fired.borrow_mut().push((name, simulation.now())))
+                    .map_err(|_| ()),
+            );
+        }
+
+        simulation.run_until_stalled();
+
+        assert_eq!(simulation.pending_tasks(), 2);
+        assert_eq!(simulation.pending_timers(), 2);
+        assert!(fired.borrow().is_empty());
+
+        simulation.advance(Duration::from_millis(150));
+
+        assert_eq!(simulation.now(), Duration::from_millis(150));
+        assert_eq!(*fired.borrow(), vec![("first", Duration::from_millis(100))]);
+
+        simulation.advance(Duration::from_millis(100));
+
+        assert_eq!(
+            *fired.borrow(),
+            vec![
+                ("first", Duration::from_millis(100)),
+                ("second", Duration::from_millis(200)),
+            ]
+        );
+        assert_eq!(simulation.pending_tasks(), 0);
+        assert_eq!(simulation.pending_timers(), 0);
+    }
+
+    #[test]
+    fn test_chained_timers() {
+        let simulation = Simulation::new().unwrap();
+        let runtime = simulation.runtime();
+        let started = runtime.now();
+        let retries = Rc::new(Cell::new(0));
+
+        runtime.spawn({
+            let retries = retries.clone();
+            let runtime = runtime.clone();
+
+            runtime
+                .sleep(Duration::from_millis(100))
+                .and_then(move |_| {
+                    retries.set(retries.get() + 1);
+
+                    runtime.sleep(Duration::from_millis(100)).map(move |_| {
+                        retries.set(retries.get() + 1);
+                    })
+                })
+                .map_err(|_| ())
+        });
+
+        simulation.advance(Duration::from_millis(250));
+
+        assert_eq!(retries.get(), 2);
+        assert_eq!(simulation.pending_tasks(), 0);
+        assert_eq!(runtime.now().duration_since(started), Duration::from_millis(250));
+    }
+
+    #[test]
+    fn test_run_to_next_timer() {
+        let simulation = Simulation::new().unwrap();
+        let runtime = simulation.runtime();
+
+        simulation.run(runtime.sleep(Duration::from_secs(30))).unwrap();
+
+        assert_eq!(simulation.now(), Duration::from_secs(30));
+
+        // nothing could wake up the future
+        match simulation.run(future::empty::<(), Error>()) {
+            Err(Error(ErrorKind::Canceled(_), _)) => {}
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_connections() {
+        let simulation = Simulation::new().unwrap();
+        let addr = "10.0.0.1:9092".parse().unwrap();
+        let listener = simulation.listen(addr).unwrap();
+
+        assert_eq!(simulation.listen(addr).unwrap_err().kind(), io::ErrorKind::AddrInUse);
+        assert_eq!(
+            simulation
+                .connect(&"10.0.0.2:9092".parse().unwrap())
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::ConnectionRefused
+        );
+
+        // echo the received bytes until the client shuts down
+        simulation.runtime().spawn(
+            listener
+                .for_each(|(stream, peer)| {
+                    assert_eq!(stream.peer_addr(), peer);
+
+                    let (reader, writer) = stream.split();
+
+                    copy(reader, writer).map(|_| ())
+                })
+                .map_err(|err| warn!("fail to serve, {}", err)),
+        );
+
+        let stream = simulation.connect(&addr).unwrap();
+
+        assert_eq!(stream.peer_addr(), addr);
+
+        let echoed = simulation
+            .run(
+                write_all(stream, b"ping")
+                    .and_then(|(stream, _)| shutdown(stream))
+                    .and_then(|stream| read_to_end(stream, Vec::new()))
+                    .map(|(_, echoed)| echoed)
+                    .from_err::<Error>(),
+            )
+            .unwrap();
+
+        assert_eq!(echoed, b"ping");
+        assert_eq!(simulation.now(), Duration::default());
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn test_produce_with_linger_and_retry() {
+        use client::{KafkaClient, RetryPolicy};
+        use mock::MockCluster;
+        use producer::{Producer, ProducerBuilder, ProducerRecord};
+        use protocol::{ApiKeys, KafkaCode};
+        use serialization::StringSerializer;
+
+        let simulation = Simulation::new().unwrap();
+        let runtime = simulation.runtime();
+        let cluster = MockCluster::simulated(1, &simulation).unwrap();
+
+        cluster.create_topic("topic", 1);
+
+        let client = KafkaClient::with_bootstrap_servers(cluster.bootstrap_servers(), simulation.handle())
+            .with_runtime(runtime.clone())
+            .with_transport(simulation.transport())
+            .build()
+            .unwrap();
+        let mut builder = ProducerBuilder::from(client)
+            .with_key_serializer(StringSerializer::default())
+            .with_value_serializer(StringSerializer::default())
+            .with_default_partitioner()
+            .with_linger(Duration::from_millis(50));
+        builder.retry_policies.retriable = Some(RetryPolicy {
+            max_retries: 1,
+            backoff: 100,
+            jitter: false,
+            ..Default::default()
+        });
+        let mut producer = builder.build().unwrap();
+        let offset = Rc::new(Cell::new(None));
+
+        runtime.spawn({
+            let offset = offset.clone();
+
+            producer
+                .send(ProducerRecord::from_value("topic", "foo".to_owned()))
+                .map(move |metadata| offset.set(Some(metadata.offset)))
+                .map_err(|err| warn!("fail to send record, {}", err))
+        });
+
+        // the metadata is loaded through the simulated connections, and the batch lingers
+        simulation.run_until_stalled();
+
+        assert!(cluster.messages("topic", 0).is_empty());
+
+        cluster.inject_error(ApiKeys::Produce, "topic", 0, KafkaCode::RequestTimedOut);
+
+        // the batch is sent once the linger elapsed, and retried after the backoff
+        simulation.advance(Duration::from_millis(50));
+
+        assert!(cluster.messages("topic", 0).is_empty());
+
+        simulation.advance(Duration::from_millis(99));
+
+        assert!(cluster.messages("topic", 0).is_empty());
+        assert_eq!(offset.get(), None);
+
+        simulation.advance(Duration::from_millis(1));
+
+        assert_eq!(cluster.messages("topic", 0).len(), 1);
+        assert_eq!(offset.get(), Some(0));
+        assert_eq!(simulation.now(), Duration::from_millis(150));
+    }
+}
@@ -0,0 +1,86 @@
+use std::fmt;
+use std::hash::Hash;
+use std::marker::PhantomData;
+
+use producer::ProducerRecord;
+
+/// A topic bound to the type of key and value.
+///
+/// The producer and consumer working on a `TypedTopic` must use the serializer and deserializer
+/// of the same key and value types, so the type mismatches are caught at compile time.
+pub struct TypedTopic<K, V> {
+    topic_name: String,
+    phantom: PhantomData<fn() -> (K, V)>,
+}
+
+impl<K, V> Clone for TypedTopic<K, V> {
+    fn clone(&self) -> Self {
+        TypedTopic::new(self.topic_name.clone())
+    }
+}
+
+impl<K, V> fmt::Debug for TypedTopic<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "TypedTopic({})", self.topic_name)
+    }
+}
+
+impl<K, V> TypedTopic<K, V> {
+    /// Construct a `TypedTopic` with the topic name
+    pub fn new<S: Into<String>>(topic_name: S) -> Self {
+        TypedTopic {
+            topic_name: topic_name.into(),
+            phantom: PhantomData,
+        }
+    }
+
+    /// The name of topic
+    pub fn name(&self) -> &str {
+        &self.topic_name
+    }
+}
+
+impl<K, V> TypedTopic<K, V>
+where
+    K: Hash,
+{
+    /// Creates a record to be sent to the topic
+    pub fn record(&self, key: K, value: V) -> ProducerRecord<K, V> {
+        ProducerRecord::from_key_value(&self.topic_name, key, value)
+    }
+
+    /// Creates a record to be sent to the topic with no key
+    pub fn value_record(&self, value: V) -> ProducerRecord<K, V> {
+        ProducerRecord {
+            topic_name: self.topic_name.clone(),
+            partition_id: None,
+            key: None,
+            value: Some(value),
+            timestamp: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_typed_topic() {
+        let topic = TypedTopic::<String, u32>::new("topic");
+
+        assert_eq!(topic.name(), "topic");
+
+        let record = topic.record("key".to_owned(), 123).with_partition(1);
+
+        assert_eq!(record.topic_name, "topic");
+        assert_eq!(record.partition_id, Some(1));
+        assert_eq!(record.key, Some("key".to_owned()));
+        assert_eq!(record.value, Some(123));
+
+        let record = topic.clone().value_record(456);
+
+        assert_eq!(record.key, None);
+        assert_eq!(record.value, Some(456));
+    }
+}
@@ -0,0 +1,76 @@
+//! The `std::future` API surface over the `futures 0.1` internals, for the applications built on
+//! `async`/`await` and the modern executors, e.g. tokio 1.x.
+//!
+//! The producers and consumers are driven on the dedicated threads with their own event loops,
+//! so the returned futures and streams are `Send` and could be polled by any executor.
+
+use std::future::Future as StdFuture;
+use std::hash::Hash;
+
+use futures::Stream;
+use futures03::compat::{Future01CompatExt, Stream01CompatExt};
+use futures03::{Stream as Stream03, StreamExt};
+use tokio_core::reactor::Handle;
+
+use errors::{Error, ErrorKind, Result};
+use producer::{ProducerRecord, RecordMetadata, SharedProducer};
+use sync::spawn_stream_thread;
+
+impl<K, V> SharedProducer<K, V>
+where
+    K: Hash + Send + 'static,
+    V: Send + 'static,
+{
+    /// Send the record, and return a `std::future::Future` which will eventually contain its metadata.
+    pub fn send_async(&self, record: ProducerRecord<K, V>) -> impl StdFuture<Output = Result<RecordMetadata>> + Send {
+        self.send(record).compat()
+    }
+
+    /// Flush the accumulated records of the producer as a `std::future::Future`.
+    pub fn flush_async(&self) -> impl StdFuture<Output = Result<()>> + Send {
+        self.flush().compat()
+    }
+}
+
+/// Run a stream on a dedicated thread with its own event loop, e.g. the records of a `KafkaConsumer`,
+/// and consume it as a `futures 0.3` stream from any executor.
+///
+/// The stream is built with the handle of the thread's event loop, and the thread exits
+/// once the stream was finished or the returned stream has been dropped.
+pub fn spawn_stream<F, S>(build: F) -> impl Stream03<Item = Result<S::Item>> + Send
+where
+    F: FnOnce(Handle) -> Result<S> + Send + 'static,
+    S: Stream<Error = Error> + 'static,
+    S::Item: Send + 'static,
+{
+    spawn_stream_thread(build).compat().map(|res| match res {
+        Ok(res) => res,
+        Err(()) => Err(ErrorKind::Canceled("stream thread exited").into()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::stream;
+    use futures03::executor::block_on;
+
+    use super::*;
+
+    #[test]
+    fn test_spawn_stream() {
+        let records = spawn_stream(|_| Ok(stream::iter_ok::<_, Error>(vec![1, 2, 3])));
+
+        let records = block_on(records.collect::<Vec<_>>())
+            .into_iter()
+            .map(|res| res.unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(records, vec![1, 2, 3]);
+
+        let mut failed = spawn_stream(|_| -> Result<stream::Empty<u32, Error>> {
+            bail!(ErrorKind::ConfigError("missed handle"))
+        });
+
+        assert!(block_on(failed.next()).unwrap().is_err());
+    }
+}
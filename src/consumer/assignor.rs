@@ -1,7 +1,7 @@
 #![cfg_attr(feature = "clippy", allow(while_let_on_iterator))]
 
 use std::borrow::Cow;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::str::FromStr;
 
 use client::{Cluster, Metadata};
@@ -49,6 +49,14 @@ pub enum AssignmentStrategy {
     /// when topic partitions move from one consumer to another.
     Sticky,
 
+    /// The cooperative sticky assignor works like the sticky assignor,
+    /// but follows the cooperative rebalancing protocol.
+    ///
+    /// The partitions migrated to the other members are revoked in the first rebalance,
+    /// and assigned in a following rebalance, so the unchanged partitions keep flowing during the rebalances.
+    #[serde(rename = "cooperative-sticky")]
+    CooperativeSticky,
+
     /// unsupported custom strategy
     Custom(String),
 }
@@ -59,6 +67,7 @@ impl AssignmentStrategy {
             AssignmentStrategy::Range => Some(Box::new(RangeAssignor::default())),
             AssignmentStrategy::RoundRobin => Some(Box::new(RoundRobinAssignor::default())),
             AssignmentStrategy::Sticky => Some(Box::new(StickyAssignor::default())),
+            AssignmentStrategy::CooperativeSticky => Some(Box::new(CooperativeStickyAssignor::default())),
             AssignmentStrategy::Custom(ref strategy) => {
                 warn!("unsupported assignment strategy: {}", strategy);
 
@@ -76,11 +85,22 @@ impl FromStr for AssignmentStrategy {
             "range" => Ok(AssignmentStrategy::Range),
             "roundrobin" => Ok(AssignmentStrategy::RoundRobin),
             "sticky" => Ok(AssignmentStrategy::Sticky),
+            "cooperative-sticky" => Ok(AssignmentStrategy::CooperativeSticky),
             _ => Ok(AssignmentStrategy::Custom(s.to_owned())),
         }
     }
 }
 
+/// The rebalancing protocol followed by the members of the consumer group.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RebalanceProtocol {
+    /// All the partitions are revoked and reassigned in a rebalance.
+    Eager,
+    /// Only the partitions migrated to the other members are revoked,
+    /// and the members rejoin the group to get them assigned in a following rebalance.
+    Cooperative,
+}
+
 /// Define custom partition assignment for use in `KafkaConsumer`
 ///
 /// Members of the consumer group subscribe to the topics they are interested in
@@ -96,12 +116,18 @@ pub trait PartitionAssignor {
     /// strategy for this assignor
     fn strategy(&self) -> AssignmentStrategy;
 
+    /// The rebalancing protocol supported by this assignor
+    fn rebalance_protocol(&self) -> RebalanceProtocol {
+        RebalanceProtocol::Eager
+    }
+
     /// Return a serializable object representing the local member's
     /// subscription.
     fn subscription<'a>(&self, topics: Vec<Cow<'a, str>>) -> Subscription<'a> {
         Subscription {
             topics,
             user_data: None,
+            owned_partitions: Vec::new(),
         }
     }
 
@@ -118,6 +144,8 @@ pub trait PartitionAssignor {
 pub struct Subscription<'a> {
     pub topics: Vec<Cow<'a, str>>,
     pub user_data: Option<Cow<'a, [u8]>>,
+    /// The partitions currently owned by the member, used by the sticky assignors.
+    pub owned_partitions: Vec<TopicPartition<'a>>,
 }
 
 #[derive(Clone, Debug, Default, PartialEq)]
@@ -381,13 +409,198 @@ impl PartitionAssignor for StickyAssignor {
 
     fn assign<'a>(
         &self,
-        _metadata: &'a Metadata,
-        _subscriptions: HashMap<Cow<'a, str>, Subscription<'a>>,
+        metadata: &'a Metadata,
+        subscriptions: HashMap<Cow<'a, str>, Subscription<'a>>,
     ) -> HashMap<Cow<'a, str>, Assignment<'a>> {
-        unimplemented!()
+        sticky_assign(metadata, &subscriptions)
+            .into_iter()
+            .map(|(member_id, partitions)| {
+                (
+                    member_id,
+                    Assignment {
+                        partitions,
+                        user_data: None,
+                    },
+                )
+            })
+            .collect()
     }
 }
 
+/// The cooperative sticky assignor follows the cooperative rebalancing protocol.
+///
+/// The assignment is the same as the sticky assignor, except that a partition owned by another member
+/// is not assigned until the owner has revoked it and rejoined the group.
+///
+/// For example, suppose `C0` owns `[t0p0, t0p1]` and `C1` joins the group.
+/// The first rebalance will result in:
+///
+/// - `C0 [t0p0]`
+/// - `C1 []`
+///
+/// `C0` revokes `t0p1` and rejoins the group, while keep consuming `t0p0`,
+/// then the following rebalance will result in:
+///
+/// - `C0 [t0p0]`
+/// - `C1 [t0p1]`
+#[derive(Debug, Default)]
+pub struct CooperativeStickyAssignor {}
+
+impl PartitionAssignor for CooperativeStickyAssignor {
+    fn name(&self) -> &'static str {
+        "cooperative-sticky"
+    }
+
+    fn strategy(&self) -> AssignmentStrategy {
+        AssignmentStrategy::CooperativeSticky
+    }
+
+    fn rebalance_protocol(&self) -> RebalanceProtocol {
+        RebalanceProtocol::Cooperative
+    }
+
+    fn assign<'a>(
+        &self,
+        metadata: &'a Metadata,
+        subscriptions: HashMap<Cow<'a, str>, Subscription<'a>>,
+    ) -> HashMap<Cow<'a, str>, Assignment<'a>> {
+        let owners = owners(&subscriptions);
+
+        sticky_assign(metadata, &subscriptions)
+            .into_iter()
+            .map(|(member_id, partitions)| {
+                // the partitions owned by the other members must be revoked before assigning them
+                let partitions = partitions
+                    .into_iter()
+                    .filter(|tp| owners.get(tp).map_or(true, |owner| **owner == member_id))
+                    .collect();
+
+                (
+                    member_id,
+                    Assignment {
+                        partitions,
+                        user_data: None,
+                    },
+                )
+            })
+            .collect()
+    }
+}
+
+/// The owner of each partition, the first member in the lexicographic order wins if it is owned by multiple members.
+fn owners<'a, 'b>(
+    subscriptions: &'b HashMap<Cow<'a, str>, Subscription<'a>>,
+) -> HashMap<&'b TopicPartition<'a>, &'b Cow<'a, str>> {
+    let mut members: Vec<&Cow<'a, str>> = subscriptions.keys().collect();
+
+    members.sort();
+
+    let mut owners = HashMap::new();
+
+    for member_id in members {
+        for tp in &subscriptions[member_id].owned_partitions {
+            owners.entry(tp).or_insert(member_id);
+        }
+    }
+
+    owners
+}
+
+/// Assign the partitions as balanced as possible, while preserving the partitions owned by the members.
+///
+/// The owned partitions are kept if the members still subscribe their topics,
+/// the remaining partitions are assigned to the least loaded members,
+/// then the partitions are moved from the members with 2+ more partitions until the assignment is balanced.
+fn sticky_assign<'a>(
+    metadata: &'a Metadata,
+    subscriptions: &HashMap<Cow<'a, str>, Subscription<'a>>,
+) -> BTreeMap<Cow<'a, str>, Vec<TopicPartition<'a>>> {
+    let subscribed = |member_id: &Cow<'a, str>, tp: &TopicPartition<'a>| {
+        subscriptions[member_id].topics.contains(&tp.topic_name)
+    };
+
+    let topic_names: BTreeSet<Cow<'a, str>> = subscriptions
+        .values()
+        .flat_map(|subscription| subscription.topics.iter().cloned())
+        .collect();
+
+    let mut unassigned: BTreeSet<TopicPartition<'a>> = topic_names
+        .iter()
+        .flat_map(|topic_name| metadata.partitions_for_topic(topic_name).unwrap_or_default())
+        .collect();
+
+    let mut assignment: BTreeMap<Cow<'a, str>, Vec<TopicPartition<'a>>> = subscriptions
+        .keys()
+        .map(|member_id| (member_id.clone(), Vec::new()))
+        .collect();
+
+    for (member_id, partitions) in &mut assignment {
+        for tp in &subscriptions[member_id].owned_partitions {
+            if subscribed(member_id, tp) && unassigned.remove(tp) {
+                partitions.push(tp.clone());
+            }
+        }
+    }
+
+    for tp in unassigned {
+        let member_id = assignment
+            .iter()
+            .filter(|&(member_id, _)| subscribed(member_id, &tp))
+            .min_by_key(|&(_, partitions)| partitions.len())
+            .map(|(member_id, _)| member_id.clone());
+
+        if let Some(member_id) = member_id {
+            assignment.get_mut(&member_id).unwrap().push(tp);
+        }
+    }
+
+    loop {
+        let mut members: Vec<(usize, Cow<'a, str>)> = assignment
+            .iter()
+            .map(|(member_id, partitions)| (partitions.len(), member_id.clone()))
+            .collect();
+
+        members.sort();
+
+        let mut reassignment = None;
+
+        'search: for &(most, ref from) in members.iter().rev() {
+            for &(least, ref to) in &members {
+                if least + 1 >= most {
+                    break;
+                }
+
+                let owned = &subscriptions[from].owned_partitions;
+
+                // prefer to move the last newly assigned partition, to keep the owned partitions sticky
+                let moved = assignment[from]
+                    .iter()
+                    .enumerate()
+                    .filter(|&(_, tp)| subscribed(to, tp))
+                    .max_by_key(|&(_, tp)| !owned.contains(tp))
+                    .map(|(idx, _)| idx);
+
+                if let Some(idx) = moved {
+                    reassignment = Some((from.clone(), to.clone(), idx));
+
+                    break 'search;
+                }
+            }
+        }
+
+        match reassignment {
+            Some((from, to, idx)) => {
+                let tp = assignment.get_mut(&from).unwrap().remove(idx);
+
+                assignment.get_mut(&to).unwrap().push(tp);
+            }
+            None => break,
+        }
+    }
+
+    assignment
+}
+
 #[cfg(test)]
 mod tests {
     use std::iter::FromIterator;
@@ -423,6 +636,7 @@ mod tests {
                     Subscription {
                         topics: vec!["t0".into(), "t1".into()],
                         user_data: None,
+                        owned_partitions: vec![],
                     },
                 ),
                 (
@@ -430,6 +644,7 @@ mod tests {
                     Subscription {
                         topics: vec!["t0".into(), "t1".into()],
                         user_data: None,
+                        owned_partitions: vec![],
                     },
                 ),
             ].into_iter(),
@@ -487,6 +702,7 @@ mod tests {
                     Subscription {
                         topics: vec!["t0".into(), "t1".into()],
                         user_data: None,
+                        owned_partitions: vec![],
                     },
                 ),
                 (
@@ -494,6 +710,7 @@ mod tests {
                     Subscription {
                         topics: vec!["t0".into(), "t1".into()],
                         user_data: None,
+                        owned_partitions: vec![],
                     },
                 ),
             ].into_iter(),
@@ -554,6 +771,7 @@ mod tests {
                     Subscription {
                         topics: vec!["t0".into()],
                         user_data: None,
+                        owned_partitions: vec![],
                     },
                 ),
                 (
@@ -561,6 +779,7 @@ mod tests {
                     Subscription {
                         topics: vec!["t0".into(), "t1".into()],
                         user_data: None,
+                        owned_partitions: vec![],
                     },
                 ),
                 (
@@ -568,6 +787,7 @@ mod tests {
                     Subscription {
                         topics: vec!["t0".into(), "t1".into(), "t2".into()],
                         user_data: None,
+                        owned_partitions: vec![],
                     },
                 ),
             ].into_iter(),
@@ -603,4 +823,199 @@ mod tests {
             }
         );
     }
+
+    fn subscription<'a>(topics: &[&'a str], owned_partitions: Vec<TopicPartition<'a>>) -> Subscription<'a> {
+        Subscription {
+            topics: topics.iter().map(|&topic_name| topic_name.into()).collect(),
+            user_data: None,
+            owned_partitions,
+        }
+    }
+
+    fn assigned<'a>(assignment: &HashMap<Cow<'a, str>, Assignment<'a>>, member_id: &str) -> Vec<TopicPartition<'a>> {
+        assignment[member_id].partitions.clone()
+    }
+
+    /// Example 1. Suppose there are three consumers `C0, `C1`, `C2`,
+    /// four topics `t0,` `t1`, `t2`, `t3`, and each topic has 2 partitions.
+    ///
+    /// The assignment will be:
+    ///
+    /// - `C0: [t0p0, t1p1, t3p0]`
+    /// - `C1: [t0p1, t2p0, t3p1]`
+    /// - `C2: [t1p0, t2p1]`
+    ///
+    /// After `C1` is removed, the assignment will be:
+    ///
+    /// - `C0 [t0p0, t1p1, t3p0, t2p0]`
+    /// - `C2 [t1p0, t2p1, t0p1, t3p1]`
+    #[test]
+    fn test_sticky_assignor() {
+        let assignor = StickyAssignor::default();
+        let topics = ["t0", "t1", "t2", "t3"];
+        let metadata = Metadata::with_topics(
+            topics
+                .iter()
+                .map(|&topic_name| (topic_name.into(), vec![PartitionInfo::new(0), PartitionInfo::new(1)]))
+                .collect(),
+        );
+        let subscriptions = HashMap::from_iter(
+            vec![
+                ("c0".into(), subscription(&topics, vec![])),
+                ("c1".into(), subscription(&topics, vec![])),
+                ("c2".into(), subscription(&topics, vec![])),
+            ].into_iter(),
+        );
+
+        let assignment = assignor.assign(&metadata, subscriptions);
+
+        assert_eq!(
+            assigned(&assignment, "c0"),
+            vec![topic_partition!("t0", 0), topic_partition!("t1", 1), topic_partition!("t3", 0)]
+        );
+        assert_eq!(
+            assigned(&assignment, "c1"),
+            vec![topic_partition!("t0", 1), topic_partition!("t2", 0), topic_partition!("t3", 1)]
+        );
+        assert_eq!(
+            assigned(&assignment, "c2"),
+            vec![topic_partition!("t1", 0), topic_partition!("t2", 1)]
+        );
+
+        let subscriptions = HashMap::from_iter(
+            vec![
+                ("c0".into(), subscription(&topics, assigned(&assignment, "c0"))),
+                ("c2".into(), subscription(&topics, assigned(&assignment, "c2"))),
+            ].into_iter(),
+        );
+
+        let assignment = assignor.assign(&metadata, subscriptions);
+
+        assert_eq!(assignment.len(), 2);
+        assert_eq!(
+            assigned(&assignment, "c0"),
+            vec![
+                topic_partition!("t0", 0),
+                topic_partition!("t1", 1),
+                topic_partition!("t3", 0),
+                topic_partition!("t2", 0),
+            ]
+        );
+        assert_eq!(
+            assigned(&assignment, "c2"),
+            vec![
+                topic_partition!("t1", 0),
+                topic_partition!("t2", 1),
+                topic_partition!("t0", 1),
+                topic_partition!("t3", 1),
+            ]
+        );
+    }
+
+    /// Example 2. There are three consumers `C0`, `C1`, `C2`,
+    /// and three topics `t0`, `t1`, `t2`, with 1, 2, and 3 partitions respectively.
+    /// `C0` is subscribed to `t0`; `C1` is subscribed to `t0`, `t1`;
+    /// and `C2` is subscribed to `t0`, `t1`, `t2`.
+    ///
+    /// The assignment will be:
+    ///
+    /// - `C0 [t0p0]`
+    /// - `C1 [t1p0, t1p1]`
+    /// - `C2 [t2p0, t2p1, t2p2]`
+    ///
+    /// After `C0` is removed, the assignment will be:
+    ///
+    /// - `C1 [t1p0, t1p1, t0p0]`
+    /// - `C2 [t2p0, t2p1, t2p2]`
+    #[test]
+    fn test_sticky_assignor_more() {
+        let assignor = StickyAssignor::default();
+        let metadata = Metadata::with_topics(vec![
+            ("t0".into(), vec![PartitionInfo::new(0)]),
+            ("t1".into(), vec![PartitionInfo::new(0), PartitionInfo::new(1)]),
+            (
+                "t2".into(),
+                vec![PartitionInfo::new(0), PartitionInfo::new(1), PartitionInfo::new(2)],
+            ),
+        ]);
+        let subscriptions = HashMap::from_iter(
+            vec![
+                ("c0".into(), subscription(&["t0"], vec![])),
+                ("c1".into(), subscription(&["t0", "t1"], vec![])),
+                ("c2".into(), subscription(&["t0", "t1", "t2"], vec![])),
+            ].into_iter(),
+        );
+
+        let assignment = assignor.assign(&metadata, subscriptions);
+
+        assert_eq!(assigned(&assignment, "c0"), vec![topic_partition!("t0", 0)]);
+        assert_eq!(
+            assigned(&assignment, "c1"),
+            vec![topic_partition!("t1", 0), topic_partition!("t1", 1)]
+        );
+        assert_eq!(
+            assigned(&assignment, "c2"),
+            vec![topic_partition!("t2", 0), topic_partition!("t2", 1), topic_partition!("t2", 2)]
+        );
+
+        let subscriptions = HashMap::from_iter(
+            vec![
+                ("c1".into(), subscription(&["t0", "t1"], assigned(&assignment, "c1"))),
+                ("c2".into(), subscription(&["t0", "t1", "t2"], assigned(&assignment, "c2"))),
+            ].into_iter(),
+        );
+
+        let assignment = assignor.assign(&metadata, subscriptions);
+
+        assert_eq!(
+            assigned(&assignment, "c1"),
+            vec![topic_partition!("t1", 0), topic_partition!("t1", 1), topic_partition!("t0", 0)]
+        );
+        assert_eq!(
+            assigned(&assignment, "c2"),
+            vec![topic_partition!("t2", 0), topic_partition!("t2", 1), topic_partition!("t2", 2)]
+        );
+    }
+
+    #[test]
+    fn test_cooperative_sticky_assignor() {
+        let assignor = CooperativeStickyAssignor::default();
+        let metadata = Metadata::with_topics(vec![
+            ("t0".into(), vec![PartitionInfo::new(0), PartitionInfo::new(1)]),
+        ]);
+
+        // the partition migrated to `c1` is revoked by `c0` in the first rebalance
+        let subscriptions = HashMap::from_iter(
+            vec![
+                (
+                    "c0".into(),
+                    subscription(&["t0"], vec![topic_partition!("t0", 0), topic_partition!("t0", 1)]),
+                ),
+                ("c1".into(), subscription(&["t0"], vec![])),
+            ].into_iter(),
+        );
+
+        let assignment = assignor.assign(&metadata, subscriptions);
+
+        assert_eq!(assigned(&assignment, "c0"), vec![topic_partition!("t0", 0)]);
+        assert!(assigned(&assignment, "c1").is_empty());
+
+        // and assigned to `c1` in the following rebalance
+        let subscriptions = HashMap::from_iter(
+            vec![
+                ("c0".into(), subscription(&["t0"], vec![topic_partition!("t0", 0)])),
+                ("c1".into(), subscription(&["t0"], vec![])),
+            ].into_iter(),
+        );
+
+        let assignment = assignor.assign(&metadata, subscriptions);
+
+        assert_eq!(assigned(&assignment, "c0"), vec![topic_partition!("t0", 0)]);
+        assert_eq!(assigned(&assignment, "c1"), vec![topic_partition!("t0", 1)]);
+        assert_eq!(assignor.rebalance_protocol(), RebalanceProtocol::Cooperative);
+        assert_eq!(
+            "cooperative-sticky".parse::<AssignmentStrategy>().unwrap(),
+            AssignmentStrategy::CooperativeSticky
+        );
+    }
 }
@@ -1,25 +1,39 @@
+use std::cell::RefCell;
 use std::ops::{Deref, DerefMut};
+use std::rc::Rc;
 use std::time::Duration;
 
 use tokio_core::reactor::Handle;
 
 use client::{KafkaClient, KafkaVersion};
-use consumer::{AssignmentStrategy, ConsumerConfig, KafkaConsumer, OffsetResetStrategy};
+use consumer::{AssignmentStrategy, ConsumerConfig, ConsumerInterceptor, ConsumerInterceptors, Interceptors,
+               KafkaConsumer, LeaderSelector, OffsetResetStrategy, ReplicaSelector};
 use errors::{ErrorKind, Result};
+use network::SaslMechanism;
 use protocol::ToMilliseconds;
 use serialization::{Deserializer, NoopDeserializer};
 
 /// A `KafkaConsumer` builder easing the process of setting up various
 /// configuration settings.
-pub struct ConsumerBuilder<'a, K, V> {
+pub struct ConsumerBuilder<'a, K, V>
+where
+    K: Deserializer,
+    V: Deserializer,
+{
     config: ConsumerConfig,
     client: Option<KafkaClient<'a>>,
     handle: Option<Handle>,
     key_deserializer: Option<K>,
     value_deserializer: Option<V>,
+    interceptors: Interceptors<'a, K::Item, V::Item>,
+    replica_selector: Option<Box<ReplicaSelector>>,
 }
 
-impl<'a, K, V> Deref for ConsumerBuilder<'a, K, V> {
+impl<'a, K, V> Deref for ConsumerBuilder<'a, K, V>
+where
+    K: Deserializer,
+    V: Deserializer,
+{
     type Target = ConsumerConfig;
 
     fn deref(&self) -> &Self::Target {
@@ -27,13 +41,21 @@ impl<'a, K, V> Deref for ConsumerBuilder<'a, K, V> {
     }
 }
 
-impl<'a, K, V> DerefMut for ConsumerBuilder<'a, K, V> {
+impl<'a, K, V> DerefMut for ConsumerBuilder<'a, K, V>
+where
+    K: Deserializer,
+    V: Deserializer,
+{
     fn deref_mut(&mut self) -> &mut Self::Target {
         &mut self.config
     }
 }
 
-impl<'a, K, V> Default for ConsumerBuilder<'a, K, V> {
+impl<'a, K, V> Default for ConsumerBuilder<'a, K, V>
+where
+    K: Deserializer,
+    V: Deserializer,
+{
     fn default() -> Self {
         ConsumerBuilder {
             config: ConsumerConfig::default(),
@@ -41,11 +63,17 @@ impl<'a, K, V> Default for ConsumerBuilder<'a, K, V> {
             handle: None,
             key_deserializer: None,
             value_deserializer: None,
+            interceptors: None,
+            replica_selector: None,
         }
     }
 }
 
-impl<'a, K, V> From<KafkaClient<'a>> for ConsumerBuilder<'a, K, V> {
+impl<'a, K, V> From<KafkaClient<'a>> for ConsumerBuilder<'a, K, V>
+where
+    K: Deserializer,
+    V: Deserializer,
+{
     fn from(client: KafkaClient<'a>) -> Self {
         ConsumerBuilder {
             client: Some(client),
@@ -54,7 +82,11 @@ impl<'a, K, V> From<KafkaClient<'a>> for ConsumerBuilder<'a, K, V> {
     }
 }
 
-impl<'a, K, V> ConsumerBuilder<'a, K, V> {
+impl<'a, K, V> ConsumerBuilder<'a, K, V>
+where
+    K: Deserializer,
+    V: Deserializer,
+{
     /// Construct a `ConsumerBuilder` from ConsumerConfig
     pub fn with_config(config: ConsumerConfig, handle: Handle) -> Self {
         ConsumerBuilder {
@@ -98,6 +130,13 @@ impl<'a, K, V> ConsumerBuilder<'a, K, V> {
         self
     }
 
+    /// Sets the maximum number of unacknowledged requests the client will send on a single
+    /// connection before blocking.
+    pub fn with_max_in_flight_requests_per_connection(mut self, max_in_flight_requests: usize) -> Self {
+        self.config.max_in_flight_requests_per_connection = max_in_flight_requests;
+        self
+    }
+
     /// Sets the maximum amount of time the client will wait for the response
     /// of a request.
     pub fn with_request_timeout(mut self, request_timeout: Duration) -> Self {
@@ -125,12 +164,78 @@ impl<'a, K, V> ConsumerBuilder<'a, K, V> {
         self
     }
 
+    /// Sets the period of time in milliseconds after which the cached metadata of a topic is
+    /// considered stale
+    pub fn with_topic_metadata_refresh_interval(mut self, refresh_interval: Duration) -> Self {
+        self.config.topic_metadata_refresh_interval = refresh_interval.as_millis();
+        self
+    }
+
     /// Sets to record metrics for client operations
     pub fn with_metrics(mut self) -> Self {
         self.config.metrics = true;
         self
     }
 
+    /// Sets the topics whose leaders will be pre-connected at startup
+    pub fn with_warm_up_topics<I, S>(mut self, topic_names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.config.warm_up_topics = topic_names.into_iter().map(|s| s.into()).collect();
+        self
+    }
+
+    /// Sets the SASL/PLAIN credentials used to authenticate the connections
+    pub fn with_sasl_plain<S>(mut self, username: S, password: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.config.sasl_username = Some(username.into());
+        self.config.sasl_password = Some(password.into());
+        self
+    }
+
+    /// Sets the SASL mechanism to use for authentication
+    pub fn with_sasl_mechanism(mut self, mechanism: SaslMechanism) -> Self {
+        self.config.sasl_mechanism = mechanism;
+        self
+    }
+
+    /// Connect to the brokers with TLS/SSL
+    pub fn with_ssl(mut self) -> Self {
+        self.config.ssl_enabled = true;
+        self
+    }
+
+    /// Sets the CA certificate used to verify the brokers, and enable TLS/SSL
+    pub fn with_ssl_ca_location<S>(mut self, location: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.config.ssl_enabled = true;
+        self.config.ssl_ca_location = Some(location.into());
+        self
+    }
+
+    /// Sets the PKCS#12 archive with the client certificate and private key, and enable TLS/SSL
+    pub fn with_ssl_keystore<S>(mut self, location: S, password: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.config.ssl_enabled = true;
+        self.config.ssl_keystore_location = Some(location.into());
+        self.config.ssl_keystore_password = Some(password.into());
+        self
+    }
+
+    /// Skip verifying the broker hostname against its certificate
+    pub fn without_ssl_hostname_verification(mut self) -> Self {
+        self.config.ssl_hostname_verification = false;
+        self
+    }
+
     /// Sets the unique string that identifies the consumer group this consumer
     /// belongs to.
     pub fn with_group_id<S>(mut self, group_id: S) -> Self
@@ -182,6 +287,13 @@ impl<'a, K, V> ConsumerBuilder<'a, K, V> {
         self
     }
 
+    /// Sets the partition assignment strategy to retry joining the group with,
+    /// when the coordinator rejects the configured strategies.
+    pub fn with_assignment_strategy_fallback(mut self, assignment_strategy: Vec<AssignmentStrategy>) -> Self {
+        self.config.assignment_strategy_fallback = assignment_strategy;
+        self
+    }
+
     /// Sets timeout used to detect consumer failures when using Kafka's group management
     /// facility.
     pub fn with_session_timeout(mut self, session_timeout: Duration) -> Self {
@@ -229,6 +341,53 @@ impl<'a, K, V> ConsumerBuilder<'a, K, V> {
         self
     }
 
+    /// Sets the maximum number of the fetched records buffered by the consumer.
+    pub fn with_queued_max_messages(mut self, queued_max_messages: usize) -> Self {
+        self.config.queued_max_messages = queued_max_messages;
+        self
+    }
+
+    /// Sets the state file shared by the groupless consumers to own the partitions exclusively.
+    pub fn with_partition_ownership_file<S>(mut self, path: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.config.partition_ownership_file = Some(path.into());
+        self
+    }
+
+    /// Sets the lease of the partitions claimed in the partition ownership file.
+    pub fn with_partition_ownership_lease(mut self, lease: Duration) -> Self {
+        self.config.partition_ownership_lease = lease.as_millis();
+        self
+    }
+
+    /// Sets to record the end-to-end latency of the consumed records, and enable the metrics
+    pub fn with_end_to_end_latency(mut self) -> Self {
+        self.config.metrics = true;
+        self.config.end_to_end_latency = true;
+        self
+    }
+
+    /// Sets the rack identifier of the client, used by the rack-aware replica selector
+    pub fn with_client_rack<S>(mut self, rack: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.config.client_rack = Some(rack.into());
+        self
+    }
+
+    /// Sets the selector which choosing the replica to fetch the partitions from,
+    /// the partitions will be fetched from the leaders by default.
+    pub fn with_replica_selector<S>(mut self, replica_selector: S) -> Self
+    where
+        S: ReplicaSelector + 'static,
+    {
+        self.replica_selector = Some(Box::new(replica_selector));
+        self
+    }
+
     /// Sets the key serializer that serialize key to record
     pub fn with_key_deserializer(mut self, key_deserializer: K) -> Self {
         self.key_deserializer = Some(key_deserializer);
@@ -240,6 +399,21 @@ impl<'a, K, V> ConsumerBuilder<'a, K, V> {
         self.value_deserializer = Some(value_deserializer);
         self
     }
+
+    /// Sets the interceptor which intercepte (and possibly mutate) the records
+    /// received by the consumer, and be notified of the committed offsets.
+    pub fn with_interceptor<I>(mut self, interceptor: I) -> Self
+    where
+        I: ConsumerInterceptor<'a, Key = K::Item, Value = V::Item> + 'static,
+    {
+        let interceptors = self.interceptors
+            .unwrap_or_else(|| Rc::new(RefCell::new(ConsumerInterceptors::new())));
+
+        interceptors.borrow_mut().push(Box::new(interceptor));
+
+        self.interceptors = Some(interceptors);
+        self
+    }
 }
 
 impl<'a, V> ConsumerBuilder<'a, NoopDeserializer<()>, V>
@@ -276,11 +450,16 @@ where
             client
         } else {
             KafkaClient::new(
-                self.config.client.clone(),
+                self.config.client_config(),
                 self.handle.ok_or(ErrorKind::ConfigError("missed handle"))?,
-            )
+            )?
         };
 
+        let mut replica_selector = self.replica_selector
+            .unwrap_or_else(|| Box::new(LeaderSelector::default()));
+
+        replica_selector.configure(&self.config);
+
         Ok(KafkaConsumer::new(
             client,
             self.config,
@@ -288,6 +467,8 @@ where
                 .ok_or(ErrorKind::ConfigError("missed key serializer"))?,
             self.value_deserializer
                 .ok_or(ErrorKind::ConfigError("missed value serializer"))?,
+            self.interceptors,
+            Rc::from(replica_selector),
         ))
     }
 }
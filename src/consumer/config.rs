@@ -1,8 +1,10 @@
+use std::cmp;
 use std::ops::{Deref, DerefMut};
 use std::time::Duration;
 
 use client::ClientConfig;
 use consumer::{AssignmentStrategy, OffsetResetStrategy};
+use protocol::ApiKeys;
 
 /// The default milliseconds that the consumer offsets are auto-committed to Kafka.
 ///
@@ -17,6 +19,9 @@ pub const DEFAULT_AUTO_COMMIT_INTERVAL_MILLIS: u64 = 5000;
 /// [`ConsumerConfig::heartbeat_interval`](struct.ConsumerConfig.html#heartbeat_interval.v)
 pub const DEFAULT_HEARTBEAT_INTERVAL_MILLIS: u64 = 3000;
 
+/// The extra milliseconds added to the `max.poll.interval.ms` to wait for the `JoinGroup` response.
+const JOIN_GROUP_TIMEOUT_MARGIN_MILLIS: u64 = 5000;
+
 /// The default maximum number of records returned in a single call to poll().
 ///
 /// Defaults to 500, see
@@ -65,6 +70,18 @@ pub const DEFAULT_FETCH_ERROR_BACKOFF_MILLIS: u64 = 500;
 /// [`ConsumerConfig::partition_fetch_bytes`](struct.ConsumerConfig.html#partition_fetch_bytes.v)
 pub const DEFAULT_PARTITION_FETCH_BYTES: usize = 1024 * 1024;
 
+/// The maximum number of the prefetched records buffered by the consumer.
+///
+/// Defaults to 100000, see
+/// [`ConsumerConfig::queued_max_messages`](struct.ConsumerConfig.html#queued_max_messages.v)
+pub const DEFAULT_QUEUED_MAX_MESSAGES: usize = 100_000;
+
+/// The default lease of the partitions owned by a groupless consumer.
+///
+/// Defaults to 30 seconds, see
+/// [`ConsumerConfig::partition_ownership_lease`](struct.ConsumerConfig.html#partition_ownership_lease.v)
+pub const DEFAULT_PARTITION_OWNERSHIP_LEASE_MILLIS: u64 = 30_000;
+
 /// Configuration for the `KafkaConsumer`.
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[serde(default)]
@@ -94,6 +111,8 @@ pub struct ConsumerConfig {
     pub heartbeat_interval: u64,
 
     /// The maximum number of records returned in a single call to poll().
+    ///
+    /// The records stream yields to the other tasks after returning so many records in a row.
     #[serde(rename = "max.poll.records")]
     pub max_poll_records: usize,
 
@@ -102,6 +121,11 @@ pub struct ConsumerConfig {
     #[serde(rename = "partition.assignment.strategy")]
     pub assignment_strategy: Vec<AssignmentStrategy>,
 
+    /// Name of partition assignment strategy to retry joining the group with,
+    /// when the coordinator rejects the configured strategies.
+    #[serde(rename = "partition.assignment.strategy.fallback")]
+    pub assignment_strategy_fallback: Vec<AssignmentStrategy>,
+
     /// The timeout used to detect consumer failures when using Kafka's group management
     /// facility.
     ///
@@ -129,8 +153,8 @@ pub struct ConsumerConfig {
     ///
     /// - earliest: automatically reset the offset to the earliest offset
     /// - latest: automatically reset the offset to the latest offset
-    /// - none: throw exception to the consumer if no previous offset is found for the
-    /// consumer's group
+    /// - none (or error): fail the consumer with `NoOffsetForPartition` if no previous offset is found
+    /// for the consumer's group, or the fetched offset is out of range
     #[serde(rename = "auto.offset.reset")]
     pub auto_offset_reset: OffsetResetStrategy,
 
@@ -177,6 +201,40 @@ pub struct ConsumerConfig {
     /// (broker config) or `max.message.bytes` (topic config).
     #[serde(rename = "max.partition.fetch.bytes")]
     pub partition_fetch_bytes: usize,
+
+    /// The maximum number of the fetched records buffered by the consumer.
+    ///
+    /// The next fetch is issued ahead of the consumption while the buffered records are fewer than this limit,
+    /// so the records stream doesn't wait for the network when the buffer has been drained.
+    /// The prefetching is disabled if it is zero.
+    #[serde(rename = "queued.max.messages")]
+    pub queued_max_messages: usize,
+
+    /// The state file shared by the consumers without a group to own the partitions exclusively.
+    ///
+    /// Each consumer only consumes the partitions it claimed in the file,
+    /// so the instances of a groupless consumer fleet don't consume the same partitions.
+    #[serde(rename = "partition.ownership.file")]
+    pub partition_ownership_file: Option<String>,
+
+    /// The lease in milliseconds of the partitions claimed in `partition.ownership.file`.
+    ///
+    /// The lease is renewed periodically, the partitions of a consumer failed to renew
+    /// will be taken over by the others once its lease expired.
+    #[serde(rename = "partition.ownership.lease.ms")]
+    pub partition_ownership_lease: u64,
+
+    /// Record the end-to-end latency of the consumed records, from the record timestamp to now.
+    ///
+    /// The latency is exported as a histogram per topic when the `metrics` are enabled.
+    #[serde(rename = "metrics.end.to.end.latency")]
+    pub end_to_end_latency: bool,
+
+    /// A rack identifier for this client.
+    ///
+    /// The rack-aware replica selector will fetch from the in-sync replica in the same rack.
+    #[serde(rename = "client.rack")]
+    pub client_rack: Option<String>,
 }
 
 impl Deref for ConsumerConfig {
@@ -203,6 +261,7 @@ impl Default for ConsumerConfig {
             heartbeat_interval: DEFAULT_HEARTBEAT_INTERVAL_MILLIS,
             max_poll_records: DEFAULT_MAX_POLL_RECORDS,
             assignment_strategy: vec![AssignmentStrategy::Range, AssignmentStrategy::RoundRobin],
+            assignment_strategy_fallback: vec![AssignmentStrategy::Range],
             session_timeout: DEFAULT_SESSION_TIMEOUT_MILLIS,
             rebalance_timeout: DEFAULT_REBALANCE_TIMEOUT_MILLIS,
             auto_offset_reset: OffsetResetStrategy::default(),
@@ -211,6 +270,11 @@ impl Default for ConsumerConfig {
             fetch_max_wait: DEFAULT_FETCH_MAX_WAIT_MILLIS,
             fetch_error_backoff: DEFAULT_FETCH_ERROR_BACKOFF_MILLIS,
             partition_fetch_bytes: DEFAULT_PARTITION_FETCH_BYTES,
+            queued_max_messages: DEFAULT_QUEUED_MAX_MESSAGES,
+            partition_ownership_file: None,
+            partition_ownership_lease: DEFAULT_PARTITION_OWNERSHIP_LEASE_MILLIS,
+            end_to_end_latency: false,
+            client_rack: None,
         }
     }
 }
@@ -255,6 +319,24 @@ impl ConsumerConfig {
         Duration::from_millis(self.rebalance_timeout)
     }
 
+    /// The configuration of the client used by the consumer.
+    ///
+    /// The `JoinGroup` request may block for the `max.poll.interval.ms` while the group is rebalancing,
+    /// so its timeout is extended to cover the rebalance unless it was configured.
+    pub fn client_config(&self) -> ClientConfig {
+        let mut config = self.client.clone();
+        let timeout = cmp::max(
+            config.request_timeout,
+            self.rebalance_timeout + JOIN_GROUP_TIMEOUT_MARGIN_MILLIS,
+        );
+
+        config
+            .request_timeouts
+            .entry(ApiKeys::JoinGroup.name().to_owned())
+            .or_insert(timeout);
+        config
+    }
+
     /// The maximum amount of time the server will block before answering the fetch request
     /// if there isn't sufficient data to immediately satisfy the requirement given by
     /// `fetch.min.bytes`.
@@ -267,6 +349,11 @@ impl ConsumerConfig {
     pub fn fetch_error_backoff(&self) -> Duration {
         Duration::from_millis(self.fetch_error_backoff)
     }
+
+    /// The lease of the partitions claimed in the partition ownership file.
+    pub fn partition_ownership_lease(&self) -> Duration {
+        Duration::from_millis(self.partition_ownership_lease)
+    }
 }
 
 #[cfg(test)]
@@ -299,6 +386,10 @@ mod tests {
             config.fetch_max_wait(),
             Duration::from_millis(DEFAULT_FETCH_MAX_WAIT_MILLIS)
         );
+        assert_eq!(
+            config.client_config().api_request_timeout(ApiKeys::JoinGroup),
+            Duration::from_millis(DEFAULT_REBALANCE_TIMEOUT_MILLIS + JOIN_GROUP_TIMEOUT_MARGIN_MILLIS)
+        );
     }
 
     #[test]
@@ -309,13 +400,38 @@ mod tests {
     "bootstrap.servers": [],
     "client.id": null,
     "connection.max.idle.ms": 5000,
+    "max.in.flight.requests.per.connection": 5,
     "request.timeout.ms": 30000,
+    "request.timeouts.ms": {},
     "api.version.request": false,
     "broker.version.fallback": "0.9.0",
     "metadata.max.age.ms": 300000,
+    "metadata.bootstrap.retries": 5,
+    "topic.metadata.refresh.interval.ms": 300000,
+    "dns.refresh.interval.ms": 60000,
+    "check.crcs": "fail",
+    "socket.nodelay": true,
+    "socket.send.buffer.bytes": null,
+    "socket.receive.buffer.bytes": null,
+    "socket.keepalive.ms": null,
+    "queue.buffering.max.requests": 0,
+    "queue.full.behavior": "fail",
     "metrics": false,
+    "warm.up.topics": [],
     "retries": 0,
-    "retry.backoff.ms": 100
+    "retry.backoff.ms": 100,
+    "retry.policies": {
+      "retriable": null,
+      "refresh.metadata": null
+    },
+    "reconnect.backoff.ms": 50,
+    "reconnect.backoff.max.ms": 1000,
+    "sasl.mechanism": "PLAIN",
+    "sasl.username": null,
+    "ssl.enabled": false,
+    "ssl.ca.location": null,
+    "ssl.keystore.location": null,
+    "ssl.endpoint.identification.enabled": true
   },
   "group.id": null,
   "enable.auto.commit": true,
@@ -326,6 +442,9 @@ mod tests {
     "range",
     "roundrobin"
   ],
+  "partition.assignment.strategy.fallback": [
+    "range"
+  ],
   "session.timeout.ms": 10000,
   "max.poll.interval.ms": 300000,
   "auto.offset.reset": "latest",
@@ -333,7 +452,12 @@ mod tests {
   "fetch.max.bytes": 52428800,
   "fetch.max.wait.ms": 500,
   "fetch.error.backoff.ms": 500,
-  "max.partition.fetch.bytes": 1048576
+  "max.partition.fetch.bytes": 1048576,
+  "queued.max.messages": 100000,
+  "partition.ownership.file": null,
+  "partition.ownership.lease.ms": 30000,
+  "metrics.end.to.end.latency": false,
+  "client.rack": null
 }"#;
 
         assert_eq!(serde_json::to_string_pretty(&config).unwrap(), json);
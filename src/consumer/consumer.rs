@@ -1,17 +1,25 @@
 use std::borrow::Cow;
 use std::cell::RefCell;
 use std::hash::Hash;
-use std::rc::Rc;
+use std::iter;
+use std::process;
+use std::rc::{Rc, Weak};
 use std::ops::Deref;
 
+use futures::future::{self, Loop};
 use futures::{Future, Stream};
+use rand;
+use regex::Regex;
 use tokio_core::reactor::Handle;
 
-use client::{Client, Cluster, KafkaClient, StaticBoxFuture, ToStaticBoxFuture};
-use consumer::{ConsumerBuilder, ConsumerConfig, ConsumerCoordinator, Fetcher, SubscribedTopics, Subscriptions};
-use errors::{Error, ErrorKind};
+use client::{Client, Cluster, KafkaClient, Metadata, ShutdownReport, StaticBoxFuture, ToStaticBoxFuture, TypedTopic};
+use consumer::{ConsumerBuilder, ConsumerConfig, ConsumerCoordinator, ConsumerInterceptor, EventDispatcher, Events,
+               Fetcher, Interceptors, OffsetResetStrategy, PartitionOwnership, ReplicaSelector, SubscribedTopics,
+               Subscriptions};
+use errors::{Error, ErrorKind, Result};
+use network::TopicPartition;
 use protocol::{MessageTimestamp, Offset, PartitionId};
-use serialization::Deserializer;
+use serialization::{DeserializeError, Deserializer};
 
 /// A trait for consuming records from a Kafka cluster.
 pub trait Consumer<'a> {
@@ -28,6 +36,23 @@ pub trait Consumer<'a> {
     where
         I: IntoIterator<Item = S>,
         S: Into<String>;
+
+    /// Subscribe to the typed topic, which key and value types must match the deserializers.
+    fn subscribe_typed(&mut self, topic: &TypedTopic<Self::Key, Self::Value>) -> Subscribe<Self::Topics> {
+        self.subscribe(iter::once(topic.name()))
+    }
+
+    /// Subscribe to all the topics matching the pattern to get dynamically assigned partitions.
+    ///
+    /// The pattern is evaluated against the topics of the cluster on every metadata refresh,
+    /// the newly created topics matching the pattern are subscribed, and the group will be rebalanced.
+    fn subscribe_pattern(&mut self, pattern: Regex) -> Subscribe<Self::Topics>;
+
+    /// Manually assign the list of partitions to this consumer, without the group management.
+    ///
+    /// The partitions will never be rebalanced, while the offsets could still be committed
+    /// to the group if the `group.id` has been configured.
+    fn assign(&mut self, partitions: Vec<TopicPartition<'a>>) -> Subscribe<Self::Topics>;
 }
 
 /// A key/value pair to be received from Kafka.
@@ -45,28 +70,60 @@ pub struct ConsumerRecord<'a, K, V> {
     pub offset: Offset,
     /// The key (or None if no key is specified)
     pub key: Option<K>,
-    /// The value
+    /// The value (or None if the value is null, e.g. a tombstone, or failed to deserialize)
+    ///
+    /// An empty value is still deserialized, which is different from a null value.
     pub value: Option<V>,
     /// The timestamp of this record
     pub timestamp: Option<MessageTimestamp>,
+    /// The error of deserializing the key or value (or None if both have been deserialized)
+    pub deserialize_error: Option<DeserializeError>,
+}
+
+impl<'a, K, V> ConsumerRecord<'a, K, V> {
+    /// Returns whether the record is a tombstone, with a key and a null value
+    ///
+    /// The tombstone marks the key as deleted in a compacted topic,
+    /// a value which failed to deserialize is not a tombstone.
+    pub fn is_tombstone(&self) -> bool {
+        self.key.is_some() && self.value.is_none()
+            && self.deserialize_error
+                .as_ref()
+                .map_or(true, |err| err.value.is_none())
+    }
 }
 
 pub type Subscribe<T> = StaticBoxFuture<T>;
 
 /// A Kafka consumer that consumes records from a Kafka cluster.
 #[derive(Clone)]
-pub struct KafkaConsumer<'a, K, V> {
+pub struct KafkaConsumer<'a, K, V>
+where
+    K: Deserializer,
+    V: Deserializer,
+{
     inner: Rc<Inner<'a, K, V>>,
 }
 
-struct Inner<'a, K, V> {
+struct Inner<'a, K, V>
+where
+    K: Deserializer,
+    V: Deserializer,
+{
     client: KafkaClient<'a>,
     config: ConsumerConfig,
     key_deserializer: K,
     value_deserializer: V,
+    interceptors: Interceptors<'a, K::Item, V::Item>,
+    replica_selector: Rc<ReplicaSelector>,
+    events: EventDispatcher<'a>,
 }
 
-impl<'a, K, V> Deref for KafkaConsumer<'a, K, V> {
+impl<'a, K, V> Deref for KafkaConsumer<'a, K, V>
+where
+    K: Deserializer,
+    V: Deserializer,
+{
     type Target = KafkaClient<'a>;
 
     fn deref(&self) -> &Self::Target {
@@ -74,19 +131,48 @@ impl<'a, K, V> Deref for KafkaConsumer<'a, K, V> {
     }
 }
 
-impl<'a, K, V> KafkaConsumer<'a, K, V> {
+impl<'a, K, V> KafkaConsumer<'a, K, V>
+where
+    K: Deserializer,
+    V: Deserializer,
+    Self: 'static,
+{
     /// Construct a `KafkaConsumer`
-    pub fn new(client: KafkaClient<'a>, config: ConsumerConfig, key_deserializer: K, value_deserializer: V) -> Self {
+    pub fn new(
+        client: KafkaClient<'a>,
+        config: ConsumerConfig,
+        key_deserializer: K,
+        value_deserializer: V,
+        interceptors: Interceptors<'a, K::Item, V::Item>,
+        replica_selector: Rc<ReplicaSelector>,
+    ) -> Self {
+        let events = EventDispatcher::new(client.metrics());
+
+        if let Some(ref interceptors) = interceptors {
+            let interceptors = interceptors.clone();
+
+            events.on_commit(move |offsets| interceptors.borrow().on_commit(offsets));
+        }
+
         KafkaConsumer {
             inner: Rc::new(Inner {
                 client,
                 config,
                 key_deserializer,
                 value_deserializer,
+                interceptors,
+                replica_selector,
+                events,
             }),
         }
     }
+}
 
+impl<'a, K, V> KafkaConsumer<'a, K, V>
+where
+    K: Deserializer,
+    V: Deserializer,
+{
     /// Construct a `ConsumerBuilder` from ConsumerConfig
     pub fn with_config(config: ConsumerConfig, handle: Handle) -> ConsumerBuilder<'a, K, V> {
         ConsumerBuilder::with_config(config, handle)
@@ -104,11 +190,32 @@ impl<'a, K, V> KafkaConsumer<'a, K, V> {
     pub fn config(&self) -> &ConsumerConfig {
         &self.inner.config
     }
+
+    /// Get a stream of the group events, e.g. joins, revocations and coordinator changes.
+    ///
+    /// The rebalance metrics will be recorded if the consumer was built `with_metrics`.
+    pub fn events(&self) -> Events<'a> {
+        self.inner.events.subscribe()
+    }
+
+    /// The dispatcher of the group events and commit hooks.
+    pub fn event_dispatcher(&self) -> &EventDispatcher<'a> {
+        &self.inner.events
+    }
+
+    /// Intercept the record before it is returned to the application.
+    pub fn intercept(&self, record: ConsumerRecord<'a, K::Item, V::Item>) -> ConsumerRecord<'a, K::Item, V::Item> {
+        match self.inner.interceptors {
+            Some(ref interceptors) => interceptors.borrow().on_consume(record),
+            None => record,
+        }
+    }
 }
 
 impl<'a, K, V> KafkaConsumer<'a, K, V>
 where
     K: Deserializer + Clone,
+    V: Deserializer,
 {
     pub fn key_deserializer(&self) -> K {
         self.inner.key_deserializer.clone()
@@ -117,6 +224,7 @@ where
 
 impl<'a, K, V> KafkaConsumer<'a, K, V>
 where
+    K: Deserializer,
     V: Deserializer + Clone,
 {
     pub fn value_deserializer(&self) -> V {
@@ -141,6 +249,59 @@ where
         S: Into<String>,
     {
         let topic_names: Vec<String> = topic_names.into_iter().map(|s| s.into()).collect();
+
+        self.subscribe_with(move |metadata, default_reset_strategy| {
+            let topics = metadata.topics();
+
+            if let Some(not_found) = topic_names
+                .iter()
+                .find(|topic_name| !topics.contains_key(topic_name.as_str()))
+            {
+                bail!(ErrorKind::TopicNotFound(not_found.clone()))
+            }
+
+            Ok(Subscriptions::with_topics(topic_names, default_reset_strategy))
+        })
+    }
+
+    fn subscribe_pattern(&mut self, pattern: Regex) -> Subscribe<Self::Topics> {
+        self.subscribe_with(move |metadata, default_reset_strategy| {
+            let mut subscriptions = Subscriptions::new(default_reset_strategy);
+
+            subscriptions.subscribe_pattern(pattern);
+            subscriptions.subscribe_from_pattern(metadata);
+
+            Ok(subscriptions)
+        })
+    }
+
+    fn assign(&mut self, partitions: Vec<TopicPartition<'a>>) -> Subscribe<Self::Topics> {
+        self.subscribe_with(move |metadata, default_reset_strategy| {
+            if let Some(not_found) = partitions.iter().find(|tp| metadata.find_partition(tp).is_none()) {
+                bail!(ErrorKind::IllegalArgument(format!("unknown partition {}", not_found)))
+            }
+
+            let mut subscriptions = Subscriptions::new(default_reset_strategy);
+
+            subscriptions.assign_from_user(partitions);
+
+            Ok(subscriptions)
+        })
+    }
+}
+
+impl<'a, K, V> KafkaConsumer<'a, K, V>
+where
+    K: Deserializer + Clone,
+    K::Item: Hash,
+    V: Deserializer + Clone,
+    Self: 'static,
+{
+    /// Subscribe with the subscriptions built from the metadata of the cluster.
+    fn subscribe_with<F>(&mut self, subscribe: F) -> Subscribe<SubscribedTopics<'a, K, V>>
+    where
+        F: 'static + FnOnce(&Metadata, OffsetResetStrategy) -> Result<Subscriptions<'a>>,
+    {
         let inner = self.inner.clone();
         let default_reset_strategy = self.inner.config.auto_offset_reset;
         let group_id = self.inner.config.group_id.clone();
@@ -158,25 +319,45 @@ where
             .iter()
             .flat_map(|strategy| strategy.assignor())
             .collect();
-        let timer = self.inner.client.timer().clone();
+        let fallback_assignors = self.inner
+            .config
+            .assignment_strategy_fallback
+            .iter()
+            .flat_map(|strategy| strategy.assignor())
+            .collect();
+        let runtime = self.inner.client.runtime().clone();
+        // the consumers without a group own the partitions exclusively in the shared state file
+        let ownership = match (&group_id, &self.inner.config.partition_ownership_file) {
+            (&None, &Some(ref path)) => {
+                let owner = format!(
+                    "{}-{}-{:08x}",
+                    self.inner.config.client_id.as_ref().map_or("consumer", |s| s.as_str()),
+                    process::id(),
+                    rand::random::<u32>()
+                );
+
+                Some(PartitionOwnership::new(
+                    path,
+                    owner,
+                    self.inner.config.partition_ownership_lease(),
+                ))
+            }
+            _ => None,
+        };
 
         self.inner
             .client
             .metadata()
             .and_then(move |metadata| {
-                let topics = metadata.topics();
+                let subscriptions = Rc::new(RefCell::new(subscribe(&metadata, default_reset_strategy)?));
+                let user_assigned = subscriptions.borrow().has_user_assignment();
 
-                if let Some(not_found) = topic_names
-                    .iter()
-                    .find(|topic_name| !topics.contains_key(topic_name.as_str()))
-                {
-                    bail!(ErrorKind::TopicNotFound(not_found.clone()))
-                }
+                // the partitions assigned by the user are not claimed from the shared state file
+                if let (Some(ownership), false) = (ownership, user_assigned) {
+                    claim_partitions(&ownership, &metadata, &subscriptions)?;
 
-                let subscriptions = Rc::new(RefCell::new(Subscriptions::with_topics(
-                    topic_names,
-                    default_reset_strategy,
-                )));
+                    renew_partition_ownership(inner.client.clone(), ownership, Rc::downgrade(&subscriptions));
+                }
 
                 let coordinator = group_id.map(|group_id| {
                     ConsumerCoordinator::new(
@@ -189,7 +370,8 @@ where
                         None,
                         auto_commit_interval,
                         assignors,
-                        timer.clone(),
+                        fallback_assignors,
+                        inner.events.clone(),
                     )
                 });
 
@@ -200,10 +382,124 @@ where
                     fetch_max_bytes,
                     fetch_max_wait,
                     partition_fetch_bytes,
+                    inner.replica_selector.clone(),
                 ));
 
-                SubscribedTopics::new(KafkaConsumer { inner }, subscriptions, coordinator, fetcher, timer)
+                SubscribedTopics::new(KafkaConsumer { inner }, subscriptions, coordinator, fetcher, runtime)
             })
             .static_boxed()
     }
 }
+
+/// Claim the partitions of the subscribed topics, and assign the owned partitions to the consumer.
+fn claim_partitions<'a>(
+    ownership: &PartitionOwnership,
+    metadata: &Metadata,
+    subscriptions: &RefCell<Subscriptions<'a>>,
+) -> Result<()> {
+    let partitions = subscriptions
+        .borrow()
+        .topics()
+        .into_iter()
+        .flat_map(|topic_name| metadata.partitions_for_topic(topic_name).unwrap_or_default())
+        .map(|tp| topic_partition!(tp.topic_name.into_owned(), tp.partition_id))
+        .collect::<Vec<_>>();
+
+    let owned = ownership.claim(partitions)?;
+
+    debug!("`{}` owns partitions: {:?}", ownership.owner(), owned);
+
+    subscriptions.borrow_mut().assign_from_subscribed(owned)
+}
+
+/// Renew the lease of the owned partitions until the consumer has been dropped, then release them.
+fn renew_partition_ownership<'a>(
+    client: KafkaClient<'a>,
+    ownership: PartitionOwnership,
+    subscriptions: Weak<RefCell<Subscriptions<'a>>>,
+) where
+    KafkaClient<'a>: 'static,
+{
+    let runtime = client.runtime().clone();
+    let interval = ownership.lease() / 3;
+
+    client.register_shutdown_hook({
+        let ownership = ownership.clone();
+
+        move || {
+            future::result(ownership.release())
+                .map(|_| ShutdownReport::default())
+                .static_boxed()
+        }
+    });
+
+    let renew = future::loop_fn((), {
+        let runtime = runtime.clone();
+
+        move |_| {
+            let client = client.clone();
+            let ownership = ownership.clone();
+            let subscriptions = subscriptions.clone();
+
+            runtime
+                .sleep(interval)
+                .and_then(move |_| client.metadata())
+                .then(move |res| match subscriptions.upgrade() {
+                    Some(subscriptions) => {
+                        let claimed = res.and_then(|metadata| claim_partitions(&ownership, &metadata, &subscriptions));
+
+                        if let Err(err) = claimed {
+                            warn!("fail to renew the partition ownership of `{}`, {}", ownership.owner(), err);
+                        }
+
+                        Ok(Loop::Continue(()))
+                    }
+                    None => {
+                        if let Err(err) = ownership.release() {
+                            warn!("fail to release the partitions of `{}`, {}", ownership.owner(), err);
+                        }
+
+                        Ok(Loop::Break(()))
+                    }
+                })
+        }
+    });
+
+    runtime.spawn(renew.map_err(|_: Error| ()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tombstone() {
+        let record = ConsumerRecord {
+            topic_name: Cow::from("topic"),
+            partition_id: 0,
+            offset: 123,
+            key: Some("key"),
+            value: None,
+            timestamp: None,
+            deserialize_error: None,
+        };
+
+        assert!(record.is_tombstone());
+
+        // an empty value is not a null value
+        assert!(!ConsumerRecord {
+            value: Some(""),
+            ..record.clone()
+        }.is_tombstone());
+
+        assert!(!ConsumerRecord {
+            deserialize_error: Some(DeserializeError {
+                key: None,
+                value: Some("invalid value".to_owned()),
+            }),
+            ..record.clone()
+        }.is_tombstone());
+
+        assert!(!ConsumerRecord { key: None, ..record }.is_tombstone());
+    }
+}
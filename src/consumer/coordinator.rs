@@ -3,20 +3,19 @@ use std::collections::{HashMap, HashSet};
 use std::iter::FromIterator;
 use std::mem;
 use std::rc::Rc;
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
-use futures::future::Either;
+use futures::future::{Either, Loop};
 use futures::{future, Future, Stream};
-use tokio_retry::{Retry, Error as RetryError};
-use tokio_timer::Timer;
 
 use client::{BrokerRef, Client, Cluster, ConsumerGroupAssignment, ConsumerGroupMember, ConsumerGroupProtocol,
              Generation, JoinGroup as JoinConsumerGroup, Metadata, OffsetCommit, OffsetFetch, StaticBoxFuture,
              ToStaticBoxFuture};
-use consumer::{Assignment, PartitionAssignor, Subscription, Subscriptions, CONSUMER_PROTOCOL};
+use consumer::{Assignment, EventDispatcher, PartitionAssignor, RebalanceProtocol, Subscription, Subscriptions,
+               CONSUMER_PROTOCOL};
 use errors::{Error, ErrorKind, Result, ResultExt};
 use network::{OffsetAndMetadata, TopicPartition};
-use protocol::{KafkaCode, Schema, ToMilliseconds};
+use protocol::{GenerationId, KafkaCode, Schema, ToMilliseconds};
 
 /// Manages the coordination process with the consumer coordinator.
 pub trait Coordinator<'a> {
@@ -32,6 +31,15 @@ pub trait Coordinator<'a> {
     where
         I: 'static + IntoIterator<Item = (TopicPartition<'a>, OffsetAndMetadata)>;
 
+    /// Commit the offsets with the generation and member id of the group metadata,
+    /// e.g. taken before processing the records.
+    ///
+    /// The coordinator rejects the partitions with `IllegalGeneration` or `UnknownMemberId`
+    /// if the group has been rebalanced since then, which fences the zombie members.
+    fn commit_offsets_with<I>(&self, group_metadata: &ConsumerGroupMetadata, offsets: I) -> CommitOffset
+    where
+        I: 'static + IntoIterator<Item = (TopicPartition<'a>, OffsetAndMetadata)>;
+
     /// Refresh the committed offsets for provided partitions.
     fn update_offsets(&self) -> UpdateOffsets;
 
@@ -50,6 +58,46 @@ pub type UpdateOffsets = StaticBoxFuture;
 
 pub type FetchOffsets = OffsetFetch;
 
+/// The metadata of the consumer group member, which identifies the generation of the group
+/// to fence the zombie members when committing the offsets.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConsumerGroupMetadata {
+    /// The group id.
+    pub group_id: String,
+
+    /// The generation of the consumer group, or -1 if the consumer has not joined the group.
+    pub generation_id: GenerationId,
+
+    /// The member id assigned by the group coordinator, or empty if the consumer has not joined the group.
+    pub member_id: String,
+
+    /// The static member id provided by the user, the static membership is not supported yet.
+    pub group_instance_id: Option<String>,
+}
+
+impl ConsumerGroupMetadata {
+    /// The metadata of a consumer which has not joined the group.
+    pub fn new(group_id: String) -> Self {
+        ConsumerGroupMetadata {
+            group_id,
+            generation_id: -1,
+            member_id: String::new(),
+            group_instance_id: None,
+        }
+    }
+}
+
+impl<'a> From<&'a Generation> for ConsumerGroupMetadata {
+    fn from(generation: &Generation) -> Self {
+        ConsumerGroupMetadata {
+            group_id: generation.group_id.clone(),
+            generation_id: generation.generation_id,
+            member_id: generation.member_id.clone(),
+            group_instance_id: None,
+        }
+    }
+}
+
 /// Manages the coordination process with the consumer coordinator.
 pub struct ConsumerCoordinator<'a, C> {
     inner: Rc<Inner<'a, C>>,
@@ -64,9 +112,10 @@ struct Inner<'a, C> {
     heartbeat_interval: Duration,
     retention_time: Option<Duration>,
     auto_commit_interval: Option<Duration>,
-    assignors: Vec<Box<PartitionAssignor>>,
+    assignors: RefCell<Vec<Box<PartitionAssignor>>>,
+    fallback_assignors: RefCell<Vec<Box<PartitionAssignor>>>,
     state: Rc<RefCell<State>>,
-    timer: Rc<Timer>,
+    events: EventDispatcher<'a>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -83,6 +132,11 @@ enum State {
         coordinator: BrokerRef,
         generation: Generation,
     },
+    /// the client commits offsets of the partitions assigned by the user, without joining the group
+    Standalone {
+        coordinator: BrokerRef,
+        generation: Generation,
+    },
 }
 
 impl State {
@@ -115,6 +169,15 @@ impl State {
             State::Stable { ref generation, .. } | State::Rebalancing { ref generation, .. } => {
                 Some(generation.member_id.clone())
             }
+            State::Unjoined | State::Standalone { .. } => None,
+        }
+    }
+
+    pub fn generation(&self) -> Option<&Generation> {
+        match *self {
+            State::Stable { ref generation, .. }
+            | State::Rebalancing { ref generation, .. }
+            | State::Standalone { ref generation, .. } => Some(generation),
             State::Unjoined => None,
         }
     }
@@ -139,6 +202,16 @@ impl State {
         )
     }
 
+    pub fn standalone(&mut self, coordinator: BrokerRef, generation: Generation) -> Self {
+        mem::replace(
+            self,
+            State::Standalone {
+                coordinator,
+                generation,
+            },
+        )
+    }
+
     pub fn leaved(&mut self) -> Self {
         mem::replace(self, State::Unjoined)
     }
@@ -155,7 +228,8 @@ impl<'a, C> ConsumerCoordinator<'a, C> {
         retention_time: Option<Duration>,
         auto_commit_interval: Option<Duration>,
         assignors: Vec<Box<PartitionAssignor>>,
-        timer: Rc<Timer>,
+        fallback_assignors: Vec<Box<PartitionAssignor>>,
+        events: EventDispatcher<'a>,
     ) -> Self {
         ConsumerCoordinator {
             inner: Rc::new(Inner {
@@ -167,8 +241,9 @@ impl<'a, C> ConsumerCoordinator<'a, C> {
                 heartbeat_interval,
                 retention_time,
                 auto_commit_interval,
-                assignors,
-                timer,
+                assignors: RefCell::new(assignors),
+                fallback_assignors: RefCell::new(fallback_assignors),
+                events,
                 state: Rc::new(RefCell::new(State::Unjoined)),
             }),
         }
@@ -187,13 +262,19 @@ where
             .iter()
             .map(|topic_name| String::from(*topic_name))
             .collect();
+        let mut owned_partitions = self.subscriptions.borrow().assigned_partitions();
+
+        owned_partitions.sort();
 
         self.assignors
+            .borrow()
             .iter()
             .flat_map(move |assignor| {
-                let subscription =
+                let mut subscription =
                     assignor.subscription(topics.iter().map(|topic_name| topic_name.as_str().into()).collect());
 
+                subscription.owned_partitions = owned_partitions.clone();
+
                 Schema::serialize(&subscription)
                     .chain_err(|| "fail to serialize subscription schema")
                     .ok()
@@ -214,7 +295,8 @@ where
         let strategy = group_protocol
             .parse()
             .chain_err(|| format!("fail to parse group protocol: {}", group_protocol))?;
-        let assignor = self.assignors
+        let assignors = self.assignors.borrow();
+        let assignor = assignors
             .iter()
             .find(|assigner| assigner.strategy() == strategy)
             .ok_or_else(|| ErrorKind::UnsupportedAssignmentStrategy(group_protocol.to_owned()))?;
@@ -288,7 +370,33 @@ where
         Ok(group_assignment)
     }
 
-    fn synced_group(&self, assignment: Assignment<'a>, coordinator: BrokerRef, generation: Generation) -> Result<()> {
+    /// The rebalancing protocol of the assignor selected by the group.
+    fn rebalance_protocol(&self, group_protocol: &str) -> RebalanceProtocol {
+        group_protocol
+            .parse()
+            .ok()
+            .and_then(|strategy| {
+                self.assignors
+                    .borrow()
+                    .iter()
+                    .find(|assignor| assignor.strategy() == strategy)
+                    .map(|assignor| assignor.rebalance_protocol())
+            })
+            .unwrap_or(RebalanceProtocol::Eager)
+    }
+
+    /// Apply the assignment of the group, returns `true` if the member should rejoin the group.
+    ///
+    /// With the cooperative protocol, only the partitions not in the new assignment are revoked,
+    /// and the member rejoins the group to get the revoked partitions assigned to the other members.
+    fn synced_group(
+        &self,
+        assignment: Assignment<'a>,
+        coordinator: BrokerRef,
+        generation: Generation,
+        leader: bool,
+        protocol: RebalanceProtocol,
+    ) -> Result<bool> {
         trace!(
             "member `{}` synced up to generation # {} with {} partitions: {:?}",
             generation.member_id,
@@ -297,14 +405,36 @@ where
             assignment.partitions
         );
 
+        let owned = self.subscriptions.borrow().assigned_partitions();
+        let revoked = match protocol {
+            RebalanceProtocol::Eager => owned,
+            RebalanceProtocol::Cooperative => owned
+                .into_iter()
+                .filter(|tp| !assignment.partitions.contains(tp))
+                .collect(),
+        };
+        let rejoin = protocol == RebalanceProtocol::Cooperative && !revoked.is_empty();
+        let assigned = assignment.partitions.clone();
+
         self.subscriptions
             .borrow_mut()
             .assign_from_subscribed(assignment.partitions)
             .chain_err(|| "fail to assign subscribed partitions")?;
 
-        self.state.borrow_mut().joined(coordinator, generation);
+        self.events.synced(&generation, leader, revoked, assigned);
 
-        Ok(())
+        if rejoin {
+            debug!(
+                "member `{}` revoked the partitions migrated to the other members, rejoin the `{}` group",
+                generation.member_id, generation.group_id
+            );
+
+            self.state.borrow_mut().rebalancing(coordinator, generation);
+        } else {
+            self.state.borrow_mut().joined(coordinator, generation);
+        }
+
+        Ok(rejoin)
     }
 
     fn heartbeat(&self, coordinator: BrokerRef, generation: Generation) -> Result<()> {
@@ -317,9 +447,9 @@ where
         let client = self.client.clone();
         let state = self.state.clone();
 
-        let heartbeat = self.timer
-            .interval_at(Instant::now() + self.heartbeat_interval, self.heartbeat_interval)
-            .from_err()
+        let heartbeat = self.client
+            .runtime()
+            .interval(self.heartbeat_interval, self.heartbeat_interval)
             .for_each(move |_| {
                 let client = client.clone();
                 let state = state.clone();
@@ -332,9 +462,21 @@ where
                 if matched {
                     let send_heartbeat = {
                         let generation = generation.clone();
+                        let runtime = client.runtime().clone();
 
-                        Retry::spawn(client.retry_strategy(), move || {
-                            client.heartbeat(coordinator, generation.clone())
+                        future::loop_fn(client.retry_strategy().into_iter(), move |mut backoffs| {
+                            let runtime = runtime.clone();
+
+                            client
+                                .heartbeat(coordinator, generation.clone())
+                                .then(move |res| match (res, backoffs.next()) {
+                                    (Err(err), Some(backoff)) => {
+                                        debug!("fail to send heartbeat, retry after {:?}, {}", backoff, err);
+
+                                        Either::A(runtime.sleep(backoff).map(move |_| Loop::Continue(backoffs)))
+                                    }
+                                    (res, _) => Either::B(future::result(res.map(Loop::Break))),
+                                })
                         })
                     };
 
@@ -342,27 +484,24 @@ where
 
                     Either::A(send_heartbeat.map_err(move |err| {
                         match err {
-                            RetryError::OperationError(ref err) => match *err {
-                                Error(ErrorKind::KafkaError(KafkaCode::CoordinatorLoadInProgress), _)
-                                | Error(ErrorKind::KafkaError(KafkaCode::RebalanceInProgress), _) => {
-                                    info!("group is loading or rebalancing, {}", err);
+                            Error(ErrorKind::KafkaError(KafkaCode::CoordinatorLoadInProgress), _)
+                            | Error(ErrorKind::KafkaError(KafkaCode::RebalanceInProgress), _) => {
+                                info!("group is loading or rebalancing, {}", err);
 
-                                    state.borrow_mut().rebalancing(coordinator, generation.clone());
-                                }
-                                Error(ErrorKind::KafkaError(KafkaCode::CoordinatorNotAvailable), _)
-                                | Error(ErrorKind::KafkaError(KafkaCode::NotCoordinator), _)
-                                | Error(ErrorKind::KafkaError(KafkaCode::IllegalGeneration), _)
-                                | Error(ErrorKind::KafkaError(KafkaCode::UnknownMemberId), _) => {
-                                    info!("group has outdated, need to rejoin, {}", err);
+                                state.borrow_mut().rebalancing(coordinator, generation.clone());
+                            }
+                            Error(ErrorKind::KafkaError(KafkaCode::CoordinatorNotAvailable), _)
+                            | Error(ErrorKind::KafkaError(KafkaCode::NotCoordinator), _)
+                            | Error(ErrorKind::KafkaError(KafkaCode::IllegalGeneration), _)
+                            | Error(ErrorKind::KafkaError(KafkaCode::UnknownMemberId), _) => {
+                                info!("group has outdated, need to rejoin, {}", err);
 
-                                    state.borrow_mut().leaved();
-                                }
-                                _ => warn!("unknown error, {}", err),
-                            },
-                            RetryError::TimerError(_) => {},
+                                state.borrow_mut().leaved();
+                            }
+                            _ => warn!("unknown error, {}", err),
                         }
 
-                        err.into()
+                        err
                     }))
                 } else {
                     Either::B(future::err(ErrorKind::Canceled("group generation outdated").into()))
@@ -377,14 +516,80 @@ where
                 }
             });
 
-        self.client.handle().spawn(heartbeat);
+        self.client.runtime().spawn(heartbeat);
+
+        Ok(())
+    }
+
+    fn auto_commit(&self, coordinator: BrokerRef, generation: Generation) -> Result<()> {
+        let interval = match self.auto_commit_interval {
+            Some(interval) => interval,
+            None => return Ok(()),
+        };
+
+        debug!(
+            "auto commit offsets of the group `{}` per {} seconds",
+            generation.group_id,
+            interval.as_secs()
+        );
+
+        let client = self.client.clone();
+        let state = self.state.clone();
+        // the joined or standalone state, the auto commit will be canceled once it has been changed
+        let active = self.state.borrow().clone();
+        let subscriptions = self.subscriptions.clone();
+        let retention_time = self.retention_time;
+        let events = self.events.clone();
+
+        let auto_commit = self.client
+            .runtime()
+            .interval(interval, interval)
+            .for_each(move |_| {
+                let matched = *state.borrow() == active;
+
+                if !matched {
+                    return future::err(ErrorKind::Canceled("group generation outdated").into()).static_boxed();
+                }
+
+                // only commit the processed offsets if the application reported them,
+                // so the consumed but unprocessed records will be redelivered after a failure.
+                let offsets = subscriptions.borrow().committable_partitions();
+
+                if offsets.is_empty() {
+                    return future::ok(()).static_boxed();
+                }
+
+                let events = events.clone();
+
+                client
+                    .offset_commit(Some(coordinator), Some(generation.clone()), retention_time, offsets.clone())
+                    .map(move |committed| events.committed(offsets, &committed))
+                    .or_else(|err| -> Result<()> {
+                        warn!("fail to auto commit offsets, {}", err);
+
+                        Ok(())
+                    })
+                    .static_boxed()
+            })
+            .map_err(move |err| match err {
+                Error(ErrorKind::Canceled(reason), _) => {
+                    trace!("auto commit canceled, {}", reason);
+                }
+                _ => {
+                    warn!("auto commit failed, {}", err);
+                }
+            });
+
+        self.client.runtime().spawn(auto_commit);
 
         Ok(())
     }
 
     fn group_coordinator(&self) -> GroupCoordinator {
         match *self.state.borrow() {
-            State::Stable { coordinator, .. } | State::Rebalancing { coordinator, .. } => {
+            State::Stable { coordinator, .. }
+            | State::Rebalancing { coordinator, .. }
+            | State::Standalone { coordinator, .. } => {
                 Either::A(future::ok(coordinator))
             }
             State::Unjoined => Either::B(
@@ -406,6 +611,62 @@ where
             self.group_protocols(),
         )
     }
+
+    /// Join the consumer group, and retry with the fallback assignment strategies
+    /// if the coordinator rejects the configured strategies.
+    fn join_group_or_fallback(inner: Rc<Self>, coordinator: BrokerRef, member_id: Option<String>) -> JoinConsumerGroup {
+        inner
+            .join_group(coordinator, member_id.clone())
+            .or_else(move |err| {
+                let rejected = match *err.kind() {
+                    ErrorKind::KafkaError(code)
+                        if code == KafkaCode::InconsistentGroupProtocol || code == KafkaCode::UnsupportedVersion =>
+                    {
+                        Some(code)
+                    }
+                    _ => None,
+                };
+
+                match rejected {
+                    Some(code) if inner.fallback() => {
+                        warn!(
+                            "the `{}` group rejected the assignment strategies, {:?}, retry with the fallback: {}",
+                            inner.group_id,
+                            code,
+                            inner
+                                .assignors
+                                .borrow()
+                                .iter()
+                                .map(|assignor| assignor.name())
+                                .collect::<Vec<_>>()
+                                .join(",")
+                        );
+
+                        Either::A(inner.join_group(coordinator, member_id))
+                    }
+                    _ => Either::B(future::err(err)),
+                }
+            })
+            .static_boxed()
+    }
+
+    /// Switch to the fallback assignment strategies, return `false` if there is nothing new to try.
+    fn fallback(&self) -> bool {
+        let fallback_assignors = mem::replace(&mut *self.fallback_assignors.borrow_mut(), Vec::new());
+        let mut assignors = self.assignors.borrow_mut();
+
+        if fallback_assignors.iter().all(|fallback| {
+            assignors
+                .iter()
+                .any(|assignor| assignor.strategy() == fallback.strategy())
+        }) {
+            return false;
+        }
+
+        *assignors = fallback_assignors;
+
+        true
+    }
 }
 
 impl<'a, C> ConsumerCoordinator<'a, C>
@@ -425,11 +686,29 @@ where
         self.inner.state.borrow().is_unstable()
     }
 
+    /// The metadata of the consumer in the group, e.g. the generation and member id.
+    pub fn group_metadata(&self) -> ConsumerGroupMetadata {
+        self.inner
+            .state
+            .borrow()
+            .generation()
+            .map_or_else(|| ConsumerGroupMetadata::new(self.inner.group_id.clone()), ConsumerGroupMetadata::from)
+    }
+
     /// Discover the current coordinator for the group.
     pub fn group_coordinator(&self) -> GroupCoordinator {
         self.inner.group_coordinator()
     }
 
+    /// Request to rejoin the group on the next `join_group`, e.g. the subscription has been changed.
+    pub fn request_rejoin(&self) {
+        let mut state = self.inner.state.borrow_mut();
+
+        if let State::Stable { coordinator, generation } = state.clone() {
+            state.rebalancing(coordinator, generation);
+        }
+    }
+
     // Ensure that the group is active (i.e. joined and synced)
     fn ensure_active_group(&self) -> ActiveGroup {
         if let State::Stable {
@@ -443,6 +722,14 @@ where
             );
 
             future::ok((coordinator, generation.clone())).static_boxed()
+        } else if let State::Standalone {
+            coordinator,
+            ref generation,
+        } = *self.inner.state.borrow()
+        {
+            future::ok((coordinator, generation.clone())).static_boxed()
+        } else if self.inner.subscriptions.borrow().has_user_assignment() {
+            self.standalone_group()
         } else {
             let member_id = self.inner.state.borrow().member_id();
 
@@ -456,12 +743,45 @@ where
         }
     }
 
+    /// Use the group only to commit the offsets of the partitions assigned by the user,
+    /// the member never joins the group, so the partitions will not be rebalanced.
+    fn standalone_group(&self) -> ActiveGroup {
+        let inner = self.inner.clone();
+
+        self.inner
+            .group_coordinator()
+            .and_then(move |coordinator| -> Result<(BrokerRef, Generation)> {
+                debug!(
+                    "commit offsets of the partitions assigned by the user to the `{}` group",
+                    inner.group_id
+                );
+
+                let generation = Generation {
+                    group_id: inner.group_id.clone(),
+                    generation_id: -1,
+                    member_id: String::new(),
+                    protocol: String::new(),
+                };
+
+                inner.state.borrow_mut().standalone(coordinator, generation.clone());
+                inner.auto_commit(coordinator, generation.clone())?;
+
+                Ok((coordinator, generation))
+            })
+            .static_boxed()
+    }
+
     /// Rejoin the consumer group if need.
     fn rejoin_group(&self, member_id: Option<String>) -> RejoinGroup {
+        let this = ConsumerCoordinator {
+            inner: self.inner.clone(),
+        };
         let inner = self.inner.clone();
         let client = inner.client.clone();
         let group_id = inner.group_id.clone();
 
+        inner.events.rebalance_started();
+
         client
             .metadata()
             .join(self.inner.group_coordinator())
@@ -475,12 +795,15 @@ where
                     )
                 );
 
-                inner
-                    .join_group(coordinator, member_id)
+                inner.events.coordinator_discovered(&group_id, coordinator);
+
+                Inner::join_group_or_fallback(inner.clone(), coordinator, member_id)
                     .and_then(move |consumer_group| {
                         let generation = consumer_group.generation();
+                        let leader = consumer_group.is_leader();
+                        let protocol = inner.rebalance_protocol(&consumer_group.protocol);
 
-                        let group_assignment = if !consumer_group.is_leader() {
+                        let group_assignment = if !leader {
                             debug!(
                                 "member `{}` joined group `{}` as follower",
                                 generation.member_id, generation.group_id
@@ -500,20 +823,36 @@ where
                             }
                         };
 
+                        let synced_generation = generation.clone();
+
                         client
                             .sync_group(coordinator, generation.clone(), group_assignment)
-                            .and_then(move |assignment| {
+                            .and_then(move |assignment| -> Result<bool> {
                                 debug!("group `{}` synced up", generation.group_id);
 
-                                inner
-                                    .synced_group(
-                                        Schema::deserialize(&assignment[..])
-                                            .chain_err(|| "fail to deserialize assignment")?,
-                                        coordinator,
-                                        generation.clone(),
-                                    )
-                                    .and_then(|_| inner.heartbeat(coordinator, generation.clone()))
-                                    .map(|_| (coordinator, generation))
+                                let assignment: Assignment = Schema::deserialize(&assignment[..])
+                                    .chain_err(|| "fail to deserialize assignment")?;
+                                let rejoin = inner.synced_group(
+                                    assignment,
+                                    coordinator,
+                                    generation.clone(),
+                                    leader,
+                                    protocol,
+                                )?;
+
+                                if !rejoin {
+                                    inner.heartbeat(coordinator, generation.clone())?;
+                                    inner.auto_commit(coordinator, generation)?;
+                                }
+
+                                Ok(rejoin)
+                            })
+                            .and_then(move |rejoin| {
+                                if rejoin {
+                                    Either::A(this.rejoin_group(Some(synced_generation.member_id)))
+                                } else {
+                                    Either::B(future::ok((coordinator, synced_generation)))
+                                }
                             })
                             .static_boxed()
                     })
@@ -568,12 +907,17 @@ where
         let state = state.borrow_mut().leaved();
 
         match state {
+            State::Standalone { .. } => future::ok(()).static_boxed(),
             State::Stable {
                 coordinator,
                 generation,
             } => {
                 let member_id = generation.member_id.clone();
 
+                self.inner
+                    .events
+                    .left(&generation, self.inner.subscriptions.borrow().assigned_partitions());
+
                 self.inner
                     .client
                     .leave_group(coordinator, generation)
@@ -599,10 +943,61 @@ where
 
         let client = self.inner.client.clone();
         let retention_time = self.inner.retention_time;
+        let events = self.inner.events.clone();
+        let offsets = offsets.into_iter().collect::<Vec<_>>();
 
         self.ensure_active_group()
             .and_then(move |(coordinator, generation)| {
-                client.offset_commit(Some(coordinator), Some(generation), retention_time, offsets)
+                client
+                    .offset_commit(Some(coordinator), Some(generation), retention_time, offsets.clone())
+                    .map(move |committed| {
+                        events.committed(offsets, &committed);
+
+                        committed
+                    })
+            })
+            .static_boxed()
+    }
+
+    fn commit_offsets_with<I>(&self, group_metadata: &ConsumerGroupMetadata, offsets: I) -> CommitOffset
+    where
+        I: 'static + IntoIterator<Item = (TopicPartition<'a>, OffsetAndMetadata)>,
+    {
+        debug!(
+            "commit offsets to the `{}` group as member `{}` of generation #{}",
+            group_metadata.group_id, group_metadata.member_id, group_metadata.generation_id
+        );
+
+        let client = self.inner.client.clone();
+        let retention_time = self.inner.retention_time;
+        let events = self.inner.events.clone();
+        let offsets = offsets.into_iter().collect::<Vec<_>>();
+        let generation = Generation {
+            group_id: group_metadata.group_id.clone(),
+            generation_id: group_metadata.generation_id,
+            member_id: group_metadata.member_id.clone(),
+            protocol: String::new(),
+        };
+
+        let coordinator = if group_metadata.group_id == self.inner.group_id {
+            self.inner.group_coordinator()
+        } else {
+            self.inner
+                .client
+                .group_coordinator(group_metadata.group_id.clone().into())
+                .map(|coordinator| coordinator.as_ref())
+                .static_boxed()
+        };
+
+        coordinator
+            .and_then(move |coordinator| {
+                client
+                    .offset_commit(Some(coordinator), Some(generation), retention_time, offsets.clone())
+                    .map(move |committed| {
+                        events.committed(offsets, &committed);
+
+                        committed
+                    })
             })
             .static_boxed()
     }
@@ -712,6 +1107,9 @@ mod tests {
         assert_eq!(rebalancing.member_id(), member_id);
         assert_eq!(stable.member_id(), member_id);
 
+        assert_eq!(unjoined.generation(), None);
+        assert_eq!(stable.generation(), Some(&TEST_GROUP.generation()));
+
         let mut state = unjoined.clone();
 
         assert_eq!(state.rebalancing(BrokerRef::new(0), TEST_GROUP.generation()), unjoined);
@@ -736,7 +1134,8 @@ mod tests {
             None,
             config.auto_commit_interval(),
             vec![Box::new(DummySubprotocol {})],
-            Rc::new(config.timer()),
+            vec![],
+            EventDispatcher::default(),
         )
     }
 
@@ -779,6 +1178,11 @@ mod tests {
 
         assert!(coordinator.is_unstable());
 
+        assert_eq!(
+            coordinator.group_metadata(),
+            ConsumerGroupMetadata::new(TEST_GROUP_ID.to_owned())
+        );
+
         match coordinator.join_group().poll() {
             Ok(Async::Ready((group_coordinator, generation))) => {
                 assert_eq!(group_coordinator, node.as_ref());
@@ -788,6 +1192,134 @@ mod tests {
         }
 
         assert!(coordinator.is_stable());
+
+        let group_metadata = coordinator.group_metadata();
+
+        assert_eq!(group_metadata.group_id, TEST_GROUP_ID);
+        assert_eq!(group_metadata.generation_id, 1);
+        assert_eq!(group_metadata.member_id, TEST_MEMBER_ID);
+        assert_eq!(group_metadata.group_instance_id, None);
+    }
+
+    #[test]
+    fn test_commit_offsets_with_group_metadata() {
+        let node = TEST_NODE.clone();
+        let group = TEST_GROUP.clone();
+        let core = Core::new().unwrap();
+        let client = MockClient::with_metadata(Metadata::with_brokers(vec![node.clone()]))
+            .with_handle(core.handle())
+            .with_group_coordinator(TEST_GROUP_ID.into(), node.clone())
+            .with_consumer_group(group.clone())
+            .with_group_member_as_follower(TEST_MEMBER_ID.into());
+        let coordinator = build_coordinator(client, ConsumerConfig::default());
+
+        assert!(coordinator.join_group().wait().is_ok());
+
+        let offsets = || vec![(topic_partition!("topic", 0), OffsetAndMetadata::new(123))];
+        let group_metadata = coordinator.group_metadata();
+
+        match coordinator.commit_offsets_with(&group_metadata, offsets()).wait() {
+            Ok(committed) => assert_eq!(
+                committed["topic"],
+                vec![
+                    client::CommittedOffset {
+                        partition_id: 0,
+                        error_code: KafkaCode::None,
+                    },
+                ]
+            ),
+            res @ _ => panic!("fail to commit offsets: {:?}", res),
+        }
+
+        // the zombie member of the previous generation is fenced
+        let stale_metadata = ConsumerGroupMetadata {
+            generation_id: group_metadata.generation_id - 1,
+            ..group_metadata
+        };
+
+        match coordinator.commit_offsets_with(&stale_metadata, offsets()).wait() {
+            Ok(committed) => assert_eq!(
+                committed["topic"],
+                vec![
+                    client::CommittedOffset {
+                        partition_id: 0,
+                        error_code: KafkaCode::IllegalGeneration,
+                    },
+                ]
+            ),
+            res @ _ => panic!("fail to commit offsets: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_standalone_group() {
+        let node = TEST_NODE.clone();
+        let core = Core::new().unwrap();
+        let client = MockClient::with_metadata(Metadata::with_brokers(vec![node.clone()]))
+            .with_handle(core.handle())
+            .with_group_coordinator(TEST_GROUP_ID.into(), node.clone());
+        let coordinator = build_coordinator(client, ConsumerConfig::default());
+
+        coordinator
+            .inner
+            .subscriptions
+            .borrow_mut()
+            .assign_from_user(vec![topic_partition!("topic", 0)]);
+
+        match coordinator.join_group().poll() {
+            Ok(Async::Ready((group_coordinator, generation))) => {
+                assert_eq!(group_coordinator, node.as_ref());
+                assert_eq!(generation.group_id, TEST_GROUP_ID);
+                assert_eq!(generation.generation_id, -1);
+                assert_eq!(generation.member_id, "");
+            }
+            res @ _ => panic!("fail to use the standalone group: {:?}", res),
+        }
+
+        assert!(!coordinator.is_stable());
+        assert!(!coordinator.is_unstable());
+
+        match coordinator.leave_group().poll() {
+            Ok(Async::Ready(())) => {}
+            res @ _ => panic!("fail to leave the standalone group: {:?}", res),
+        }
+
+        assert!(coordinator.is_unstable());
+    }
+
+    #[test]
+    fn test_join_group_with_fallback() {
+        let node = TEST_NODE.clone();
+        let group = TEST_GROUP.clone();
+        let core = Core::new().unwrap();
+        let client = MockClient::with_metadata(Metadata::with_brokers(vec![node.clone()]))
+            .with_handle(core.handle())
+            .with_group_coordinator(TEST_GROUP_ID.into(), node.clone())
+            .with_consumer_group(group.clone())
+            .with_group_member_as_follower(TEST_MEMBER_ID.into());
+        let config = ConsumerConfig::default();
+        let coordinator = ConsumerCoordinator::new(
+            client,
+            TEST_GROUP_ID.to_owned(),
+            Rc::new(RefCell::new(Subscriptions::new(OffsetResetStrategy::Earliest))),
+            config.session_timeout(),
+            config.rebalance_timeout(),
+            config.heartbeat_interval(),
+            None,
+            config.auto_commit_interval(),
+            AssignmentStrategy::Range.assignor().into_iter().collect(),
+            vec![Box::new(DummySubprotocol {})],
+            EventDispatcher::default(),
+        );
+
+        match coordinator.join_group().poll() {
+            Ok(Async::Ready((_, generation))) => {
+                assert_eq!(generation, group.generation());
+            }
+            res @ _ => panic!("fail to join group with the fallback strategies: {:?}", res),
+        }
+
+        assert!(coordinator.is_stable());
     }
 
     #[test]
@@ -0,0 +1,256 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::Instant;
+
+use futures::unsync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+use client::{BrokerRef, CommittedOffset, Generation, Metrics};
+use network::{OffsetAndMetadata, TopicPartition};
+use protocol::KafkaCode;
+
+/// The membership events of a consumer group.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConsumerEvent<'a> {
+    /// The coordinator of the group was discovered or has been changed.
+    CoordinatorChanged { group_id: String, coordinator: BrokerRef },
+    /// The member joined the group and synced up the assigned partitions.
+    Joined {
+        generation: Generation,
+        leader: bool,
+        partitions: Vec<TopicPartition<'a>>,
+    },
+    /// The partitions assigned in the previous generation were revoked.
+    Revoked {
+        generation: Generation,
+        partitions: Vec<TopicPartition<'a>>,
+    },
+    /// The member left the group.
+    Left { generation: Generation },
+}
+
+/// A stream of the consumer group events.
+pub type Events<'a> = UnboundedReceiver<ConsumerEvent<'a>>;
+
+/// A hook called with the committed offsets.
+pub type CommitHook<'a> = Rc<Fn(&[(TopicPartition<'a>, OffsetAndMetadata)]) + 'a>;
+
+/// Dispatch the group events to the subscribers, and record the rebalance metrics.
+#[derive(Clone, Default)]
+pub struct EventDispatcher<'a> {
+    inner: Rc<RefCell<Inner<'a>>>,
+}
+
+#[derive(Default)]
+struct Inner<'a> {
+    metrics: Option<Rc<Metrics>>,
+    subscribers: Vec<UnboundedSender<ConsumerEvent<'a>>>,
+    commit_hooks: Vec<CommitHook<'a>>,
+    coordinator: Option<BrokerRef>,
+    rebalance_started: Option<Instant>,
+    generation: Option<(Generation, Instant)>,
+}
+
+impl<'a> EventDispatcher<'a> {
+    pub fn new(metrics: Option<Rc<Metrics>>) -> Self {
+        EventDispatcher {
+            inner: Rc::new(RefCell::new(Inner {
+                metrics,
+                ..Default::default()
+            })),
+        }
+    }
+
+    /// Subscribe a new stream of the group events.
+    pub fn subscribe(&self) -> Events<'a> {
+        let (sender, receiver) = mpsc::unbounded();
+
+        self.inner.borrow_mut().subscribers.push(sender);
+
+        receiver
+    }
+
+    /// Register a hook called with the offsets which have been committed.
+    pub fn on_commit<F>(&self, hook: F)
+    where
+        F: Fn(&[(TopicPartition<'a>, OffsetAndMetadata)]) + 'a,
+    {
+        self.inner.borrow_mut().commit_hooks.push(Rc::new(hook));
+    }
+
+    /// Call the commit hooks with the offsets committed without error.
+    pub fn committed(
+        &self,
+        offsets: Vec<(TopicPartition<'a>, OffsetAndMetadata)>,
+        results: &HashMap<String, Vec<CommittedOffset>>,
+    ) {
+        let hooks = self.inner.borrow().commit_hooks.clone();
+
+        if hooks.is_empty() {
+            return;
+        }
+
+        let offsets = offsets
+            .into_iter()
+            .filter(|&(ref tp, _)| {
+                results.get(tp.topic_name.as_ref()).map_or(false, |partitions| {
+                    partitions.iter().any(|partition| {
+                        partition.partition_id == tp.partition_id && partition.error_code == KafkaCode::None
+                    })
+                })
+            })
+            .collect::<Vec<_>>();
+
+        if !offsets.is_empty() {
+            for hook in hooks {
+                hook(&offsets);
+            }
+        }
+    }
+
+    pub fn coordinator_discovered(&self, group_id: &str, coordinator: BrokerRef) {
+        let changed = {
+            let mut inner = self.inner.borrow_mut();
+            let changed = inner.coordinator != Some(coordinator);
+
+            inner.coordinator = Some(coordinator);
+
+            changed
+        };
+
+        if changed {
+            self.dispatch(ConsumerEvent::CoordinatorChanged {
+                group_id: group_id.to_owned(),
+                coordinator,
+            })
+        }
+    }
+
+    pub fn rebalance_started(&self) {
+        let mut inner = self.inner.borrow_mut();
+
+        if inner.rebalance_started.is_none() {
+            inner.rebalance_started = Some(Instant::now());
+        }
+    }
+
+    pub fn synced(
+        &self,
+        generation: &Generation,
+        leader: bool,
+        revoked: Vec<TopicPartition<'a>>,
+        assigned: Vec<TopicPartition<'a>>,
+    ) {
+        let previous = self.generation_ended();
+
+        if !revoked.is_empty() {
+            self.dispatch(ConsumerEvent::Revoked {
+                generation: previous.unwrap_or_else(|| generation.clone()),
+                partitions: revoked,
+            });
+        }
+
+        {
+            let mut inner = self.inner.borrow_mut();
+            let now = Instant::now();
+            let latency = now - inner.rebalance_started.take().unwrap_or(now);
+
+            if let Some(ref metrics) = inner.metrics {
+                metrics.group_rebalanced(&generation.group_id, generation.generation_id, latency, assigned.len());
+            }
+
+            inner.generation = Some((generation.clone(), now));
+        }
+
+        self.dispatch(ConsumerEvent::Joined {
+            generation: generation.clone(),
+            leader,
+            partitions: assigned,
+        })
+    }
+
+    pub fn left(&self, generation: &Generation, revoked: Vec<TopicPartition<'a>>) {
+        self.generation_ended();
+
+        if !revoked.is_empty() {
+            self.dispatch(ConsumerEvent::Revoked {
+                generation: generation.clone(),
+                partitions: revoked,
+            });
+        }
+
+        self.dispatch(ConsumerEvent::Left {
+            generation: generation.clone(),
+        })
+    }
+
+    fn generation_ended(&self) -> Option<Generation> {
+        let mut inner = self.inner.borrow_mut();
+        let generation = inner.generation.take();
+
+        generation.map(|(generation, joined)| {
+            if let Some(ref metrics) = inner.metrics {
+                metrics.group_generation_ended(&generation.group_id, joined.elapsed());
+            }
+
+            generation
+        })
+    }
+
+    fn dispatch(&self, event: ConsumerEvent<'a>) {
+        trace!("dispatch consumer event: {:?}", event);
+
+        self.inner
+            .borrow_mut()
+            .subscribers
+            .retain(|subscriber| subscriber.unbounded_send(event.clone()).is_ok());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{Future, Stream};
+
+    use super::*;
+
+    #[test]
+    fn test_dispatch_events() {
+        let dispatcher = EventDispatcher::new(None);
+        let events = dispatcher.subscribe();
+        let generation = Generation {
+            group_id: "group".to_owned(),
+            generation_id: 1,
+            member_id: "member".to_owned(),
+            protocol: "range".to_owned(),
+        };
+        let tp = topic_partition!("topic", 0);
+
+        dispatcher.coordinator_discovered("group", BrokerRef::new(0));
+        dispatcher.coordinator_discovered("group", BrokerRef::new(0));
+        dispatcher.rebalance_started();
+        dispatcher.synced(&generation, true, vec![], vec![tp.clone()]);
+        dispatcher.left(&generation, vec![tp.clone()]);
+
+        drop(dispatcher);
+
+        assert_eq!(
+            events.collect().wait().unwrap(),
+            vec![
+                ConsumerEvent::CoordinatorChanged {
+                    group_id: "group".to_owned(),
+                    coordinator: BrokerRef::new(0),
+                },
+                ConsumerEvent::Joined {
+                    generation: generation.clone(),
+                    leader: true,
+                    partitions: vec![tp.clone()],
+                },
+                ConsumerEvent::Revoked {
+                    generation: generation.clone(),
+                    partitions: vec![tp],
+                },
+                ConsumerEvent::Left { generation },
+            ]
+        );
+    }
+}
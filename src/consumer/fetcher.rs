@@ -5,13 +5,15 @@ use std::marker::PhantomData;
 use std::rc::Rc;
 use std::time::Duration;
 
-use futures::{Async, Future, Poll};
+use futures::future::Either;
+use futures::{future, Async, Future, Poll};
 
-use client::{Client, FetchRecords, KafkaClient, ListOffsets, PartitionData, StaticBoxFuture, ToStaticBoxFuture};
-use consumer::{OffsetResetStrategy, SeekTo, Subscriptions};
-use errors::{Error, ErrorKind};
+use client::{Client, EpochEndOffset, Feature, FetchRecords, KafkaClient, ListOffsets, ListedOffset, PartitionData,
+             StaticBoxFuture, ToStaticBoxFuture};
+use consumer::{OffsetResetStrategy, ReplicaSelector, SeekTo, Subscriptions};
+use errors::{Error, ErrorKind, Result};
 use network::TopicPartition;
-use protocol::{FetchOffset, KafkaCode, Offset};
+use protocol::{FetchOffset, KafkaCode, LeaderEpoch, Offset};
 
 pub struct Fetcher<'a> {
     client: KafkaClient<'a>,
@@ -20,6 +22,7 @@ pub struct Fetcher<'a> {
     fetch_max_bytes: usize,
     fetch_max_wait: Duration,
     partition_fetch_bytes: usize,
+    replica_selector: Rc<ReplicaSelector>,
 }
 
 impl<'a> Fetcher<'a>
@@ -33,6 +36,7 @@ where
         fetch_max_bytes: usize,
         fetch_max_wait: Duration,
         partition_fetch_bytes: usize,
+        replica_selector: Rc<ReplicaSelector>,
     ) -> Self {
         Fetcher {
             client,
@@ -41,6 +45,7 @@ where
             fetch_max_bytes,
             fetch_max_wait,
             partition_fetch_bytes,
+            replica_selector,
         }
     }
 
@@ -86,6 +91,8 @@ where
     }
 
     /// Reset offsets for the given partition using the offset reset strategy.
+    ///
+    /// Fails with `NoOffsetForPartition` if a partition needs the offset reset without a reset strategy.
     fn reset_offsets<I>(&self, partitions: I) -> ResetOffsets
     where
         I: IntoIterator<Item = TopicPartition<'a>>,
@@ -93,16 +100,26 @@ where
         let offset_resets = partitions
             .into_iter()
             .flat_map(|tp| {
-                self.subscriptions
+                let reset_strategy = self.subscriptions
                     .borrow()
                     .assigned_state(&tp)
-                    .and_then(|state| match state.reset_strategy {
-                        Some(OffsetResetStrategy::Earliest) => Some((tp, FetchOffset::Earliest)),
-                        Some(OffsetResetStrategy::Latest) => Some((tp, FetchOffset::Latest)),
-                        _ => None,
-                    })
+                    .and_then(|state| state.reset_strategy);
+
+                reset_strategy.map(|reset_strategy| match reset_strategy.fetch_offset() {
+                    Some(fetch_offset) => Ok((tp, fetch_offset)),
+                    None => Err(ErrorKind::NoOffsetForPartition(tp.topic_name.to_string(), tp.partition_id).into()),
+                })
             })
-            .collect::<Vec<_>>();
+            .collect::<Result<Vec<_>>>();
+
+        let offset_resets = match offset_resets {
+            Ok(offset_resets) => offset_resets,
+            Err(err) => {
+                warn!("fail to reset offsets, {}", err);
+
+                return future::err(err).static_boxed();
+            }
+        };
 
         let subscriptions = self.subscriptions.clone();
 
@@ -132,10 +149,14 @@ where
     }
 
     /// Set-up a fetch request for any node that we have assigned partitions.
+    ///
+    /// If the fetch offset of a partition is out of range, and the leader epoch of the last fetched record is known,
+    /// the end offset of the leader epoch is looked up to detect the log truncation before resetting the offset.
     pub fn fetch_records<I>(&self, partitions: I) -> FetchRecords
     where
         I: IntoIterator<Item = TopicPartition<'a>>,
     {
+        let client = self.client.clone();
         let subscriptions = self.subscriptions.clone();
         let default_reset_strategy = self.subscriptions.borrow().default_reset_strategy();
 
@@ -144,7 +165,7 @@ where
             .flat_map(|tp| {
                 subscriptions.borrow().assigned_state(&tp).map(|state| {
                     let fetch_data = PartitionData {
-                        offset: state.position.unwrap(),
+                        offset: state.fetch_offset().unwrap(),
                         max_bytes: Some(self.partition_fetch_bytes as i32),
                     };
 
@@ -158,41 +179,134 @@ where
                 self.fetch_max_wait,
                 self.fetch_min_bytes,
                 self.fetch_max_bytes,
+                self.replica_selector.clone(),
                 fetch_partitions,
             )
-            .and_then(move |(throttle_time, records)| {
-                for (topic_name, records) in &records {
-                    for record in records {
-                        let tp = topic_partition!(topic_name.clone(), record.partition_id);
-
-                        if let Some(mut state) = subscriptions.borrow_mut().assigned_state_mut(&tp) {
-                            if !state.is_fetchable() {
-                                debug!("ignoring fetched records for {} since it is no longer fetchable", tp);
-                            } else {
-                                match record.error_code {
-                                    KafkaCode::None => {
-                                        if state.position != Some(record.fetch_offset) {
-                                            debug!("discarding stale fetch response for {} since its offset {} does not match the expected offset {:?}", tp, record.fetch_offset, state.position);
-                                            continue;
+            .and_then({
+                let subscriptions = subscriptions.clone();
+
+                move |(throttle_time, mut records)| {
+                    let mut truncations = Vec::new();
+
+                    for (topic_name, records) in &mut records {
+                        for record in records.iter_mut() {
+                            let tp = topic_partition!(topic_name.clone(), record.partition_id);
+
+                            if let Some(mut state) = subscriptions.borrow_mut().assigned_state_mut(&tp) {
+                                if !state.is_fetchable() {
+                                    debug!("ignoring fetched records for {} since it is no longer fetchable", tp);
+
+                                    record.messages.clear();
+                                } else {
+                                    match record.error_code {
+                                        KafkaCode::None => {
+                                            if state.fetch_offset() != Some(record.fetch_offset) {
+                                                debug!("discarding stale fetch response for {} since its offset {} does not match the expected offset {:?}", tp, record.fetch_offset, state.fetch_offset());
+                                                record.messages.clear();
+                                                continue;
+                                            }
+
+                                            state.high_watermark = record.high_watermark;
+
+                                            if let Some(last) = record.messages.last() {
+                                                state.prefetched(last.offset + 1);
+                                                state.leader_epoch = last.leader_epoch.or(state.leader_epoch);
+                                            }
                                         }
+                                        KafkaCode::OffsetOutOfRange => {
+                                            if state.fetch_offset() != Some(record.fetch_offset) {
+                                                debug!("discarding stale fetch response for {} since its offset {} does not match the expected offset {:?}", tp, record.fetch_offset, state.fetch_offset());
+                                            } else if let Some(leader_epoch) = state.leader_epoch {
+                                                truncations.push((tp, leader_epoch, record.fetch_offset));
+                                            } else {
+                                                state.need_offset_reset(default_reset_strategy);
+                                            }
+                                        }
+                                        code if code.is_retriable() => {
+                                            debug!("fail to fetch records for {}, {}, retry later", tp, code);
 
-                                        state.high_watermark = record.high_watermark;
-                                    }
-                                    KafkaCode::OffsetOutOfRange => {
-                                        if state.position != Some(record.fetch_offset) {
-                                            debug!("discarding stale fetch response for {} since its offset {} does not match the expected offset {:?}", tp, record.fetch_offset, state.position);
-                                        } else {
-                                            state.need_offset_reset(default_reset_strategy);
+                                            record.messages.clear();
                                         }
+                                        _ => bail!(ErrorKind::KafkaError(record.error_code)),
                                     }
-                                    _ => bail!(ErrorKind::KafkaError(record.error_code)),
                                 }
                             }
                         }
                     }
+
+                    Ok((throttle_time, records, truncations))
+                }
+            })
+            .and_then(move |(throttle_time, records, truncations)| {
+                Fetcher::detect_truncation(client, subscriptions, default_reset_strategy, truncations)
+                    .map(move |_| (throttle_time, records))
+            })
+            .static_boxed()
+    }
+
+    /// Look up the end offsets of the last fetched leader epochs of the out of range partitions.
+    ///
+    /// If the log was truncated before the fetch offset, e.g. after an unclean leader election,
+    /// the position is moved back to the end offset of the leader epoch, which is the first offset diverged.
+    /// Otherwise, or the leader epochs are not supported by the brokers, the offset is reset with the strategy.
+    fn detect_truncation(
+        client: KafkaClient<'a>,
+        subscriptions: Rc<RefCell<Subscriptions<'a>>>,
+        reset_strategy: OffsetResetStrategy,
+        partitions: Vec<(TopicPartition<'a>, LeaderEpoch, Offset)>,
+    ) -> DetectTruncation {
+        if partitions.is_empty() {
+            return future::ok(()).static_boxed();
+        }
+
+        let epochs = partitions
+            .iter()
+            .map(|&(ref tp, leader_epoch, _)| (tp.clone(), leader_epoch))
+            .collect::<Vec<_>>();
+
+        client
+            .supports(Feature::OffsetsForLeaderEpoch)
+            .and_then(move |supported| {
+                if supported {
+                    Either::A(client.offsets_for_leader_epoch(epochs))
+                } else {
+                    Either::B(future::ok(HashMap::new()))
+                }
+            })
+            .then(move |res| {
+                let end_offsets = res.unwrap_or_else(|err| {
+                    warn!("fail to look up the end offsets of leader epochs, {}", err);
+
+                    HashMap::new()
+                });
+                let mut subscriptions = subscriptions.borrow_mut();
+
+                for (tp, leader_epoch, fetch_offset) in partitions {
+                    let diverged = end_offsets
+                        .get(tp.topic_name.as_ref())
+                        .and_then(|partitions| {
+                            partitions
+                                .iter()
+                                .find(|partition| partition.partition_id == tp.partition_id)
+                        })
+                        .and_then(|end_offset| diverged_offset(end_offset, fetch_offset));
+
+                    if let Some(state) = subscriptions.assigned_state_mut(&tp) {
+                        if let Some(offset) = diverged {
+                            warn!(
+                                "log truncation detected for partition {}, the end offset of leader epoch {} is {}, \
+                                 reset the fetch offset from {} to {}",
+                                tp, leader_epoch, offset, fetch_offset, offset
+                            );
+
+                            state.seek(offset);
+                        } else {
+                            state.need_offset_reset(reset_strategy);
+                        }
+                    }
                 }
 
-                Ok((throttle_time, records))
+                Ok(())
             })
             .static_boxed()
     }
@@ -204,6 +318,8 @@ where
 
 pub type ResetOffsets = StaticBoxFuture;
 
+pub type DetectTruncation = StaticBoxFuture;
+
 pub type UpdatePositions = StaticBoxFuture;
 
 pub struct RetrieveOffsets<'a, T: 'a> {
@@ -220,8 +336,28 @@ impl<'a, T> RetrieveOffsets<'a, T> {
     }
 }
 
+/// The first offset diverged from the log of the leader, if the log was truncated before the fetch offset.
+fn diverged_offset(end_offset: &EpochEndOffset, fetch_offset: Offset) -> Option<Offset> {
+    if end_offset.error_code != KafkaCode::None {
+        return None;
+    }
+
+    end_offset.end_offset().filter(|&offset| offset < fetch_offset)
+}
+
+/// The offset of a listed partition, or the error which failed the partition.
+fn listed_offset(topic_name: &str, listed: &ListedOffset) -> Result<Offset> {
+    if listed.error_code != KafkaCode::None {
+        bail!(ErrorKind::KafkaError(listed.error_code))
+    }
+
+    listed
+        .offset()
+        .ok_or_else(|| ErrorKind::NoOffsetForPartition(topic_name.to_owned(), listed.partition_id).into())
+}
+
 impl<'a> Future for RetrieveOffsets<'a, Offset> {
-    type Item = HashMap<TopicPartition<'a>, Offset>;
+    type Item = HashMap<TopicPartition<'a>, Result<Offset>>;
     type Error = Error;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
@@ -230,10 +366,11 @@ impl<'a> Future for RetrieveOffsets<'a, Offset> {
                 offsets
                     .into_iter()
                     .flat_map(|(topic_name, partitions)| {
-                        partitions.into_iter().flat_map(move |listed| {
-                            listed
-                                .offset()
-                                .map(|offset| (topic_partition!(topic_name.clone(), listed.partition_id), offset))
+                        partitions.into_iter().map(move |listed| {
+                            (
+                                topic_partition!(topic_name.clone(), listed.partition_id),
+                                listed_offset(&topic_name, &listed),
+                            )
                         })
                     })
                     .collect(),
@@ -243,3 +380,67 @@ impl<'a> Future for RetrieveOffsets<'a, Offset> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use futures::future;
+
+    use super::*;
+
+    #[test]
+    fn test_diverged_offset() {
+        let end_offset = |error_code, end_offset| EpochEndOffset {
+            partition_id: 0,
+            error_code,
+            leader_epoch: Some(5),
+            end_offset,
+        };
+
+        // the log was truncated at offset 100
+        assert_eq!(diverged_offset(&end_offset(KafkaCode::None, 100), 120), Some(100));
+
+        // the fetch offset is in the log of the leader epoch
+        assert_eq!(diverged_offset(&end_offset(KafkaCode::None, 120), 120), None);
+        assert_eq!(diverged_offset(&end_offset(KafkaCode::None, 150), 120), None);
+
+        // the leader epoch is unknown
+        assert_eq!(diverged_offset(&end_offset(KafkaCode::None, -1), 120), None);
+        assert_eq!(diverged_offset(&end_offset(KafkaCode::NotLeaderForPartition, 100), 120), None);
+    }
+
+    #[test]
+    fn test_retrieve_offsets() {
+        let listed = |partition_id, error_code, offsets| ListedOffset {
+            partition_id,
+            error_code,
+            offsets,
+            timestamp: None,
+        };
+        let mut offsets = HashMap::new();
+
+        offsets.insert(
+            "topic".to_owned(),
+            vec![
+                listed(0, KafkaCode::None, vec![123]),
+                listed(1, KafkaCode::LeaderNotAvailable, vec![]),
+                listed(2, KafkaCode::None, vec![]),
+            ],
+        );
+
+        let offsets = RetrieveOffsets::<Offset>::new(future::ok(offsets).static_boxed())
+            .wait()
+            .unwrap();
+
+        assert_eq!(offsets.len(), 3);
+        assert_eq!(offsets[&topic_partition!("topic", 0)].as_ref().unwrap(), &123);
+
+        match *offsets[&topic_partition!("topic", 1)].as_ref().unwrap_err().kind() {
+            ErrorKind::KafkaError(KafkaCode::LeaderNotAvailable) => {}
+            ref kind => panic!("unexpected error: {:?}", kind),
+        }
+        match *offsets[&topic_partition!("topic", 2)].as_ref().unwrap_err().kind() {
+            ErrorKind::NoOffsetForPartition(ref topic_name, 2) if topic_name == "topic" => {}
+            ref kind => panic!("unexpected error: {:?}", kind),
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use std::cell::RefCell;
+use std::ops::{Deref, DerefMut};
+use std::rc::Rc;
+
+use consumer::ConsumerRecord;
+use network::{OffsetAndMetadata, TopicPartition};
+
+pub type Interceptors<'a, K, V> = Option<Rc<RefCell<ConsumerInterceptors<'a, K, V>>>>;
+
+/// A trait for intercepting (and possibly mutate) the records received by the consumer.
+pub trait ConsumerInterceptor<'a> {
+    /// The type of key
+    type Key;
+    /// The type of value
+    type Value;
+
+    /// This is called just before the record is returned by the stream of subscribed topics.
+    fn on_consume(
+        &self,
+        record: ConsumerRecord<'a, Self::Key, Self::Value>,
+    ) -> ConsumerRecord<'a, Self::Key, Self::Value>;
+
+    /// This is called when the offsets have been committed, by the application or auto-commit.
+    fn on_commit(&self, offsets: &[(TopicPartition<'a>, OffsetAndMetadata)]);
+}
+
+pub struct ConsumerInterceptors<'a, K, V> {
+    interceptors: Vec<Box<ConsumerInterceptor<'a, Key = K, Value = V>>>,
+}
+
+impl<'a, K, V> Deref for ConsumerInterceptors<'a, K, V> {
+    type Target = Vec<Box<ConsumerInterceptor<'a, Key = K, Value = V>>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.interceptors
+    }
+}
+
+impl<'a, K, V> DerefMut for ConsumerInterceptors<'a, K, V> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.interceptors
+    }
+}
+
+impl<'a, K, V> Default for ConsumerInterceptors<'a, K, V> {
+    fn default() -> Self {
+        ConsumerInterceptors {
+            interceptors: Vec::new(),
+        }
+    }
+}
+
+impl<'a, K, V> ConsumerInterceptors<'a, K, V> {
+    pub fn new() -> Self {
+        ConsumerInterceptors::default()
+    }
+}
+
+impl<'a, K, V> ConsumerInterceptor<'a> for ConsumerInterceptors<'a, K, V> {
+    type Key = K;
+    type Value = V;
+
+    fn on_consume(&self, mut record: ConsumerRecord<'a, K, V>) -> ConsumerRecord<'a, K, V> {
+        for interceptor in &self.interceptors {
+            record = interceptor.on_consume(record);
+        }
+
+        record
+    }
+
+    fn on_commit(&self, offsets: &[(TopicPartition<'a>, OffsetAndMetadata)]) {
+        for interceptor in &self.interceptors {
+            interceptor.on_commit(offsets);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use std::collections::HashMap;
+
+    use client::CommittedOffset;
+    use consumer::EventDispatcher;
+    use protocol::KafkaCode;
+
+    use super::*;
+
+    struct Uppercase {
+        committed: Rc<RefCell<Vec<(TopicPartition<'static>, OffsetAndMetadata)>>>,
+    }
+
+    impl ConsumerInterceptor<'static> for Uppercase {
+        type Key = ();
+        type Value = String;
+
+        fn on_consume(&self, mut record: ConsumerRecord<'static, (), String>) -> ConsumerRecord<'static, (), String> {
+            record.value = record.value.map(|value| value.to_uppercase());
+            record
+        }
+
+        fn on_commit(&self, offsets: &[(TopicPartition<'static>, OffsetAndMetadata)]) {
+            self.committed.borrow_mut().extend(offsets.iter().cloned());
+        }
+    }
+
+    #[test]
+    fn test_consumer_interceptors() {
+        let committed = Rc::new(RefCell::new(Vec::new()));
+        let interceptors = Rc::new(RefCell::new(ConsumerInterceptors::new()));
+
+        interceptors.borrow_mut().push(Box::new(Uppercase {
+            committed: committed.clone(),
+        }));
+
+        let record = interceptors.borrow().on_consume(ConsumerRecord {
+            topic_name: Cow::from("topic"),
+            partition_id: 0,
+            offset: 1,
+            key: None,
+            value: Some("value".to_owned()),
+            timestamp: None,
+            deserialize_error: None,
+        });
+
+        assert_eq!(record.value, Some("VALUE".to_owned()));
+
+        let events = EventDispatcher::new(None);
+
+        {
+            let interceptors = interceptors.clone();
+
+            events.on_commit(move |offsets| interceptors.borrow().on_commit(offsets));
+        }
+
+        let mut results = HashMap::new();
+
+        results.insert(
+            "topic".to_owned(),
+            vec![
+                CommittedOffset {
+                    partition_id: 0,
+                    error_code: KafkaCode::None,
+                },
+                CommittedOffset {
+                    partition_id: 1,
+                    error_code: KafkaCode::OffsetMetadataTooLarge,
+                },
+            ],
+        );
+
+        events.committed(
+            vec![
+                (topic_partition!("topic", 0), OffsetAndMetadata::new(2)),
+                (topic_partition!("topic", 1), OffsetAndMetadata::new(5)),
+            ],
+            &results,
+        );
+
+        assert_eq!(
+            *committed.borrow(),
+            vec![(topic_partition!("topic", 0), OffsetAndMetadata::new(2))]
+        );
+    }
+}
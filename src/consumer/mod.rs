@@ -3,18 +3,31 @@ mod builder;
 mod config;
 mod consumer;
 mod coordinator;
+mod events;
 mod fetcher;
+mod interceptor;
+mod ownership;
 mod protocol;
+mod replica;
 mod subscribed;
 mod subscriptions;
+mod writer;
 
-pub use self::assignor::{Assignment, AssignmentStrategy, PartitionAssignor, Subscription};
+pub use self::assignor::{Assignment, AssignmentStrategy, PartitionAssignor, RebalanceProtocol, Subscription};
 pub use self::builder::ConsumerBuilder;
-pub use self::config::{ConsumerConfig, DEFAULT_AUTO_COMMIT_INTERVAL_MILLIS, DEFAULT_HEARTBEAT_INTERVAL_MILLIS,
-                       DEFAULT_MAX_POLL_RECORDS, DEFAULT_SESSION_TIMEOUT_MILLIS};
+pub use self::config::{ConsumerConfig, DEFAULT_AUTO_COMMIT_INTERVAL_MILLIS, DEFAULT_FETCH_MAX_BYTES,
+                       DEFAULT_HEARTBEAT_INTERVAL_MILLIS, DEFAULT_MAX_POLL_RECORDS, DEFAULT_PARTITION_FETCH_BYTES,
+                       DEFAULT_PARTITION_OWNERSHIP_LEASE_MILLIS, DEFAULT_QUEUED_MAX_MESSAGES,
+                       DEFAULT_SESSION_TIMEOUT_MILLIS};
 pub use self::consumer::{Consumer, ConsumerRecord, KafkaConsumer};
-pub use self::coordinator::{CommitOffset, ConsumerCoordinator, Coordinator, JoinGroup, LeaveGroup};
+pub use self::coordinator::{CommitOffset, ConsumerCoordinator, ConsumerGroupMetadata, Coordinator, JoinGroup,
+                            LeaveGroup};
+pub use self::events::{CommitHook, ConsumerEvent, EventDispatcher, Events};
 pub use self::fetcher::{Fetcher, RetrieveOffsets, UpdatePositions};
+pub use self::interceptor::{ConsumerInterceptor, ConsumerInterceptors, Interceptors};
+pub use self::ownership::PartitionOwnership;
 pub use self::protocol::{ConsumerProtocol, CONSUMER_PROTOCOL};
-pub use self::subscribed::{Subscribed, SubscribedTopics};
+pub use self::replica::{LeaderSelector, RackAwareReplicaSelector, ReplicaSelector};
+pub use self::subscribed::{Commit, Subscribed, SubscribedTopics};
 pub use self::subscriptions::{OffsetResetStrategy, SeekTo, Subscriptions, TopicPartitionState};
+pub use self::writer::{write_to, RecordFormat, WriteTo, DEFAULT_WRITE_BUFFER_SIZE};
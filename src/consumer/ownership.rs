@@ -0,0 +1,286 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use errors::Result;
+use network::TopicPartition;
+use protocol::{PartitionId, ToMilliseconds};
+
+const LOCK_RETRIES: usize = 100;
+const LOCK_RETRY_BACKOFF_MILLIS: u64 = 10;
+
+/// The partitions owned by the instances of a groupless consumer fleet, shared in a state file.
+///
+/// Each instance claims the partitions not owned by the others for a lease,
+/// and renews the lease by claiming again before it expires.
+/// The partitions of a crashed instance are taken over by the others once its lease expired.
+///
+/// The state file is locked by creating a `.lock` file next to it while it is updated,
+/// so the instances must share the same file system.
+#[derive(Clone, Debug)]
+pub struct PartitionOwnership {
+    path: PathBuf,
+    owner: String,
+    lease: Duration,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Lease {
+    owner: String,
+    expires_at: u64,
+}
+
+impl PartitionOwnership {
+    /// Construct a `PartitionOwnership` of the owner in the state file.
+    pub fn new<P: AsRef<Path>, S: AsRef<str>>(path: P, owner: S, lease: Duration) -> Self {
+        PartitionOwnership {
+            path: path.as_ref().to_owned(),
+            // the whitespaces are used to separate the fields in the state file
+            owner: owner.as_ref().split_whitespace().collect::<Vec<_>>().join("_"),
+            lease,
+        }
+    }
+
+    /// The path of the state file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The owner of the claimed partitions.
+    pub fn owner(&self) -> &str {
+        &self.owner
+    }
+
+    /// The lease of the claimed partitions.
+    pub fn lease(&self) -> Duration {
+        self.lease
+    }
+
+    /// Claim the partitions not owned by the others, and renew the lease of the owned partitions.
+    ///
+    /// Returns the partitions owned after claiming.
+    pub fn claim<'a, I>(&self, partitions: I) -> Result<Vec<TopicPartition<'a>>>
+    where
+        I: IntoIterator<Item = TopicPartition<'a>>,
+    {
+        let now = now();
+        let expires_at = now + self.lease.as_millis();
+
+        self.update(|leases| {
+            let mut claimed = Vec::new();
+
+            for tp in partitions {
+                let key = (tp.topic_name.to_string(), tp.partition_id);
+                let owned = match leases.get(&key) {
+                    Some(lease) => lease.owner == self.owner || lease.expires_at <= now,
+                    None => true,
+                };
+
+                if owned {
+                    leases.insert(
+                        key,
+                        Lease {
+                            owner: self.owner.clone(),
+                            expires_at,
+                        },
+                    );
+
+                    claimed.push(tp);
+                }
+            }
+
+            claimed
+        })
+    }
+
+    /// Release all the partitions owned by the owner.
+    pub fn release(&self) -> Result<()> {
+        self.update(|leases| {
+            let owned = leases
+                .iter()
+                .filter(|&(_, lease)| lease.owner == self.owner)
+                .map(|(key, _)| key.clone())
+                .collect::<Vec<_>>();
+
+            for key in owned {
+                leases.remove(&key);
+            }
+        })
+    }
+
+    fn update<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut BTreeMap<(String, PartitionId), Lease>) -> T,
+    {
+        let lock_path = self.lock_path();
+
+        self.lock(&lock_path)?;
+
+        let res = self.read().and_then(|mut leases| {
+            let res = f(&mut leases);
+
+            self.write(&leases).map(|_| res)
+        });
+
+        if let Err(err) = fs::remove_file(&lock_path) {
+            warn!("fail to unlock the ownership file {:?}, {}", lock_path, err);
+        }
+
+        res
+    }
+
+    fn lock_path(&self) -> PathBuf {
+        let mut lock_path = self.path.clone().into_os_string();
+        lock_path.push(".lock");
+        PathBuf::from(lock_path)
+    }
+
+    fn lock(&self, lock_path: &Path) -> Result<()> {
+        for _ in 0..LOCK_RETRIES {
+            match OpenOptions::new().write(true).create_new(true).open(lock_path) {
+                Ok(_) => return Ok(()),
+                Err(ref err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                    // the lock left by a crashed instance is broken once the lease expired
+                    let stale = fs::metadata(lock_path)
+                        .and_then(|metadata| metadata.modified())
+                        .ok()
+                        .and_then(|modified| modified.elapsed().ok())
+                        .map_or(false, |elapsed| elapsed > self.lease);
+
+                    if stale {
+                        warn!("break the stale lock of the ownership file {:?}", lock_path);
+
+                        fs::remove_file(lock_path)?;
+                    } else {
+                        thread::sleep(Duration::from_millis(LOCK_RETRY_BACKOFF_MILLIS));
+                    }
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        Err(io::Error::new(io::ErrorKind::WouldBlock, format!("fail to lock {:?}", lock_path)).into())
+    }
+
+    fn read(&self) -> Result<BTreeMap<(String, PartitionId), Lease>> {
+        let mut leases = BTreeMap::new();
+
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(leases),
+            Err(err) => return Err(err.into()),
+        };
+
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+
+            if fields.is_empty() {
+                continue;
+            }
+
+            let parsed = if fields.len() == 4 {
+                match (fields[1].parse(), fields[3].parse()) {
+                    (Ok(partition_id), Ok(expires_at)) => Some((partition_id, expires_at)),
+                    _ => None,
+                }
+            } else {
+                None
+            };
+
+            match parsed {
+                Some((partition_id, expires_at)) => {
+                    leases.insert(
+                        (fields[0].to_owned(), partition_id),
+                        Lease {
+                            owner: fields[2].to_owned(),
+                            expires_at,
+                        },
+                    );
+                }
+                None => warn!("skip malformed line in the ownership file {:?}: {}", self.path, line),
+            }
+        }
+
+        Ok(leases)
+    }
+
+    fn write(&self, leases: &BTreeMap<(String, PartitionId), Lease>) -> Result<()> {
+        // write to a temporary file and rename it, the state file is never left half written
+        let mut tmp_path = self.path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        let tmp_path = PathBuf::from(tmp_path);
+
+        {
+            let mut file = File::create(&tmp_path)?;
+
+            for (&(ref topic_name, partition_id), lease) in leases {
+                writeln!(file, "{} {} {} {}", topic_name, partition_id, lease.owner, lease.expires_at)?;
+            }
+
+            file.sync_all()?;
+        }
+
+        fs::rename(&tmp_path, &self.path)?;
+
+        Ok(())
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::process;
+
+    use super::*;
+
+    #[test]
+    fn test_claim_and_release() {
+        let path = env::temp_dir().join(format!("tokio-kafka-ownership-{}", process::id()));
+        let lease = Duration::from_secs(60);
+
+        let first = PartitionOwnership::new(&path, "first", lease);
+        let second = PartitionOwnership::new(&path, "second", lease);
+
+        let partitions = vec![topic_partition!("topic", 0), topic_partition!("topic", 1)];
+
+        assert_eq!(first.claim(vec![partitions[0].clone()]).unwrap(), vec![partitions[0].clone()]);
+        assert_eq!(second.claim(partitions.clone()).unwrap(), vec![partitions[1].clone()]);
+
+        // renew the owned partitions
+        assert_eq!(first.claim(partitions.clone()).unwrap(), vec![partitions[0].clone()]);
+
+        first.release().unwrap();
+
+        assert_eq!(second.claim(partitions.clone()).unwrap(), partitions);
+        assert!(!first.lock_path().exists());
+
+        ::std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_expired_lease() {
+        let path = env::temp_dir().join(format!("tokio-kafka-ownership-expired-{}", process::id()));
+
+        let crashed = PartitionOwnership::new(&path, "crashed", Duration::from_millis(0));
+        let alive = PartitionOwnership::new(&path, "alive host", Duration::from_secs(60));
+
+        let partitions = vec![topic_partition!("topic", 0)];
+
+        assert_eq!(crashed.claim(partitions.clone()).unwrap(), partitions);
+        assert_eq!(alive.owner(), "alive_host");
+        assert_eq!(alive.claim(partitions.clone()).unwrap(), partitions);
+
+        ::std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,13 +1,21 @@
 use std::borrow::Cow;
 use std::collections::HashMap;
+use std::fmt;
+use std::marker::PhantomData;
 
 use serde::{de, ser};
 
 use consumer::{Assignment, Subscription};
+use network::TopicPartition;
 use protocol::Nullable;
 
 const CONSUMER_PROTOCOL_V0: i16 = 0;
 
+/// The version adds the owned partitions to the subscription, for the cooperative rebalancing.
+const CONSUMER_PROTOCOL_V1: i16 = 1;
+
+const SUBSCRIPTION_FIELDS: &[&str] = &["header", "topics", "user_data", "owned_partitions"];
+
 pub const CONSUMER_PROTOCOL: &str = "consumer";
 
 pub struct ConsumerProtocol {}
@@ -24,6 +32,14 @@ pub struct SubscriptionSchema {
     user_data: Nullable<Vec<u8>>,
 }
 
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SubscriptionSchemaV1 {
+    header: ConsumerProtocolHeader,
+    topics: Vec<String>,
+    user_data: Nullable<Vec<u8>>,
+    owned_partitions: Vec<TopicAssignment>,
+}
+
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct TopicAssignment {
     topics: String,
@@ -42,20 +58,34 @@ impl<'a> ser::Serialize for Subscription<'a> {
     where
         S: ser::Serializer,
     {
-        let mut schema = SubscriptionSchema {
-            header: ConsumerProtocolHeader {
-                version: CONSUMER_PROTOCOL_V0,
-            },
-            topics: self.topics
-                .iter()
-                .map(|topic_name| String::from(topic_name.to_owned()))
-                .collect(),
-            user_data: self.user_data.as_ref().map(|user_data| user_data.to_vec()).into(),
-        };
-
-        schema.topics.sort();
-
-        schema.serialize(serializer)
+        let mut topics: Vec<String> = self.topics
+            .iter()
+            .map(|topic_name| String::from(topic_name.to_owned()))
+            .collect();
+
+        topics.sort();
+
+        let user_data: Nullable<Vec<u8>> = self.user_data.as_ref().map(|user_data| user_data.to_vec()).into();
+
+        // keep the first version if there is no owned partitions, for the brokers and members don't support it
+        if self.owned_partitions.is_empty() {
+            SubscriptionSchema {
+                header: ConsumerProtocolHeader {
+                    version: CONSUMER_PROTOCOL_V0,
+                },
+                topics,
+                user_data,
+            }.serialize(serializer)
+        } else {
+            SubscriptionSchemaV1 {
+                header: ConsumerProtocolHeader {
+                    version: CONSUMER_PROTOCOL_V1,
+                },
+                topics,
+                user_data,
+                owned_partitions: topic_assignments(&self.owned_partitions),
+            }.serialize(serializer)
+        }
     }
 }
 
@@ -64,57 +94,103 @@ impl<'a, 'de> de::Deserialize<'de> for Subscription<'a> {
     where
         D: de::Deserializer<'de>,
     {
-        let SubscriptionSchema {
-            header,
-            topics,
-            user_data,
-        } = SubscriptionSchema::deserialize(deserializer)?;
+        deserializer.deserialize_struct("SubscriptionSchema", SUBSCRIPTION_FIELDS, SubscriptionVisitor(PhantomData))
+    }
+}
+
+/// Read the fields of the subscription by its version, the owned partitions were added in the second version.
+struct SubscriptionVisitor<'a>(PhantomData<&'a ()>);
+
+impl<'a, 'de> de::Visitor<'de> for SubscriptionVisitor<'a> {
+    type Value = Subscription<'a>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("consumer protocol subscription")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: de::SeqAccess<'de>,
+    {
+        let header: ConsumerProtocolHeader = seq.next_element()?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
 
         if header.version < CONSUMER_PROTOCOL_V0 {
-            Err(de::Error::custom(format!(
+            return Err(de::Error::custom(format!(
                 "unsupported subscription version: {}",
                 header.version
-            )))
-        } else {
-            Ok(Subscription {
-                topics: topics.into_iter().map(Cow::Owned).collect(),
-                user_data: user_data.into_raw().map(Cow::Owned),
-            })
+            )));
         }
+
+        let topics: Vec<String> = seq.next_element()?
+            .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        let user_data: Nullable<Vec<u8>> = seq.next_element()?
+            .ok_or_else(|| de::Error::invalid_length(2, &self))?;
+        let owned_partitions: Vec<TopicAssignment> = if header.version >= CONSUMER_PROTOCOL_V1 {
+            seq.next_element()?
+                .ok_or_else(|| de::Error::invalid_length(3, &self))?
+        } else {
+            Vec::new()
+        };
+
+        Ok(Subscription {
+            topics: topics.into_iter().map(Cow::Owned).collect(),
+            user_data: user_data.into_raw().map(Cow::Owned),
+            owned_partitions: topic_partitions(&owned_partitions),
+        })
     }
 }
 
+/// Group the partitions by topic, in the order of topic name.
+fn topic_assignments(partitions: &[TopicPartition]) -> Vec<TopicAssignment> {
+    let mut topic_partitions = HashMap::new();
+
+    for tp in partitions {
+        topic_partitions
+            .entry(tp.topic_name.to_owned())
+            .or_insert_with(Vec::new)
+            .push(tp.partition_id);
+    }
+
+    let mut assignments: Vec<TopicAssignment> = topic_partitions
+        .into_iter()
+        .map(|(topic_name, partitions)| TopicAssignment {
+            topics: String::from(topic_name.to_owned()),
+            partitions,
+        })
+        .collect();
+
+    assignments.sort_by(|lhs, rhs| lhs.topics.cmp(&rhs.topics));
+
+    assignments
+}
+
+fn topic_partitions<'a>(assignments: &[TopicAssignment]) -> Vec<TopicPartition<'a>> {
+    assignments
+        .iter()
+        .flat_map(|assignment| {
+            let topic_name = assignment.topics.to_owned();
+
+            assignment
+                .partitions
+                .iter()
+                .map(move |&partition| topic_partition!(topic_name.clone(), partition))
+        })
+        .collect()
+}
+
 impl<'a> ser::Serialize for Assignment<'a> {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: ser::Serializer,
     {
-        let mut topic_partitions = HashMap::new();
-
-        for tp in &self.partitions {
-            topic_partitions
-                .entry(tp.topic_name.to_owned())
-                .or_insert_with(Vec::new)
-                .push(tp.partition_id);
-        }
-
-        let mut schema = AssignmentSchema {
+        AssignmentSchema {
             header: ConsumerProtocolHeader {
                 version: CONSUMER_PROTOCOL_V0,
             },
-            topic_partitions: topic_partitions
-                .into_iter()
-                .map(|(topic_name, partitions)| TopicAssignment {
-                    topics: String::from(topic_name.to_owned()),
-                    partitions,
-                })
-                .collect(),
+            topic_partitions: topic_assignments(&self.partitions),
             user_data: self.user_data.as_ref().map(|user_data| user_data.to_vec()).into(),
-        };
-
-        schema.topic_partitions.sort_by(|lhs, rhs| lhs.topics.cmp(&rhs.topics));
-
-        schema.serialize(serializer)
+        }.serialize(serializer)
     }
 }
 
@@ -135,20 +211,8 @@ impl<'a, 'de> de::Deserialize<'de> for Assignment<'a> {
                 header.version
             )))
         } else {
-            let partitions = topic_partitions
-                .iter()
-                .flat_map(|assignment| {
-                    let topic_name = assignment.topics.to_owned();
-
-                    assignment
-                        .partitions
-                        .iter()
-                        .map(move |&partition| topic_partition!(topic_name.clone(), partition))
-                })
-                .collect();
-
             Ok(Assignment {
-                partitions,
+                partitions: topic_partitions(&topic_partitions),
                 user_data: user_data.into_raw().map(Cow::Owned),
             })
         }
@@ -166,6 +230,7 @@ mod tests {
         static ref TEST_SUBSCRIPTION: Subscription<'static> = Subscription {
             topics: vec!["t0".into(), "t1".into()],
             user_data: Some(b"data".to_vec().into()),
+            owned_partitions: vec![],
         };
 
         static ref TEST_SUBSCRIPTION_DATA: Vec<u8> = vec![
@@ -182,6 +247,33 @@ mod tests {
             0, 0, 0, 4, b'd', b'a', b't', b'a',
         ];
 
+        static ref TEST_SUBSCRIPTION_V1: Subscription<'static> = Subscription {
+            topics: vec!["t0".into()],
+            user_data: None,
+            owned_partitions: vec![topic_partition!("t0", 0), topic_partition!("t0", 1)],
+        };
+
+        static ref TEST_SUBSCRIPTION_V1_DATA: Vec<u8> = vec![
+            // SubscriptionSchemaV1
+            // header: ConsumerProtocolHeader
+                0, 1, // version
+
+            // topic_partitions: [&str]
+            0, 0, 0, 1,
+                0, 2, b't', b'0',
+
+            // user_data
+            255, 255, 255, 255,
+
+            // owned_partitions: [TopicAssignment]
+            0, 0, 0, 1,
+                // TopicAssignment
+                0, 2, b't', b'0',   // topics
+                0, 0, 0, 2,         // partitions
+                    0, 0, 0, 0,
+                    0, 0, 0, 1,
+        ];
+
         static ref TEST_ASSIGNMENT: Assignment<'static> = Assignment {
             partitions: vec![
                 topic_partition!("t0", 0),
@@ -227,6 +319,18 @@ mod tests {
         assert_eq!(subscription, *TEST_SUBSCRIPTION);
     }
 
+    #[test]
+    fn test_subscription_v1() {
+        assert_eq!(
+            Schema::serialize(&*TEST_SUBSCRIPTION_V1).unwrap(),
+            *TEST_SUBSCRIPTION_V1_DATA
+        );
+
+        let subscription: Subscription = Schema::deserialize(Cursor::new(TEST_SUBSCRIPTION_V1_DATA.clone())).unwrap();
+
+        assert_eq!(subscription, *TEST_SUBSCRIPTION_V1);
+    }
+
     #[test]
     fn test_assignment_serializer() {
         assert_eq!(Schema::serialize(&*TEST_ASSIGNMENT).unwrap(), *TEST_ASSIGNMENT_DATA);
@@ -0,0 +1,151 @@
+use client::{Broker, BrokerRef, Cluster, PartitionInfo};
+use consumer::ConsumerConfig;
+use network::TopicPartition;
+
+/// A trait for choosing the replica to fetch a partition from.
+///
+/// The consumer could fetch from the closest in-sync replica instead of the leader (KIP-392),
+/// to save the cross rack or data center traffic.
+pub trait ReplicaSelector {
+    /// Configure the selector with the consumer configuration.
+    fn configure(&mut self, _config: &ConsumerConfig) {}
+
+    /// Select the replica to fetch the partition from, or `None` if no replica is available.
+    fn select(&self, tp: &TopicPartition, partition: &PartitionInfo, cluster: &Cluster) -> Option<BrokerRef>;
+
+    /// The rack of the consumer, which is sent with the fetch requests.
+    fn rack(&self) -> Option<&str> {
+        None
+    }
+}
+
+/// The default selector which always fetches from the leader.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LeaderSelector;
+
+impl ReplicaSelector for LeaderSelector {
+    fn select(&self, _tp: &TopicPartition, partition: &PartitionInfo, _cluster: &Cluster) -> Option<BrokerRef> {
+        partition.leader
+    }
+}
+
+/// The selector which fetches from the in-sync replica in the same rack as the consumer.
+///
+/// The rack is configured by `client.rack`, the leader will be selected
+/// if the rack is not configured or no in-sync replica in the rack.
+#[derive(Clone, Debug, Default)]
+pub struct RackAwareReplicaSelector {
+    rack: Option<String>,
+}
+
+impl RackAwareReplicaSelector {
+    /// Create a `RackAwareReplicaSelector` in the given rack, regardless of `client.rack`.
+    pub fn new<S: Into<String>>(rack: S) -> Self {
+        RackAwareReplicaSelector {
+            rack: Some(rack.into()),
+        }
+    }
+}
+
+impl ReplicaSelector for RackAwareReplicaSelector {
+    fn configure(&mut self, config: &ConsumerConfig) {
+        if self.rack.is_none() {
+            self.rack = config.client_rack.clone();
+        }
+    }
+
+    fn select(&self, tp: &TopicPartition, partition: &PartitionInfo, cluster: &Cluster) -> Option<BrokerRef> {
+        let rack = match self.rack {
+            Some(ref rack) => rack.as_str(),
+            None => return partition.leader,
+        };
+        let in_rack = |replica: &BrokerRef| {
+            cluster
+                .find_broker(*replica)
+                .and_then(Broker::rack)
+                .map_or(false, |replica_rack| replica_rack == rack)
+        };
+
+        if partition.leader.as_ref().map_or(false, &in_rack) {
+            return partition.leader;
+        }
+
+        match partition.in_sync_replicas.iter().find(|replica| in_rack(*replica)) {
+            Some(replica) => {
+                trace!("select replica #{} in rack {} for {}", replica.index(), rack, tp);
+
+                Some(*replica)
+            }
+            None => partition.leader,
+        }
+    }
+
+    fn rack(&self) -> Option<&str> {
+        self.rack.as_ref().map(|rack| rack.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use client::Metadata;
+
+    fn metadata() -> Metadata {
+        Metadata::with_brokers(vec![
+            Broker::new(0, "localhost", 9092).with_rack(Some("a".to_owned())),
+            Broker::new(1, "localhost", 9093).with_rack(Some("b".to_owned())),
+            Broker::new(2, "localhost", 9094),
+        ])
+    }
+
+    fn partition() -> PartitionInfo {
+        PartitionInfo {
+            partition_id: 0,
+            leader: Some(BrokerRef::new(0)),
+            replicas: vec![BrokerRef::new(0), BrokerRef::new(1), BrokerRef::new(2)],
+            in_sync_replicas: vec![BrokerRef::new(0), BrokerRef::new(1), BrokerRef::new(2)],
+        }
+    }
+
+    #[test]
+    fn test_leader_selector() {
+        let tp = topic_partition!("topic", 0);
+
+        assert_eq!(
+            LeaderSelector.select(&tp, &partition(), &metadata()),
+            Some(BrokerRef::new(0))
+        );
+    }
+
+    #[test]
+    fn test_rack_aware_replica_selector() {
+        let tp = topic_partition!("topic", 0);
+        let metadata = metadata();
+        let partition = partition();
+
+        let mut selector = RackAwareReplicaSelector::default();
+
+        // no rack configured
+        assert_eq!(selector.select(&tp, &partition, &metadata), Some(BrokerRef::new(0)));
+
+        selector.configure(&ConsumerConfig {
+            client_rack: Some("b".to_owned()),
+            ..Default::default()
+        });
+
+        assert_eq!(selector.select(&tp, &partition, &metadata), Some(BrokerRef::new(1)));
+
+        // the leader is preferred in the same rack
+        assert_eq!(
+            RackAwareReplicaSelector::new("a").select(&tp, &partition, &metadata),
+            Some(BrokerRef::new(0))
+        );
+
+        // no in-sync replica in the rack
+        assert_eq!(
+            RackAwareReplicaSelector::new("c").select(&tp, &partition, &metadata),
+            Some(BrokerRef::new(0))
+        );
+    }
+}
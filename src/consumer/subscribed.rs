@@ -3,20 +3,23 @@ use std::cell::RefCell;
 use std::collections::HashMap;
 use std::hash::Hash;
 use std::cmp;
-use std::time::Duration;
+use std::fmt;
+use std::time::{Duration, Instant};
 use std::rc::Rc;
 
 use bytes::IntoBuf;
-use futures::{future, Async, Future, Poll, Stream};
-use tokio_timer::{Sleep, Timer};
+use futures::{future, task, Async, Future, Poll, Stream};
+use time;
 
-use client::{Client, FetchRecords, FetchedRecords, KafkaClient, StaticBoxFuture, ToStaticBoxFuture};
-use consumer::{CommitOffset, ConsumerCoordinator, ConsumerRecord, Coordinator, Fetcher, JoinGroup, KafkaConsumer,
-               LeaveGroup, RetrieveOffsets, SeekTo, Subscriptions, UpdatePositions};
+use client::{Client, FetchRecords, FetchedRecords, GetMetadata, KafkaClient, Metadata, Metrics, OffsetsForTimes,
+             Runtime, ShutdownReport, Sleep, StaticBoxFuture, ToStaticBoxFuture};
+use consumer::{CommitOffset, ConsumerCoordinator, ConsumerGroupMetadata, ConsumerRecord, Coordinator, Fetcher,
+               JoinGroup, KafkaConsumer, LeaveGroup, RetrieveOffsets, SeekTo, Subscriptions, UpdatePositions};
 use errors::{Error, ErrorKind, Result};
-use network::{OffsetAndMetadata, OffsetAndTimestamp, TopicPartition};
-use protocol::{FetchOffset, Offset, Timestamp};
-use serialization::Deserializer;
+use monitoring::{fetch_group_lag, FetchLags};
+use network::{OffsetAndMetadata, TopicPartition};
+use protocol::{FetchOffset, KafkaCode, Offset, Timestamp, ToMilliseconds};
+use serialization::{DeserializeError, Deserializer};
 
 /// A trait for to the subscribed list of topics.
 pub trait Subscribed<'a> {
@@ -26,13 +29,25 @@ pub trait Subscribed<'a> {
     /// Get the current subscription.
     fn subscription(&self) -> Vec<String>;
 
+    /// Get the metadata of the consumer in the group, or `None` if the consumer has no `group.id`.
+    fn group_metadata(&self) -> Option<ConsumerGroupMetadata>;
+
     /// Unsubscribe from topics currently subscribed with `Consumer::subscribe`
     fn unsubscribe(&self) -> Unsubscribe;
 
     /// Commit offsets returned on the last record for all the subscribed list of topics and
     /// partitions.
+    ///
+    /// If the processed offsets were reported with `commit_processed`,
+    /// only the processed offsets will be committed.
     fn commit(&self) -> Commit;
 
+    /// Report the record at the given offset has been processed by the application.
+    ///
+    /// Once called, the auto-commit will never commit past the processed offsets,
+    /// even the following records have been fetched or consumed.
+    fn commit_processed(&self, partition: &TopicPartition<'a>, offset: Offset) -> Result<()>;
+
     /// Commit the specified offsets for the specified list of topics and
     /// partitions.
     fn commit_offsets<I>(&self, offsets: I) -> Commit
@@ -91,14 +106,26 @@ pub trait Subscribed<'a> {
     /// Look up the offsets for the given partitions by timestamp.
     fn offsets_for_times(&self, partitions: HashMap<TopicPartition<'a>, Timestamp>) -> OffsetsForTimes<'a>;
 
+    /// Seek to the earliest offsets whose timestamps are greater than or equal to the given timestamps.
+    ///
+    /// The partitions without such a record are seeked to the end.
+    fn seek_to_timestamp(&self, partitions: HashMap<TopicPartition<'a>, Timestamp>) -> SeekToTimestamp;
+
     /// Get the first offset for the given partitions.
+    ///
+    /// The partitions which failed to be listed are returned with their errors.
     fn beginning_offsets(&self, partitions: Vec<TopicPartition<'a>>) -> BeginningOffsets<'a>;
 
     /// Get the last offset for the given partitions.
     ///
     /// The last offset of a partition is the offset of the upcoming message,
     /// i.e. the offset of the last available message + 1.
+    ///
+    /// The partitions which failed to be listed are returned with their errors.
     fn end_offsets(&self, partitions: Vec<TopicPartition<'a>>) -> EndOffsets<'a>;
+
+    /// Fetch the committed offsets of the group and the log end offsets of the subscribed topics.
+    fn lag(&self) -> FetchLags;
 }
 
 pub type Unsubscribe = LeaveGroup;
@@ -107,7 +134,7 @@ pub type Commit = CommitOffset;
 
 pub type Committed = StaticBoxFuture<OffsetAndMetadata>;
 
-pub type OffsetsForTimes<'a> = RetrieveOffsets<'a, OffsetAndTimestamp>;
+pub type SeekToTimestamp = StaticBoxFuture;
 
 pub type BeginningOffsets<'a> = RetrieveOffsets<'a, Offset>;
 
@@ -133,24 +160,67 @@ where
         subscriptions: Rc<RefCell<Subscriptions<'a>>>,
         coordinator: Option<ConsumerCoordinator<'a, KafkaClient<'a>>>,
         fetcher: Rc<Fetcher<'a>>,
-        timer: Rc<Timer>,
+        runtime: Runtime,
     ) -> Result<SubscribedTopics<'a, K, V>> {
-        let state = if let Some(ref coordinator) = coordinator {
-            State::Joining(coordinator.join_group())
-        } else {
-            State::fetching(subscriptions.clone(), fetcher.clone())
+        let user_assigned = subscriptions.borrow().has_user_assignment();
+        let state = match coordinator {
+            Some(ref coordinator) if user_assigned => State::UpdatingOffsets(coordinator.update_offsets()),
+            Some(ref coordinator) => State::Joining(coordinator.join_group()),
+            None if user_assigned => State::updating(subscriptions.clone(), fetcher.clone()),
+            None => State::fetching(subscriptions.clone(), fetcher.clone()),
         };
 
-        Ok(SubscribedTopics {
+        let client = (*consumer).clone();
+        let subscribed = SubscribedTopics {
             inner: Rc::new(RefCell::new(Inner {
                 consumer,
                 subscriptions,
                 coordinator,
                 fetcher,
-                timer,
+                runtime,
                 state,
+                prefetch: None,
+                polled: 0,
+                metadata: None,
             })),
-        })
+        };
+
+        let inner = Rc::downgrade(&subscribed.inner);
+
+        client.register_shutdown_hook(move || match inner.upgrade() {
+            Some(inner) => {
+                debug!("commit offsets and leave group before shutdown");
+
+                let started = Instant::now();
+                let commit = inner.borrow().commit();
+
+                commit
+                    .then(move |res| {
+                        let offsets_committed = match res {
+                            Ok(committed) => committed
+                                .values()
+                                .flat_map(|partitions| partitions.iter())
+                                .filter(|partition| partition.error_code == KafkaCode::None)
+                                .count(),
+                            Err(err) => {
+                                warn!("fail to commit offsets before shutdown, {}", err);
+
+                                0
+                            }
+                        };
+
+                        inner.borrow().unsubscribe().map(move |_| ShutdownReport {
+                            offsets_committed,
+                            duration: started.elapsed(),
+                            ..Default::default()
+                        })
+                    })
+                    .static_boxed()
+            }
+            None => future::ok(ShutdownReport::default()).static_boxed(),
+        });
+
+        Ok(subscribed)
     }
 }
 
@@ -178,8 +248,14 @@ where
     subscriptions: Rc<RefCell<Subscriptions<'a>>>,
     coordinator: Option<ConsumerCoordinator<'a, KafkaClient<'a>>>,
     fetcher: Rc<Fetcher<'a>>,
-    timer: Rc<Timer>,
+    runtime: Runtime,
     state: State<'a, K::Item, V::Item>,
+    /// The next fetch issued ahead of consuming the buffered records.
+    prefetch: Option<Prefetch>,
+    /// The number of records returned in a row without yielding to the other tasks.
+    polled: usize,
+    /// The metadata which the subscribed pattern has been evaluated against.
+    metadata: Option<Rc<Metadata>>,
 }
 
 enum State<'a, K, V> {
@@ -188,7 +264,36 @@ enum State<'a, K, V> {
     Updating(UpdatePositions),
     Fetching(FetchRecords),
     Retry(Sleep),
-    Fetched(Box<Iterator<Item = ConsumerRecord<'a, K, V>>>, Duration),
+    /// The fetched records, the number of the buffered records and the throttle time.
+    Fetched(Box<Iterator<Item = ConsumerRecord<'a, K, V>>>, usize, Duration),
+}
+
+enum Prefetch {
+    Fetching(FetchRecords),
+    Fetched(Result<(Duration, HashMap<String, Vec<FetchedRecords>>)>),
+}
+
+impl Prefetch {
+    /// Drive the prefetch in the background, and keep the fetched records until the buffer has been drained.
+    fn poll(prefetch: &mut Option<Prefetch>) {
+        let fetched = match *prefetch {
+            Some(Prefetch::Fetching(ref mut fetching)) => match fetching.poll() {
+                Ok(Async::Ready(records)) => Ok(records),
+                Ok(Async::NotReady) => return,
+                Err(err) => Err(err),
+            },
+            _ => return,
+        };
+
+        *prefetch = Some(Prefetch::Fetched(fetched));
+    }
+
+    fn into_future(self) -> FetchRecords {
+        match self {
+            Prefetch::Fetching(fetching) => fetching,
+            Prefetch::Fetched(fetched) => future::result(fetched).static_boxed(),
+        }
+    }
 }
 
 impl<'a, K, V> State<'a, K, V>
@@ -196,7 +301,7 @@ where
     Self: 'static,
 {
     fn updating(subscriptions: Rc<RefCell<Subscriptions<'a>>>, fetcher: Rc<Fetcher<'a>>) -> Self {
-        let partitions = subscriptions.borrow().assigned_partitions();
+        let partitions = subscriptions.borrow().unpositioned_partitions();
 
         trace!("updating postion of partitions: {:?}", partitions);
 
@@ -211,11 +316,21 @@ where
         State::Fetching(fetcher.fetch_records(partitions))
     }
 
+    /// Update the positions of the partitions which have been seeked before fetching the records.
+    fn next_fetch(subscriptions: Rc<RefCell<Subscriptions<'a>>>, fetcher: Rc<Fetcher<'a>>) -> Self {
+        if subscriptions.borrow().unpositioned_partitions().is_empty() {
+            State::fetching(subscriptions, fetcher)
+        } else {
+            State::updating(subscriptions, fetcher)
+        }
+    }
+
     fn fetched<KD, VD>(
         key_deserializer: KD,
         value_deserializer: VD,
         subscriptions: Rc<RefCell<Subscriptions<'a>>>,
         _auto_commit_enabled: bool,
+        metrics: Option<Rc<Metrics>>,
         throttle_time: Duration,
         records: HashMap<String, Vec<FetchedRecords>>,
     ) -> State<'a, KD::Item, VD::Item>
@@ -223,11 +338,18 @@ where
         KD: 'static + Deserializer + Clone,
         VD: 'static + Deserializer + Clone,
     {
+        let buffered = records
+            .values()
+            .flat_map(|records| records)
+            .map(|record| record.messages.len())
+            .sum::<usize>();
+
         State::Fetched(
             Box::new(records.into_iter().flat_map(move |(topic_name, records)| {
                 let key_deserializer = key_deserializer.clone();
                 let value_deserializer = value_deserializer.clone();
                 let subscriptions = subscriptions.clone();
+                let metrics = metrics.clone();
 
                 records.into_iter().flat_map(move |record| {
                     let topic_name = topic_name.clone();
@@ -236,41 +358,100 @@ where
                     let subscriptions = subscriptions.clone();
                     let key_deserializer = key_deserializer.clone();
                     let value_deserializer = value_deserializer.clone();
+                    let metrics = metrics.clone();
+                    let mut expected = record.fetch_offset;
+
+                    record.messages.into_iter().filter_map(move |message| {
+                        // the buffered records are dropped if the partition was seeked, paused or revoked,
+                        // and the compressed message set may contain the records before the fetch offset.
+                        let consumable = match subscriptions.borrow_mut().assigned_state_mut(&tp) {
+                            Some(state) => {
+                                let consumable = state.is_fetchable() && state.position == Some(expected)
+                                    && message.offset >= expected;
+
+                                if consumable {
+                                    state.consumed(message.offset + 1);
+                                    expected = message.offset + 1;
+                                }
+
+                                consumable
+                            }
+                            None => false,
+                        };
+
+                        if !consumable {
+                            trace!("skip the buffered record of {} @ {}", tp, message.offset);
+
+                            return None;
+                        }
 
-                    record.messages.into_iter().map(move |message| {
-                        if let Some(state) = subscriptions.borrow_mut().assigned_state_mut(&tp) {
-                            state.seek(message.offset + 1);
+                        if let (Some(metrics), Some(timestamp)) = (metrics.as_ref(), message.timestamp.as_ref()) {
+                            // the records without timestamp (before v0.10) have a negative timestamp
+                            if timestamp.value() >= 0 {
+                                let now = time::now_utc().to_timespec().as_millis() as Timestamp;
+                                let latency = cmp::max(now - timestamp.value(), 0) as u64;
+
+                                metrics.record_consumed(&topic_name, Duration::from_millis(latency));
+                            }
                         }
 
-                        ConsumerRecord {
+                        let (key, key_error) = deserialized(message.key.as_ref().map(|buf| {
+                            key_deserializer
+                                .clone()
+                                .deserialize(topic_name.as_ref(), &mut buf.into_buf())
+                        }));
+                        let (value, value_error) = deserialized(message.value.as_ref().map(|buf| {
+                            value_deserializer
+                                .clone()
+                                .deserialize(topic_name.as_ref(), &mut buf.into_buf())
+                        }));
+
+                        // surface the error with the record instead of failing the whole fetch
+                        let deserialize_error = if key_error.is_some() || value_error.is_some() {
+                            let err = DeserializeError {
+                                key: key_error,
+                                value: value_error,
+                            };
+
+                            warn!("{}#{} @ {}: {}", topic_name, partition_id, message.offset, err);
+
+                            Some(err)
+                        } else {
+                            None
+                        };
+
+                        Some(ConsumerRecord {
                             topic_name: Cow::from(topic_name.clone()),
                             partition_id,
                             offset: message.offset,
-                            key: message.key.as_ref().and_then(|buf| {
-                                key_deserializer
-                                    .clone()
-                                    .deserialize(topic_name.as_ref(), &mut buf.into_buf())
-                                    .ok()
-                            }),
-                            value: message.value.as_ref().and_then(|buf| {
-                                value_deserializer
-                                    .clone()
-                                    .deserialize(topic_name.as_ref(), &mut buf.into_buf())
-                                    .ok()
-                            }),
+                            key,
+                            value,
                             timestamp: message.timestamp.clone(),
-                        }
+                            deserialize_error,
+                        })
                     })
                 })
             })),
+            buffered,
             throttle_time,
         )
     }
 
-    fn retry(timer: Rc<Timer>, backoff: Duration) -> Self {
+    fn retry(runtime: &Runtime, backoff: Duration) -> Self {
         trace!("request was failed or throttled due to quota violation, {:?}", backoff);
 
-        State::Retry(timer.sleep(backoff))
+        State::Retry(runtime.sleep(backoff))
+    }
+}
+
+fn deserialized<T, E>(result: Option<::std::result::Result<T, E>>) -> (Option<T>, Option<String>)
+where
+    E: fmt::Display,
+{
+    match result {
+        Some(Ok(data)) => (Some(data), None),
+        Some(Err(err)) => (None, Some(err.to_string())),
+        None => (None, None),
     }
 }
 
@@ -285,6 +466,26 @@ where
     type Error = Error;
 
     fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        let res = self.poll_records();
+
+        if let Ok(Async::NotReady) = res {
+            self.polled = 0;
+        }
+
+        res
+    }
+}
+
+impl<'a, K, V> Inner<'a, K, V>
+where
+    K: 'static + Deserializer + Clone,
+    K::Item: Hash,
+    V: 'static + Deserializer + Clone,
+    Self: 'static,
+{
+    fn poll_records(&mut self) -> Poll<Option<ConsumerRecord<'a, K::Item, V::Item>>, Error> {
+        self.update_pattern_subscription();
+
         loop {
             self.state = match self.state {
                 State::Joining(ref mut join_group) => {
@@ -319,21 +520,34 @@ where
                             .map(|record| record.messages.len())
                             .sum::<usize>() == 0 =>
                     {
-                        State::retry(
-                            self.timer.clone(),
-                            cmp::max(throttle_time, self.consumer.config().fetch_error_backoff()),
-                        )
+                        if throttle_time == Duration::default()
+                            && !self.subscriptions.borrow().unpositioned_partitions().is_empty()
+                        {
+                            // reset the out of range offsets at once, instead of waiting for the backoff
+                            State::updating(self.subscriptions.clone(), self.fetcher.clone())
+                        } else {
+                            State::retry(
+                                &self.runtime,
+                                cmp::max(throttle_time, self.consumer.config().fetch_error_backoff()),
+                            )
+                        }
                     }
                     Ok(Async::Ready((throttle_time, records))) => {
                         let key_deserializer = self.consumer.key_deserializer();
                         let value_deserializer = self.consumer.value_deserializer();
                         let auto_commit_enabled = self.consumer.config().auto_commit_enabled;
+                        let metrics = if self.consumer.config().end_to_end_latency {
+                            self.consumer.metrics()
+                        } else {
+                            None
+                        };
 
                         State::<K::Item, V::Item>::fetched(
                             key_deserializer,
                             value_deserializer,
                             self.subscriptions.clone(),
                             auto_commit_enabled,
+                            metrics,
                             throttle_time,
                             records,
                         )
@@ -342,18 +556,61 @@ where
                         return Ok(Async::NotReady);
                     }
                     Err(err) => {
+                        // the fatal errors, e.g. the authorization failed, will not be resolved by retrying
+                        if err.kafka_code().map_or(false, |code| code.is_fatal()) {
+                            warn!("fail to fetch the records, {}", err);
+
+                            return Err(err);
+                        }
+
                         trace!("fail to fetch the records, {}", err);
 
-                        State::retry(self.timer.clone(), self.consumer.config().fetch_error_backoff())
+                        State::retry(&self.runtime, self.consumer.config().fetch_error_backoff())
                     }
                 },
-                State::Fetched(ref mut records, throttle_time) => {
+                State::Fetched(ref mut records, ref mut buffered, throttle_time) => {
+                    let max_poll_records = self.consumer.config().max_poll_records;
+
+                    if max_poll_records > 0 && self.polled >= max_poll_records {
+                        trace!("returned {} records in a row, yield to the other tasks", self.polled);
+
+                        task::current().notify();
+
+                        return Ok(Async::NotReady);
+                    }
+
+                    // issue the next fetch ahead of the consumption, unless the broker has throttled the fetch
+                    if self.prefetch.is_none() && throttle_time == Duration::default()
+                        && *buffered < self.consumer.config().queued_max_messages
+                        && self.subscriptions.borrow().unpositioned_partitions().is_empty()
+                    {
+                        let partitions = self.subscriptions.borrow().fetchable_partitions();
+
+                        trace!("prefetching records of partitions: {:?}", partitions);
+
+                        self.prefetch = Some(Prefetch::Fetching(self.fetcher.fetch_records(partitions)));
+                    }
+
+                    Prefetch::poll(&mut self.prefetch);
+
                     if let Some(record) = records.next() {
-                        return Ok(Async::Ready(Some(record)));
+                        *buffered = buffered.saturating_sub(1);
+                        self.polled += 1;
+
+                        return Ok(Async::Ready(Some(self.consumer.intercept(record))));
                     } else if throttle_time > Duration::default() {
-                        State::retry(self.timer.clone(), throttle_time)
+                        State::retry(&self.runtime, throttle_time)
                     } else {
-                        State::fetching(self.subscriptions.clone(), self.fetcher.clone())
+                        let positioned = self.subscriptions.borrow().unpositioned_partitions().is_empty();
+
+                        match (self.prefetch.take(), positioned) {
+                            (Some(prefetch), true) => State::Fetching(prefetch.into_future()),
+                            _ => {
+                                self.subscriptions.borrow_mut().reset_fetch_positions();
+
+                                State::next_fetch(self.subscriptions.clone(), self.fetcher.clone())
+                            }
+                        }
                     }
                 }
             };
@@ -367,6 +624,50 @@ where
     V: Deserializer,
     Self: 'static,
 {
+    /// Evaluate the subscribed pattern against the refreshed metadata,
+    /// and rejoin the group to get the partitions of the newly matched topics assigned.
+    fn update_pattern_subscription(&mut self) {
+        if !self.subscriptions.borrow().has_pattern_subscription() {
+            return;
+        }
+
+        if let State::Joining(_) = self.state {
+            return;
+        }
+
+        let metadata = match self.consumer.metadata() {
+            GetMetadata::Loaded(metadata) => metadata,
+            GetMetadata::Loading(_) | GetMetadata::Failed(_) => return,
+        };
+
+        if self.metadata
+            .as_ref()
+            .map_or(false, |evaluated| Rc::ptr_eq(evaluated, &metadata))
+        {
+            return;
+        }
+
+        let changed = self.subscriptions.borrow_mut().subscribe_from_pattern(&metadata);
+
+        self.metadata = Some(metadata);
+
+        if changed {
+            info!(
+                "subscription changed to {:?}, rejoin the group",
+                self.subscriptions.borrow().subscription()
+            );
+
+            if let Some(ref coordinator) = self.coordinator {
+                self.prefetch = None;
+                self.subscriptions.borrow_mut().reset_fetch_positions();
+
+                coordinator.request_rejoin();
+
+                self.state = State::Joining(coordinator.join_group());
+            }
+        }
+    }
+
     fn assigment(&self) -> Vec<TopicPartition<'a>> {
         self.subscriptions.borrow().assigned_partitions()
     }
@@ -375,6 +676,10 @@ where
         self.subscriptions.borrow().subscription()
     }
 
+    fn group_metadata(&self) -> Option<ConsumerGroupMetadata> {
+        self.coordinator.as_ref().map(|coordinator| coordinator.group_metadata())
+    }
+
     fn unsubscribe(&self) -> Unsubscribe {
         if let Some(ref coordinator) = self.coordinator {
             coordinator.leave_group()
@@ -384,7 +689,11 @@ where
     }
 
     fn commit(&self) -> Commit {
-        self.commit_offsets(self.subscriptions.borrow().consumed_partitions())
+        self.commit_offsets(self.subscriptions.borrow().committable_partitions())
+    }
+
+    fn commit_processed(&self, partition: &TopicPartition<'a>, offset: Offset) -> Result<()> {
+        self.subscriptions.borrow_mut().processed(partition, offset)
     }
 
     fn commit_offsets<I>(&self, offsets: I) -> Commit
@@ -394,12 +703,34 @@ where
         if let Some(ref coordinator) = self.coordinator {
             coordinator.commit_offsets(offsets)
         } else {
-            self.consumer.offset_commit(None, None, None, offsets)
+            let events = self.consumer.event_dispatcher().clone();
+            let offsets = offsets.into_iter().collect::<Vec<_>>();
+
+            self.consumer
+                .offset_commit(None, None, None, offsets.clone())
+                .map(move |committed| {
+                    events.committed(offsets, &committed);
+
+                    committed
+                })
+                .static_boxed()
         }
     }
 
-    fn seek(&self, partition: &TopicPartition<'a>, pos: SeekTo) -> Result<()> {
-        self.subscriptions.borrow_mut().seek(partition, pos)
+    fn seek(&mut self, partition: &TopicPartition<'a>, pos: SeekTo) -> Result<()> {
+        self.subscriptions.borrow_mut().seek(partition, pos)?;
+        self.discard_fetched();
+        Ok(())
+    }
+
+    /// Discard the fetched records which have not been consumed,
+    /// so the records from the old positions or the paused partitions will not be returned.
+    fn discard_fetched(&mut self) {
+        if let State::Fetched(..) = self.state {
+            self.prefetch = None;
+            self.subscriptions.borrow_mut().reset_fetch_positions();
+            self.state = State::next_fetch(self.subscriptions.clone(), self.fetcher.clone());
+        }
     }
 
     fn position(&self, partition: &TopicPartition<'a>) -> Result<Option<Offset>> {
@@ -437,17 +768,21 @@ where
             self.consumer
                 .list_offsets(vec![(tp, FetchOffset::Latest)])
                 .and_then(move |mut offsets| {
-                    offsets
-                        .remove(&topic_name)
-                        .and_then(|partitions| {
-                            partitions
-                                .into_iter()
-                                .find(|partition| partition.partition_id == partition_id)
-                                .and_then(move |fetched| {
-                                    fetched.offsets.first().map(|&offset| OffsetAndMetadata::new(offset))
-                                })
-                        })
-                        .ok_or_else(|| ErrorKind::NoOffsetForPartition(topic_name, partition_id).into())
+                    let listed = offsets.remove(&topic_name).and_then(|partitions| {
+                        partitions
+                            .into_iter()
+                            .find(|partition| partition.partition_id == partition_id)
+                    });
+
+                    match listed {
+                        Some(ref listed) if listed.error_code != KafkaCode::None => {
+                            bail!(ErrorKind::KafkaError(listed.error_code))
+                        }
+                        _ => listed
+                            .and_then(|listed| listed.offset())
+                            .map(OffsetAndMetadata::new)
+                            .ok_or_else(|| ErrorKind::NoOffsetForPartition(topic_name, partition_id).into()),
+                    }
                 })
                 .static_boxed()
         }
@@ -457,13 +792,14 @@ where
         self.subscriptions.borrow().paused_partitions()
     }
 
-    fn pause<I>(&self, partitions: I) -> Result<()>
+    fn pause<I>(&mut self, partitions: I) -> Result<()>
     where
         I: IntoIterator<Item = &'a TopicPartition<'a>>,
     {
         for partition in partitions {
             self.subscriptions.borrow_mut().pause(partition)?;
         }
+        self.discard_fetched();
         Ok(())
     }
 
@@ -493,6 +829,10 @@ where
         self.inner.borrow().subscription()
     }
 
+    fn group_metadata(&self) -> Option<ConsumerGroupMetadata> {
+        self.inner.borrow().group_metadata()
+    }
+
     fn unsubscribe(&self) -> Unsubscribe {
         self.inner.borrow().unsubscribe()
     }
@@ -501,6 +841,10 @@ where
         self.inner.borrow().commit()
     }
 
+    fn commit_processed(&self, partition: &TopicPartition<'a>, offset: Offset) -> Result<()> {
+        self.inner.borrow().commit_processed(partition, offset)
+    }
+
     fn commit_offsets<I>(&self, offsets: I) -> Commit
     where
         I: 'static + IntoIterator<Item = (TopicPartition<'a>, OffsetAndMetadata)>,
@@ -509,7 +853,7 @@ where
     }
 
     fn seek(&self, partition: &TopicPartition<'a>, pos: SeekTo) -> Result<()> {
-        self.inner.borrow().seek(partition, pos)
+        self.inner.borrow_mut().seek(partition, pos)
     }
 
     fn position(&self, partition: &TopicPartition<'a>) -> Result<Option<Offset>> {
@@ -528,7 +872,7 @@ where
     where
         I: IntoIterator<Item = &'a TopicPartition<'a>>,
     {
-        self.inner.borrow().pause(partitions)
+        self.inner.borrow_mut().pause(partitions)
     }
 
     fn resume<I>(&self, partitions: I) -> Result<()>
@@ -539,12 +883,29 @@ where
     }
 
     fn offsets_for_times(&self, partitions: HashMap<TopicPartition<'a>, Timestamp>) -> OffsetsForTimes<'a> {
-        self.inner.borrow().fetcher.retrieve_offsets(
-            partitions
-                .into_iter()
-                .map(|(tp, ts)| (tp, FetchOffset::ByTime(ts)))
-                .collect(),
-        )
+        self.inner.borrow().consumer.offsets_for_times(partitions)
+    }
+
+    fn seek_to_timestamp(&self, partitions: HashMap<TopicPartition<'a>, Timestamp>) -> SeekToTimestamp {
+        let subscribed = SubscribedTopics {
+            inner: self.inner.clone(),
+        };
+        let tps = partitions.keys().cloned().collect::<Vec<_>>();
+
+        self.offsets_for_times(partitions)
+            .and_then(move |offsets| {
+                for tp in tps {
+                    let pos = match offsets.get(&tp) {
+                        Some(offset) => SeekTo::Position(offset.offset),
+                        None => SeekTo::End,
+                    };
+
+                    subscribed.seek(&tp, pos)?;
+                }
+
+                Ok(())
+            })
+            .static_boxed()
     }
 
     fn beginning_offsets(&self, partitions: Vec<TopicPartition<'a>>) -> BeginningOffsets<'a> {
@@ -560,4 +921,15 @@ where
             .fetcher
             .retrieve_offsets(partitions.into_iter().map(|tp| (tp, FetchOffset::Latest)).collect())
     }
+
+    fn lag(&self) -> FetchLags {
+        let inner = self.inner.borrow();
+
+        match inner.consumer.config().group_id {
+            Some(ref group_id) => fetch_group_lag((*inner.consumer).clone(), group_id.clone(), inner.subscription()),
+            None => {
+                future::err(ErrorKind::ConfigError("the lag of consumer requires `group.id`").into()).static_boxed()
+            }
+        }
+    }
 }
@@ -1,12 +1,16 @@
+use std::cmp;
 use std::collections::{HashMap, HashSet};
 use std::hash::Hash;
 use std::iter::FromIterator;
 use std::mem;
 use std::str::FromStr;
 
+use regex::Regex;
+
+use client::{Cluster, Metadata};
 use errors::{Error, ErrorKind, Result};
 use network::{OffsetAndMetadata, TopicPartition};
-use protocol::Offset;
+use protocol::{FetchOffset, LeaderEpoch, Offset};
 
 #[derive(Debug, Default)]
 pub struct Subscriptions<'a> {
@@ -15,6 +19,10 @@ pub struct Subscriptions<'a> {
     /// the list of topics the user has requested
     subscription: HashSet<String>,
 
+    /// the pattern of topics the user has requested,
+    /// the subscription is updated with the matching topics on every metadata refresh
+    subscribed_pattern: Option<Regex>,
+
     /// the list of topics the group has subscribed to
     /// (set only for the leader on join group completion)
     group_subscription: HashSet<String>,
@@ -22,6 +30,13 @@ pub struct Subscriptions<'a> {
     /// the partitions that are currently assigned,
     /// note that the order of partition matters
     assignment: HashMap<TopicPartition<'a>, TopicPartitionState>,
+
+    /// whether the application reports the processed offsets,
+    /// the auto-commit will only commit the processed offsets if true
+    track_processed: bool,
+
+    /// whether the partitions are assigned manually by the user instead of the group coordinator
+    user_assigned: bool,
 }
 
 impl<'a> Subscriptions<'a> {
@@ -29,8 +44,11 @@ impl<'a> Subscriptions<'a> {
         Subscriptions {
             default_reset_strategy,
             subscription: HashSet::new(),
+            subscribed_pattern: None,
             group_subscription: HashSet::new(),
             assignment: HashMap::new(),
+            track_processed: false,
+            user_assigned: false,
         }
     }
 
@@ -44,8 +62,11 @@ impl<'a> Subscriptions<'a> {
         Subscriptions {
             default_reset_strategy,
             subscription: topic_names.clone(),
+            subscribed_pattern: None,
             group_subscription: topic_names,
             assignment: HashMap::new(),
+            track_processed: false,
+            user_assigned: false,
         }
     }
 
@@ -63,6 +84,41 @@ impl<'a> Subscriptions<'a> {
         self.group_subscription = &self.group_subscription | &self.subscription;
     }
 
+    /// Subscribe to all the topics matching the pattern.
+    ///
+    /// The subscription is updated with the matching topics by `subscribe_from_pattern`.
+    pub fn subscribe_pattern(&mut self, pattern: Regex) {
+        self.subscribed_pattern = Some(pattern);
+    }
+
+    pub fn has_pattern_subscription(&self) -> bool {
+        self.subscribed_pattern.is_some()
+    }
+
+    /// Subscribe to the topics of the metadata matching the subscribed pattern,
+    /// returns `true` if the subscription has been changed.
+    ///
+    /// The Kafka internal topics, e.g. `__consumer_offsets`, are never matched.
+    pub fn subscribe_from_pattern(&mut self, metadata: &Metadata) -> bool {
+        let topic_names: HashSet<String> = match self.subscribed_pattern {
+            Some(ref pattern) => metadata
+                .topic_names()
+                .into_iter()
+                .filter(|topic_name| !metadata.is_internal_topic(topic_name) && pattern.is_match(topic_name))
+                .map(|topic_name| topic_name.to_owned())
+                .collect(),
+            None => return false,
+        };
+
+        if topic_names == self.subscription {
+            false
+        } else {
+            self.subscribe(topic_names);
+
+            true
+        }
+    }
+
     /// Add topics to the current group subscription.
     ///
     /// This is used by the group leader to ensure that it receives metadata updates for all
@@ -96,6 +152,25 @@ impl<'a> Subscriptions<'a> {
         Ok(())
     }
 
+    /// Change the assignment to the specified partitions assigned manually by the user,
+    /// the partitions will not be assigned or revoked by the group coordinator.
+    pub fn assign_from_user(&mut self, partitions: Vec<TopicPartition<'a>>) {
+        self.subscription = partitions
+            .iter()
+            .map(|tp| String::from(tp.topic_name.to_owned()))
+            .collect();
+        self.group_subscription = self.subscription.clone();
+        self.subscribed_pattern = None;
+        self.user_assigned = true;
+
+        self.assignment = HashMap::from_iter(partitions.into_iter()
+                .map(|tp| (tp.clone(), self.assignment.get(&tp).cloned().unwrap_or(TopicPartitionState::default()))));
+    }
+
+    pub fn has_user_assignment(&self) -> bool {
+        self.user_assigned
+    }
+
     pub fn missing_positions(&self) -> Vec<&TopicPartition<'a>> {
         self.assignment
             .iter()
@@ -140,6 +215,55 @@ impl<'a> Subscriptions<'a> {
             .collect()
     }
 
+    /// Mark the record at the given offset, and all the records before it, as processed.
+    pub fn processed(&mut self, tp: &TopicPartition<'a>, offset: Offset) -> Result<()> {
+        {
+            let state = match self.assignment.get_mut(tp) {
+                Some(state) => state,
+                None => bail!(ErrorKind::IllegalArgument(format!(
+                    "No current assignment for partition {}",
+                    tp
+                ))),
+            };
+
+            if state.position.map_or(true, |position| offset >= position) {
+                bail!(ErrorKind::IllegalArgument(format!(
+                    "offset {} of partition {} has not been consumed",
+                    offset, tp
+                )))
+            }
+
+            state.processed = Some(cmp::max(state.processed.unwrap_or_default(), offset + 1));
+        }
+
+        self.track_processed = true;
+
+        Ok(())
+    }
+
+    pub fn processed_partitions(&self) -> Vec<(TopicPartition<'a>, OffsetAndMetadata)> {
+        self.assignment
+            .iter()
+            .flat_map(|(tp, state)| {
+                state
+                    .processed
+                    .map(|processed| (tp.clone(), offset_and_metadata!(processed)))
+            })
+            .collect()
+    }
+
+    /// The offsets could be committed in the background.
+    ///
+    /// Once the application reported the processed offsets, only those offsets will be committed,
+    /// the records consumed but not processed yet will not be skipped after a failure.
+    pub fn committable_partitions(&self) -> Vec<(TopicPartition<'a>, OffsetAndMetadata)> {
+        if self.track_processed {
+            self.processed_partitions()
+        } else {
+            self.consumed_partitions()
+        }
+    }
+
     pub fn assigned_partitions(&self) -> Vec<TopicPartition<'a>> {
         self.assignment.keys().cloned().collect()
     }
@@ -152,6 +276,15 @@ impl<'a> Subscriptions<'a> {
             .collect()
     }
 
+    /// The partitions without a valid position, or waiting for the offset reset after seeking.
+    pub fn unpositioned_partitions(&self) -> Vec<TopicPartition<'a>> {
+        self.assignment
+            .iter()
+            .filter(|&(_, state)| !state.has_valid_position() || state.is_offset_reset_needed())
+            .map(|(tp, _)| tp.clone())
+            .collect()
+    }
+
     pub fn subscription(&self) -> Vec<String> {
         self.subscription.iter().cloned().collect()
     }
@@ -169,15 +302,24 @@ impl<'a> Subscriptions<'a> {
             .get_mut(tp)
             .map(|state| {
                 state.paused = true;
+                // the prefetched records are dropped, and will be fetched again after resuming
+                state.fetch_position = None;
             })
             .ok_or_else(|| ErrorKind::IllegalArgument(format!("No current assignment for partition {}", tp)).into())
     }
 
+    /// Fetch the partitions from the consumed positions again, when the prefetched records have been dropped.
+    pub fn reset_fetch_positions(&mut self) {
+        for state in self.assignment.values_mut() {
+            state.fetch_position = None;
+        }
+    }
+
     pub fn resume(&mut self, tp: &TopicPartition<'a>) -> Result<()> {
         self.assignment
             .get_mut(tp)
             .map(|state| {
-                state.paused = true;
+                state.paused = false;
             })
             .ok_or_else(|| ErrorKind::IllegalArgument(format!("No current assignment for partition {}", tp)).into())
     }
@@ -208,8 +350,14 @@ pub struct TopicPartitionState {
     pub paused: bool,
     /// last consumed position
     pub position: Option<Offset>,
+    /// the position of the next fetch, ahead of the consumed position when the records have been prefetched
+    pub fetch_position: Option<Offset>,
+    /// the position after the last record processed by the application
+    pub processed: Option<Offset>,
     /// the high watermark from last fetch
     pub high_watermark: Offset,
+    /// the leader epoch of the last fetched record, used to detect the log truncation
+    pub leader_epoch: Option<LeaderEpoch>,
     /// last committed position
     pub committed: Option<OffsetAndMetadata>,
     /// the strategy to use if the offset needs resetting
@@ -218,7 +366,12 @@ pub struct TopicPartitionState {
 
 impl TopicPartitionState {
     pub fn is_fetchable(&self) -> bool {
-        !self.paused && self.position.is_some()
+        !self.paused && self.position.is_some() && self.reset_strategy.is_none()
+    }
+
+    /// The offset to fetch the following records from.
+    pub fn fetch_offset(&self) -> Option<Offset> {
+        self.fetch_position.or(self.position)
     }
 
     pub fn has_valid_position(&self) -> bool {
@@ -234,21 +387,39 @@ impl TopicPartitionState {
     }
 
     pub fn need_offset_reset(&mut self, reset_strategy: OffsetResetStrategy) -> Option<OffsetResetStrategy> {
+        self.fetch_position = None;
         mem::replace(&mut self.reset_strategy, Some(reset_strategy))
     }
 
     pub fn seek(&mut self, offset: Offset) -> Offset {
         self.position = Some(offset);
+        self.fetch_position = None;
         self.reset_strategy = None;
+        self.leader_epoch = None;
         offset
     }
+
+    /// The records before the offset have been fetched, and will be consumed from the buffer.
+    pub fn prefetched(&mut self, offset: Offset) {
+        self.fetch_position = Some(offset);
+    }
+
+    /// The records before the offset have been consumed, without changing the next fetch offset.
+    pub fn consumed(&mut self, offset: Offset) {
+        self.position = Some(offset);
+    }
 }
 
+/// What to do when there is no initial offset or the current offset is out of range.
 #[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum OffsetResetStrategy {
+    /// Reset the offset to the latest offset.
     Latest,
+    /// Reset the offset to the earliest offset.
     Earliest,
+    /// Fail the consumer with `NoOffsetForPartition` instead of resetting the offset.
+    #[serde(alias = "error")]
     None,
 }
 
@@ -265,8 +436,202 @@ impl FromStr for OffsetResetStrategy {
         match s {
             "latest" => Ok(OffsetResetStrategy::Latest),
             "earliest" => Ok(OffsetResetStrategy::Earliest),
-            "none" => Ok(OffsetResetStrategy::None),
+            "none" | "error" => Ok(OffsetResetStrategy::None),
             _ => bail!(ErrorKind::UnsupportedOffsetResetStrategy(s.to_owned())),
         }
     }
 }
+
+impl OffsetResetStrategy {
+    /// The offset to look up for resetting the position, or `None` if the offset should not be reset.
+    pub fn fetch_offset(&self) -> Option<FetchOffset> {
+        match *self {
+            OffsetResetStrategy::Latest => Some(FetchOffset::Latest),
+            OffsetResetStrategy::Earliest => Some(FetchOffset::Earliest),
+            OffsetResetStrategy::None => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use client::PartitionInfo;
+
+    use super::*;
+
+    #[test]
+    fn test_offset_reset_strategy() {
+        assert_eq!("earliest".parse::<OffsetResetStrategy>().unwrap(), OffsetResetStrategy::Earliest);
+        assert_eq!("latest".parse::<OffsetResetStrategy>().unwrap(), OffsetResetStrategy::Latest);
+        assert_eq!("none".parse::<OffsetResetStrategy>().unwrap(), OffsetResetStrategy::None);
+        assert_eq!("error".parse::<OffsetResetStrategy>().unwrap(), OffsetResetStrategy::None);
+        assert!("smallest".parse::<OffsetResetStrategy>().is_err());
+
+        assert_eq!(OffsetResetStrategy::Earliest.fetch_offset(), Some(FetchOffset::Earliest));
+        assert_eq!(OffsetResetStrategy::Latest.fetch_offset(), Some(FetchOffset::Latest));
+        assert_eq!(OffsetResetStrategy::None.fetch_offset(), None);
+    }
+
+    #[test]
+    fn test_committable_partitions() {
+        let tp = topic_partition!("topic", 0);
+        let mut subscriptions = Subscriptions::with_topics(vec!["topic"], OffsetResetStrategy::Earliest);
+
+        subscriptions.assign_from_subscribed(vec![tp.clone()]).unwrap();
+        subscriptions.seek(&tp, SeekTo::Position(10)).unwrap();
+
+        assert_eq!(
+            subscriptions.committable_partitions(),
+            vec![(tp.clone(), offset_and_metadata!(10))]
+        );
+
+        assert!(subscriptions.processed(&tp, 10).is_err());
+        assert!(subscriptions.processed(&topic_partition!("topic", 1), 5).is_err());
+
+        subscriptions.processed(&tp, 5).unwrap();
+
+        assert_eq!(
+            subscriptions.committable_partitions(),
+            vec![(tp.clone(), offset_and_metadata!(6))]
+        );
+
+        subscriptions.processed(&tp, 3).unwrap();
+
+        assert_eq!(
+            subscriptions.committable_partitions(),
+            vec![(tp.clone(), offset_and_metadata!(6))]
+        );
+    }
+
+    #[test]
+    fn test_seek() {
+        let tp = topic_partition!("topic", 0);
+        let mut subscriptions = Subscriptions::with_topics(vec!["topic"], OffsetResetStrategy::Earliest);
+
+        subscriptions.assign_from_subscribed(vec![tp.clone()]).unwrap();
+
+        assert_eq!(subscriptions.unpositioned_partitions(), vec![tp.clone()]);
+        assert!(subscriptions.fetchable_partitions().is_empty());
+
+        subscriptions.seek(&tp, SeekTo::Position(10)).unwrap();
+
+        assert!(subscriptions.unpositioned_partitions().is_empty());
+        assert_eq!(subscriptions.fetchable_partitions(), vec![tp.clone()]);
+
+        subscriptions.seek(&tp, SeekTo::Beginning).unwrap();
+
+        assert_eq!(subscriptions.unpositioned_partitions(), vec![tp.clone()]);
+        assert!(subscriptions.fetchable_partitions().is_empty());
+
+        subscriptions.seek(&tp, SeekTo::Position(0)).unwrap();
+
+        assert_eq!(subscriptions.fetchable_partitions(), vec![tp.clone()]);
+        assert!(subscriptions.seek(&topic_partition!("topic", 1), SeekTo::End).is_err());
+    }
+
+    #[test]
+    fn test_pause_resume() {
+        let tp = topic_partition!("topic", 0);
+        let mut subscriptions = Subscriptions::with_topics(vec!["topic"], OffsetResetStrategy::Earliest);
+
+        subscriptions.assign_from_subscribed(vec![tp.clone()]).unwrap();
+        subscriptions.seek(&tp, SeekTo::Position(10)).unwrap();
+        subscriptions.pause(&tp).unwrap();
+
+        assert_eq!(subscriptions.paused_partitions(), vec![tp.clone()]);
+        assert!(subscriptions.fetchable_partitions().is_empty());
+
+        subscriptions.resume(&tp).unwrap();
+
+        assert!(subscriptions.paused_partitions().is_empty());
+        assert_eq!(subscriptions.fetchable_partitions(), vec![tp.clone()]);
+        assert!(subscriptions.pause(&topic_partition!("topic", 1)).is_err());
+    }
+
+    #[test]
+    fn test_fetch_position() {
+        let tp = topic_partition!("topic", 0);
+        let mut subscriptions = Subscriptions::with_topics(vec!["topic"], OffsetResetStrategy::Earliest);
+
+        subscriptions.assign_from_subscribed(vec![tp.clone()]).unwrap();
+        subscriptions.seek(&tp, SeekTo::Position(10)).unwrap();
+
+        {
+            let state = subscriptions.assigned_state_mut(&tp).unwrap();
+
+            assert_eq!(state.fetch_offset(), Some(10));
+
+            state.prefetched(20);
+            state.consumed(15);
+
+            assert_eq!(state.position, Some(15));
+            assert_eq!(state.fetch_offset(), Some(20));
+        }
+
+        // the prefetched records are dropped after seeking
+        subscriptions.seek(&tp, SeekTo::Position(5)).unwrap();
+
+        assert_eq!(subscriptions.assigned_state(&tp).unwrap().fetch_offset(), Some(5));
+
+        subscriptions.assigned_state_mut(&tp).unwrap().prefetched(20);
+        subscriptions.pause(&tp).unwrap();
+
+        assert_eq!(subscriptions.assigned_state(&tp).unwrap().fetch_offset(), Some(5));
+
+        subscriptions.resume(&tp).unwrap();
+        subscriptions.assigned_state_mut(&tp).unwrap().prefetched(20);
+        subscriptions.reset_fetch_positions();
+
+        assert_eq!(subscriptions.assigned_state(&tp).unwrap().fetch_offset(), Some(5));
+    }
+
+    #[test]
+    fn test_assign_from_user() {
+        let tp = topic_partition!("topic", 0);
+        let mut subscriptions = Subscriptions::new(OffsetResetStrategy::Earliest);
+
+        assert!(!subscriptions.has_user_assignment());
+
+        subscriptions.assign_from_user(vec![tp.clone(), topic_partition!("topic", 1)]);
+
+        assert!(subscriptions.has_user_assignment());
+        assert_eq!(subscriptions.subscription(), vec!["topic".to_owned()]);
+        assert_eq!(subscriptions.unpositioned_partitions().len(), 2);
+
+        subscriptions.seek(&tp, SeekTo::Position(10)).unwrap();
+        subscriptions.assign_from_user(vec![tp.clone()]);
+
+        assert_eq!(subscriptions.assigned_partitions(), vec![tp.clone()]);
+        assert_eq!(subscriptions.assigned_state(&tp).unwrap().position, Some(10));
+    }
+
+    #[test]
+    fn test_subscribe_pattern() {
+        let metadata = |topic_names: &[&str]| {
+            Metadata::with_topics(
+                topic_names
+                    .iter()
+                    .map(|topic_name| (topic_name.to_string(), vec![PartitionInfo::default()]))
+                    .collect(),
+            )
+        };
+        let mut subscriptions = Subscriptions::new(OffsetResetStrategy::Earliest);
+
+        assert!(!subscriptions.subscribe_from_pattern(&metadata(&["foo"])));
+
+        subscriptions.subscribe_pattern(Regex::new("^foo.*").unwrap());
+
+        assert!(subscriptions.has_pattern_subscription());
+        assert!(subscriptions.subscribe_from_pattern(&metadata(&["foo", "bar"])));
+        assert_eq!(subscriptions.subscription(), vec!["foo".to_owned()]);
+
+        assert!(!subscriptions.subscribe_from_pattern(&metadata(&["foo", "bar", "baz"])));
+
+        assert!(subscriptions.subscribe_from_pattern(&metadata(&["foo", "foobar", "bar"])));
+
+        let mut topics = subscriptions.topics();
+        topics.sort();
+
+        assert_eq!(topics, vec!["foo", "foobar"]);
+    }
+}
@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use futures::{Async, Future, Poll, Stream};
+use tokio_io::AsyncWrite;
+
+#[cfg(feature = "json")]
+use serde_json;
+
+use consumer::{Commit, ConsumerRecord, Subscribed};
+use errors::{Error, Result};
+use network::{OffsetAndMetadata, TopicPartition};
+use protocol::{Offset, PartitionId, Timestamp};
+
+/// The size of buffered records to write before flushing the writer.
+pub const DEFAULT_WRITE_BUFFER_SIZE: usize = 64 * 1024;
+
+/// The format of records written to the `AsyncWrite`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RecordFormat {
+    /// One JSON object per line, with the topic, partition, offset, timestamp, key and value.
+    #[cfg(feature = "json")]
+    JsonLines,
+    /// The value of records followed by a newline.
+    Raw,
+    /// The key and value of records separated by a tab, followed by a newline.
+    KeyValue,
+}
+
+#[cfg(feature = "json")]
+#[derive(Serialize)]
+struct JsonRecord<'a> {
+    topic: &'a str,
+    partition: PartitionId,
+    offset: Offset,
+    timestamp: Option<Timestamp>,
+    key: Option<String>,
+    value: Option<String>,
+}
+
+impl RecordFormat {
+    fn write<K, V>(&self, buf: &mut Vec<u8>, record: &ConsumerRecord<K, V>) -> Result<()>
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        match *self {
+            #[cfg(feature = "json")]
+            RecordFormat::JsonLines => {
+                let text = |data: &[u8]| String::from_utf8_lossy(data).into_owned();
+
+                serde_json::to_writer(
+                    &mut *buf,
+                    &JsonRecord {
+                        topic: &record.topic_name,
+                        partition: record.partition_id,
+                        offset: record.offset,
+                        timestamp: record.timestamp.as_ref().map(|ts| ts.value()),
+                        key: record.key.as_ref().map(|key| text(key.as_ref())),
+                        value: record.value.as_ref().map(|value| text(value.as_ref())),
+                    },
+                )?;
+            }
+            RecordFormat::Raw => {
+                if let Some(ref value) = record.value {
+                    buf.write_all(value.as_ref())?;
+                }
+            }
+            RecordFormat::KeyValue => {
+                if let Some(ref key) = record.key {
+                    buf.write_all(key.as_ref())?;
+                }
+                buf.write_all(b"\t")?;
+                if let Some(ref value) = record.value {
+                    buf.write_all(value.as_ref())?;
+                }
+            }
+        }
+
+        buf.write_all(b"\n")?;
+
+        Ok(())
+    }
+}
+
+/// Write the consumed records to the `AsyncWrite` in the format.
+///
+/// The offsets of written records are committed once the writer has been flushed,
+/// so the records will be written at least once.
+/// The future will be resolved when the stream of records is ended, and the writer has been shut down.
+pub fn write_to<'a, S, K, V, W>(topics: S, writer: W, format: RecordFormat) -> WriteTo<'a, S, W>
+where
+    S: Stream<Item = ConsumerRecord<'a, K, V>, Error = Error> + Subscribed<'a>,
+    K: AsRef<[u8]>,
+    V: AsRef<[u8]>,
+    W: AsyncWrite,
+{
+    WriteTo {
+        topics,
+        writer,
+        format,
+        buf: Vec::with_capacity(DEFAULT_WRITE_BUFFER_SIZE),
+        written: HashMap::new(),
+        flushed: HashMap::new(),
+        committing: None,
+        eof: false,
+    }
+}
+
+/// The future of writing the consumed records to the `AsyncWrite`.
+pub struct WriteTo<'a, S, W> {
+    topics: S,
+    writer: W,
+    format: RecordFormat,
+    buf: Vec<u8>,
+    written: HashMap<TopicPartition<'a>, Offset>,
+    flushed: HashMap<TopicPartition<'a>, Offset>,
+    committing: Option<Commit>,
+    eof: bool,
+}
+
+impl<'a, S, W> WriteTo<'a, S, W> {
+    /// Consume the future, return the stream of records and the writer.
+    pub fn into_inner(self) -> (S, W) {
+        (self.topics, self.writer)
+    }
+}
+
+impl<'a, S, K, V, W> WriteTo<'a, S, W>
+where
+    S: Stream<Item = ConsumerRecord<'a, K, V>, Error = Error> + Subscribed<'a>,
+    K: AsRef<[u8]>,
+    V: AsRef<[u8]>,
+    W: AsyncWrite,
+    Self: 'static,
+{
+    fn poll_records(&mut self) -> Poll<(), Error> {
+        while !self.eof && self.buf.len() < DEFAULT_WRITE_BUFFER_SIZE {
+            match self.topics.poll()? {
+                Async::Ready(Some(record)) => {
+                    self.format.write(&mut self.buf, &record)?;
+                    self.written.insert(
+                        topic_partition!(record.topic_name.clone(), record.partition_id),
+                        record.offset + 1,
+                    );
+                }
+                Async::Ready(None) => self.eof = true,
+                Async::NotReady => return Ok(Async::NotReady),
+            }
+        }
+
+        Ok(Async::Ready(()))
+    }
+
+    fn poll_write(&mut self) -> Poll<(), Error> {
+        while !self.buf.is_empty() {
+            match self.writer.write(&self.buf) {
+                Ok(0) => return Err(io::Error::new(io::ErrorKind::WriteZero, "fail to write records").into()),
+                Ok(n) => {
+                    self.buf.drain(..n);
+                }
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(Async::NotReady),
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        if !self.written.is_empty() {
+            match self.writer.flush() {
+                Ok(()) => self.flushed.extend(self.written.drain()),
+                Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(Async::NotReady),
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        Ok(Async::Ready(()))
+    }
+
+    fn poll_commit(&mut self) -> Poll<(), Error> {
+        loop {
+            if let Some(mut committing) = self.committing.take() {
+                if let Async::NotReady = committing.poll()? {
+                    self.committing = Some(committing);
+
+                    return Ok(Async::NotReady);
+                }
+            }
+
+            if self.flushed.is_empty() {
+                return Ok(Async::Ready(()));
+            }
+
+            let offsets = self.flushed
+                .drain()
+                .map(|(tp, offset)| (tp, OffsetAndMetadata::new(offset)))
+                .collect::<Vec<_>>();
+
+            trace!("commit the written offsets: {:?}", offsets);
+
+            self.committing = Some(self.topics.commit_offsets(offsets));
+        }
+    }
+}
+
+impl<'a, S, K, V, W> Future for WriteTo<'a, S, W>
+where
+    S: Stream<Item = ConsumerRecord<'a, K, V>, Error = Error> + Subscribed<'a>,
+    K: AsRef<[u8]>,
+    V: AsRef<[u8]>,
+    W: AsyncWrite,
+    Self: 'static,
+{
+    type Item = ();
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            let records = self.poll_records()?;
+            let written = self.poll_write()?;
+            let committed = self.poll_commit()?;
+
+            if self.eof && written.is_ready() && committed.is_ready() {
+                try_ready!(self.writer.shutdown());
+
+                return Ok(Async::Ready(()));
+            }
+
+            // keep reading if the buffer was full and has been written
+            if self.eof || records.is_not_ready() || written.is_not_ready() {
+                return Ok(Async::NotReady);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use protocol::MessageTimestamp;
+
+    use super::*;
+
+    fn record() -> ConsumerRecord<'static, &'static str, &'static str> {
+        ConsumerRecord {
+            topic_name: Cow::from("topic"),
+            partition_id: 1,
+            offset: 123,
+            key: Some("key"),
+            value: Some("value"),
+            timestamp: Some(MessageTimestamp::CreateTime(456)),
+            deserialize_error: None,
+        }
+    }
+
+    fn format(format: RecordFormat, record: &ConsumerRecord<&str, &str>) -> String {
+        let mut buf = Vec::new();
+        format.write(&mut buf, record).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn test_record_format() {
+        let mut record = record();
+
+        assert_eq!(format(RecordFormat::Raw, &record), "value\n");
+        assert_eq!(format(RecordFormat::KeyValue, &record), "key\tvalue\n");
+
+        record.key = None;
+
+        assert_eq!(format(RecordFormat::KeyValue, &record), "\tvalue\n");
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_json_lines_format() {
+        assert_eq!(
+            format(RecordFormat::JsonLines, &record()),
+            concat!(
+                r#"{"topic":"topic","partition":1,"offset":123,"timestamp":456,"key":"key","value":"value"}"#,
+                "\n"
+            )
+        );
+    }
+}
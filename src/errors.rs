@@ -1,6 +1,7 @@
 use std::borrow::{Borrow, Cow};
 use std::error::Error as StdError;
 use std::fmt;
+use std::net::SocketAddr;
 
 use serde::{de, ser};
 
@@ -16,6 +17,8 @@ error_chain!{
         MetricsError(::prometheus::Error);
         SnappyError(::snap::Error) #[cfg(feature = "snappy")];
         JsonError(::serde_json::Error) #[cfg(feature = "json")];
+        HttpError(::hyper::Error) #[cfg(feature = "avro")];
+        UriError(::hyper::error::UriError) #[cfg(feature = "avro")];
         TimerError(::tokio_timer::TimerError);
         ResolveError(::abstract_ns::Error);
     }
@@ -41,10 +44,18 @@ error_chain!{
             description("invalid argument")
             display("invalid argument, {}", reason)
         }
+        ProtocolError(reason: String) {
+            description("protocol error")
+            display("protocol error, {}", reason)
+        }
         UnexpectedResponse(api_key: ApiKeys) {
             description("unexpected response")
             display("unexpected response, {:?}", api_key)
         }
+        UnsupportedApiKey(api_key: ApiKeys) {
+            description("unsupported api key")
+            display("unsupported api key, {:?}", api_key)
+        }
         Canceled(task: &'static str) {
             description("task canceled")
             display("task canceled, {}", task)
@@ -61,6 +72,10 @@ error_chain!{
             description("retry failed")
             display("retry failed, {}", reason)
         }
+        MetadataError(reason: String) {
+            description("fail to load metadata")
+            display("fail to load metadata, {}", reason)
+        }
         UnsupportedCompression {
             description("Unsupported compression format")
         }
@@ -88,6 +103,17 @@ error_chain!{
             description("topic not found")
             display("topic `{}` not found", topic_name)
         }
+        ProducerClosed {
+            description("producer has been closed")
+        }
+        RecordTooLarge(size: usize, max_size: usize) {
+            description("record too large")
+            display("record of {} bytes is larger than the maximum size of {} bytes", size, max_size)
+        }
+        QueueFull(addr: SocketAddr, max_requests: usize) {
+            description("request queue full")
+            display("request queue of broker {} is full, {} requests are pending", addr, max_requests)
+        }
         BrokerNotFound(broker: BrokerRef) {
             description("broker not found")
             display("broker `{}` not found", broker.index())
@@ -96,12 +122,37 @@ error_chain!{
             description("schema error")
             display("schema error, {}", reason)
         }
+        #[cfg(feature = "avro")]
+        AvroError(reason: String) {
+            description("avro error")
+            display("avro error, {}", reason)
+        }
+        #[cfg(feature = "avro")]
+        SchemaNotFound(schema_id: u32) {
+            description("schema not found")
+            display("schema #{} not found", schema_id)
+        }
+        #[cfg(feature = "avro")]
+        SchemaRegistryError(status: u16, reason: String) {
+            description("schema registry error")
+            display("schema registry error, {} {}", status, reason)
+        }
     }
 }
 
 unsafe impl Sync for Error {}
 unsafe impl Send for Error {}
 
+impl Error {
+    /// The error code returned by the server, if the request was failed by the broker.
+    pub fn kafka_code(&self) -> Option<KafkaCode> {
+        match *self.kind() {
+            ErrorKind::KafkaError(code) => Some(code),
+            _ => None,
+        }
+    }
+}
+
 impl ser::Error for Error {
     fn custom<T>(msg: T) -> Self
     where
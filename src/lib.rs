@@ -13,19 +13,27 @@ extern crate lazy_static;
 extern crate bytes;
 #[macro_use]
 extern crate nom;
+extern crate base64;
 extern crate byteorder;
 extern crate crc;
 #[cfg(feature = "encoding")]
 extern crate encoding;
 extern crate hexplay;
+extern crate hmac;
 extern crate rand;
+extern crate regex;
 extern crate serde;
+extern crate sha2;
 extern crate time;
 extern crate twox_hash;
 #[macro_use]
 extern crate serde_derive;
 #[cfg(feature = "json")]
 extern crate serde_json;
+#[cfg(feature = "avro")]
+extern crate avro_rs;
+#[cfg(feature = "avro")]
+extern crate hyper;
 #[macro_use]
 extern crate prometheus;
 extern crate abstract_ns;
@@ -35,6 +43,8 @@ extern crate ns_std_threaded;
 #[macro_use]
 extern crate futures;
 extern crate futures_cpupool;
+#[cfg(feature = "std-future")]
+extern crate futures03;
 extern crate native_tls;
 extern crate tokio_core;
 extern crate tokio_io;
@@ -70,24 +80,68 @@ mod network;
 mod client;
 mod consumer;
 mod producer;
+mod monitoring;
+mod mirror;
+mod sources;
+mod sync;
+#[cfg(feature = "mock")]
+mod mock;
+#[cfg(feature = "std-future")]
+mod compat;
 
-pub use client::{Broker, BrokerRef, Client, ClientBuilder, ClientConfig, Cluster, KafkaClient, KafkaVersion,
-                 ListOffsets, ListedOffset, LoadMetadata, Metadata, PartitionRecord, ProduceRecords,
-                 ToStaticBoxFuture, TopicRecord, DEFAULT_MAX_CONNECTION_IDLE_TIMEOUT_MILLIS,
-                 DEFAULT_METADATA_MAX_AGE_MILLS, DEFAULT_REQUEST_TIMEOUT_MILLS, DEFAULT_RETRY_BACKOFF_MILLIS};
+pub use client::{AlterConfigs, Broker, BrokerRef, Client, ClientBuilder, ClientConfig, ClientListener, ClientListeners,
+                 Cluster, ClusterDescription, ConfigEntry, ConfigResource, ConfigSource, DeleteRecords, DeletedRecords,
+                 DescribeCluster, DescribeConfigs, DescribeGroups, EpochEndOffset, ErrorClass, Executor, Feature,
+                 FetchPartitionRecords, FetchedRecords, GroupDescription, GroupListing, GroupMemberDescription,
+                 GroupState, Interval, KafkaClient, KafkaVersion, ListGroups, ListOffsets, ListedOffset, LoadMetadata,
+                 Metadata, Metrics, MetricsReporter, OffsetsForLeaderEpoch, OffsetsForTimes, PartitionRecord,
+                 ProduceRecords, PrometheusReporter, QueueFullBehavior, RetryPolicies, RetryPolicy, Runtime,
+                 ShutdownReport, Sleep, Spawned, Timer, ToStaticBoxFuture, TopicRecord, TypedTopic, WithTimeout,
+                 DEFAULT_DNS_REFRESH_INTERVAL_MILLIS,
+                 DEFAULT_MAX_CONNECTION_IDLE_TIMEOUT_MILLIS, DEFAULT_MAX_IN_FLIGHT_REQUESTS_PER_CONNECTION,
+                 DEFAULT_METADATA_BOOTSTRAP_RETRIES, DEFAULT_METADATA_MAX_AGE_MILLS,
+                 DEFAULT_RECONNECT_BACKOFF_MAX_MILLIS, DEFAULT_RECONNECT_BACKOFF_MILLIS, DEFAULT_REQUEST_TIMEOUT_MILLS,
+                 DEFAULT_RETRY_BACKOFF_MAX_MILLIS, DEFAULT_RETRY_BACKOFF_MILLIS};
+#[cfg(feature = "chaos")]
+pub use client::{Chaos, Fault};
+#[cfg(feature = "simulation")]
+pub use client::{SimulatedListener, SimulatedStream, SimulatedTransport, Simulation};
+#[cfg(feature = "std-future")]
+pub use compat::spawn_stream;
 pub use compression::Compression;
-pub use consumer::{Consumer, ConsumerBuilder, KafkaConsumer, OffsetResetStrategy, SeekTo, Subscribed};
+#[cfg(feature = "mock")]
+pub use mock::MockCluster;
+pub use consumer::{write_to, Consumer, ConsumerBuilder, ConsumerGroupMetadata, ConsumerInterceptor,
+                   ConsumerInterceptors, KafkaConsumer, LeaderSelector, OffsetResetStrategy, PartitionOwnership,
+                   RackAwareReplicaSelector, RecordFormat, ReplicaSelector, SeekTo, Subscribed, WriteTo};
 pub use errors::{Error, ErrorKind, Result};
-pub use network::{OffsetAndMetadata, OffsetAndTimestamp, TopicPartition, DEFAULT_PORT};
-pub use producer::{DefaultPartitioner, GetTopic, KafkaProducer, Partitioner, Producer, ProducerBuilder,
-                   ProducerConfig, ProducerInterceptor, ProducerPartition, ProducerRecord, ProducerTopic,
-                   RecordMetadata, SendRecord, DEFAULT_ACK_TIMEOUT_MILLIS, DEFAULT_BATCH_SIZE, DEFAULT_LINGER_MILLIS,
-                   DEFAULT_MAX_REQUEST_SIZE};
-pub use protocol::{ApiKey, ApiKeys, ErrorCode, FetchOffset, KafkaCode, Offset, PartitionId, RequiredAcks, Timestamp,
-                   ToMilliseconds, UsableApiVersion, UsableApiVersions};
-pub use serialization::{BytesDeserializer, BytesSerializer, Deserializer, NoopDeserializer, NoopSerializer,
-                        RawDeserializer, RawSerializer, Serializer, StringDeserializer, StringSerializer};
+pub use mirror::{CommitGroup, OffsetTranslator, TranslateGroup};
+pub use monitoring::{fetch_group_lag, FetchLags, LagMonitor, PartitionLag};
+pub use network::{OffsetAndMetadata, OffsetAndTimestamp, SaslMechanism, SharedTransport, SocketOptions, TcpTransport,
+                  TopicPartition, Transport, TransportConnect, TransportStream, DEFAULT_PORT};
+pub use producer::{AuditInterceptor, AuditRecord, AuditSink, Close, DefaultPartitioner, DeliveryReport, DeliveryReports,
+                   GetTopic, KafkaProducer, LogAuditSink, PartitionStrategy, Partitioner, Producer, ProducerBuilder,
+                   ProducerConfig, ProducerInterceptor, ProducerPartition, ProducerRecord, ProducerTopic, RateLimit,
+                   RateLimiter, RecordId, RecordMetadata, SendAll, SendRecord, SharedFlush, SharedProducer,
+                   SharedSendAll, SharedSendRecord, StickyPartitioner, TokenBucketRateLimiter, TopicOverrides,
+                   WeightedPartitioner, WriteAuditSink, DEFAULT_ACK_TIMEOUT_MILLIS, DEFAULT_BATCH_SIZE,
+                   DEFAULT_BUFFER_MEMORY, DEFAULT_DELIVERY_TIMEOUT_MILLIS, DEFAULT_LINGER_MILLIS,
+                   DEFAULT_MAX_BLOCK_MILLIS, DEFAULT_MAX_REQUEST_SIZE, DEFAULT_MESSAGE_MAX_BYTES,
+                   DEFAULT_PARTITION_WEIGHT};
+#[cfg(feature = "bench")]
+pub use producer::{Accumulator, Batches, InFlight, ProducerBatch, RecordAccumulator};
+pub use protocol::{ApiKey, ApiKeys, CrcPolicy, ErrorCode, FetchOffset, KafkaCode, LeaderEpoch, Offset, PartitionId,
+                   RequiredAcks, Timestamp, ToMilliseconds, UsableApiVersion, UsableApiVersions};
+#[cfg(feature = "bench")]
+pub use protocol::{ApiVersion, MessageSet, MessageSetBuilder, MessageSetEncoder};
+pub use serialization::{BytesDeserializer, BytesSerializer, DeserializeError, Deserializer, NoopDeserializer,
+                        NoopSerializer, RawDeserializer, RawSerializer, Serializer, StringDeserializer,
+                        StringSerializer};
+#[cfg(feature = "avro")]
+pub use serialization::{AvroDeserializer, AvroSerializer, SchemaId, SchemaRegistry};
 #[cfg(feature = "json")]
 pub use serialization::{JsonDeserializer, JsonSerializer};
 #[cfg(feature = "encoding")]
 pub use serialization::{StrEncodingDeserializer, StrEncodingSerializer};
+pub use sources::{FileTailer, TailFiles};
+pub use sync::{BlockingConsumer, BlockingProducer};
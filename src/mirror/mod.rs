@@ -0,0 +1,3 @@
+mod translator;
+
+pub use self::translator::{CommitGroup, OffsetTranslator, TranslateGroup, DEFAULT_MAX_OFFSET_CHECKPOINTS};
@@ -0,0 +1,277 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+
+use futures::Future;
+
+use client::{Client, Generation, KafkaClient, StaticBoxFuture, ToStaticBoxFuture};
+use errors::{ErrorKind, Result};
+use network::{OffsetAndMetadata, TopicPartition};
+use producer::RecordMetadata;
+use protocol::{KafkaCode, Offset};
+
+/// The default maximum number of offset checkpoints kept for each source partition.
+pub const DEFAULT_MAX_OFFSET_CHECKPOINTS: usize = 10_000;
+
+/// The future of translating the committed offsets of a consumer group.
+pub type TranslateGroup<'a> = StaticBoxFuture<Vec<(TopicPartition<'a>, OffsetAndMetadata)>>;
+
+/// The future of committing the translated offsets of a consumer group to the destination cluster.
+pub type CommitGroup<'a> = StaticBoxFuture<Vec<(TopicPartition<'a>, OffsetAndMetadata)>>;
+
+/// Translate the offsets of the mirrored records from the source cluster to the destination cluster.
+///
+/// The offsets are recorded from the `RecordMetadata` of the produced records,
+/// so the committed offsets of a consumer group could be translated for failover.
+/// The checkpoints are shared between the clones.
+#[derive(Clone)]
+pub struct OffsetTranslator<'a> {
+    partitions: Rc<RefCell<HashMap<TopicPartition<'a>, Checkpoints<'a>>>>,
+    max_checkpoints: usize,
+}
+
+struct Checkpoints<'a> {
+    destination: TopicPartition<'a>,
+    offsets: BTreeMap<Offset, Offset>,
+}
+
+impl<'a> Default for OffsetTranslator<'a> {
+    fn default() -> Self {
+        OffsetTranslator {
+            partitions: Rc::new(RefCell::new(HashMap::new())),
+            max_checkpoints: DEFAULT_MAX_OFFSET_CHECKPOINTS,
+        }
+    }
+}
+
+impl<'a> OffsetTranslator<'a> {
+    /// Construct an `OffsetTranslator`
+    pub fn new() -> Self {
+        OffsetTranslator::default()
+    }
+
+    /// Sets the maximum number of offset checkpoints kept for each source partition.
+    ///
+    /// The oldest checkpoints will be dropped, and the offsets before them could not be translated.
+    pub fn with_max_checkpoints(mut self, max_checkpoints: usize) -> Self {
+        self.max_checkpoints = max_checkpoints;
+        self
+    }
+
+    /// Record the source offset of a record mirrored to the destination cluster.
+    pub fn record(&self, source: TopicPartition<'a>, source_offset: Offset, metadata: &RecordMetadata) {
+        let mut partitions = self.partitions.borrow_mut();
+        let checkpoints = partitions.entry(source).or_insert_with(|| Checkpoints {
+            destination: topic_partition!(metadata.topic_name.clone(), metadata.partition_id),
+            offsets: BTreeMap::new(),
+        });
+
+        if checkpoints.destination.topic_name != metadata.topic_name
+            || checkpoints.destination.partition_id != metadata.partition_id
+        {
+            // the source partition was mirrored to another partition, the old checkpoints are useless
+            checkpoints.destination = topic_partition!(metadata.topic_name.clone(), metadata.partition_id);
+            checkpoints.offsets.clear();
+        }
+
+        checkpoints.offsets.insert(source_offset, metadata.offset);
+
+        while checkpoints.offsets.len() > self.max_checkpoints {
+            let oldest = *checkpoints.offsets.keys().next().unwrap();
+
+            checkpoints.offsets.remove(&oldest);
+        }
+    }
+
+    /// Translate the committed offset of the source partition to the destination partition.
+    ///
+    /// The committed offset is the offset of the next record to consume, if it was not mirrored,
+    /// the offset after the last mirrored record before it will be used, so the records may be consumed again
+    /// but never be skipped. Returns `None` if the offset is older than the checkpoints.
+    pub fn translate(&self, source: &TopicPartition<'a>, offset: Offset) -> Option<(TopicPartition<'a>, Offset)> {
+        if offset < 0 {
+            return None;
+        }
+
+        let partitions = self.partitions.borrow();
+        let checkpoints = partitions.get(source)?;
+
+        checkpoints
+            .offsets
+            .range(..offset + 1)
+            .next_back()
+            .map(|(&source_offset, &destination_offset)| {
+                let offset = if source_offset == offset {
+                    destination_offset
+                } else {
+                    destination_offset + 1
+                };
+
+                (checkpoints.destination.clone(), offset)
+            })
+    }
+
+    /// Translate the committed offsets, the offsets which could not be translated are skipped.
+    pub fn translate_offsets<I>(&self, offsets: I) -> Vec<(TopicPartition<'a>, OffsetAndMetadata)>
+    where
+        I: IntoIterator<Item = (TopicPartition<'a>, OffsetAndMetadata)>,
+    {
+        offsets
+            .into_iter()
+            .filter_map(|(tp, committed)| match self.translate(&tp, committed.offset) {
+                Some((destination, offset)) => Some((
+                    destination,
+                    OffsetAndMetadata::with_metadata(offset, committed.metadata),
+                )),
+                None => {
+                    warn!("fail to translate offset {} of {:?}", committed.offset, tp);
+
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Fetch the committed offsets of the group from the source cluster, and translate them.
+    pub fn translate_group<S, I>(&self, source: &KafkaClient<'a>, group_id: S, partitions: I) -> TranslateGroup<'a>
+    where
+        S: Into<String>,
+        I: 'static + IntoIterator<Item = TopicPartition<'a>>,
+        Self: 'static,
+    {
+        let translator = self.clone();
+        let client = source.clone();
+        let generation = simple_generation(group_id.into());
+
+        source
+            .group_coordinator(generation.group_id.clone().into())
+            .and_then(move |coordinator| client.offset_fetch(coordinator.as_ref(), generation, partitions))
+            .map(move |committed| {
+                let offsets = committed.into_iter().flat_map(|(topic_name, partitions)| {
+                    partitions
+                        .into_iter()
+                        .filter(|partition| partition.error_code == KafkaCode::None && partition.offset >= 0)
+                        .map(move |partition| {
+                            (
+                                topic_partition!(topic_name.clone(), partition.partition_id),
+                                OffsetAndMetadata::with_metadata(partition.offset, partition.metadata),
+                            )
+                        })
+                });
+
+                translator.translate_offsets(offsets)
+            })
+            .static_boxed()
+    }
+
+    /// Translate the committed offsets of the group, and commit them to the destination cluster.
+    ///
+    /// The group should not have active members in the destination cluster.
+    pub fn commit_group<S, I>(
+        &self,
+        source: &KafkaClient<'a>,
+        destination: &KafkaClient<'a>,
+        group_id: S,
+        partitions: I,
+    ) -> CommitGroup<'a>
+    where
+        S: Into<String>,
+        I: 'static + IntoIterator<Item = TopicPartition<'a>>,
+        Self: 'static,
+    {
+        let group_id = group_id.into();
+        let client = destination.clone();
+
+        self.translate_group(source, group_id.clone(), partitions)
+            .and_then(move |offsets| {
+                let generation = simple_generation(group_id);
+                let committing = offsets.clone();
+
+                client
+                    .group_coordinator(generation.group_id.clone().into())
+                    .and_then(move |coordinator| {
+                        client.offset_commit(Some(coordinator.as_ref()), Some(generation), None, committing)
+                    })
+                    .and_then(move |committed| -> Result<Vec<_>> {
+                        for (topic_name, partitions) in committed {
+                            for partition in partitions {
+                                if partition.error_code != KafkaCode::None {
+                                    warn!(
+                                        "fail to commit translated offset of {}#{}, {:?}",
+                                        topic_name, partition.partition_id, partition.error_code
+                                    );
+
+                                    bail!(ErrorKind::KafkaError(partition.error_code))
+                                }
+                            }
+                        }
+
+                        Ok(offsets)
+                    })
+            })
+            .static_boxed()
+    }
+}
+
+/// The generation of a group committing or fetching offsets without joining it.
+fn simple_generation(group_id: String) -> Generation {
+    Generation {
+        group_id,
+        generation_id: -1,
+        member_id: String::new(),
+        protocol: String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn metadata(offset: Offset) -> RecordMetadata {
+        RecordMetadata {
+            topic_name: "mirrored".to_owned(),
+            partition_id: 1,
+            offset,
+            timestamp: 0,
+            serialized_key_size: 0,
+            serialized_value_size: 0,
+            serialized_key_hash: None,
+            latency: Duration::default(),
+        }
+    }
+
+    #[test]
+    fn test_translate() {
+        let translator = OffsetTranslator::new().with_max_checkpoints(3);
+        let source = topic_partition!("topic", 0);
+        let destination = topic_partition!("mirrored", 1);
+
+        assert_eq!(translator.translate(&source, 10), None);
+
+        translator.record(source.clone(), 10, &metadata(100));
+        translator.record(source.clone(), 12, &metadata(101));
+        translator.record(source.clone(), 13, &metadata(102));
+
+        assert_eq!(translator.translate(&source, 9), None);
+        assert_eq!(translator.translate(&source, 10), Some((destination.clone(), 100)));
+        assert_eq!(translator.translate(&source, 11), Some((destination.clone(), 101)));
+        assert_eq!(translator.translate(&source, 12), Some((destination.clone(), 101)));
+        assert_eq!(translator.translate(&source, 14), Some((destination.clone(), 103)));
+        assert_eq!(translator.translate(&topic_partition!("topic", 1), 10), None);
+
+        translator.record(source.clone(), 14, &metadata(103));
+
+        assert_eq!(translator.translate(&source, 10), None);
+        assert_eq!(translator.translate(&source, 12), Some((destination.clone(), 101)));
+
+        assert_eq!(
+            translator.translate_offsets(vec![
+                (source.clone(), OffsetAndMetadata::with_metadata(13, Some("meta".to_owned()))),
+                (source.clone(), OffsetAndMetadata::new(10)),
+            ]),
+            vec![(destination, OffsetAndMetadata::with_metadata(102, Some("meta".to_owned())))]
+        );
+    }
+}
@@ -0,0 +1,687 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use std::rc::Rc;
+use std::time::Duration;
+
+use futures::future;
+use futures::{Future, Stream};
+use tokio_core::net::TcpListener;
+use tokio_core::reactor::Handle;
+use tokio_io::{AsyncRead, AsyncWrite};
+
+#[cfg(feature = "simulation")]
+use client::Simulation;
+use client::{Runtime, StaticBoxFuture, ToStaticBoxFuture};
+use compression::Compression;
+use errors::{Error, ErrorKind, Result};
+use mock::{MockCodec, MockRequest, MockResponse};
+#[cfg(feature = "simulation")]
+use network::DEFAULT_PORT;
+use protocol::{ApiKeys, ApiVersionsRequest, ApiVersionsResponse, BrokerMetadata, ErrorCode, FetchPartitionData,
+               FetchRequest, FetchResponse, FetchTopicData, KafkaCode, Message, MessageSet, MetadataRequest,
+               MetadataResponse, NodeId, PartitionId, PartitionMetadata, ProducePartitionStatus, ProduceRequest,
+               ProduceResponse, ProduceTopicStatus, Record, ResponseHeader, TopicMetadata, UsableApiVersion};
+
+/// An in-process Kafka cluster, which speaks enough of the protocol to test the clients without a real broker.
+///
+/// The brokers listen on the loopback interface, or in a `Simulation` with the feature `simulation`,
+/// and serve the `ApiVersions`, `Metadata`, `Produce` and `Fetch` requests from the in-memory partition logs,
+/// the connections sending the other requests will be closed.
+///
+/// The errors could be injected into the partitions to test the retries and the metadata refreshing.
+///
+/// The cluster is shared between the clones.
+#[derive(Clone)]
+pub struct MockCluster {
+    inner: Rc<RefCell<MockClusterInner>>,
+    runtime: Runtime,
+}
+
+struct MockClusterInner {
+    brokers: Vec<(NodeId, SocketAddr)>,
+    topics: HashMap<String, Vec<MockPartition>>,
+    errors: Vec<InjectedError>,
+}
+
+struct MockPartition {
+    leader: NodeId,
+    messages: Vec<Message>,
+}
+
+struct InjectedError {
+    api_key: ApiKeys,
+    topic_name: String,
+    partition_id: PartitionId,
+    error_code: KafkaCode,
+    times: usize,
+}
+
+impl MockCluster {
+    /// Start a cluster with the number of brokers on the event loop.
+    pub fn new(brokers: usize, handle: &Handle) -> Result<Self> {
+        let cluster = MockCluster::empty(brokers, Runtime::from(handle.clone()));
+
+        for node_id in 0..brokers as NodeId {
+            let listener = TcpListener::bind(&([127, 0, 0, 1], 0).into(), handle)?;
+            let addr = listener.local_addr()?;
+
+            cluster.serve(node_id, addr, listener.incoming());
+        }
+
+        Ok(cluster)
+    }
+
+    /// Start a cluster with the number of brokers in the simulation.
+    ///
+    /// The brokers listen on `10.0.0.1:9092`, `10.0.0.2:9092` and so on,
+    /// which are connected with the `SimulatedTransport` of the simulation.
+    #[cfg(feature = "simulation")]
+    pub fn simulated(brokers: usize, simulation: &Simulation) -> Result<Self> {
+        let cluster = MockCluster::empty(brokers, simulation.runtime());
+
+        for node_id in 0..brokers as NodeId {
+            let addr = SocketAddr::from(([10, 0, 0, node_id as u8 + 1], DEFAULT_PORT));
+            let listener = simulation.listen(addr)?;
+
+            cluster.serve(node_id, addr, listener);
+        }
+
+        Ok(cluster)
+    }
+
+    fn empty(brokers: usize, runtime: Runtime) -> Self {
+        MockCluster {
+            inner: Rc::new(RefCell::new(MockClusterInner {
+                brokers: Vec::with_capacity(brokers),
+                topics: HashMap::new(),
+                errors: Vec::new(),
+            })),
+            runtime,
+        }
+    }
+
+    /// The addresses of the brokers, which could be used as the bootstrap servers.
+    pub fn bootstrap_servers(&self) -> Vec<String> {
+        self.inner
+            .borrow()
+            .brokers
+            .iter()
+            .map(|&(_, addr)| addr.to_string())
+            .collect()
+    }
+
+    /// Create a topic with the number of partitions, the leaders are assigned to the brokers in turn.
+    pub fn create_topic<S: Into<String>>(&self, topic_name: S, partitions: usize) {
+        let mut inner = self.inner.borrow_mut();
+        let leaders = inner.brokers.iter().map(|&(node_id, _)| node_id).collect::<Vec<_>>();
+
+        inner.topics.insert(
+            topic_name.into(),
+            (0..partitions)
+                .map(|partition_id| MockPartition {
+                    leader: leaders[partition_id % leaders.len()],
+                    messages: Vec::new(),
+                })
+                .collect(),
+        );
+    }
+
+    /// Move the leader of the partition to another broker.
+    ///
+    /// The old leader will reply `NotLeaderForPartition` until the client refreshes its metadata.
+    pub fn move_leader(&self, topic_name: &str, partition_id: PartitionId, leader: NodeId) -> Result<()> {
+        let mut inner = self.inner.borrow_mut();
+
+        if !inner.brokers.iter().any(|&(node_id, _)| node_id == leader) {
+            bail!(ErrorKind::IllegalArgument(format!("unknown broker #{}", leader)))
+        }
+
+        inner
+            .topics
+            .get_mut(topic_name)
+            .and_then(|partitions| partitions.get_mut(partition_id as usize))
+            .map(|partition| partition.leader = leader)
+            .ok_or_else(|| ErrorKind::TopicNotFound(topic_name.to_owned()).into())
+    }
+
+    /// Reply the error to the next request of the API for the partition.
+    pub fn inject_error(&self, api_key: ApiKeys, topic_name: &str, partition_id: PartitionId, error_code: KafkaCode) {
+        self.inject_errors(api_key, topic_name, partition_id, error_code, 1)
+    }
+
+    /// Reply the error to the next `times` requests of the API for the partition.
+    pub fn inject_errors(
+        &self,
+        api_key: ApiKeys,
+        topic_name: &str,
+        partition_id: PartitionId,
+        error_code: KafkaCode,
+        times: usize,
+    ) {
+        self.inner.borrow_mut().errors.push(InjectedError {
+            api_key,
+            topic_name: topic_name.to_owned(),
+            partition_id,
+            error_code,
+            times,
+        })
+    }
+
+    /// The messages appended to the partition.
+    pub fn messages(&self, topic_name: &str, partition_id: PartitionId) -> Vec<Message> {
+        self.inner
+            .borrow()
+            .topics
+            .get(topic_name)
+            .and_then(|partitions| partitions.get(partition_id as usize))
+            .map(|partition| partition.messages.clone())
+            .unwrap_or_default()
+    }
+
+    fn serve<S, T>(&self, node_id: NodeId, addr: SocketAddr, incoming: S)
+    where
+        S: 'static + Stream<Item = (T, SocketAddr), Error = io::Error>,
+        T: 'static + AsyncRead + AsyncWrite,
+    {
+        debug!("mock broker #{} listening on {}", node_id, addr);
+
+        self.inner.borrow_mut().brokers.push((node_id, addr));
+
+        let cluster = self.clone();
+        let runtime = self.runtime.clone();
+
+        let serving = incoming
+            .for_each(move |(socket, peer)| {
+                debug!("mock broker #{} accepted connection from {}", node_id, peer);
+
+                let cluster = cluster.clone();
+                let (responses, requests) = socket.framed(MockCodec).split();
+
+                let conn = requests
+                    .map_err(Error::from)
+                    .and_then(move |request| cluster.reply(node_id, request))
+                    .filter_map(|response| response)
+                    .forward(responses)
+                    .map(|_| ())
+                    .map_err(move |err| debug!("mock broker #{} closed connection from {}, {}", node_id, peer, err));
+
+                runtime.spawn(conn);
+
+                Ok(())
+            })
+            .map_err(move |err| warn!("mock broker #{} stopped, {}", node_id, err));
+
+        self.runtime.spawn(serving);
+    }
+
+    fn reply(&self, node_id: NodeId, request: MockRequest) -> StaticBoxFuture<Option<MockResponse>> {
+        trace!("mock broker #{} received request: {:?}", node_id, request);
+
+        match request {
+            MockRequest::ApiVersions(req) => future::ok(Some(self.api_versions(&req))).static_boxed(),
+            MockRequest::Metadata(req) => future::ok(Some(self.metadata(&req))).static_boxed(),
+            MockRequest::Produce(req) => {
+                let res = self.produce(node_id, &req);
+
+                // the broker doesn't send any response when the acks is zero
+                future::ok(if req.required_acks == 0 { None } else { Some(res) }).static_boxed()
+            }
+            MockRequest::Fetch(req) => {
+                let res = self.fetch(node_id, &req);
+                let is_empty = match res {
+                    MockResponse::Fetch(_, ref res) => res.topics
+                        .iter()
+                        .all(|topic| topic.partitions.iter().all(|partition| partition.message_set.is_empty())),
+                    _ => true,
+                };
+
+                if is_empty && req.max_wait_time > 0 {
+                    // wait for the max wait time like the broker, instead of letting the consumer spin
+                    self.runtime
+                        .sleep(Duration::from_millis(req.max_wait_time as u64))
+                        .map(move |_| Some(res))
+                        .static_boxed()
+                } else {
+                    future::ok(Some(res)).static_boxed()
+                }
+            }
+            MockRequest::Unsupported(header) => future::err(
+                ErrorKind::ProtocolError(format!(
+                    "unsupported {:?} request",
+                    ApiKeys::from(header.api_key)
+                )).into(),
+            ).static_boxed(),
+        }
+    }
+
+    fn api_versions(&self, req: &ApiVersionsRequest) -> MockResponse {
+        let api_version = |api_key, min_version, max_version| UsableApiVersion {
+            api_key,
+            min_version,
+            max_version,
+        };
+
+        MockResponse::ApiVersions(ApiVersionsResponse {
+            header: ResponseHeader {
+                correlation_id: req.header.correlation_id,
+            },
+            error_code: KafkaCode::None as ErrorCode,
+            api_versions: vec![
+                api_version(ApiKeys::Produce, 0, 2),
+                api_version(ApiKeys::Fetch, 0, 3),
+                api_version(ApiKeys::Metadata, 0, 5),
+                api_version(ApiKeys::ApiVersions, 0, 0),
+            ],
+        })
+    }
+
+    fn metadata(&self, req: &MetadataRequest) -> MockResponse {
+        let inner = self.inner.borrow();
+        let topic_names = if req.topic_names.is_empty() {
+            inner.topics.keys().cloned().collect()
+        } else {
+            req.topic_names.iter().map(|topic_name| topic_name.to_string()).collect::<Vec<_>>()
+        };
+
+        MockResponse::Metadata(
+            req.header.api_version,
+            MetadataResponse {
+                header: ResponseHeader {
+                    correlation_id: req.header.correlation_id,
+                },
+                throttle_time: Some(0),
+                brokers: inner
+                    .brokers
+                    .iter()
+                    .map(|&(node_id, addr)| BrokerMetadata {
+                        node_id,
+                        host: addr.ip().to_string(),
+                        port: i32::from(addr.port()),
+                        rack: None,
+                    })
+                    .collect(),
+                cluster_id: Some("mock-cluster".to_owned()),
+                controller_id: inner.brokers.first().map(|&(node_id, _)| node_id),
+                topics: topic_names
+                    .into_iter()
+                    .map(|topic_name| match inner.topics.get(&topic_name) {
+                        Some(partitions) => TopicMetadata {
+                            error_code: KafkaCode::None as ErrorCode,
+                            topic_name,
+                            is_internal: false,
+                            partitions: partitions
+                                .iter()
+                                .enumerate()
+                                .map(|(partition_id, partition)| PartitionMetadata {
+                                    error_code: KafkaCode::None as ErrorCode,
+                                    partition_id: partition_id as PartitionId,
+                                    leader: partition.leader,
+                                    replicas: vec![partition.leader],
+                                    isr: vec![partition.leader],
+                                    offline_replicas: vec![],
+                                })
+                                .collect(),
+                        },
+                        None => TopicMetadata {
+                            error_code: KafkaCode::UnknownTopicOrPartition as ErrorCode,
+                            topic_name,
+                            is_internal: false,
+                            partitions: vec![],
+                        },
+                    })
+                    .collect(),
+            },
+        )
+    }
+
+    fn produce(&self, node_id: NodeId, req: &ProduceRequest) -> MockResponse {
+        let mut inner = self.inner.borrow_mut();
+
+        let topics = req.topics
+            .iter()
+            .map(|topic| ProduceTopicStatus {
+                topic_name: topic.topic_name.to_string(),
+                partitions: topic
+                    .partitions
+                    .iter()
+                    .map(|data| {
+                        let (error_code, offset) =
+                            match inner.check(ApiKeys::Produce, node_id, &topic.topic_name, data.partition_id) {
+                                Err(code) => (code, -1),
+                                Ok(partition) => {
+                                    let base_offset = partition.messages.len() as i64;
+
+                                    partition
+                                        .messages
+                                        .extend(data.message_set.messages.iter().enumerate().map(|(i, message)| {
+                                            Message {
+                                                offset: base_offset + i as i64,
+                                                compression: Compression::None,
+                                                ..message.clone()
+                                            }
+                                        }));
+
+                                    (KafkaCode::None, base_offset)
+                                }
+                            };
+
+                        ProducePartitionStatus {
+                            partition_id: data.partition_id,
+                            error_code: error_code as ErrorCode,
+                            offset,
+                            timestamp: Some(-1),
+                        }
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        MockResponse::Produce(
+            req.header.api_version,
+            ProduceResponse {
+                header: ResponseHeader {
+                    correlation_id: req.header.correlation_id,
+                },
+                topics,
+                throttle_time: Some(0),
+            },
+        )
+    }
+
+    fn fetch(&self, node_id: NodeId, req: &FetchRequest) -> MockResponse {
+        let mut inner = self.inner.borrow_mut();
+
+        let topics = req.topics
+            .iter()
+            .map(|topic| FetchTopicData {
+                topic_name: topic.topic_name.to_string(),
+                partitions: topic
+                    .partitions
+                    .iter()
+                    .map(|fetch| {
+                        let (error_code, high_watermark, messages) =
+                            match inner.check(ApiKeys::Fetch, node_id, &topic.topic_name, fetch.partition_id) {
+                                Err(code) => (code, -1, vec![]),
+                                Ok(ref partition) if fetch.fetch_offset > partition.messages.len() as i64 => {
+                                    (KafkaCode::OffsetOutOfRange, partition.messages.len() as i64, vec![])
+                                }
+                                Ok(partition) => {
+                                    let mut size = 0;
+                                    let messages = partition.messages[fetch.fetch_offset as usize..]
+                                        .iter()
+                                        .take_while(|message| {
+                                            // returns at least one message even if it is larger than the max bytes
+                                            let fits = size == 0 || size + message.size(1) <= fetch.max_bytes as usize;
+                                            size += message.size(1);
+                                            fits
+                                        })
+                                        .cloned()
+                                        .collect();
+
+                                    (KafkaCode::None, partition.messages.len() as i64, messages)
+                                }
+                            };
+
+                        FetchPartitionData {
+                            partition_id: fetch.partition_id,
+                            error_code: error_code as ErrorCode,
+                            high_watermark,
+                            last_stable_offset: None,
+                            log_start_offset: None,
+                            aborted_transactions: None,
+                            preferred_read_replica: None,
+                            message_set: MessageSet { messages },
+                        }
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        MockResponse::Fetch(
+            req.header.api_version,
+            FetchResponse {
+                header: ResponseHeader {
+                    correlation_id: req.header.correlation_id,
+                },
+                throttle_time: Some(0),
+                error_code: None,
+                session_id: None,
+                topics,
+            },
+        )
+    }
+}
+
+impl MockClusterInner {
+    /// Find the partition led by the broker, or the error should be replied.
+    fn check(
+        &mut self,
+        api_key: ApiKeys,
+        node_id: NodeId,
+        topic_name: &str,
+        partition_id: PartitionId,
+    ) -> ::std::result::Result<&mut MockPartition, KafkaCode> {
+        if let Some(pos) = self.errors.iter().position(|err| {
+            err.api_key == api_key && err.topic_name == topic_name && err.partition_id == partition_id
+        }) {
+            let error_code = self.errors[pos].error_code;
+
+            self.errors[pos].times -= 1;
+
+            if self.errors[pos].times == 0 {
+                self.errors.remove(pos);
+            }
+
+            return Err(error_code);
+        }
+
+        match self.topics
+            .get_mut(topic_name)
+            .and_then(|partitions| partitions.get_mut(partition_id as usize))
+        {
+            Some(ref partition) if partition.leader != node_id => Err(KafkaCode::NotLeaderForPartition),
+            Some(partition) => Ok(partition),
+            None => Err(KafkaCode::UnknownTopicOrPartition),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use bytes::Bytes;
+    use tokio_core::reactor::Core;
+
+    use super::*;
+
+    use client::{Client, ClientListener, Cluster, KafkaClient, Metadata};
+    use network::TopicPartition;
+    use producer::{KafkaProducer, Producer, ProducerRecord};
+    use protocol::{MessageTimestamp, RequiredAcks};
+    use serialization::StringSerializer;
+
+    fn message_set(value: &'static [u8]) -> Cow<'static, MessageSet> {
+        Cow::Owned(MessageSet {
+            messages: vec![
+                Message {
+                    offset: 0,
+                    timestamp: Some(MessageTimestamp::CreateTime(123)),
+                    compression: Compression::None,
+                    key: None,
+                    value: Some(Bytes::from(value)),
+                    leader_epoch: None,
+                },
+            ],
+        })
+    }
+
+    #[test]
+    fn test_produce_and_fetch() {
+        let mut core = Core::new().unwrap();
+        let cluster = MockCluster::new(2, &core.handle()).unwrap();
+
+        cluster.create_topic("topic", 2);
+
+        let client = KafkaClient::with_bootstrap_servers(cluster.bootstrap_servers(), core.handle())
+            .build()
+            .unwrap();
+
+        let metadata = core.run(client.metadata()).unwrap();
+
+        assert_eq!(metadata.brokers().len(), 2);
+        assert_eq!(metadata.partitions_for_topic("topic").map(|partitions| partitions.len()), Some(2));
+
+        let tp = TopicPartition {
+            topic_name: "topic".into(),
+            partition_id: 1,
+        };
+
+        let records = vec![message_set(b"foo")];
+
+        core.run(client.produce_records(RequiredAcks::One, Duration::from_secs(1), tp.clone(), records))
+            .unwrap();
+
+        assert_eq!(cluster.messages("topic", 1).len(), 1);
+        assert!(cluster.messages("topic", 0).is_empty());
+
+        let fetched = core.run(client.fetch_partition_records(tp.clone(), 0, 1024)).unwrap();
+
+        assert_eq!(fetched.high_watermark, 1);
+        assert_eq!(fetched.messages.len(), 1);
+        assert_eq!(fetched.messages[0].value, Some(Bytes::from(&b"foo"[..])));
+
+        // the injected error is replied only once
+        cluster.inject_error(ApiKeys::Fetch, "topic", 1, KafkaCode::NotLeaderForPartition);
+
+        match core.run(client.fetch_partition_records(tp.clone(), 0, 1024)) {
+            Err(Error(ErrorKind::KafkaError(KafkaCode::NotLeaderForPartition), _)) => {}
+            res => panic!("unexpected result: {:?}", res),
+        }
+
+        assert!(core.run(client.fetch_partition_records(tp.clone(), 0, 1024)).is_ok());
+    }
+
+    #[derive(Clone, Default)]
+    struct MetadataRefreshes(Rc<RefCell<Vec<Option<NodeId>>>>);
+
+    impl ClientListener for MetadataRefreshes {
+        fn on_metadata_refresh(&self, metadata: &Metadata) {
+            let tp = topic_partition!("topic", 0);
+
+            self.0
+                .borrow_mut()
+                .push(metadata.leader_for(&tp).map(|broker| broker.id()))
+        }
+    }
+
+    #[test]
+    fn test_produce_to_moved_leader() {
+        let mut core = Core::new().unwrap();
+        let cluster = MockCluster::new(2, &core.handle()).unwrap();
+        let refreshes = MetadataRefreshes::default();
+
+        cluster.create_topic("topic", 1);
+
+        let mut builder = KafkaClient::with_bootstrap_servers(cluster.bootstrap_servers(), core.handle())
+            .with_listener(refreshes.clone());
+        builder.retries = 1;
+        let client = builder.build().unwrap();
+
+        core.run(client.metadata()).unwrap();
+
+        assert_eq!(refreshes.0.borrow().last(), Some(&Some(0)));
+
+        let loaded = refreshes.0.borrow().len();
+
+        // the broker #0 replies `NotLeaderForPartition` to the request sent with the cached metadata
+        cluster.move_leader("topic", 0, 1).unwrap();
+
+        let tp = TopicPartition {
+            topic_name: "topic".into(),
+            partition_id: 0,
+        };
+
+        let records = vec![message_set(b"foo")];
+
+        core.run(client.produce_records(RequiredAcks::One, Duration::from_secs(1), tp, records))
+            .unwrap();
+
+        // the metadata is reloaded only once, and the retried request is accepted by the new leader
+        assert_eq!(&refreshes.0.borrow()[loaded..], &[Some(1)]);
+        assert_eq!(cluster.messages("topic", 0).len(), 1);
+    }
+
+    #[test]
+    fn test_producer_send_all() {
+        let mut core = Core::new().unwrap();
+        let cluster = MockCluster::new(2, &core.handle()).unwrap();
+
+        cluster.create_topic("topic", 2);
+
+        let mut producer = KafkaProducer::with_bootstrap_servers(cluster.bootstrap_servers(), core.handle())
+            .with_key_serializer(StringSerializer::default())
+            .with_value_serializer(StringSerializer::default())
+            .with_default_partitioner()
+            .build()
+            .unwrap();
+
+        let records = vec![
+            ProducerRecord::from_value("topic", "foo".to_owned()).with_partition(1),
+            ProducerRecord::from_value("topic", "bar".to_owned()).with_partition(0),
+            ProducerRecord::from_value("topic", "baz".to_owned()).with_partition(1),
+        ];
+
+        let metadata = core.run(producer.send_all(records)).unwrap();
+
+        assert_eq!(
+            metadata
+                .iter()
+                .map(|metadata| (metadata.partition_id, metadata.offset))
+                .collect::<Vec<_>>(),
+            vec![(1, 0), (0, 0), (1, 1)]
+        );
+        assert_eq!(cluster.messages("topic", 0).len(), 1);
+        assert_eq!(cluster.messages("topic", 1).len(), 2);
+    }
+
+    #[test]
+    fn test_producer_produce() {
+        let mut core = Core::new().unwrap();
+        let cluster = MockCluster::new(1, &core.handle()).unwrap();
+
+        cluster.create_topic("topic", 1);
+
+        let producer = KafkaProducer::with_bootstrap_servers(cluster.bootstrap_servers(), core.handle())
+            .with_key_serializer(StringSerializer::default())
+            .with_value_serializer(StringSerializer::default())
+            .with_default_partitioner()
+            .with_delivery_reports(true)
+            .build()
+            .unwrap();
+        let reports = producer.delivery_reports().unwrap();
+
+        // the first record waits for the metadata of the topic in a background task
+        let first = producer
+            .produce(ProducerRecord::from_value("topic", "foo".to_owned()))
+            .unwrap();
+
+        let (report, reports) = core.run(reports.into_future()).ok().unwrap();
+
+        assert_eq!(report.map(|report| (report.id, report.result.is_ok())), Some((first, true)));
+
+        // the record is pushed at once with the cached metadata, before the producer has been closed
+        let second = producer
+            .produce(ProducerRecord::from_value("topic", "bar".to_owned()))
+            .unwrap();
+
+        core.run(producer.close(Duration::from_secs(1))).unwrap();
+
+        let (report, _) = core.run(reports.into_future()).ok().unwrap();
+
+        assert_eq!(report.map(|report| (report.id, report.result.is_ok())), Some((second, true)));
+        assert_eq!(cluster.messages("topic", 0).len(), 2);
+    }
+}
@@ -0,0 +1,559 @@
+use std::borrow::Cow;
+use std::io;
+use std::mem;
+
+use bytes::{BigEndian, BufMut, ByteOrder, BytesMut};
+
+use nom::{IResult, be_i16, be_i32, be_i64, be_u8};
+
+use tokio_io::codec::{Decoder, Encoder};
+
+use errors::Result;
+use protocol::{display_parse_error, parse_frame, parse_message_set, parse_opt_string, parse_string, ApiKeys, ApiVersion,
+               ApiVersionsRequest, ApiVersionsResponse, FetchPartition, FetchRequest, FetchResponse, FetchTopic,
+               MessageSetEncoder, MetadataRequest, MetadataResponse, ProducePartitionData, ProduceRequest,
+               ProduceResponse, ProduceTopicData, RequestHeader, WriteExt, FINAL_SESSION_EPOCH, INVALID_SESSION_ID,
+               READ_UNCOMMITTED, UNDEFINED_LEADER_EPOCH};
+
+/// The request received by the mock broker.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MockRequest {
+    Produce(ProduceRequest<'static>),
+    Fetch(FetchRequest<'static>),
+    Metadata(MetadataRequest<'static>),
+    ApiVersions(ApiVersionsRequest<'static>),
+    /// The request of an API not served by the mock broker, only the header is parsed.
+    Unsupported(RequestHeader<'static>),
+}
+
+impl MockRequest {
+    pub fn header(&self) -> &RequestHeader<'static> {
+        match *self {
+            MockRequest::Produce(ref req) => &req.header,
+            MockRequest::Fetch(ref req) => &req.header,
+            MockRequest::Metadata(ref req) => &req.header,
+            MockRequest::ApiVersions(ref req) => &req.header,
+            MockRequest::Unsupported(ref header) => header,
+        }
+    }
+
+    pub fn parse(buf: &[u8]) -> IResult<&[u8], Self> {
+        match parse_request_header(buf) {
+            IResult::Done(remaining, header) => match ApiKeys::from(header.api_key) {
+                ApiKeys::Produce => parse_produce_request(remaining, header).map(MockRequest::Produce),
+                ApiKeys::Fetch => parse_fetch_request(remaining, header).map(MockRequest::Fetch),
+                ApiKeys::Metadata => parse_metadata_request(remaining, header).map(MockRequest::Metadata),
+                ApiKeys::ApiVersions => {
+                    IResult::Done(remaining, MockRequest::ApiVersions(ApiVersionsRequest { header }))
+                }
+                _ => IResult::Done(&remaining[remaining.len()..], MockRequest::Unsupported(header)),
+            },
+            IResult::Incomplete(needed) => IResult::Incomplete(needed),
+            IResult::Error(err) => IResult::Error(err),
+        }
+    }
+}
+
+/// The response sent by the mock broker, encoded in the version of the request.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MockResponse {
+    Produce(ApiVersion, ProduceResponse),
+    Fetch(ApiVersion, FetchResponse),
+    Metadata(ApiVersion, MetadataResponse),
+    ApiVersions(ApiVersionsResponse),
+}
+
+impl MockResponse {
+    pub fn encode<T: ByteOrder>(&self, dst: &mut BytesMut) -> Result<()> {
+        match *self {
+            MockResponse::Produce(api_version, ref res) => encode_produce_response::<T>(res, api_version, dst),
+            MockResponse::Fetch(api_version, ref res) => encode_fetch_response::<T>(res, api_version, dst),
+            MockResponse::Metadata(api_version, ref res) => encode_metadata_response::<T>(res, api_version, dst),
+            MockResponse::ApiVersions(ref res) => encode_api_versions_response::<T>(res, dst),
+        }
+    }
+}
+
+/// The server side codec of Kafka protocol, which decodes the requests and encodes the responses.
+#[derive(Debug, Default)]
+pub struct MockCodec;
+
+impl Decoder for MockCodec {
+    type Item = MockRequest;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<Self::Item>> {
+        let size_header_len = mem::size_of::<i32>();
+
+        if src.len() < size_header_len {
+            return Ok(None);
+        }
+
+        let size = BigEndian::read_i32(&src[..]) as usize;
+
+        if size_header_len + size > src.len() {
+            return Ok(None);
+        }
+
+        let buf = src.split_to(size + size_header_len).split_off(size_header_len).freeze();
+
+        match parse_frame(&buf, MockRequest::parse) {
+            IResult::Done(_, request) => Ok(Some(request)),
+            res => {
+                display_parse_error::<MockRequest>(&buf[..], res);
+
+                Err(io::Error::new(io::ErrorKind::InvalidData, "invalid request"))
+            }
+        }
+    }
+}
+
+impl Encoder for MockCodec {
+    type Item = MockResponse;
+    type Error = io::Error;
+
+    fn encode(&mut self, response: Self::Item, dst: &mut BytesMut) -> io::Result<()> {
+        let off = dst.len();
+
+        dst.put_i32::<BigEndian>(0);
+
+        response
+            .encode::<BigEndian>(dst)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, format!("invalid response, {}", err)))?;
+
+        let size = dst.len() - off - mem::size_of::<i32>();
+
+        BigEndian::write_i32(&mut dst[off..off + mem::size_of::<i32>()], size as i32);
+
+        Ok(())
+    }
+}
+
+named!(parse_request_header<RequestHeader<'static>>,
+    do_parse!(
+        api_key: be_i16
+     >> api_version: be_i16
+     >> correlation_id: be_i32
+     >> client_id: parse_opt_string
+     >> (RequestHeader {
+            api_key,
+            api_version,
+            correlation_id,
+            client_id: client_id.map(Cow::from),
+        })
+    )
+);
+
+named_args!(parse_produce_request(header: RequestHeader<'static>)<ProduceRequest<'static>>,
+    do_parse!(
+        required_acks: be_i16
+     >> ack_timeout: be_i32
+     >> topics: length_count!(be_i32, apply!(parse_produce_topic_data, header.api_version))
+     >> (ProduceRequest {
+            header: header.clone(),
+            required_acks,
+            ack_timeout,
+            topics,
+        })
+    )
+);
+
+named_args!(parse_produce_topic_data(api_version: ApiVersion)<ProduceTopicData<'static>>,
+    do_parse!(
+        topic_name: parse_string
+     >> partitions: length_count!(be_i32, apply!(parse_produce_partition_data, api_version))
+     >> (ProduceTopicData {
+            topic_name: topic_name.into(),
+            partitions,
+        })
+    )
+);
+
+named_args!(parse_produce_partition_data(api_version: ApiVersion)<ProducePartitionData<'static>>,
+    do_parse!(
+        partition_id: be_i32
+     >> message_set: length_value!(be_i32, apply!(parse_message_set, api_version))
+     >> (ProducePartitionData {
+            partition_id,
+            message_set: Cow::Owned(message_set),
+        })
+    )
+);
+
+named_args!(parse_fetch_request(header: RequestHeader<'static>)<FetchRequest<'static>>,
+    do_parse!(
+        replica_id: be_i32
+     >> max_wait_time: be_i32
+     >> min_bytes: be_i32
+     >> max_bytes: cond!(header.api_version > 2, be_i32)
+     >> topics: length_count!(be_i32, parse_fetch_topic)
+     >> (FetchRequest {
+            header: header.clone(),
+            replica_id,
+            max_wait_time,
+            min_bytes,
+            max_bytes: max_bytes.unwrap_or_default(),
+            isolation_level: READ_UNCOMMITTED,
+            session_id: INVALID_SESSION_ID,
+            session_epoch: FINAL_SESSION_EPOCH,
+            topics,
+            forgotten_topics: vec![],
+            rack_id: "".into(),
+        })
+    )
+);
+
+named!(parse_fetch_topic<FetchTopic<'static>>,
+    do_parse!(
+        topic_name: parse_string
+     >> partitions: length_count!(be_i32, parse_fetch_partition)
+     >> (FetchTopic {
+            topic_name: topic_name.into(),
+            partitions,
+        })
+    )
+);
+
+named!(parse_fetch_partition<FetchPartition>,
+    do_parse!(
+        partition_id: be_i32
+     >> fetch_offset: be_i64
+     >> max_bytes: be_i32
+     >> (FetchPartition {
+            partition_id,
+            current_leader_epoch: UNDEFINED_LEADER_EPOCH,
+            fetch_offset,
+            log_start_offset: -1,
+            max_bytes,
+        })
+    )
+);
+
+named_args!(parse_metadata_request(header: RequestHeader<'static>)<MetadataRequest<'static>>,
+    do_parse!(
+        // all the topics are requested with an empty array before v1, or a null array since v1
+        count: be_i32
+     >> topic_names: cond!(count > 0, count!(parse_string, count as usize))
+     >> allow_auto_topic_creation: cond!(header.api_version > 3, be_u8)
+     >> (MetadataRequest {
+            header: header.clone(),
+            topic_names: topic_names.unwrap_or_default().into_iter().map(Cow::from).collect(),
+            allow_auto_topic_creation: allow_auto_topic_creation.map_or(true, |allow| allow != 0),
+        })
+    )
+);
+
+fn encode_produce_response<T: ByteOrder>(
+    res: &ProduceResponse,
+    api_version: ApiVersion,
+    dst: &mut BytesMut,
+) -> Result<()> {
+    dst.put_i32::<T>(res.header.correlation_id);
+    dst.put_array::<T, _, _>(&res.topics, |buf, topic| {
+        buf.put_str::<T, _>(Some(topic.topic_name.as_str()))?;
+        buf.put_array::<T, _, _>(&topic.partitions, |buf, partition| {
+            buf.put_i32::<T>(partition.partition_id);
+            buf.put_i16::<T>(partition.error_code);
+            buf.put_i64::<T>(partition.offset);
+            if api_version > 1 {
+                buf.put_i64::<T>(partition.timestamp.unwrap_or(-1));
+            }
+            Ok(())
+        })
+    })?;
+    if api_version > 0 {
+        dst.put_i32::<T>(res.throttle_time.unwrap_or_default());
+    }
+    Ok(())
+}
+
+fn encode_fetch_response<T: ByteOrder>(
+    res: &FetchResponse,
+    api_version: ApiVersion,
+    dst: &mut BytesMut,
+) -> Result<()> {
+    // the message format v1 with timestamp is returned since v2
+    let encoder = MessageSetEncoder::new(if api_version > 1 { 1 } else { 0 }, None);
+
+    dst.put_i32::<T>(res.header.correlation_id);
+    if api_version > 0 {
+        dst.put_i32::<T>(res.throttle_time.unwrap_or_default());
+    }
+    dst.put_array::<T, _, _>(&res.topics, |buf, topic| {
+        buf.put_str::<T, _>(Some(topic.topic_name.as_str()))?;
+        buf.put_array::<T, _, _>(&topic.partitions, |buf, partition| {
+            buf.put_i32::<T>(partition.partition_id);
+            buf.put_i16::<T>(partition.error_code);
+            buf.put_i64::<T>(partition.high_watermark);
+
+            let size_off = buf.len();
+            buf.put_i32::<T>(0);
+
+            encoder.encode::<T>(&partition.message_set, buf)?;
+
+            let message_set_size = buf.len() - size_off - mem::size_of::<i32>();
+            T::write_i32(&mut buf[size_off..], message_set_size as i32);
+
+            Ok(())
+        })
+    })
+}
+
+fn encode_metadata_response<T: ByteOrder>(
+    res: &MetadataResponse,
+    api_version: ApiVersion,
+    dst: &mut BytesMut,
+) -> Result<()> {
+    dst.put_i32::<T>(res.header.correlation_id);
+    if api_version > 2 {
+        dst.put_i32::<T>(res.throttle_time.unwrap_or_default());
+    }
+    dst.put_array::<T, _, _>(&res.brokers, |buf, broker| {
+        buf.put_i32::<T>(broker.node_id);
+        buf.put_str::<T, _>(Some(broker.host.as_str()))?;
+        buf.put_i32::<T>(broker.port);
+        if api_version > 0 {
+            buf.put_str::<T, _>(broker.rack.as_ref())?;
+        }
+        Ok(())
+    })?;
+    if api_version > 1 {
+        dst.put_str::<T, _>(res.cluster_id.as_ref())?;
+    }
+    if api_version > 0 {
+        dst.put_i32::<T>(res.controller_id.unwrap_or(-1));
+    }
+    dst.put_array::<T, _, _>(&res.topics, |buf, topic| {
+        buf.put_i16::<T>(topic.error_code);
+        buf.put_str::<T, _>(Some(topic.topic_name.as_str()))?;
+        if api_version > 0 {
+            buf.put_i8(topic.is_internal as i8);
+        }
+        buf.put_array::<T, _, _>(&topic.partitions, |buf, partition| {
+            buf.put_i16::<T>(partition.error_code);
+            buf.put_i32::<T>(partition.partition_id);
+            buf.put_i32::<T>(partition.leader);
+            buf.put_array::<T, _, _>(&partition.replicas, |buf, &node_id| {
+                buf.put_i32::<T>(node_id);
+                Ok(())
+            })?;
+            buf.put_array::<T, _, _>(&partition.isr, |buf, &node_id| {
+                buf.put_i32::<T>(node_id);
+                Ok(())
+            })?;
+            if api_version > 4 {
+                buf.put_array::<T, _, _>(&partition.offline_replicas, |buf, &node_id| {
+                    buf.put_i32::<T>(node_id);
+                    Ok(())
+                })?;
+            }
+            Ok(())
+        })
+    })
+}
+
+fn encode_api_versions_response<T: ByteOrder>(res: &ApiVersionsResponse, dst: &mut BytesMut) -> Result<()> {
+    dst.put_i32::<T>(res.header.correlation_id);
+    dst.put_i16::<T>(res.error_code);
+    dst.put_array::<T, _, _>(&res.api_versions, |buf, api_version| {
+        buf.put_i16::<T>(api_version.api_key.key());
+        buf.put_i16::<T>(api_version.min_version);
+        buf.put_i16::<T>(api_version.max_version);
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    use super::*;
+
+    use compression::Compression;
+    use protocol::*;
+
+    fn header(api_key: ApiKeys, api_version: ApiVersion) -> RequestHeader<'static> {
+        RequestHeader {
+            api_key: api_key.key(),
+            api_version,
+            correlation_id: 123,
+            client_id: Some("client".into()),
+        }
+    }
+
+    fn decode<R: Encodable>(request: &R) -> MockRequest {
+        let mut buf = BytesMut::with_capacity(64);
+
+        buf.put_i32::<BigEndian>(0);
+        request.encode::<BigEndian>(&mut buf).unwrap();
+
+        let size = buf.len() - mem::size_of::<i32>();
+        BigEndian::write_i32(&mut buf[..], size as i32);
+
+        MockCodec.decode(&mut buf).unwrap().unwrap()
+    }
+
+    fn encode(response: MockResponse) -> Bytes {
+        let mut buf = BytesMut::with_capacity(64);
+
+        MockCodec.encode(response, &mut buf).unwrap();
+
+        buf.split_off(mem::size_of::<i32>()).freeze()
+    }
+
+    #[test]
+    fn test_decode_requests() {
+        let request = MetadataRequest {
+            header: header(ApiKeys::Metadata, 4),
+            topic_names: vec!["topic".into()],
+            allow_auto_topic_creation: false,
+        };
+
+        assert_eq!(decode(&request), MockRequest::Metadata(request));
+
+        let request = ProduceRequest {
+            header: header(ApiKeys::Produce, 1),
+            required_acks: 1,
+            ack_timeout: 100,
+            topics: vec![
+                ProduceTopicData {
+                    topic_name: "topic".into(),
+                    partitions: vec![
+                        ProducePartitionData {
+                            partition_id: 1,
+                            message_set: Cow::Owned(MessageSet {
+                                messages: vec![
+                                    Message {
+                                        offset: 0,
+                                        timestamp: Some(MessageTimestamp::CreateTime(456)),
+                                        compression: Compression::None,
+                                        key: Some(Bytes::from(&b"key"[..])),
+                                        value: Some(Bytes::from(&b"value"[..])),
+                                        leader_epoch: None,
+                                    },
+                                ],
+                            }),
+                        },
+                    ],
+                },
+            ],
+        };
+
+        assert_eq!(decode(&request), MockRequest::Produce(request));
+
+        let request = FetchRequest {
+            header: header(ApiKeys::Fetch, 3),
+            replica_id: -1,
+            max_wait_time: 100,
+            min_bytes: 1,
+            max_bytes: 1024,
+            isolation_level: READ_UNCOMMITTED,
+            session_id: INVALID_SESSION_ID,
+            session_epoch: FINAL_SESSION_EPOCH,
+            topics: vec![
+                FetchTopic {
+                    topic_name: "topic".into(),
+                    partitions: vec![
+                        FetchPartition {
+                            partition_id: 1,
+                            current_leader_epoch: UNDEFINED_LEADER_EPOCH,
+                            fetch_offset: 2,
+                            log_start_offset: -1,
+                            max_bytes: 512,
+                        },
+                    ],
+                },
+            ],
+            forgotten_topics: vec![],
+            rack_id: "".into(),
+        };
+
+        assert_eq!(decode(&request), MockRequest::Fetch(request));
+
+        let request = ApiVersionsRequest {
+            header: header(ApiKeys::ApiVersions, 0),
+        };
+
+        assert_eq!(decode(&request), MockRequest::ApiVersions(request));
+    }
+
+    #[test]
+    fn test_encode_responses() {
+        let response = MetadataResponse {
+            header: ResponseHeader { correlation_id: 123 },
+            throttle_time: Some(0),
+            brokers: vec![
+                BrokerMetadata {
+                    node_id: 1,
+                    host: "127.0.0.1".to_owned(),
+                    port: 9092,
+                    rack: None,
+                },
+            ],
+            cluster_id: Some("cluster".to_owned()),
+            controller_id: Some(1),
+            topics: vec![
+                TopicMetadata {
+                    error_code: 0,
+                    topic_name: "topic".to_owned(),
+                    is_internal: false,
+                    partitions: vec![
+                        PartitionMetadata {
+                            error_code: 0,
+                            partition_id: 0,
+                            leader: 1,
+                            replicas: vec![1],
+                            isr: vec![1],
+                            offline_replicas: vec![],
+                        },
+                    ],
+                },
+            ],
+        };
+
+        assert_eq!(
+            MetadataResponse::parse(&encode(MockResponse::Metadata(5, response.clone())), 5),
+            IResult::Done(&[][..], response)
+        );
+
+        let response = FetchResponse {
+            header: ResponseHeader { correlation_id: 123 },
+            throttle_time: Some(0),
+            error_code: None,
+            session_id: None,
+            topics: vec![
+                FetchTopicData {
+                    topic_name: "topic".to_owned(),
+                    partitions: vec![
+                        FetchPartitionData {
+                            partition_id: 1,
+                            error_code: 0,
+                            high_watermark: 3,
+                            last_stable_offset: None,
+                            log_start_offset: None,
+                            aborted_transactions: None,
+                            preferred_read_replica: None,
+                            message_set: MessageSet {
+                                messages: vec![
+                                    Message {
+                                        offset: 2,
+                                        timestamp: Some(MessageTimestamp::CreateTime(456)),
+                                        compression: Compression::None,
+                                        key: None,
+                                        value: Some(Bytes::from(&b"value"[..])),
+                                        leader_epoch: None,
+                                    },
+                                ],
+                            },
+                        },
+                    ],
+                },
+            ],
+        };
+
+        assert_eq!(
+            FetchResponse::parse(&encode(MockResponse::Fetch(2, response.clone())), 2, CrcPolicy::Fail),
+            IResult::Done(&[][..], response)
+        );
+    }
+}
@@ -0,0 +1,5 @@
+mod cluster;
+mod codec;
+
+pub use self::cluster::MockCluster;
+pub use self::codec::{MockCodec, MockRequest, MockResponse};
@@ -0,0 +1,234 @@
+use std::cmp;
+use std::time::Duration;
+
+use futures::{Future, Stream};
+
+use client::{Client, Cluster, Generation, KafkaClient, StaticBoxFuture, ToStaticBoxFuture};
+use errors::{Error, ErrorKind};
+use network::TopicPartition;
+use protocol::{FetchOffset, KafkaCode, Offset, PartitionId};
+
+/// The default interval to fetch the consumer lag.
+pub const DEFAULT_LAG_MONITOR_INTERVAL_MILLIS: u64 = 30_000;
+
+/// The consumer lag of a partition.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PartitionLag {
+    /// The topic name
+    pub topic_name: String,
+    /// The partition id
+    pub partition_id: PartitionId,
+    /// The last committed offset of the group, or `None` if the group never committed.
+    pub committed: Option<Offset>,
+    /// The offset of the upcoming record in the partition.
+    pub log_end_offset: Offset,
+}
+
+impl PartitionLag {
+    /// The number of records not yet committed by the group.
+    pub fn lag(&self) -> Option<i64> {
+        self.committed.map(|committed| cmp::max(0, self.log_end_offset - committed))
+    }
+}
+
+/// Monitor the consumer lag of a group without joining it.
+///
+/// The lags will be recorded to the metrics if the client was built `with_metrics`.
+#[derive(Clone)]
+pub struct LagMonitor<'a> {
+    client: KafkaClient<'a>,
+    group_id: String,
+    topic_names: Vec<String>,
+    interval: Duration,
+}
+
+/// The future of fetching the consumer lags.
+pub type FetchLags = StaticBoxFuture<Vec<PartitionLag>>;
+
+/// The stream of the consumer lags fetched periodically.
+pub type MonitorLags = Box<Stream<Item = Vec<PartitionLag>, Error = Error>>;
+
+impl<'a> LagMonitor<'a>
+where
+    Self: 'static,
+{
+    /// Construct a `LagMonitor` for the group and topics
+    pub fn new<S, I>(client: KafkaClient<'a>, group_id: S, topic_names: I) -> Self
+    where
+        S: Into<String>,
+        I: IntoIterator<Item = S>,
+    {
+        LagMonitor {
+            client,
+            group_id: group_id.into(),
+            topic_names: topic_names.into_iter().map(|topic_name| topic_name.into()).collect(),
+            interval: Duration::from_millis(DEFAULT_LAG_MONITOR_INTERVAL_MILLIS),
+        }
+    }
+
+    /// Sets the interval to fetch the consumer lag.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Fetch the committed offsets of the group and the log end offsets of the topics.
+    pub fn fetch_lags(&self) -> FetchLags {
+        let client = self.client.clone();
+        let group_id = self.group_id.clone();
+
+        fetch_group_lag(self.client.clone(), self.group_id.clone(), self.topic_names.clone())
+            .map(move |lags| {
+                if let Some(metrics) = client.metrics() {
+                    for lag in &lags {
+                        if let Some(n) = lag.lag() {
+                            metrics.consumer_lag(&group_id, &lag.topic_name, lag.partition_id, n);
+                        }
+                    }
+                }
+
+                lags
+            })
+            .static_boxed()
+    }
+
+    /// Fetch the consumer lags periodically.
+    ///
+    /// The failed fetches will be yielded as errors without terminating the stream.
+    pub fn run(self) -> MonitorLags {
+        debug!(
+            "monitor lag of the `{}` group per {} seconds",
+            self.group_id,
+            self.interval.as_secs()
+        );
+
+        let monitor = self.clone();
+
+        Box::new(
+            self.client
+                .runtime()
+                .interval(Duration::default(), self.interval)
+                .and_then(move |_| monitor.fetch_lags()),
+        )
+    }
+}
+
+/// Fetch the committed offsets of the group and the log end offsets of the topics.
+///
+/// The committed offsets are fetched without joining the group.
+pub fn fetch_group_lag<'a, C>(client: C, group_id: String, topic_names: Vec<String>) -> FetchLags
+where
+    C: Client<'a> + Clone,
+{
+    client
+        .metadata()
+        .and_then(move |metadata| {
+            let mut partitions = Vec::new();
+
+            for topic_name in &topic_names {
+                match metadata.partitions_for_topic(topic_name) {
+                    Some(topic_partitions) => partitions.extend(
+                        topic_partitions
+                            .into_iter()
+                            .map(|tp| topic_partition!(tp.topic_name.into_owned(), tp.partition_id)),
+                    ),
+                    None => bail!(ErrorKind::TopicNotFound(topic_name.clone())),
+                }
+            }
+
+            Ok(partitions)
+        })
+        .and_then(move |partitions: Vec<TopicPartition<'a>>| {
+            // the committed offsets could be fetched without joining the group
+            let generation = Generation {
+                group_id: group_id.clone(),
+                generation_id: -1,
+                member_id: String::new(),
+                protocol: String::new(),
+            };
+
+            let committed = {
+                let client = client.clone();
+                let partitions = partitions.clone();
+
+                client
+                    .group_coordinator(group_id.clone().into())
+                    .and_then(move |coordinator| client.offset_fetch(coordinator.as_ref(), generation, partitions))
+            };
+            let latest = client.list_offsets(partitions.into_iter().map(|tp| (tp, FetchOffset::Latest)));
+
+            committed.join(latest).map(move |(committed, latest)| {
+                let mut lags = Vec::new();
+
+                for (topic_name, partitions) in latest {
+                    for partition in partitions {
+                        let log_end_offset = match partition.latest() {
+                            Some(offset) if partition.error_code == KafkaCode::None => offset,
+                            _ => {
+                                warn!(
+                                    "fail to list offset of {}#{}, {:?}",
+                                    topic_name, partition.partition_id, partition.error_code
+                                );
+
+                                continue;
+                            }
+                        };
+
+                        let committed = committed
+                            .get(&topic_name)
+                            .and_then(|offsets| {
+                                offsets
+                                    .iter()
+                                    .find(|offset| offset.partition_id == partition.partition_id)
+                            })
+                            .and_then(|offset| {
+                                if offset.error_code == KafkaCode::None && offset.offset >= 0 {
+                                    Some(offset.offset)
+                                } else {
+                                    None
+                                }
+                            });
+
+                        lags.push(PartitionLag {
+                            topic_name: topic_name.clone(),
+                            partition_id: partition.partition_id,
+                            committed,
+                            log_end_offset,
+                        });
+                    }
+                }
+
+                lags.sort_by(|lhs, rhs| {
+                    (&lhs.topic_name, lhs.partition_id).cmp(&(&rhs.topic_name, rhs.partition_id))
+                });
+
+                lags
+            })
+        })
+        .static_boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partition_lag() {
+        let mut lag = PartitionLag {
+            topic_name: "topic".to_owned(),
+            partition_id: 0,
+            committed: None,
+            log_end_offset: 100,
+        };
+
+        assert_eq!(lag.lag(), None);
+
+        lag.committed = Some(40);
+
+        assert_eq!(lag.lag(), Some(60));
+
+        lag.committed = Some(120);
+
+        assert_eq!(lag.lag(), Some(0));
+    }
+}
@@ -0,0 +1,4 @@
+mod lag;
+
+pub use self::lag::{fetch_group_lag, FetchLags, LagMonitor, MonitorLags, PartitionLag,
+                    DEFAULT_LAG_MONITOR_INTERVAL_MILLIS};
@@ -7,19 +7,32 @@ use bytes::{BigEndian, BufMut, ByteOrder, BytesMut};
 
 use tokio_io::codec::{Decoder, Encoder};
 
+use errors::{Error, ErrorKind};
 use network::{KafkaRequest, KafkaResponse};
-use protocol::{ApiKeys, ApiVersion, CorrelationId, Encodable, Record, RequestHeader};
+use protocol::{parse_frame, ApiKeys, ApiVersion, CorrelationId, CrcPolicy, Encodable, Record, RequestHeader};
 
+/// The codec of Kafka protocol, which matches the responses to the outstanding requests.
+///
+/// The broker sends the responses in the order of requests on a connection,
+/// a response with an unexpected correlation id is a protocol error which closes the connection,
+/// instead of being decoded as the response of another request.
 #[derive(Debug)]
 pub struct KafkaCodec<'a> {
     requests: VecDeque<(ApiKeys, ApiVersion, CorrelationId)>,
+    check_crcs: CrcPolicy,
     phantom: PhantomData<&'a u8>,
 }
 
 impl<'a> KafkaCodec<'a> {
     pub fn new() -> Self {
+        Self::with_check_crcs(CrcPolicy::default())
+    }
+
+    /// Create a codec which checks the CRCs of the fetched messages with the policy.
+    pub fn with_check_crcs(check_crcs: CrcPolicy) -> Self {
         KafkaCodec {
             requests: VecDeque::new(),
+            check_crcs,
             phantom: PhantomData,
         }
     }
@@ -83,16 +96,160 @@ impl<'a> Decoder for KafkaCodec<'a> {
 
                 let buf = src.split_to(size + size_header_len).split_off(size_header_len).freeze();
 
-                if let Some((api_key, api_version, correlation_id)) = self.requests.pop_front() {
-                    if BigEndian::read_i32(&buf[..]) != correlation_id {
-                        Err(io::Error::new(io::ErrorKind::InvalidData, "correlation id mismatch"))
-                    } else {
-                        KafkaResponse::parse(&buf[..], api_key, api_version)
+                if buf.len() < mem::size_of::<CorrelationId>() {
+                    return Err(protocol_error(format!("truncated response with {} bytes", buf.len())));
+                }
+
+                let correlation_id = BigEndian::read_i32(&buf[..]);
+
+                match self.requests.pop_front() {
+                    Some((api_key, api_version, expected)) if expected == correlation_id => {
+                        // the records of response will share the memory of frame instead of being copied
+                        let check_crcs = self.check_crcs;
+
+                        parse_frame(&buf, |buf| KafkaResponse::parse(buf, api_key, api_version, check_crcs))
                     }
-                } else {
-                    Err(io::Error::new(io::ErrorKind::InvalidData, "unexpected response"))
+                    Some((api_key, _, expected)) => {
+                        if self.requests.iter().any(|&(_, _, pending)| pending == correlation_id) {
+                            Err(protocol_error(format!(
+                                "response #{} arrived before the response #{} of {:?} request",
+                                correlation_id, expected, api_key
+                            )))
+                        } else {
+                            Err(protocol_error(format!(
+                                "unknown response #{}, expected the response #{} of {:?} request",
+                                correlation_id, expected, api_key
+                            )))
+                        }
+                    }
+                    None => Err(protocol_error(format!(
+                        "unexpected response #{} without outstanding request",
+                        correlation_id
+                    ))),
                 }
             }
         }
     }
 }
+
+fn protocol_error(reason: String) -> io::Error {
+    warn!("{}", reason);
+
+    io::Error::new(io::ErrorKind::InvalidData, Error::from(ErrorKind::ProtocolError(reason)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn api_versions_response(correlation_id: CorrelationId) -> BytesMut {
+        let mut buf = BytesMut::with_capacity(14);
+
+        buf.put_i32::<BigEndian>(10);
+        buf.put_i32::<BigEndian>(correlation_id);
+        buf.put_i16::<BigEndian>(0);
+        buf.put_i32::<BigEndian>(0);
+        buf
+    }
+
+    fn truncated_response(correlation_id: CorrelationId) -> BytesMut {
+        let mut buf = BytesMut::with_capacity(8);
+
+        buf.put_i32::<BigEndian>(4);
+        buf.put_i32::<BigEndian>(correlation_id);
+        buf
+    }
+
+    #[test]
+    fn test_decode_response() {
+        let mut codec = KafkaCodec::new();
+        let mut buf = BytesMut::new();
+
+        codec.encode(KafkaRequest::api_versions(0, 123, None), &mut buf).unwrap();
+
+        match codec.decode(&mut api_versions_response(123)).unwrap() {
+            Some(KafkaResponse::ApiVersions(res)) => assert_eq!(res.header.correlation_id, 123),
+            res => panic!("unexpected response: {:?}", res),
+        }
+
+        assert!(codec.requests.is_empty());
+    }
+
+    #[test]
+    fn test_correlation_id_mismatch() {
+        let mut codec = KafkaCodec::new();
+        let mut buf = BytesMut::new();
+
+        codec.encode(KafkaRequest::api_versions(0, 1, None), &mut buf).unwrap();
+        codec.encode(KafkaRequest::api_versions(0, 2, None), &mut buf).unwrap();
+
+        let err = codec.decode(&mut api_versions_response(2)).unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let err = KafkaCodec::new().decode(&mut api_versions_response(1)).unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_decode_unsupported_api_key() {
+        let mut codec = KafkaCodec::new();
+
+        codec.requests.push_back((ApiKeys::LeaderAndIsr, 0, 123));
+
+        let err = codec.decode(&mut api_versions_response(123)).unwrap_err();
+
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        match err.get_ref().and_then(|err| err.downcast_ref::<Error>()) {
+            Some(&Error(ErrorKind::UnsupportedApiKey(ApiKeys::LeaderAndIsr), _)) => {}
+            err => panic!("unexpected error: {:?}", err),
+        }
+
+        assert!(codec.requests.is_empty());
+    }
+
+    #[test]
+    fn test_decode_truncated_response() {
+        let api_keys = [
+            ApiKeys::Produce,
+            ApiKeys::Fetch,
+            ApiKeys::ListOffsets,
+            ApiKeys::Metadata,
+            ApiKeys::OffsetCommit,
+            ApiKeys::OffsetFetch,
+            ApiKeys::GroupCoordinator,
+            ApiKeys::JoinGroup,
+            ApiKeys::Heartbeat,
+            ApiKeys::LeaveGroup,
+            ApiKeys::SyncGroup,
+            ApiKeys::DescribeGroups,
+            ApiKeys::ListGroups,
+            ApiKeys::SaslHandshake,
+            ApiKeys::ApiVersions,
+            ApiKeys::CreateTopics,
+            ApiKeys::DeleteTopics,
+            ApiKeys::DeleteRecords,
+            ApiKeys::OffsetForLeaderEpoch,
+            ApiKeys::DescribeConfigs,
+            ApiKeys::AlterConfigs,
+            ApiKeys::SaslAuthenticate,
+        ];
+
+        for &api_key in &api_keys {
+            let mut codec = KafkaCodec::new();
+
+            codec.requests.push_back((api_key, 0, 123));
+
+            // the whole frame has been received, the decoder must not wait for more bytes
+            match codec.decode(&mut truncated_response(123)) {
+                Ok(None) => panic!("decoder of {:?} response is waiting for more bytes", api_key),
+                Ok(Some(res)) => panic!("unexpected {:?} response: {:?}", api_key, res),
+                Err(err) => assert_eq!(err.kind(), io::ErrorKind::InvalidData),
+            }
+
+            assert!(codec.requests.is_empty());
+        }
+    }
+}
@@ -7,7 +7,7 @@ use bytes::BytesMut;
 
 use futures::sink::Sink;
 use futures::stream::Stream;
-use futures::{AsyncSink, Poll, StartSend};
+use futures::{Async, AsyncSink, Poll, StartSend};
 use tokio_io::codec::Framed;
 use tokio_io::{AsyncRead, AsyncWrite};
 use tokio_proto::streaming::pipeline::{Frame, Transport};
@@ -18,7 +18,10 @@ use network::{ConnectionId, KafkaCodec, KafkaRequest, KafkaResponse};
 pub enum Status {
     Idle(Instant),
     Busy,
+    Authenticating,
     Closed,
+    /// Closed without waiting for the in-flight requests, e.g. a request was timed out.
+    Aborted,
 }
 
 pub trait KeepAlive {
@@ -26,11 +29,42 @@ pub trait KeepAlive {
     fn busy(&mut self);
     fn close(&mut self);
     fn idle(&mut self);
+    /// Notify the current task when the value has been closed.
+    fn park(&mut self);
 }
 
 #[derive(Debug)]
 struct State<K> {
     keep_alive: K,
+    in_flight: usize,
+    max_in_flight: usize,
+}
+
+impl<K> State<K>
+where
+    K: KeepAlive,
+{
+    fn sent(&mut self) {
+        self.in_flight += 1;
+    }
+
+    fn received(&mut self) {
+        self.in_flight = self.in_flight.saturating_sub(1);
+
+        if self.in_flight < self.max_in_flight {
+            self.keep_alive.idle();
+        }
+    }
+
+    /// Return the connection to the pool only when it could accept more requests,
+    /// otherwise it will be returned once a pending response has been received.
+    fn flushed(&mut self) {
+        if self.in_flight < self.max_in_flight {
+            self.keep_alive.idle();
+        } else {
+            trace!("{} requests in flight, keep connection busy", self.in_flight);
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -111,19 +145,52 @@ where
 impl<'a, I, K> Stream for KafkaConnection<'a, I, K>
 where
     I: AsyncRead + AsyncWrite,
+    K: KeepAlive,
 {
     type Item = Frame<KafkaResponse, BytesMut, io::Error>;
     type Error = io::Error;
 
     fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
-        self.stream.poll().map(|res| {
-            res.map(|res| {
-                res.map(|res| Frame::Message {
+        match self.state.keep_alive.status() {
+            Status::Closed if self.state.in_flight == 0 => {
+                debug!("connection #{} was closed", self.id);
+
+                return Ok(Async::Ready(None));
+            }
+            Status::Aborted => {
+                debug!(
+                    "connection #{} was aborted with {} requests in flight",
+                    self.id, self.state.in_flight
+                );
+
+                // the pending requests are failed with the error
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "connection aborted"));
+            }
+            _ => {}
+        }
+
+        self.state.keep_alive.park();
+
+        match self.stream.poll() {
+            Ok(Async::Ready(Some(res))) => {
+                self.state.received();
+
+                Ok(Async::Ready(Some(Frame::Message {
                     message: res,
                     body: false,
-                })
-            })
-        })
+                })))
+            }
+            Ok(Async::Ready(None)) => Ok(Async::Ready(None)),
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(err) => {
+                // the connection is broken, don't return it to the pool again
+                warn!("connection #{} failed, {}", self.id, err);
+
+                self.state.keep_alive.close();
+
+                Err(err)
+            }
+        }
     }
 }
 
@@ -139,10 +206,18 @@ where
         trace!("send request: {:?}", frame);
 
         match frame {
-            Frame::Message { message: request, body } => self.stream.start_send(request).map(|async| match async {
-                AsyncSink::Ready => AsyncSink::Ready,
-                AsyncSink::NotReady(request) => AsyncSink::NotReady(Frame::Message { message: request, body }),
-            }),
+            Frame::Message { message: request, body } => {
+                let state = &mut self.state;
+
+                self.stream.start_send(request).map(|async| match async {
+                    AsyncSink::Ready => {
+                        state.sent();
+
+                        AsyncSink::Ready
+                    }
+                    AsyncSink::NotReady(request) => AsyncSink::NotReady(Frame::Message { message: request, body }),
+                })
+            }
             Frame::Body { .. } | Frame::Error { .. } => Ok(AsyncSink::Ready),
         }
     }
@@ -150,7 +225,7 @@ where
     fn poll_complete(&mut self) -> Poll<(), Self::SinkError> {
         trace!("poll complete");
 
-        self.state.keep_alive.idle();
+        self.state.flushed();
 
         self.stream.poll_complete()
     }
@@ -169,15 +244,71 @@ where
     I: AsyncRead + AsyncWrite,
     K: KeepAlive,
 {
-    pub fn new(id: ConnectionId, stream: I, codec: KafkaCodec<'a>, keep_alive: K) -> Self {
+    pub fn new(id: ConnectionId, stream: I, codec: KafkaCodec<'a>, keep_alive: K, max_in_flight: usize) -> Self {
         KafkaConnection {
             id,
             stream: stream.framed(codec),
-            state: State { keep_alive },
+            state: State {
+                keep_alive,
+                in_flight: 0,
+                max_in_flight: max_in_flight.max(1),
+            },
         }
     }
 
     pub fn id(&self) -> ConnectionId {
         self.id
     }
+
+    /// The number of requests sent without receiving the responses.
+    pub fn in_flight(&self) -> usize {
+        self.state.in_flight
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Default)]
+    struct MockKeepAlive {
+        idled: usize,
+    }
+
+    impl KeepAlive for MockKeepAlive {
+        fn status(&self) -> Status {
+            Status::Busy
+        }
+        fn busy(&mut self) {}
+        fn close(&mut self) {}
+        fn idle(&mut self) {
+            self.idled += 1;
+        }
+        fn park(&mut self) {}
+    }
+
+    #[test]
+    fn test_max_in_flight() {
+        let mut state = State {
+            keep_alive: MockKeepAlive::default(),
+            in_flight: 0,
+            max_in_flight: 2,
+        };
+
+        state.sent();
+        state.flushed();
+
+        assert_eq!(state.keep_alive.idled, 1);
+
+        state.sent();
+        state.flushed();
+
+        assert_eq!(state.in_flight, 2);
+        assert_eq!(state.keep_alive.idled, 1);
+
+        state.received();
+
+        assert_eq!(state.in_flight, 1);
+        assert_eq!(state.keep_alive.idled, 2);
+    }
 }
@@ -4,14 +4,20 @@ mod codec;
 mod conn;
 mod pool;
 mod response;
+mod sasl;
+mod scram;
 mod stream;
+mod transport;
 
 pub use self::codec::KafkaCodec;
 pub use self::conn::{KafkaConnection, KeepAlive, Status};
-pub use self::pool::{Pool, Pooled};
+pub use self::pool::{Clock, Pool, Pooled};
 pub use self::request::KafkaRequest;
 pub use self::response::KafkaResponse;
-pub use self::stream::{Connect, KafkaConnector, KafkaStream};
+pub use self::sasl::{Authenticate, SaslAuthenticator, SaslChannel, SaslMechanism, SaslSession, SendRequest};
+pub use self::scram::ScramClient;
+pub use self::stream::{Connect, KafkaConnector, KafkaStream, TlsSettings};
+pub use self::transport::{SharedTransport, SocketOptions, TcpTransport, Transport, TransportConnect, TransportStream};
 
 use std::borrow::Cow;
 use std::fmt;
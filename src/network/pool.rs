@@ -1,17 +1,22 @@
 use std::cell::{Cell, RefCell};
+use std::cmp;
 use std::collections::HashMap;
 use std::collections::vec_deque::VecDeque;
-use std::fmt::Debug;
+use std::fmt::{self, Debug};
 use std::hash::Hash;
 use std::io;
+use std::mem;
 use std::ops::{Deref, DerefMut};
 use std::rc::Rc;
 use std::time::{Duration, Instant};
 
+use futures::task::{self, Task};
 use futures::unsync::oneshot;
 use futures::{Async, Future, Poll};
+use rand::{self, Rng};
 
 use network::{KeepAlive, Status};
+use protocol::ToMilliseconds;
 
 #[derive(Clone, Debug)]
 pub struct Pool<K, T>
@@ -32,6 +37,46 @@ where
     timeout: Option<Duration>,
     idle: HashMap<K, Vec<Entry<T>>>,
     parked: HashMap<K, VecDeque<oneshot::Sender<Entry<T>>>>,
+    reconnect_backoff: Duration,
+    reconnect_backoff_max: Duration,
+    failures: HashMap<K, Reconnect>,
+    clock: Clock,
+}
+
+/// The clock which the idle time and the reconnect backoff of the pooled values are measured with.
+#[derive(Clone)]
+pub struct Clock(Rc<Fn() -> Instant>);
+
+impl Clock {
+    pub fn new<F>(now: F) -> Self
+    where
+        F: 'static + Fn() -> Instant,
+    {
+        Clock(Rc::new(now))
+    }
+
+    pub fn now(&self) -> Instant {
+        (self.0)()
+    }
+}
+
+impl Default for Clock {
+    fn default() -> Self {
+        Clock::new(Instant::now)
+    }
+}
+
+impl Debug for Clock {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Clock").finish()
+    }
+}
+
+/// The consecutive connection failures of a key.
+#[derive(Clone, Debug)]
+struct Reconnect {
+    attempts: u32,
+    next_attempt: Instant,
 }
 
 impl<K, T> Pool<K, T>
@@ -39,17 +84,31 @@ where
     K: Clone + Debug + Hash + Eq,
     T: Clone,
 {
-    pub fn new(timeout: Duration) -> Self {
+    pub fn new(timeout: Duration, reconnect_backoff: Duration, reconnect_backoff_max: Duration) -> Self {
         Pool {
             inner: Rc::new(RefCell::new(PoolInner {
                 enabled: true,
                 timeout: Some(timeout),
                 idle: HashMap::new(),
                 parked: HashMap::new(),
+                reconnect_backoff,
+                reconnect_backoff_max,
+                failures: HashMap::new(),
+                clock: Clock::default(),
             })),
         }
     }
 
+    /// Measure the idle time and the reconnect backoff with the clock.
+    pub fn with_clock(self, clock: Clock) -> Self {
+        self.inner.borrow_mut().clock = clock;
+        self
+    }
+
+    pub fn now(&self) -> Instant {
+        self.inner.borrow().clock.now()
+    }
+
     pub fn is_enabled(&self) -> bool {
         self.inner.borrow().enabled
     }
@@ -74,6 +133,90 @@ where
         }
     }
 
+    /// Close and remove all the idle pooled values, the busy values are kept until they are returned.
+    ///
+    /// Returns the keys of the closed values, one for each closed value.
+    pub fn clear(&self) -> Vec<K> {
+        let idle = mem::replace(&mut self.inner.borrow_mut().idle, HashMap::new());
+        let mut closed = Vec::new();
+
+        for (key, entries) in idle {
+            trace!("close {} idle pooled items for {:?}", entries.len(), key);
+
+            for entry in entries {
+                entry.close();
+
+                closed.push(key.clone());
+            }
+        }
+
+        closed
+    }
+
+    /// Close and remove the pooled values which have been idle for longer than the timeout.
+    ///
+    /// Returns the keys of the closed values, one for each closed value.
+    pub fn reap(&self) -> Vec<K> {
+        let expiration = Expiration::new(self.timeout(), self.now());
+        let mut inner = self.inner.borrow_mut();
+        let mut closed = Vec::new();
+
+        for (key, entries) in &mut inner.idle {
+            entries.retain(|entry| match entry.status.get() {
+                Status::Idle(idle_at) if expiration.expires(idle_at) => {
+                    trace!("close expired pooled item for {:?}", key);
+
+                    entry.close();
+
+                    closed.push(key.clone());
+
+                    false
+                }
+                Status::Idle(_) => true,
+                _ => false,
+            })
+        }
+
+        inner.idle.retain(|_, entries| !entries.is_empty());
+
+        closed
+    }
+
+    /// The remaining time to wait before reconnecting to the key which failed to connect.
+    pub fn reconnect_delay(&self, key: &K) -> Option<Duration> {
+        let now = self.now();
+
+        self.inner.borrow().failures.get(key).and_then(|reconnect| {
+            if reconnect.next_attempt > now {
+                Some(reconnect.next_attempt - now)
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Failed to connect, back off exponentially before the next attempt.
+    pub fn connect_failed(&self, key: K) -> Duration {
+        let now = self.now();
+        let mut inner = self.inner.borrow_mut();
+        let (base, max) = (inner.reconnect_backoff, inner.reconnect_backoff_max);
+        let reconnect = inner.failures.entry(key).or_insert_with(|| Reconnect {
+            attempts: 0,
+            next_attempt: now,
+        });
+        let backoff = reconnect_backoff(base, max, reconnect.attempts);
+
+        reconnect.attempts += 1;
+        reconnect.next_attempt = now + backoff;
+
+        backoff
+    }
+
+    /// Connected, reset the reconnect backoff.
+    pub fn connected(&self, key: &K) {
+        self.inner.borrow_mut().failures.remove(key);
+    }
+
     fn put(&mut self, key: K, entry: Entry<T>) {
         trace!("put {:?}", key);
 
@@ -114,6 +257,7 @@ where
                 value,
                 reused: false,
                 status: Rc::new(Cell::new(Status::Busy)),
+                task: Rc::new(RefCell::new(None)),
             },
             key,
             pool: self.clone(),
@@ -163,6 +307,30 @@ where
     value: T,
     reused: bool,
     status: Rc<Cell<Status>>,
+    task: Rc<RefCell<Option<Task>>>,
+}
+
+impl<T> Entry<T>
+where
+    T: Clone,
+{
+    /// Close the pooled value and wake up its connection to shut down.
+    fn close(&self) {
+        self.shutdown(Status::Closed)
+    }
+
+    /// Abort the pooled value and wake up its connection to fail the in-flight requests.
+    fn abort(&self) {
+        self.shutdown(Status::Aborted)
+    }
+
+    fn shutdown(&self, status: Status) {
+        self.status.set(status);
+
+        if let Some(task) = self.task.borrow_mut().take() {
+            task.notify();
+        }
+    }
 }
 
 impl<K, T> Deref for Pooled<K, T>
@@ -207,9 +375,23 @@ where
         self.entry.status.set(Status::Closed)
     }
 
+    fn park(&mut self) {
+        *self.entry.task.borrow_mut() = Some(task::current());
+    }
+
     fn idle(&mut self) {
         let previous = self.status();
-        self.entry.status.set(Status::Idle(Instant::now()));
+        if let Status::Authenticating = previous {
+            trace!("authenticating, {:?}", self);
+
+            return;
+        }
+        if let Status::Aborted = previous {
+            trace!("aborted, {:?}", self);
+
+            return;
+        }
+        self.entry.status.set(Status::Idle(self.pool.now()));
         if let Status::Idle(..) = previous {
             trace!("already idle, {:?}", self);
 
@@ -224,6 +406,41 @@ where
     }
 }
 
+impl<K, T> Pooled<K, T>
+where
+    K: Clone + Debug + Hash + Eq,
+    T: Clone + Debug,
+{
+    /// Keep the pooled value from being checked out until it has been re-authenticated.
+    pub fn authenticating(&mut self) {
+        trace!("authenticating: {:?}", self);
+
+        self.entry.status.set(Status::Authenticating)
+    }
+
+    /// Re-authenticated, return the pooled value to the pool.
+    pub fn authenticated(&mut self) {
+        self.entry.status.set(Status::Busy);
+        self.idle()
+    }
+
+    /// Abort the pooled value without waiting for its in-flight requests, it will never be reused.
+    pub fn abort(&self) {
+        trace!("aborted: {:?}", self);
+
+        self.entry.abort()
+    }
+
+    /// The pooled value was closed or has been idle for too long.
+    pub fn is_expired(&self) -> bool {
+        match self.status() {
+            Status::Idle(idle_at) => Expiration::new(self.pool.timeout()).expires(idle_at),
+            Status::Closed | Status::Aborted => true,
+            _ => false,
+        }
+    }
+}
+
 pub struct Checkout<K, T>
 where
     K: Clone + Hash + Eq,
@@ -329,8 +546,8 @@ where
 struct Expiration(Option<Instant>);
 
 impl Expiration {
-    fn new(dur: Option<Duration>) -> Expiration {
-        Expiration(dur.map(|dur| Instant::now() - dur))
+    fn new(dur: Option<Duration>, now: Instant) -> Expiration {
+        Expiration(dur.map(|dur| now - dur))
     }
 
     fn expires(&self, instant: Instant) -> bool {
@@ -340,3 +557,53 @@ impl Expiration {
         }
     }
 }
+
+/// The exponential backoff with a random jitter of +/- 20%, capped by the maximum backoff.
+fn reconnect_backoff(base: Duration, max: Duration, attempts: u32) -> Duration {
+    let backoff = base.as_millis().saturating_mul(1 << cmp::min(attempts, 31));
+    let backoff = cmp::min(backoff, max.as_millis()) as f64;
+
+    Duration::from_millis((backoff * rand::thread_rng().gen_range(0.8, 1.2)) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reconnect_backoff() {
+        let base = Duration::from_millis(50);
+        let max = Duration::from_millis(1000);
+
+        for &(attempts, expected) in &[(0, 50), (1, 100), (3, 400), (5, 1000), (64, 1000)] {
+            let backoff = reconnect_backoff(base, max, attempts).as_millis();
+
+            assert!(
+                backoff >= expected * 8 / 10 && backoff <= expected * 12 / 10,
+                "attempts {}, backoff {}",
+                attempts,
+                backoff
+            );
+        }
+    }
+
+    #[test]
+    fn test_reconnect_delay() {
+        let pool: Pool<&str, ()> = Pool::new(
+            Duration::from_secs(5),
+            Duration::from_secs(10),
+            Duration::from_secs(60),
+        );
+
+        assert_eq!(pool.reconnect_delay(&"broker"), None);
+
+        pool.connect_failed("broker");
+
+        assert!(pool.reconnect_delay(&"broker").is_some());
+        assert!(pool.connect_failed("broker") >= Duration::from_secs(16));
+
+        pool.connected(&"broker");
+
+        assert_eq!(pool.reconnect_delay(&"broker"), None);
+    }
+}
@@ -6,14 +6,19 @@ use bytes::{ByteOrder, BytesMut};
 
 use errors::Result;
 use network::{OffsetAndMetadata, TopicPartition};
-use protocol::{ApiKey, ApiKeys, ApiVersion, ApiVersionsRequest, CorrelationId, DescribeGroupsRequest, Encodable,
-               FetchOffset, FetchRequest, FetchTopic, GenerationId, GroupCoordinatorRequest, HeartbeatRequest,
-               JoinGroupProtocol, JoinGroupRequest, LeaveGroupRequest, ListGroupsRequest, ListOffsetRequest,
-               ListPartitionOffset, ListTopicOffset, MessageSet, MetadataRequest, OffsetCommitPartition,
-               OffsetCommitRequest, OffsetCommitTopic, OffsetFetchPartition, OffsetFetchRequest, OffsetFetchTopic,
-               PartitionId, ProducePartitionData, ProduceRequest, ProduceTopicData, Record, RequestHeader,
-               RequiredAck, RequiredAcks, SyncGroupAssignment, SyncGroupRequest, ToMilliseconds, CONSUMER_REPLICA_ID,
-               DEFAULT_TIMESTAMP};
+use protocol::{AlterConfigsEntry, AlterConfigsRequest, AlterConfigsResource, ApiKey, ApiKeys, ApiVersion,
+               ApiVersionsRequest, CoordinatorType, CorrelationId, CreateTopicsRequest, CreateTopicsTopic,
+               DeleteRecordsPartition, DeleteRecordsRequest, DeleteRecordsTopic, DeleteTopicsRequest,
+               DescribeConfigsRequest, DescribeConfigsResource, DescribeGroupsRequest, Encodable, FetchOffset,
+               FetchRequest, FetchSessionEpoch, FetchSessionId, FetchTopic, ForgottenTopic, GenerationId,
+               GroupCoordinatorRequest, HeartbeatRequest, JoinGroupProtocol, JoinGroupRequest, LeaderEpoch,
+               LeaveGroupRequest, ListGroupsRequest, ListOffsetRequest, ListPartitionOffset, ListTopicOffset,
+               MessageSet, MetadataRequest, Offset, OffsetCommitPartition, OffsetCommitRequest, OffsetCommitTopic,
+               OffsetFetchPartition, OffsetFetchRequest, OffsetFetchTopic, OffsetsForLeaderEpochPartition,
+               OffsetsForLeaderEpochRequest, OffsetsForLeaderEpochTopic, PartitionId, ProducePartitionData,
+               ProduceRequest, ProduceTopicData, Record, RequestHeader, RequiredAck, RequiredAcks, ResourceType,
+               SaslAuthenticateRequest, SaslHandshakeRequest, SyncGroupAssignment, SyncGroupRequest, ToMilliseconds,
+               CONSUMER_REPLICA_ID, DEFAULT_TIMESTAMP, READ_UNCOMMITTED, UNDEFINED_LEADER_EPOCH};
 
 #[derive(Debug)]
 pub enum KafkaRequest<'a> {
@@ -30,7 +35,15 @@ pub enum KafkaRequest<'a> {
     SyncGroup(SyncGroupRequest<'a>),
     DescribeGroups(DescribeGroupsRequest<'a>),
     ListGroups(ListGroupsRequest<'a>),
+    SaslHandshake(SaslHandshakeRequest<'a>),
     ApiVersions(ApiVersionsRequest<'a>),
+    CreateTopics(CreateTopicsRequest<'a>),
+    DeleteTopics(DeleteTopicsRequest<'a>),
+    DeleteRecords(DeleteRecordsRequest<'a>),
+    OffsetsForLeaderEpoch(OffsetsForLeaderEpochRequest<'a>),
+    DescribeConfigs(DescribeConfigsRequest<'a>),
+    AlterConfigs(AlterConfigsRequest<'a>),
+    SaslAuthenticate(SaslAuthenticateRequest<'a>),
 }
 
 impl<'a> KafkaRequest<'a> {
@@ -49,7 +62,15 @@ impl<'a> KafkaRequest<'a> {
             KafkaRequest::SyncGroup(ref req) => &req.header,
             KafkaRequest::DescribeGroups(ref req) => &req.header,
             KafkaRequest::ListGroups(ref req) => &req.header,
+            KafkaRequest::SaslHandshake(ref req) => &req.header,
             KafkaRequest::ApiVersions(ref req) => &req.header,
+            KafkaRequest::CreateTopics(ref req) => &req.header,
+            KafkaRequest::DeleteTopics(ref req) => &req.header,
+            KafkaRequest::DeleteRecords(ref req) => &req.header,
+            KafkaRequest::OffsetsForLeaderEpoch(ref req) => &req.header,
+            KafkaRequest::DescribeConfigs(ref req) => &req.header,
+            KafkaRequest::AlterConfigs(ref req) => &req.header,
+            KafkaRequest::SaslAuthenticate(ref req) => &req.header,
         }
     }
 
@@ -90,6 +111,44 @@ impl<'a> KafkaRequest<'a> {
         KafkaRequest::Produce(request)
     }
 
+    /// Produce the batches of multiple partitions, which share the same leader, in a single request.
+    pub fn produce_batches(
+        api_version: ApiVersion,
+        correlation_id: CorrelationId,
+        client_id: Option<Cow<'a, str>>,
+        required_acks: RequiredAcks,
+        ack_timeout: Duration,
+        topics: HashMap<Cow<'a, str>, Vec<(PartitionId, Cow<'a, MessageSet>)>>,
+    ) -> KafkaRequest<'a> {
+        let topics = topics
+            .into_iter()
+            .map(|(topic_name, partitions)| ProduceTopicData {
+                topic_name,
+                partitions: partitions
+                    .into_iter()
+                    .map(|(partition_id, message_set)| ProducePartitionData {
+                        partition_id,
+                        message_set,
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        let request = ProduceRequest {
+            header: RequestHeader {
+                api_key: ApiKeys::Produce as ApiKey,
+                api_version,
+                correlation_id,
+                client_id,
+            },
+            required_acks: required_acks as RequiredAck,
+            ack_timeout: ack_timeout.as_millis() as i32,
+            topics,
+        };
+
+        KafkaRequest::Produce(request)
+    }
+
     pub fn fetch_records(
         api_version: ApiVersion,
         correlation_id: CorrelationId,
@@ -97,7 +156,10 @@ impl<'a> KafkaRequest<'a> {
         max_wait_time: Duration,
         min_bytes: i32,
         max_bytes: i32,
+        session_id: FetchSessionId,
+        session_epoch: FetchSessionEpoch,
         topics: Vec<FetchTopic<'a>>,
+        forgotten_topics: Vec<ForgottenTopic<'a>>,
     ) -> KafkaRequest<'a> {
         let request = FetchRequest {
             header: RequestHeader {
@@ -110,17 +172,32 @@ impl<'a> KafkaRequest<'a> {
             max_wait_time: max_wait_time.as_millis() as i32,
             min_bytes,
             max_bytes,
+            isolation_level: READ_UNCOMMITTED,
+            session_id,
+            session_epoch,
             topics,
+            forgotten_topics,
+            rack_id: "".into(),
         };
 
         KafkaRequest::Fetch(request)
     }
 
+    /// Set the rack of the consumer to the fetch request, so the broker could select the closest replica (KIP-392).
+    pub fn with_rack_id(mut self, rack_id: Cow<'a, str>) -> Self {
+        if let KafkaRequest::Fetch(ref mut request) = self {
+            request.rack_id = rack_id;
+        }
+
+        self
+    }
+
     pub fn list_offsets(
         api_version: ApiVersion,
         correlation_id: CorrelationId,
         client_id: Option<Cow<'a, str>>,
         topics: HashMap<Cow<'a, str>, Vec<(PartitionId, FetchOffset)>>,
+        max_number_of_offsets: i32,
     ) -> KafkaRequest<'a> {
         let topics = topics
             .into_iter()
@@ -131,7 +208,7 @@ impl<'a> KafkaRequest<'a> {
                     .map(|(id, offset)| ListPartitionOffset {
                         partition_id: id,
                         timestamp: offset.into(),
-                        max_number_of_offsets: 16,
+                        max_number_of_offsets,
                     })
                     .collect(),
             })
@@ -156,6 +233,7 @@ impl<'a> KafkaRequest<'a> {
         correlation_id: CorrelationId,
         client_id: Option<Cow<'a, str>>,
         topic_names: &[S],
+        allow_auto_topic_creation: bool,
     ) -> KafkaRequest<'a> {
         let request = MetadataRequest {
             header: RequestHeader {
@@ -165,6 +243,7 @@ impl<'a> KafkaRequest<'a> {
                 client_id,
             },
             topic_names: topic_names.iter().map(|s| Cow::from(s.as_ref().to_owned())).collect(),
+            allow_auto_topic_creation,
         };
 
         KafkaRequest::Metadata(request)
@@ -270,12 +349,14 @@ impl<'a> KafkaRequest<'a> {
                 client_id,
             },
             group_id,
+            coordinator_type: CoordinatorType::Group,
         };
 
         KafkaRequest::GroupCoordinator(request)
     }
 
     pub fn heartbeat(
+        api_version: ApiVersion,
         correlation_id: CorrelationId,
         client_id: Option<Cow<'a, str>>,
         group_id: Cow<'a, str>,
@@ -285,7 +366,7 @@ impl<'a> KafkaRequest<'a> {
         let request = HeartbeatRequest {
             header: RequestHeader {
                 api_key: ApiKeys::Heartbeat as ApiKey,
-                api_version: 0,
+                api_version,
                 correlation_id,
                 client_id,
             },
@@ -297,6 +378,42 @@ impl<'a> KafkaRequest<'a> {
         KafkaRequest::Heartbeat(request)
     }
 
+    pub fn describe_groups(
+        api_version: ApiVersion,
+        correlation_id: CorrelationId,
+        client_id: Option<Cow<'a, str>>,
+        groups: Vec<Cow<'a, str>>,
+    ) -> KafkaRequest<'a> {
+        let request = DescribeGroupsRequest {
+            header: RequestHeader {
+                api_key: ApiKeys::DescribeGroups as ApiKey,
+                api_version,
+                correlation_id,
+                client_id,
+            },
+            groups,
+        };
+
+        KafkaRequest::DescribeGroups(request)
+    }
+
+    pub fn list_groups(
+        api_version: ApiVersion,
+        correlation_id: CorrelationId,
+        client_id: Option<Cow<'a, str>>,
+    ) -> KafkaRequest<'a> {
+        let request = ListGroupsRequest {
+            header: RequestHeader {
+                api_key: ApiKeys::ListGroups as ApiKey,
+                api_version,
+                correlation_id,
+                client_id,
+            },
+        };
+
+        KafkaRequest::ListGroups(request)
+    }
+
     pub fn join_group(
         api_version: ApiVersion,
         correlation_id: CorrelationId,
@@ -327,6 +444,7 @@ impl<'a> KafkaRequest<'a> {
     }
 
     pub fn leave_group(
+        api_version: ApiVersion,
         correlation_id: CorrelationId,
         client_id: Option<Cow<'a, str>>,
         group_id: Cow<'a, str>,
@@ -335,7 +453,7 @@ impl<'a> KafkaRequest<'a> {
         let request = LeaveGroupRequest {
             header: RequestHeader {
                 api_key: ApiKeys::LeaveGroup as ApiKey,
-                api_version: 0,
+                api_version,
                 correlation_id,
                 client_id,
             },
@@ -347,6 +465,7 @@ impl<'a> KafkaRequest<'a> {
     }
 
     pub fn sync_group(
+        api_version: ApiVersion,
         correlation_id: CorrelationId,
         client_id: Option<Cow<'a, str>>,
         group_id: Cow<'a, str>,
@@ -357,7 +476,7 @@ impl<'a> KafkaRequest<'a> {
         let request = SyncGroupRequest {
             header: RequestHeader {
                 api_key: ApiKeys::SyncGroup as ApiKey,
-                api_version: 0,
+                api_version,
                 correlation_id,
                 client_id,
             },
@@ -376,11 +495,53 @@ impl<'a> KafkaRequest<'a> {
         KafkaRequest::SyncGroup(request)
     }
 
-    pub fn api_versions(correlation_id: CorrelationId, client_id: Option<Cow<'a, str>>) -> KafkaRequest<'a> {
+    pub fn sasl_handshake(
+        api_version: ApiVersion,
+        correlation_id: CorrelationId,
+        client_id: Option<Cow<'a, str>>,
+        mechanism: Cow<'a, str>,
+    ) -> KafkaRequest<'a> {
+        let request = SaslHandshakeRequest {
+            header: RequestHeader {
+                api_key: ApiKeys::SaslHandshake as ApiKey,
+                api_version,
+                correlation_id,
+                client_id,
+            },
+            mechanism,
+        };
+
+        KafkaRequest::SaslHandshake(request)
+    }
+
+    pub fn sasl_authenticate(
+        api_version: ApiVersion,
+        correlation_id: CorrelationId,
+        client_id: Option<Cow<'a, str>>,
+        auth_bytes: Cow<'a, [u8]>,
+    ) -> KafkaRequest<'a> {
+        let request = SaslAuthenticateRequest {
+            header: RequestHeader {
+                api_key: ApiKeys::SaslAuthenticate as ApiKey,
+                api_version,
+                correlation_id,
+                client_id,
+            },
+            auth_bytes,
+        };
+
+        KafkaRequest::SaslAuthenticate(request)
+    }
+
+    pub fn api_versions(
+        api_version: ApiVersion,
+        correlation_id: CorrelationId,
+        client_id: Option<Cow<'a, str>>,
+    ) -> KafkaRequest<'a> {
         let request = ApiVersionsRequest {
             header: RequestHeader {
                 api_key: ApiKeys::ApiVersions as ApiKey,
-                api_version: 0,
+                api_version,
                 correlation_id,
                 client_id,
             },
@@ -388,6 +549,175 @@ impl<'a> KafkaRequest<'a> {
 
         KafkaRequest::ApiVersions(request)
     }
+
+    pub fn create_topics(
+        api_version: ApiVersion,
+        correlation_id: CorrelationId,
+        client_id: Option<Cow<'a, str>>,
+        timeout: Duration,
+        topics: Vec<CreateTopicsTopic<'a>>,
+    ) -> KafkaRequest<'a> {
+        let request = CreateTopicsRequest {
+            header: RequestHeader {
+                api_key: ApiKeys::CreateTopics as ApiKey,
+                api_version,
+                correlation_id,
+                client_id,
+            },
+            topics,
+            timeout: timeout.as_millis() as i32,
+        };
+
+        KafkaRequest::CreateTopics(request)
+    }
+
+    pub fn delete_topics(
+        api_version: ApiVersion,
+        correlation_id: CorrelationId,
+        client_id: Option<Cow<'a, str>>,
+        timeout: Duration,
+        topic_names: Vec<Cow<'a, str>>,
+    ) -> KafkaRequest<'a> {
+        let request = DeleteTopicsRequest {
+            header: RequestHeader {
+                api_key: ApiKeys::DeleteTopics as ApiKey,
+                api_version,
+                correlation_id,
+                client_id,
+            },
+            topic_names,
+            timeout: timeout.as_millis() as i32,
+        };
+
+        KafkaRequest::DeleteTopics(request)
+    }
+
+    pub fn delete_records(
+        api_version: ApiVersion,
+        correlation_id: CorrelationId,
+        client_id: Option<Cow<'a, str>>,
+        timeout: Duration,
+        offsets: HashMap<Cow<'a, str>, Vec<(PartitionId, Offset)>>,
+    ) -> KafkaRequest<'a> {
+        let topics = offsets
+            .into_iter()
+            .map(|(topic_name, partitions)| DeleteRecordsTopic {
+                topic_name,
+                partitions: partitions
+                    .into_iter()
+                    .map(|(partition_id, offset)| DeleteRecordsPartition { partition_id, offset })
+                    .collect(),
+            })
+            .collect();
+
+        let request = DeleteRecordsRequest {
+            header: RequestHeader {
+                api_key: ApiKeys::DeleteRecords as ApiKey,
+                api_version,
+                correlation_id,
+                client_id,
+            },
+            topics,
+            timeout: timeout.as_millis() as i32,
+        };
+
+        KafkaRequest::DeleteRecords(request)
+    }
+
+    pub fn offsets_for_leader_epoch(
+        api_version: ApiVersion,
+        correlation_id: CorrelationId,
+        client_id: Option<Cow<'a, str>>,
+        epochs: HashMap<Cow<'a, str>, Vec<(PartitionId, LeaderEpoch)>>,
+    ) -> KafkaRequest<'a> {
+        let topics = epochs
+            .into_iter()
+            .map(|(topic_name, partitions)| OffsetsForLeaderEpochTopic {
+                topic_name,
+                partitions: partitions
+                    .into_iter()
+                    .map(|(partition_id, leader_epoch)| OffsetsForLeaderEpochPartition {
+                        partition_id,
+                        current_leader_epoch: UNDEFINED_LEADER_EPOCH,
+                        leader_epoch,
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        let request = OffsetsForLeaderEpochRequest {
+            header: RequestHeader {
+                api_key: ApiKeys::OffsetForLeaderEpoch as ApiKey,
+                api_version,
+                correlation_id,
+                client_id,
+            },
+            topics,
+        };
+
+        KafkaRequest::OffsetsForLeaderEpoch(request)
+    }
+
+    pub fn describe_configs(
+        api_version: ApiVersion,
+        correlation_id: CorrelationId,
+        client_id: Option<Cow<'a, str>>,
+        resources: Vec<(ResourceType, Cow<'a, str>, Option<Vec<Cow<'a, str>>>)>,
+    ) -> KafkaRequest<'a> {
+        let request = DescribeConfigsRequest {
+            header: RequestHeader {
+                api_key: ApiKeys::DescribeConfigs as ApiKey,
+                api_version,
+                correlation_id,
+                client_id,
+            },
+            resources: resources
+                .into_iter()
+                .map(|(resource_type, resource_name, config_names)| DescribeConfigsResource {
+                    resource_type,
+                    resource_name,
+                    config_names,
+                })
+                .collect(),
+            include_synonyms: false,
+        };
+
+        KafkaRequest::DescribeConfigs(request)
+    }
+
+    pub fn alter_configs(
+        api_version: ApiVersion,
+        correlation_id: CorrelationId,
+        client_id: Option<Cow<'a, str>>,
+        resources: Vec<(ResourceType, Cow<'a, str>, HashMap<String, String>)>,
+        validate_only: bool,
+    ) -> KafkaRequest<'a> {
+        let request = AlterConfigsRequest {
+            header: RequestHeader {
+                api_key: ApiKeys::AlterConfigs as ApiKey,
+                api_version,
+                correlation_id,
+                client_id,
+            },
+            resources: resources
+                .into_iter()
+                .map(|(resource_type, resource_name, configs)| AlterConfigsResource {
+                    resource_type,
+                    resource_name,
+                    config_entries: configs
+                        .into_iter()
+                        .map(|(name, value)| AlterConfigsEntry {
+                            config_name: name.into(),
+                            config_value: Some(value.into()),
+                        })
+                        .collect(),
+                })
+                .collect(),
+            validate_only,
+        };
+
+        KafkaRequest::AlterConfigs(request)
+    }
 }
 
 impl<'a> Record for KafkaRequest<'a> {
@@ -406,7 +736,15 @@ impl<'a> Record for KafkaRequest<'a> {
             KafkaRequest::SyncGroup(ref req) => req.size(api_version),
             KafkaRequest::DescribeGroups(ref req) => req.size(api_version),
             KafkaRequest::ListGroups(ref req) => req.size(api_version),
+            KafkaRequest::SaslHandshake(ref req) => req.size(api_version),
             KafkaRequest::ApiVersions(ref req) => req.size(api_version),
+            KafkaRequest::CreateTopics(ref req) => req.size(api_version),
+            KafkaRequest::DeleteTopics(ref req) => req.size(api_version),
+            KafkaRequest::DeleteRecords(ref req) => req.size(api_version),
+            KafkaRequest::OffsetsForLeaderEpoch(ref req) => req.size(api_version),
+            KafkaRequest::DescribeConfigs(ref req) => req.size(api_version),
+            KafkaRequest::AlterConfigs(ref req) => req.size(api_version),
+            KafkaRequest::SaslAuthenticate(ref req) => req.size(api_version),
         }
     }
 }
@@ -427,7 +765,15 @@ impl<'a> Encodable for KafkaRequest<'a> {
             KafkaRequest::SyncGroup(ref req) => req.encode::<T>(dst),
             KafkaRequest::DescribeGroups(ref req) => req.encode::<T>(dst),
             KafkaRequest::ListGroups(ref req) => req.encode::<T>(dst),
+            KafkaRequest::SaslHandshake(ref req) => req.encode::<T>(dst),
             KafkaRequest::ApiVersions(ref req) => req.encode::<T>(dst),
+            KafkaRequest::CreateTopics(ref req) => req.encode::<T>(dst),
+            KafkaRequest::DeleteTopics(ref req) => req.encode::<T>(dst),
+            KafkaRequest::DeleteRecords(ref req) => req.encode::<T>(dst),
+            KafkaRequest::OffsetsForLeaderEpoch(ref req) => req.encode::<T>(dst),
+            KafkaRequest::DescribeConfigs(ref req) => req.encode::<T>(dst),
+            KafkaRequest::AlterConfigs(ref req) => req.encode::<T>(dst),
+            KafkaRequest::SaslAuthenticate(ref req) => req.encode::<T>(dst),
         }
     }
 }
@@ -1,13 +1,18 @@
 use std::io;
+use std::time::Duration;
 
 use log::Level::Debug;
 
-use nom::{self, ErrorKind, IResult, Needed};
+use nom::{IResult, Needed};
 
-use protocol::{display_parse_error, ApiKeys, ApiVersion, ApiVersionsResponse, DescribeGroupsResponse, FetchResponse,
-               GroupCoordinatorResponse, HeartbeatResponse, JoinGroupResponse, LeaveGroupResponse, ListGroupsResponse,
-               ListOffsetResponse, MetadataResponse, OffsetCommitResponse, OffsetFetchResponse, ParseTag,
-               ProduceResponse, SyncGroupResponse};
+use errors::{Error, ErrorKind};
+
+use protocol::{display_parse_error, AlterConfigsResponse, ApiKeys, ApiVersion, ApiVersionsResponse, CrcPolicy,
+               CreateTopicsResponse, DeleteRecordsResponse, DeleteTopicsResponse, DescribeConfigsResponse,
+               DescribeGroupsResponse, FetchResponse, GroupCoordinatorResponse, HeartbeatResponse, JoinGroupResponse,
+               LeaveGroupResponse, ListGroupsResponse, ListOffsetResponse, MetadataResponse, OffsetCommitResponse,
+               OffsetFetchResponse, OffsetsForLeaderEpochResponse, ProduceResponse, SaslAuthenticateResponse,
+               SaslHandshakeResponse, SyncGroupResponse};
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum KafkaResponse {
@@ -24,7 +29,15 @@ pub enum KafkaResponse {
     SyncGroup(SyncGroupResponse),
     DescribeGroups(DescribeGroupsResponse),
     ListGroups(ListGroupsResponse),
+    SaslHandshake(SaslHandshakeResponse),
     ApiVersions(ApiVersionsResponse),
+    CreateTopics(CreateTopicsResponse),
+    DeleteTopics(DeleteTopicsResponse),
+    DeleteRecords(DeleteRecordsResponse),
+    OffsetsForLeaderEpoch(OffsetsForLeaderEpochResponse),
+    DescribeConfigs(DescribeConfigsResponse),
+    AlterConfigs(AlterConfigsResponse),
+    SaslAuthenticate(SaslAuthenticateResponse),
 }
 
 impl KafkaResponse {
@@ -43,11 +56,46 @@ impl KafkaResponse {
             KafkaResponse::SyncGroup(_) => ApiKeys::SyncGroup,
             KafkaResponse::DescribeGroups(_) => ApiKeys::DescribeGroups,
             KafkaResponse::ListGroups(_) => ApiKeys::ListGroups,
+            KafkaResponse::SaslHandshake(_) => ApiKeys::SaslHandshake,
             KafkaResponse::ApiVersions(_) => ApiKeys::ApiVersions,
+            KafkaResponse::CreateTopics(_) => ApiKeys::CreateTopics,
+            KafkaResponse::DeleteTopics(_) => ApiKeys::DeleteTopics,
+            KafkaResponse::DeleteRecords(_) => ApiKeys::DeleteRecords,
+            KafkaResponse::OffsetsForLeaderEpoch(_) => ApiKeys::OffsetForLeaderEpoch,
+            KafkaResponse::DescribeConfigs(_) => ApiKeys::DescribeConfigs,
+            KafkaResponse::AlterConfigs(_) => ApiKeys::AlterConfigs,
+            KafkaResponse::SaslAuthenticate(_) => ApiKeys::SaslAuthenticate,
         }
     }
 
-    pub fn parse<T: AsRef<[u8]>>(src: T, api_key: ApiKeys, api_version: ApiVersion) -> io::Result<Option<Self>> {
+    /// The duration for which the request was throttled by the broker, if it violated the quota.
+    pub fn throttle_time(&self) -> Option<Duration> {
+        let throttle_time = match *self {
+            KafkaResponse::Produce(ProduceResponse { throttle_time, .. })
+            | KafkaResponse::Fetch(FetchResponse { throttle_time, .. })
+            | KafkaResponse::Metadata(MetadataResponse { throttle_time, .. })
+            | KafkaResponse::GroupCoordinator(GroupCoordinatorResponse { throttle_time, .. })
+            | KafkaResponse::OffsetsForLeaderEpoch(OffsetsForLeaderEpochResponse { throttle_time, .. }) => {
+                throttle_time
+            }
+            KafkaResponse::DeleteRecords(DeleteRecordsResponse { throttle_time, .. })
+            | KafkaResponse::DescribeConfigs(DescribeConfigsResponse { throttle_time, .. })
+            | KafkaResponse::AlterConfigs(AlterConfigsResponse { throttle_time, .. }) => Some(throttle_time),
+            _ => None,
+        };
+
+        match throttle_time {
+            Some(throttle_time) if throttle_time > 0 => Some(Duration::from_millis(throttle_time as u64)),
+            _ => None,
+        }
+    }
+
+    pub fn parse<T: AsRef<[u8]>>(
+        src: T,
+        api_key: ApiKeys,
+        api_version: ApiVersion,
+        check_crcs: CrcPolicy,
+    ) -> io::Result<Option<Self>> {
         let buf = src.as_ref();
 
         debug!(
@@ -59,20 +107,44 @@ impl KafkaResponse {
 
         let res = match api_key {
             ApiKeys::Produce => ProduceResponse::parse(buf, api_version).map(KafkaResponse::Produce),
-            ApiKeys::Fetch => FetchResponse::parse(buf, api_version).map(KafkaResponse::Fetch),
+            ApiKeys::Fetch => FetchResponse::parse(buf, api_version, check_crcs).map(KafkaResponse::Fetch),
             ApiKeys::ListOffsets => ListOffsetResponse::parse(buf, api_version).map(KafkaResponse::ListOffsets),
-            ApiKeys::Metadata => MetadataResponse::parse(buf).map(KafkaResponse::Metadata),
+            ApiKeys::Metadata => MetadataResponse::parse(buf, api_version).map(KafkaResponse::Metadata),
             ApiKeys::OffsetCommit => OffsetCommitResponse::parse(buf).map(KafkaResponse::OffsetCommit),
             ApiKeys::OffsetFetch => OffsetFetchResponse::parse(buf).map(KafkaResponse::OffsetFetch),
-            ApiKeys::GroupCoordinator => GroupCoordinatorResponse::parse(buf).map(KafkaResponse::GroupCoordinator),
+            ApiKeys::GroupCoordinator => {
+                GroupCoordinatorResponse::parse(buf, api_version).map(KafkaResponse::GroupCoordinator)
+            }
             ApiKeys::JoinGroup => JoinGroupResponse::parse(buf).map(KafkaResponse::JoinGroup),
             ApiKeys::Heartbeat => HeartbeatResponse::parse(buf).map(KafkaResponse::Heartbeat),
             ApiKeys::LeaveGroup => LeaveGroupResponse::parse(buf).map(KafkaResponse::LeaveGroup),
             ApiKeys::SyncGroup => SyncGroupResponse::parse(buf).map(KafkaResponse::SyncGroup),
             ApiKeys::DescribeGroups => DescribeGroupsResponse::parse(buf).map(KafkaResponse::DescribeGroups),
             ApiKeys::ListGroups => ListGroupsResponse::parse(buf).map(KafkaResponse::ListGroups),
+            ApiKeys::SaslHandshake => SaslHandshakeResponse::parse(buf).map(KafkaResponse::SaslHandshake),
             ApiKeys::ApiVersions => ApiVersionsResponse::parse(buf).map(KafkaResponse::ApiVersions),
-            _ => IResult::Error(nom::Err::Code(ErrorKind::Custom(ParseTag::ApiKey as u32))),
+            ApiKeys::CreateTopics => CreateTopicsResponse::parse(buf).map(KafkaResponse::CreateTopics),
+            ApiKeys::DeleteTopics => DeleteTopicsResponse::parse(buf).map(KafkaResponse::DeleteTopics),
+            ApiKeys::DeleteRecords => DeleteRecordsResponse::parse(buf).map(KafkaResponse::DeleteRecords),
+            ApiKeys::OffsetForLeaderEpoch => {
+                OffsetsForLeaderEpochResponse::parse(buf, api_version).map(KafkaResponse::OffsetsForLeaderEpoch)
+            }
+            ApiKeys::DescribeConfigs => {
+                DescribeConfigsResponse::parse(buf, api_version).map(KafkaResponse::DescribeConfigs)
+            }
+            ApiKeys::AlterConfigs => AlterConfigsResponse::parse(buf).map(KafkaResponse::AlterConfigs),
+            ApiKeys::SaslAuthenticate => {
+                SaslAuthenticateResponse::parse(buf, api_version).map(KafkaResponse::SaslAuthenticate)
+            }
+            _ => {
+                warn!("unsupported {:?} response with {} bytes", api_key, buf.len());
+
+                // fail the response instead of waiting for more bytes, the whole frame has been received
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    Error::from(ErrorKind::UnsupportedApiKey(api_key)),
+                ));
+            }
         };
 
         match res {
@@ -97,7 +169,11 @@ impl KafkaResponse {
 
                 debug!("\n{}", hexdump!(buf));
 
-                Ok(None)
+                // the frame has been received completely, more bytes will never arrive
+                Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    Error::from(ErrorKind::ParseError(format!("truncated {:?} response", api_key))),
+                ))
             }
             IResult::Error(err) => {
                 if log_enabled!(Debug) {
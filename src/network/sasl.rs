@@ -0,0 +1,609 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::io;
+use std::mem;
+use std::str::FromStr;
+use std::time::Duration;
+
+use bytes::{BigEndian, BufMut, ByteOrder, BytesMut};
+use rand::{self, Rng};
+
+use futures::future::{self, Either, Future};
+use tokio_io::codec::{Decoder, Encoder};
+use tokio_io::io::{read_exact, write_all};
+use tokio_io::{AsyncRead, AsyncWrite};
+
+use errors::{Error, ErrorKind, Result};
+use network::{KafkaCodec, KafkaRequest, KafkaResponse, ScramClient};
+use protocol::{ApiKeys, ApiVersion, KafkaCode, ToMilliseconds, UsableApiVersions};
+
+/// The SASL mechanism to use for authentication.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SaslMechanism {
+    /// Simple username/password authentication, see [RFC 4616](https://tools.ietf.org/html/rfc4616).
+    #[serde(rename = "PLAIN")]
+    Plain,
+    /// Salted Challenge Response Authentication Mechanism with SHA-256
+    #[serde(rename = "SCRAM-SHA-256")]
+    ScramSha256,
+    /// Salted Challenge Response Authentication Mechanism with SHA-512
+    #[serde(rename = "SCRAM-SHA-512")]
+    ScramSha512,
+}
+
+impl Default for SaslMechanism {
+    fn default() -> Self {
+        SaslMechanism::Plain
+    }
+}
+
+impl SaslMechanism {
+    /// The mechanism name used in the SASL handshake.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            SaslMechanism::Plain => "PLAIN",
+            SaslMechanism::ScramSha256 => "SCRAM-SHA-256",
+            SaslMechanism::ScramSha512 => "SCRAM-SHA-512",
+        }
+    }
+}
+
+impl fmt::Display for SaslMechanism {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for SaslMechanism {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_uppercase().as_str() {
+            "PLAIN" => Ok(SaslMechanism::Plain),
+            "SCRAM-SHA-256" => Ok(SaslMechanism::ScramSha256),
+            "SCRAM-SHA-512" => Ok(SaslMechanism::ScramSha512),
+            _ => bail!(ErrorKind::ParseError(format!("unknown SASL mechanism: {}", s))),
+        }
+    }
+}
+
+/// Authenticate a new connection with SASL before it is used to send Kafka requests.
+///
+/// The client sends a `SaslHandshakeRequest` (v0) to select the mechanism,
+/// and then exchanges the raw SASL tokens framed by a 4 bytes size prefix.
+///
+/// When `api.version.request` is enabled and the broker supports the `SaslAuthenticate` API,
+/// the client sends a `SaslHandshakeRequest` (v1) instead, and then exchanges the SASL tokens
+/// wrapped in the `SaslAuthenticateRequest`. The broker may limit the lifetime of the session
+/// (KIP-368), which requires the connection be re-authenticated before it expires.
+#[derive(Clone, PartialEq)]
+pub struct SaslAuthenticator {
+    client_id: Option<String>,
+    mechanism: SaslMechanism,
+    username: String,
+    password: String,
+    api_version_request: bool,
+}
+
+impl fmt::Debug for SaslAuthenticator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SaslAuthenticator")
+            .field("client_id", &self.client_id)
+            .field("mechanism", &self.mechanism)
+            .field("username", &self.username)
+            .field("password", &"******")
+            .field("api_version_request", &self.api_version_request)
+            .finish()
+    }
+}
+
+/// The SASL session negotiated with the broker.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SaslSession {
+    /// The version of `SaslAuthenticate` API used to exchange the tokens,
+    /// or `None` if the tokens were exchanged without the Kafka request header.
+    pub authenticate_version: Option<ApiVersion>,
+    /// The lifetime of the session reported by the broker, or `None` if it never expires.
+    pub lifetime: Option<Duration>,
+}
+
+impl SaslSession {
+    /// The delay after which the connection should be re-authenticated.
+    ///
+    /// It picks a random point between 85% and 95% of the session lifetime,
+    /// to avoid re-authenticating all the connections at the same time.
+    pub fn reauthenticate_after(&self) -> Option<Duration> {
+        match (self.authenticate_version, self.lifetime) {
+            (Some(_), Some(lifetime)) => {
+                let percent = rand::thread_rng().gen_range(85, 96);
+
+                Some(Duration::from_millis(lifetime.as_millis() * percent / 100))
+            }
+            _ => None,
+        }
+    }
+}
+
+pub type Authenticate<S> = Box<Future<Item = (S, SaslSession), Error = io::Error>>;
+
+/// A channel to send the SASL requests to the broker.
+pub trait SaslChannel: Sized + 'static {
+    /// Send the request and wait for the response.
+    fn send_request(self, request: KafkaRequest<'static>) -> SendRequest<Self>;
+}
+
+pub type SendRequest<C> = Box<Future<Item = (C, KafkaResponse), Error = io::Error>>;
+
+/// Send the SASL requests on a connection which has not been authenticated.
+pub struct RawChannel<S>(pub S);
+
+impl<S> SaslChannel for RawChannel<S>
+where
+    S: AsyncRead + AsyncWrite + 'static,
+{
+    fn send_request(self, request: KafkaRequest<'static>) -> SendRequest<Self> {
+        Box::new(send_request(self.0, request).map(|(stream, response)| (RawChannel(stream), response)))
+    }
+}
+
+impl SaslAuthenticator {
+    /// Construct a `SaslAuthenticator` with the given SASL mechanism
+    pub fn new<S>(client_id: Option<String>, mechanism: SaslMechanism, username: S, password: S) -> Self
+    where
+        S: Into<String>,
+    {
+        SaslAuthenticator {
+            client_id,
+            mechanism,
+            username: username.into(),
+            password: password.into(),
+            api_version_request: false,
+        }
+    }
+
+    /// Construct a `SaslAuthenticator` for the SASL/PLAIN mechanism
+    pub fn plain<S: Into<String>>(client_id: Option<String>, username: S, password: S) -> Self {
+        Self::new(client_id, SaslMechanism::Plain, username, password)
+    }
+
+    /// Request broker's supported API versions before the handshake,
+    /// to exchange the tokens with the `SaslAuthenticate` API if possible.
+    pub fn with_api_version_request(mut self, api_version_request: bool) -> Self {
+        self.api_version_request = api_version_request;
+        self
+    }
+
+    pub fn mechanism(&self) -> SaslMechanism {
+        self.mechanism
+    }
+
+    /// Authenticate the stream, and return it once the broker accepted the credentials.
+    pub fn authenticate<S>(&self, stream: S) -> Authenticate<S>
+    where
+        S: AsyncRead + AsyncWrite + 'static,
+    {
+        let mechanism = self.mechanism;
+        let authenticator = self.clone();
+
+        let negotiate = if self.api_version_request {
+            Either::A(self.authenticate_version(stream))
+        } else {
+            Either::B(future::ok((stream, None)))
+        };
+
+        Box::new(
+            negotiate
+                .and_then(move |(stream, authenticate_version)| match authenticate_version {
+                    Some(api_version) => Either::A(
+                        authenticator
+                            .authenticate_channel(RawChannel(stream), api_version)
+                            .map(|(RawChannel(stream), session)| (stream, session)),
+                    ),
+                    None => Either::B(
+                        authenticator
+                            .handshake(RawChannel(stream), 0)
+                            .and_then(move |RawChannel(stream)| authenticator.exchange_tokens(RawTokens(stream)))
+                            .map(|RawTokens(stream)| (stream, SaslSession::default())),
+                    ),
+                })
+                .map(move |(stream, session)| {
+                    trace!("SASL/{} authentication succeeded, {:?}", mechanism, session);
+
+                    (stream, session)
+                })
+                .map_err(authentication_failed),
+        )
+    }
+
+    /// Re-authenticate an authenticated connection before the session expires (KIP-368).
+    pub fn reauthenticate<C>(&self, channel: C, session: &SaslSession) -> Authenticate<C>
+    where
+        C: SaslChannel,
+    {
+        let mechanism = self.mechanism;
+
+        match session.authenticate_version {
+            Some(api_version) => Box::new(
+                self.authenticate_channel(channel, api_version)
+                    .map(move |(channel, session)| {
+                        trace!("SASL/{} re-authentication succeeded, {:?}", mechanism, session);
+
+                        (channel, session)
+                    })
+                    .map_err(authentication_failed),
+            ),
+            None => Box::new(future::err(io::Error::new(
+                io::ErrorKind::Other,
+                "SASL re-authentication requires the SaslAuthenticate API",
+            ))),
+        }
+    }
+
+    fn authenticate_version<S>(&self, stream: S) -> Box<Future<Item = (S, Option<ApiVersion>), Error = io::Error>>
+    where
+        S: AsyncRead + AsyncWrite + 'static,
+    {
+        let request = KafkaRequest::api_versions(0, 0, self.client_id.clone().map(Cow::Owned));
+
+        Box::new(send_request(stream, request).map(|(stream, response)| {
+            let api_version = match response {
+                KafkaResponse::ApiVersions(ref res) if KafkaCode::from(res.error_code) == KafkaCode::None => {
+                    UsableApiVersions::new(res.api_versions.clone()).negotiate(ApiKeys::SaslAuthenticate)
+                }
+                _ => None,
+            };
+
+            debug!("use SaslAuthenticate API version {:?}", api_version);
+
+            (stream, api_version)
+        }))
+    }
+
+    fn authenticate_channel<C>(&self, channel: C, api_version: ApiVersion) -> Authenticate<C>
+    where
+        C: SaslChannel,
+    {
+        let authenticator = self.clone();
+        let client_id = self.client_id.clone();
+
+        Box::new(
+            self.handshake(channel, 1)
+                .and_then(move |channel| {
+                    authenticator.exchange_tokens(AuthenticateTokens {
+                        channel,
+                        client_id,
+                        api_version,
+                        session_lifetime: None,
+                    })
+                })
+                .map(move |tokens| {
+                    let session = SaslSession {
+                        authenticate_version: Some(api_version),
+                        lifetime: tokens.session_lifetime,
+                    };
+
+                    (tokens.channel, session)
+                }),
+        )
+    }
+
+    fn handshake<C>(&self, channel: C, api_version: ApiVersion) -> Box<Future<Item = C, Error = io::Error>>
+    where
+        C: SaslChannel,
+    {
+        let mechanism = self.mechanism;
+        let request = KafkaRequest::sasl_handshake(
+            api_version,
+            0,
+            self.client_id.clone().map(Cow::Owned),
+            Cow::Borrowed(mechanism.name()),
+        );
+
+        Box::new(channel.send_request(request).and_then(move |(channel, response)| {
+            if let KafkaResponse::SaslHandshake(res) = response {
+                let error_code = KafkaCode::from(res.error_code);
+
+                if error_code == KafkaCode::None {
+                    Ok(channel)
+                } else {
+                    Err(io::Error::new(
+                        io::ErrorKind::PermissionDenied,
+                        format!(
+                            "SASL mechanism {} not enabled, {}, enabled mechanisms: {}",
+                            mechanism,
+                            error_code,
+                            res.enabled_mechanisms.join(",")
+                        ),
+                    ))
+                }
+            } else {
+                Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unexpected SASL handshake response, {:?}", response.api_key()),
+                ))
+            }
+        }))
+    }
+
+    fn exchange_tokens<T>(&self, transport: T) -> Box<Future<Item = T, Error = io::Error>>
+    where
+        T: SaslTransport,
+    {
+        match self.mechanism {
+            SaslMechanism::Plain => Box::new(
+                transport
+                    .round_trip(self.plain_token())
+                    .map(|(transport, _token)| transport),
+            ),
+            SaslMechanism::ScramSha256 | SaslMechanism::ScramSha512 => {
+                let mut scram = ScramClient::new(self.mechanism, self.username.as_str(), self.password.as_str());
+                let client_first = scram.client_first();
+
+                Box::new(
+                    transport
+                        .round_trip(client_first)
+                        .and_then(move |(transport, server_first)| {
+                            future::result(scram.client_final(&server_first))
+                                .and_then(move |client_final| transport.round_trip(client_final))
+                                .and_then(move |(transport, server_final)| {
+                                    scram.verify_server_final(&server_final).map(|_| transport)
+                                })
+                        }),
+                )
+            }
+        }
+    }
+
+    fn plain_token(&self) -> Vec<u8> {
+        let mut token = Vec::with_capacity(self.username.len() + self.password.len() + 2);
+
+        // authzid (empty) NUL authcid NUL passwd
+        token.push(0);
+        token.extend_from_slice(self.username.as_bytes());
+        token.push(0);
+        token.extend_from_slice(self.password.as_bytes());
+        token
+    }
+}
+
+fn authentication_failed(err: io::Error) -> io::Error {
+    warn!("fail to authenticate with SASL, {}", err);
+
+    match err.kind() {
+        io::ErrorKind::UnexpectedEof | io::ErrorKind::ConnectionReset => {
+            io::Error::new(io::ErrorKind::PermissionDenied, "SASL authentication failed")
+        }
+        _ => err,
+    }
+}
+
+/// Exchange a SASL token with the broker.
+trait SaslTransport: Sized + 'static {
+    fn round_trip(self, token: Vec<u8>) -> Box<Future<Item = (Self, Vec<u8>), Error = io::Error>>;
+}
+
+/// Exchange the raw SASL tokens framed by a 4 bytes size prefix.
+struct RawTokens<S>(S);
+
+impl<S> SaslTransport for RawTokens<S>
+where
+    S: AsyncRead + AsyncWrite + 'static,
+{
+    fn round_trip(self, token: Vec<u8>) -> Box<Future<Item = (Self, Vec<u8>), Error = io::Error>> {
+        Box::new(
+            send_token(self.0, token)
+                .and_then(recv_token)
+                .map(|(stream, token)| (RawTokens(stream), token)),
+        )
+    }
+}
+
+/// Exchange the SASL tokens wrapped in the `SaslAuthenticateRequest`.
+struct AuthenticateTokens<C> {
+    channel: C,
+    client_id: Option<String>,
+    api_version: ApiVersion,
+    session_lifetime: Option<Duration>,
+}
+
+impl<C> SaslTransport for AuthenticateTokens<C>
+where
+    C: SaslChannel,
+{
+    fn round_trip(self, token: Vec<u8>) -> Box<Future<Item = (Self, Vec<u8>), Error = io::Error>> {
+        let AuthenticateTokens {
+            channel,
+            client_id,
+            api_version,
+            ..
+        } = self;
+        let request =
+            KafkaRequest::sasl_authenticate(api_version, 0, client_id.clone().map(Cow::Owned), Cow::Owned(token));
+
+        Box::new(channel.send_request(request).and_then(move |(channel, response)| {
+            if let KafkaResponse::SaslAuthenticate(res) = response {
+                let error_code = KafkaCode::from(res.error_code);
+
+                if error_code != KafkaCode::None {
+                    return Err(io::Error::new(
+                        io::ErrorKind::PermissionDenied,
+                        format!(
+                            "SASL authentication failed, {}, {}",
+                            error_code,
+                            res.error_message.unwrap_or_default()
+                        ),
+                    ));
+                }
+
+                let session_lifetime = res.session_lifetime
+                    .and_then(|millis| if millis > 0 { Some(Duration::from_millis(millis as u64)) } else { None });
+
+                Ok((
+                    AuthenticateTokens {
+                        channel,
+                        client_id,
+                        api_version,
+                        session_lifetime,
+                    },
+                    res.auth_bytes.to_vec(),
+                ))
+            } else {
+                Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unexpected SASL authenticate response, {:?}", response.api_key()),
+                ))
+            }
+        }))
+    }
+}
+
+fn send_request<S>(
+    stream: S,
+    request: KafkaRequest<'static>,
+) -> Box<Future<Item = (S, KafkaResponse), Error = io::Error>>
+where
+    S: AsyncRead + AsyncWrite + 'static,
+{
+    let mut codec = KafkaCodec::new();
+    let mut buf = BytesMut::new();
+
+    if let Err(err) = codec.encode(request, &mut buf) {
+        return Box::new(future::err(err));
+    }
+
+    Box::new(
+        write_all(stream, buf)
+            .and_then(|(stream, _)| recv_frame(stream))
+            .and_then(move |(stream, mut frame)| match codec.decode(&mut frame)? {
+                Some(response) => Ok((stream, response)),
+                None => Err(io::Error::new(io::ErrorKind::InvalidData, "incomplete SASL response")),
+            }),
+    )
+}
+
+fn send_token<S>(stream: S, token: Vec<u8>) -> Box<Future<Item = S, Error = io::Error>>
+where
+    S: AsyncWrite + 'static,
+{
+    let mut buf = BytesMut::with_capacity(mem::size_of::<i32>() + token.len());
+
+    buf.put_i32::<BigEndian>(token.len() as i32);
+    buf.put_slice(&token);
+
+    Box::new(write_all(stream, buf).map(|(stream, _)| stream))
+}
+
+fn recv_token<S>(stream: S) -> Box<Future<Item = (S, Vec<u8>), Error = io::Error>>
+where
+    S: AsyncRead + 'static,
+{
+    Box::new(recv_frame(stream).map(|(stream, frame)| (stream, frame[mem::size_of::<i32>()..].to_vec())))
+}
+
+/// The maximum size of the SASL frames received before the connection has been authenticated,
+/// the same as the default `sasl.server.max.receive.size` of the brokers.
+const MAX_SASL_FRAME_SIZE: i32 = 512 * 1024;
+
+fn recv_frame<S>(stream: S) -> Box<Future<Item = (S, BytesMut), Error = io::Error>>
+where
+    S: AsyncRead + 'static,
+{
+    Box::new(
+        read_exact(stream, [0u8; 4]).and_then(|(stream, header)| {
+            let size = BigEndian::read_i32(&header[..]);
+
+            if size < 0 || size > MAX_SASL_FRAME_SIZE {
+                return future::Either::A(future::err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid frame size: {}", size),
+                )));
+            }
+
+            future::Either::B(read_exact(stream, vec![0u8; size as usize]).map(move |(stream, body)| {
+                let mut frame = BytesMut::with_capacity(header.len() + body.len());
+
+                frame.put_slice(&header[..]);
+                frame.put_slice(&body);
+
+                (stream, frame)
+            }))
+        }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_token() {
+        let authenticator = SaslAuthenticator::plain(None, "user", "pass");
+
+        assert_eq!(authenticator.mechanism(), SaslMechanism::Plain);
+        assert_eq!(
+            authenticator.plain_token(),
+            vec![0, b'u', b's', b'e', b'r', 0, b'p', b'a', b's', b's']
+        );
+    }
+
+    #[test]
+    fn test_redact_password() {
+        let authenticator = SaslAuthenticator::plain(None, "user", "secret");
+
+        assert!(!format!("{:?}", authenticator).contains("secret"));
+    }
+
+    #[test]
+    fn test_recv_frame() {
+        let frame = vec![0, 0, 0, 3, 1, 2, 3];
+
+        let (_, received) = recv_frame(io::Cursor::new(frame.clone())).wait().unwrap();
+
+        assert_eq!(&received[..], &frame[..]);
+
+        // the oversized frame is rejected before allocating its body
+        let mut header = vec![0u8; 4];
+
+        BigEndian::write_i32(&mut header, i32::max_value());
+
+        match recv_frame(io::Cursor::new(header)).wait() {
+            Err(ref err) if err.kind() == io::ErrorKind::InvalidData => {}
+            res => panic!("unexpected result: {:?}", res.map(|(_, frame)| frame)),
+        }
+    }
+
+    #[test]
+    fn test_sasl_mechanism() {
+        assert_eq!("PLAIN".parse::<SaslMechanism>().unwrap(), SaslMechanism::Plain);
+        assert_eq!(
+            "scram-sha-256".parse::<SaslMechanism>().unwrap(),
+            SaslMechanism::ScramSha256
+        );
+        assert_eq!(
+            "SCRAM-SHA-512".parse::<SaslMechanism>().unwrap(),
+            SaslMechanism::ScramSha512
+        );
+        assert!("GSSAPI".parse::<SaslMechanism>().is_err());
+        assert_eq!(SaslMechanism::ScramSha256.to_string(), "SCRAM-SHA-256");
+    }
+
+    #[test]
+    fn test_reauthenticate_after() {
+        let mut session = SaslSession {
+            authenticate_version: None,
+            lifetime: Some(Duration::from_secs(100)),
+        };
+
+        assert_eq!(session.reauthenticate_after(), None);
+
+        session.authenticate_version = Some(1);
+
+        let delay = session.reauthenticate_after().unwrap();
+
+        assert!(delay >= Duration::from_secs(85) && delay <= Duration::from_secs(95));
+
+        session.lifetime = None;
+
+        assert_eq!(session.reauthenticate_after(), None);
+    }
+}
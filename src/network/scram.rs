@@ -0,0 +1,271 @@
+use std::io;
+use std::str;
+
+use base64;
+use hmac::{Hmac, Mac};
+use rand::{self, Rng};
+use sha2::{Digest, Sha256, Sha512};
+
+use network::SaslMechanism;
+
+const NONCE_LENGTH: usize = 24;
+/// The minimum iteration count of the SCRAM-SHA-256 and SCRAM-SHA-512 mechanisms, see RFC 7677.
+const MIN_ITERATIONS: u32 = 4096;
+/// The maximum iteration count accepted from the server, which keeps a hostile broker from
+/// tying up the client with an endless key derivation.
+const MAX_ITERATIONS: u32 = 1 << 20;
+/// The GS2 header without channel binding, base64 encoded as `biws`.
+const GS2_HEADER: &str = "n,,";
+
+/// The client side of the SCRAM authentication exchange, see [RFC 5802](https://tools.ietf.org/html/rfc5802).
+#[derive(Debug)]
+pub struct ScramClient {
+    mechanism: SaslMechanism,
+    username: String,
+    password: String,
+    nonce: String,
+    state: State,
+}
+
+#[derive(Debug)]
+enum State {
+    Initial,
+    ClientFirst { client_first_bare: String },
+    ClientFinal { server_signature: Vec<u8> },
+}
+
+impl ScramClient {
+    pub fn new<S: Into<String>>(mechanism: SaslMechanism, username: S, password: S) -> Self {
+        let nonce = rand::thread_rng().gen_ascii_chars().take(NONCE_LENGTH).collect();
+
+        Self::with_nonce(mechanism, username, password, nonce)
+    }
+
+    fn with_nonce<S: Into<String>>(mechanism: SaslMechanism, username: S, password: S, nonce: String) -> Self {
+        ScramClient {
+            mechanism,
+            username: username.into(),
+            password: password.into(),
+            nonce,
+            state: State::Initial,
+        }
+    }
+
+    /// Build the `client-first-message`
+    pub fn client_first(&mut self) -> Vec<u8> {
+        let client_first_bare = format!("n={},r={}", escape_username(&self.username), self.nonce);
+        let client_first = format!("{}{}", GS2_HEADER, client_first_bare);
+
+        self.state = State::ClientFirst { client_first_bare };
+
+        client_first.into_bytes()
+    }
+
+    /// Handle the `server-first-message` and build the `client-final-message`
+    pub fn client_final(&mut self, server_first: &[u8]) -> io::Result<Vec<u8>> {
+        let client_first_bare = match self.state {
+            State::ClientFirst { ref client_first_bare } => client_first_bare.clone(),
+            _ => return Err(invalid_data("unexpected SCRAM server-first-message")),
+        };
+
+        let server_first =
+            str::from_utf8(server_first).map_err(|_| invalid_data("invalid SCRAM server-first-message"))?;
+
+        let mut nonce = None;
+        let mut salt = None;
+        let mut iterations = None;
+
+        for attr in server_first.split(',') {
+            if attr.starts_with("r=") {
+                nonce = Some(&attr[2..]);
+            } else if attr.starts_with("s=") {
+                salt = Some(base64::decode(&attr[2..]).map_err(|_| invalid_data("invalid SCRAM salt"))?);
+            } else if attr.starts_with("i=") {
+                iterations = Some(attr[2..]
+                    .parse::<u32>()
+                    .map_err(|_| invalid_data("invalid SCRAM iteration count"))?);
+            } else if attr.starts_with("e=") {
+                return Err(authentication_failed(&attr[2..]));
+            }
+        }
+
+        let nonce = nonce.ok_or_else(|| invalid_data("missing SCRAM nonce"))?;
+        let salt = salt.ok_or_else(|| invalid_data("missing SCRAM salt"))?;
+        let iterations = iterations.ok_or_else(|| invalid_data("missing SCRAM iteration count"))?;
+
+        if iterations < MIN_ITERATIONS || iterations > MAX_ITERATIONS {
+            return Err(invalid_data("SCRAM iteration count out of range"));
+        }
+
+        if !nonce.starts_with(&self.nonce) {
+            return Err(invalid_data("SCRAM server nonce doesn't start with the client nonce"));
+        }
+
+        let salted_password = self.hi(self.password.as_bytes(), &salt, iterations);
+        let client_key = self.hmac(&salted_password, b"Client Key");
+        let stored_key = self.hash(&client_key);
+        let client_final_without_proof = format!("c={},r={}", base64::encode(GS2_HEADER.as_bytes()), nonce);
+        let auth_message = format!("{},{},{}", client_first_bare, server_first, client_final_without_proof);
+        let client_signature = self.hmac(&stored_key, auth_message.as_bytes());
+        let client_proof = client_key
+            .iter()
+            .zip(client_signature.iter())
+            .map(|(key, signature)| key ^ signature)
+            .collect::<Vec<u8>>();
+        let server_key = self.hmac(&salted_password, b"Server Key");
+        let server_signature = self.hmac(&server_key, auth_message.as_bytes());
+
+        self.state = State::ClientFinal { server_signature };
+
+        Ok(format!("{},p={}", client_final_without_proof, base64::encode(&client_proof)).into_bytes())
+    }
+
+    /// Verify the `server-final-message`
+    pub fn verify_server_final(&self, server_final: &[u8]) -> io::Result<()> {
+        let server_signature = match self.state {
+            State::ClientFinal { ref server_signature } => server_signature,
+            _ => return Err(invalid_data("unexpected SCRAM server-final-message")),
+        };
+
+        let server_final =
+            str::from_utf8(server_final).map_err(|_| invalid_data("invalid SCRAM server-final-message"))?;
+
+        for attr in server_final.split(',') {
+            if attr.starts_with("v=") {
+                let verifier = base64::decode(&attr[2..]).map_err(|_| invalid_data("invalid SCRAM verifier"))?;
+
+                return if verifier == *server_signature {
+                    Ok(())
+                } else {
+                    Err(authentication_failed("invalid server signature"))
+                };
+            } else if attr.starts_with("e=") {
+                return Err(authentication_failed(&attr[2..]));
+            }
+        }
+
+        Err(invalid_data("missing SCRAM server signature"))
+    }
+
+    fn hash(&self, data: &[u8]) -> Vec<u8> {
+        match self.mechanism {
+            SaslMechanism::ScramSha512 => Sha512::digest(data).to_vec(),
+            _ => Sha256::digest(data).to_vec(),
+        }
+    }
+
+    fn hmac(&self, key: &[u8], data: &[u8]) -> Vec<u8> {
+        match self.mechanism {
+            SaslMechanism::ScramSha512 => {
+                let mut mac = Hmac::<Sha512>::new_varkey(key).expect("HMAC can take key of any size");
+                mac.input(data);
+                mac.result().code().to_vec()
+            }
+            _ => {
+                let mut mac = Hmac::<Sha256>::new_varkey(key).expect("HMAC can take key of any size");
+                mac.input(data);
+                mac.result().code().to_vec()
+            }
+        }
+    }
+
+    /// The PBKDF2 function with HMAC as the pseudorandom function
+    fn hi(&self, password: &[u8], salt: &[u8], iterations: u32) -> Vec<u8> {
+        let mut salt = salt.to_vec();
+        salt.extend_from_slice(&[0, 0, 0, 1]);
+
+        let mut u = self.hmac(password, &salt);
+        let mut result = u.clone();
+
+        for _ in 1..iterations {
+            u = self.hmac(password, &u);
+
+            for (r, b) in result.iter_mut().zip(u.iter()) {
+                *r ^= b;
+            }
+        }
+
+        result
+    }
+}
+
+fn escape_username(username: &str) -> String {
+    username.replace('=', "=3D").replace(',', "=2C")
+}
+
+fn invalid_data(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+fn authentication_failed(reason: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        format!("SCRAM authentication failed, {}", reason),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape_username() {
+        assert_eq!(escape_username("user"), "user");
+        assert_eq!(escape_username("a=b,c"), "a=3Db=2Cc");
+    }
+
+    #[test]
+    fn test_scram_sha256() {
+        // test vectors from RFC 7677
+        let mut scram = ScramClient::with_nonce(
+            SaslMechanism::ScramSha256,
+            "user",
+            "pencil",
+            "rOprNGfwEbeRWgbNEkqO".to_owned(),
+        );
+
+        assert_eq!(scram.client_first(), b"n,,n=user,r=rOprNGfwEbeRWgbNEkqO".to_vec());
+
+        let client_final = scram
+            .client_final(b"r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096")
+            .unwrap();
+
+        assert_eq!(
+            str::from_utf8(&client_final).unwrap(),
+            "c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,p=dHzbZapWIk4jUhN+Ute9ytag9zjfMHgsqmmiz7AndVQ="
+        );
+
+        assert!(
+            scram
+                .verify_server_final(b"v=6rriTRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4=")
+                .is_ok()
+        );
+        assert!(scram.verify_server_final(b"v=AAAA").is_err());
+        assert!(scram.verify_server_final(b"e=invalid-proof").is_err());
+    }
+
+    #[test]
+    fn test_invalid_server_nonce() {
+        let mut scram = ScramClient::with_nonce(SaslMechanism::ScramSha512, "user", "pencil", "nonce".to_owned());
+
+        scram.client_first();
+
+        assert!(scram.client_final(b"r=other,s=QSXCR+Q6sek8bf92,i=4096").is_err());
+    }
+
+    #[test]
+    fn test_iteration_count_out_of_range() {
+        for &iterations in &[1, 4095, (1 << 20) + 1, u32::max_value()] {
+            let mut scram = ScramClient::with_nonce(SaslMechanism::ScramSha256, "user", "pencil", "nonce".to_owned());
+
+            scram.client_first();
+
+            let server_first = format!("r=nonce-server,s=QSXCR+Q6sek8bf92,i={}", iterations);
+
+            match scram.client_final(server_first.as_bytes()) {
+                Err(ref err) if err.kind() == io::ErrorKind::InvalidData => {}
+                res => panic!("iterations {} unexpected result: {:?}", iterations, res),
+            }
+        }
+    }
+}
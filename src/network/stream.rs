@@ -7,33 +7,47 @@ use std::net::SocketAddr;
 use futures::future::Future;
 use futures::{Async, Poll};
 use native_tls::TlsConnector;
-use tokio_core::net::{TcpStream, TcpStreamNew};
 use tokio_core::reactor::Handle;
 use tokio_io::{AsyncRead, AsyncWrite};
 use tokio_tls::{ConnectAsync, TlsConnectorExt, TlsStream};
 use ns_router::{AutoName, Router};
 use ns_router::future::ResolveFuture;
 
-use network::DEFAULT_PORT;
+use network::{SharedTransport, Transport, TransportConnect, TransportStream, DEFAULT_PORT};
+
+/// The TLS settings used to wrap the connections.
+#[derive(Clone)]
+pub struct TlsSettings {
+    /// The connector with the trusted CA certificates and the client identity.
+    pub connector: TlsConnector,
+    /// Whether to verify the broker hostname against its certificate.
+    pub hostname_verification: bool,
+}
 
 pub struct KafkaConnector {
     handle: Handle,
     router: Rc<Router>,
+    transport: SharedTransport,
 }
 
 impl KafkaConnector {
-    pub fn new(handle: Handle, router: Rc<Router>) -> Self {
-        KafkaConnector { handle, router }
+    pub fn new(handle: Handle, router: Rc<Router>, transport: SharedTransport) -> Self {
+        KafkaConnector {
+            handle,
+            router,
+            transport,
+        }
     }
 
-    pub fn tcp<'n, N>(&self, addr: N) -> Connect
+    pub fn plain<'n, N>(&self, addr: N) -> Connect
     where
         N: Into<AutoName<'n>> + fmt::Debug,
     {
-        trace!("TCP connect to {:?}", addr);
+        trace!("connect to {:?}", addr);
 
         Connect {
             handle: self.handle.clone(),
+            transport: self.transport.clone(),
             domain: None,
             connector: None,
             state: State::Resolving(self.router.resolve_auto(addr, DEFAULT_PORT)),
@@ -49,21 +63,40 @@ impl KafkaConnector {
 
         Connect {
             handle: self.handle.clone(),
+            transport: self.transport.clone(),
             domain: Some(domain.into()),
             connector: Some(connector),
             state: State::Resolving(self.router.resolve_auto(addr, DEFAULT_PORT)),
         }
     }
+
+    /// Connect with TLS but skip the hostname verification and SNI,
+    /// the broker certificate will still be validated against the trusted CA certificates.
+    pub fn tls_without_hostname_verification<'n, N>(&self, addr: N, connector: TlsConnector) -> Connect
+    where
+        N: Into<AutoName<'n>> + fmt::Debug,
+    {
+        trace!("TLS connect to {:?} without hostname verification", addr);
+
+        Connect {
+            handle: self.handle.clone(),
+            transport: self.transport.clone(),
+            domain: None,
+            connector: Some(connector),
+            state: State::Resolving(self.router.resolve_auto(addr, DEFAULT_PORT)),
+        }
+    }
 }
 
 enum State {
     Resolving(ResolveFuture),
-    Connecting(TcpStreamNew, SocketAddr, Vec<SocketAddr>),
-    Handshaking(ConnectAsync<TcpStream>, SocketAddr),
+    Connecting(TransportConnect, SocketAddr, Vec<SocketAddr>),
+    Handshaking(ConnectAsync<Box<TransportStream>>, SocketAddr),
 }
 
 pub struct Connect {
     handle: Handle,
+    transport: SharedTransport,
     domain: Option<String>,
     connector: Option<TlsConnector>,
     state: State,
@@ -89,9 +122,9 @@ impl Future for Connect {
                         addrs.reverse();
 
                         if let Some(addr) = addrs.pop() {
-                            trace!("TCP connecting to {}", addr);
+                            trace!("connecting to {}", addr);
 
-                            State::Connecting(TcpStream::connect(&addr, &self.handle), addr, addrs)
+                            State::Connecting(self.transport.connect(&addr, &self.handle), addr, addrs)
                         } else {
                             bail!(io::Error::new(io::ErrorKind::AddrNotAvailable, "no more address"));
                         }
@@ -103,14 +136,25 @@ impl Future for Connect {
                 },
                 State::Connecting(ref mut connecting, peer_addr, ref mut addrs) => match connecting.poll() {
                     Ok(Async::Ready(stream)) => {
-                        if let (&Some(ref domain), &Some(ref connector)) = (domain, connector) {
-                            trace!("TCP connected to {}, start TLS handshake", peer_addr);
-
-                            State::Handshaking(connector.connect_async(domain, stream), peer_addr)
-                        } else {
-                            trace!("TCP connected to {}", peer_addr);
-
-                            return Ok(Async::Ready(KafkaStream::Tcp(peer_addr, stream)));
+                        match (domain, connector) {
+                            (&Some(ref domain), &Some(ref connector)) => {
+                                trace!("connected to {}, start TLS handshake with {}", peer_addr, domain);
+
+                                State::Handshaking(connector.connect_async(domain, stream), peer_addr)
+                            }
+                            (&None, &Some(ref connector)) => {
+                                trace!("connected to {}, start TLS handshake", peer_addr);
+
+                                State::Handshaking(
+                                    connector.danger_connect_async_without_providing_domain_for_certificate_verification_and_server_name_indication(stream),
+                                    peer_addr,
+                                )
+                            }
+                            _ => {
+                                trace!("connected to {}", peer_addr);
+
+                                return Ok(Async::Ready(KafkaStream::Plain(peer_addr, stream)));
+                            }
                         }
                     }
                     Ok(Async::NotReady) => return Ok(Async::NotReady),
@@ -118,9 +162,9 @@ impl Future for Connect {
                         warn!("fail to connect {}, {}", peer_addr, err);
 
                         if let Some(addr) = addrs.pop() {
-                            trace!("TCP connecting to {}", addr);
+                            trace!("connecting to {}", addr);
 
-                            State::Connecting(TcpStream::connect(&addr, &self.handle), addr, addrs.clone())
+                            State::Connecting(self.transport.connect(&addr, &self.handle), addr, addrs.clone())
                         } else {
                             bail!(io::Error::new(io::ErrorKind::NotConnected, err));
                         }
@@ -147,14 +191,14 @@ impl Future for Connect {
 }
 
 pub enum KafkaStream {
-    Tcp(SocketAddr, TcpStream),
-    Tls(SocketAddr, TlsStream<TcpStream>),
+    Plain(SocketAddr, Box<TransportStream>),
+    Tls(SocketAddr, TlsStream<Box<TransportStream>>),
 }
 
 impl fmt::Debug for KafkaStream {
     fn fmt(&self, w: &mut fmt::Formatter) -> fmt::Result {
         match *self {
-            KafkaStream::Tcp(ref addr, _) => write!(w, "TcpStream({})", addr),
+            KafkaStream::Plain(ref addr, _) => write!(w, "PlainStream({})", addr),
             KafkaStream::Tls(ref addr, _) => write!(w, "TlsStream({})", addr),
         }
     }
@@ -163,7 +207,7 @@ impl fmt::Debug for KafkaStream {
 impl Read for KafkaStream {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         match *self {
-            KafkaStream::Tcp(_, ref mut stream) => stream.read(buf),
+            KafkaStream::Plain(_, ref mut stream) => stream.read(buf),
             KafkaStream::Tls(_, ref mut stream) => stream.read(buf),
         }
     }
@@ -172,14 +216,14 @@ impl Read for KafkaStream {
 impl Write for KafkaStream {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         match *self {
-            KafkaStream::Tcp(_, ref mut stream) => stream.write(buf),
+            KafkaStream::Plain(_, ref mut stream) => stream.write(buf),
             KafkaStream::Tls(_, ref mut stream) => stream.write(buf),
         }
     }
 
     fn flush(&mut self) -> io::Result<()> {
         match *self {
-            KafkaStream::Tcp(_, ref mut stream) => stream.flush(),
+            KafkaStream::Plain(_, ref mut stream) => stream.flush(),
             KafkaStream::Tls(_, ref mut stream) => stream.flush(),
         }
     }
@@ -190,7 +234,7 @@ impl AsyncRead for KafkaStream {}
 impl AsyncWrite for KafkaStream {
     fn shutdown(&mut self) -> Poll<(), io::Error> {
         match *self {
-            KafkaStream::Tcp(_, ref mut stream) => AsyncWrite::shutdown(stream),
+            KafkaStream::Plain(_, ref mut stream) => AsyncWrite::shutdown(stream),
             KafkaStream::Tls(_, ref mut stream) => stream.shutdown(),
         }
     }
@@ -199,7 +243,7 @@ impl AsyncWrite for KafkaStream {
 impl KafkaStream {
     pub fn addr(&self) -> &SocketAddr {
         match *self {
-            KafkaStream::Tcp(ref addr, _) | KafkaStream::Tls(ref addr, _) => addr,
+            KafkaStream::Plain(ref addr, _) | KafkaStream::Tls(ref addr, _) => addr,
         }
     }
 }
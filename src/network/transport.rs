@@ -0,0 +1,179 @@
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::rc::Rc;
+use std::time::Duration;
+
+use futures::Future;
+use tokio_core::net::TcpStream;
+use tokio_core::reactor::Handle;
+use tokio_io::{AsyncRead, AsyncWrite};
+
+/// A byte stream to the broker established by a `Transport`.
+pub trait TransportStream: AsyncRead + AsyncWrite + fmt::Debug {}
+
+impl<T> TransportStream for T
+where
+    T: AsyncRead + AsyncWrite + fmt::Debug,
+{
+}
+
+/// The future of a `Transport` connecting to the broker.
+pub type TransportConnect = Box<Future<Item = Box<TransportStream>, Error = io::Error>>;
+
+/// The transport which establishes the byte streams to the brokers,
+/// e.g. TCP, a SOCKS5 or HTTP proxy, a Unix domain socket or an in-memory pipe for testing.
+///
+/// The TLS and SASL handshakes are performed on the established streams.
+pub trait Transport {
+    /// Connect to the broker at the resolved address.
+    fn connect(&self, addr: &SocketAddr, handle: &Handle) -> TransportConnect;
+}
+
+/// The options of the TCP sockets connected to the brokers.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SocketOptions {
+    /// Disable the Nagle's algorithm, so the small requests are sent without the delay.
+    pub nodelay: bool,
+    /// The size of the TCP send buffer, or the OS default if not set.
+    pub send_buffer_size: Option<usize>,
+    /// The size of the TCP receive buffer, or the OS default if not set.
+    pub recv_buffer_size: Option<usize>,
+    /// The idle time before the TCP keepalive probes are sent, or the keepalive is disabled if not set.
+    pub keepalive: Option<Duration>,
+}
+
+impl Default for SocketOptions {
+    fn default() -> Self {
+        SocketOptions {
+            nodelay: true,
+            send_buffer_size: None,
+            recv_buffer_size: None,
+            keepalive: None,
+        }
+    }
+}
+
+impl SocketOptions {
+    /// Apply the options to the connected socket.
+    pub fn apply(&self, stream: &TcpStream) -> io::Result<()> {
+        stream.set_nodelay(self.nodelay)?;
+
+        if let Some(size) = self.send_buffer_size {
+            stream.set_send_buffer_size(size)?;
+        }
+        if let Some(size) = self.recv_buffer_size {
+            stream.set_recv_buffer_size(size)?;
+        }
+
+        stream.set_keepalive(self.keepalive)
+    }
+}
+
+/// Connect to the brokers with TCP.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TcpTransport {
+    options: SocketOptions,
+}
+
+impl TcpTransport {
+    pub fn new(options: SocketOptions) -> Self {
+        TcpTransport { options }
+    }
+}
+
+impl Transport for TcpTransport {
+    fn connect(&self, addr: &SocketAddr, handle: &Handle) -> TransportConnect {
+        let options = self.options;
+
+        Box::new(TcpStream::connect(addr, handle).and_then(move |stream| {
+            options.apply(&stream)?;
+
+            Ok(Box::new(stream) as Box<TransportStream>)
+        }))
+    }
+}
+
+/// The transport registered on the `ClientConfig`, the brokers are connected with TCP by default.
+#[derive(Clone, Default)]
+pub struct SharedTransport(Option<Rc<Transport>>);
+
+impl SharedTransport {
+    pub fn new<T>(transport: T) -> Self
+    where
+        T: 'static + Transport,
+    {
+        SharedTransport(Some(Rc::new(transport)))
+    }
+
+    /// Returns the registered transport, or a TCP transport with the socket options.
+    pub fn or_tcp(&self, options: SocketOptions) -> Self {
+        match self.0 {
+            Some(_) => self.clone(),
+            None => SharedTransport::new(TcpTransport::new(options)),
+        }
+    }
+}
+
+impl Transport for SharedTransport {
+    fn connect(&self, addr: &SocketAddr, handle: &Handle) -> TransportConnect {
+        match self.0 {
+            Some(ref transport) => transport.connect(addr, handle),
+            None => TcpTransport::default().connect(addr, handle),
+        }
+    }
+}
+
+impl fmt::Debug for SharedTransport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(_) => write!(f, "SharedTransport(Custom)"),
+            None => write!(f, "SharedTransport(Tcp)"),
+        }
+    }
+}
+
+impl PartialEq for SharedTransport {
+    fn eq(&self, other: &Self) -> bool {
+        match (&self.0, &other.0) {
+            (&Some(ref l), &Some(ref r)) => Rc::ptr_eq(l, r),
+            (&None, &None) => true,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future;
+    use tokio_core::reactor::Core;
+
+    use super::*;
+
+    struct UnreachableTransport;
+
+    impl Transport for UnreachableTransport {
+        fn connect(&self, _addr: &SocketAddr, _handle: &Handle) -> TransportConnect {
+            Box::new(future::err(io::Error::new(io::ErrorKind::Other, "unreachable")))
+        }
+    }
+
+    #[test]
+    fn test_shared_transport() {
+        let mut core = Core::new().unwrap();
+        let handle = core.handle();
+        let addr = "127.0.0.1:9092".parse().unwrap();
+        let transport = SharedTransport::new(UnreachableTransport);
+
+        assert_eq!(transport, transport.clone());
+        assert_ne!(transport, SharedTransport::default());
+        assert_eq!(SharedTransport::default(), SharedTransport::default());
+        // the registered transport is kept, otherwise the brokers are connected with TCP and the socket options
+        assert_eq!(transport.or_tcp(SocketOptions::default()), transport);
+        assert_ne!(SharedTransport::default().or_tcp(SocketOptions::default()), SharedTransport::default());
+
+        let err = core.run(transport.connect(&addr, &handle)).unwrap_err();
+
+        assert_eq!(err.to_string(), "unreachable");
+    }
+}
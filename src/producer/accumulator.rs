@@ -1,18 +1,21 @@
 use std::cell::RefCell;
-use std::collections::{HashMap, VecDeque};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::rc::Rc;
 use std::time::Duration;
 
 use bytes::Bytes;
 
-use futures::{Async, Future, IntoFuture, Poll, Stream};
+use futures::task::{self, Task};
+use futures::future::Either;
+use futures::{future, Async, Future, IntoFuture, Poll, Stream};
 
-use client::{StaticBoxFuture, ToStaticBoxFuture};
+use client::{Runtime, StaticBoxFuture, ToStaticBoxFuture};
 use compression::Compression;
-use errors::Error;
+use errors::{Error, ErrorKind};
 use network::TopicPartition;
-use producer::{ProducerBatch, RecordMetadata};
-use protocol::{ApiVersion, Timestamp};
+use producer::{DeliveryReporter, ProducerBatch, RecordId, RecordMetadata, SpillEntry, SpillFile, SpilledRecord,
+               TopicOverrides};
+use protocol::{ApiVersion, KafkaCode, Timestamp};
 
 /// Accumulator acts as a queue that accumulates records
 pub trait Accumulator<'a> {
@@ -31,6 +34,7 @@ pub trait Accumulator<'a> {
 
 /// `RecordAccumulator` acts as a queue that accumulates records into `ProducerRecord` instances to
 /// be sent to the server.
+#[derive(Clone)]
 pub struct RecordAccumulator<'a> {
     /// The size to use when allocating ProducerRecord instances
     batch_size: usize,
@@ -38,6 +42,9 @@ pub struct RecordAccumulator<'a> {
     /// The compression codec for the records
     compression: Compression,
 
+    /// The compression codec and batch size overrides of the topics.
+    topic_overrides: Rc<BTreeMap<String, TopicOverrides>>,
+
     /// An artificial delay time to add before declaring a records instance that isn't full ready
     /// for sending.
     ///
@@ -47,45 +54,186 @@ pub struct RecordAccumulator<'a> {
     linger: Duration,
 
     batches: Rc<RefCell<HashMap<TopicPartition<'a>, VecDeque<ProducerBatch>>>>,
+
+    /// The partitions which have a batch in flight.
+    in_flight: InFlight<'a>,
+
+    /// The memory budget of the buffered records.
+    memory: BufferMemory,
+
+    /// The maximum amount of time that pushing a record will wait for the buffer memory.
+    max_block: Duration,
+
+    runtime: Runtime,
+
+    /// The records will be spilled to the file when the buffer memory is exhausted.
+    spill: Option<Rc<RefCell<SpillFile>>>,
+
+    /// The delivery results of the records pushed with ids will be reported instead of resolving their futures.
+    reporter: Option<DeliveryReporter>,
 }
 
 impl<'a> RecordAccumulator<'a> {
-    pub fn new(batch_size: usize, compression: Compression, linger: Duration) -> Self {
+    pub fn new(
+        batch_size: usize,
+        compression: Compression,
+        linger: Duration,
+        buffer_memory: usize,
+        max_block: Duration,
+        runtime: Runtime,
+    ) -> Self {
         RecordAccumulator {
             batch_size,
             compression,
+            topic_overrides: Rc::new(BTreeMap::new()),
             linger,
             batches: Rc::new(RefCell::new(HashMap::new())),
+            in_flight: InFlight::default(),
+            memory: BufferMemory::new(buffer_memory),
+            max_block,
+            runtime,
+            spill: None,
+            reporter: None,
         }
     }
 
+    /// Spill the records to the file instead of blocking when the buffer memory is exhausted.
+    pub fn with_spill_file(mut self, spill: SpillFile) -> Self {
+        self.spill = Some(Rc::new(RefCell::new(spill)));
+        self
+    }
+
+    /// Report the delivery results of the records pushed with `push_reported_record`.
+    pub fn with_delivery_reporter(mut self, reporter: DeliveryReporter) -> Self {
+        self.reporter = Some(reporter);
+        self
+    }
+
+    /// Override the compression codec and batch size of the batches for the topics.
+    pub fn with_topic_overrides(mut self, topic_overrides: BTreeMap<String, TopicOverrides>) -> Self {
+        self.topic_overrides = Rc::new(topic_overrides);
+        self
+    }
+
     pub fn batches(&self, force: bool) -> Batches<'a> {
         Batches {
             batches: self.batches.clone(),
+            in_flight: self.in_flight.clone(),
             linger: self.linger,
             force,
+            runtime: self.runtime.clone(),
         }
     }
-}
 
-impl<'a> Accumulator<'a> for RecordAccumulator<'a> {
-    fn push_record(
+    pub fn in_flight(&self) -> InFlight<'a> {
+        self.in_flight.clone()
+    }
+
+    pub fn memory(&self) -> BufferMemory {
+        self.memory.clone()
+    }
+
+    /// Remove all the pending batches which have not been sent, and release their buffer memory.
+    pub fn abort(&self) -> Vec<(TopicPartition<'a>, ProducerBatch)> {
+        let mut aborted = Vec::new();
+
+        for (tp, batches) in self.batches.borrow_mut().drain() {
+            for batch in batches {
+                self.memory.release(batch.buffered_bytes());
+
+                aborted.push((tp.clone(), batch));
+            }
+        }
+
+        aborted
+    }
+
+    /// Remove the pending batches which have not been sent before the delivery timeout,
+    /// and release their buffer memory.
+    pub fn expire(&self, delivery_timeout: Duration) -> Vec<(TopicPartition<'a>, ProducerBatch)> {
+        let mut expired = Vec::new();
+        let now = self.runtime.now();
+
+        for (tp, batches) in self.batches.borrow_mut().iter_mut() {
+            // the batches of a partition are created in order
+            while batches
+                .front()
+                .map_or(false, |batch| now.duration_since(*batch.create_time()) >= delivery_timeout)
+            {
+                if let Some(batch) = batches.pop_front() {
+                    self.memory.release(batch.buffered_bytes());
+
+                    expired.push((tp.clone(), batch));
+                }
+            }
+        }
+
+        expired
+    }
+
+    /// Create an empty batch for the topic with its compression codec and batch size.
+    fn new_batch(&self, topic_name: &str, api_version: ApiVersion) -> ProducerBatch {
+        let overrides = self.topic_overrides.get(topic_name);
+        let compression = overrides
+            .and_then(|overrides| overrides.compression)
+            .unwrap_or(self.compression);
+        let batch_size = overrides
+            .and_then(|overrides| overrides.batch_size)
+            .unwrap_or(self.batch_size);
+
+        ProducerBatch::new(api_version, compression, batch_size).with_create_time(self.runtime.now())
+    }
+
+    /// Push the record to the batch, its delivery result will be reported if it has an id,
+    /// otherwise it resolves the future of the record.
+    fn push_to_batch(
+        &self,
+        batch: &mut ProducerBatch,
+        timestamp: Timestamp,
+        key: Option<Bytes>,
+        value: Option<Bytes>,
+        tracking: &Tracking,
+        new_batch: bool,
+    ) -> Result<PushRecord> {
+        let push_record = match (tracking.id, self.reporter.as_ref()) {
+            (Some(id), Some(reporter)) => {
+                batch.push_reported_record(timestamp, key, value, reporter, id)?;
+
+                PushRecord::reported(batch.is_full(), new_batch)
+            }
+            _ => {
+                let future = batch.push_record(timestamp, key, value)?;
+
+                PushRecord::new(future, batch.is_full(), new_batch)
+            }
+        };
+
+        if let Some(ref spilled) = tracking.spilled {
+            batch.spilled(spilled.clone());
+        }
+
+        Ok(push_record)
+    }
+
+    fn append(
         &self,
         tp: TopicPartition<'a>,
         timestamp: Timestamp,
         key: Option<Bytes>,
         value: Option<Bytes>,
         api_version: ApiVersion,
+        tracking: Tracking,
     ) -> PushRecord {
+        let size = key.as_ref().map_or(0, |b| b.len()) + value.as_ref().map_or(0, |b| b.len());
         let mut batches = self.batches.borrow_mut();
-        let batches = batches.entry(tp).or_insert_with(VecDeque::new);
+        let batches = batches.entry(tp.clone()).or_insert_with(VecDeque::new);
 
         if let Some(batch) = batches.back_mut() {
-            match batch.push_record(timestamp, key.clone(), value.clone()) {
+            match self.push_to_batch(batch, timestamp, key.clone(), value.clone(), &tracking, false) {
                 Ok(push_recrod) => {
                     trace!("pushed record to latest batch, {:?}", batch);
 
-                    return PushRecord::new(push_recrod, batch.is_full(), false);
+                    return push_recrod;
                 }
                 Err(err) => {
                     debug!("fail to push record, {}", err);
@@ -93,34 +241,199 @@ impl<'a> Accumulator<'a> for RecordAccumulator<'a> {
             }
         }
 
-        let mut batch = ProducerBatch::new(api_version, self.compression, self.batch_size);
+        let mut batch = self.new_batch(&tp.topic_name, api_version);
 
-        match batch.push_record(timestamp, key, value) {
+        match self.push_to_batch(&mut batch, timestamp, key, value, &tracking, true) {
             Ok(push_recrod) => {
                 trace!("pushed record to a new batch, {:?}", batch);
 
-                let batch_is_full = batch.is_full();
-
                 batches.push_back(batch);
 
-                PushRecord::new(push_recrod, batch_is_full, true)
+                push_recrod
             }
             Err(err) => {
                 warn!("fail to push record, {}", err);
 
+                self.memory.release(size);
+
                 PushRecord::new(Err(err), false, true)
             }
         }
     }
+}
+
+impl<'a> RecordAccumulator<'a>
+where
+    Self: 'static,
+{
+    /// Push a record whose delivery result will be reported with its id, instead of resolving a future.
+    pub fn push_reported_record(
+        &self,
+        tp: TopicPartition<'a>,
+        timestamp: Timestamp,
+        key: Option<Bytes>,
+        value: Option<Bytes>,
+        api_version: ApiVersion,
+        id: RecordId,
+    ) -> PushRecord {
+        self.push(tp, timestamp, key, value, api_version, Some(id))
+    }
+
+    fn push(
+        &self,
+        tp: TopicPartition<'a>,
+        timestamp: Timestamp,
+        key: Option<Bytes>,
+        value: Option<Bytes>,
+        api_version: ApiVersion,
+        id: Option<RecordId>,
+    ) -> PushRecord {
+        let size = key.as_ref().map_or(0, |b| b.len()) + value.as_ref().map_or(0, |b| b.len());
+
+        if size > self.memory.limit() {
+            warn!(
+                "record of {} bytes is larger than the buffer memory of {} bytes",
+                size,
+                self.memory.limit()
+            );
+
+            return PushRecord::new(Err(ErrorKind::KafkaError(KafkaCode::MessageSizeTooLarge).into()), false, false);
+        }
+
+        if self.memory.try_reserve(size) {
+            return self.append(tp, timestamp, key, value, api_version, Tracking { id, spilled: None });
+        }
+
+        if let Some(ref spill) = self.spill {
+            let record = SpilledRecord {
+                topic_name: tp.topic_name.to_string(),
+                partition_id: tp.partition_id,
+                timestamp,
+                api_version,
+                key: key.clone(),
+                value: value.clone(),
+            };
+
+            match spill.borrow_mut().append(&record) {
+                Ok(offset) => {
+                    debug!(
+                        "buffer memory exhausted, spill record of {} bytes to {:?}",
+                        size,
+                        spill.borrow().path()
+                    );
+
+                    let accumulator = self.clone();
+                    let memory = self.memory.clone();
+                    let spilled = SpillEntry::new(spill.clone(), offset);
+                    let spill = spill.clone();
+
+                    // the spilled record will be read back when the buffer memory is available
+                    return PushRecord::blocked(self.memory.reserve(size).and_then(move |_| {
+                        let replayed = spill.borrow_mut().replay(offset);
+
+                        match replayed {
+                            Ok(record) => Ok(accumulator.append(
+                                tp,
+                                record.timestamp,
+                                record.key,
+                                record.value,
+                                record.api_version,
+                                Tracking {
+                                    id,
+                                    spilled: Some(spilled),
+                                },
+                            )),
+                            Err(err) => {
+                                memory.release(size);
+
+                                Err(err.into())
+                            }
+                        }
+                    }));
+                }
+                Err(err) => {
+                    warn!("fail to spill record to {:?}, {}", spill.borrow().path(), err);
+                }
+            }
+        }
+
+        debug!(
+            "buffer memory exhausted, {} of {} bytes used, wait up to {:?} for {} bytes",
+            self.memory.used(),
+            self.memory.limit(),
+            self.max_block,
+            size
+        );
+
+        let accumulator = self.clone();
+        let tracking = Tracking { id, spilled: None };
+
+        PushRecord::blocked(
+            self.runtime
+                .timeout(self.memory.reserve(size), self.max_block)
+                .map(move |_| accumulator.append(tp, timestamp, key, value, api_version, tracking)),
+        )
+    }
+
+    /// Push a record recovered from the spill file, which waits for the buffer memory instead of spilling again,
+    /// and its entry will be acknowledged once the record has been delivered.
+    pub fn push_spilled_record(&self, tp: TopicPartition<'a>, record: SpilledRecord, offset: u64) -> PushRecord {
+        let size = record.key.as_ref().map_or(0, |b| b.len()) + record.value.as_ref().map_or(0, |b| b.len());
+        let tracking = Tracking {
+            id: None,
+            spilled: self.spill
+                .as_ref()
+                .map(|spill| SpillEntry::new(spill.clone(), offset)),
+        };
+
+        if self.memory.try_reserve(size) {
+            return self.append(tp, record.timestamp, record.key, record.value, record.api_version, tracking);
+        }
+
+        let accumulator = self.clone();
+
+        PushRecord::blocked(
+            self.runtime
+                .timeout(self.memory.reserve(size), self.max_block)
+                .map(move |_| {
+                    accumulator.append(tp, record.timestamp, record.key, record.value, record.api_version, tracking)
+                }),
+        )
+    }
+}
+
+/// How the delivery of a pushed record is tracked.
+#[derive(Clone, Debug)]
+struct Tracking {
+    /// The id to report the delivery result, instead of resolving the future of the record.
+    id: Option<RecordId>,
+    /// The entry in the spill file, which will be acknowledged once the record has been delivered.
+    spilled: Option<SpillEntry>,
+}
+
+impl<'a> Accumulator<'a> for RecordAccumulator<'a>
+where
+    Self: 'static,
+{
+    fn push_record(
+        &self,
+        tp: TopicPartition<'a>,
+        timestamp: Timestamp,
+        key: Option<Bytes>,
+        value: Option<Bytes>,
+        api_version: ApiVersion,
+    ) -> PushRecord {
+        self.push(tp, timestamp, key, value, api_version, None)
+    }
 
     fn flush(&mut self) {
         trace!("flush all batches");
 
-        for (_, batches) in self.batches.borrow_mut().iter_mut() {
+        for (tp, batches) in self.batches.borrow_mut().iter_mut() {
             let api_version = batches.back().map(|batch| batch.api_version());
 
             if let Some(api_version) = api_version {
-                batches.push_back(ProducerBatch::new(api_version, self.compression, self.batch_size))
+                batches.push_back(self.new_batch(&tp.topic_name, api_version))
             }
         }
     }
@@ -130,6 +443,8 @@ pub struct PushRecord {
     future: StaticBoxFuture<RecordMetadata>,
     is_full: bool,
     new_batch: bool,
+    blocked: Option<StaticBoxFuture<PushRecord>>,
+    reported: bool,
 }
 
 impl PushRecord {
@@ -141,6 +456,33 @@ impl PushRecord {
             future: future.static_boxed(),
             is_full,
             new_batch,
+            blocked: None,
+            reported: false,
+        }
+    }
+
+    /// The record was pushed, and its delivery result will be reported instead of resolving this future.
+    pub fn reported(is_full: bool, new_batch: bool) -> Self {
+        PushRecord {
+            future: future::empty().static_boxed(),
+            is_full,
+            new_batch,
+            blocked: None,
+            reported: true,
+        }
+    }
+
+    /// The record is waiting for the buffer memory, and will be pushed when the future resolved.
+    pub fn blocked<F>(future: F) -> Self
+    where
+        F: Future<Item = PushRecord, Error = Error> + 'static,
+    {
+        PushRecord {
+            future: future::empty().static_boxed(),
+            is_full: false,
+            new_batch: false,
+            blocked: Some(future.static_boxed()),
+            reported: false,
         }
     }
 
@@ -151,6 +493,37 @@ impl PushRecord {
     pub fn new_batch(&self) -> bool {
         self.new_batch
     }
+
+    pub fn is_blocked(&self) -> bool {
+        self.blocked.is_some()
+    }
+
+    /// The record was pushed to a batch which will report its delivery result.
+    pub fn is_reported(&self) -> bool {
+        self.reported
+    }
+
+    /// Wait until the record was pushed to a batch.
+    pub fn pushed(self) -> StaticBoxFuture<PushRecord> {
+        match self.blocked {
+            Some(blocked) => blocked,
+            None => future::ok(self).static_boxed(),
+        }
+    }
+
+    /// Wait until the record was pushed to a batch which will report its delivery result,
+    /// or fail if the record could not be pushed.
+    pub fn settled(self) -> StaticBoxFuture {
+        self.pushed()
+            .and_then(|push_record| {
+                if push_record.reported {
+                    Either::A(future::ok(()))
+                } else {
+                    Either::B(push_record.map(|_| ()))
+                }
+            })
+            .static_boxed()
+    }
 }
 
 impl Future for PushRecord {
@@ -158,14 +531,187 @@ impl Future for PushRecord {
     type Error = Error;
 
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
-        self.future.poll()
+        let pushed = match self.blocked {
+            Some(ref mut blocked) => try_ready!(blocked.poll()),
+            None => return self.future.poll(),
+        };
+
+        *self = pushed;
+
+        self.poll()
+    }
+}
+
+/// The memory budget shared by the buffered records.
+///
+/// The memory is reserved when a record was pushed to the accumulator,
+/// and released after the batch of the record was resolved.
+#[derive(Clone)]
+pub struct BufferMemory {
+    inner: Rc<RefCell<BufferMemoryInner>>,
+}
+
+struct BufferMemoryInner {
+    limit: usize,
+    used: usize,
+    waiters: Vec<Task>,
+}
+
+impl BufferMemory {
+    pub fn new(limit: usize) -> Self {
+        BufferMemory {
+            inner: Rc::new(RefCell::new(BufferMemoryInner {
+                limit,
+                used: 0,
+                waiters: vec![],
+            })),
+        }
+    }
+
+    /// The total bytes of the buffer memory.
+    pub fn limit(&self) -> usize {
+        self.inner.borrow().limit
+    }
+
+    /// The bytes of the buffer memory in use.
+    pub fn used(&self) -> usize {
+        self.inner.borrow().used
+    }
+
+    /// Reserve the memory if there is enough space.
+    pub fn try_reserve(&self, size: usize) -> bool {
+        let mut inner = self.inner.borrow_mut();
+
+        if inner.used + size > inner.limit {
+            false
+        } else {
+            inner.used += size;
+
+            true
+        }
+    }
+
+    /// Reserve the memory, wait until there is enough space.
+    pub fn reserve(&self, size: usize) -> ReserveMemory {
+        ReserveMemory {
+            memory: self.clone(),
+            size,
+        }
+    }
+
+    /// Release the memory, wake up the pending records.
+    pub fn release(&self, size: usize) {
+        let waiters = {
+            let mut inner = self.inner.borrow_mut();
+
+            inner.used = inner.used.saturating_sub(size);
+            inner.waiters.drain(..).collect::<Vec<_>>()
+        };
+
+        for waiter in waiters {
+            waiter.notify();
+        }
+    }
+}
+
+/// The future of reserving the buffer memory.
+pub struct ReserveMemory {
+    memory: BufferMemory,
+    size: usize,
+}
+
+impl Future for ReserveMemory {
+    type Item = ();
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        if self.memory.try_reserve(self.size) {
+            Ok(Async::Ready(()))
+        } else {
+            self.memory.inner.borrow_mut().waiters.push(task::current());
+
+            Ok(Async::NotReady)
+        }
+    }
+}
+
+/// The partitions which have a batch in flight.
+///
+/// The batches of a partition are sent strictly in order,
+/// a batch will not be sent until the previous batch of the same partition
+/// has been acknowledged or failed after retries.
+#[derive(Clone, Default)]
+pub struct InFlight<'a> {
+    inner: Rc<RefCell<InFlightInner<'a>>>,
+}
+
+#[derive(Default)]
+struct InFlightInner<'a> {
+    partitions: HashSet<TopicPartition<'a>>,
+    waiters: Vec<Task>,
+}
+
+impl<'a> InFlight<'a> {
+    pub fn is_sending(&self, tp: &TopicPartition<'a>) -> bool {
+        self.inner.borrow().partitions.contains(tp)
+    }
+
+    fn sending(&self, tp: TopicPartition<'a>) {
+        self.inner.borrow_mut().partitions.insert(tp);
+    }
+
+    /// The in-flight batch of the partition was resolved, wake up the pending batches.
+    pub fn completed(&self, tp: &TopicPartition<'a>) {
+        let waiters = {
+            let mut inner = self.inner.borrow_mut();
+
+            inner.partitions.remove(tp);
+            inner.waiters.drain(..).collect::<Vec<_>>()
+        };
+
+        for waiter in waiters {
+            waiter.notify();
+        }
+    }
+
+    fn wait(&self) {
+        self.inner.borrow_mut().waiters.push(task::current());
+    }
+
+    /// Wait until all the in-flight batches have been resolved.
+    pub fn drained(&self) -> Drained<'a> {
+        Drained {
+            in_flight: self.clone(),
+        }
+    }
+}
+
+/// The future of waiting until all the in-flight batches have been resolved.
+pub struct Drained<'a> {
+    in_flight: InFlight<'a>,
+}
+
+impl<'a> Future for Drained<'a> {
+    type Item = ();
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        if self.in_flight.inner.borrow().partitions.is_empty() {
+            Ok(Async::Ready(()))
+        } else {
+            self.in_flight.wait();
+
+            Ok(Async::NotReady)
+        }
     }
 }
 
 pub struct Batches<'a> {
     batches: Rc<RefCell<HashMap<TopicPartition<'a>, VecDeque<ProducerBatch>>>>,
+    in_flight: InFlight<'a>,
     linger: Duration,
     force: bool,
+    runtime: Runtime,
 }
 
 impl<'a> Stream for Batches<'a> {
@@ -173,18 +719,307 @@ impl<'a> Stream for Batches<'a> {
     type Error = Error;
 
     fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        let mut blocked = false;
+        let now = self.runtime.now();
+
         for (tp, batches) in self.batches.borrow_mut().iter_mut() {
             let ready = self.force || batches.back().map_or(false, |batch| {
-                batch.is_full() || batch.create_time().elapsed() >= self.linger
+                batch.is_full() || now.duration_since(*batch.create_time()) >= self.linger
             });
 
+            if ready && !batches.is_empty() && self.in_flight.is_sending(tp) {
+                trace!("partition {:?} has a batch in flight, wait for it", tp);
+
+                blocked = true;
+
+                continue;
+            }
+
             if ready {
                 if let Some(batch) = batches.pop_front() {
+                    self.in_flight.sending(tp.clone());
+
                     return Ok(Async::Ready(Some((tp.clone(), batch))));
                 }
             }
         }
 
-        Ok(Async::NotReady)
+        if blocked {
+            self.in_flight.wait();
+
+            Ok(Async::NotReady)
+        } else {
+            // all the ready batches have been sent
+            Ok(Async::Ready(None))
+        }
+    }
+}
+
+impl<'a> Batches<'a> {
+    /// Collect the batches which are ready at once, so they could be grouped by the leaders of their partitions.
+    pub fn ready(self) -> ReadyBatches<'a> {
+        ReadyBatches { batches: self }
+    }
+}
+
+/// The stream of the batches which are ready at once.
+pub struct ReadyBatches<'a> {
+    batches: Batches<'a>,
+}
+
+impl<'a> Stream for ReadyBatches<'a> {
+    type Item = Vec<(TopicPartition<'a>, ProducerBatch)>;
+    type Error = Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        let mut ready = Vec::new();
+
+        loop {
+            match self.batches.poll()? {
+                Async::Ready(Some(batch)) => ready.push(batch),
+                Async::Ready(None) if ready.is_empty() => return Ok(Async::Ready(None)),
+                Async::NotReady if ready.is_empty() => return Ok(Async::NotReady),
+                Async::Ready(None) | Async::NotReady => return Ok(Async::Ready(Some(ready))),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::future;
+    use tokio_core::reactor::Core;
+
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "gzip")]
+    fn test_topic_overrides() {
+        let mut topic_overrides = BTreeMap::new();
+
+        topic_overrides.insert(
+            "bulk".to_owned(),
+            TopicOverrides {
+                compression: Some(Compression::GZIP),
+                batch_size: Some(4096),
+                ..Default::default()
+            },
+        );
+
+        let core = Core::new().unwrap();
+        let accumulator = RecordAccumulator::new(
+            1024,
+            Compression::None,
+            Duration::from_millis(0),
+            1024 * 1024,
+            Duration::from_millis(0),
+            Runtime::from(core.handle()),
+        ).with_topic_overrides(topic_overrides);
+
+        let batch = accumulator.new_batch("bulk", 0);
+
+        assert_eq!(batch.compression(), Compression::GZIP);
+        assert_eq!(batch.write_limit(), 4096);
+
+        let batch = accumulator.new_batch("topic", 0);
+
+        assert_eq!(batch.compression(), Compression::None);
+        assert_eq!(batch.write_limit(), 1024);
+    }
+
+    #[test]
+    fn test_in_flight_batches() {
+        let core = Core::new().unwrap();
+        let accumulator = RecordAccumulator::new(
+            1024,
+            Compression::None,
+            Duration::from_millis(0),
+            1024,
+            Duration::from_millis(0),
+            Runtime::from(core.handle()),
+        );
+        let tp = topic_partition!("topic", 0);
+
+        accumulator.batches.borrow_mut().insert(
+            tp.clone(),
+            vec![
+                ProducerBatch::new(0, Compression::None, 1024),
+                ProducerBatch::new(0, Compression::None, 1024),
+            ].into_iter()
+                .collect(),
+        );
+
+        let mut batches = accumulator.batches(false);
+
+        future::lazy(move || {
+            assert_eq!(batches.poll().unwrap().map(|item| item.map(|(tp, _)| tp)), Async::Ready(Some(tp.clone())));
+            assert!(accumulator.in_flight().is_sending(&tp));
+            assert!(batches.poll().unwrap().is_not_ready());
+
+            accumulator.in_flight().completed(&tp);
+
+            assert!(!accumulator.in_flight().is_sending(&tp));
+            assert_eq!(batches.poll().unwrap().map(|item| item.map(|(tp, _)| tp)), Async::Ready(Some(tp.clone())));
+            assert!(accumulator.in_flight().is_sending(&tp));
+
+            accumulator.in_flight().completed(&tp);
+
+            assert_eq!(batches.poll().unwrap().map(|item| item.map(|(tp, _)| tp)), Async::Ready(None));
+
+            Ok::<(), ()>(())
+        }).wait()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_ready_batches() {
+        let core = Core::new().unwrap();
+        let accumulator = RecordAccumulator::new(
+            1024,
+            Compression::None,
+            Duration::from_millis(0),
+            1024,
+            Duration::from_millis(0),
+            Runtime::from(core.handle()),
+        );
+        let tp0 = topic_partition!("topic", 0);
+        let tp1 = topic_partition!("topic", 1);
+
+        for tp in vec![tp0.clone(), tp0.clone(), tp1.clone()] {
+            accumulator
+                .batches
+                .borrow_mut()
+                .entry(tp)
+                .or_insert_with(VecDeque::new)
+                .push_back(ProducerBatch::new(0, Compression::None, 1024));
+        }
+
+        let mut batches = accumulator.batches(true).ready();
+
+        future::lazy(move || {
+            // the first batches of both partitions are ready at once
+            let mut ready = match batches.poll().unwrap() {
+                Async::Ready(Some(ready)) => ready.into_iter().map(|(tp, _)| tp).collect::<Vec<_>>(),
+                res => panic!("unexpected result, {:?}", res.map(|ready| ready.map(|ready| ready.len()))),
+            };
+
+            ready.sort_by_key(|tp| tp.partition_id);
+
+            assert_eq!(ready, vec![tp0.clone(), tp1.clone()]);
+
+            // the second batch of partition waits for the in-flight batch
+            assert!(batches.poll().unwrap().is_not_ready());
+
+            accumulator.in_flight().completed(&tp0);
+            accumulator.in_flight().completed(&tp1);
+
+            match batches.poll().unwrap() {
+                Async::Ready(Some(ref ready)) if ready.len() == 1 => assert_eq!(ready[0].0, tp0),
+                res => panic!("unexpected result, {:?}", res.map(|ready| ready.map(|ready| ready.len()))),
+            }
+
+            accumulator.in_flight().completed(&tp0);
+
+            match batches.poll().unwrap() {
+                Async::Ready(None) => {}
+                res => panic!("unexpected result, {:?}", res.map(|ready| ready.map(|ready| ready.len()))),
+            }
+
+            Ok::<(), ()>(())
+        }).wait()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_abort_batches() {
+        let core = Core::new().unwrap();
+        let accumulator = RecordAccumulator::new(
+            1024,
+            Compression::None,
+            Duration::from_millis(0),
+            1024,
+            Duration::from_millis(0),
+            Runtime::from(core.handle()),
+        );
+        let tp = topic_partition!("topic", 0);
+        let push_record = accumulator.push_record(tp.clone(), 0, None, Some(Bytes::from(&b"value"[..])), 0);
+
+        assert!(push_record.new_batch());
+        assert_eq!(accumulator.memory().used(), 5);
+
+        let aborted = accumulator.abort();
+
+        assert_eq!(aborted.len(), 1);
+        assert_eq!(aborted[0].0, tp);
+        assert_eq!(accumulator.memory().used(), 0);
+        assert!(accumulator.abort().is_empty());
+
+        let mut drained = accumulator.in_flight().drained();
+
+        future::lazy(move || {
+            assert!(drained.poll().unwrap().is_ready());
+
+            accumulator.in_flight().sending(tp.clone());
+
+            let mut drained = accumulator.in_flight().drained();
+
+            assert!(drained.poll().unwrap().is_not_ready());
+
+            accumulator.in_flight().completed(&tp);
+
+            assert!(drained.poll().unwrap().is_ready());
+
+            Ok::<(), ()>(())
+        }).wait()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_expire_batches() {
+        let core = Core::new().unwrap();
+        let accumulator = RecordAccumulator::new(
+            1024,
+            Compression::None,
+            Duration::from_millis(0),
+            1024,
+            Duration::from_millis(0),
+            Runtime::from(core.handle()),
+        );
+        let tp = topic_partition!("topic", 0);
+        let push_record = accumulator.push_record(tp.clone(), 0, None, Some(Bytes::from(&b"value"[..])), 0);
+
+        assert!(push_record.new_batch());
+        assert!(accumulator.expire(Duration::from_secs(60)).is_empty());
+        assert_eq!(accumulator.memory().used(), 5);
+
+        let expired = accumulator.expire(Duration::from_millis(0));
+
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].0, tp);
+        assert_eq!(accumulator.memory().used(), 0);
+        assert!(accumulator.abort().is_empty());
+    }
+
+    #[test]
+    fn test_buffer_memory() {
+        let memory = BufferMemory::new(10);
+
+        assert!(memory.try_reserve(6));
+        assert!(!memory.try_reserve(6));
+        assert_eq!(memory.used(), 6);
+
+        let mut reserve = memory.reserve(6);
+
+        future::lazy(move || {
+            assert!(reserve.poll().unwrap().is_not_ready());
+
+            memory.release(6);
+
+            assert!(reserve.poll().unwrap().is_ready());
+            assert_eq!(memory.used(), 6);
+
+            Ok::<(), ()>(())
+        }).wait()
+            .unwrap();
     }
 }
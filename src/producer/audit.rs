@@ -0,0 +1,199 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::hash::Hash;
+use std::io::Write;
+use std::marker::PhantomData;
+use std::time::Duration;
+
+use errors::{Error, Result};
+use producer::{ProducerInterceptor, ProducerRecord, RecordMetadata};
+use protocol::{Offset, PartitionId, Timestamp};
+
+/// A structured audit record of an acknowledged produce.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AuditRecord {
+    /// The topic the record was appended to
+    pub topic_name: String,
+    /// The partition the record was sent to
+    pub partition_id: PartitionId,
+    /// The offset of the record in the topic/partition.
+    pub offset: Offset,
+    /// The timestamp of the record in the topic/partition.
+    pub timestamp: Timestamp,
+    /// The hash of the serialized key (or `None` if no key is specified).
+    pub key_hash: Option<u64>,
+    /// The size of the serialized, uncompressed key and value in bytes.
+    pub size: usize,
+    /// The elapsed time from the record was pushed to the producer until it was acknowledged.
+    pub latency: Duration,
+}
+
+impl<'a> From<&'a RecordMetadata> for AuditRecord {
+    fn from(metadata: &'a RecordMetadata) -> Self {
+        AuditRecord {
+            topic_name: metadata.topic_name.clone(),
+            partition_id: metadata.partition_id,
+            offset: metadata.offset,
+            timestamp: metadata.timestamp,
+            key_hash: metadata.serialized_key_hash,
+            size: metadata.serialized_key_size + metadata.serialized_value_size,
+            latency: metadata.latency,
+        }
+    }
+}
+
+impl fmt::Display for AuditRecord {
+    /// Format the audit record as `key=value` pairs, the latency in milliseconds.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "topic={} partition={} offset={} timestamp={} key_hash=",
+            self.topic_name, self.partition_id, self.offset, self.timestamp
+        )?;
+
+        match self.key_hash {
+            Some(key_hash) => write!(f, "{:016x}", key_hash)?,
+            None => write!(f, "-")?,
+        }
+
+        write!(
+            f,
+            " size={} latency={}",
+            self.size,
+            self.latency.as_secs() * 1000 + u64::from(self.latency.subsec_nanos()) / 1_000_000
+        )
+    }
+}
+
+/// A trait for writing the audit records to a pluggable sink.
+pub trait AuditSink {
+    /// Write the audit record of an acknowledged produce.
+    fn write(&self, record: &AuditRecord);
+}
+
+impl<F> AuditSink for F
+where
+    F: Fn(&AuditRecord),
+{
+    fn write(&self, record: &AuditRecord) {
+        self(record)
+    }
+}
+
+/// An `AuditSink` that writes the audit records to the log.
+#[derive(Clone, Debug, Default)]
+pub struct LogAuditSink;
+
+impl AuditSink for LogAuditSink {
+    fn write(&self, record: &AuditRecord) {
+        info!(target: "tokio_kafka::audit", "{}", record);
+    }
+}
+
+/// An `AuditSink` that writes the audit records line by line to a `std::io::Write`.
+pub struct WriteAuditSink<W> {
+    writer: RefCell<W>,
+}
+
+impl<W> WriteAuditSink<W>
+where
+    W: Write,
+{
+    pub fn new(writer: W) -> Self {
+        WriteAuditSink {
+            writer: RefCell::new(writer),
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer.into_inner()
+    }
+}
+
+impl<W> AuditSink for WriteAuditSink<W>
+where
+    W: Write,
+{
+    fn write(&self, record: &AuditRecord) {
+        if let Err(err) = writeln!(self.writer.borrow_mut(), "{}", record) {
+            warn!("fail to write audit record, {}", err);
+        }
+    }
+}
+
+/// A `ProducerInterceptor` that writes an audit record for every acknowledged produce.
+///
+/// The failed produces are not audited.
+pub struct AuditInterceptor<K, V, S> {
+    sink: S,
+    phantom: PhantomData<(K, V)>,
+}
+
+impl<K, V, S> AuditInterceptor<K, V, S>
+where
+    S: AuditSink,
+{
+    pub fn new(sink: S) -> Self {
+        AuditInterceptor {
+            sink,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<K, V, S> ProducerInterceptor for AuditInterceptor<K, V, S>
+where
+    K: Hash,
+    S: AuditSink,
+{
+    type Key = K;
+    type Value = V;
+
+    fn send(&self, record: ProducerRecord<Self::Key, Self::Value>) -> Result<ProducerRecord<Self::Key, Self::Value>> {
+        Ok(record)
+    }
+
+    fn on_acknowledgement(&self, metadata: &RecordMetadata, err: Option<&Error>) {
+        if err.is_none() {
+            self.sink.write(&AuditRecord::from(metadata));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use errors::ErrorKind;
+    use protocol::KafkaCode;
+
+    use super::*;
+
+    #[test]
+    fn test_audit_interceptor() {
+        let interceptor = AuditInterceptor::<(), (), _>::new(WriteAuditSink::new(Vec::new()));
+
+        let mut metadata = RecordMetadata {
+            topic_name: "topic".to_owned(),
+            partition_id: 1,
+            offset: 123,
+            timestamp: 456,
+            serialized_key_size: 3,
+            serialized_value_size: 5,
+            serialized_key_hash: Some(0xabcd),
+            latency: Duration::from_millis(12),
+        };
+
+        interceptor.on_acknowledgement(&metadata, None);
+
+        metadata.offset = -1;
+
+        interceptor.on_acknowledgement(
+            &metadata,
+            Some(&ErrorKind::KafkaError(KafkaCode::NotLeaderForPartition).into()),
+        );
+
+        assert_eq!(
+            String::from_utf8(interceptor.sink.into_inner()).unwrap(),
+            "topic=topic partition=1 offset=123 timestamp=456 key_hash=000000000000abcd size=8 latency=12\n"
+        );
+    }
+}
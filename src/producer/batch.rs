@@ -1,59 +1,115 @@
 use std::cell::RefCell;
-use std::hash::Hash;
+use std::hash::{Hash, Hasher};
 use std::ops::Deref;
 use std::rc::Rc;
 use std::time::Instant;
 
 use bytes::{BigEndian, Bytes};
+use twox_hash::XxHash;
 
 use futures::unsync::oneshot::{channel, Canceled, Receiver, Sender};
 use futures::{Async, Future, Poll};
 
+use client::ProducedRecords;
 use compression::Compression;
 use errors::{Error, ErrorKind, Result};
-use producer::{ProducerInterceptor, ProducerInterceptors, RecordMetadata};
+use producer::{DeliveryReporter, ProducerInterceptor, ProducerInterceptors, RecordId, RecordMetadata, SpillEntry};
 use protocol::{ApiVersion, KafkaCode, MessageSet, MessageSetBuilder, Offset, PartitionId, Timestamp};
 
 #[derive(Debug)]
 pub struct Thunk {
-    sender: Sender<Result<RecordMetadata>>,
+    completion: Completion,
     relative_offset: Offset,
     timestamp: Timestamp,
+    key: Option<Bytes>,
+    value: Option<Bytes>,
     key_size: usize,
     value_size: usize,
+    key_hash: Option<u64>,
+    push_time: Instant,
+    spilled: Option<SpillEntry>,
+}
+
+/// How the delivery result of a record is returned.
+#[derive(Debug)]
+enum Completion {
+    /// Resolve the future returned when the record was sent.
+    Future(Sender<Result<RecordMetadata>>),
+    /// Report the result to the delivery reports with the id of the record.
+    Report(DeliveryReporter, RecordId),
+}
+
+impl Completion {
+    fn complete(self, result: Result<RecordMetadata>) -> ::std::result::Result<(), Result<RecordMetadata>> {
+        match self {
+            Completion::Future(sender) => sender.send(result),
+            Completion::Report(reporter, id) => reporter.report(id, result),
+        }
+    }
 }
 
 impl Thunk {
-    pub fn fail(self, err: Error) -> ::std::result::Result<(), Result<RecordMetadata>> {
-        self.sender.send(Err(err))
+    pub fn fail<K: Hash, V>(
+        self,
+        interceptors: Option<Rc<RefCell<ProducerInterceptors<K, V>>>>,
+        topic_name: &str,
+        partition_id: PartitionId,
+        err: Error,
+    ) -> ::std::result::Result<(), Result<RecordMetadata>> {
+        if let Some(interceptors) = interceptors {
+            let metadata = self.metadata(topic_name, partition_id, -1);
+
+            (*interceptors).borrow().on_acknowledgement(&metadata, Some(&err));
+        }
+
+        self.completion.complete(Err(err))
     }
 
     pub fn done<K: Hash, V>(
         self,
         interceptors: Option<Rc<RefCell<ProducerInterceptors<K, V>>>>,
         topic_name: &str,
-        partition_id: PartitionId,
-        base_offset: Offset,
-        error_code: KafkaCode,
+        partition: &ProducedRecords,
     ) -> ::std::result::Result<(), Result<RecordMetadata>> {
-        let result = if error_code == KafkaCode::None {
-            Ok(RecordMetadata {
-                topic_name: topic_name.to_owned(),
-                partition_id,
-                offset: base_offset + self.relative_offset,
-                timestamp: self.timestamp,
-                serialized_key_size: self.key_size,
-                serialized_value_size: self.value_size,
-            })
+        let (offset, err) = if partition.error_code == KafkaCode::None {
+            (partition.base_offset + self.relative_offset, None)
         } else {
-            Err(ErrorKind::KafkaError(error_code).into())
+            (-1, Some(Error::from(ErrorKind::KafkaError(partition.error_code))))
         };
+        let mut metadata = self.metadata(topic_name, partition.partition_id, offset);
+
+        // the broker assigns the timestamps of the records if the topic uses `LogAppendTime`
+        if let Some(timestamp) = partition.log_append_time {
+            metadata.timestamp = timestamp;
+        }
 
         if let Some(interceptors) = interceptors {
-            (*interceptors).borrow().ack(&result);
+            (*interceptors).borrow().on_acknowledgement(&metadata, err.as_ref());
         }
 
-        self.sender.send(result)
+        if err.is_none() {
+            if let Some(spilled) = self.spilled {
+                spilled.ack();
+            }
+        }
+
+        self.completion.complete(match err {
+            None => Ok(metadata),
+            Some(err) => Err(err),
+        })
+    }
+
+    fn metadata(&self, topic_name: &str, partition_id: PartitionId, offset: Offset) -> RecordMetadata {
+        RecordMetadata {
+            topic_name: topic_name.to_owned(),
+            partition_id,
+            offset,
+            timestamp: self.timestamp,
+            serialized_key_size: self.key_size,
+            serialized_value_size: self.value_size,
+            serialized_key_hash: self.key_hash,
+            latency: self.push_time.elapsed(),
+        }
     }
 }
 
@@ -63,6 +119,7 @@ pub struct ProducerBatch {
     thunks: Vec<Thunk>,
     create_time: Instant,
     last_push_time: Instant,
+    buffered_bytes: usize,
 }
 
 impl Deref for ProducerBatch {
@@ -82,9 +139,16 @@ impl ProducerBatch {
             thunks: vec![],
             create_time: now,
             last_push_time: now,
+            buffered_bytes: 0,
         }
     }
 
+    /// Set the create time of the empty batch, which its linger and delivery timeout are measured from.
+    pub fn with_create_time(mut self, create_time: Instant) -> Self {
+        self.create_time = create_time;
+        self
+    }
+
     pub fn create_time(&self) -> &Instant {
         &self.create_time
     }
@@ -93,29 +157,103 @@ impl ProducerBatch {
         &self.last_push_time
     }
 
+    /// The bytes of records buffered in the batch
+    pub fn buffered_bytes(&self) -> usize {
+        self.buffered_bytes
+    }
+
     pub fn push_record(
         &mut self,
         timestamp: Timestamp,
         key: Option<Bytes>,
         value: Option<Bytes>,
     ) -> Result<FutureRecordMetadata> {
+        let (sender, receiver) = channel();
+
+        self.push(timestamp, key, value, Completion::Future(sender))?;
+
+        Ok(FutureRecordMetadata { receiver })
+    }
+
+    /// Push a record whose delivery result will be reported with its id instead of resolving a future.
+    pub fn push_reported_record(
+        &mut self,
+        timestamp: Timestamp,
+        key: Option<Bytes>,
+        value: Option<Bytes>,
+        reporter: &DeliveryReporter,
+        id: RecordId,
+    ) -> Result<()> {
+        self.push(timestamp, key, value, Completion::Report(reporter.clone(), id))
+    }
+
+    /// The last pushed record was read back from the spill file,
+    /// its entry will be acknowledged once the record has been delivered.
+    pub fn spilled(&mut self, entry: SpillEntry) {
+        if let Some(thunk) = self.thunks.last_mut() {
+            thunk.spilled = Some(entry);
+        }
+    }
+
+    fn push(
+        &mut self,
+        timestamp: Timestamp,
+        key: Option<Bytes>,
+        value: Option<Bytes>,
+        completion: Completion,
+    ) -> Result<()> {
         let key_size = key.as_ref().map_or(0, |b| b.len());
         let value_size = value.as_ref().map_or(0, |b| b.len());
+        let key_hash = key.as_ref().map(|b| {
+            let mut hasher = XxHash::default();
+            hasher.write(b);
+            hasher.finish()
+        });
 
-        let relative_offset = self.builder.push(timestamp, key, value)?;
-
-        let (sender, receiver) = channel();
+        let relative_offset = self.builder.push(timestamp, key.clone(), value.clone())?;
 
         self.thunks.push(Thunk {
-            sender,
+            completion,
             relative_offset,
             timestamp,
+            key,
+            value,
             key_size,
             value_size,
+            key_hash,
+            push_time: Instant::now(),
+            spilled: None,
         });
         self.last_push_time = Instant::now();
+        self.buffered_bytes += key_size + value_size;
 
-        Ok(FutureRecordMetadata { receiver })
+        Ok(())
+    }
+
+    /// Rebuild a batch with the records of the thunks, e.g. to split a batch which is too large.
+    ///
+    /// The batch keeps the create time of the original batch, so it will expire at the same time.
+    pub fn rebuild(
+        api_version: ApiVersion,
+        compression: Compression,
+        write_limit: usize,
+        create_time: Instant,
+        thunks: Vec<Thunk>,
+    ) -> Result<Self> {
+        let mut batch = ProducerBatch::new(api_version, compression, write_limit);
+
+        batch.create_time = create_time;
+
+        for mut thunk in thunks {
+            thunk.relative_offset = batch
+                .builder
+                .push(thunk.timestamp, thunk.key.clone(), thunk.value.clone())?;
+
+            batch.buffered_bytes += thunk.key_size + thunk.value_size;
+            batch.thunks.push(thunk);
+        }
+
+        Ok(batch)
     }
 
     pub fn build(self) -> Result<(Vec<Thunk>, MessageSet)> {
@@ -140,3 +278,166 @@ impl Future for FutureRecordMetadata {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use futures::Stream;
+
+    use super::*;
+
+    #[test]
+    fn test_complete_records() {
+        let mut batch = ProducerBatch::new(1, Compression::None, 1024);
+        let first = batch.push_record(1000, None, Some(Bytes::from(&b"first"[..]))).unwrap();
+        let second = batch
+            .push_record(2000, Some(Bytes::from(&b"key"[..])), Some(Bytes::from(&b"second"[..])))
+            .unwrap();
+        let (thunks, _) = batch.build().unwrap();
+        let partition = ProducedRecords {
+            partition_id: 1,
+            error_code: KafkaCode::None,
+            base_offset: 100,
+            log_append_time: None,
+        };
+
+        for thunk in thunks {
+            thunk.done::<(), ()>(None, "topic", &partition).unwrap();
+        }
+
+        let first = first.wait().unwrap();
+
+        assert_eq!(first.topic_name, "topic");
+        assert_eq!(first.partition_id, 1);
+        assert_eq!(first.offset, 100);
+        assert_eq!(first.timestamp, 1000);
+        assert_eq!(first.serialized_value_size, 5);
+
+        let second = second.wait().unwrap();
+
+        assert_eq!(second.offset, 101);
+        assert_eq!(second.timestamp, 2000);
+        assert_eq!(second.serialized_key_size, 3);
+        assert!(second.serialized_key_hash.is_some());
+
+        // the timestamps assigned by the broker
+        let mut batch = ProducerBatch::new(1, Compression::None, 1024);
+        let record = batch.push_record(1000, None, None).unwrap();
+        let (thunks, _) = batch.build().unwrap();
+
+        for thunk in thunks {
+            thunk
+                .done::<(), ()>(
+                    None,
+                    "topic",
+                    &ProducedRecords {
+                        log_append_time: Some(3000),
+                        ..partition.clone()
+                    },
+                )
+                .unwrap();
+        }
+
+        assert_eq!(record.wait().unwrap().timestamp, 3000);
+
+        // the partition error is propagated to every record
+        let mut batch = ProducerBatch::new(1, Compression::None, 1024);
+        let record = batch.push_record(1000, None, None).unwrap();
+        let (thunks, _) = batch.build().unwrap();
+
+        for thunk in thunks {
+            thunk
+                .fail::<(), ()>(None, "topic", 1, ErrorKind::KafkaError(KafkaCode::MessageSizeTooLarge).into())
+                .unwrap();
+        }
+
+        match record.wait() {
+            Err(Error(ErrorKind::KafkaError(KafkaCode::MessageSizeTooLarge), _)) => {}
+            res => panic!("unexpected result, {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_report_records() {
+        let (reporter, reports) = DeliveryReporter::new();
+        let mut batch = ProducerBatch::new(1, Compression::None, 1024);
+
+        batch
+            .push_reported_record(1000, None, Some(Bytes::from(&b"first"[..])), &reporter, 7)
+            .unwrap();
+        batch
+            .push_reported_record(2000, None, Some(Bytes::from(&b"second"[..])), &reporter, 8)
+            .unwrap();
+
+        let (thunks, _) = batch.build().unwrap();
+        let partition = ProducedRecords {
+            partition_id: 1,
+            error_code: KafkaCode::None,
+            base_offset: 100,
+            log_append_time: None,
+        };
+
+        for thunk in thunks {
+            thunk.done::<(), ()>(None, "topic", &partition).unwrap();
+        }
+
+        drop(reporter);
+
+        let reports = reports
+            .map(|report| (report.id, report.result.unwrap().offset))
+            .collect()
+            .wait()
+            .unwrap();
+
+        assert_eq!(reports, vec![(7, 100), (8, 101)]);
+    }
+
+    #[test]
+    fn test_rebuild_batch() {
+        let mut batch = ProducerBatch::new(1, Compression::None, 1024);
+        let records = (0..4)
+            .map(|i| batch.push_record(1000 + i, None, Some(Bytes::from(format!("value{}", i)))).unwrap())
+            .collect::<Vec<_>>();
+        let create_time = *batch.create_time();
+        let (mut thunks, _) = batch.build().unwrap();
+        let second = thunks.split_off(2);
+
+        // the records of the second half are rebased to the start of the new batch
+        let batch = ProducerBatch::rebuild(1, Compression::None, 1024, create_time, second).unwrap();
+
+        assert_eq!(*batch.create_time(), create_time);
+        assert_eq!(batch.buffered_bytes(), 12);
+
+        let (thunks, message_set) = batch.build().unwrap();
+
+        assert_eq!(
+            message_set
+                .messages
+                .iter()
+                .map(|message| (message.offset, message.value.clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                (0, Some(Bytes::from(&b"value2"[..]))),
+                (1, Some(Bytes::from(&b"value3"[..]))),
+            ]
+        );
+
+        let partition = ProducedRecords {
+            partition_id: 1,
+            error_code: KafkaCode::None,
+            base_offset: 100,
+            log_append_time: None,
+        };
+
+        for thunk in thunks {
+            thunk.done::<(), ()>(None, "topic", &partition).unwrap();
+        }
+
+        let offsets = records
+            .into_iter()
+            .skip(2)
+            .map(|record| record.wait().unwrap().offset)
+            .collect::<Vec<_>>();
+
+        assert_eq!(offsets, vec![100, 101]);
+    }
+}
@@ -9,8 +9,10 @@ use tokio_core::reactor::Handle;
 use client::{KafkaClient, KafkaVersion};
 use compression::Compression;
 use errors::{ErrorKind, Result};
-use producer::{DefaultPartitioner, Interceptors, KafkaProducer, ProducerConfig, ProducerInterceptor,
-               ProducerInterceptors};
+use network::SaslMechanism;
+use producer::{AuditInterceptor, AuditSink, DefaultPartitioner, Interceptors, KafkaProducer, PartitionStrategy,
+               Partitioner, ProducerConfig, ProducerInterceptor, ProducerInterceptors, RateLimit, RateLimiter,
+               StickyPartitioner, TokenBucketRateLimiter, TopicOverrides};
 use protocol::{RequiredAcks, ToMilliseconds};
 use serialization::{NoopSerializer, Serializer};
 
@@ -28,6 +30,7 @@ where
     value_serializer: Option<V>,
     partitioner: Option<P>,
     interceptors: Interceptors<K::Item, V::Item>,
+    rate_limiter: Option<Rc<RateLimiter>>,
 }
 
 impl<'a, K, V, P> Deref for ProducerBuilder<'a, K, V, P>
@@ -66,6 +69,7 @@ where
             value_serializer: None,
             partitioner: None,
             interceptors: None,
+            rate_limiter: None,
         }
     }
 }
@@ -85,6 +89,7 @@ where
             value_serializer: None,
             partitioner: None,
             interceptors: None,
+            rate_limiter: None,
         }
     }
 }
@@ -104,6 +109,7 @@ where
             value_serializer: None,
             partitioner: None,
             interceptors: None,
+            rate_limiter: None,
         }
     }
 
@@ -144,12 +150,25 @@ where
         self
     }
 
+    /// Sets the maximum number of unacknowledged requests the client will send on a single
+    /// connection before blocking.
+    pub fn with_max_in_flight_requests_per_connection(mut self, max_in_flight_requests: usize) -> Self {
+        self.config.max_in_flight_requests_per_connection = max_in_flight_requests;
+        self
+    }
+
     /// Sets the maximum size of a request in bytes.
     pub fn with_max_request_size(mut self, max_request_size: usize) -> Self {
         self.config.max_request_size = max_request_size;
         self
     }
 
+    /// Sets the maximum size of a record in bytes.
+    pub fn with_message_max_bytes(mut self, message_max_bytes: usize) -> Self {
+        self.config.message_max_bytes = message_max_bytes;
+        self
+    }
+
     /// Sets the maximum amount of time the client will wait for the response
     /// of a request.
     pub fn with_request_timeout(mut self, request_timeout: Duration) -> Self {
@@ -177,12 +196,78 @@ where
         self
     }
 
+    /// Sets the period of time in milliseconds after which the cached metadata of a topic is
+    /// considered stale
+    pub fn with_topic_metadata_refresh_interval(mut self, refresh_interval: Duration) -> Self {
+        self.config.topic_metadata_refresh_interval = refresh_interval.as_millis();
+        self
+    }
+
     /// Sets to record metrics for client operations
     pub fn with_metrics(mut self) -> Self {
         self.config.metrics = true;
         self
     }
 
+    /// Sets the topics whose leaders will be pre-connected at startup
+    pub fn with_warm_up_topics<I, S>(mut self, topic_names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.config.warm_up_topics = topic_names.into_iter().map(|s| s.into()).collect();
+        self
+    }
+
+    /// Sets the SASL/PLAIN credentials used to authenticate the connections
+    pub fn with_sasl_plain<S>(mut self, username: S, password: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.config.sasl_username = Some(username.into());
+        self.config.sasl_password = Some(password.into());
+        self
+    }
+
+    /// Sets the SASL mechanism to use for authentication
+    pub fn with_sasl_mechanism(mut self, mechanism: SaslMechanism) -> Self {
+        self.config.sasl_mechanism = mechanism;
+        self
+    }
+
+    /// Connect to the brokers with TLS/SSL
+    pub fn with_ssl(mut self) -> Self {
+        self.config.ssl_enabled = true;
+        self
+    }
+
+    /// Sets the CA certificate used to verify the brokers, and enable TLS/SSL
+    pub fn with_ssl_ca_location<S>(mut self, location: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.config.ssl_enabled = true;
+        self.config.ssl_ca_location = Some(location.into());
+        self
+    }
+
+    /// Sets the PKCS#12 archive with the client certificate and private key, and enable TLS/SSL
+    pub fn with_ssl_keystore<S>(mut self, location: S, password: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.config.ssl_enabled = true;
+        self.config.ssl_keystore_location = Some(location.into());
+        self.config.ssl_keystore_password = Some(password.into());
+        self
+    }
+
+    /// Skip verifying the broker hostname against its certificate
+    pub fn without_ssl_hostname_verification(mut self) -> Self {
+        self.config.ssl_hostname_verification = false;
+        self
+    }
+
     /// Sets the number of acknowledgments the producer requires the leader
     /// to have received before considering a request complete.
     pub fn with_required_acks(mut self, acks: RequiredAcks) -> Self {
@@ -210,6 +295,12 @@ where
         self
     }
 
+    /// Sets the overrides of the acks, ack timeout, compression and batch size for the topic.
+    pub fn with_topic_overrides<S: Into<String>>(mut self, topic_name: S, overrides: TopicOverrides) -> Self {
+        self.config.topic_overrides.insert(topic_name.into(), overrides);
+        self
+    }
+
     /// Sets the time that the producer groups together any records
     /// that arrive in between request transmissions into a single batched request.
     pub fn with_linger(mut self, linger: Duration) -> Self {
@@ -217,6 +308,65 @@ where
         self
     }
 
+    /// Sets the total bytes of memory the producer can use to buffer records waiting to be sent to the server.
+    pub fn with_buffer_memory(mut self, buffer_memory: usize) -> Self {
+        self.config.buffer_memory = buffer_memory;
+        self
+    }
+
+    /// Sets the maximum amount of time that sending a record will block when the buffer is full.
+    pub fn with_max_block(mut self, max_block: Duration) -> Self {
+        self.config.max_block = max_block.as_millis();
+        self
+    }
+
+    /// Sets whether the brokers could create the unknown topics when sending records to them.
+    pub fn with_allow_auto_create_topics(mut self, allow_auto_create_topics: bool) -> Self {
+        self.config.allow_auto_create_topics = allow_auto_create_topics;
+        self
+    }
+
+    /// Sets the strategy for choosing a partition for the records without key.
+    pub fn with_partition_strategy(mut self, partition_strategy: PartitionStrategy) -> Self {
+        self.config.partition_strategy = partition_strategy;
+        self
+    }
+
+    /// Sets the file to spill the records when the buffer memory is exhausted.
+    pub fn with_spill_file<S>(mut self, spill_file: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.config.spill_file = Some(spill_file.into());
+        self
+    }
+
+    /// Sets whether to report the delivery results of the records sent with `KafkaProducer::produce`
+    /// to the `KafkaProducer::delivery_reports` stream.
+    pub fn with_delivery_reports(mut self, delivery_reports: bool) -> Self {
+        self.config.delivery_reports = delivery_reports;
+        self
+    }
+
+    /// Sets the maximum publish rate of the producer to all the topics.
+    pub fn with_rate_limit(mut self, limit: RateLimit) -> Self {
+        self.config.rate_limit_records = limit.records_per_sec;
+        self.config.rate_limit_bytes = limit.bytes_per_sec;
+        self
+    }
+
+    /// Sets the rate limiter which limits the publish rate of the producer,
+    /// e.g. a `TokenBucketRateLimiter` with the limits of the individual topics.
+    ///
+    /// The rate limiter overrides the rate limit of the producer config.
+    pub fn with_rate_limiter<L>(mut self, rate_limiter: L) -> Self
+    where
+        L: RateLimiter + 'static,
+    {
+        self.rate_limiter = Some(Rc::new(rate_limiter));
+        self
+    }
+
     /// Sets the key serializer that serialize key to record
     pub fn with_key_serializer(mut self, key_serializer: K) -> Self {
         self.key_serializer = Some(key_serializer);
@@ -251,6 +401,16 @@ where
         self.interceptors = Some(interceptors);
         self
     }
+
+    /// Sets the sink which writes an audit record for every acknowledged produce.
+    pub fn with_audit_sink<S>(self, sink: S) -> Self
+    where
+        S: AuditSink + 'static,
+        K::Item: Hash + 'static,
+        V::Item: 'static,
+    {
+        self.with_interceptor(AuditInterceptor::new(sink))
+    }
 }
 
 impl<'a, V, P> ProducerBuilder<'a, NoopSerializer<()>, V, P>
@@ -287,11 +447,24 @@ where
     }
 }
 
+impl<'a, K, V> ProducerBuilder<'a, K, V, StickyPartitioner>
+where
+    K: Serializer,
+    V: Serializer,
+{
+    /// Sets the sticky partitioner
+    pub fn with_sticky_partitioner(mut self) -> Self {
+        self.partitioner = Some(StickyPartitioner::new());
+        self
+    }
+}
+
 impl<'a, K, V, P> ProducerBuilder<'a, K, V, P>
 where
     K: Serializer,
     K::Item: Hash,
     V: Serializer,
+    P: Partitioner,
     Self: 'static,
 {
     /// Construct a `KafkaProducer`
@@ -302,9 +475,18 @@ where
             KafkaClient::new(
                 self.config.client.clone(),
                 self.handle.ok_or(ErrorKind::ConfigError("missed handle"))?,
-            )
+            )?
         };
 
+        let rate_limit = self.config.rate_limit();
+        let rate_limiter = self.rate_limiter.or_else(|| {
+            if rate_limit.is_unlimited() {
+                None
+            } else {
+                Some(Rc::new(TokenBucketRateLimiter::new(rate_limit)) as Rc<RateLimiter>)
+            }
+        });
+
         Ok(KafkaProducer::new(
             client,
             self.config,
@@ -314,6 +496,7 @@ where
                 .ok_or(ErrorKind::ConfigError("missed value serializer"))?,
             self.partitioner.ok_or(ErrorKind::ConfigError("missed partitioner"))?,
             self.interceptors,
+            rate_limiter,
         ))
     }
 }
@@ -1,8 +1,10 @@
+use std::collections::BTreeMap;
 use std::ops::{Deref, DerefMut};
 use std::time::Duration;
 
-use client::ClientConfig;
+use client::{ClientConfig, RetryPolicies};
 use compression::Compression;
+use producer::{PartitionStrategy, RateLimit};
 use protocol::RequiredAcks;
 
 /// The default amount of time the server will wait for acknowledgments
@@ -22,12 +24,34 @@ pub const DEFAULT_BATCH_SIZE: usize = 16 * 1024;
 /// [`ProducerConfig::max_request_size`](struct.ProducerConfig.html#max_request_size.v)
 pub const DEFAULT_MAX_REQUEST_SIZE: usize = 1024 * 1024;
 
+/// The default maximum size of a record in bytes, the same as the `message.max.bytes` of the brokers.
+///
+/// Defaults to 1000012 bytes, see
+/// [`ProducerConfig::message_max_bytes`](struct.ProducerConfig.html#message_max_bytes.v)
+pub const DEFAULT_MESSAGE_MAX_BYTES: usize = 1_000_012;
+
 /// The default millionseconds that producer groups together any records
 /// that arrive in between request transmissions into a single batched request.
 ///
 /// Defaults to 0 ms, see [`ProducerConfig::linger`](struct.ProducerConfig.html#linger.v)
 pub const DEFAULT_LINGER_MILLIS: u64 = 0;
 
+/// The default total bytes of memory the producer can use to buffer records waiting to be sent.
+///
+/// Defaults to 32 MB, see [`ProducerConfig::buffer_memory`](struct.ProducerConfig.html#buffer_memory.v)
+pub const DEFAULT_BUFFER_MEMORY: usize = 32 * 1024 * 1024;
+
+/// The default millionseconds that sending a record will block when the buffer is full.
+///
+/// Defaults to 60 seconds, see [`ProducerConfig::max_block`](struct.ProducerConfig.html#max_block.v)
+pub const DEFAULT_MAX_BLOCK_MILLIS: u64 = 60_000;
+
+/// The default upper bound on the time to report success or failure after sending a record.
+///
+/// Defaults to 2 minutes, see
+/// [`ProducerConfig::delivery_timeout`](struct.ProducerConfig.html#delivery_timeout.v)
+pub const DEFAULT_DELIVERY_TIMEOUT_MILLIS: u64 = 120_000;
+
 /// Configuration for the `KafkaProducer`.
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[serde(default)]
@@ -53,13 +77,102 @@ pub struct ProducerConfig {
     pub batch_size: usize,
 
     /// The maximum size of a request in bytes.
+    ///
+    /// The ready batches of the partitions sharing the same leader are sent in a single request
+    /// up to this size.
     #[serde(rename = "max.request.size")]
     pub max_request_size: usize,
 
+    /// The maximum size of a record in bytes, which should not exceed the `message.max.bytes` of the brokers.
+    ///
+    /// The records larger than this size or `max.request.size` are rejected before being sent.
+    #[serde(rename = "message.max.bytes")]
+    pub message_max_bytes: usize,
+
     /// The producer groups together any records
     /// that arrive in between request transmissions into a single batched request.
     #[serde(rename = "linger.ms")]
     pub linger: u64,
+
+    /// The total bytes of memory the producer can use to buffer records waiting to be sent to the server.
+    #[serde(rename = "buffer.memory")]
+    pub buffer_memory: usize,
+
+    /// The maximum amount of time that sending a record will block when the buffer is full,
+    /// or when waiting for the brokers to create an unknown topic.
+    #[serde(rename = "max.block.ms")]
+    pub max_block: u64,
+
+    /// Allow the brokers to create the unknown topics when the producer sends records to them,
+    /// if the brokers also enable `auto.create.topics.enable`.
+    ///
+    /// Sending records to an unknown topic fails at once with `UnknownTopicOrPartition` if disabled.
+    #[serde(rename = "allow.auto.create.topics")]
+    pub allow_auto_create_topics: bool,
+
+    /// An upper bound on the time to report success or failure after sending a record,
+    /// the batches which have not been acknowledged before the timeout will be expired.
+    ///
+    /// This limits the total time that a record will be delayed prior to sending,
+    /// the time to await acknowledgement from the broker, and the time allowed for retriable send failures.
+    #[serde(rename = "delivery.timeout.ms")]
+    pub delivery_timeout: u64,
+
+    /// The strategy for choosing a partition for the records without key.
+    #[serde(rename = "partitioner.strategy")]
+    pub partition_strategy: PartitionStrategy,
+
+    /// The file to spill the records when the buffer memory is exhausted,
+    /// instead of blocking the sending.
+    ///
+    /// The records are kept in the file until delivered, and the records left will be replayed when the producer starts.
+    #[serde(rename = "spill.file")]
+    pub spill_file: Option<String>,
+
+    /// The maximum number of records per second the producer will publish to all the topics,
+    /// the records are delayed before pushed to the batches when the rate has been exceeded.
+    #[serde(rename = "rate.limit.records.per.sec")]
+    pub rate_limit_records: Option<u64>,
+
+    /// The maximum bytes per second the producer will publish to all the topics,
+    /// the records are delayed before pushed to the batches when the rate has been exceeded.
+    #[serde(rename = "rate.limit.bytes.per.sec")]
+    pub rate_limit_bytes: Option<u64>,
+
+    /// The retry policies of the produce requests for each class of errors,
+    /// which overrides the retry policies of the client for the configured error classes.
+    #[serde(rename = "retry.policies")]
+    pub retry_policies: RetryPolicies,
+
+    /// Report the delivery results of the records sent with `KafkaProducer::produce` to a single stream,
+    /// instead of resolving a future for each record.
+    #[serde(rename = "delivery.reports")]
+    pub delivery_reports: bool,
+
+    /// The configuration overrides of the topics keyed by the topic name,
+    /// which override the acks, ack timeout, compression and batch size of the producer.
+    #[serde(rename = "topic.overrides")]
+    pub topic_overrides: BTreeMap<String, TopicOverrides>,
+}
+
+/// The configuration overrides of a topic, the unset settings fall back to the producer's.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TopicOverrides {
+    /// The number of acknowledgments the producer requires for the records of the topic.
+    pub acks: Option<RequiredAcks>,
+
+    /// The maximum amount of time the server will wait for acknowledgments of the records of the topic.
+    #[serde(rename = "timeout.ms")]
+    pub ack_timeout: Option<u64>,
+
+    /// The compression type for the records of the topic.
+    #[serde(rename = "compression.type")]
+    pub compression: Option<Compression>,
+
+    /// The bytes that producer will attempt to batch the records of the topic together.
+    #[serde(rename = "batch.size")]
+    pub batch_size: Option<usize>,
 }
 
 impl Deref for ProducerConfig {
@@ -85,7 +198,19 @@ impl Default for ProducerConfig {
             compression: Compression::default(),
             batch_size: DEFAULT_BATCH_SIZE,
             max_request_size: DEFAULT_MAX_REQUEST_SIZE,
+            message_max_bytes: DEFAULT_MESSAGE_MAX_BYTES,
             linger: DEFAULT_LINGER_MILLIS,
+            buffer_memory: DEFAULT_BUFFER_MEMORY,
+            max_block: DEFAULT_MAX_BLOCK_MILLIS,
+            allow_auto_create_topics: true,
+            delivery_timeout: DEFAULT_DELIVERY_TIMEOUT_MILLIS,
+            partition_strategy: PartitionStrategy::default(),
+            spill_file: None,
+            rate_limit_records: None,
+            rate_limit_bytes: None,
+            retry_policies: RetryPolicies::default(),
+            delivery_reports: false,
+            topic_overrides: BTreeMap::new(),
         }
     }
 }
@@ -113,6 +238,44 @@ impl ProducerConfig {
     pub fn ack_timeout(&self) -> Duration {
         Duration::from_millis(self.ack_timeout)
     }
+
+    /// The maximum amount of time that sending a record will block when the buffer is full.
+    pub fn max_block(&self) -> Duration {
+        Duration::from_millis(self.max_block)
+    }
+
+    /// An upper bound on the time to report success or failure after sending a record.
+    pub fn delivery_timeout(&self) -> Duration {
+        Duration::from_millis(self.delivery_timeout)
+    }
+
+    /// The maximum publish rate of the producer to all the topics.
+    pub fn rate_limit(&self) -> RateLimit {
+        RateLimit::new(self.rate_limit_records, self.rate_limit_bytes)
+    }
+
+    /// The retry policies of the produce requests for each class of errors.
+    pub fn retry_policies(&self) -> RetryPolicies {
+        self.retry_policies.clone().or(self.client.retry_policies())
+    }
+
+    /// The number of acknowledgments the producer requires for the records of the topic.
+    pub fn topic_acks(&self, topic_name: &str) -> RequiredAcks {
+        self.topic_overrides
+            .get(topic_name)
+            .and_then(|overrides| overrides.acks)
+            .unwrap_or(self.acks)
+    }
+
+    /// The maximum amount of time the server will wait for acknowledgments of the records of the topic.
+    pub fn topic_ack_timeout(&self, topic_name: &str) -> Duration {
+        Duration::from_millis(
+            self.topic_overrides
+                .get(topic_name)
+                .and_then(|overrides| overrides.ack_timeout)
+                .unwrap_or(self.ack_timeout),
+        )
+    }
 }
 
 #[cfg(test)]
@@ -121,12 +284,56 @@ mod tests {
 
     use super::*;
 
+    use client::{ErrorClass, RetryPolicy};
+
     #[test]
     fn test_properties() {
         let config = ProducerConfig::default();
 
         assert_eq!(config.linger(), Duration::from_millis(DEFAULT_LINGER_MILLIS));
         assert_eq!(config.ack_timeout(), Duration::from_millis(DEFAULT_ACK_TIMEOUT_MILLIS));
+        assert_eq!(config.max_block(), Duration::from_millis(DEFAULT_MAX_BLOCK_MILLIS));
+        assert_eq!(
+            config.delivery_timeout(),
+            Duration::from_millis(DEFAULT_DELIVERY_TIMEOUT_MILLIS)
+        );
+
+        let config = ProducerConfig {
+            client: ClientConfig {
+                retries: 3,
+                ..Default::default()
+            },
+            retry_policies: RetryPolicies {
+                refresh_metadata: Some(RetryPolicy {
+                    max_retries: 5,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        assert_eq!(config.retry_policies().backoffs(ErrorClass::Retriable).len(), 3);
+        assert_eq!(config.retry_policies().backoffs(ErrorClass::RefreshMetadata).len(), 5);
+
+        let mut config = ProducerConfig::default();
+
+        config.topic_overrides.insert(
+            "topic".to_owned(),
+            TopicOverrides {
+                acks: Some(RequiredAcks::All),
+                ack_timeout: Some(1000),
+                ..Default::default()
+            },
+        );
+
+        assert_eq!(config.topic_acks("topic"), RequiredAcks::All);
+        assert_eq!(config.topic_acks("other"), RequiredAcks::One);
+        assert_eq!(config.topic_ack_timeout("topic"), Duration::from_millis(1000));
+        assert_eq!(
+            config.topic_ack_timeout("other"),
+            Duration::from_millis(DEFAULT_ACK_TIMEOUT_MILLIS)
+        );
     }
 
     #[test]
@@ -137,20 +344,60 @@ mod tests {
     "bootstrap.servers": [],
     "client.id": null,
     "connection.max.idle.ms": 5000,
+    "max.in.flight.requests.per.connection": 5,
     "request.timeout.ms": 30000,
+    "request.timeouts.ms": {},
     "api.version.request": false,
     "broker.version.fallback": "0.9.0",
     "metadata.max.age.ms": 300000,
+    "metadata.bootstrap.retries": 5,
+    "topic.metadata.refresh.interval.ms": 300000,
+    "dns.refresh.interval.ms": 60000,
+    "check.crcs": "fail",
+    "socket.nodelay": true,
+    "socket.send.buffer.bytes": null,
+    "socket.receive.buffer.bytes": null,
+    "socket.keepalive.ms": null,
+    "queue.buffering.max.requests": 0,
+    "queue.full.behavior": "fail",
     "metrics": false,
+    "warm.up.topics": [],
     "retries": 0,
-    "retry.backoff.ms": 100
+    "retry.backoff.ms": 100,
+    "retry.policies": {
+      "retriable": null,
+      "refresh.metadata": null
+    },
+    "reconnect.backoff.ms": 50,
+    "reconnect.backoff.max.ms": 1000,
+    "sasl.mechanism": "PLAIN",
+    "sasl.username": null,
+    "ssl.enabled": false,
+    "ssl.ca.location": null,
+    "ssl.keystore.location": null,
+    "ssl.endpoint.identification.enabled": true
   },
   "acks": "one",
   "timeout.ms": 30000,
   "compression.type": "none",
   "batch.size": 16384,
   "max.request.size": 1048576,
-  "linger.ms": 0
+  "message.max.bytes": 1000012,
+  "linger.ms": 0,
+  "buffer.memory": 33554432,
+  "max.block.ms": 60000,
+  "allow.auto.create.topics": true,
+  "delivery.timeout.ms": 120000,
+  "partitioner.strategy": "roundrobin",
+  "spill.file": null,
+  "rate.limit.records.per.sec": null,
+  "rate.limit.bytes.per.sec": null,
+  "retry.policies": {
+    "retriable": null,
+    "refresh.metadata": null
+  },
+  "delivery.reports": false,
+  "topic.overrides": {}
 }"#;
 
         assert_eq!(serde_json::to_string_pretty(&config).unwrap(), json);
@@ -3,7 +3,7 @@ use std::hash::Hash;
 use std::ops::{Deref, DerefMut};
 use std::rc::Rc;
 
-use errors::Result;
+use errors::{Error, Result};
 
 use producer::{ProducerRecord, RecordMetadata};
 
@@ -24,7 +24,9 @@ pub trait ProducerInterceptor {
 
     /// This method is called when the record sent to the server has been acknowledged,
     /// or when sending the record fails before it gets sent to the server.
-    fn ack(&self, result: &Result<RecordMetadata>);
+    ///
+    /// The `err` is `None` if the record was acknowledged, otherwise the offset of `metadata` is `-1`.
+    fn on_acknowledgement(&self, metadata: &RecordMetadata, err: Option<&Error>);
 }
 
 pub struct ProducerInterceptors<K, V> {
@@ -74,9 +76,9 @@ where
         Ok(record)
     }
 
-    fn ack(&self, result: &Result<RecordMetadata>) {
+    fn on_acknowledgement(&self, metadata: &RecordMetadata, err: Option<&Error>) {
         for interceptor in &self.interceptors {
-            interceptor.ack(result);
+            interceptor.on_acknowledgement(metadata, err);
         }
     }
 }
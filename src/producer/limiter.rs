@@ -0,0 +1,209 @@
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// A trait for limiting the publish rate of the producer.
+///
+/// The limiter is asked before each record is pushed to the accumulator,
+/// and the record is delayed for the returned duration when the rate has been exceeded.
+pub trait RateLimiter {
+    /// Acquire the permits to publish the records of the serialized size in bytes to the topic.
+    ///
+    /// Returns the time to wait before the records could be published, or `None` if they could be published at once.
+    fn acquire(&self, topic_name: &str, records: usize, bytes: usize) -> Option<Duration>;
+}
+
+/// The maximum publish rate of the records and bytes.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RateLimit {
+    /// The maximum number of records per second, unlimited if not set or zero.
+    pub records_per_sec: Option<u64>,
+    /// The maximum bytes per second, unlimited if not set or zero.
+    pub bytes_per_sec: Option<u64>,
+}
+
+impl RateLimit {
+    pub fn new(records_per_sec: Option<u64>, bytes_per_sec: Option<u64>) -> Self {
+        RateLimit {
+            records_per_sec,
+            bytes_per_sec,
+        }
+    }
+
+    /// Returns `true` if neither the records nor the bytes are limited.
+    pub fn is_unlimited(&self) -> bool {
+        self.records_per_sec.map_or(true, |rate| rate == 0) && self.bytes_per_sec.map_or(true, |rate| rate == 0)
+    }
+}
+
+/// A token bucket which is refilled at the rate, and holds up to the tokens of one second.
+#[derive(Clone, Debug)]
+struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: u64, now: Instant) -> Self {
+        TokenBucket {
+            rate: rate as f64,
+            tokens: rate as f64,
+            updated: now,
+        }
+    }
+
+    /// Take the tokens, and returns the time to wait until the taken tokens have been refilled.
+    ///
+    /// The tokens may be overdrawn, so the following acquirers will wait behind the current one.
+    fn take(&mut self, tokens: usize, now: Instant) -> Option<Duration> {
+        if now > self.updated {
+            let elapsed = now - self.updated;
+            let elapsed = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1_000_000_000.0;
+
+            self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+            self.updated = now;
+        }
+
+        self.tokens -= tokens as f64;
+
+        if self.tokens >= 0.0 {
+            None
+        } else {
+            let secs = -self.tokens / self.rate;
+
+            Some(Duration::new(
+                secs.trunc() as u64,
+                (secs.fract() * 1_000_000_000.0) as u32,
+            ))
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct Buckets {
+    records: Option<TokenBucket>,
+    bytes: Option<TokenBucket>,
+}
+
+impl Buckets {
+    fn new(limit: RateLimit, now: Instant) -> Self {
+        Buckets {
+            records: limit
+                .records_per_sec
+                .filter(|&rate| rate > 0)
+                .map(|rate| TokenBucket::new(rate, now)),
+            bytes: limit
+                .bytes_per_sec
+                .filter(|&rate| rate > 0)
+                .map(|rate| TokenBucket::new(rate, now)),
+        }
+    }
+
+    fn take(&mut self, records: usize, bytes: usize, now: Instant) -> Option<Duration> {
+        let records = self.records
+            .as_mut()
+            .and_then(|bucket| bucket.take(records, now));
+        let bytes = self.bytes.as_mut().and_then(|bucket| bucket.take(bytes, now));
+
+        cmp::max(records, bytes)
+    }
+}
+
+/// A `RateLimiter` with the token buckets of the records and bytes,
+/// which limits the publish rate of all the topics, and of the individual topics.
+///
+/// A record is delayed until both the global and its topic limits allow it.
+#[derive(Debug, Default)]
+pub struct TokenBucketRateLimiter {
+    global: RefCell<Buckets>,
+    limits: HashMap<String, RateLimit>,
+    topics: RefCell<HashMap<String, Buckets>>,
+}
+
+impl TokenBucketRateLimiter {
+    /// Construct a `TokenBucketRateLimiter` which limits the publish rate of all the topics.
+    pub fn new(limit: RateLimit) -> Self {
+        TokenBucketRateLimiter {
+            global: RefCell::new(Buckets::new(limit, Instant::now())),
+            limits: HashMap::new(),
+            topics: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Limit the publish rate of the topic.
+    pub fn with_topic_limit<S>(mut self, topic_name: S, limit: RateLimit) -> Self
+    where
+        S: Into<String>,
+    {
+        self.limits.insert(topic_name.into(), limit);
+        self
+    }
+}
+
+impl RateLimiter for TokenBucketRateLimiter {
+    fn acquire(&self, topic_name: &str, records: usize, bytes: usize) -> Option<Duration> {
+        let now = Instant::now();
+        let global = self.global.borrow_mut().take(records, bytes, now);
+        let topic = self.limits.get(topic_name).and_then(|&limit| {
+            self.topics
+                .borrow_mut()
+                .entry(topic_name.to_owned())
+                .or_insert_with(|| Buckets::new(limit, now))
+                .take(records, bytes, now)
+        });
+
+        cmp::max(global, topic)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(10, now);
+
+        assert_eq!(bucket.take(5, now), None);
+        assert_eq!(bucket.take(5, now), None);
+        assert_eq!(bucket.take(5, now), Some(Duration::from_millis(500)));
+
+        // the overdrawn tokens must be refilled before the following acquirers
+        assert_eq!(bucket.take(5, now + Duration::from_millis(500)), Some(Duration::from_millis(500)));
+        assert_eq!(bucket.take(5, now + Duration::from_secs(2)), None);
+
+        // the bucket holds up to the tokens of one second
+        assert_eq!(bucket.take(10, now + Duration::from_secs(10)), None);
+        assert!(bucket.take(1, now + Duration::from_secs(10)).is_some());
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = TokenBucketRateLimiter::new(RateLimit::new(None, Some(1000)))
+            .with_topic_limit("slow", RateLimit::new(Some(2), None));
+
+        assert_eq!(limiter.acquire("fast", 1, 100), None);
+        assert_eq!(limiter.acquire("slow", 1, 100), None);
+        assert_eq!(limiter.acquire("slow", 1, 100), None);
+
+        // the topic limit is exceeded
+        assert!(limiter.acquire("slow", 1, 100).is_some());
+
+        // the global limit is exceeded
+        assert!(limiter.acquire("fast", 1, 1000).is_some());
+    }
+
+    #[test]
+    fn test_unlimited() {
+        let limiter = TokenBucketRateLimiter::default();
+
+        assert!(RateLimit::default().is_unlimited());
+
+        for _ in 0..1000 {
+            assert_eq!(limiter.acquire("topic", 1, 1024 * 1024), None);
+        }
+    }
+}
@@ -1,20 +1,34 @@
 mod accumulator;
+mod audit;
 mod batch;
 mod builder;
 mod config;
 mod interceptor;
+mod limiter;
 mod partitioner;
 mod producer;
 mod record;
+mod report;
 mod sender;
+mod shared;
+mod spill;
 
-pub use self::accumulator::{Accumulator, PushRecord, RecordAccumulator};
+pub use self::accumulator::{Accumulator, Batches, BufferMemory, Drained, InFlight, PushRecord, RecordAccumulator,
+                            ReserveMemory};
+pub use self::audit::{AuditInterceptor, AuditRecord, AuditSink, LogAuditSink, WriteAuditSink};
 pub use self::batch::{ProducerBatch, Thunk};
 pub use self::builder::ProducerBuilder;
-pub use self::config::{ProducerConfig, DEFAULT_ACK_TIMEOUT_MILLIS, DEFAULT_BATCH_SIZE, DEFAULT_LINGER_MILLIS,
-                       DEFAULT_MAX_REQUEST_SIZE};
+pub use self::config::{ProducerConfig, TopicOverrides, DEFAULT_ACK_TIMEOUT_MILLIS, DEFAULT_BATCH_SIZE,
+                       DEFAULT_BUFFER_MEMORY, DEFAULT_DELIVERY_TIMEOUT_MILLIS, DEFAULT_LINGER_MILLIS,
+                       DEFAULT_MAX_BLOCK_MILLIS, DEFAULT_MAX_REQUEST_SIZE, DEFAULT_MESSAGE_MAX_BYTES};
 pub use self::interceptor::{Interceptors, ProducerInterceptor, ProducerInterceptors};
-pub use self::partitioner::{DefaultPartitioner, Partitioner};
-pub use self::producer::{Flush, GetTopic, KafkaProducer, Producer, ProducerPartition, ProducerTopic, SendRecord};
+pub use self::limiter::{RateLimit, RateLimiter, TokenBucketRateLimiter};
+pub use self::partitioner::{DefaultPartitioner, PartitionStrategy, Partitioner, StickyPartitioner, WeightedPartitioner,
+                            DEFAULT_PARTITION_WEIGHT};
+pub use self::producer::{Close, Flush, GetTopic, KafkaProducer, Producer, ProducerPartition, ProducerTopic, SendAll,
+                         SendRecord};
 pub use self::record::{ProducerRecord, RecordMetadata};
+pub use self::report::{DeliveryReport, DeliveryReporter, DeliveryReports, RecordId};
 pub use self::sender::{SendBatch, Sender};
+pub use self::shared::{SharedFlush, SharedProducer, SharedSendAll, SharedSendRecord};
+pub use self::spill::{SpillEntry, SpillFile, SpilledRecord};
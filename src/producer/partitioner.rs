@@ -1,13 +1,29 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::hash::{BuildHasher, BuildHasherDefault, Hash, Hasher};
 use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
 
 use twox_hash::XxHash;
 
 use client::{Cluster, Metadata};
-use protocol::PartitionId;
+use producer::ProducerConfig;
+use protocol::{PartitionId, ToMilliseconds};
+
+/// The default weight of the partitions for the `WeightedPartitioner`.
+pub const DEFAULT_PARTITION_WEIGHT: u32 = 100;
+
+/// The smoothing factor of the latency observed by the `WeightedPartitioner`.
+const LATENCY_SMOOTHING_FACTOR: f64 = 0.3;
+
+/// The minimum penalty of a partition with the failed batches, never starves the partition.
+const MIN_FAILURE_PENALTY: f64 = 0.01;
 
 /// A trait for choosing a partition for a message to be sent to Kafka.
 pub trait Partitioner {
+    /// Configure the partitioner with the producer configuration.
+    fn configure(&mut self, _config: &ProducerConfig) {}
+
     /// Compute the partition for the given record.
     fn partition<K: Hash, V>(
         &self,
@@ -17,6 +33,49 @@ pub trait Partitioner {
         value: Option<&V>,
         metadata: &Metadata,
     ) -> Option<PartitionId>;
+
+    /// The partitions of the topic which have a leader,
+    /// or all the partitions if none of them is available, e.g. during a leader election.
+    fn available_partitions(&self, topic_name: &str, metadata: &Metadata) -> Vec<PartitionId> {
+        match metadata.available_partitions_for_topic(topic_name) {
+            Some(ref partitions) if !partitions.is_empty() => partitions.iter().map(|tp| tp.partition_id).collect(),
+            _ => metadata
+                .partitions_for_topic(topic_name)
+                .map(|partitions| partitions.iter().map(|tp| tp.partition_id).collect())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Notify the partitioner that a new batch of the partition was created for the record.
+    fn on_new_batch(&self, _topic_name: &str, _partition_id: PartitionId) {}
+
+    /// Notify the partitioner that the batch of the partition is full or lingered,
+    /// and will be sent to the broker.
+    fn batch_completed(&self, _topic_name: &str, _partition_id: PartitionId) {}
+
+    /// Notify the partitioner that the batch of the partition has been acknowledged or failed,
+    /// after the latency including the retries.
+    fn batch_sent(&self, _topic_name: &str, _partition_id: PartitionId, _latency: Duration, _succeeded: bool) {}
+}
+
+/// Strategy for choosing a partition for the records without key.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PartitionStrategy {
+    /// Choose a partition for each record in a round-robin fashion.
+    RoundRobin,
+
+    /// Stick to a partition until the current batch is full or lingered,
+    /// then switch to the next partition in a round-robin fashion.
+    ///
+    /// The records will be batched together into fewer requests.
+    Sticky,
+}
+
+impl Default for PartitionStrategy {
+    fn default() -> Self {
+        PartitionStrategy::RoundRobin
+    }
 }
 
 pub type DefaultHasher = XxHash;
@@ -26,11 +85,14 @@ pub type DefaultHasher = XxHash;
 /// - If a partition is specified in the record, use it
 /// - If no partition is specified but a key is present choose a partition based on a hash of the
 /// key
-/// - If no partition or key is present choose a partition in a round-robin fashion
+/// - If no partition or key is present choose an available partition with the `PartitionStrategy`
+/// of the producer configuration, in a round-robin fashion by default
 #[derive(Default)]
 pub struct DefaultPartitioner<H: BuildHasher = BuildHasherDefault<DefaultHasher>> {
     hash_builder: H,
+    strategy: PartitionStrategy,
     records: AtomicUsize,
+    sticky_partitions: RefCell<HashMap<String, PartitionId>>,
 }
 
 impl DefaultPartitioner {
@@ -43,19 +105,54 @@ impl DefaultPartitioner {
     pub fn with_hasher<B: BuildHasher>(hash_builder: B) -> DefaultPartitioner<B> {
         DefaultPartitioner {
             hash_builder,
+            strategy: PartitionStrategy::default(),
             records: AtomicUsize::new(0),
+            sticky_partitions: RefCell::new(HashMap::new()),
         }
     }
+}
+
+impl<H> DefaultPartitioner<H>
+where
+    H: BuildHasher,
+{
+    /// The strategy for choosing a partition for the records without key.
+    pub fn strategy(&self) -> PartitionStrategy {
+        self.strategy
+    }
 
     fn records(&self) -> usize {
         self.records.load(Ordering::Relaxed)
     }
+
+    fn sticky_partition(&self, topic_name: &str, partitions: &[PartitionId]) -> usize {
+        let mut sticky_partitions = self.sticky_partitions.borrow_mut();
+
+        if let Some(index) = sticky_partitions
+            .get(topic_name)
+            .and_then(|partition_id| partitions.iter().position(|id| id == partition_id))
+        {
+            return index;
+        }
+
+        let index = self.records.fetch_add(1, Ordering::Relaxed) % partitions.len();
+
+        trace!("stick to partition #{} of topic `{}`", partitions[index], topic_name);
+
+        sticky_partitions.insert(topic_name.to_owned(), partitions[index]);
+
+        index
+    }
 }
 
 impl<H> Partitioner for DefaultPartitioner<H>
 where
     H: BuildHasher,
 {
+    fn configure(&mut self, config: &ProducerConfig) {
+        self.strategy = config.partition_strategy;
+    }
+
     fn partition<K: Hash, V>(
         &self,
         topic_name: &str,
@@ -71,41 +168,296 @@ where
             }
         }
 
-        // TODO: use available partitions for topic in cluster
-        if let Some(partitions) = metadata.partitions_for_topic(topic_name) {
-            let index = if let Some(key) = key {
-                // If no partition is specified but a key is present choose a partition based on a
-                // hash of the key
-                let mut hasher = self.hash_builder.build_hasher();
-                key.hash(&mut hasher);
-                hasher.finish() as usize
-            } else {
-                // If no partition or key is present choose a partition in a round-robin fashion
-                self.records.fetch_add(1, Ordering::Relaxed)
-            } % partitions.len();
-
-            trace!(
-                "partition record to #{} base on {}",
-                index,
-                key.map_or("round-robin", |_| "hash-key")
-            );
+        match metadata.partitions_for_topic(topic_name) {
+            Some(ref partitions) if !partitions.is_empty() => {
+                let partition_id = if let Some(key) = key {
+                    // If no partition is specified but a key is present choose a partition based on a
+                    // hash of the key, all the partitions are used to keep the records of a key in order
+                    let mut hasher = self.hash_builder.build_hasher();
+                    key.hash(&mut hasher);
+                    partitions[hasher.finish() as usize % partitions.len()].partition_id
+                } else {
+                    let partitions = self.available_partitions(topic_name, metadata);
+
+                    if self.strategy == PartitionStrategy::Sticky {
+                        // If no partition or key is present stick to a partition until the batch was completed
+                        partitions[self.sticky_partition(topic_name, &partitions)]
+                    } else {
+                        // If no partition or key is present choose an available partition in a round-robin fashion
+                        partitions[self.records.fetch_add(1, Ordering::Relaxed) % partitions.len()]
+                    }
+                };
+
+                trace!(
+                    "partition record to #{} base on {}",
+                    partition_id,
+                    key.map_or("round-robin", |_| "hash-key")
+                );
+
+                Some(partition_id)
+            }
+            _ => {
+                warn!(
+                    "missed partitions info for topic `{}`, fallback to partition #0",
+                    topic_name
+                );
+
+                None
+            }
+        }
+    }
+
+    fn batch_completed(&self, topic_name: &str, partition_id: PartitionId) {
+        let mut sticky_partitions = self.sticky_partitions.borrow_mut();
 
-            Some(partitions[index].partition_id)
+        if sticky_partitions.get(topic_name) == Some(&partition_id) {
+            trace!("batch of partition #{} of topic `{}` completed", partition_id, topic_name);
+
+            sticky_partitions.remove(topic_name);
+        }
+    }
+}
+
+/// The sticky partitioning strategy
+///
+/// Same as the `DefaultPartitioner` but always sticks to a partition for the records without key,
+/// until the current batch is full or lingered, regardless of the producer configuration.
+pub struct StickyPartitioner<H: BuildHasher = BuildHasherDefault<DefaultHasher>>(DefaultPartitioner<H>);
+
+impl StickyPartitioner {
+    /// Create a `StickyPartitioner` with the default hasher.
+    pub fn new() -> StickyPartitioner<BuildHasherDefault<DefaultHasher>> {
+        Self::with_hasher(Default::default())
+    }
+
+    /// Create a `StickyPartitioner` with the special hasher.
+    pub fn with_hasher<B: BuildHasher>(hash_builder: B) -> StickyPartitioner<B> {
+        let mut partitioner = DefaultPartitioner::with_hasher(hash_builder);
+
+        partitioner.strategy = PartitionStrategy::Sticky;
+
+        StickyPartitioner(partitioner)
+    }
+}
+
+impl<H> Partitioner for StickyPartitioner<H>
+where
+    H: BuildHasher,
+{
+    fn partition<K: Hash, V>(
+        &self,
+        topic_name: &str,
+        partition_id: Option<PartitionId>,
+        key: Option<&K>,
+        value: Option<&V>,
+        metadata: &Metadata,
+    ) -> Option<PartitionId> {
+        self.0.partition(topic_name, partition_id, key, value, metadata)
+    }
+
+    fn batch_completed(&self, topic_name: &str, partition_id: PartitionId) {
+        self.0.batch_completed(topic_name, partition_id)
+    }
+}
+
+/// The weighted round-robin partitioning strategy
+///
+/// Same as the `DefaultPartitioner` for the records with a partition or key,
+/// but distributes the records without key across the partitions proportionally to their weights,
+/// in a smooth weighted round-robin fashion.
+///
+/// The partitions are weighted by `DEFAULT_PARTITION_WEIGHT` unless configured with `with_weight`.
+/// If the weights are adaptive, the weight of a partition will also be scaled inversely
+/// to the observed latency and failures of its batches,
+/// so the partitions on the overloaded brokers will receive fewer records.
+pub struct WeightedPartitioner<H: BuildHasher = BuildHasherDefault<DefaultHasher>> {
+    partitioner: DefaultPartitioner<H>,
+    weights: HashMap<(String, PartitionId), u32>,
+    adaptive: bool,
+    stats: RefCell<HashMap<(String, PartitionId), PartitionStats>>,
+    current_weights: RefCell<HashMap<(String, PartitionId), f64>>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct PartitionStats {
+    latency: f64,
+    penalty: f64,
+}
+
+impl Default for WeightedPartitioner {
+    fn default() -> Self {
+        Self::with_hasher(Default::default())
+    }
+}
+
+impl WeightedPartitioner {
+    /// Create a `WeightedPartitioner` with the default hasher.
+    pub fn new() -> WeightedPartitioner<BuildHasherDefault<DefaultHasher>> {
+        Default::default()
+    }
+
+    /// Create a `WeightedPartitioner` with the special hasher.
+    pub fn with_hasher<B: BuildHasher>(hash_builder: B) -> WeightedPartitioner<B> {
+        WeightedPartitioner {
+            partitioner: DefaultPartitioner::with_hasher(hash_builder),
+            weights: HashMap::new(),
+            adaptive: false,
+            stats: RefCell::new(HashMap::new()),
+            current_weights: RefCell::new(HashMap::new()),
+        }
+    }
+}
+
+impl<H> WeightedPartitioner<H>
+where
+    H: BuildHasher,
+{
+    /// Sets the weight of the partition, a partition weighted 0 will never receive the records without key.
+    pub fn with_weight<S: Into<String>>(mut self, topic_name: S, partition_id: PartitionId, weight: u32) -> Self {
+        self.weights.insert((topic_name.into(), partition_id), weight);
+        self
+    }
+
+    /// Scale the weights inversely to the observed latency and failures of the batches.
+    pub fn with_adaptive_weights(mut self) -> Self {
+        self.adaptive = true;
+        self
+    }
+
+    /// The weights of the partitions, scaled by the observed latency and failures if adaptive.
+    fn effective_weights(&self, topic_name: &str, partitions: &[PartitionId]) -> Vec<f64> {
+        let key = |partition_id: PartitionId| (topic_name.to_owned(), partition_id);
+        let weights = partitions.iter().map(|&partition_id| {
+            f64::from(
+                self.weights
+                    .get(&key(partition_id))
+                    .cloned()
+                    .unwrap_or(DEFAULT_PARTITION_WEIGHT),
+            )
+        });
+
+        if !self.adaptive {
+            return weights.collect();
+        }
+
+        let stats = self.stats.borrow();
+        let observed = partitions
+            .iter()
+            .flat_map(|&partition_id| stats.get(&key(partition_id)))
+            .collect::<Vec<_>>();
+
+        // the partitions not observed yet are assumed to have the average latency
+        let average_latency = if observed.is_empty() {
+            1.0
         } else {
-            warn!(
-                "missed partitions info for topic `{}`, fallback to partition #0",
-                topic_name
-            );
+            observed.iter().map(|stats| stats.latency).sum::<f64>() / observed.len() as f64
+        };
 
-            None
+        weights
+            .zip(partitions)
+            .map(|(weight, &partition_id)| match stats.get(&key(partition_id)) {
+                Some(stats) => weight * stats.penalty * average_latency.max(1.0) / stats.latency.max(1.0),
+                None => weight,
+            })
+            .collect()
+    }
+
+    fn weighted_partition(&self, topic_name: &str, partitions: &[PartitionId]) -> Option<PartitionId> {
+        let weights = self.effective_weights(topic_name, partitions);
+        let total = weights.iter().sum::<f64>();
+
+        if total <= 0.0 {
+            return None;
+        }
+
+        let mut current_weights = self.current_weights.borrow_mut();
+        let mut selected: Option<(PartitionId, f64)> = None;
+
+        // the smooth weighted round-robin, the partition with the highest current weight is selected
+        for (&partition_id, &weight) in partitions.iter().zip(weights.iter()) {
+            let current = current_weights
+                .entry((topic_name.to_owned(), partition_id))
+                .or_insert(0.0);
+
+            *current += weight;
+
+            if selected.map_or(true, |(_, max)| *current > max) {
+                selected = Some((partition_id, *current));
+            }
         }
+
+        selected.map(|(partition_id, _)| {
+            if let Some(current) = current_weights.get_mut(&(topic_name.to_owned(), partition_id)) {
+                *current -= total;
+            }
+
+            partition_id
+        })
+    }
+}
+
+impl<H> Partitioner for WeightedPartitioner<H>
+where
+    H: BuildHasher,
+{
+    fn partition<K: Hash, V>(
+        &self,
+        topic_name: &str,
+        partition_id: Option<PartitionId>,
+        key: Option<&K>,
+        value: Option<&V>,
+        metadata: &Metadata,
+    ) -> Option<PartitionId> {
+        if partition_id.map_or(false, |partition_id| partition_id >= 0) || key.is_some() {
+            return self.partitioner
+                .partition(topic_name, partition_id, key, value, metadata);
+        }
+
+        let partitions = self.available_partitions(topic_name, metadata);
+
+        match self.weighted_partition(topic_name, &partitions) {
+            Some(partition_id) => {
+                trace!("partition record to #{} base on weighted round-robin", partition_id);
+
+                Some(partition_id)
+            }
+            None => self.partitioner
+                .partition(topic_name, partition_id, key, value, metadata),
+        }
+    }
+
+    fn batch_sent(&self, topic_name: &str, partition_id: PartitionId, latency: Duration, succeeded: bool) {
+        if !self.adaptive {
+            return;
+        }
+
+        let latency = latency.as_millis() as f64;
+        let mut stats = self.stats.borrow_mut();
+        let stats = stats
+            .entry((topic_name.to_owned(), partition_id))
+            .or_insert(PartitionStats { latency, penalty: 1.0 });
+
+        stats.latency += LATENCY_SMOOTHING_FACTOR * (latency - stats.latency);
+        stats.penalty = if succeeded {
+            (stats.penalty * 2.0).min(1.0)
+        } else {
+            (stats.penalty / 2.0).max(MIN_FAILURE_PENALTY)
+        };
+
+        trace!(
+            "partition #{} of topic `{}` observed latency {:.1} ms, penalty {}",
+            partition_id,
+            topic_name,
+            stats.latency,
+            stats.penalty
+        );
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use client::PartitionInfo;
+    use client::{BrokerRef, PartitionInfo};
 
     #[test]
     fn test_skip_partitioning() {
@@ -148,4 +500,132 @@ mod tests {
 
         assert_eq!(partitioner.records(), 100);
     }
+
+    #[test]
+    fn test_available_partitioning() {
+        let partitions = (0..3)
+            .map(|id| PartitionInfo {
+                partition_id: id,
+                leader: if id == 1 { None } else { Some(BrokerRef::new(0)) },
+                ..Default::default()
+            })
+            .collect();
+        let metadata = Metadata::with_topics(vec![("topic".to_owned(), partitions)]);
+
+        let partitioner = DefaultPartitioner::new();
+
+        assert_eq!(partitioner.available_partitions("topic", &metadata), vec![0, 2]);
+
+        // the partition without leader is skipped
+        for id in 0..10 {
+            assert_eq!(
+                partitioner.partition::<(), &str>("topic", None, None, Some("value").as_ref(), &metadata),
+                Some(id % 2 * 2)
+            );
+        }
+
+        // all the partitions are used if none of them is available
+        let partitions = (0..3)
+            .map(|id| PartitionInfo {
+                partition_id: id,
+                ..Default::default()
+            })
+            .collect();
+        let metadata = Metadata::with_topics(vec![("topic".to_owned(), partitions)]);
+
+        assert_eq!(partitioner.available_partitions("topic", &metadata), vec![0, 1, 2]);
+        assert!(partitioner.available_partitions("unknown", &metadata).is_empty());
+    }
+
+    #[test]
+    fn test_sticky_partitioning() {
+        let partitions = (0..3)
+            .map(|id| PartitionInfo {
+                partition_id: id,
+                ..Default::default()
+            })
+            .collect();
+        let metadata = Metadata::with_topics(vec![("topic".to_owned(), partitions)]);
+
+        let partitioner = StickyPartitioner::new();
+
+        // stick to the partition until the batch completed
+        for _ in 0..10 {
+            assert_eq!(
+                partitioner.partition::<(), &str>("topic", None, None, Some("value").as_ref(), &metadata),
+                Some(0)
+            );
+        }
+
+        // the completed batch of other partitions doesn't matter
+        partitioner.batch_completed("topic", 1);
+
+        assert_eq!(
+            partitioner.partition::<(), &str>("topic", None, None, Some("value").as_ref(), &metadata),
+            Some(0)
+        );
+
+        // switch to the next partition
+        partitioner.batch_completed("topic", 0);
+
+        assert_eq!(
+            partitioner.partition::<(), &str>("topic", None, None, Some("value").as_ref(), &metadata),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn test_weighted_partitioning() {
+        let partitions = (0..3)
+            .map(|id| PartitionInfo {
+                partition_id: id,
+                ..Default::default()
+            })
+            .collect();
+        let metadata = Metadata::with_topics(vec![("topic".to_owned(), partitions)]);
+
+        let partitioner = WeightedPartitioner::new()
+            .with_weight("topic", 0, 1)
+            .with_weight("topic", 1, 3)
+            .with_weight("topic", 2, 0);
+
+        let mut counts = HashMap::new();
+
+        for _ in 0..100 {
+            let partition_id = partitioner
+                .partition::<(), &str>("topic", None, None, Some("value").as_ref(), &metadata)
+                .unwrap();
+
+            *counts.entry(partition_id).or_insert(0) += 1;
+        }
+
+        assert_eq!(counts.get(&0), Some(&25));
+        assert_eq!(counts.get(&1), Some(&75));
+        assert_eq!(counts.get(&2), None);
+
+        // the partition specified in the record is used
+        assert_eq!(
+            partitioner.partition::<(), &str>("topic", Some(2), None, Some("value").as_ref(), &metadata),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn test_adaptive_weights() {
+        let partitioner = WeightedPartitioner::new().with_adaptive_weights();
+        let partitions = vec![0, 1];
+
+        assert_eq!(partitioner.effective_weights("topic", &partitions), vec![100.0, 100.0]);
+
+        partitioner.batch_sent("topic", 0, Duration::from_millis(10), true);
+        partitioner.batch_sent("topic", 1, Duration::from_millis(30), true);
+
+        // inversely to the latency
+        assert_eq!(partitioner.effective_weights("topic", &partitions), vec![200.0, 200.0 / 3.0]);
+
+        partitioner.batch_sent("topic", 0, Duration::from_millis(10), false);
+
+        // penalized by the failure
+        assert_eq!(partitioner.effective_weights("topic", &partitions), vec![100.0, 200.0 / 3.0]);
+    }
 }
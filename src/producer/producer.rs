@@ -1,22 +1,30 @@
 use std::borrow::{Borrow, Cow};
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::cmp;
+use std::collections::HashMap;
 use std::fmt::Debug;
 use std::ops::Deref;
 use std::hash::Hash;
+use std::iter;
 use std::mem;
 use std::rc::Rc;
+use std::time::{Duration, Instant};
 
 use time;
 
+use bytes::Bytes;
+use futures::future::{Either, Loop};
 use futures::{future, Async, AsyncSink, Future, Poll, Sink, StartSend, Stream};
-use tokio_core::reactor::{Handle, Timeout};
-use tokio_retry::Retry;
-
-use client::{Client, Cluster, KafkaClient, Metadata, PartitionRecord, StaticBoxFuture, ToStaticBoxFuture, TopicRecord};
-use errors::{Error, ErrorKind};
-use producer::{Accumulator, Interceptors, Partitioner, ProducerBuilder, ProducerConfig, ProducerInterceptor,
-               ProducerInterceptors, ProducerRecord, PushRecord, RecordAccumulator, RecordMetadata, Sender};
-use protocol::{ApiKeys, PartitionId, ToMilliseconds};
+use tokio_core::reactor::Handle;
+
+use client::{Broker, BrokerRef, Client, Cluster, ErrorClass, KafkaClient, Metadata, PartitionRecord, ProducedRecords,
+             RetryPolicies, ShutdownReport, StaticBoxFuture, ToStaticBoxFuture, TopicRecord};
+use errors::{Error, ErrorKind, Result};
+use network::TopicPartition;
+use producer::{Accumulator, DeliveryReporter, DeliveryReports, Interceptors, Partitioner, ProducerBatch,
+               ProducerBuilder, ProducerConfig, ProducerInterceptor, ProducerInterceptors, ProducerRecord, PushRecord,
+               RateLimiter, RecordAccumulator, RecordId, RecordMetadata, Sender, SpillFile, SpilledRecord};
+use protocol::{record_size, ApiKeys, ApiVersion, KafkaCode, PartitionId, Timestamp, ToMilliseconds};
 use serialization::Serializer;
 
 /// A trait for publishing records to the Kafka cluster.
@@ -32,6 +40,12 @@ pub trait Producer<'a> {
     /// return a future which will eventually contain the response information.
     fn send(&mut self, record: ProducerRecord<Self::Key, Self::Value>) -> SendRecord;
 
+    /// Send the given records, flush them from the producer, and
+    /// return a future which resolves when every record was acknowledged.
+    ///
+    /// The metadata of the records are returned in the same order as the records.
+    fn send_all(&mut self, records: Vec<ProducerRecord<Self::Key, Self::Value>>) -> SendAll;
+
     /// Flush any accumulated records from the producer.
     fn flush(&mut self) -> Flush;
 
@@ -42,12 +56,18 @@ pub trait Producer<'a> {
 /// The future of records metadata information.
 pub type SendRecord = StaticBoxFuture<RecordMetadata>;
 
+/// The future of records metadata information, in the same order as the sent records.
+pub type SendAll = StaticBoxFuture<Vec<RecordMetadata>>;
+
 /// The future of flushing records.
 pub type Flush = StaticBoxFuture;
 
 /// The future of `futures::Sink` to send records..
 pub type GetTopic<T> = StaticBoxFuture<T>;
 
+/// The future of closing the producer.
+pub type Close = StaticBoxFuture<ShutdownReport>;
+
 /// A Kafka producer that publishes records to the Kafka cluster.
 #[derive(Clone)]
 pub struct KafkaProducer<'a, K, V, P>
@@ -66,12 +86,45 @@ where
     V: Serializer,
 {
     client: KafkaClient<'a>,
-    config: ProducerConfig,
+    config: Rc<ProducerConfig>,
     accumulator: RecordAccumulator<'a>,
     key_serializer: K,
     value_serializer: V,
-    partitioner: P,
+    partitioner: Rc<P>,
     interceptors: Interceptors<K::Item, V::Item>,
+    rate_limiter: Option<Rc<RateLimiter>>,
+    closed: Cell<bool>,
+    delivered: Rc<Delivered>,
+    reporter: Option<DeliveryReporter>,
+    delivery_reports: RefCell<Option<DeliveryReports>>,
+}
+
+/// The number of the records which have been sent or failed to be sent by the producer.
+#[derive(Debug, Default)]
+struct Delivered {
+    flushed: Cell<usize>,
+    failed: Cell<usize>,
+}
+
+impl Delivered {
+    fn record(&self, records: usize, succeeded: bool) {
+        let counter = if succeeded { &self.flushed } else { &self.failed };
+
+        counter.set(counter.get() + records);
+    }
+
+    /// Report the records which have been sent or failed since the snapshot.
+    fn since(&self, snapshot: (usize, usize)) -> ShutdownReport {
+        ShutdownReport {
+            records_flushed: self.flushed.get() - snapshot.0,
+            records_failed: self.failed.get() - snapshot.1,
+            ..Default::default()
+        }
+    }
+
+    fn snapshot(&self) -> (usize, usize) {
+        (self.flushed.get(), self.failed.get())
+    }
 }
 
 impl<'a, K, V, P> Deref for KafkaProducer<'a, K, V, P>
@@ -92,6 +145,7 @@ where
     K: Serializer,
     K::Item: Hash,
     V: Serializer,
+    P: Partitioner,
     Self: 'static,
 {
     pub fn new(
@@ -99,22 +153,127 @@ where
         config: ProducerConfig,
         key_serializer: K,
         value_serializer: V,
-        partitioner: P,
+        mut partitioner: P,
         interceptors: Interceptors<K::Item, V::Item>,
+        rate_limiter: Option<Rc<RateLimiter>>,
     ) -> Self {
-        let accumulator = RecordAccumulator::new(config.batch_size, config.compression, config.linger());
+        partitioner.configure(&config);
+
+        let mut accumulator = RecordAccumulator::new(
+            config.batch_size,
+            config.compression,
+            config.linger(),
+            config.buffer_memory,
+            config.max_block(),
+            client.runtime().clone(),
+        ).with_topic_overrides(config.topic_overrides.clone());
+        let (reporter, delivery_reports) = if config.delivery_reports {
+            let (reporter, delivery_reports) = DeliveryReporter::new();
+
+            accumulator = accumulator.with_delivery_reporter(reporter.clone());
+
+            (Some(reporter), Some(delivery_reports))
+        } else {
+            (None, None)
+        };
+        let mut recovered = Vec::new();
+
+        if let Some(ref path) = config.spill_file {
+            match SpillFile::open(path).and_then(|mut spill| spill.recover().map(|records| (spill, records))) {
+                Ok((spill, records)) => {
+                    recovered = records;
+                    accumulator = accumulator.with_spill_file(spill);
+                }
+                Err(err) => warn!("fail to open spill file {}, {}", path, err),
+            }
+        }
 
-        KafkaProducer {
+        let producer = KafkaProducer {
             inner: Rc::new(Inner {
                 client,
-                config,
+                config: Rc::new(config),
                 accumulator,
                 key_serializer,
                 value_serializer,
-                partitioner,
+                partitioner: Rc::new(partitioner),
                 interceptors,
+                rate_limiter,
+                closed: Cell::new(false),
+                delivered: Rc::new(Delivered::default()),
+                reporter,
+                delivery_reports: RefCell::new(delivery_reports),
             }),
+        };
+
+        let inner = Rc::downgrade(&producer.inner);
+
+        producer
+            .inner
+            .client
+            .register_shutdown_hook(move || match inner.upgrade() {
+                Some(inner) => {
+                    debug!("flush pending records before shutdown");
+
+                    let started = Instant::now();
+                    let delivered = inner.delivered.clone();
+                    let snapshot = delivered.snapshot();
+
+                    inner
+                        .flush_batches(true)
+                        .map(move |_| ShutdownReport {
+                            duration: started.elapsed(),
+                            ..delivered.since(snapshot)
+                        })
+                        .static_boxed()
+                }
+                None => future::ok(ShutdownReport::default()).static_boxed(),
+            });
+
+        if !recovered.is_empty() {
+            producer.replay(recovered);
+        }
+
+        producer
+    }
+
+    /// Send the records left in the spill file by the previous producer,
+    /// they are kept in the spill file until delivered.
+    fn replay(&self, records: Vec<(u64, SpilledRecord)>) {
+        info!("replay {} records left in the spill file", records.len());
+
+        let runtime = self.inner.client.runtime();
+
+        for (offset, record) in records {
+            let tp = topic_partition!(record.topic_name.clone(), record.partition_id);
+            let push_record = self.inner.accumulator.push_spilled_record(tp, record, offset);
+            let blocked = push_record.is_blocked();
+            let inner = self.inner.clone();
+
+            runtime.spawn(
+                push_record
+                    .pushed()
+                    .and_then(move |push_record| {
+                        if blocked {
+                            // the record was pushed after the buffer memory was released
+                            let flush = inner.flush_batches(true).map_err(|err| {
+                                warn!("fail to flush replayed records, {}", err);
+                            });
+
+                            inner.client.runtime().spawn(flush);
+                        }
+
+                        push_record
+                    })
+                    .map(|_| ())
+                    .map_err(|err| {
+                        warn!("fail to replay spilled record, {}", err);
+                    }),
+            );
         }
+
+        runtime.spawn(self.inner.flush_batches(true).map_err(|err| {
+            warn!("fail to flush replayed records, {}", err);
+        }));
     }
 
     /// Construct a `ProducerBuilder` from ProducerConfig
@@ -134,6 +293,142 @@ where
     pub fn client(&self) -> KafkaClient<'a> {
         self.inner.client.clone()
     }
+
+    /// Close the producer, wait up to the timeout for the pending records to be sent.
+    ///
+    /// The producer stops accepting new records, flushes all the accumulated batches
+    /// and waits for the in-flight batches to be acknowledged.
+    /// The records which have not been sent before the timeout will fail with `ErrorKind::ProducerClosed`,
+    /// and the idle connections to the brokers will be closed.
+    ///
+    /// The returned `ShutdownReport` tells how many pending records have been sent or failed,
+    /// it resolves even if the pending records failed to be flushed before the timeout.
+    pub fn close(&self, timeout: Duration) -> Close {
+        debug!("closing producer, wait up to {:?} for the pending records", timeout);
+
+        self.inner.closed.set(true);
+
+        let inner = self.inner.clone();
+        let started = Instant::now();
+        let snapshot = self.inner.delivered.snapshot();
+        let in_flight = self.inner.accumulator.in_flight();
+        let flush = self.inner
+            .flush_batches(true)
+            .and_then(move |_| in_flight.drained());
+
+        self.inner
+            .client
+            .runtime()
+            .timeout(flush, timeout)
+            .then(move |res| {
+                if let Err(ref err) = res {
+                    warn!("fail to flush pending records before closing, {}", err);
+                }
+
+                let mut report = inner.delivered.since(snapshot);
+
+                report.records_failed += inner.abort_batches();
+                report.connections_closed = inner.client.close_idle_connections();
+                report.duration = started.elapsed();
+
+                debug!("producer closed, {}", report);
+
+                Ok::<_, Error>(report)
+            })
+            .static_boxed()
+    }
+
+    /// Returns `true` if the producer has been closed.
+    pub fn is_closed(&self) -> bool {
+        self.inner.closed.get()
+    }
+
+    /// Take the stream of the delivery reports of the records sent with `produce`.
+    ///
+    /// Returns `None` if the `delivery.reports` is disabled or the stream has been taken.
+    pub fn delivery_reports(&self) -> Option<DeliveryReports> {
+        self.inner.delivery_reports.borrow_mut().take()
+    }
+}
+
+impl<'a, K, V, P> KafkaProducer<'a, K, V, P>
+where
+    K: Serializer,
+    K::Item: Debug + Hash,
+    V: Serializer,
+    V::Item: Debug,
+    P: Partitioner,
+    Self: 'static,
+{
+    /// Send the given record without waiting for its delivery result,
+    /// which will be reported to the `delivery_reports` stream with the returned id.
+    ///
+    /// It avoids the future of each record for the high throughput producers,
+    /// and requires the `delivery.reports` to be enabled. The record is pushed at once
+    /// if the metadata of its topic has been cached and the buffer memory is available,
+    /// otherwise it is pushed by a background task which waits for them.
+    pub fn produce(&self, record: ProducerRecord<K::Item, V::Item>) -> Result<RecordId> {
+        let reporter = match self.inner.reporter {
+            Some(ref reporter) => reporter.clone(),
+            None => bail!(ErrorKind::ConfigError("the delivery reports are disabled")),
+        };
+
+        if self.inner.closed.get() {
+            bail!(ErrorKind::ProducerClosed);
+        }
+
+        let id = reporter.next_id();
+        let metadata = self.inner.client.cached_topic_metadata(&record.topic_name);
+        let available = metadata.as_ref().map_or(false, |metadata| {
+            metadata
+                .topics()
+                .get(record.topic_name.as_str())
+                .map_or(false, |partitions| !partitions.is_empty())
+        });
+
+        let pushed = match metadata {
+            Some(ref metadata) if available => {
+                let push_record = self.inner.push_record(metadata, record, Some(id));
+
+                // the record was pushed at once, only its id is registered to report the delivery result
+                if push_record.is_reported() {
+                    Inner::schedule_flush(&self.inner, &push_record);
+
+                    return Ok(id);
+                }
+
+                // the record is blocked until the buffer memory is available, or failed to be pushed
+                push_record.pushed()
+            }
+            _ => {
+                let inner = self.inner.clone();
+
+                self.inner
+                    .wait_on_metadata(record.topic_name.clone())
+                    .and_then(move |metadata| inner.push_record(&metadata, record, Some(id)).pushed())
+                    .static_boxed()
+            }
+        };
+
+        let inner = self.inner.clone();
+        let future = pushed
+            .and_then(move |push_record| {
+                Inner::schedule_flush(&inner, &push_record);
+
+                push_record.settled()
+            })
+            .or_else(move |err| {
+                if let Err(result) = reporter.report(id, Err(err)) {
+                    warn!("fail to report the delivery of record #{}, {:?}", id, result);
+                }
+
+                Ok::<(), ()>(())
+            });
+
+        self.inner.client.runtime().spawn(future);
+
+        Ok(id)
+    }
 }
 
 impl<'a, K, V, P> Producer<'a> for KafkaProducer<'a, K, V, P>
@@ -150,46 +445,78 @@ where
     type Topic = ProducerTopic<'a, K, V, P>;
 
     fn send(&mut self, record: ProducerRecord<Self::Key, Self::Value>) -> SendRecord {
+        if self.inner.closed.get() {
+            return future::err(ErrorKind::ProducerClosed.into()).static_boxed();
+        }
+
         let inner = self.inner.clone();
 
         self.inner
-            .client
-            .metadata()
-            .and_then(move |metadata| {
-                let push_record = inner.push_record(&metadata, record);
+            .wait_on_metadata(record.topic_name.clone())
+            .and_then({
+                let inner = inner.clone();
 
-                if push_record.is_full() {
-                    let flush = inner.flush_batches(false).map_err(|err| {
-                        warn!("fail to flush full batch, {}", err);
-                    });
+                // wait until the record was pushed when the buffer memory is exhausted
+                move |metadata| inner.push_record(&metadata, record, None).pushed()
+            })
+            .and_then(move |push_record| {
+                Inner::schedule_flush(&inner, &push_record);
 
-                    inner.client.handle().spawn(flush);
-                }
+                push_record
+            })
+            .static_boxed()
+    }
 
-                if push_record.new_batch() {
-                    let timeout = Timeout::new(inner.config.linger(), inner.client.handle());
+    fn send_all(&mut self, records: Vec<ProducerRecord<Self::Key, Self::Value>>) -> SendAll {
+        if self.inner.closed.get() {
+            return future::err(ErrorKind::ProducerClosed.into()).static_boxed();
+        }
+
+        let inner = self.inner.clone();
+        let pending = Vec::with_capacity(records.len());
+
+        // push the records one by one, so they are appended to the batches in order
+        let pushed = future::loop_fn((records.into_iter(), pending), {
+            let inner = inner.clone();
 
-                    match timeout {
-                        Ok(timeout) => {
-                            let future = {
+            move |(mut records, mut pending)| match records.next() {
+                Some(record) => {
+                    let inner = inner.clone();
+
+                    Either::A(
+                        inner
+                            .wait_on_metadata(record.topic_name.clone())
+                            .and_then({
                                 let inner = inner.clone();
 
-                                timeout
-                                    .from_err()
-                                    .and_then(move |_| inner.flush_batches(false))
-                                    .map(|_| ())
-                                    .map_err(|e| warn!("flush batch error: {:?}", e))
-                            };
+                                move |metadata| inner.push_record(&metadata, record, None).pushed()
+                            })
+                            .map(move |push_record| {
+                                if push_record.is_full() {
+                                    let flush = inner.flush_batches(false).map_err(|err| {
+                                        warn!("fail to flush full batch, {}", err);
+                                    });
 
-                            inner.clone().client.handle().spawn(future);
-                        }
-                        Err(err) => {
-                            warn!("fail to create timeout, {}", err);
-                        }
-                    }
+                                    inner.client.runtime().spawn(flush);
+                                }
+
+                                pending.push(push_record);
+
+                                Loop::Continue((records, pending))
+                            }),
+                    )
                 }
+                None => Either::B(future::ok(Loop::Break(pending))),
+            }
+        });
 
-                push_record
+        pushed
+            .and_then(move |pending| {
+                trace!("flushing {} records", pending.len());
+
+                inner
+                    .flush_batches(true)
+                    .and_then(move |_| future::join_all(pending))
             })
             .static_boxed()
     }
@@ -202,8 +529,7 @@ where
         let topic_name = topic_name.to_owned();
         let inner = self.inner.clone();
         self.inner
-            .client
-            .metadata()
+            .wait_on_metadata(topic_name.clone())
             .and_then(move |metadata| {
                 if let Some(partitions) = metadata.topics().get(topic_name.as_str()) {
                     Ok(ProducerTopic {
@@ -229,9 +555,71 @@ where
     P: Partitioner,
     Self: 'static,
 {
-    fn push_record(&self, metadata: &Metadata, mut record: ProducerRecord<K::Item, V::Item>) -> PushRecord {
+    /// Wait for the metadata which contains the partitions of the topic.
+    ///
+    /// An unknown topic fails at once if `allow.auto.create.topics` is disabled,
+    /// otherwise its metadata is refreshed after the retry backoff until the brokers have created it,
+    /// up to the `max.block.ms`.
+    fn wait_on_metadata(&self, topic_name: String) -> StaticBoxFuture<Rc<Metadata>> {
+        let client = self.client.clone();
+        let allow_auto_create_topics = self.config.allow_auto_create_topics;
+        let retry_backoff = self.config.retry_backoff();
+        let max_block = self.config.max_block();
+        let deadline = self.client.runtime().now() + max_block;
+
+        future::loop_fn((), move |_| {
+            let client = client.clone();
+            let topic_name = topic_name.clone();
+
+            client
+                .topic_metadata_with_auto_creation(iter::once(topic_name.clone()), allow_auto_create_topics)
+                .and_then(move |metadata| {
+                    let available = metadata
+                        .topics()
+                        .get(topic_name.as_str())
+                        .map_or(false, |partitions| !partitions.is_empty());
+
+                    if available {
+                        Either::A(future::ok(Loop::Break(metadata)))
+                    } else if !allow_auto_create_topics {
+                        Either::A(future::err(
+                            ErrorKind::KafkaError(KafkaCode::UnknownTopicOrPartition).into(),
+                        ))
+                    } else if client.runtime().now() + retry_backoff > deadline {
+                        Either::A(future::err(
+                            ErrorKind::TimeoutError(format!(
+                                "topic {} not present in metadata after {:?}",
+                                topic_name, max_block
+                            )).into(),
+                        ))
+                    } else {
+                        debug!("wait for the topic {} to be created", topic_name);
+
+                        Either::B(
+                            client
+                                .runtime()
+                                .sleep(retry_backoff)
+                                .from_err()
+                                .map(|_| Loop::Continue(())),
+                        )
+                    }
+                })
+        }).static_boxed()
+    }
+
+    /// Push the record to the accumulator, its delivery result will be reported if it has an id.
+    fn push_record(
+        &self,
+        metadata: &Metadata,
+        mut record: ProducerRecord<K::Item, V::Item>,
+        id: Option<RecordId>,
+    ) -> PushRecord {
         trace!("sending record {:?}", record);
 
+        if self.closed.get() {
+            return PushRecord::new(future::err(ErrorKind::ProducerClosed.into()), false, false);
+        }
+
         if let Some(ref interceptors) = self.interceptors {
             let interceptors: &RefCell<ProducerInterceptors<K::Item, V::Item>> = interceptors.borrow();
 
@@ -253,9 +641,18 @@ where
             .partition(&topic_name, partition_id, key.as_ref(), value.as_ref(), metadata)
             .unwrap_or_default();
 
-        let key = key.and_then(|key| self.key_serializer.serialize(&topic_name, key).ok());
+        // a record failed to serialize must not be sent as a null key or value, which means a tombstone
+        let key = match key.map(|key| self.key_serializer.serialize(&topic_name, key)) {
+            Some(Ok(key)) => Some(key),
+            Some(Err(err)) => return PushRecord::new(future::err(err), false, false),
+            None => None,
+        };
 
-        let value = value.and_then(|value| self.value_serializer.serialize(&topic_name, value).ok());
+        let value = match value.map(|value| self.value_serializer.serialize(&topic_name, value)) {
+            Some(Ok(value)) => Some(value),
+            Some(Err(err)) => return PushRecord::new(future::err(err), false, false),
+            None => None,
+        };
 
         let tp = topic_partition!(topic_name, partition);
 
@@ -268,35 +665,476 @@ where
 
         trace!("use API version {} for {:?}", api_version, tp);
 
-        self.accumulator.push_record(tp, timestamp, key, value, api_version)
+        let size = record_size(api_version, key.as_ref(), value.as_ref());
+        let max_size = cmp::min(self.config.max_request_size, self.config.message_max_bytes);
+
+        if size > max_size {
+            warn!("record of {} bytes to {:?} is larger than the maximum size of {} bytes", size, tp, max_size);
+
+            return PushRecord::new(future::err(ErrorKind::RecordTooLarge(size, max_size).into()), false, false);
+        }
+
+        let delay = self.rate_limiter
+            .as_ref()
+            .and_then(|limiter| limiter.acquire(&tp.topic_name, 1, size));
+        let accumulator = self.accumulator.clone();
+        let push = move |tp| match id {
+            Some(id) => accumulator.push_reported_record(tp, timestamp, key, value, api_version, id),
+            None => accumulator.push_record(tp, timestamp, key, value, api_version),
+        };
+
+        if let Some(delay) = delay {
+            trace!("publish rate of {:?} was exceeded, delay the record for {:?}", tp, delay);
+
+            let partitioner = self.partitioner.clone();
+
+            return PushRecord::blocked(
+                self.client
+                    .runtime()
+                    .sleep(delay)
+                    .from_err()
+                    .and_then(move |_| append_record(&*partitioner, tp, push).pushed()),
+            );
+        }
+
+        append_record(&*self.partitioner, tp, push)
+    }
+}
+
+/// Append the record to the batch of its partition, and notify the partitioner of the batch changes.
+fn append_record<'a, P, F>(partitioner: &P, tp: TopicPartition<'a>, push: F) -> PushRecord
+where
+    P: Partitioner,
+    F: FnOnce(TopicPartition<'a>) -> PushRecord,
+{
+    let push_record = push(tp.clone());
+
+    if push_record.new_batch() {
+        partitioner.on_new_batch(&tp.topic_name, tp.partition_id);
+    }
+
+    if push_record.is_full() {
+        partitioner.batch_completed(&tp.topic_name, tp.partition_id);
+    }
+
+    push_record
+}
+
+impl<'a, K, V, P> Inner<'a, K, V, P>
+where
+    K: Serializer,
+    K::Item: Hash,
+    V: Serializer,
+    P: Partitioner,
+    Self: 'static,
+{
+    /// Flush the batch of the pushed record once it is full, or after the linger time for a new batch.
+    fn schedule_flush(inner: &Rc<Self>, push_record: &PushRecord) {
+        if push_record.is_full() {
+            let flush = inner.flush_batches(false).map_err(|err| {
+                warn!("fail to flush full batch, {}", err);
+            });
+
+            inner.client.runtime().spawn(flush);
+        }
+
+        if push_record.new_batch() {
+            let linger = inner.client.runtime().sleep(inner.config.linger());
+            let future = {
+                let inner = inner.clone();
+
+                linger
+                    .and_then(move |_| inner.flush_batches(false))
+                    .map(|_| ())
+                    .map_err(|e| warn!("flush batch error: {:?}", e))
+            };
+
+            inner.client.runtime().spawn(future);
+        }
     }
 
     /// Flush full or expired batches
+    ///
+    /// The ready batches of the partitions sharing the same leader are sent in a single request,
+    /// up to the `max.request.size`, and the batches failed in the request are retried individually.
     fn flush_batches(&self, force: bool) -> Flush {
         let client = self.client.clone();
+        let partitioner = self.partitioner.clone();
         let interceptor = self.interceptors.clone();
-        let acks = self.config.acks;
-        let ack_timeout = self.config.ack_timeout();
-        let retry_strategy = self.config.retry_strategy();
+        let config = self.config.clone();
+        let delivery_timeout = self.config.delivery_timeout();
+        let dispatcher = Dispatcher {
+            client: client.clone(),
+            accumulator: self.accumulator.clone(),
+            partitioner: self.partitioner.clone(),
+            delivered: self.delivered.clone(),
+            delivery_timeout,
+            retry_policies: self.config.retry_policies(),
+            max_request_size: self.config.max_request_size,
+        };
+
+        self.expire_batches();
 
         self.accumulator
             .batches(force)
-            .for_each(move |(tp, batch)| {
-                let sender = Sender::new(client.clone(), interceptor.clone(), acks, ack_timeout, tp, batch);
+            .ready()
+            .for_each(move |batches| {
+                let mut pending = Vec::new();
+                let mut resolved = Vec::new();
+
+                for (tp, batch) in batches {
+                    // the keyless records could be sent to another partition
+                    partitioner.batch_completed(&tp.topic_name, tp.partition_id);
+
+                    let buffered_bytes = batch.buffered_bytes();
+                    let deadline = *batch.create_time() + delivery_timeout;
+                    let sender = Sender::new(
+                        client.clone(),
+                        interceptor.clone(),
+                        config.topic_acks(&tp.topic_name),
+                        config.topic_ack_timeout(&tp.topic_name),
+                        tp.clone(),
+                        batch,
+                    );
+                    let sent = client.runtime().now();
+
+                    match sender {
+                        // the batch has been waiting for the in-flight batch of the partition until expired
+                        Ok(ref sender) if sent >= deadline => {
+                            let records = sender.records();
+
+                            sender.expire(delivery_timeout);
+
+                            dispatcher.accumulator.in_flight().completed(&tp);
+                            dispatcher.accumulator.memory().release(buffered_bytes);
+                            dispatcher.delivered.record(records, false);
+                        }
+                        Ok(sender) => pending.push(PendingBatch {
+                            records: sender.records(),
+                            tp,
+                            sender: Rc::new(sender),
+                            buffered_bytes,
+                            deadline,
+                            sent,
+                        }),
+                        Err(err) => {
+                            warn!("fail to create sender, {}", err);
+
+                            dispatcher.accumulator.in_flight().completed(&tp);
+                            dispatcher.accumulator.memory().release(buffered_bytes);
+
+                            resolved.push(err.into());
+                        }
+                    }
+                }
+
+                let dispatcher = dispatcher.clone();
+                let send_batches = client.metadata().then(move |metadata| {
+                    let metadata = metadata.ok();
+                    let requests = dispatcher.group_by_leader(metadata.as_ref().map(|metadata| &**metadata), pending);
+
+                    join_batches(
+                        requests
+                            .into_iter()
+                            .map(|(leader, batches)| dispatcher.send_batches(leader, batches))
+                            .collect(),
+                    )
+                });
+
+                resolved.push(send_batches.static_boxed());
+
+                join_batches(resolved)
+            })
+            .static_boxed()
+    }
+
+    /// Fail the records of the pending batches which have not been sent.
+    ///
+    /// Returns the number of the aborted records.
+    fn abort_batches(&self) -> usize {
+        self.fail_batches(self.accumulator.abort(), "abort", || ErrorKind::ProducerClosed.into())
+    }
+
+    /// Fail the records of the pending batches which have not been sent before the delivery timeout.
+    ///
+    /// Returns the number of the expired records.
+    fn expire_batches(&self) -> usize {
+        let delivery_timeout = self.config.delivery_timeout();
+        let expired = self.fail_batches(self.accumulator.expire(delivery_timeout), "expire", || {
+            ErrorKind::TimeoutError(format!("batch expired after {} ms", delivery_timeout.as_millis())).into()
+        });
+
+        self.delivered.record(expired, false);
+
+        expired
+    }
+
+    fn fail_batches<F>(&self, batches: Vec<(TopicPartition<'a>, ProducerBatch)>, action: &str, err: F) -> usize
+    where
+        F: Fn() -> Error,
+    {
+        let mut failed = 0;
+
+        for (tp, batch) in batches {
+            match batch.build() {
+                Ok((thunks, _)) => {
+                    warn!("{} {} records of {:?} which have not been sent", action, thunks.len(), tp);
 
-                match sender {
-                    Ok(sender) => Retry::spawn(retry_strategy.clone(), move || sender.send_batch())
-                        .from_err()
-                        .static_boxed(),
-                    Err(err) => {
-                        warn!("fail to create sender, {}", err);
+                    failed += thunks.len();
 
-                        err.into()
+                    for thunk in thunks {
+                        let err = err();
+
+                        if let Err(err) = thunk.fail(self.interceptors.clone(), &tp.topic_name, tp.partition_id, err) {
+                            warn!("fail to send error to thunk, {:?}", err);
+                        }
                     }
                 }
+                Err(err) => warn!("fail to {} batch of {:?}, {}", action, tp, err),
+            }
+        }
+
+        failed
+    }
+}
+
+/// The batch being sent, which holds the resources to be released once it was resolved.
+struct PendingBatch<'a, K, V>
+where
+    K: Hash,
+{
+    tp: TopicPartition<'a>,
+    sender: Rc<Sender<'a, K, V>>,
+    buffered_bytes: usize,
+    records: usize,
+    deadline: Instant,
+    sent: Instant,
+}
+
+/// Send the ready batches, grouped by the leaders of their partitions.
+struct Dispatcher<'a, P> {
+    client: KafkaClient<'a>,
+    accumulator: RecordAccumulator<'a>,
+    partitioner: Rc<P>,
+    delivered: Rc<Delivered>,
+    delivery_timeout: Duration,
+    retry_policies: RetryPolicies,
+    max_request_size: usize,
+}
+
+impl<'a, P> Clone for Dispatcher<'a, P> {
+    fn clone(&self) -> Self {
+        Dispatcher {
+            client: self.client.clone(),
+            accumulator: self.accumulator.clone(),
+            partitioner: self.partitioner.clone(),
+            delivered: self.delivered.clone(),
+            delivery_timeout: self.delivery_timeout,
+            retry_policies: self.retry_policies.clone(),
+            max_request_size: self.max_request_size,
+        }
+    }
+}
+
+impl<'a, P> Dispatcher<'a, P>
+where
+    P: Partitioner,
+    Self: 'static,
+{
+    /// Group the batches by the leaders of their partitions, and split the groups by the `max.request.size`.
+    ///
+    /// The batches whose leader is unknown are sent individually.
+    fn group_by_leader<K, V>(
+        &self,
+        metadata: Option<&Metadata>,
+        batches: Vec<PendingBatch<'a, K, V>>,
+    ) -> Vec<(Option<BrokerRef>, Vec<PendingBatch<'a, K, V>>)>
+    where
+        K: Hash,
+    {
+        let mut requests = Vec::new();
+        let mut leaders: HashMap<BrokerRef, Vec<(usize, Vec<PendingBatch<'a, K, V>>)>> = HashMap::new();
+
+        for batch in batches {
+            let leader = metadata.and_then(|metadata| metadata.leader_for(&batch.tp).map(Broker::as_ref));
+            let leader = match leader {
+                Some(leader) => leader,
+                None => {
+                    requests.push((None, vec![batch]));
+
+                    continue;
+                }
+            };
+
+            let size = batch.sender.size();
+            let groups = leaders.entry(leader).or_insert_with(Vec::new);
+            // the batches sent in a request share the acks, which could be overridden by their topics
+            let acks = (batch.sender.acks(), batch.sender.ack_timeout());
+            let group = groups
+                .iter()
+                .rposition(|&(_, ref group)| (group[0].sender.acks(), group[0].sender.ack_timeout()) == acks);
+
+            match group {
+                Some(index) if groups[index].0 + size <= self.max_request_size => {
+                    groups[index].0 += size;
+                    groups[index].1.push(batch);
+                }
+                _ => groups.push((size, vec![batch])),
+            }
+        }
+
+        for (leader, groups) in leaders {
+            for (_, group) in groups {
+                requests.push((Some(leader), group));
+            }
+        }
+
+        requests
+    }
+
+    /// Send the batches of the partitions led by the broker in a single request,
+    /// and retry the failed batches individually.
+    fn send_batches<K, V>(&self, leader: Option<BrokerRef>, batches: Vec<PendingBatch<'a, K, V>>) -> Flush
+    where
+        K: Hash,
+        Sender<'a, K, V>: 'static,
+    {
+        let leader = match leader {
+            Some(leader) if batches.len() > 1 => leader,
+            _ => {
+                return join_batches(
+                    batches
+                        .into_iter()
+                        .map(|batch| self.resolve_batch(batch, None))
+                        .collect(),
+                )
+            }
+        };
+
+        // the request is sent once, and the failed batches will be retried until their deadlines
+        let timeout = batches
+            .iter()
+            .map(|batch| batch.deadline - batch.sent)
+            .min()
+            .unwrap_or_default();
+        let request = batches
+            .iter()
+            .map(|batch| (batch.tp.clone(), batch.sender.message_set()))
+            .collect();
+        let acks = batches[0].sender.acks();
+        let ack_timeout = batches[0].sender.ack_timeout();
+
+        trace!("sending {} batches to broker #{} in a request", batches.len(), leader.index());
+
+        let dispatcher = self.clone();
+
+        self.client
+            .runtime()
+            .timeout(
+                self.client.produce_batches(leader, acks, ack_timeout, request),
+                timeout,
+            )
+            .then(move |res| {
+                let produced = res.unwrap_or_else(|err| {
+                    debug!("fail to send batches to broker #{}, {}", leader.index(), err);
+
+                    HashMap::new()
+                });
+
+                join_batches(
+                    batches
+                        .into_iter()
+                        .map(|batch| {
+                            let partition = produced
+                                .get(&*batch.tp.topic_name)
+                                .and_then(|partitions| {
+                                    partitions
+                                        .iter()
+                                        .find(|partition| partition.partition_id == batch.tp.partition_id)
+                                })
+                                .cloned();
+
+                            dispatcher.resolve_batch(batch, partition)
+                        })
+                        .collect(),
+                )
             })
             .static_boxed()
     }
+
+    /// Resolve the batch with its partition in the response of a shared request,
+    /// or send it individually with the retry policies.
+    fn resolve_batch<K, V>(&self, batch: PendingBatch<'a, K, V>, produced: Option<ProducedRecords>) -> Flush
+    where
+        K: Hash,
+        Sender<'a, K, V>: 'static,
+    {
+        let PendingBatch {
+            tp,
+            sender,
+            buffered_bytes,
+            records,
+            deadline,
+            sent,
+        } = batch;
+        let runtime = self.client.runtime().clone();
+        let now = runtime.now();
+
+        let send_batch = match produced {
+            Some(ref partition) if partition.error_code == KafkaCode::None => sender.complete(partition).static_boxed(),
+            Some(ref partition)
+                if partition.error_code != KafkaCode::MessageSizeTooLarge
+                    && ErrorClass::from_code(partition.error_code) == ErrorClass::Fatal =>
+            {
+                ErrorKind::KafkaError(partition.error_code).into()
+            }
+            _ if now >= deadline => ErrorKind::TimeoutError("batch expired".to_owned()).into(),
+            _ => self.client
+                .runtime()
+                .timeout(
+                    Sender::send_with_retry(sender.clone(), self.retry_policies.clone()),
+                    deadline - now,
+                )
+                .static_boxed(),
+        };
+
+        let accumulator = self.accumulator.clone();
+        let partitioner = self.partitioner.clone();
+        let delivered = self.delivered.clone();
+        let delivery_timeout = self.delivery_timeout;
+
+        send_batch
+            .then(move |res| {
+                let now = runtime.now();
+                let elapsed = now.duration_since(sent);
+
+                if let Err(ref err) = res {
+                    // the batch will not be retried after the delivery timeout
+                    if now >= deadline {
+                        sender.expire(delivery_timeout);
+                    } else {
+                        sender.fail(err);
+                    }
+                }
+
+                // the next batch of the partition could be sent after this batch was resolved
+                accumulator.in_flight().completed(&tp);
+                accumulator.memory().release(buffered_bytes);
+                partitioner.batch_sent(&tp.topic_name, tp.partition_id, elapsed, res.is_ok());
+                delivered.record(records, res.is_ok());
+
+                res
+            })
+            .static_boxed()
+    }
+}
+
+/// Wait until all the batches have been resolved, and fail with the error of the first failed batch.
+fn join_batches(batches: Vec<Flush>) -> Flush {
+    future::join_all(batches.into_iter().map(|batch| batch.then(Ok::<_, Error>)))
+        .and_then(|results| results.into_iter().collect::<Result<Vec<()>>>())
+        .map(|_| ())
+        .static_boxed()
 }
 
 struct Pending {
@@ -1,4 +1,5 @@
 use std::hash::Hash;
+use std::time::Duration;
 
 use client::{PartitionRecord, TopicRecord};
 use protocol::{Offset, PartitionId, Timestamp};
@@ -68,6 +69,24 @@ where
         }
     }
 
+    /// Creates a tombstone to be sent to a specified topic, with a key and a null value
+    ///
+    /// The tombstone marks the key as deleted in a compacted topic.
+    pub fn tombstone<S: AsRef<str>>(topic_name: S, key: K) -> Self {
+        ProducerRecord {
+            topic_name: topic_name.as_ref().to_owned(),
+            partition_id: None,
+            key: Some(key),
+            value: None,
+            timestamp: None,
+        }
+    }
+
+    /// Returns whether the record is a tombstone, with a key and a null value
+    pub fn is_tombstone(&self) -> bool {
+        self.key.is_some() && self.value.is_none()
+    }
+
     pub fn from_partition_record<S: AsRef<str>>(
         topic_name: S,
         partition_id: Option<PartitionId>,
@@ -120,4 +139,8 @@ pub struct RecordMetadata {
     pub serialized_key_size: usize,
     /// The size of the serialized, uncompressed value in bytes.
     pub serialized_value_size: usize,
+    /// The hash of the serialized key (or `None` if no key is specified).
+    pub serialized_key_hash: Option<u64>,
+    /// The elapsed time from the record was pushed to the producer until it was acknowledged.
+    pub latency: Duration,
 }
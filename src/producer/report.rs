@@ -0,0 +1,109 @@
+use std::cell::Cell;
+use std::fmt;
+use std::rc::Rc;
+
+use futures::unsync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+use errors::Result;
+use producer::RecordMetadata;
+
+/// The id of a record sent with `KafkaProducer::produce`, which identifies its delivery report.
+pub type RecordId = u64;
+
+/// The delivery result of a record sent with `KafkaProducer::produce`.
+#[derive(Debug)]
+pub struct DeliveryReport {
+    /// The id returned when the record was sent.
+    pub id: RecordId,
+    /// The metadata of the acknowledged record, or the error failed to send it.
+    pub result: Result<RecordMetadata>,
+}
+
+/// A stream of the delivery reports of the records, in the order of their acknowledgements.
+pub type DeliveryReports = UnboundedReceiver<DeliveryReport>;
+
+/// Report the delivery results of the records to a single stream,
+/// instead of resolving a future for each record.
+#[derive(Clone)]
+pub struct DeliveryReporter {
+    inner: Rc<Inner>,
+}
+
+struct Inner {
+    sender: UnboundedSender<DeliveryReport>,
+    next_id: Cell<RecordId>,
+}
+
+impl fmt::Debug for DeliveryReporter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DeliveryReporter")
+            .field("next_id", &self.inner.next_id.get())
+            .finish()
+    }
+}
+
+impl DeliveryReporter {
+    pub fn new() -> (Self, DeliveryReports) {
+        let (sender, receiver) = mpsc::unbounded();
+
+        (
+            DeliveryReporter {
+                inner: Rc::new(Inner {
+                    sender,
+                    next_id: Cell::new(0),
+                }),
+            },
+            receiver,
+        )
+    }
+
+    /// Allocate the id of a new record.
+    pub fn next_id(&self) -> RecordId {
+        let id = self.inner.next_id.get();
+
+        self.inner.next_id.set(id + 1);
+
+        id
+    }
+
+    /// Report the delivery result of the record, or return it if the stream has been dropped.
+    pub fn report(
+        &self,
+        id: RecordId,
+        result: Result<RecordMetadata>,
+    ) -> ::std::result::Result<(), Result<RecordMetadata>> {
+        self.inner
+            .sender
+            .unbounded_send(DeliveryReport { id, result })
+            .map_err(|err| err.into_inner().result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{Future, Stream};
+
+    use super::*;
+    use errors::ErrorKind;
+
+    #[test]
+    fn test_report_delivery() {
+        let (reporter, reports) = DeliveryReporter::new();
+
+        assert_eq!(reporter.next_id(), 0);
+        assert_eq!(reporter.next_id(), 1);
+
+        reporter.report(1, Err(ErrorKind::ProducerClosed.into())).unwrap();
+
+        let (report, reports) = reports.into_future().wait().ok().unwrap();
+
+        match report {
+            Some(DeliveryReport { id: 1, result: Err(_) }) => {}
+            report => panic!("unexpected delivery report: {:?}", report),
+        }
+
+        drop(reports);
+
+        assert!(reporter.report(0, Err(ErrorKind::ProducerClosed.into())).is_err());
+    }
+}
@@ -2,15 +2,17 @@ use std::borrow::{Borrow, Cow};
 use std::cell::RefCell;
 use std::hash::Hash;
 use std::rc::Rc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use futures::Future;
 
-use client::{Client, KafkaClient, StaticBoxFuture, ToStaticBoxFuture};
-use errors::Result;
+use client::{retry, Client, Cluster, KafkaClient, Metrics, ProducedRecords, RetryPolicies, StaticBoxFuture,
+             ToStaticBoxFuture};
+use compression::Compression;
+use errors::{Error, ErrorKind, Result};
 use network::TopicPartition;
 use producer::{Interceptors, ProducerBatch, Thunk};
-use protocol::{MessageSet, RequiredAcks};
+use protocol::{ApiVersion, KafkaCode, MessageSet, Record, RequiredAcks, ToMilliseconds};
 
 pub struct Sender<'a, K, V> {
     client: KafkaClient<'a>,
@@ -20,6 +22,27 @@ pub struct Sender<'a, K, V> {
     tp: TopicPartition<'a>,
     thunks: Rc<RefCell<Option<Vec<Thunk>>>>,
     message_set: MessageSet,
+    stats: Option<(Rc<Metrics>, BatchStats)>,
+    layout: BatchLayout,
+    /// The halves of the batch after it was split, which own the records of the batch.
+    splits: RefCell<Vec<Rc<Sender<'a, K, V>>>>,
+}
+
+/// The settings of batch used to rebuild the smaller batches when it was split.
+#[derive(Clone, Copy, Debug)]
+struct BatchLayout {
+    api_version: ApiVersion,
+    compression: Compression,
+    write_limit: usize,
+    create_time: Instant,
+}
+
+/// The statistics of batch recorded to the metrics.
+#[derive(Clone, Copy, Debug)]
+struct BatchStats {
+    records: usize,
+    bytes: usize,
+    fill_ratio: f64,
 }
 
 pub type SendBatch = StaticBoxFuture;
@@ -37,7 +60,43 @@ where
         tp: TopicPartition<'a>,
         batch: ProducerBatch,
     ) -> Result<Sender<'a, K, V>> {
+        let bytes = batch.buffered_bytes();
+        let fill_ratio = batch.fill_ratio();
+        let queue_time = client.runtime().now().duration_since(*batch.create_time());
+        let written_bytes = batch.written_bytes();
+        let allocated_bytes = batch.allocated_bytes();
+        let api_version = batch.api_version();
+        let layout = BatchLayout {
+            api_version,
+            compression: batch.compression(),
+            write_limit: batch.write_limit(),
+            create_time: *batch.create_time(),
+        };
         let (thunks, message_set) = batch.build()?;
+        let stats = client.metrics().map(|metrics| {
+            let topic_name: &str = tp.topic_name.borrow();
+            let encoded_bytes = message_set.size(api_version);
+
+            if written_bytes > 0 {
+                metrics.batch_drained(topic_name, queue_time, encoded_bytes as f64 / written_bytes as f64);
+            }
+
+            // the keys and values of an uncompressed batch are shared until the request is written
+            if layout.compression == Compression::None {
+                metrics.batch_encoded(topic_name, 0, bytes);
+            } else {
+                metrics.batch_encoded(topic_name, allocated_bytes + encoded_bytes, 0);
+            }
+
+            (
+                metrics,
+                BatchStats {
+                    records: thunks.len(),
+                    bytes,
+                    fill_ratio,
+                },
+            )
+        });
 
         Ok(Sender {
             client,
@@ -47,9 +106,159 @@ where
             tp,
             thunks: Rc::new(RefCell::new(Some(thunks))),
             message_set,
+            stats,
+            layout,
+            splits: RefCell::new(vec![]),
         })
     }
 
+    /// The number of the records in the batch, or zero after the batch has been resolved.
+    pub fn records(&self) -> usize {
+        self.thunks.borrow().as_ref().map_or(0, Vec::len)
+            + self.splits.borrow().iter().map(|split| split.records()).sum::<usize>()
+    }
+
+    /// Fail the records of the batch which have not been resolved before the delivery timeout.
+    pub fn expire(&self, delivery_timeout: Duration) {
+        if self.records() > 0 {
+            warn!("expire {} records of {:?} after {:?}", self.records(), self.tp, delivery_timeout);
+        }
+
+        self.fail_with(KafkaCode::RequestTimedOut, &|| {
+            ErrorKind::TimeoutError(format!("batch expired after {} ms", delivery_timeout.as_millis())).into()
+        })
+    }
+
+    /// Fail the records of the batch which have not been resolved with the error of the last attempt.
+    pub fn fail(&self, err: &Error) {
+        match *err.kind() {
+            ErrorKind::KafkaError(code) => self.fail_with(code, &|| ErrorKind::KafkaError(code).into()),
+            _ => self.fail_with(KafkaCode::Unknown, &|| format!("{}", err).into()),
+        }
+    }
+
+    fn fail_with(&self, error_code: KafkaCode, err: &Fn() -> Error) {
+        for split in self.splits.borrow().iter() {
+            split.fail_with(error_code, err);
+        }
+
+        if let Some(thunks) = (*self.thunks).borrow_mut().take() {
+            let topic_name: &str = self.tp.topic_name.borrow();
+
+            if let Some((ref metrics, _)) = self.stats {
+                metrics.produce_failed(topic_name, error_code);
+            }
+
+            for thunk in thunks {
+                if let Err(err) = thunk.fail(self.interceptors.clone(), topic_name, self.tp.partition_id, err()) {
+                    warn!("fail to send error to thunk, {:?}", err);
+                }
+            }
+        }
+    }
+
+    /// Split the batch into two halves which could be sent separately,
+    /// or `None` if the batch contains only one record.
+    ///
+    /// The records of the batch are handed over to the halves,
+    /// so the batch will be resolved once both the halves have been resolved.
+    pub fn split(&self) -> Result<Option<(Rc<Self>, Rc<Self>)>> {
+        if self.thunks.borrow().as_ref().map_or(0, Vec::len) < 2 {
+            return Ok(None);
+        }
+
+        let mut first = match (*self.thunks).borrow_mut().take() {
+            Some(thunks) => thunks,
+            None => return Ok(None),
+        };
+        let second = first.split_off(first.len() / 2);
+        let layout = self.layout;
+        let rebuild = |thunks: Vec<Thunk>| -> Result<Rc<Self>> {
+            let batch = ProducerBatch::rebuild(
+                layout.api_version,
+                layout.compression,
+                layout.write_limit,
+                layout.create_time,
+                thunks,
+            )?;
+
+            Ok(Rc::new(Sender::new(
+                self.client.clone(),
+                self.interceptors.clone(),
+                self.acks,
+                self.ack_timeout,
+                self.tp.clone(),
+                batch,
+            )?))
+        };
+        let first = rebuild(first)?;
+        let second = rebuild(second)?;
+
+        self.splits.borrow_mut().extend(vec![first.clone(), second.clone()]);
+
+        Ok(Some((first, second)))
+    }
+
+    /// Send the batch with the retry policies.
+    ///
+    /// If the broker rejected the batch as too large, the batch is split into halves which are sent in order,
+    /// down to the single records, and only the records individually exceeding the limit are failed.
+    pub fn send_with_retry(sender: Rc<Self>, retry_policies: RetryPolicies) -> SendBatch {
+        let reload_metadata = {
+            let client = sender.client.clone();
+            let topic_name = String::from(sender.tp.topic_name.borrow());
+
+            move || client.refresh_metadata(vec![topic_name.clone()]).map(|_| ()).static_boxed()
+        };
+        let send_batch = retry(sender.client.runtime().clone(), &retry_policies, reload_metadata, {
+            let sender = sender.clone();
+
+            move || sender.send_batch()
+        });
+
+        send_batch
+            .or_else(move |err| -> SendBatch {
+                if let ErrorKind::KafkaError(KafkaCode::MessageSizeTooLarge) = *err.kind() {
+                    match sender.split() {
+                        Ok(Some((first, second))) => {
+                            debug!(
+                                "batch of {:?} is too large, split into {} and {} records",
+                                sender.tp,
+                                first.records(),
+                                second.records()
+                            );
+
+                            return Self::send_with_retry(first.clone(), retry_policies.clone())
+                                .then(move |first_res| {
+                                    if let Err(ref err) = first_res {
+                                        first.fail(err);
+                                    }
+
+                                    Self::send_with_retry(second.clone(), retry_policies).then(move |second_res| {
+                                        if let Err(ref err) = second_res {
+                                            second.fail(err);
+                                        }
+
+                                        first_res.and(second_res)
+                                    })
+                                })
+                                .static_boxed();
+                        }
+                        Ok(None) => {}
+                        Err(split_err) => warn!("fail to split batch of {:?}, {}", sender.tp, split_err),
+                    }
+                }
+
+                err.into()
+            })
+            .static_boxed()
+    }
+
+    /// Send the batch and complete the records with the offsets and timestamps in the produce response.
+    ///
+    /// The batch is sent once to the leader in the cached metadata, the records are kept pending if the batch failed,
+    /// the request could be retried, and the records should be failed with `Sender::fail`
+    /// after the retries have been exhausted.
     pub fn send_batch(&self) -> SendBatch {
         trace!("sending batch to {:?}: {:?}", self.tp, self.message_set);
 
@@ -59,49 +268,87 @@ where
         let ack_timeout = self.ack_timeout;
         let message_set = Cow::Owned(self.message_set.clone());
         let thunks = self.thunks.clone();
-        let thunks1 = self.thunks.clone();
         let interceptors = self.interceptors.clone();
+        let stats = self.stats.clone();
+        let client = self.client.clone();
+        let tp = self.tp.clone();
 
         self.client
-            .produce_records(
-                acks,
-                ack_timeout,
-                topic_partition!(topic_name.clone(), partition_id),
-                vec![message_set],
-            )
-            .map(move |responses| {
-                responses.get(&topic_name).map(|partitions| {
-                    partitions
-                        .iter()
-                        .find(|partition| partition.partition_id == partition_id)
-                        .map(|partition| {
-                            if let Some(thunks) = (*thunks).borrow_mut().take() {
-                                for thunk in thunks {
-                                    match thunk.done(
-                                        interceptors.clone(),
-                                        &topic_name,
-                                        partition.partition_id,
-                                        partition.base_offset,
-                                        partition.error_code,
-                                    ) {
-                                        Ok(()) => {}
-                                        Err(metadata) => warn!("fail to send record metadata, {:?}", metadata),
-                                    }
-                                }
-                            }
-                        });
-                });
-            })
-            .map_err(move |err| {
-                if let Some(thunks) = (*thunks1).borrow_mut().take() {
-                    for thunk in thunks {
-                        if let Err(err) = thunk.fail(format!("{}", err).into()) {
-                            warn!("fail to send error to thunk, {:?}", err);
-                        }
-                    }
+            .metadata()
+            .and_then(move |metadata| {
+                match metadata.find_partition(&tp).and_then(|partition| partition.leader) {
+                    Some(leader) => client.produce_batches(leader, acks, ack_timeout, vec![(tp, message_set)]),
+                    None => ErrorKind::KafkaError(KafkaCode::NotLeaderForPartition).into(),
                 }
-                err
+            })
+            .and_then(move |responses| -> Result<()> {
+                let partition = match responses
+                    .get(&topic_name)
+                    .and_then(|partitions| partitions.iter().find(|partition| partition.partition_id == partition_id))
+                {
+                    Some(partition) => partition.clone(),
+                    None => bail!(ErrorKind::ProtocolError(format!(
+                        "partition {}:{} not found in the produce response",
+                        topic_name, partition_id
+                    ))),
+                };
+
+                Self::complete_with(&topic_name, &partition, &thunks, &interceptors, &stats)
             })
             .static_boxed()
     }
+
+    /// Complete the records with the partition of the produce response, e.g. the batch was sent within
+    /// a request of multiple partitions, or fail with the partition error to be retried.
+    pub fn complete(&self, partition: &ProducedRecords) -> Result<()> {
+        let topic_name: &str = self.tp.topic_name.borrow();
+
+        Self::complete_with(topic_name, partition, &self.thunks, &self.interceptors, &self.stats)
+    }
+
+    fn complete_with(
+        topic_name: &str,
+        partition: &ProducedRecords,
+        thunks: &RefCell<Option<Vec<Thunk>>>,
+        interceptors: &Interceptors<K, V>,
+        stats: &Option<(Rc<Metrics>, BatchStats)>,
+    ) -> Result<()> {
+        if partition.error_code != KafkaCode::None {
+            bail!(ErrorKind::KafkaError(partition.error_code))
+        }
+
+        if let Some((ref metrics, stats)) = *stats {
+            metrics.batch_produced(topic_name, stats.records, stats.bytes, stats.fill_ratio);
+        }
+
+        if let Some(thunks) = thunks.borrow_mut().take() {
+            for thunk in thunks {
+                if let Err(metadata) = thunk.done(interceptors.clone(), topic_name, partition) {
+                    warn!("fail to send record metadata, {:?}", metadata);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The batch to be sent within a request.
+    pub fn message_set(&self) -> Cow<'a, MessageSet> {
+        Cow::Owned(self.message_set.clone())
+    }
+
+    /// The encoded size of the batch.
+    pub fn size(&self) -> usize {
+        self.message_set.size(self.layout.api_version)
+    }
+
+    /// The number of acknowledgments required for the batch.
+    pub fn acks(&self) -> RequiredAcks {
+        self.acks
+    }
+
+    /// The maximum amount of time the server will wait for acknowledgments of the batch.
+    pub fn ack_timeout(&self) -> Duration {
+        self.ack_timeout
+    }
 }
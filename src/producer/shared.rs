@@ -0,0 +1,244 @@
+use std::hash::Hash;
+use std::sync::mpsc as std_mpsc;
+use std::thread;
+
+use futures::future;
+use futures::sync::{mpsc, oneshot};
+use futures::{Future, Stream};
+use tokio_core::reactor::{Core, Handle};
+
+use errors::{Error, ErrorKind, Result};
+use producer::{Producer, ProducerRecord, RecordMetadata};
+
+/// The future of a record sent by the `SharedProducer`, which could be waited in any thread.
+pub type SharedSendRecord = Box<Future<Item = RecordMetadata, Error = Error> + Send>;
+
+/// The future of the records sent by the `SharedProducer` in a batch, which could be waited in any thread.
+pub type SharedSendAll = Box<Future<Item = Vec<RecordMetadata>, Error = Error> + Send>;
+
+/// The future of flushing the records of the `SharedProducer`, which could be waited in any thread.
+pub type SharedFlush = Box<Future<Item = (), Error = Error> + Send>;
+
+enum Command<K, V>
+where
+    K: Hash,
+{
+    Send(ProducerRecord<K, V>, oneshot::Sender<Result<RecordMetadata>>),
+    SendAll(Vec<ProducerRecord<K, V>>, oneshot::Sender<Result<Vec<RecordMetadata>>>),
+    Flush(oneshot::Sender<Result<()>>),
+}
+
+/// A `Send + Sync` handle of a producer, which could be cloned and shared across the threads.
+///
+/// The producer is built and driven on a dedicated thread with its own event loop,
+/// and the records are passed to it through a channel.
+/// The thread exits once all the handles have been dropped, the pending sends have been completed
+/// and the remaining records have been flushed.
+pub struct SharedProducer<K, V>
+where
+    K: Hash,
+{
+    tx: mpsc::UnboundedSender<Command<K, V>>,
+}
+
+impl<K, V> Clone for SharedProducer<K, V>
+where
+    K: Hash,
+{
+    fn clone(&self) -> Self {
+        SharedProducer { tx: self.tx.clone() }
+    }
+}
+
+impl<K, V> SharedProducer<K, V>
+where
+    K: Hash + Send + 'static,
+    V: Send + 'static,
+{
+    /// Spawn a thread to run the producer, which is built with the handle of the thread's event loop.
+    ///
+    /// Returns once the producer has been built, or failed to be built.
+    pub fn spawn<F, P>(build: F) -> Result<Self>
+    where
+        F: FnOnce(Handle) -> Result<P> + Send + 'static,
+        P: Producer<'static, Key = K, Value = V> + 'static,
+    {
+        let (tx, rx) = mpsc::unbounded();
+        let (built_tx, built_rx) = std_mpsc::channel();
+
+        thread::Builder::new()
+            .name("kafka-producer".to_owned())
+            .spawn(move || {
+                let mut core = match Core::new() {
+                    Ok(core) => core,
+                    Err(err) => {
+                        drop(built_tx.send(Err(err.into())));
+
+                        return;
+                    }
+                };
+                let mut producer = match build(core.handle()) {
+                    Ok(producer) => producer,
+                    Err(err) => {
+                        drop(built_tx.send(Err(err)));
+
+                        return;
+                    }
+                };
+
+                drop(built_tx.send(Ok(())));
+
+                // keep the pending futures on the stream instead of spawning them,
+                // so the records waiting on the metadata are pushed before the final flush
+                let commands = rx.map(|command| -> Box<Future<Item = (), Error = ()>> {
+                    match command {
+                        Command::Send(record, reply) => Box::new(producer.send(record).then(move |res| {
+                            drop(reply.send(res));
+
+                            Ok(())
+                        })),
+                        Command::SendAll(records, reply) => Box::new(producer.send_all(records).then(move |res| {
+                            drop(reply.send(res));
+
+                            Ok(())
+                        })),
+                        Command::Flush(reply) => Box::new(producer.flush().then(move |res| {
+                            drop(reply.send(res));
+
+                            Ok(())
+                        })),
+                    }
+                });
+
+                drop(core.run(commands.buffer_unordered(usize::max_value()).for_each(|_| Ok(()))));
+
+                debug!("all the producer handles were dropped, flush the pending records");
+
+                if let Err(err) = core.run(producer.flush()) {
+                    warn!("fail to flush the pending records, {}", err);
+                }
+            })?;
+
+        built_rx
+            .recv()
+            .map_err(|_| Error::from(ErrorKind::Canceled("producer thread exited")))??;
+
+        Ok(SharedProducer { tx })
+    }
+
+    /// Send the record asynchronously, and return a future which will eventually contain its metadata.
+    pub fn send(&self, record: ProducerRecord<K, V>) -> SharedSendRecord {
+        let (tx, rx) = oneshot::channel();
+
+        if self.tx.unbounded_send(Command::Send(record, tx)).is_err() {
+            return Box::new(future::err(ErrorKind::Canceled("producer thread exited").into()));
+        }
+
+        Box::new(rx.then(|res| match res {
+            Ok(res) => res,
+            Err(_) => bail!(ErrorKind::Canceled("producer thread exited")),
+        }))
+    }
+
+    /// Send the records in a batch, and return a future which resolves when every record was acknowledged.
+    ///
+    /// The metadata of the records are returned in the same order as the records.
+    pub fn send_all(&self, records: Vec<ProducerRecord<K, V>>) -> SharedSendAll {
+        let (tx, rx) = oneshot::channel();
+
+        if self.tx.unbounded_send(Command::SendAll(records, tx)).is_err() {
+            return Box::new(future::err(ErrorKind::Canceled("producer thread exited").into()));
+        }
+
+        Box::new(rx.then(|res| match res {
+            Ok(res) => res,
+            Err(_) => bail!(ErrorKind::Canceled("producer thread exited")),
+        }))
+    }
+
+    /// Flush the accumulated records of the producer.
+    pub fn flush(&self) -> SharedFlush {
+        let (tx, rx) = oneshot::channel();
+
+        if self.tx.unbounded_send(Command::Flush(tx)).is_err() {
+            return Box::new(future::err(ErrorKind::Canceled("producer thread exited").into()));
+        }
+
+        Box::new(rx.then(|res| match res {
+            Ok(res) => res,
+            Err(_) => bail!(ErrorKind::Canceled("producer thread exited")),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use producer::{DefaultPartitioner, KafkaProducer};
+    use serialization::NoopSerializer;
+
+    type TestProducer = KafkaProducer<'static, NoopSerializer<String>, NoopSerializer<String>, DefaultPartitioner>;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn test_send_sync() {
+        assert_send_sync::<SharedProducer<String, String>>();
+    }
+
+    #[test]
+    fn test_build_failed() {
+        let res = SharedProducer::<String, String>::spawn(|_| -> Result<TestProducer> {
+            bail!(ErrorKind::ConfigError("missed handle"))
+        });
+
+        assert!(res.is_err());
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn test_drop_handle_with_pending_send() {
+        use std::time::Duration;
+
+        use MockCluster;
+        use serialization::StringSerializer;
+
+        let (servers_tx, servers_rx) = std_mpsc::channel();
+        let (done_tx, done_rx) = oneshot::channel::<()>();
+
+        let broker = thread::spawn(move || {
+            let mut core = Core::new().unwrap();
+            let cluster = MockCluster::new(1, &core.handle()).unwrap();
+
+            cluster.create_topic("topic", 1);
+
+            servers_tx.send(cluster.bootstrap_servers()).unwrap();
+
+            drop(core.run(done_rx));
+
+            cluster.messages("topic", 0).len()
+        });
+
+        let servers = servers_rx.recv().unwrap();
+
+        let producer = SharedProducer::spawn(move |handle| {
+            KafkaProducer::with_bootstrap_servers(servers, handle)
+                .with_key_serializer(StringSerializer::default())
+                .with_value_serializer(StringSerializer::default())
+                .with_default_partitioner()
+                .with_linger(Duration::from_millis(10))
+                .build()
+        }).unwrap();
+
+        // the metadata of the topic is not cached yet, the record is pushed after the handle has been dropped
+        let sent = producer.send(ProducerRecord::from_value("topic", "foo".to_owned()));
+
+        drop(producer);
+
+        assert_eq!(sent.wait().unwrap().offset, 0);
+
+        done_tx.send(()).unwrap();
+
+        assert_eq!(broker.join().unwrap(), 1);
+    }
+}
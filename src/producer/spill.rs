@@ -0,0 +1,339 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use bytes::Bytes;
+
+use protocol::{ApiVersion, PartitionId, Timestamp};
+
+/// A record spilled to the disk when the buffer memory is exhausted.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpilledRecord {
+    pub topic_name: String,
+    pub partition_id: PartitionId,
+    pub timestamp: Timestamp,
+    pub api_version: ApiVersion,
+    pub key: Option<Bytes>,
+    pub value: Option<Bytes>,
+}
+
+impl SpilledRecord {
+    fn encode(&self) -> io::Result<Vec<u8>> {
+        let mut buf = Vec::with_capacity(64);
+
+        buf.write_u16::<BigEndian>(self.topic_name.len() as u16)?;
+        buf.write_all(self.topic_name.as_bytes())?;
+        buf.write_i32::<BigEndian>(self.partition_id)?;
+        buf.write_i64::<BigEndian>(self.timestamp)?;
+        buf.write_i16::<BigEndian>(self.api_version)?;
+
+        for data in &[&self.key, &self.value] {
+            match **data {
+                Some(ref data) => {
+                    buf.write_i32::<BigEndian>(data.len() as i32)?;
+                    buf.write_all(data)?;
+                }
+                None => buf.write_i32::<BigEndian>(-1)?,
+            }
+        }
+
+        Ok(buf)
+    }
+
+    fn decode(buf: &[u8]) -> io::Result<Self> {
+        let mut buf = Cursor::new(buf);
+
+        let len = buf.read_u16::<BigEndian>()? as usize;
+        let mut topic_name = vec![0; len];
+        buf.read_exact(&mut topic_name)?;
+        let topic_name =
+            String::from_utf8(topic_name).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        let partition_id = buf.read_i32::<BigEndian>()?;
+        let timestamp = buf.read_i64::<BigEndian>()?;
+        let api_version = buf.read_i16::<BigEndian>()?;
+
+        let mut read_bytes = || -> io::Result<Option<Bytes>> {
+            let len = buf.read_i32::<BigEndian>()?;
+
+            if len < 0 {
+                Ok(None)
+            } else {
+                let mut data = vec![0; len as usize];
+                buf.read_exact(&mut data)?;
+                Ok(Some(Bytes::from(data)))
+            }
+        };
+
+        let key = read_bytes()?;
+        let value = read_bytes()?;
+
+        Ok(SpilledRecord {
+            topic_name,
+            partition_id,
+            timestamp,
+            api_version,
+            key,
+            value,
+        })
+    }
+}
+
+/// The length prefix of a tombstone entry, which is followed by the offset of a delivered record.
+const TOMBSTONE: u32 = ::std::u32::MAX;
+
+/// An append-only write-ahead file of the spilled records.
+///
+/// Each record is written as a length-prefixed entry, and will be read back
+/// when the buffer memory is available again.
+/// A tombstone entry is appended once a spilled record has been delivered,
+/// and the file is truncated after all the spilled records have been delivered.
+pub struct SpillFile {
+    path: PathBuf,
+    file: File,
+    pending: usize,
+}
+
+impl SpillFile {
+    /// Open or create the spill file.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<SpillFile> {
+        let path = path.as_ref().to_owned();
+        let file = OpenOptions::new().read(true).write(true).create(true).open(&path)?;
+
+        Ok(SpillFile { path, file, pending: 0 })
+    }
+
+    /// The path of the spill file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The number of spilled records not yet delivered.
+    pub fn pending(&self) -> usize {
+        self.pending
+    }
+
+    /// Read the records left by the previous producer (e.g. crashed during a broker outage)
+    /// with their offsets, which should be acknowledged after the records have been delivered.
+    ///
+    /// The records are kept in the file until acknowledged, so they will be recovered again
+    /// if the producer crashed before delivering them.
+    pub fn recover(&mut self) -> io::Result<Vec<(u64, SpilledRecord)>> {
+        let mut records = Vec::new();
+        let mut delivered = HashSet::new();
+
+        self.file.seek(SeekFrom::Start(0))?;
+
+        loop {
+            let offset = self.file.seek(SeekFrom::Current(0))?;
+            let len = match self.file.read_u32::<BigEndian>() {
+                Ok(len) => len,
+                Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(err) => return Err(err),
+            };
+
+            let entry = if len == TOMBSTONE {
+                self.file.read_u64::<BigEndian>().map(|offset| {
+                    delivered.insert(offset);
+                })
+            } else {
+                let mut buf = vec![0; len as usize];
+
+                self.file
+                    .read_exact(&mut buf)
+                    .and_then(|_| SpilledRecord::decode(&buf))
+                    .map(|record| records.push((offset, record)))
+            };
+
+            match entry {
+                Ok(()) => {}
+                Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                    warn!("skip the partial written entry in the spill file {:?}", self.path);
+
+                    break;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        records.retain(|&(offset, _)| !delivered.contains(&offset));
+
+        self.pending = records.len();
+
+        if self.pending == 0 {
+            self.truncate()?;
+        }
+
+        Ok(records)
+    }
+
+    /// Append the record to the file, return the offset to replay it.
+    pub fn append(&mut self, record: &SpilledRecord) -> io::Result<u64> {
+        let buf = record.encode()?;
+        let offset = self.file.seek(SeekFrom::End(0))?;
+
+        self.file.write_u32::<BigEndian>(buf.len() as u32)?;
+        self.file.write_all(&buf)?;
+        self.file.sync_data()?;
+
+        self.pending += 1;
+
+        Ok(offset)
+    }
+
+    /// Read back the record at the offset, it is kept in the file until acknowledged.
+    pub fn replay(&mut self, offset: u64) -> io::Result<SpilledRecord> {
+        self.file.seek(SeekFrom::Start(offset))?;
+
+        let len = self.file.read_u32::<BigEndian>()? as usize;
+        let mut buf = vec![0; len];
+
+        self.file.read_exact(&mut buf)?;
+
+        SpilledRecord::decode(&buf)
+    }
+
+    /// Acknowledge the record at the offset has been delivered,
+    /// and truncate the file once all the spilled records have been delivered.
+    pub fn ack(&mut self, offset: u64) -> io::Result<()> {
+        self.pending = self.pending.saturating_sub(1);
+
+        if self.pending == 0 {
+            return self.truncate();
+        }
+
+        self.file.seek(SeekFrom::End(0))?;
+        self.file.write_u32::<BigEndian>(TOMBSTONE)?;
+        self.file.write_u64::<BigEndian>(offset)?;
+        self.file.sync_data()
+    }
+
+    fn truncate(&mut self) -> io::Result<()> {
+        self.file.set_len(0)?;
+        self.file.sync_data()?;
+        self.file.seek(SeekFrom::Start(0))?;
+
+        Ok(())
+    }
+}
+
+/// The entry of a spilled record, which will be acknowledged once the record has been delivered.
+#[derive(Clone)]
+pub struct SpillEntry {
+    spill: Rc<RefCell<SpillFile>>,
+    offset: u64,
+}
+
+impl fmt::Debug for SpillEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SpillEntry").field("offset", &self.offset).finish()
+    }
+}
+
+impl SpillEntry {
+    pub fn new(spill: Rc<RefCell<SpillFile>>, offset: u64) -> Self {
+        SpillEntry { spill, offset }
+    }
+
+    /// The offset of the record in the spill file.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Acknowledge the record has been delivered, so it will not be recovered again.
+    pub fn ack(&self) {
+        let mut spill = self.spill.borrow_mut();
+
+        if let Err(err) = spill.ack(self.offset) {
+            warn!(
+                "fail to acknowledge the spilled record #{} in {:?}, {}",
+                self.offset,
+                spill.path(),
+                err
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::process;
+
+    use super::*;
+
+    #[test]
+    fn test_spill_file() {
+        let path = env::temp_dir().join(format!("tokio-kafka-spill-{}", process::id()));
+        let record = |offset: i64| SpilledRecord {
+            topic_name: "topic".to_owned(),
+            partition_id: 1,
+            timestamp: offset,
+            api_version: 2,
+            key: if offset % 2 == 0 { Some(Bytes::from("key")) } else { None },
+            value: Some(Bytes::from(format!("value{}", offset))),
+        };
+
+        {
+            let mut spill = SpillFile::open(&path).unwrap();
+
+            assert_eq!(spill.recover().unwrap(), vec![]);
+
+            let first = spill.append(&record(1)).unwrap();
+            let second = spill.append(&record(2)).unwrap();
+
+            assert_eq!(spill.pending(), 2);
+            assert_eq!(spill.replay(second).unwrap(), record(2));
+            assert_eq!(spill.replay(first).unwrap(), record(1));
+            assert_eq!(spill.pending(), 2);
+
+            spill.ack(second).unwrap();
+            spill.ack(first).unwrap();
+
+            assert_eq!(spill.pending(), 0);
+            assert_eq!(spill.recover().unwrap(), vec![]);
+
+            spill.append(&record(3)).unwrap();
+            spill.append(&record(4)).unwrap();
+        }
+
+        let recovered = {
+            let mut spill = SpillFile::open(&path).unwrap();
+            let recovered = spill.recover().unwrap();
+
+            assert_eq!(
+                recovered.iter().map(|&(_, ref record)| record.clone()).collect::<Vec<_>>(),
+                vec![record(3), record(4)]
+            );
+            assert_eq!(spill.pending(), 2);
+
+            // the spill file is dropped before the recovered records have been delivered
+            recovered
+        };
+
+        {
+            let mut spill = SpillFile::open(&path).unwrap();
+
+            assert_eq!(spill.recover().unwrap(), recovered);
+
+            spill.ack(recovered[0].0).unwrap();
+        }
+
+        {
+            let mut spill = SpillFile::open(&path).unwrap();
+
+            assert_eq!(spill.recover().unwrap(), vec![recovered[1].clone()]);
+            assert_eq!(spill.pending(), 1);
+
+            spill.ack(recovered[1].0).unwrap();
+
+            assert_eq!(spill.recover().unwrap(), vec![]);
+        }
+
+        ::std::fs::remove_file(&path).unwrap();
+    }
+}
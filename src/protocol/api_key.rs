@@ -114,6 +114,13 @@ impl ApiKeys {
             ApiKeys::DeleteGroups => "DeleteGroups",
         }
     }
+
+    /// Find the API by its name.
+    pub fn from_name(name: &str) -> Option<ApiKeys> {
+        (0..ApiKeys::DeleteGroups.key() + 1)
+            .map(ApiKeys::from)
+            .find(|api_key| api_key.name() == name)
+    }
 }
 
 impl From<ApiKey> for ApiKeys {
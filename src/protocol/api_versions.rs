@@ -1,3 +1,4 @@
+use std::cmp;
 use std::ops::Deref;
 use std::str::FromStr;
 use std::mem;
@@ -96,6 +97,9 @@ named!(
 );
 
 // api versions we support
+//
+// The requests are encoded and the responses are parsed up to the max version of each API,
+// any API not listed here is not implemented by the client.
 lazy_static! {
     pub static ref SUPPORTED_API_VERSIONS: UsableApiVersions = UsableApiVersions::new(vec![
         UsableApiVersion {
@@ -106,13 +110,18 @@ lazy_static! {
         UsableApiVersion {
             api_key: ApiKeys::Fetch,
             min_version: 0,
-            max_version: 3,
+            max_version: 11,
         },
         UsableApiVersion {
             api_key: ApiKeys::ListOffsets,
             min_version: 0,
             max_version: 1,
         },
+        UsableApiVersion {
+            api_key: ApiKeys::Metadata,
+            min_version: 0,
+            max_version: 5,
+        },
         UsableApiVersion {
             api_key: ApiKeys::OffsetCommit,
             min_version: 0,
@@ -123,11 +132,86 @@ lazy_static! {
             min_version: 0,
             max_version: 1,
         },
+        UsableApiVersion {
+            api_key: ApiKeys::GroupCoordinator,
+            min_version: 0,
+            max_version: 1,
+        },
         UsableApiVersion {
             api_key: ApiKeys::JoinGroup,
             min_version: 0,
             max_version: 1,
-        }
+        },
+        UsableApiVersion {
+            api_key: ApiKeys::Heartbeat,
+            min_version: 0,
+            max_version: 0,
+        },
+        UsableApiVersion {
+            api_key: ApiKeys::LeaveGroup,
+            min_version: 0,
+            max_version: 0,
+        },
+        UsableApiVersion {
+            api_key: ApiKeys::SyncGroup,
+            min_version: 0,
+            max_version: 0,
+        },
+        UsableApiVersion {
+            api_key: ApiKeys::DescribeGroups,
+            min_version: 0,
+            max_version: 0,
+        },
+        UsableApiVersion {
+            api_key: ApiKeys::ListGroups,
+            min_version: 0,
+            max_version: 0,
+        },
+        UsableApiVersion {
+            api_key: ApiKeys::SaslHandshake,
+            min_version: 0,
+            max_version: 1,
+        },
+        UsableApiVersion {
+            api_key: ApiKeys::ApiVersions,
+            min_version: 0,
+            max_version: 0,
+        },
+        UsableApiVersion {
+            api_key: ApiKeys::CreateTopics,
+            min_version: 0,
+            max_version: 0,
+        },
+        UsableApiVersion {
+            api_key: ApiKeys::DeleteTopics,
+            min_version: 0,
+            max_version: 0,
+        },
+        UsableApiVersion {
+            api_key: ApiKeys::DeleteRecords,
+            min_version: 0,
+            max_version: 0,
+        },
+        UsableApiVersion {
+            api_key: ApiKeys::OffsetForLeaderEpoch,
+            min_version: 0,
+            max_version: 2,
+        },
+        UsableApiVersion {
+            api_key: ApiKeys::DescribeConfigs,
+            min_version: 0,
+            max_version: 1,
+        },
+        UsableApiVersion {
+            api_key: ApiKeys::AlterConfigs,
+            min_version: 0,
+            max_version: 0,
+        },
+        UsableApiVersion {
+            api_key: ApiKeys::SaslAuthenticate,
+            min_version: 0,
+            max_version: 1,
+        },
     ]);
 }
 
@@ -190,6 +274,28 @@ impl UsableApiVersions {
     pub fn find(&self, api_key: ApiKeys) -> Option<&UsableApiVersion> {
         self.0.iter().find(|v| v.api_key == api_key)
     }
+
+    /// Negotiate the API version for the given ApiKeys
+    ///
+    /// Returns the highest version supported by both the broker and the client,
+    /// or `None` if the API is not supported by either of them.
+    pub fn negotiate(&self, api_key: ApiKeys) -> Option<ApiVersion> {
+        let (broker, supported) = match (self.find(api_key), SUPPORTED_API_VERSIONS.find(api_key)) {
+            (Some(broker), Some(supported)) => (broker, supported),
+            _ => return None,
+        };
+
+        if broker.min_version > supported.max_version || broker.max_version < supported.min_version {
+            warn!(
+                "no common version of API {:?}, broker supports {}..{}, client supports {}..{}",
+                api_key, broker.min_version, broker.max_version, supported.min_version, supported.max_version
+            );
+
+            None
+        } else {
+            Some(cmp::min(broker.max_version, supported.max_version))
+        }
+    }
 }
 
 impl ApiVersionsResponse {
@@ -268,6 +374,43 @@ mod tests {
         };
     }
 
+    #[test]
+    fn test_negotiate_api_versions() {
+        let api_versions = UsableApiVersions::new(vec![
+            UsableApiVersion {
+                api_key: ApiKeys::Produce,
+                min_version: 0,
+                max_version: 5,
+            },
+            UsableApiVersion {
+                api_key: ApiKeys::Fetch,
+                min_version: 0,
+                max_version: 2,
+            },
+            UsableApiVersion {
+                api_key: ApiKeys::Metadata,
+                min_version: 6,
+                max_version: 7,
+            },
+            UsableApiVersion {
+                api_key: ApiKeys::InitProducerId,
+                min_version: 0,
+                max_version: 0,
+            },
+        ]);
+
+        // clamp to the versions implemented by the client
+        assert_eq!(api_versions.negotiate(ApiKeys::Produce), Some(1));
+        // clamp to the versions supported by the broker
+        assert_eq!(api_versions.negotiate(ApiKeys::Fetch), Some(2));
+        // no common version
+        assert_eq!(api_versions.negotiate(ApiKeys::Metadata), None);
+        // not implemented by the client
+        assert_eq!(api_versions.negotiate(ApiKeys::InitProducerId), None);
+        // not supported by the broker
+        assert_eq!(api_versions.negotiate(ApiKeys::ListOffsets), None);
+    }
+
     #[test]
     fn test_parse_api_versions() {
         let api_versions = vec![
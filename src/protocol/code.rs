@@ -205,9 +205,45 @@ pub enum KafkaCode {
     FetchSessionIdNotFound = 70,
     /// The fetch session epoch is invalid
     InvalidFetchSessionEpoch = 71,
+    /// There is no listener on the leader broker that matches the listener
+    /// on which metadata request was processed.
+    ListenerNotFound = 72,
+    /// Topic deletion is disabled.
+    TopicDeletionDisabled = 73,
+    /// The leader epoch in the request is older than the epoch on the broker.
+    FencedLeaderEpoch = 74,
+    /// The leader epoch in the request is newer than the epoch on the broker.
+    UnknownLeaderEpoch = 75,
+    /// The requesting client does not support the compression type of given partition.
+    UnsupportedCompressionType = 76,
+    /// Broker epoch has changed.
+    StaleBrokerEpoch = 77,
+    /// The leader high watermark has not caught up from a recent leader election
+    /// so the offsets cannot be guaranteed to be monotonically increasing.
+    OffsetNotAvailable = 78,
+    /// The group member needs to have a valid member id before actually entering a consumer group.
+    MemberIdRequired = 79,
+    /// The preferred leader was not available.
+    PreferredLeaderNotAvailable = 80,
+    /// The consumer group has reached its max size.
+    GroupMaxSizeReached = 81,
+    /// The broker rejected this static consumer since another consumer
+    /// with the same group instance id has registered with a different member id.
+    FencedInstanceId = 82,
+    /// Eligible topic partition leaders are not available.
+    EligibleLeadersNotAvailable = 83,
+    /// Leader election not needed for topic partition.
+    ElectionNotNeeded = 84,
+    /// No partition reassignment is in progress.
+    NoReassignmentInProgress = 85,
+    /// Deleting offsets of a topic is forbidden while the consumer group is actively subscribed to it.
+    GroupSubscribedToTopic = 86,
+    /// This record has failed the validation on broker and hence will be rejected.
+    InvalidRecord = 87,
 }
 
 impl KafkaCode {
+    /// The transient error, the request may succeed if it is retried.
     pub fn is_retriable(&self) -> bool {
         match *self {
             KafkaCode::CorruptMessage
@@ -224,7 +260,52 @@ impl KafkaCode {
             | KafkaCode::NotController
             | KafkaCode::KafkaStorageError
             | KafkaCode::FetchSessionIdNotFound
-            | KafkaCode::InvalidFetchSessionEpoch => true,
+            | KafkaCode::InvalidFetchSessionEpoch
+            | KafkaCode::ListenerNotFound
+            | KafkaCode::FencedLeaderEpoch
+            | KafkaCode::UnknownLeaderEpoch
+            | KafkaCode::OffsetNotAvailable
+            | KafkaCode::PreferredLeaderNotAvailable
+            | KafkaCode::EligibleLeadersNotAvailable => true,
+            _ => false,
+        }
+    }
+
+    /// The error which leaves the client in an unrecoverable state,
+    /// e.g. the authentication or authorization failed, or the producer has been fenced.
+    ///
+    /// The client should be closed instead of sending the following requests.
+    pub fn is_fatal(&self) -> bool {
+        match *self {
+            KafkaCode::TopicAuthorizationFailed
+            | KafkaCode::GroupAuthorizationFailed
+            | KafkaCode::ClusterAuthorizationFailed
+            | KafkaCode::TransactionalIdAuthorizationFailed
+            | KafkaCode::DelegationTokenAuthorizationFailed
+            | KafkaCode::UnsupportedSaslMechanism
+            | KafkaCode::IllegalSaslState
+            | KafkaCode::SaslAuthenticationFailed
+            | KafkaCode::UnsupportedVersion
+            | KafkaCode::UnsupportedForMessageFormat
+            | KafkaCode::UnsupportedCompressionType
+            | KafkaCode::OutOfOrderSequenceNumber
+            | KafkaCode::InvalidProducerEpoch
+            | KafkaCode::InvalidProducerIdMapper
+            | KafkaCode::FencedInstanceId => true,
+            _ => false,
+        }
+    }
+
+    /// The error caused by the stale metadata, e.g. the partition leadership has been changed.
+    pub fn is_stale_metadata(&self) -> bool {
+        match *self {
+            KafkaCode::UnknownTopicOrPartition
+            | KafkaCode::LeaderNotAvailable
+            | KafkaCode::NotLeaderForPartition
+            | KafkaCode::KafkaStorageError
+            | KafkaCode::ListenerNotFound
+            | KafkaCode::FencedLeaderEpoch
+            | KafkaCode::UnknownLeaderEpoch => true,
             _ => false,
         }
     }
@@ -362,13 +443,48 @@ impl KafkaCode {
             KafkaCode::GroupIdNotFound => "The group id The group id does not exist was not found",
             KafkaCode::FetchSessionIdNotFound =>"The fetch session ID was not found",
             KafkaCode::InvalidFetchSessionEpoch => "The fetch session epoch is invalid",
+            KafkaCode::ListenerNotFound => {
+                "There is no listener on the leader broker that matches the listener on which metadata request was processed."
+            }
+            KafkaCode::TopicDeletionDisabled => "Topic deletion is disabled.",
+            KafkaCode::FencedLeaderEpoch => "The leader epoch in the request is older than the epoch on the broker.",
+            KafkaCode::UnknownLeaderEpoch => "The leader epoch in the request is newer than the epoch on the broker.",
+            KafkaCode::UnsupportedCompressionType => {
+                "The requesting client does not support the compression type of given partition."
+            }
+            KafkaCode::StaleBrokerEpoch => "Broker epoch has changed.",
+            KafkaCode::OffsetNotAvailable => {
+                "The leader high watermark has not caught up from a recent leader election so the offsets cannot be guaranteed to be monotonically increasing."
+            }
+            KafkaCode::MemberIdRequired => {
+                "The group member needs to have a valid member id before actually entering a consumer group."
+            }
+            KafkaCode::PreferredLeaderNotAvailable => "The preferred leader was not available.",
+            KafkaCode::GroupMaxSizeReached => "The consumer group has reached its max size.",
+            KafkaCode::FencedInstanceId => {
+                "The broker rejected this static consumer since another consumer with the same group.instance.id has registered with a different member.id."
+            }
+            KafkaCode::EligibleLeadersNotAvailable => "Eligible topic partition leaders are not available.",
+            KafkaCode::ElectionNotNeeded => "Leader election not needed for topic partition.",
+            KafkaCode::NoReassignmentInProgress => "No partition reassignment is in progress.",
+            KafkaCode::GroupSubscribedToTopic => {
+                "Deleting offsets of a topic is forbidden while the consumer group is actively subscribed to it."
+            }
+            KafkaCode::InvalidRecord => {
+                "This record has failed the validation on broker and hence will be rejected."
+            }
         }
     }
 }
 
 impl From<ErrorCode> for KafkaCode {
+    /// Convert the error code from the server, the unknown error codes are converted to `KafkaCode::Unknown`.
     fn from(v: ErrorCode) -> Self {
-        unsafe { mem::transmute(v) }
+        if v >= KafkaCode::Unknown as ErrorCode && v <= KafkaCode::InvalidRecord as ErrorCode {
+            unsafe { mem::transmute(v) }
+        } else {
+            KafkaCode::Unknown
+        }
     }
 }
 
@@ -377,3 +493,36 @@ impl fmt::Display for KafkaCode {
         write!(w, "{:?}", self)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_error_code() {
+        assert_eq!(KafkaCode::from(-1), KafkaCode::Unknown);
+        assert_eq!(KafkaCode::from(0), KafkaCode::None);
+        assert_eq!(KafkaCode::from(6), KafkaCode::NotLeaderForPartition);
+        assert_eq!(KafkaCode::from(87), KafkaCode::InvalidRecord);
+
+        // the error codes introduced by the newer brokers
+        assert_eq!(KafkaCode::from(88), KafkaCode::Unknown);
+        assert_eq!(KafkaCode::from(-2), KafkaCode::Unknown);
+    }
+
+    #[test]
+    fn test_classify_error_code() {
+        assert!(KafkaCode::NotLeaderForPartition.is_retriable());
+        assert!(KafkaCode::NotLeaderForPartition.is_stale_metadata());
+        assert!(!KafkaCode::NotLeaderForPartition.is_fatal());
+
+        assert!(KafkaCode::FencedLeaderEpoch.is_retriable());
+        assert!(KafkaCode::FencedLeaderEpoch.is_stale_metadata());
+
+        assert!(KafkaCode::TopicAuthorizationFailed.is_fatal());
+        assert!(!KafkaCode::TopicAuthorizationFailed.is_retriable());
+
+        assert!(!KafkaCode::OffsetOutOfRange.is_retriable());
+        assert!(!KafkaCode::OffsetOutOfRange.is_fatal());
+    }
+}
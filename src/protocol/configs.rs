@@ -0,0 +1,493 @@
+use std::borrow::Cow;
+
+use bytes::{BufMut, ByteOrder, BytesMut};
+
+use nom::{IResult, be_i16, be_i32, be_i8};
+
+use errors::Result;
+use protocol::{parse_opt_string, parse_response_header, parse_string, ApiVersion, Encodable, ErrorCode, ParseTag,
+               Record, RequestHeader, ResponseHeader, WriteExt, ARRAY_LEN_SIZE, STR_LEN_SIZE};
+
+const RESOURCE_TYPE_SIZE: usize = 1;
+const BOOL_SIZE: usize = 1;
+
+/// The type of resource which has configurations.
+pub type ResourceType = i8;
+
+pub const TOPIC_RESOURCE_TYPE: ResourceType = 2;
+pub const BROKER_RESOURCE_TYPE: ResourceType = 4;
+
+/// The source of configuration.
+pub type ConfigSourceType = i8;
+
+pub const UNKNOWN_CONFIG_SOURCE: ConfigSourceType = 0;
+pub const DEFAULT_CONFIG_SOURCE: ConfigSourceType = 5;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DescribeConfigsRequest<'a> {
+    pub header: RequestHeader<'a>,
+    /// An array of config resources to be returned.
+    pub resources: Vec<DescribeConfigsResource<'a>>,
+    /// Return the synonyms of configurations (since v1).
+    pub include_synonyms: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DescribeConfigsResource<'a> {
+    /// The type of resource.
+    pub resource_type: ResourceType,
+    /// The name of resource.
+    pub resource_name: Cow<'a, str>,
+    /// The configurations to be returned, `None` means all the configurations.
+    pub config_names: Option<Vec<Cow<'a, str>>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DescribeConfigsResponse {
+    pub header: ResponseHeader,
+    /// Duration in milliseconds for which the request was throttled due to quota violation.
+    pub throttle_time: i32,
+    pub resources: Vec<DescribeConfigsResourceStatus>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DescribeConfigsResourceStatus {
+    /// The error code
+    pub error_code: ErrorCode,
+    /// The error message
+    pub error_message: Option<String>,
+    /// The type of resource.
+    pub resource_type: ResourceType,
+    /// The name of resource.
+    pub resource_name: String,
+    pub config_entries: Vec<DescribeConfigsEntry>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DescribeConfigsEntry {
+    /// Configuration name
+    pub config_name: String,
+    /// Configuration value
+    pub config_value: Option<String>,
+    /// The configuration could not be altered.
+    pub read_only: bool,
+    /// The source of configuration, derived from the `is_default` flag in v0.
+    pub config_source: ConfigSourceType,
+    /// The configuration value should not be displayed.
+    pub is_sensitive: bool,
+    /// The synonyms of configuration (since v1).
+    pub config_synonyms: Vec<DescribeConfigsSynonym>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DescribeConfigsSynonym {
+    /// Configuration name
+    pub config_name: String,
+    /// Configuration value
+    pub config_value: Option<String>,
+    /// The source of configuration.
+    pub config_source: ConfigSourceType,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AlterConfigsRequest<'a> {
+    pub header: RequestHeader<'a>,
+    /// An array of resources to update with the provided configs.
+    pub resources: Vec<AlterConfigsResource<'a>>,
+    /// Validate the request, but do not alter the configurations.
+    pub validate_only: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AlterConfigsResource<'a> {
+    /// The type of resource.
+    pub resource_type: ResourceType,
+    /// The name of resource.
+    pub resource_name: Cow<'a, str>,
+    pub config_entries: Vec<AlterConfigsEntry<'a>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AlterConfigsEntry<'a> {
+    /// Configuration name
+    pub config_name: Cow<'a, str>,
+    /// Configuration value
+    pub config_value: Option<Cow<'a, str>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AlterConfigsResponse {
+    pub header: ResponseHeader,
+    /// Duration in milliseconds for which the request was throttled due to quota violation.
+    pub throttle_time: i32,
+    pub resources: Vec<AlterConfigsResourceStatus>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AlterConfigsResourceStatus {
+    /// The error code
+    pub error_code: ErrorCode,
+    /// The error message
+    pub error_message: Option<String>,
+    /// The type of resource.
+    pub resource_type: ResourceType,
+    /// The name of resource.
+    pub resource_name: String,
+}
+
+impl<'a> Record for DescribeConfigsRequest<'a> {
+    fn size(&self, api_version: ApiVersion) -> usize {
+        self.header.size(api_version) + self.resources.iter().fold(ARRAY_LEN_SIZE, |size, resource| {
+            size + RESOURCE_TYPE_SIZE + STR_LEN_SIZE + resource.resource_name.len()
+                + resource.config_names.as_ref().map_or(ARRAY_LEN_SIZE, |config_names| {
+                    config_names
+                        .iter()
+                        .fold(ARRAY_LEN_SIZE, |size, config_name| size + STR_LEN_SIZE + config_name.len())
+                })
+        }) + if api_version > 0 { BOOL_SIZE } else { 0 }
+    }
+}
+
+impl<'a> Encodable for DescribeConfigsRequest<'a> {
+    fn encode<T: ByteOrder>(&self, dst: &mut BytesMut) -> Result<()> {
+        let api_version = self.header.api_version;
+
+        self.header.encode::<T>(dst)?;
+
+        dst.put_array::<T, _, _>(&self.resources, |buf, resource| {
+            buf.put_i8(resource.resource_type);
+            buf.put_str::<T, _>(Some(resource.resource_name.as_ref()))?;
+
+            match resource.config_names {
+                Some(ref config_names) => buf.put_array::<T, _, _>(config_names, |buf, config_name| {
+                    buf.put_str::<T, _>(Some(config_name.as_ref()))
+                }),
+                None => {
+                    buf.put_i32::<T>(-1);
+                    Ok(())
+                }
+            }
+        })?;
+
+        if api_version > 0 {
+            dst.put_u8(self.include_synonyms as u8);
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> Record for AlterConfigsRequest<'a> {
+    fn size(&self, api_version: ApiVersion) -> usize {
+        self.header.size(api_version) + self.resources.iter().fold(ARRAY_LEN_SIZE, |size, resource| {
+            size + RESOURCE_TYPE_SIZE + STR_LEN_SIZE + resource.resource_name.len()
+                + resource.config_entries.iter().fold(ARRAY_LEN_SIZE, |size, entry| {
+                    size + STR_LEN_SIZE + entry.config_name.len() + STR_LEN_SIZE
+                        + entry.config_value.as_ref().map_or(0, |value| value.len())
+                })
+        }) + BOOL_SIZE
+    }
+}
+
+impl<'a> Encodable for AlterConfigsRequest<'a> {
+    fn encode<T: ByteOrder>(&self, dst: &mut BytesMut) -> Result<()> {
+        self.header.encode::<T>(dst)?;
+
+        dst.put_array::<T, _, _>(&self.resources, |buf, resource| {
+            buf.put_i8(resource.resource_type);
+            buf.put_str::<T, _>(Some(resource.resource_name.as_ref()))?;
+            buf.put_array::<T, _, _>(&resource.config_entries, |buf, entry| {
+                buf.put_str::<T, _>(Some(entry.config_name.as_ref()))?;
+                buf.put_str::<T, _>(entry.config_value.as_ref())
+            })
+        })?;
+
+        dst.put_u8(self.validate_only as u8);
+
+        Ok(())
+    }
+}
+
+impl DescribeConfigsResponse {
+    pub fn parse(buf: &[u8], api_version: ApiVersion) -> IResult<&[u8], Self> {
+        parse_describe_configs_response(buf, api_version)
+    }
+}
+
+named_args!(parse_describe_configs_response(api_version: ApiVersion)<DescribeConfigsResponse>,
+    parse_tag!(ParseTag::DescribeConfigsResponse,
+        do_parse!(
+            header: parse_response_header
+         >> throttle_time: be_i32
+         >> resources: length_count!(be_i32, apply!(parse_describe_configs_resource_status, api_version))
+         >> (DescribeConfigsResponse {
+                header,
+                throttle_time,
+                resources,
+            })
+        )
+    )
+);
+
+named_args!(parse_describe_configs_resource_status(api_version: ApiVersion)<DescribeConfigsResourceStatus>,
+    parse_tag!(ParseTag::DescribeConfigsResourceStatus,
+        do_parse!(
+            error_code: be_i16
+         >> error_message: parse_opt_string
+         >> resource_type: be_i8
+         >> resource_name: parse_string
+         >> config_entries: length_count!(be_i32, apply!(parse_describe_configs_entry, api_version))
+         >> (DescribeConfigsResourceStatus {
+                error_code,
+                error_message,
+                resource_type,
+                resource_name,
+                config_entries,
+            })
+        )
+    )
+);
+
+named_args!(parse_describe_configs_entry(api_version: ApiVersion)<DescribeConfigsEntry>,
+    parse_tag!(ParseTag::DescribeConfigsEntry,
+        do_parse!(
+            config_name: parse_string
+         >> config_value: parse_opt_string
+         >> read_only: be_i8
+         >> is_default: cond!(api_version == 0, be_i8)
+         >> config_source: cond!(api_version > 0, be_i8)
+         >> is_sensitive: be_i8
+         >> config_synonyms: cond!(api_version > 0, length_count!(be_i32, parse_describe_configs_synonym))
+         >> (DescribeConfigsEntry {
+                config_name,
+                config_value,
+                read_only: read_only != 0,
+                config_source: match (is_default, config_source) {
+                    (_, Some(config_source)) => config_source,
+                    (Some(is_default), _) if is_default != 0 => DEFAULT_CONFIG_SOURCE,
+                    _ => UNKNOWN_CONFIG_SOURCE,
+                },
+                is_sensitive: is_sensitive != 0,
+                config_synonyms: config_synonyms.unwrap_or_default(),
+            })
+        )
+    )
+);
+
+named!(
+    parse_describe_configs_synonym<DescribeConfigsSynonym>,
+    parse_tag!(
+        ParseTag::DescribeConfigsSynonym,
+        do_parse!(
+            config_name: parse_string >> config_value: parse_opt_string >> config_source: be_i8
+                >> (DescribeConfigsSynonym {
+                    config_name,
+                    config_value,
+                    config_source,
+                })
+        )
+    )
+);
+
+impl AlterConfigsResponse {
+    pub fn parse(buf: &[u8]) -> IResult<&[u8], Self> {
+        parse_alter_configs_response(buf)
+    }
+}
+
+named!(
+    parse_alter_configs_response<AlterConfigsResponse>,
+    parse_tag!(
+        ParseTag::AlterConfigsResponse,
+        do_parse!(
+            header: parse_response_header >> throttle_time: be_i32
+                >> resources: length_count!(be_i32, parse_alter_configs_resource_status)
+                >> (AlterConfigsResponse {
+                    header,
+                    throttle_time,
+                    resources,
+                })
+        )
+    )
+);
+
+named!(
+    parse_alter_configs_resource_status<AlterConfigsResourceStatus>,
+    parse_tag!(
+        ParseTag::AlterConfigsResourceStatus,
+        do_parse!(
+            error_code: be_i16 >> error_message: parse_opt_string >> resource_type: be_i8
+                >> resource_name: parse_string >> (AlterConfigsResourceStatus {
+                error_code,
+                error_message,
+                resource_type,
+                resource_name,
+            })
+        )
+    )
+);
+
+#[cfg(test)]
+mod tests {
+    use bytes::BigEndian;
+
+    use nom::IResult;
+
+    use protocol::*;
+
+    use super::*;
+
+    #[test]
+    fn test_encode_describe_configs_request() {
+        let req = DescribeConfigsRequest {
+            header: RequestHeader {
+                api_key: ApiKeys::DescribeConfigs as ApiKey,
+                api_version: 1,
+                correlation_id: 123,
+                client_id: Some("client".into()),
+            },
+            resources: vec![
+                DescribeConfigsResource {
+                    resource_type: TOPIC_RESOURCE_TYPE,
+                    resource_name: "topic".into(),
+                    config_names: Some(vec!["key".into()]),
+                },
+                DescribeConfigsResource {
+                    resource_type: BROKER_RESOURCE_TYPE,
+                    resource_name: "1".into(),
+                    config_names: None,
+                },
+            ],
+            include_synonyms: false,
+        };
+
+        let data = vec![
+            /* DescribeConfigsRequest
+             * RequestHeader */ 0, 32 /* api_key */, 0,
+            1 /* api_version */, 0, 0, 0, 123 /* correlation_id */, 0, 6, b'c', b'l', b'i', b'e', b'n',
+            b't' /* client_id */, /* resources: [DescribeConfigsResource] */ 0, 0, 0, 2, 2 /* resource_type */,
+            0, 5, b't', b'o', b'p', b'i', b'c' /* resource_name */, 0, 0, 0, 1, 0, 3, b'k', b'e',
+            b'y' /* config_names */, 4 /* resource_type */, 0, 1, b'1' /* resource_name */, 255, 255, 255,
+            255 /* config_names */, 0 /* include_synonyms */,
+        ];
+
+        let mut buf = BytesMut::with_capacity(128);
+
+        req.encode::<BigEndian>(&mut buf).unwrap();
+
+        assert_eq!(req.size(req.header.api_version), buf.len());
+
+        assert_eq!(&buf[..], &data[..]);
+    }
+
+    #[test]
+    fn test_parse_describe_configs_response() {
+        let response = DescribeConfigsResponse {
+            header: ResponseHeader { correlation_id: 123 },
+            throttle_time: 0,
+            resources: vec![
+                DescribeConfigsResourceStatus {
+                    error_code: 0,
+                    error_message: None,
+                    resource_type: TOPIC_RESOURCE_TYPE,
+                    resource_name: "topic".to_owned(),
+                    config_entries: vec![
+                        DescribeConfigsEntry {
+                            config_name: "key".to_owned(),
+                            config_value: Some("value".to_owned()),
+                            read_only: false,
+                            config_source: DEFAULT_CONFIG_SOURCE,
+                            is_sensitive: true,
+                            config_synonyms: vec![],
+                        },
+                    ],
+                },
+            ],
+        };
+
+        let data = vec![
+            /* ResponseHeader */ 0, 0, 0, 123 /* correlation_id */, 0, 0, 0, 0 /* throttle_time */,
+            /* resources: [DescribeConfigsResourceStatus] */ 0, 0, 0, 1, 0, 0 /* error_code */, 255,
+            255 /* error_message */, 2 /* resource_type */, 0, 5, b't', b'o', b'p', b'i',
+            b'c' /* resource_name */, /* config_entries: [DescribeConfigsEntry] */ 0, 0, 0, 1, 0, 3, b'k',
+            b'e', b'y' /* config_name */, 0, 5, b'v', b'a', b'l', b'u', b'e' /* config_value */,
+            0 /* read_only */, 1 /* is_default */, 1 /* is_sensitive */,
+        ];
+
+        assert_eq!(
+            parse_describe_configs_response(data.as_slice(), 0),
+            IResult::Done(&[][..], response)
+        );
+    }
+
+    #[test]
+    fn test_encode_alter_configs_request() {
+        let req = AlterConfigsRequest {
+            header: RequestHeader {
+                api_key: ApiKeys::AlterConfigs as ApiKey,
+                api_version: 0,
+                correlation_id: 123,
+                client_id: Some("client".into()),
+            },
+            resources: vec![
+                AlterConfigsResource {
+                    resource_type: TOPIC_RESOURCE_TYPE,
+                    resource_name: "topic".into(),
+                    config_entries: vec![
+                        AlterConfigsEntry {
+                            config_name: "key".into(),
+                            config_value: Some("value".into()),
+                        },
+                    ],
+                },
+            ],
+            validate_only: true,
+        };
+
+        let data = vec![
+            /* AlterConfigsRequest
+             * RequestHeader */ 0, 33 /* api_key */, 0,
+            0 /* api_version */, 0, 0, 0, 123 /* correlation_id */, 0, 6, b'c', b'l', b'i', b'e', b'n',
+            b't' /* client_id */, /* resources: [AlterConfigsResource] */ 0, 0, 0, 1, 2 /* resource_type */, 0,
+            5, b't', b'o', b'p', b'i', b'c' /* resource_name */, /* config_entries: [AlterConfigsEntry] */ 0, 0,
+            0, 1, 0, 3, b'k', b'e', b'y' /* config_name */, 0, 5, b'v', b'a', b'l', b'u',
+            b'e' /* config_value */, 1 /* validate_only */,
+        ];
+
+        let mut buf = BytesMut::with_capacity(128);
+
+        req.encode::<BigEndian>(&mut buf).unwrap();
+
+        assert_eq!(req.size(req.header.api_version), buf.len());
+
+        assert_eq!(&buf[..], &data[..]);
+    }
+
+    #[test]
+    fn test_parse_alter_configs_response() {
+        let response = AlterConfigsResponse {
+            header: ResponseHeader { correlation_id: 123 },
+            throttle_time: 1,
+            resources: vec![
+                AlterConfigsResourceStatus {
+                    error_code: 40,
+                    error_message: Some("invalid".to_owned()),
+                    resource_type: TOPIC_RESOURCE_TYPE,
+                    resource_name: "topic".to_owned(),
+                },
+            ],
+        };
+
+        let data = vec![
+            /* ResponseHeader */ 0, 0, 0, 123 /* correlation_id */, 0, 0, 0, 1 /* throttle_time */,
+            /* resources: [AlterConfigsResourceStatus] */ 0, 0, 0, 1, 0, 40 /* error_code */, 0, 7, b'i', b'n',
+            b'v', b'a', b'l', b'i', b'd' /* error_message */, 2 /* resource_type */, 0, 5, b't', b'o', b'p',
+            b'i', b'c' /* resource_name */,
+        ];
+
+        assert_eq!(
+            parse_alter_configs_response(data.as_slice()),
+            IResult::Done(&[][..], response)
+        );
+    }
+}
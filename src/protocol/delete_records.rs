@@ -0,0 +1,221 @@
+use std::borrow::Cow;
+
+use bytes::{BufMut, ByteOrder, BytesMut};
+
+use nom::{IResult, be_i16, be_i32, be_i64};
+
+use errors::Result;
+use protocol::{parse_response_header, parse_string, ApiVersion, Encodable, ErrorCode, Offset, ParseTag, PartitionId,
+               Record, RequestHeader, ResponseHeader, WriteExt, ARRAY_LEN_SIZE, OFFSET_SIZE, PARTITION_ID_SIZE,
+               STR_LEN_SIZE};
+
+const TIMEOUT_SIZE: usize = 4;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeleteRecordsRequest<'a> {
+    pub header: RequestHeader<'a>,
+    /// Topics to delete records.
+    pub topics: Vec<DeleteRecordsTopic<'a>>,
+    /// The time in ms to wait for the deletion to complete.
+    pub timeout: i32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeleteRecordsTopic<'a> {
+    /// The name of the topic.
+    pub topic_name: Cow<'a, str>,
+    /// Partitions to delete records.
+    pub partitions: Vec<DeleteRecordsPartition>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeleteRecordsPartition {
+    /// The id of the partition.
+    pub partition_id: PartitionId,
+    /// The offset before which the messages will be deleted, -1 means the high watermark.
+    pub offset: Offset,
+}
+
+impl<'a> Record for DeleteRecordsRequest<'a> {
+    fn size(&self, api_version: ApiVersion) -> usize {
+        self.header.size(api_version) + self.topics.iter().fold(ARRAY_LEN_SIZE, |size, topic| {
+            size + STR_LEN_SIZE + topic.topic_name.len() + ARRAY_LEN_SIZE
+                + topic.partitions.len() * (PARTITION_ID_SIZE + OFFSET_SIZE)
+        }) + TIMEOUT_SIZE
+    }
+}
+
+impl<'a> Encodable for DeleteRecordsRequest<'a> {
+    fn encode<T: ByteOrder>(&self, dst: &mut BytesMut) -> Result<()> {
+        self.header.encode::<T>(dst)?;
+
+        dst.put_array::<T, _, _>(&self.topics, |buf, topic| {
+            buf.put_str::<T, _>(Some(topic.topic_name.as_ref()))?;
+            buf.put_array::<T, _, _>(&topic.partitions, |buf, partition| {
+                buf.put_i32::<T>(partition.partition_id);
+                buf.put_i64::<T>(partition.offset);
+                Ok(())
+            })
+        })?;
+
+        dst.put_i32::<T>(self.timeout);
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeleteRecordsResponse {
+    pub header: ResponseHeader,
+    /// Duration in milliseconds for which the request was throttled due to quota violation.
+    pub throttle_time: i32,
+    pub topics: Vec<DeleteRecordsTopicStatus>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeleteRecordsTopicStatus {
+    /// The name of the topic.
+    pub topic_name: String,
+    pub partitions: Vec<DeleteRecordsPartitionStatus>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeleteRecordsPartitionStatus {
+    /// The id of the partition.
+    pub partition_id: PartitionId,
+    /// Smallest available offset of the partition after the deletion.
+    pub low_watermark: Offset,
+    /// The error code
+    pub error_code: ErrorCode,
+}
+
+impl DeleteRecordsResponse {
+    pub fn parse(buf: &[u8]) -> IResult<&[u8], Self> {
+        parse_delete_records_response(buf)
+    }
+}
+
+named!(
+    parse_delete_records_response<DeleteRecordsResponse>,
+    parse_tag!(
+        ParseTag::DeleteRecordsResponse,
+        do_parse!(
+            header: parse_response_header >> throttle_time: be_i32
+                >> topics: length_count!(be_i32, parse_delete_records_topic_status)
+                >> (DeleteRecordsResponse {
+                    header,
+                    throttle_time,
+                    topics,
+                })
+        )
+    )
+);
+
+named!(
+    parse_delete_records_topic_status<DeleteRecordsTopicStatus>,
+    parse_tag!(
+        ParseTag::DeleteRecordsTopicStatus,
+        do_parse!(
+            topic_name: parse_string >> partitions: length_count!(be_i32, parse_delete_records_partition_status)
+                >> (DeleteRecordsTopicStatus { topic_name, partitions })
+        )
+    )
+);
+
+named!(
+    parse_delete_records_partition_status<DeleteRecordsPartitionStatus>,
+    parse_tag!(
+        ParseTag::DeleteRecordsPartitionStatus,
+        do_parse!(
+            partition_id: be_i32 >> low_watermark: be_i64 >> error_code: be_i16 >> (DeleteRecordsPartitionStatus {
+                partition_id,
+                low_watermark,
+                error_code,
+            })
+        )
+    )
+);
+
+#[cfg(test)]
+mod tests {
+    use bytes::BigEndian;
+
+    use nom::IResult;
+
+    use protocol::*;
+
+    use super::*;
+
+    #[test]
+    fn test_encode_delete_records_request() {
+        let req = DeleteRecordsRequest {
+            header: RequestHeader {
+                api_key: ApiKeys::DeleteRecords as ApiKey,
+                api_version: 0,
+                correlation_id: 123,
+                client_id: Some("client".into()),
+            },
+            topics: vec![
+                DeleteRecordsTopic {
+                    topic_name: "topic".into(),
+                    partitions: vec![
+                        DeleteRecordsPartition {
+                            partition_id: 1,
+                            offset: 456,
+                        },
+                    ],
+                },
+            ],
+            timeout: 1000,
+        };
+
+        let data = vec![
+            /* DeleteRecordsRequest
+             * RequestHeader */ 0, 21 /* api_key */, 0,
+            0 /* api_version */, 0, 0, 0, 123 /* correlation_id */, 0, 6, b'c', b'l', b'i', b'e', b'n',
+            b't' /* client_id */, /* topics: [DeleteRecordsTopic] */ 0, 0, 0, 1, 0, 5, b't', b'o', b'p', b'i',
+            b'c' /* topic_name */, /* partitions: [DeleteRecordsPartition] */ 0, 0, 0, 1, 0, 0, 0,
+            1 /* partition_id */, 0, 0, 0, 0, 0, 0, 1, 200 /* offset */, 0, 0, 3, 232 /* timeout */,
+        ];
+
+        let mut buf = BytesMut::with_capacity(128);
+
+        req.encode::<BigEndian>(&mut buf).unwrap();
+
+        assert_eq!(req.size(req.header.api_version), buf.len());
+
+        assert_eq!(&buf[..], &data[..]);
+    }
+
+    #[test]
+    fn test_parse_delete_records_response() {
+        let response = DeleteRecordsResponse {
+            header: ResponseHeader { correlation_id: 123 },
+            throttle_time: 1,
+            topics: vec![
+                DeleteRecordsTopicStatus {
+                    topic_name: "topic".to_owned(),
+                    partitions: vec![
+                        DeleteRecordsPartitionStatus {
+                            partition_id: 1,
+                            low_watermark: 456,
+                            error_code: 0,
+                        },
+                    ],
+                },
+            ],
+        };
+
+        let data = vec![
+            /* ResponseHeader */ 0, 0, 0, 123 /* correlation_id */, 0, 0, 0, 1 /* throttle_time */,
+            /* topics: [DeleteRecordsTopicStatus] */ 0, 0, 0, 1, 0, 5, b't', b'o', b'p', b'i',
+            b'c' /* topic_name */, /* partitions: [DeleteRecordsPartitionStatus] */ 0, 0, 0, 1, 0, 0, 0,
+            1 /* partition_id */, 0, 0, 0, 0, 0, 0, 1, 200 /* low_watermark */, 0, 0 /* error_code */,
+        ];
+
+        assert_eq!(
+            parse_delete_records_response(data.as_slice()),
+            IResult::Done(&[][..], response)
+        );
+    }
+}
@@ -6,17 +6,39 @@ use bytes::{BufMut, ByteOrder, BytesMut};
 use nom::{IResult, be_i16, be_i32, be_i64};
 
 use errors::Result;
-use protocol::{parse_message_set, parse_response_header, parse_string, ApiVersion, Encodable, ErrorCode, MessageSet,
-               Offset, ParseTag, PartitionId, Record, ReplicaId, RequestHeader, ResponseHeader, WriteExt,
-               ARRAY_LEN_SIZE, OFFSET_SIZE, PARTITION_ID_SIZE, REPLICA_ID_SIZE, STR_LEN_SIZE};
+use protocol::{parse_message_set_with, parse_response_header, parse_string, ApiVersion, CrcPolicy, Encodable,
+               ErrorCode, LeaderEpoch, MessageSet, NodeId, Offset, ParseTag, PartitionId, Record, ReplicaId,
+               RequestHeader, ResponseHeader, WriteExt, ARRAY_LEN_SIZE, OFFSET_SIZE, PARTITION_ID_SIZE,
+               REPLICA_ID_SIZE, STR_LEN_SIZE};
 
 pub const DEFAULT_RESPONSE_MAX_BYTES: i32 = i32::MAX;
 
+/// The isolation level to return all the records, including the aborted transactional records.
+pub const READ_UNCOMMITTED: i8 = 0;
+
+/// The fetch request without a fetch session (KIP-227).
+pub const INVALID_SESSION_ID: FetchSessionId = 0;
+
+/// The epoch of the fetch request to create a new fetch session.
+pub const INITIAL_SESSION_EPOCH: FetchSessionEpoch = 0;
+
+/// The epoch of the fetch request to close the fetch session, or without a fetch session.
+pub const FINAL_SESSION_EPOCH: FetchSessionEpoch = -1;
+
 const MAX_WAIT_TIME: usize = 4;
 const MIN_BYTES_SIZE: usize = 4;
 const MAX_BYTES_SIZE: usize = 4;
+const ISOLATION_LEVEL_SIZE: usize = 1;
+const SESSION_ID_SIZE: usize = 4;
+const SESSION_EPOCH_SIZE: usize = 4;
 const REQUEST_OVERHEAD: usize = REPLICA_ID_SIZE + MAX_WAIT_TIME + MIN_BYTES_SIZE;
 const FETCH_OFFSET_SIZE: usize = OFFSET_SIZE;
+const LOG_START_OFFSET_SIZE: usize = OFFSET_SIZE;
+const LEADER_EPOCH_SIZE: usize = 4;
+
+pub type FetchSessionId = i32;
+
+pub type FetchSessionEpoch = i32;
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct FetchRequest<'a> {
@@ -37,8 +59,18 @@ pub struct FetchRequest<'a> {
     /// the fetch is larger than this value, the message will still be returned to ensure that
     /// progress can be made.
     pub max_bytes: i32,
+    /// The visibility of the transactional records (v4+).
+    pub isolation_level: i8,
+    /// The fetch session id (v7+).
+    pub session_id: FetchSessionId,
+    /// The epoch of the fetch session (v7+).
+    pub session_epoch: FetchSessionEpoch,
     /// Topics to fetch in the order provided.
     pub topics: Vec<FetchTopic<'a>>,
+    /// The partitions to remove from the fetch session (v7+).
+    pub forgotten_topics: Vec<ForgottenTopic<'a>>,
+    /// The rack of the consumer, which the broker uses to select the preferred read replica (v11+).
+    pub rack_id: Cow<'a, str>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -55,19 +87,44 @@ pub struct FetchPartition {
     pub partition_id: PartitionId,
     /// The offset to begin this fetch from.
     pub fetch_offset: Offset,
+    /// The current leader epoch known by the client, or -1 to skip fencing the stale leader (v9+).
+    pub current_leader_epoch: LeaderEpoch,
+    /// The earliest available offset of the follower replica, -1 for the consumers (v5+).
+    pub log_start_offset: Offset,
     /// The maximum bytes to include in the message set for this partition.
     pub max_bytes: i32,
 }
 
+#[derive(Clone, Debug, PartialEq)]
+pub struct ForgottenTopic<'a> {
+    /// The name of the topic.
+    pub topic_name: Cow<'a, str>,
+    /// Partitions to remove from the fetch session.
+    pub partitions: Vec<PartitionId>,
+}
+
 impl<'a> Record for FetchRequest<'a> {
     fn size(&self, api_version: ApiVersion) -> usize {
+        let partition_size = PARTITION_ID_SIZE + FETCH_OFFSET_SIZE + MAX_BYTES_SIZE
+            + if api_version > 8 { LEADER_EPOCH_SIZE } else { 0 }
+            + if api_version > 4 { LOG_START_OFFSET_SIZE } else { 0 };
+        let forgotten_topics_size = if api_version > 6 {
+            self.forgotten_topics.iter().fold(ARRAY_LEN_SIZE, |size, topic| {
+                size + STR_LEN_SIZE + topic.topic_name.len() + ARRAY_LEN_SIZE
+                    + topic.partitions.len() * PARTITION_ID_SIZE
+            })
+        } else {
+            0
+        };
+
         self.header.size(api_version) + REQUEST_OVERHEAD + if api_version > 2 { MAX_BYTES_SIZE } else { 0 }
+            + if api_version > 3 { ISOLATION_LEVEL_SIZE } else { 0 }
+            + if api_version > 6 { SESSION_ID_SIZE + SESSION_EPOCH_SIZE } else { 0 }
             + self.topics.iter().fold(ARRAY_LEN_SIZE, |size, topic| {
-                size + STR_LEN_SIZE + topic.topic_name.len()
-                    + topic.partitions.iter().fold(ARRAY_LEN_SIZE, |size, _| {
-                        size + PARTITION_ID_SIZE + FETCH_OFFSET_SIZE + MAX_BYTES_SIZE
-                    })
-            })
+                size + STR_LEN_SIZE + topic.topic_name.len() + ARRAY_LEN_SIZE
+                    + topic.partitions.len() * partition_size
+            }) + forgotten_topics_size
+            + if api_version > 10 { STR_LEN_SIZE + self.rack_id.len() } else { 0 }
     }
 }
 
@@ -83,15 +140,40 @@ impl<'a> Encodable for FetchRequest<'a> {
         if api_version > 2 {
             dst.put_i32::<T>(self.max_bytes);
         }
+        if api_version > 3 {
+            dst.put_i8(self.isolation_level);
+        }
+        if api_version > 6 {
+            dst.put_i32::<T>(self.session_id);
+            dst.put_i32::<T>(self.session_epoch);
+        }
         dst.put_array::<T, _, _>(&self.topics, |buf, topic| {
             buf.put_str::<T, _>(Some(topic.topic_name.as_ref()))?;
             buf.put_array::<T, _, _>(&topic.partitions, |buf, partition| {
                 buf.put_i32::<T>(partition.partition_id);
+                if api_version > 8 {
+                    buf.put_i32::<T>(partition.current_leader_epoch);
+                }
                 buf.put_i64::<T>(partition.fetch_offset);
+                if api_version > 4 {
+                    buf.put_i64::<T>(partition.log_start_offset);
+                }
                 buf.put_i32::<T>(partition.max_bytes);
                 Ok(())
             })
         })?;
+        if api_version > 6 {
+            dst.put_array::<T, _, _>(&self.forgotten_topics, |buf, topic| {
+                buf.put_str::<T, _>(Some(topic.topic_name.as_ref()))?;
+                buf.put_array::<T, _, _>(&topic.partitions, |buf, &partition_id| {
+                    buf.put_i32::<T>(partition_id);
+                    Ok(())
+                })
+            })?;
+        }
+        if api_version > 10 {
+            dst.put_str::<T, _>(Some(self.rack_id.as_ref()))?;
+        }
         Ok(())
     }
 }
@@ -102,6 +184,10 @@ pub struct FetchResponse {
     /// Duration in milliseconds for which the request was throttled due to
     /// quota violation.
     pub throttle_time: Option<i32>,
+    /// The top level error of the fetch session (v7+).
+    pub error_code: Option<ErrorCode>,
+    /// The fetch session id, or 0 if the broker doesn't create a fetch session (v7+).
+    pub session_id: Option<FetchSessionId>,
     pub topics: Vec<FetchTopicData>,
 }
 
@@ -119,35 +205,55 @@ pub struct FetchPartitionData {
     pub error_code: ErrorCode,
     /// The offset at the end of the log for this partition.
     pub high_watermark: Offset,
+    /// The offset below which all the transactions have been decided (v4+).
+    pub last_stable_offset: Option<Offset>,
+    /// The earliest available offset of the partition (v5+).
+    pub log_start_offset: Option<Offset>,
+    /// The aborted transactions in the fetched records (v4+).
+    pub aborted_transactions: Option<Vec<AbortedTransaction>>,
+    /// The replica preferred by the broker to fetch the partition from, or -1 for the leader (v11+).
+    pub preferred_read_replica: Option<NodeId>,
     pub message_set: MessageSet,
 }
 
+#[derive(Clone, Debug, PartialEq)]
+pub struct AbortedTransaction {
+    /// The producer id of the aborted transaction.
+    pub producer_id: i64,
+    /// The first offset of the aborted transaction.
+    pub first_offset: Offset,
+}
+
 impl FetchResponse {
-    pub fn parse(buf: &[u8], api_version: ApiVersion) -> IResult<&[u8], Self> {
-        parse_fetch_response(buf, api_version)
+    pub fn parse(buf: &[u8], api_version: ApiVersion, check_crcs: CrcPolicy) -> IResult<&[u8], Self> {
+        parse_fetch_response(buf, api_version, check_crcs)
     }
 }
 
-named_args!(parse_fetch_response(api_version: ApiVersion)<FetchResponse>,
+named_args!(parse_fetch_response(api_version: ApiVersion, check_crcs: CrcPolicy)<FetchResponse>,
     parse_tag!(ParseTag::FetchResponse,
         do_parse!(
             header: parse_response_header
          >> throttle_time: cond!(api_version > 0, be_i32)
-         >> topics: length_count!(be_i32, apply!(parse_fetch_topic_data, api_version))
+         >> error_code: cond!(api_version > 6, be_i16)
+         >> session_id: cond!(api_version > 6, be_i32)
+         >> topics: length_count!(be_i32, apply!(parse_fetch_topic_data, api_version, check_crcs))
          >> (FetchResponse {
                 header,
                 throttle_time,
+                error_code,
+                session_id,
                 topics,
             })
         )
     )
 );
 
-named_args!(parse_fetch_topic_data(api_version: ApiVersion)<FetchTopicData>,
+named_args!(parse_fetch_topic_data(api_version: ApiVersion, check_crcs: CrcPolicy)<FetchTopicData>,
     parse_tag!(ParseTag::FetchTopicData,
         do_parse!(
             topic_name: parse_string
-         >> partitions: length_count!(be_i32, apply!(parse_fetch_partition_data, api_version))
+         >> partitions: length_count!(be_i32, apply!(parse_fetch_partition_data, api_version, check_crcs))
          >> (FetchTopicData {
                 topic_name,
                 partitions,
@@ -156,23 +262,50 @@ named_args!(parse_fetch_topic_data(api_version: ApiVersion)<FetchTopicData>,
     )
 );
 
-named_args!(parse_fetch_partition_data(api_version: ApiVersion)<FetchPartitionData>,
+named_args!(parse_fetch_partition_data(api_version: ApiVersion, check_crcs: CrcPolicy)<FetchPartitionData>,
     parse_tag!(ParseTag::FetchPartitionData,
         do_parse!(
             partition_id: be_i32
          >> error_code: be_i16
          >> high_watermark: be_i64
-         >> message_set: length_value!(be_i32, apply!(parse_message_set, api_version))
+         >> last_stable_offset: cond!(api_version > 3, be_i64)
+         >> log_start_offset: cond!(api_version > 4, be_i64)
+         >> aborted_transactions: cond!(api_version > 3, parse_aborted_transactions)
+         >> preferred_read_replica: cond!(api_version > 10, be_i32)
+         >> message_set: length_value!(be_i32, apply!(parse_message_set_with, api_version, check_crcs))
          >> (FetchPartitionData {
                 partition_id,
                 error_code,
                 high_watermark,
+                last_stable_offset,
+                log_start_offset,
+                aborted_transactions: aborted_transactions.and_then(|aborted_transactions| aborted_transactions),
+                preferred_read_replica,
                 message_set,
             })
         )
     )
 );
 
+named!(parse_aborted_transactions<Option<Vec<AbortedTransaction>>>,
+    do_parse!(
+        n: be_i32
+     >> aborted_transactions: cond!(n >= 0, count!(parse_aborted_transaction, n as usize))
+     >> (aborted_transactions)
+    )
+);
+
+named!(parse_aborted_transaction<AbortedTransaction>,
+    do_parse!(
+        producer_id: be_i64
+     >> first_offset: be_i64
+     >> (AbortedTransaction {
+            producer_id,
+            first_offset,
+        })
+    )
+);
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -195,6 +328,9 @@ mod tests {
             max_wait_time: 3,
             min_bytes: 4,
             max_bytes: 0,
+            isolation_level: READ_UNCOMMITTED,
+            session_id: INVALID_SESSION_ID,
+            session_epoch: FINAL_SESSION_EPOCH,
             topics: vec![
                 FetchTopic {
                     topic_name: "topic".into(),
@@ -202,11 +338,15 @@ mod tests {
                         FetchPartition {
                             partition_id: 5,
                             fetch_offset: 6,
+                            current_leader_epoch: -1,
+                            log_start_offset: -1,
                             max_bytes: 7,
                         },
                     ],
                 },
             ],
+            forgotten_topics: vec![],
+            rack_id: "".into(),
         };
 
         let data = vec![
@@ -242,6 +382,9 @@ mod tests {
             max_wait_time: 3,
             min_bytes: 4,
             max_bytes: 1024,
+            isolation_level: READ_UNCOMMITTED,
+            session_id: INVALID_SESSION_ID,
+            session_epoch: FINAL_SESSION_EPOCH,
             topics: vec![
                 FetchTopic {
                     topic_name: "topic".into(),
@@ -249,11 +392,15 @@ mod tests {
                         FetchPartition {
                             partition_id: 5,
                             fetch_offset: 6,
+                            current_leader_epoch: -1,
+                            log_start_offset: -1,
                             max_bytes: 7,
                         },
                     ],
                 },
             ],
+            forgotten_topics: vec![],
+            rack_id: "".into(),
         };
 
         let data = vec![
@@ -281,6 +428,8 @@ mod tests {
         let response = FetchResponse {
             header: ResponseHeader { correlation_id: 123 },
             throttle_time: None,
+            error_code: None,
+            session_id: None,
             topics: vec![
                 FetchTopicData {
                     topic_name: "topic".to_owned(),
@@ -289,6 +438,10 @@ mod tests {
                             partition_id: 1,
                             error_code: 2,
                             high_watermark: 3,
+                            last_stable_offset: None,
+                            log_start_offset: None,
+                            aborted_transactions: None,
+                            preferred_read_replica: None,
                             message_set: MessageSet {
                                 messages: vec![
                                     Message {
@@ -297,6 +450,7 @@ mod tests {
                                         key: Some(Bytes::from(&b"key"[..])),
                                         value: Some(Bytes::from(&b"value"[..])),
                                         timestamp: None,
+                                        leader_epoch: None,
                                     },
                                 ],
                             },
@@ -319,7 +473,7 @@ mod tests {
             0, 5, 118, 97, 108, 117, 101 /* value */,
         ];
 
-        let res = parse_fetch_response(&data[..], 0);
+        let res = parse_fetch_response(&data[..], 0, CrcPolicy::Fail);
 
         display_parse_error::<_>(&data[..], res.clone());
 
@@ -331,6 +485,8 @@ mod tests {
         let response = FetchResponse {
             header: ResponseHeader { correlation_id: 123 },
             throttle_time: Some(1),
+            error_code: None,
+            session_id: None,
             topics: vec![
                 FetchTopicData {
                     topic_name: "topic".to_owned(),
@@ -339,6 +495,10 @@ mod tests {
                             partition_id: 1,
                             error_code: 2,
                             high_watermark: 3,
+                            last_stable_offset: None,
+                            log_start_offset: None,
+                            aborted_transactions: None,
+                            preferred_read_replica: None,
                             message_set: MessageSet {
                                 messages: vec![
                                     Message {
@@ -347,6 +507,7 @@ mod tests {
                                         key: Some(Bytes::from(&b"key"[..])),
                                         value: Some(Bytes::from(&b"value"[..])),
                                         timestamp: Some(MessageTimestamp::LogAppendTime(456)),
+                                        leader_epoch: None,
                                     },
                                 ],
                             },
@@ -367,7 +528,218 @@ mod tests {
             117, 101 /* value */,
         ];
 
-        let res = parse_fetch_response(&data[..], 1);
+        let res = parse_fetch_response(&data[..], 1, CrcPolicy::Fail);
+
+        display_parse_error::<_>(&data[..], res.clone());
+
+        assert_eq!(res, IResult::Done(&[][..], response));
+    }
+
+    #[test]
+    fn encode_fetch_request_v7() {
+        let request = FetchRequest {
+            header: RequestHeader {
+                api_key: ApiKeys::Fetch as ApiKey,
+                api_version: 7,
+                correlation_id: 123,
+                client_id: Some("client".into()),
+            },
+            replica_id: -1,
+            max_wait_time: 3,
+            min_bytes: 4,
+            max_bytes: 1024,
+            isolation_level: READ_UNCOMMITTED,
+            session_id: 8,
+            session_epoch: 9,
+            topics: vec![
+                FetchTopic {
+                    topic_name: "topic".into(),
+                    partitions: vec![
+                        FetchPartition {
+                            partition_id: 5,
+                            fetch_offset: 6,
+                            current_leader_epoch: -1,
+                            log_start_offset: -1,
+                            max_bytes: 7,
+                        },
+                    ],
+                },
+            ],
+            forgotten_topics: vec![
+                ForgottenTopic {
+                    topic_name: "topic".into(),
+                    partitions: vec![10],
+                },
+            ],
+            rack_id: "".into(),
+        };
+
+        let data = vec![
+            /* FetchRequest
+             * RequestHeader */ 0, 1 /* api_key */, 0, 7 /* api_version */,
+            0, 0, 0, 123 /* correlation_id */, 0, 6, 99, 108, 105, 101, 110, 116 /* client_id */, 255, 255, 255,
+            255 /* replica_id */, 0, 0, 0, 3 /* max_wait_time */, 0, 0, 0, 4 /* min_bytes */, 0, 0, 4,
+            0 /* max_bytes */, 0 /* isolation_level */, 0, 0, 0, 8 /* session_id */, 0, 0, 0,
+            9 /* session_epoch */, /* topics: [FetchTopicData] */ 0, 0, 0, 1, /* FetchTopicData */ 0, 5,
+            116, 111, 112, 105, 99 /* topic_name */, /* partitions: [FetchPartitionData] */ 0, 0, 0, 1,
+            /* FetchPartitionData */ 0, 0, 0, 5 /* partition */, 0, 0, 0, 0, 0, 0, 0,
+            6 /* fetch_offset */, 255, 255, 255, 255, 255, 255, 255, 255 /* log_start_offset */, 0, 0, 0,
+            7 /* max_bytes */, /* forgotten_topics: [ForgottenTopic] */ 0, 0, 0, 1, 0, 5, 116, 111, 112, 105,
+            99 /* topic_name */, 0, 0, 0, 1, 0, 0, 0, 10 /* partitions */,
+        ];
+
+        let mut buf = BytesMut::with_capacity(128);
+
+        request.encode::<BigEndian>(&mut buf).unwrap();
+
+        assert_eq!(request.size(request.header.api_version), buf.len());
+
+        assert_eq!(&buf[..], &data[..]);
+    }
+
+    #[test]
+    fn parse_fetch_response_v7() {
+        let response = FetchResponse {
+            header: ResponseHeader { correlation_id: 123 },
+            throttle_time: Some(1),
+            error_code: Some(0),
+            session_id: Some(8),
+            topics: vec![
+                FetchTopicData {
+                    topic_name: "topic".to_owned(),
+                    partitions: vec![
+                        FetchPartitionData {
+                            partition_id: 1,
+                            error_code: 2,
+                            high_watermark: 3,
+                            last_stable_offset: Some(4),
+                            log_start_offset: Some(5),
+                            aborted_transactions: Some(vec![
+                                AbortedTransaction {
+                                    producer_id: 6,
+                                    first_offset: 7,
+                                },
+                            ]),
+                            preferred_read_replica: None,
+                            message_set: MessageSet { messages: vec![] },
+                        },
+                    ],
+                },
+            ],
+        };
+
+        let data = vec![
+            /* ResponseHeader */ 0, 0, 0, 123 /* correlation_id */, 0, 0, 0, 1 /* throttle_time */, 0,
+            0 /* error_code */, 0, 0, 0, 8 /* session_id */, /* topics: [TopicData] */ 0, 0, 0, 1, 0, 5,
+            b't', b'o', b'p', b'i', b'c' /* topic_name */, /* partitions: [PartitionData] */ 0, 0, 0, 1, 0,
+            0, 0, 1 /* partition */, 0, 2 /* error_code */, 0, 0, 0, 0, 0, 0, 0,
+            3 /* highwater_mark_offset */, 0, 0, 0, 0, 0, 0, 0, 4 /* last_stable_offset */, 0, 0, 0, 0, 0,
+            0, 0, 5 /* log_start_offset */, /* aborted_transactions */ 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0,
+            6 /* producer_id */, 0, 0, 0, 0, 0, 0, 0, 7 /* first_offset */, /* MessageSet */ 0, 0, 0,
+            0 /* size */,
+        ];
+
+        let res = parse_fetch_response(&data[..], 7, CrcPolicy::Fail);
+
+        display_parse_error::<_>(&data[..], res.clone());
+
+        assert_eq!(res, IResult::Done(&[][..], response));
+    }
+
+    #[test]
+    fn encode_fetch_request_v11() {
+        let request = FetchRequest {
+            header: RequestHeader {
+                api_key: ApiKeys::Fetch as ApiKey,
+                api_version: 11,
+                correlation_id: 123,
+                client_id: Some("client".into()),
+            },
+            replica_id: -1,
+            max_wait_time: 3,
+            min_bytes: 4,
+            max_bytes: 1024,
+            isolation_level: READ_UNCOMMITTED,
+            session_id: 8,
+            session_epoch: 9,
+            topics: vec![
+                FetchTopic {
+                    topic_name: "topic".into(),
+                    partitions: vec![
+                        FetchPartition {
+                            partition_id: 5,
+                            current_leader_epoch: 11,
+                            fetch_offset: 6,
+                            log_start_offset: -1,
+                            max_bytes: 7,
+                        },
+                    ],
+                },
+            ],
+            forgotten_topics: vec![],
+            rack_id: "rack".into(),
+        };
+
+        let data = vec![
+            /* FetchRequest
+             * RequestHeader */ 0, 1 /* api_key */, 0, 11 /* api_version */,
+            0, 0, 0, 123 /* correlation_id */, 0, 6, 99, 108, 105, 101, 110, 116 /* client_id */, 255, 255, 255,
+            255 /* replica_id */, 0, 0, 0, 3 /* max_wait_time */, 0, 0, 0, 4 /* min_bytes */, 0, 0, 4,
+            0 /* max_bytes */, 0 /* isolation_level */, 0, 0, 0, 8 /* session_id */, 0, 0, 0,
+            9 /* session_epoch */, /* topics: [FetchTopicData] */ 0, 0, 0, 1, /* FetchTopicData */ 0, 5,
+            116, 111, 112, 105, 99 /* topic_name */, /* partitions: [FetchPartitionData] */ 0, 0, 0, 1,
+            /* FetchPartitionData */ 0, 0, 0, 5 /* partition */, 0, 0, 0, 11 /* current_leader_epoch */, 0, 0,
+            0, 0, 0, 0, 0, 6 /* fetch_offset */, 255, 255, 255, 255, 255, 255, 255, 255 /* log_start_offset */,
+            0, 0, 0, 7 /* max_bytes */, /* forgotten_topics: [ForgottenTopic] */ 0, 0, 0, 0, 0, 4, b'r', b'a',
+            b'c', b'k' /* rack_id */,
+        ];
+
+        let mut buf = BytesMut::with_capacity(128);
+
+        request.encode::<BigEndian>(&mut buf).unwrap();
+
+        assert_eq!(request.size(request.header.api_version), buf.len());
+
+        assert_eq!(&buf[..], &data[..]);
+    }
+
+    #[test]
+    fn parse_fetch_response_v11() {
+        let response = FetchResponse {
+            header: ResponseHeader { correlation_id: 123 },
+            throttle_time: Some(1),
+            error_code: Some(0),
+            session_id: Some(8),
+            topics: vec![
+                FetchTopicData {
+                    topic_name: "topic".to_owned(),
+                    partitions: vec![
+                        FetchPartitionData {
+                            partition_id: 1,
+                            error_code: 0,
+                            high_watermark: 3,
+                            last_stable_offset: Some(4),
+                            log_start_offset: Some(5),
+                            aborted_transactions: None,
+                            preferred_read_replica: Some(2),
+                            message_set: MessageSet { messages: vec![] },
+                        },
+                    ],
+                },
+            ],
+        };
+
+        let data = vec![
+            /* ResponseHeader */ 0, 0, 0, 123 /* correlation_id */, 0, 0, 0, 1 /* throttle_time */, 0,
+            0 /* error_code */, 0, 0, 0, 8 /* session_id */, /* topics: [TopicData] */ 0, 0, 0, 1, 0, 5,
+            b't', b'o', b'p', b'i', b'c' /* topic_name */, /* partitions: [PartitionData] */ 0, 0, 0, 1, 0,
+            0, 0, 1 /* partition */, 0, 0 /* error_code */, 0, 0, 0, 0, 0, 0, 0,
+            3 /* highwater_mark_offset */, 0, 0, 0, 0, 0, 0, 0, 4 /* last_stable_offset */, 0, 0, 0, 0, 0,
+            0, 0, 5 /* log_start_offset */, 255, 255, 255, 255 /* aborted_transactions */, 0, 0, 0,
+            2 /* preferred_read_replica */, /* MessageSet */ 0, 0, 0, 0 /* size */,
+        ];
+
+        let res = parse_fetch_response(&data[..], 11, CrcPolicy::Fail);
 
         display_parse_error::<_>(&data[..], res.clone());
 
@@ -5,25 +5,49 @@ use bytes::{BufMut, ByteOrder, Bytes, BytesMut};
 use nom::{IResult, be_i16, be_i32};
 
 use errors::Result;
-use protocol::{parse_bytes, parse_response_header, parse_string, ApiVersion, Encodable, ErrorCode, GenerationId,
-               ParseTag, Record, RequestHeader, ResponseHeader, WriteExt, ARRAY_LEN_SIZE, BYTES_LEN_SIZE, STR_LEN_SIZE};
+use protocol::{parse_bytes, parse_opt_string, parse_response_header, parse_string, ApiVersion, Encodable, ErrorCode,
+               GenerationId, ParseTag, Record, RequestHeader, ResponseHeader, WriteExt, ARRAY_LEN_SIZE, BYTES_LEN_SIZE,
+               STR_LEN_SIZE};
 
+const COORDINATOR_TYPE_SIZE: usize = 1;
 const SESSION_TIMEOUT_SIZE: usize = 4;
 const REBALANCE_TIMEOUT_SIZE: usize = 4;
 const GROUP_GENERATION_ID_SIZE: usize = 4;
 
+/// The `FindCoordinator` request was named `GroupCoordinator` before Kafka 0.11
+pub type FindCoordinatorRequest<'a> = GroupCoordinatorRequest<'a>;
+
+/// The `FindCoordinator` response was named `GroupCoordinator` before Kafka 0.11
+pub type FindCoordinatorResponse = GroupCoordinatorResponse;
+
+/// The type of coordinator to find.
+#[repr(i8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CoordinatorType {
+    /// The coordinator of a consumer group
+    Group = 0,
+    /// The coordinator of a transactional producer
+    Transaction = 1,
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct GroupCoordinatorRequest<'a> {
     pub header: RequestHeader<'a>,
-    /// The unique group id.
+    /// The unique group id, or the transactional id.
     pub group_id: Cow<'a, str>,
+    /// The type of coordinator to find (v1)
+    pub coordinator_type: CoordinatorType,
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct GroupCoordinatorResponse {
     pub header: ResponseHeader,
+    /// Duration in milliseconds for which the request was throttled due to quota violation. (v1)
+    pub throttle_time: Option<i32>,
     /// Error code.
     pub error_code: ErrorCode,
+    /// The error message. (v1)
+    pub error_message: Option<String>,
     /// The broker id.
     pub coordinator_id: i32,
     /// The hostname of the broker.
@@ -222,14 +246,21 @@ pub struct ListGroupsGroupStatus {
 impl<'a> Record for GroupCoordinatorRequest<'a> {
     fn size(&self, api_version: ApiVersion) -> usize {
         self.header.size(api_version) + STR_LEN_SIZE + self.group_id.len()
+            + if api_version > 0 { COORDINATOR_TYPE_SIZE } else { 0 }
     }
 }
 
 impl<'a> Encodable for GroupCoordinatorRequest<'a> {
     fn encode<T: ByteOrder>(&self, dst: &mut BytesMut) -> Result<()> {
+        let api_version = self.header.api_version;
+
         self.header.encode::<T>(dst)?;
 
-        dst.put_str::<T, _>(Some(self.group_id.as_ref()))
+        dst.put_str::<T, _>(Some(self.group_id.as_ref()))?;
+        if api_version > 0 {
+            dst.put_i8(self.coordinator_type as i8);
+        }
+        Ok(())
     }
 }
 
@@ -355,25 +386,30 @@ impl<'a> Encodable for ListGroupsRequest<'a> {
 }
 
 impl GroupCoordinatorResponse {
-    pub fn parse(buf: &[u8]) -> IResult<&[u8], Self> {
-        parse_group_corordinator_response(buf)
+    pub fn parse(buf: &[u8], api_version: ApiVersion) -> IResult<&[u8], Self> {
+        parse_group_corordinator_response(buf, api_version)
     }
 }
 
-named!(
-    parse_group_corordinator_response<GroupCoordinatorResponse>,
-    parse_tag!(
-        ParseTag::GroupCoordinatorResponse,
+named_args!(parse_group_corordinator_response(api_version: ApiVersion)<GroupCoordinatorResponse>,
+    parse_tag!(ParseTag::GroupCoordinatorResponse,
         do_parse!(
-            header: parse_response_header >> error_code: be_i16 >> coordinator_id: be_i32
-                >> coordinator_host: parse_string >> coordinator_port: be_i32
-                >> (GroupCoordinatorResponse {
-                    header,
-                    error_code,
-                    coordinator_id,
-                    coordinator_host,
-                    coordinator_port,
-                })
+            header: parse_response_header
+         >> throttle_time: cond!(api_version > 0, be_i32)
+         >> error_code: be_i16
+         >> error_message: cond!(api_version > 0, parse_opt_string)
+         >> coordinator_id: be_i32
+         >> coordinator_host: parse_string
+         >> coordinator_port: be_i32
+         >> (GroupCoordinatorResponse {
+                header,
+                throttle_time,
+                error_code,
+                error_message: error_message.and_then(|message| message),
+                coordinator_id,
+                coordinator_host,
+                coordinator_port,
+            })
         )
     )
 );
@@ -575,6 +611,7 @@ mod tests {
                 client_id: Some("client".into()),
             },
             group_id: "consumer".into(),
+            coordinator_type: CoordinatorType::Group,
         };
 
         let data = vec![
@@ -603,14 +640,70 @@ mod tests {
 
         let res = GroupCoordinatorResponse {
             header: ResponseHeader { correlation_id: 123 },
+            throttle_time: None,
             error_code: 1,
+            error_message: None,
+            coordinator_id: 2,
+            coordinator_host: "localhost".to_owned(),
+            coordinator_port: 3,
+        };
+
+        assert_eq!(
+            parse_group_corordinator_response(data.as_slice(), 0),
+            IResult::Done(&[][..], res)
+        );
+    }
+
+    #[test]
+    fn test_encode_find_coordinator_request_v1() {
+        let req = FindCoordinatorRequest {
+            header: RequestHeader {
+                api_key: ApiKeys::GroupCoordinator as ApiKey,
+                api_version: 1,
+                correlation_id: 123,
+                client_id: Some("client".into()),
+            },
+            group_id: "txn".into(),
+            coordinator_type: CoordinatorType::Transaction,
+        };
+
+        let data = vec![
+            /* FindCoordinatorRequest
+             * RequestHeader */ 0, 10 /* api_key */, 0,
+            1 /* api_version */, 0, 0, 0, 123 /* correlation_id */, 0, 6, b'c', b'l', b'i', b'e', b'n',
+            b't' /* client_id */, 0, 3, b't', b'x', b'n' /* group_id */, 1 /* coordinator_type */,
+        ];
+
+        let mut buf = BytesMut::with_capacity(128);
+
+        req.encode::<BigEndian>(&mut buf).unwrap();
+
+        assert_eq!(req.size(req.header.api_version), buf.len());
+
+        assert_eq!(&buf[..], &data[..]);
+    }
+
+    #[test]
+    fn test_parse_find_coordinator_response_v1() {
+        let data = vec![
+            /* ResponseHeader */ 0, 0, 0, 123 /* correlation_id */, 0, 0, 0, 4 /* throttle_time */, 0,
+            0 /* error_code */, 0xff, 0xff /* error_message */, 0, 0, 0, 2 /* coordinator_id */, 0, 9, b'l',
+            b'o', b'c', b'a', b'l', b'h', b'o', b's', b't' /* coordinator_host */, 0, 0, 0,
+            3 /* coordinator_port */,
+        ];
+
+        let res = FindCoordinatorResponse {
+            header: ResponseHeader { correlation_id: 123 },
+            throttle_time: Some(4),
+            error_code: 0,
+            error_message: None,
             coordinator_id: 2,
             coordinator_host: "localhost".to_owned(),
             coordinator_port: 3,
         };
 
         assert_eq!(
-            parse_group_corordinator_response(data.as_slice()),
+            parse_group_corordinator_response(data.as_slice(), 1),
             IResult::Done(&[][..], res)
         );
     }
@@ -0,0 +1,229 @@
+use std::borrow::Cow;
+
+use bytes::{BufMut, ByteOrder, BytesMut};
+
+use nom::{IResult, be_i16, be_i32, be_i64};
+
+use errors::Result;
+use protocol::{parse_response_header, parse_string, ApiVersion, Encodable, ErrorCode, LeaderEpoch, Offset, ParseTag,
+               PartitionId, Record, RequestHeader, ResponseHeader, WriteExt, ARRAY_LEN_SIZE, PARTITION_ID_SIZE,
+               STR_LEN_SIZE};
+
+const LEADER_EPOCH_SIZE: usize = 4;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OffsetsForLeaderEpochRequest<'a> {
+    pub header: RequestHeader<'a>,
+    /// Topics to look up the end offsets of the leader epochs.
+    pub topics: Vec<OffsetsForLeaderEpochTopic<'a>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OffsetsForLeaderEpochTopic<'a> {
+    /// The name of the topic.
+    pub topic_name: Cow<'a, str>,
+    /// Partitions to look up the end offsets.
+    pub partitions: Vec<OffsetsForLeaderEpochPartition>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OffsetsForLeaderEpochPartition {
+    /// The id of the partition.
+    pub partition_id: PartitionId,
+    /// The current leader epoch known by the client, used to fence the stale leader (v2+).
+    pub current_leader_epoch: LeaderEpoch,
+    /// The leader epoch to look up the end offset.
+    pub leader_epoch: LeaderEpoch,
+}
+
+impl<'a> Record for OffsetsForLeaderEpochRequest<'a> {
+    fn size(&self, api_version: ApiVersion) -> usize {
+        let partition_size = PARTITION_ID_SIZE + LEADER_EPOCH_SIZE
+            + if api_version > 1 { LEADER_EPOCH_SIZE } else { 0 };
+
+        self.header.size(api_version) + self.topics.iter().fold(ARRAY_LEN_SIZE, |size, topic| {
+            size + STR_LEN_SIZE + topic.topic_name.len() + ARRAY_LEN_SIZE + topic.partitions.len() * partition_size
+        })
+    }
+}
+
+impl<'a> Encodable for OffsetsForLeaderEpochRequest<'a> {
+    fn encode<T: ByteOrder>(&self, dst: &mut BytesMut) -> Result<()> {
+        let api_version = self.header.api_version;
+
+        self.header.encode::<T>(dst)?;
+
+        dst.put_array::<T, _, _>(&self.topics, |buf, topic| {
+            buf.put_str::<T, _>(Some(topic.topic_name.as_ref()))?;
+            buf.put_array::<T, _, _>(&topic.partitions, |buf, partition| {
+                buf.put_i32::<T>(partition.partition_id);
+                if api_version > 1 {
+                    buf.put_i32::<T>(partition.current_leader_epoch);
+                }
+                buf.put_i32::<T>(partition.leader_epoch);
+                Ok(())
+            })
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OffsetsForLeaderEpochResponse {
+    pub header: ResponseHeader,
+    /// Duration in milliseconds for which the request was throttled due to quota violation (v2+).
+    pub throttle_time: Option<i32>,
+    pub topics: Vec<OffsetsForLeaderEpochTopicStatus>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OffsetsForLeaderEpochTopicStatus {
+    /// The name of the topic.
+    pub topic_name: String,
+    pub partitions: Vec<OffsetsForLeaderEpochPartitionStatus>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OffsetsForLeaderEpochPartitionStatus {
+    /// The error code
+    pub error_code: ErrorCode,
+    /// The id of the partition.
+    pub partition_id: PartitionId,
+    /// The largest leader epoch not greater than the requested one (v1+).
+    pub leader_epoch: Option<LeaderEpoch>,
+    /// The end offset of the leader epoch, -1 if the leader epoch is unknown.
+    pub end_offset: Offset,
+}
+
+impl OffsetsForLeaderEpochResponse {
+    pub fn parse(buf: &[u8], api_version: ApiVersion) -> IResult<&[u8], Self> {
+        parse_offsets_for_leader_epoch_response(buf, api_version)
+    }
+}
+
+named_args!(parse_offsets_for_leader_epoch_response(api_version: ApiVersion)<OffsetsForLeaderEpochResponse>,
+    parse_tag!(ParseTag::OffsetsForLeaderEpochResponse,
+        do_parse!(
+            header: parse_response_header
+         >> throttle_time: cond!(api_version > 1, be_i32)
+         >> topics: length_count!(be_i32, apply!(parse_leader_epoch_topic_status, api_version))
+         >> (OffsetsForLeaderEpochResponse {
+                header,
+                throttle_time,
+                topics,
+            })
+        )
+    )
+);
+
+named_args!(parse_leader_epoch_topic_status(api_version: ApiVersion)<OffsetsForLeaderEpochTopicStatus>,
+    parse_tag!(ParseTag::OffsetsForLeaderEpochTopicStatus,
+        do_parse!(
+            topic_name: parse_string
+         >> partitions: length_count!(be_i32, apply!(parse_leader_epoch_partition_status, api_version))
+         >> (OffsetsForLeaderEpochTopicStatus { topic_name, partitions })
+        )
+    )
+);
+
+named_args!(parse_leader_epoch_partition_status(api_version: ApiVersion)<OffsetsForLeaderEpochPartitionStatus>,
+    parse_tag!(ParseTag::OffsetsForLeaderEpochPartitionStatus,
+        do_parse!(
+            error_code: be_i16
+         >> partition_id: be_i32
+         >> leader_epoch: cond!(api_version > 0, be_i32)
+         >> end_offset: be_i64
+         >> (OffsetsForLeaderEpochPartitionStatus {
+                error_code,
+                partition_id,
+                leader_epoch,
+                end_offset,
+            })
+        )
+    )
+);
+
+#[cfg(test)]
+mod tests {
+    use bytes::BigEndian;
+
+    use nom::IResult;
+
+    use protocol::*;
+
+    use super::*;
+
+    #[test]
+    fn test_encode_offsets_for_leader_epoch_request() {
+        let req = OffsetsForLeaderEpochRequest {
+            header: RequestHeader {
+                api_key: ApiKeys::OffsetForLeaderEpoch as ApiKey,
+                api_version: 2,
+                correlation_id: 123,
+                client_id: Some("client".into()),
+            },
+            topics: vec![
+                OffsetsForLeaderEpochTopic {
+                    topic_name: "topic".into(),
+                    partitions: vec![
+                        OffsetsForLeaderEpochPartition {
+                            partition_id: 1,
+                            current_leader_epoch: 6,
+                            leader_epoch: 5,
+                        },
+                    ],
+                },
+            ],
+        };
+
+        let data = vec![
+            /* OffsetsForLeaderEpochRequest
+             * RequestHeader */ 0, 23 /* api_key */, 0,
+            2 /* api_version */, 0, 0, 0, 123 /* correlation_id */, 0, 6, b'c', b'l', b'i', b'e', b'n',
+            b't' /* client_id */, /* topics: [OffsetsForLeaderEpochTopic] */ 0, 0, 0, 1, 0, 5, b't', b'o', b'p',
+            b'i', b'c' /* topic_name */, /* partitions: [OffsetsForLeaderEpochPartition] */ 0, 0, 0, 1, 0, 0, 0,
+            1 /* partition_id */, 0, 0, 0, 6 /* current_leader_epoch */, 0, 0, 0, 5 /* leader_epoch */,
+        ];
+
+        let mut buf = BytesMut::with_capacity(128);
+
+        req.encode::<BigEndian>(&mut buf).unwrap();
+
+        assert_eq!(req.size(req.header.api_version), buf.len());
+
+        assert_eq!(&buf[..], &data[..]);
+    }
+
+    #[test]
+    fn test_parse_offsets_for_leader_epoch_response() {
+        let response = OffsetsForLeaderEpochResponse {
+            header: ResponseHeader { correlation_id: 123 },
+            throttle_time: Some(1),
+            topics: vec![
+                OffsetsForLeaderEpochTopicStatus {
+                    topic_name: "topic".to_owned(),
+                    partitions: vec![
+                        OffsetsForLeaderEpochPartitionStatus {
+                            error_code: 0,
+                            partition_id: 1,
+                            leader_epoch: Some(5),
+                            end_offset: 456,
+                        },
+                    ],
+                },
+            ],
+        };
+
+        let data = vec![
+            /* ResponseHeader */ 0, 0, 0, 123 /* correlation_id */, 0, 0, 0, 1 /* throttle_time */,
+            /* topics: [OffsetsForLeaderEpochTopicStatus] */ 0, 0, 0, 1, 0, 5, b't', b'o', b'p', b'i',
+            b'c' /* topic_name */, /* partitions: [OffsetsForLeaderEpochPartitionStatus] */ 0, 0, 0, 1, 0,
+            0 /* error_code */, 0, 0, 0, 1 /* partition_id */, 0, 0, 0, 5 /* leader_epoch */, 0, 0, 0, 0, 0, 0, 1,
+            200 /* end_offset */,
+        ];
+
+        assert_eq!(
+            parse_offsets_for_leader_epoch_response(data.as_slice(), 2),
+            IResult::Done(&[][..], response)
+        );
+    }
+}
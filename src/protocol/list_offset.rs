@@ -13,9 +13,12 @@ const MAX_NUMBER_OF_OFFSETS_SIZE: usize = 4;
 pub const LATEST_TIMESTAMP: Timestamp = -1;
 pub const EARLIEST_TIMESTAMP: Timestamp = -2;
 
+/// The maximum number of offsets returned for a partition by `ListOffsets` v0.
+pub const DEFAULT_MAX_NUMBER_OF_OFFSETS: i32 = 16;
+
 /// Possible values when querying a topic's offset.
 /// See `KafkaClient::fetch_offsets`.
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum FetchOffset {
     /// Receive the earliest available offset.
     Earliest,
@@ -1,22 +1,25 @@
 use std::fmt;
 use std::mem;
 use std::ops::Deref;
+use std::str::FromStr;
 
-use bytes::{BufMut, ByteOrder, Bytes, BytesMut};
+use bytes::{BigEndian, BufMut, ByteOrder, Bytes, BytesMut};
 
-use nom::{be_i32, be_i64, be_i8, IResult};
+use nom::{be_i16, be_i32, be_i64, be_i8, be_u32, IResult};
 
 use time;
 
 use crc::crc32;
 
 use compression::Compression;
-use errors::{ErrorKind, Result};
-use protocol::{parse_opt_bytes, ApiVersion, Offset, ParseTag, Record, Timestamp, WriteExt, BYTES_LEN_SIZE,
-               OFFSET_SIZE, TIMESTAMP_SIZE};
+use errors::{Error, ErrorKind, Result};
+use protocol::{parse_frame, parse_opt_bytes, parse_varint, parse_varint_opt_bytes, parse_varlong, ApiVersion,
+               LeaderEpoch, Offset, ParseTag, Record, Timestamp, WriteExt, BYTES_LEN_SIZE, OFFSET_SIZE, TIMESTAMP_SIZE,
+               UNDEFINED_LEADER_EPOCH};
 
 pub const TIMESTAMP_TYPE_MASK: i8 = 0x08;
 pub const COMPRESSION_CODEC_MASK: i8 = 0x07;
+pub const CONTROL_BATCH_MASK: i16 = 0x20;
 
 const MSG_SIZE: usize = 4;
 const CRC_SIZE: usize = 4;
@@ -24,8 +27,52 @@ const MAGIC_SIZE: usize = 1;
 const ATTRIBUTE_SIZE: usize = 1;
 const RECORD_HEADER_SIZE: usize = OFFSET_SIZE + MSG_SIZE + CRC_SIZE + MAGIC_SIZE + ATTRIBUTE_SIZE;
 
+const RECORD_BATCH_MAGIC: i8 = 2;
+const PARTITION_LEADER_EPOCH_SIZE: usize = 4;
+/// The bytes after the length of record batch, which are not covered by the CRC.
+const RECORD_BATCH_CRC_OFFSET: usize = PARTITION_LEADER_EPOCH_SIZE + MAGIC_SIZE + CRC_SIZE;
+
 const COMPRESSION_RATE_ESTIMATION_FACTOR: f32 = 1.05;
 
+/// The estimated ratio of the compressed bytes to the uncompressed bytes.
+fn estimated_rate(compression: Compression) -> f32 {
+    match compression {
+        Compression::None => 1.0,
+        Compression::GZIP | Compression::Snappy | Compression::LZ4 => 0.5,
+    } * COMPRESSION_RATE_ESTIMATION_FACTOR
+}
+
+/// How to handle the messages whose CRC mismatched when parsing the fetched message sets.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CrcPolicy {
+    /// Fail to parse the response, which protects the consumer from the silent corruption.
+    Fail,
+    /// Skip the corrupted messages or record batches, and report them with a warning.
+    Skip,
+    /// Don't calculate the CRC at all, which saves the CPU for the performance-sensitive consumer.
+    Ignore,
+}
+
+impl Default for CrcPolicy {
+    fn default() -> Self {
+        CrcPolicy::Fail
+    }
+}
+
+impl FromStr for CrcPolicy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "fail" => Ok(CrcPolicy::Fail),
+            "skip" => Ok(CrcPolicy::Skip),
+            "ignore" => Ok(CrcPolicy::Ignore),
+            _ => bail!(ErrorKind::ParseError(format!("unknown CRC policy: {}", s))),
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum RecordFormat {
     V0,
@@ -88,18 +135,24 @@ pub struct Message {
     pub compression: Compression,
     pub key: Option<Bytes>,
     pub value: Option<Bytes>,
+    pub leader_epoch: Option<LeaderEpoch>,
 }
 
 impl Record for Message {
     fn size(&self, api_version: ApiVersion) -> usize {
-        let record_overhead_size = RECORD_HEADER_SIZE + if api_version > 0 { TIMESTAMP_SIZE } else { 0 };
-        let key_size = BYTES_LEN_SIZE + self.key.as_ref().map_or(0, |b| b.len());
-        let value_size = BYTES_LEN_SIZE + self.value.as_ref().map_or(0, |b| b.len());
-
-        record_overhead_size + key_size + value_size
+        record_size(api_version, self.key.as_ref(), self.value.as_ref())
     }
 }
 
+/// The size of an uncompressed record with the key and value, including the overhead of message format.
+pub fn record_size(api_version: ApiVersion, key: Option<&Bytes>, value: Option<&Bytes>) -> usize {
+    let record_overhead_size = RECORD_HEADER_SIZE + if api_version > 0 { TIMESTAMP_SIZE } else { 0 };
+    let key_size = BYTES_LEN_SIZE + key.map_or(0, |b| b.len());
+    let value_size = BYTES_LEN_SIZE + value.map_or(0, |b| b.len());
+
+    record_overhead_size + key_size + value_size
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum MessageTimestamp {
     CreateTime(Timestamp),
@@ -163,7 +216,7 @@ impl MessageSetEncoder {
         Ok(())
     }
 
-    fn encode_message<T: ByteOrder>(&self, message: &Message, offset: Offset, buf: &mut BytesMut) -> Result<()> {
+    pub fn encode_message<T: ByteOrder>(&self, message: &Message, offset: Offset, buf: &mut BytesMut) -> Result<()> {
         buf.put_i64::<T>(offset);
         let size_off = buf.len();
         buf.put_i32::<T>(0);
@@ -204,9 +257,14 @@ impl MessageSetEncoder {
 }
 
 named_args!(pub parse_message_set(api_version: ApiVersion)<MessageSet>,
+    apply!(parse_message_set_with, api_version, CrcPolicy::Fail)
+);
+
+named_args!(pub parse_message_set_with(api_version: ApiVersion, check_crcs: CrcPolicy)<MessageSet>,
     parse_tag!(ParseTag::MessageSet,
         do_parse!(
-            messages: many0!(apply!(parse_message, api_version))
+            messages: many0!(apply!(parse_message, api_version, check_crcs))
+         >> apply!(parse_message_set_end, api_version, check_crcs)
          >> (MessageSet {
                 messages: messages.into_iter().flat_map(|i| i).collect(),
             })
@@ -214,51 +272,113 @@ named_args!(pub parse_message_set(api_version: ApiVersion)<MessageSet>,
     )
 );
 
-fn decompress_message(message: Message) -> Result<Vec<Message>> {
+/// Check the remaining bytes of message set, which could be a partial message truncated by the broker.
+///
+/// A complete message or record batch which failed to be parsed, e.g. its CRC mismatched,
+/// fails the message set when the CRCs are checked with `CrcPolicy::Fail`.
+fn parse_message_set_end(i: &[u8], api_version: ApiVersion, check_crcs: CrcPolicy) -> IResult<&[u8], ()> {
+    let header_size = OFFSET_SIZE + MSG_SIZE;
+    let complete = i.len() >= header_size && {
+        let size = BigEndian::read_i32(&i[OFFSET_SIZE..]);
+
+        size >= 0 && i.len() >= header_size + size as usize
+    };
+
+    if check_crcs == CrcPolicy::Fail && complete {
+        if let IResult::Error(err) = parse_message(i, api_version, check_crcs) {
+            return IResult::Error(err);
+        }
+    }
+
+    IResult::Done(i, ())
+}
+
+/// Check the CRC of a message or record batch with the policy.
+///
+/// Returns `None` to fail the parsing, or whether the message or record batch should be accepted.
+fn check_crc<F>(check_crcs: CrcPolicy, offset: Offset, checksum: u32, crc: F) -> Option<bool>
+where
+    F: FnOnce() -> u32,
+{
+    if check_crcs == CrcPolicy::Ignore {
+        return Some(true);
+    }
+
+    let crc = crc();
+
+    if crc == checksum {
+        Some(true)
+    } else if check_crcs == CrcPolicy::Skip {
+        warn!(
+            "skip the corrupted message at offset {}, checksum mismatched, expected={}, current={}",
+            offset, crc, checksum
+        );
+
+        Some(false)
+    } else {
+        trace!("message checksum mismatched, expected={}, current={}", crc, checksum);
+
+        None
+    }
+}
+
+fn decompress_message(message: Option<Message>, check_crcs: CrcPolicy) -> Result<Vec<Message>> {
+    let message = match message {
+        Some(message) => message,
+        None => return Ok(vec![]),
+    };
     if message.compression == Compression::None || message.value == None {
         return Ok(vec![message]);
     }
     let value = message.value.unwrap();
     let decompressed = message.compression.decompress(&value)?;
-    let decompressed = decompressed.unwrap();
+    // the inner messages will share the memory of the decompressed buffer
+    let decompressed = Bytes::from(decompressed.unwrap());
     let version = if message.timestamp.is_some() {
         1
     } else {
         0
     };
-    match parse_message_set(&decompressed, version) {
+    match parse_frame(&decompressed, |buf| parse_message_set_with(buf, version, check_crcs)) {
         IResult::Done(_, message_set) => Ok(message_set.messages),
-        _ => unimplemented!()
+        _ => bail!(ErrorKind::ParseError(format!(
+            "malformed inner messages of the compressed message at offset {}",
+            message.offset
+        ))),
     }
 }
 
-named_args!(parse_message(api_version: ApiVersion)<Vec<Message>>,
-    map_res!(apply!(parse_message_outer, api_version), decompress_message)
+// the magic byte is at the same position of both the message (v0 and v1) and the record batch (v2),
+// so the fetched partitions could mix the formats, e.g. the topic was upgraded.
+named_args!(parse_message(api_version: ApiVersion, check_crcs: CrcPolicy)<Vec<Message>>,
+    switch!(peek!(call!(parse_magic)),
+        RECORD_BATCH_MAGIC => apply!(parse_record_batch, check_crcs)
+      | _ => map_res!(apply!(parse_message_outer, api_version, check_crcs),
+                      |message| decompress_message(message, check_crcs))
+    )
 );
 
-named_args!(parse_message_outer(_api_version: ApiVersion)<Message>,
+named!(parse_magic<i8>, preceded!(take!(OFFSET_SIZE + MSG_SIZE + CRC_SIZE), be_i8));
+
+// parse a message (v0 or v1), returns `None` if the corrupted message was skipped.
+named_args!(parse_message_outer(_api_version: ApiVersion, check_crcs: CrcPolicy)<Option<Message>>,
     parse_tag!(ParseTag::Message,
         do_parse!(
             offset: be_i64
          >> size: be_i32
          >> data: peek!(take!(size))
-         >> _crc: parse_tag!(ParseTag::MessageCrc,
-            verify!(be_i32, |checksum: i32| {
-                let crc = crc32::checksum_ieee(&data[mem::size_of::<i32>()..]);
-
-                if crc != checksum as u32 {
-                    trace!("message checksum mismatched, expected={}, current={}", crc, checksum as u32);
-                }
-
-                crc == checksum as u32
-            }))
+         >> checksum: be_u32
+         >> accepted: parse_tag!(ParseTag::MessageCrc,
+            expr_opt!(check_crc(check_crcs, offset, checksum, || {
+                crc32::checksum_ieee(&data[mem::size_of::<i32>()..])
+            })))
          >> magic: be_i8
          >> attrs: be_i8
          >> timestamp: cond!(magic > 0, be_i64)
          >> key: parse_opt_bytes
          >> value: parse_opt_bytes
-         >> ({
-            Message {
+         >> (if accepted {
+            Some(Message {
                 offset,
                 timestamp: timestamp.map(|ts| if (attrs & TIMESTAMP_TYPE_MASK) == 0 {
                     MessageTimestamp::CreateTime(ts)
@@ -268,12 +388,130 @@ named_args!(parse_message_outer(_api_version: ApiVersion)<Message>,
                 compression: Compression::from(attrs & COMPRESSION_CODEC_MASK),
                 key,
                 value,
-            }})
+                leader_epoch: None,
+            })
+        } else {
+            None
+        })
+        )
+    )
+);
+
+// Record batch format (v2, supported since 0.11.0)
+//
+// RecordBatch => BaseOffset Length PartitionLeaderEpoch Magic Crc Attributes LastOffsetDelta FirstTimestamp
+//                MaxTimestamp ProducerId ProducerEpoch BaseSequence [Record]
+//
+// The records, which may be compressed as a whole, are returned as the messages without compression.
+named_args!(parse_record_batch(check_crcs: CrcPolicy)<Vec<Message>>,
+    parse_tag!(ParseTag::RecordBatch,
+        do_parse!(
+            base_offset: be_i64
+         >> length: verify!(be_i32, |length: i32| length >= RECORD_BATCH_CRC_OFFSET as i32)
+         >> partition_leader_epoch: be_i32
+         >> _magic: be_i8
+         >> checksum: be_u32
+         >> batch: take!(length as usize - RECORD_BATCH_CRC_OFFSET)
+         >> accepted: parse_tag!(ParseTag::RecordBatchCrc,
+            expr_opt!(check_crc(check_crcs, base_offset, checksum, || crc32::checksum_castagnoli(batch))))
+         >> messages: expr_res!(if accepted {
+                parse_batch_records(base_offset, partition_leader_epoch, batch)
+            } else {
+                Ok(vec![])
+            })
+         >> (messages)
+        )
+    )
+);
+
+named!(parse_record_batch_header<(i16, Timestamp, Timestamp)>,
+    do_parse!(
+        attributes: be_i16
+     >> _last_offset_delta: be_i32
+     >> first_timestamp: be_i64
+     >> max_timestamp: be_i64
+     >> _producer_id: be_i64
+     >> _producer_epoch: be_i16
+     >> _base_sequence: be_i32
+     >> _records: be_i32
+     >> (attributes, first_timestamp, max_timestamp)
+    )
+);
+
+struct BatchRecord {
+    timestamp_delta: i64,
+    offset_delta: i32,
+    key: Option<Bytes>,
+    value: Option<Bytes>,
+}
+
+named!(parse_batch_record<BatchRecord>,
+    parse_tag!(ParseTag::Record,
+        do_parse!(
+            _length: parse_varint
+         >> _attributes: be_i8
+         >> timestamp_delta: parse_varlong
+         >> offset_delta: parse_varint
+         >> key: parse_varint_opt_bytes
+         >> value: parse_varint_opt_bytes
+         >> _headers: length_count!(parse_varint, pair!(parse_varint_opt_bytes, parse_varint_opt_bytes))
+         >> (BatchRecord {
+                timestamp_delta,
+                offset_delta,
+                key,
+                value,
+            })
         )
     )
 );
 
+fn parse_batch_records(base_offset: Offset, partition_leader_epoch: LeaderEpoch, batch: &[u8]) -> Result<Vec<Message>> {
+    let (records, (attributes, first_timestamp, max_timestamp)) = match parse_record_batch_header(batch) {
+        IResult::Done(records, header) => (records, header),
+        _ => bail!(ErrorKind::ParseError("incomplete record batch header".to_owned())),
+    };
+
+    // the control records, e.g. the markers of the committed or aborted transactions, are not consumed
+    if attributes & CONTROL_BATCH_MASK != 0 {
+        return Ok(vec![]);
+    }
+
+    let compression = Compression::from(attributes as i8 & COMPRESSION_CODEC_MASK);
+    let log_append_time = attributes as i8 & TIMESTAMP_TYPE_MASK != 0;
+    let leader_epoch = Some(partition_leader_epoch).filter(|&epoch| epoch != UNDEFINED_LEADER_EPOCH);
+    // the records will share the memory of the decompressed buffer
+    let decompressed = compression.decompress(records)?.map(Bytes::from);
+    let parsed = match decompressed {
+        Some(ref decompressed) => parse_frame(decompressed, |buf| many0!(buf, parse_batch_record)),
+        None => many0!(records, parse_batch_record),
+    };
+
+    match parsed {
+        IResult::Done(_, records) => Ok(records
+            .into_iter()
+            .map(|record| Message {
+                offset: base_offset + Offset::from(record.offset_delta),
+                timestamp: Some(if log_append_time {
+                    MessageTimestamp::LogAppendTime(max_timestamp)
+                } else {
+                    MessageTimestamp::CreateTime(first_timestamp + record.timestamp_delta)
+                }),
+                compression: Compression::None,
+                key: record.key,
+                value: record.value,
+                leader_epoch,
+            })
+            .collect()),
+        _ => bail!(ErrorKind::ParseError("malformed records of record batch".to_owned())),
+    }
+}
+
 /// This class is used to write new log data in memory, i.e.
+///
+/// The keys and values of an uncompressed message set are shared with the pushed records,
+/// and copied only once when the produce request is encoded.
+/// The records of a compressed message set are encoded into a pre-sized buffer as they are pushed,
+/// which is compressed in place when the message set is built.
 #[derive(Debug)]
 pub struct MessageSetBuilder {
     api_version: ApiVersion,
@@ -284,10 +522,18 @@ pub struct MessageSetBuilder {
     last_offset: Option<Offset>,
     base_timestamp: Option<Timestamp>,
     message_set: MessageSet,
+    buf: BytesMut,
 }
 
 impl MessageSetBuilder {
     pub fn new(api_version: ApiVersion, compression: Compression, write_limit: usize, base_offset: Offset) -> Self {
+        let buf = if compression == Compression::None {
+            BytesMut::new()
+        } else {
+            // the uncompressed bytes which the message set is estimated to hold before it is full
+            BytesMut::with_capacity((write_limit as f32 / estimated_rate(compression)) as usize)
+        };
+
         MessageSetBuilder {
             api_version,
             compression,
@@ -297,6 +543,7 @@ impl MessageSetBuilder {
             last_offset: None,
             base_timestamp: None,
             message_set: MessageSet { messages: vec![] },
+            buf,
         }
     }
 
@@ -304,38 +551,62 @@ impl MessageSetBuilder {
         self.api_version
     }
 
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    /// The maximum bytes to be written to the message set
+    pub fn write_limit(&self) -> usize {
+        self.write_limit
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.last_offset.is_none()
+    }
+
     pub fn is_full(&self) -> bool {
-        !self.message_set.is_empty() && self.write_limit <= self.estimated_bytes()
+        !self.is_empty() && self.write_limit <= self.estimated_bytes()
     }
 
     pub fn has_room_for(&self, timestamp: Timestamp, key: Option<&Bytes>, value: Option<&Bytes>) -> bool {
-        self.message_set.is_empty()
+        self.is_empty()
             || self.write_limit >= self.estimated_bytes() + self.record_size(timestamp, key, value)
     }
 
+    /// The uncompressed bytes of the records written to the message set
+    pub fn written_bytes(&self) -> usize {
+        self.written_uncompressed
+    }
+
+    /// The bytes allocated to encode the records before the message set is built.
+    ///
+    /// It is always zero for an uncompressed message set, whose keys and values are shared with the records.
+    pub fn allocated_bytes(&self) -> usize {
+        self.buf.capacity()
+    }
+
+    /// The ratio, between 0.0 and 1.0, of the estimated written bytes to the write limit
+    pub fn fill_ratio(&self) -> f64 {
+        if self.write_limit == 0 {
+            1.0
+        } else {
+            (self.estimated_bytes() as f64 / self.write_limit as f64).min(1.0)
+        }
+    }
+
     /// Estimate the written bytes to the underlying byte buffer based on uncompressed written
     /// bytes
     fn estimated_bytes(&self) -> usize {
-        (self.written_uncompressed as f32 * match self.compression {
-            Compression::None => 1.0,
-            Compression::GZIP | Compression::Snappy | Compression::LZ4 => 0.5,
-        } * COMPRESSION_RATE_ESTIMATION_FACTOR) as usize
+        (self.written_uncompressed as f32 * estimated_rate(self.compression)) as usize
     }
 
     fn record_size(&self, _timestamp: Timestamp, key: Option<&Bytes>, value: Option<&Bytes>) -> usize {
-        let record_overhead_size = RECORD_HEADER_SIZE + if self.api_version > 0 { TIMESTAMP_SIZE } else { 0 };
-        let key_size = BYTES_LEN_SIZE + key.map_or(0, |b| b.len());
-        let value_size = BYTES_LEN_SIZE + value.map_or(0, |b| b.len());
-
-        record_overhead_size + key_size + value_size
+        record_size(self.api_version, key, value)
     }
 
     #[cfg(any(feature = "gzip", feature = "snappy", feature = "lz4"))]
-    fn wrap<T: ByteOrder>(&self, compression: Compression) -> Result<MessageSet> {
-        let mut buf = BytesMut::with_capacity((self.message_set.size(self.api_version) * 6 / 5).next_power_of_two());
-        let encoder = MessageSetEncoder::new(self.api_version, Some(Compression::None));
-        encoder.encode::<T>(&self.message_set, &mut buf)?;
-        let compressed = compression.compress(self.api_version, &buf)?;
+    fn wrap(&self, compression: Compression) -> Result<MessageSet> {
+        let compressed = compression.compress(self.api_version, &self.buf)?;
         Ok(MessageSet {
             messages: vec![
                 Message {
@@ -344,6 +615,7 @@ impl MessageSetBuilder {
                     compression,
                     key: None,
                     value: Some(Bytes::from(compressed)),
+                    leader_epoch: None,
                 },
             ],
         })
@@ -352,11 +624,11 @@ impl MessageSetBuilder {
     pub fn build<T: ByteOrder>(self) -> Result<MessageSet> {
         match self.compression {
             #[cfg(feature = "gzip")]
-            Compression::GZIP => self.wrap::<T>(Compression::GZIP),
+            Compression::GZIP => self.wrap(Compression::GZIP),
             #[cfg(feature = "snappy")]
-            Compression::Snappy => self.wrap::<T>(Compression::Snappy),
+            Compression::Snappy => self.wrap(Compression::Snappy),
             #[cfg(feature = "lz4")]
-            Compression::LZ4 => self.wrap::<T>(Compression::LZ4),
+            Compression::LZ4 => self.wrap(Compression::LZ4),
             Compression::None => Ok(self.message_set),
         }
     }
@@ -398,13 +670,24 @@ impl MessageSetBuilder {
         let record_size = self.record_size(timestamp, key.as_ref(), value.as_ref());
         let relative_offset = offset - self.base_offset;
 
-        self.message_set.messages.push(Message {
+        let message = Message {
             offset: relative_offset,
             timestamp: Some(MessageTimestamp::CreateTime(timestamp)),
             compression: self.compression,
             key,
             value,
-        });
+            leader_epoch: None,
+        };
+
+        if self.compression == Compression::None {
+            self.message_set.messages.push(message);
+        } else {
+            MessageSetEncoder::new(self.api_version, Some(Compression::None)).encode_message::<BigEndian>(
+                &message,
+                relative_offset,
+                &mut self.buf,
+            )?;
+        }
 
         self.last_offset = Some(offset);
 
@@ -420,11 +703,126 @@ impl MessageSetBuilder {
 
 #[cfg(test)]
 mod tests {
+    use bytes::BigEndian;
     use nom::IResult;
 
     use super::*;
     use protocol::*;
 
+    fn record_batch(base_offset: Offset, attributes: i16, records: &[u8]) -> Vec<u8> {
+        let mut batch = vec![];
+
+        batch.put_i16::<BigEndian>(attributes);
+        batch.put_i32::<BigEndian>(1); // last offset delta
+        batch.put_i64::<BigEndian>(1000); // first timestamp
+        batch.put_i64::<BigEndian>(1010); // max timestamp
+        batch.put_i64::<BigEndian>(-1); // producer id
+        batch.put_i16::<BigEndian>(-1); // producer epoch
+        batch.put_i32::<BigEndian>(-1); // base sequence
+        batch.put_i32::<BigEndian>(2); // records
+        batch.extend_from_slice(records);
+
+        let mut buf = vec![];
+
+        buf.put_i64::<BigEndian>(base_offset);
+        buf.put_i32::<BigEndian>((RECORD_BATCH_CRC_OFFSET + batch.len()) as i32);
+        buf.put_i32::<BigEndian>(5); // partition leader epoch
+        buf.put_i8(RECORD_BATCH_MAGIC);
+        buf.put_u32::<BigEndian>(crc32::checksum_castagnoli(&batch));
+        buf.extend_from_slice(&batch);
+        buf
+    }
+
+    #[test]
+    fn parse_record_batch_v2() {
+        let records = [
+            /* length */ 28, /* attributes */ 0, /* timestamp delta */ 0, /* offset delta */ 0,
+            /* key */ 6, b'k', b'e', b'y', /* value */ 10, b'v', b'a', b'l', b'u', b'e', /* headers */ 0,
+            /* length */ 22, /* attributes */ 0, /* timestamp delta */ 20, /* offset delta */ 2,
+            /* key */ 1, /* value */ 4, b'v', b'2', /* headers */ 2, 2, b'h', 1,
+        ];
+        let message = |offset, timestamp, key: Option<&[u8]>, value: &[u8]| Message {
+            offset,
+            timestamp: Some(timestamp),
+            compression: Compression::None,
+            key: key.map(Bytes::from),
+            value: Some(Bytes::from(value)),
+            leader_epoch: Some(5),
+        };
+
+        let mut data = record_batch(100, 0, &records);
+
+        data.extend(record_batch(102, TIMESTAMP_TYPE_MASK as i16, &records));
+        data.extend(record_batch(104, CONTROL_BATCH_MASK, &records));
+
+        assert_eq!(
+            parse_message_set(&data[..], 0),
+            IResult::Done(
+                &[][..],
+                MessageSet {
+                    messages: vec![
+                        message(100, MessageTimestamp::CreateTime(1000), Some(&b"key"[..]), &b"value"[..]),
+                        message(101, MessageTimestamp::CreateTime(1010), None, &b"v2"[..]),
+                        message(102, MessageTimestamp::LogAppendTime(1010), Some(&b"key"[..]), &b"value"[..]),
+                        message(103, MessageTimestamp::LogAppendTime(1010), None, &b"v2"[..]),
+                    ],
+                }
+            )
+        );
+
+        // the corrupted batch fails the message set
+        let mut data = record_batch(100, 0, &records);
+        let last = data.len() - 1;
+
+        data[last] = b'x';
+
+        assert!(parse_message_set(&data[..], 0).is_err());
+
+        // or is skipped
+        assert_eq!(
+            parse_message_set_with(&data[..], 0, CrcPolicy::Skip),
+            IResult::Done(&[][..], MessageSet { messages: vec![] })
+        );
+    }
+
+    #[test]
+    fn parse_corrupted_message_set() {
+        let message = [
+            0, 0, 0, 0, 0, 0, 0, 0 /* offset */, 0, 0, 0, 22 /* size */, 0, 0, 0, 0 /* crc */, 0 /* magic */,
+            8 /* attributes */, 0, 0, 0, 3, b'k', b'e', b'y' /* key */, 0, 0, 0, 5, b'v', b'a', b'l', b'u',
+            b'e' /* value */,
+        ];
+        let records = [
+            /* length */ 28, /* attributes */ 0, /* timestamp delta */ 0, /* offset delta */ 0,
+            /* key */ 6, b'k', b'e', b'y', /* value */ 10, b'v', b'a', b'l', b'u', b'e', /* headers */ 0,
+        ];
+        let mut batch = record_batch(1, 0, &records);
+
+        // corrupt the CRC of record batch
+        batch[OFFSET_SIZE + MSG_SIZE + PARTITION_LEADER_EPOCH_SIZE + MAGIC_SIZE] ^= 0xFF;
+
+        for data in &[&message[..], &batch[..]] {
+            assert!(parse_message_set_with(data, 0, CrcPolicy::Fail).is_err());
+
+            assert_eq!(
+                parse_message_set_with(data, 0, CrcPolicy::Skip),
+                IResult::Done(&[][..], MessageSet { messages: vec![] })
+            );
+
+            match parse_message_set_with(data, 0, CrcPolicy::Ignore) {
+                IResult::Done(remaining, message_set) => {
+                    assert!(remaining.is_empty());
+                    assert_eq!(message_set.messages.len(), 1);
+                    assert_eq!(message_set.messages[0].value, Some(Bytes::from(&b"value"[..])));
+                }
+                res => panic!("unexpected result: {:?}", res),
+            }
+        }
+
+        assert_eq!("skip".parse::<CrcPolicy>().unwrap(), CrcPolicy::Skip);
+        assert!("unknown".parse::<CrcPolicy>().is_err());
+    }
+
     #[test]
     fn parse_empty_message_set() {
         assert_eq!(
@@ -449,6 +847,7 @@ mod tests {
                     key: Some(Bytes::from(&b"key"[..])),
                     value: Some(Bytes::from(&b"value"[..])),
                     timestamp: None,
+                    leader_epoch: None,
                 },
             ],
         };
@@ -460,6 +859,102 @@ mod tests {
         assert_eq!(res, IResult::Done(&[][..], message_set));
     }
 
+    #[test]
+    fn test_fill_ratio() {
+        let mut builder = MessageSetBuilder::new(0, Compression::None, 1024, 0);
+
+        assert_eq!(builder.fill_ratio(), 0.0);
+
+        builder
+            .push(0, Some(Bytes::from(&b"key"[..])), Some(Bytes::from(&b"value"[..])))
+            .unwrap();
+
+        let fill_ratio = builder.fill_ratio();
+
+        assert!(fill_ratio > 0.0 && fill_ratio < 1.0);
+
+        while !builder.is_full() {
+            builder.push(0, None, Some(Bytes::from(&b"value"[..]))).unwrap();
+        }
+
+        assert_eq!(builder.fill_ratio(), 1.0);
+    }
+
+    #[cfg(feature = "snappy")]
+    #[test]
+    fn encode_compressed_message_set() {
+        let mut builder = MessageSetBuilder::new(1, Compression::Snappy, 1024, 0);
+
+        assert!(builder.allocated_bytes() >= 1024);
+
+        builder.push(123, Some(Bytes::from(&b"key"[..])), Some(Bytes::from(&b"value"[..]))).unwrap();
+        builder.push(456, None, Some(Bytes::from(&b"value"[..]))).unwrap();
+
+        let message_set = builder.build::<BigEndian>().unwrap();
+
+        assert_eq!(message_set.messages.len(), 1);
+        assert_eq!(message_set.messages[0].compression, Compression::Snappy);
+
+        let mut buf = BytesMut::with_capacity(64);
+
+        MessageSetEncoder::new(1, None)
+            .encode::<BigEndian>(&message_set, &mut buf)
+            .unwrap();
+
+        let res = parse_message_set(&buf[..], 1);
+
+        display_parse_error::<_>(&buf[..], res.clone());
+
+        match res {
+            IResult::Done(remaining, parsed) => {
+                assert!(remaining.is_empty());
+                assert_eq!(parsed.messages.len(), 2);
+                assert_eq!(parsed.messages[0].offset, 0);
+                assert_eq!(parsed.messages[0].key, Some(Bytes::from(&b"key"[..])));
+                assert_eq!(parsed.messages[0].timestamp, Some(MessageTimestamp::CreateTime(123)));
+                assert_eq!(parsed.messages[1].offset, 1);
+                assert_eq!(parsed.messages[1].key, None);
+                assert_eq!(parsed.messages[1].value, Some(Bytes::from(&b"value"[..])));
+            }
+            res => panic!("fail to parse message set, {:?}", res),
+        }
+    }
+
+    #[test]
+    fn encode_tombstone() {
+        let mut builder = MessageSetBuilder::new(1, Compression::None, 1024, 0);
+
+        builder.push(123, Some(Bytes::from(&b"key"[..])), Some(Bytes::new())).unwrap();
+        builder.push(456, Some(Bytes::from(&b"key"[..])), None).unwrap();
+
+        let message_set = builder.build::<BigEndian>().unwrap();
+        let mut buf = BytesMut::with_capacity(64);
+
+        MessageSetEncoder::new(1, None)
+            .encode::<BigEndian>(&message_set, &mut buf)
+            .unwrap();
+
+        // the null value is encoded as -1 instead of an empty value
+        assert_eq!(&buf[buf.len() - 4..], &[255, 255, 255, 255][..]);
+
+        let res = parse_message_set(&buf[..], 1);
+
+        display_parse_error::<_>(&buf[..], res.clone());
+
+        match res {
+            IResult::Done(remaining, parsed) => {
+                assert!(remaining.is_empty());
+                assert_eq!(parsed.messages.len(), 2);
+                assert_eq!(parsed.messages[0].key, Some(Bytes::from(&b"key"[..])));
+                // the empty value is decoded as an empty value instead of a null value
+                assert_eq!(parsed.messages[0].value, Some(Bytes::new()));
+                assert_eq!(parsed.messages[1].key, Some(Bytes::from(&b"key"[..])));
+                assert_eq!(parsed.messages[1].value, None);
+            }
+            res => panic!("fail to parse message set, {:?}", res),
+        }
+    }
+
     #[test]
     fn parse_message_set_v1() {
         let data = vec![
@@ -477,6 +972,7 @@ mod tests {
                     key: Some(Bytes::from(&b"key"[..])),
                     value: Some(Bytes::from(&b"value"[..])),
                     timestamp: Some(MessageTimestamp::LogAppendTime(456)),
+                    leader_epoch: None,
                 },
             ],
         };
@@ -1,34 +1,56 @@
 use std::borrow::Cow;
 
-use bytes::{ByteOrder, BytesMut};
+use bytes::{BufMut, ByteOrder, BytesMut};
 
-use nom::{IResult, be_i16, be_i32};
+use nom::{IResult, be_i16, be_i32, be_i8};
 
 use errors::Result;
-use protocol::{parse_response_header, parse_string, ApiVersion, Encodable, ErrorCode, NodeId, ParseTag, PartitionId,
-               Record, RequestHeader, ResponseHeader, WriteExt, ARRAY_LEN_SIZE, STR_LEN_SIZE};
+use protocol::{parse_opt_string, parse_response_header, parse_string, ApiVersion, Encodable, ErrorCode, NodeId,
+               ParseTag, PartitionId, Record, RequestHeader, ResponseHeader, WriteExt, ARRAY_LEN_SIZE, STR_LEN_SIZE};
+
+const ALLOW_AUTO_TOPIC_CREATION_SIZE: usize = 1;
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct MetadataRequest<'a> {
     pub header: RequestHeader<'a>,
+    /// The topics to fetch metadata for, or all the topics if empty.
     pub topic_names: Vec<Cow<'a, str>>,
+    /// Whether the brokers should create the unknown topics if `auto.create.topics.enable` (v4+).
+    ///
+    /// The brokers create the topics regardless of this flag before v4.
+    pub allow_auto_topic_creation: bool,
 }
 
 impl<'a> Record for MetadataRequest<'a> {
     fn size(&self, api_version: ApiVersion) -> usize {
         self.header.size(api_version) + self.topic_names.iter().fold(ARRAY_LEN_SIZE, |size, topic_name| {
             size + STR_LEN_SIZE + topic_name.len()
-        })
+        }) + if api_version > 3 {
+            ALLOW_AUTO_TOPIC_CREATION_SIZE
+        } else {
+            0
+        }
     }
 }
 
 impl<'a> Encodable for MetadataRequest<'a> {
     fn encode<T: ByteOrder>(&self, dst: &mut BytesMut) -> Result<()> {
+        let api_version = self.header.api_version;
+
         self.header.encode::<T>(dst)?;
 
-        dst.put_array::<T, _, _>(&self.topic_names, |buf, topic_name| {
-            buf.put_str::<T, _>(Some(topic_name.as_ref()))
-        })?;
+        // an empty array means no topic since v1, all the topics are requested with a null array
+        if api_version > 0 && self.topic_names.is_empty() {
+            dst.put_i32::<T>(-1);
+        } else {
+            dst.put_array::<T, _, _>(&self.topic_names, |buf, topic_name| {
+                buf.put_str::<T, _>(Some(topic_name.as_ref()))
+            })?;
+        }
+
+        if api_version > 3 {
+            dst.put_u8(self.allow_auto_topic_creation as u8);
+        }
 
         Ok(())
     }
@@ -37,7 +59,13 @@ impl<'a> Encodable for MetadataRequest<'a> {
 #[derive(Clone, Debug, PartialEq)]
 pub struct MetadataResponse {
     pub header: ResponseHeader,
+    /// Duration in milliseconds for which the request was throttled due to quota violation (v3+).
+    pub throttle_time: Option<i32>,
     pub brokers: Vec<BrokerMetadata>,
+    /// The cluster id that this broker belongs to (v2+).
+    pub cluster_id: Option<String>,
+    /// The broker id of the controller broker, or -1 if unknown (v1+).
+    pub controller_id: Option<NodeId>,
     pub topics: Vec<TopicMetadata>,
 }
 
@@ -46,12 +74,16 @@ pub struct BrokerMetadata {
     pub node_id: NodeId,
     pub host: String,
     pub port: i32,
+    /// The rack of the broker (v1+).
+    pub rack: Option<String>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct TopicMetadata {
     pub error_code: ErrorCode,
     pub topic_name: String,
+    /// Indicates if the topic is considered a Kafka internal topic (v1+).
+    pub is_internal: bool,
     pub partitions: Vec<PartitionMetadata>,
 }
 
@@ -62,64 +94,87 @@ pub struct PartitionMetadata {
     pub leader: NodeId,
     pub replicas: Vec<NodeId>,
     pub isr: Vec<NodeId>,
+    /// The set of offline replicas of this partition (v5+).
+    pub offline_replicas: Vec<NodeId>,
 }
 
 impl MetadataResponse {
-    pub fn parse(buf: &[u8]) -> IResult<&[u8], Self> {
-        parse_metadata_response(buf)
+    pub fn parse(buf: &[u8], api_version: ApiVersion) -> IResult<&[u8], Self> {
+        parse_metadata_response(buf, api_version)
     }
 }
 
-named!(
-    parse_metadata_response<MetadataResponse>,
-    parse_tag!(
-        ParseTag::MetadataResponse,
+named_args!(parse_metadata_response(api_version: ApiVersion)<MetadataResponse>,
+    parse_tag!(ParseTag::MetadataResponse,
         do_parse!(
-            header: parse_response_header >> brokers: length_count!(be_i32, parse_broker_metadata)
-                >> topics: length_count!(be_i32, parse_topic_metadata) >> (MetadataResponse {
+            header: parse_response_header
+         >> throttle_time: cond!(api_version > 2, be_i32)
+         >> brokers: length_count!(be_i32, apply!(parse_broker_metadata, api_version))
+         >> cluster_id: cond!(api_version > 1, parse_opt_string)
+         >> controller_id: cond!(api_version > 0, be_i32)
+         >> topics: length_count!(be_i32, apply!(parse_topic_metadata, api_version))
+         >> (MetadataResponse {
                 header,
+                throttle_time,
                 brokers,
+                cluster_id: cluster_id.unwrap_or_default(),
+                controller_id,
                 topics,
             })
         )
     )
 );
 
-named!(
-    parse_broker_metadata<BrokerMetadata>,
-    parse_tag!(
-        ParseTag::BrokerMetadata,
-        do_parse!(node_id: be_i32 >> host: parse_string >> port: be_i32 >> (BrokerMetadata { node_id, host, port }))
+named_args!(parse_broker_metadata(api_version: ApiVersion)<BrokerMetadata>,
+    parse_tag!(ParseTag::BrokerMetadata,
+        do_parse!(
+            node_id: be_i32
+         >> host: parse_string
+         >> port: be_i32
+         >> rack: cond!(api_version > 0, parse_opt_string)
+         >> (BrokerMetadata {
+                node_id,
+                host,
+                port,
+                rack: rack.unwrap_or_default(),
+            })
+        )
     )
 );
 
-named!(
-    parse_topic_metadata<TopicMetadata>,
-    parse_tag!(
-        ParseTag::TopicMetadata,
+named_args!(parse_topic_metadata(api_version: ApiVersion)<TopicMetadata>,
+    parse_tag!(ParseTag::TopicMetadata,
         do_parse!(
-            error_code: be_i16 >> topic_name: parse_string
-                >> partitions: length_count!(be_i32, parse_partition_metadata) >> (TopicMetadata {
+            error_code: be_i16
+         >> topic_name: parse_string
+         >> is_internal: cond!(api_version > 0, be_i8)
+         >> partitions: length_count!(be_i32, apply!(parse_partition_metadata, api_version))
+         >> (TopicMetadata {
                 error_code,
                 topic_name,
+                is_internal: is_internal.map_or(false, |is_internal| is_internal != 0),
                 partitions,
             })
         )
     )
 );
 
-named!(
-    parse_partition_metadata<PartitionMetadata>,
-    parse_tag!(
-        ParseTag::PartitionMetadata,
+named_args!(parse_partition_metadata(api_version: ApiVersion)<PartitionMetadata>,
+    parse_tag!(ParseTag::PartitionMetadata,
         do_parse!(
-            error_code: be_i16 >> partition_id: be_i32 >> leader: be_i32 >> replicas: length_count!(be_i32, be_i32)
-                >> isr: length_count!(be_i32, be_i32) >> (PartitionMetadata {
+            error_code: be_i16
+         >> partition_id: be_i32
+         >> leader: be_i32
+         >> replicas: length_count!(be_i32, be_i32)
+         >> isr: length_count!(be_i32, be_i32)
+         >> offline_replicas: cond!(api_version > 4, length_count!(be_i32, be_i32))
+         >> (PartitionMetadata {
                 error_code,
                 partition_id,
                 leader,
                 replicas,
                 isr,
+                offline_replicas: offline_replicas.unwrap_or_default(),
             })
         )
     )
@@ -174,20 +229,86 @@ mod tests {
 
         static ref TEST_RESPONSE: MetadataResponse = MetadataResponse {
             header: ResponseHeader { correlation_id: 123 },
+            throttle_time: None,
             brokers: vec![BrokerMetadata {
                 node_id: 1,
                 host: "host".to_owned(),
                 port: 80,
+                rack: None,
             }],
+            cluster_id: None,
+            controller_id: None,
             topics: vec![TopicMetadata {
                 error_code: 2,
                 topic_name: "topic".to_owned(),
+                is_internal: false,
                 partitions: vec![PartitionMetadata {
                     error_code: 3,
                     partition_id: 4,
                     leader: 5,
                     replicas: vec![6],
                     isr: vec![7],
+                    offline_replicas: vec![],
+                }],
+            }],
+        };
+
+        static ref TEST_RESPONSE_DATA_V5: Vec<u8> = vec![
+            // ResponseHeader
+            0, 0, 0, 123, // correlation_id
+            0, 0, 0, 10, // throttle_time
+            // brokers: [BrokerMetadata]
+            0, 0, 0, 1,
+                0, 0, 0, 1,                         // node_id
+                0, 4, b'h', b'o', b's', b't',       // host
+                0, 0, 0, 80,                        // port
+                0, 4, b'r', b'a', b'c', b'k',       // rack
+            0, 7, b'c', b'l', b'u', b's', b't', b'e', b'r', // cluster_id
+            0, 0, 0, 1, // controller_id
+            // topics: [TopicMetadata]
+            0, 0, 0, 1,
+                0, 2,                               // error_code
+                0, 5, b't', b'o', b'p', b'i', b'c', // topic_name
+                1,                                  // is_internal
+                // partitions: [PartitionMetadata]
+                0, 0, 0, 1,
+                    0, 3,                           // error_code
+                    0, 0, 0, 4,                     // partition_id
+                    0, 0, 0, 5,                     // leader
+                    // replicas: [ReplicaId]
+                    0, 0, 0, 2,
+                        0, 0, 0, 6,
+                        0, 0, 0, 8,
+                    // isr: [i32]
+                    0, 0, 0, 1,
+                        0, 0, 0, 6,
+                    // offline_replicas: [i32]
+                    0, 0, 0, 1,
+                        0, 0, 0, 8,
+        ];
+
+        static ref TEST_RESPONSE_V5: MetadataResponse = MetadataResponse {
+            header: ResponseHeader { correlation_id: 123 },
+            throttle_time: Some(10),
+            brokers: vec![BrokerMetadata {
+                node_id: 1,
+                host: "host".to_owned(),
+                port: 80,
+                rack: Some("rack".to_owned()),
+            }],
+            cluster_id: Some("cluster".to_owned()),
+            controller_id: Some(1),
+            topics: vec![TopicMetadata {
+                error_code: 2,
+                topic_name: "topic".to_owned(),
+                is_internal: true,
+                partitions: vec![PartitionMetadata {
+                    error_code: 3,
+                    partition_id: 4,
+                    leader: 5,
+                    replicas: vec![6, 8],
+                    isr: vec![6],
+                    offline_replicas: vec![8],
                 }],
             }],
         };
@@ -203,6 +324,7 @@ mod tests {
                 client_id: Some("client".into()),
             },
             topic_names: vec!["topic".into()],
+            allow_auto_topic_creation: false,
         };
 
         let mut buf = BytesMut::with_capacity(128);
@@ -214,11 +336,51 @@ mod tests {
         assert_eq!(&buf[..], &TEST_REQUEST_DATA[..]);
     }
 
+    #[test]
+    fn test_encode_metadata_request_v4() {
+        let req = MetadataRequest {
+            header: RequestHeader {
+                api_key: ApiKeys::Metadata as ApiKey,
+                api_version: 4,
+                correlation_id: 123,
+                client_id: Some("client".into()),
+            },
+            topic_names: vec![],
+            allow_auto_topic_creation: true,
+        };
+
+        let mut buf = BytesMut::with_capacity(128);
+
+        req.encode::<BigEndian>(&mut buf).unwrap();
+
+        assert_eq!(req.size(req.header.api_version), buf.len());
+
+        assert_eq!(
+            &buf[..],
+            &[
+                0, 3,                               // api_key
+                0, 4,                               // api_version
+                0, 0, 0, 123,                       // correlation_id
+                0, 6, 99, 108, 105, 101, 110, 116,  // client_id
+                255, 255, 255, 255,                 // topic_names: null
+                1,                                  // allow_auto_topic_creation
+            ][..]
+        );
+    }
+
     #[test]
     fn test_parse_metadata_response() {
         assert_eq!(
-            parse_metadata_response(TEST_RESPONSE_DATA.as_slice()),
+            parse_metadata_response(TEST_RESPONSE_DATA.as_slice(), 0),
             IResult::Done(&[][..], TEST_RESPONSE.clone())
         );
     }
+
+    #[test]
+    fn test_parse_metadata_response_v5() {
+        assert_eq!(
+            parse_metadata_response(TEST_RESPONSE_DATA_V5.as_slice(), 5),
+            IResult::Done(&[][..], TEST_RESPONSE_V5.clone())
+        );
+    }
 }
@@ -10,44 +10,67 @@ use errors::{Error, ErrorKind, Result};
 
 mod api_key;
 mod code;
+mod configs;
 mod encode;
 #[macro_use]
 mod parse;
 mod api_versions;
+mod delete_records;
 mod fetch;
 mod group;
 mod header;
+mod leader_epoch;
 mod list_offset;
 mod message;
 mod metadata;
 mod offset_commit;
 mod offset_fetch;
 mod produce;
+mod sasl;
 mod schema;
+mod topics;
 
 pub use self::api_key::{ApiKey, ApiKeys};
 pub use self::api_versions::{ApiVersionsRequest, ApiVersionsResponse, UsableApiVersion, UsableApiVersions, SUPPORTED_API_VERSIONS};
 pub use self::code::{ErrorCode, KafkaCode};
+pub use self::configs::{AlterConfigsEntry, AlterConfigsRequest, AlterConfigsResource, AlterConfigsResourceStatus,
+                        AlterConfigsResponse, ConfigSourceType, DescribeConfigsEntry, DescribeConfigsRequest,
+                        DescribeConfigsResource, DescribeConfigsResourceStatus, DescribeConfigsResponse,
+                        DescribeConfigsSynonym, ResourceType, BROKER_RESOURCE_TYPE, DEFAULT_CONFIG_SOURCE,
+                        TOPIC_RESOURCE_TYPE, UNKNOWN_CONFIG_SOURCE};
+pub use self::delete_records::{DeleteRecordsPartition, DeleteRecordsPartitionStatus, DeleteRecordsRequest,
+                               DeleteRecordsResponse, DeleteRecordsTopic, DeleteRecordsTopicStatus};
 pub use self::encode::{Encodable, WriteExt, ARRAY_LEN_SIZE, BYTES_LEN_SIZE, OFFSET_SIZE, PARTITION_ID_SIZE,
                        REPLICA_ID_SIZE, STR_LEN_SIZE, TIMESTAMP_SIZE};
-pub use self::fetch::{FetchPartition, FetchRequest, FetchResponse, FetchTopic, FetchTopicData,
-                      DEFAULT_RESPONSE_MAX_BYTES};
-pub use self::group::{DescribeGroupsRequest, DescribeGroupsResponse, GroupCoordinatorRequest,
-                      GroupCoordinatorResponse, HeartbeatRequest, HeartbeatResponse, JoinGroupMember,
-                      JoinGroupProtocol, JoinGroupRequest, JoinGroupResponse, LeaveGroupRequest, LeaveGroupResponse,
-                      ListGroupsRequest, ListGroupsResponse, SyncGroupAssignment, SyncGroupRequest, SyncGroupResponse};
+pub use self::fetch::{AbortedTransaction, FetchPartition, FetchPartitionData, FetchRequest, FetchResponse,
+                      FetchSessionEpoch, FetchSessionId, FetchTopic, FetchTopicData, ForgottenTopic,
+                      DEFAULT_RESPONSE_MAX_BYTES, FINAL_SESSION_EPOCH, INITIAL_SESSION_EPOCH, INVALID_SESSION_ID,
+                      READ_UNCOMMITTED};
+pub use self::group::{CoordinatorType, DescribeGroupsRequest, DescribeGroupsResponse, FindCoordinatorRequest,
+                      FindCoordinatorResponse, GroupCoordinatorRequest, GroupCoordinatorResponse, HeartbeatRequest,
+                      HeartbeatResponse, JoinGroupMember, JoinGroupProtocol, JoinGroupRequest, JoinGroupResponse,
+                      LeaveGroupRequest, LeaveGroupResponse, ListGroupsRequest, ListGroupsResponse,
+                      SyncGroupAssignment, SyncGroupRequest, SyncGroupResponse};
 pub use self::header::{parse_response_header, RequestHeader, ResponseHeader};
+pub use self::leader_epoch::{OffsetsForLeaderEpochPartition, OffsetsForLeaderEpochPartitionStatus,
+                             OffsetsForLeaderEpochRequest, OffsetsForLeaderEpochResponse, OffsetsForLeaderEpochTopic,
+                             OffsetsForLeaderEpochTopicStatus};
 pub use self::list_offset::{FetchOffset, ListOffsetRequest, ListOffsetResponse, ListPartitionOffset, ListTopicOffset,
-                            EARLIEST_TIMESTAMP, LATEST_TIMESTAMP};
-pub use self::message::{parse_message_set, Message, MessageSet, MessageSetBuilder, MessageSetEncoder,
-                        MessageTimestamp, RecordFormat};
+                            DEFAULT_MAX_NUMBER_OF_OFFSETS, EARLIEST_TIMESTAMP, LATEST_TIMESTAMP};
+pub use self::message::{parse_message_set, parse_message_set_with, record_size, CrcPolicy, Message, MessageSet,
+                        MessageSetBuilder, MessageSetEncoder, MessageTimestamp, RecordFormat};
 pub use self::metadata::{BrokerMetadata, MetadataRequest, MetadataResponse, PartitionMetadata, TopicMetadata};
 pub use self::offset_commit::{OffsetCommitPartition, OffsetCommitRequest, OffsetCommitResponse, OffsetCommitTopic};
 pub use self::offset_fetch::{OffsetFetchPartition, OffsetFetchRequest, OffsetFetchResponse, OffsetFetchTopic};
-pub use self::parse::{display_parse_error, parse_bytes, parse_opt_bytes, parse_opt_str, parse_opt_string, parse_str,
-                      parse_string, ParseTag, PARSE_TAGS};
+pub use self::parse::{display_parse_error, parse_bytes, parse_frame, parse_opt_bytes, parse_opt_str, parse_opt_string,
+                      parse_str, parse_string, parse_varint, parse_varint_opt_bytes, parse_varlong, ParseTag,
+                      PARSE_TAGS};
 pub use self::produce::{ProducePartitionData, ProduceRequest, ProduceResponse, ProduceTopicData};
+pub use self::sasl::{SaslAuthenticateRequest, SaslAuthenticateResponse, SaslHandshakeRequest, SaslHandshakeResponse};
 pub use self::schema::{Nullable, Schema, SchemaType, VarInt, VarLong};
+pub use self::topics::{CreateTopicsConfigEntry, CreateTopicsReplicaAssignment, CreateTopicsRequest,
+                       CreateTopicsResponse, CreateTopicsTopic, CreateTopicsTopicStatus, DeleteTopicsRequest,
+                       DeleteTopicsResponse, DeleteTopicsTopicStatus};
 
 /// Normal client consumers should always specify this as -1 as they have no
 /// node id.
@@ -86,6 +109,15 @@ pub type Timestamp = i64;
 /// The broker id.
 pub type NodeId = i32;
 
+/// The epoch of the partition leader, which is bumped whenever a new leader is elected.
+pub type LeaderEpoch = i32;
+
+/// The leader epoch is unknown, e.g. the records were written in the message format before v2.
+pub const UNDEFINED_LEADER_EPOCH: LeaderEpoch = -1;
+
+/// The end offset of an unknown leader epoch.
+pub const UNDEFINED_EPOCH_OFFSET: Offset = -1;
+
 /// Broker id of the follower.
 pub type ReplicaId = i32;
 
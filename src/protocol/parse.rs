@@ -1,5 +1,7 @@
 use std::borrow::Cow;
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::mem;
 use std::str;
 
 use bytes::Bytes;
@@ -36,6 +38,10 @@ pub enum ParseTag {
     MessageSet = 9001,
     Message = 9002,
     MessageCrc = 9003,
+    RecordBatch = 9004,
+    RecordBatchCrc = 9005,
+    Record = 9006,
+    Varint = 9007,
 
     ProduceResponse = 10000,
     ProduceTopicStatus = 10001,
@@ -80,8 +86,34 @@ pub enum ParseTag {
     ListGroupsResponse = 11600,
     ListGroupsGroupStatus = 11601,
 
+    SaslHandshakeResponse = 11700,
+
     ApiVersionsResponse = 11800,
     ApiVersion = 11801,
+
+    CreateTopicsResponse = 11900,
+    CreateTopicsTopicStatus = 11901,
+
+    DeleteTopicsResponse = 12000,
+    DeleteTopicsTopicStatus = 12001,
+
+    DeleteRecordsResponse = 12100,
+    DeleteRecordsTopicStatus = 12101,
+    DeleteRecordsPartitionStatus = 12102,
+
+    OffsetsForLeaderEpochResponse = 12300,
+    OffsetsForLeaderEpochTopicStatus = 12301,
+    OffsetsForLeaderEpochPartitionStatus = 12302,
+
+    DescribeConfigsResponse = 13200,
+    DescribeConfigsResourceStatus = 13201,
+    DescribeConfigsEntry = 13202,
+    DescribeConfigsSynonym = 13203,
+
+    AlterConfigsResponse = 13300,
+    AlterConfigsResourceStatus = 13301,
+
+    SaslAuthenticateResponse = 13600,
 }
 
 lazy_static! {
@@ -95,6 +127,10 @@ lazy_static! {
         h.insert(ParseTag::MessageSet as u32, "MessageSet");
         h.insert(ParseTag::Message as u32, "Message");
         h.insert(ParseTag::MessageCrc as u32, "MessageCrc");
+        h.insert(ParseTag::RecordBatch as u32, "RecordBatch");
+        h.insert(ParseTag::RecordBatchCrc as u32, "RecordBatchCrc");
+        h.insert(ParseTag::Record as u32, "Record");
+        h.insert(ParseTag::Varint as u32, "Varint");
 
         h.insert(ParseTag::ProduceResponse as u32, "ProduceResponse");
         h.insert(ParseTag::ProduceTopicStatus as u32, "ProduceTopicStatus");
@@ -148,8 +184,34 @@ lazy_static! {
         h.insert(ParseTag::ListGroupsResponse as u32, "ListGroupsResponse");
         h.insert(ParseTag::ListGroupsGroupStatus as u32, "ListGroupsGroupStatus");
 
+        h.insert(ParseTag::SaslHandshakeResponse as u32, "SaslHandshakeResponse");
+
         h.insert(ParseTag::ApiVersionsResponse as u32, "ApiVersionsResponse");
         h.insert(ParseTag::ApiVersion as u32, "ApiVersion");
+
+        h.insert(ParseTag::CreateTopicsResponse as u32, "CreateTopicsResponse");
+        h.insert(ParseTag::CreateTopicsTopicStatus as u32, "CreateTopicsTopicStatus");
+
+        h.insert(ParseTag::DeleteTopicsResponse as u32, "DeleteTopicsResponse");
+        h.insert(ParseTag::DeleteTopicsTopicStatus as u32, "DeleteTopicsTopicStatus");
+
+        h.insert(ParseTag::DeleteRecordsResponse as u32, "DeleteRecordsResponse");
+        h.insert(ParseTag::DeleteRecordsTopicStatus as u32, "DeleteRecordsTopicStatus");
+        h.insert(ParseTag::DeleteRecordsPartitionStatus as u32, "DeleteRecordsPartitionStatus");
+
+        h.insert(ParseTag::OffsetsForLeaderEpochResponse as u32, "OffsetsForLeaderEpochResponse");
+        h.insert(ParseTag::OffsetsForLeaderEpochTopicStatus as u32, "OffsetsForLeaderEpochTopicStatus");
+        h.insert(ParseTag::OffsetsForLeaderEpochPartitionStatus as u32, "OffsetsForLeaderEpochPartitionStatus");
+
+        h.insert(ParseTag::DescribeConfigsResponse as u32, "DescribeConfigsResponse");
+        h.insert(ParseTag::DescribeConfigsResourceStatus as u32, "DescribeConfigsResourceStatus");
+        h.insert(ParseTag::DescribeConfigsEntry as u32, "DescribeConfigsEntry");
+        h.insert(ParseTag::DescribeConfigsSynonym as u32, "DescribeConfigsSynonym");
+
+        h.insert(ParseTag::AlterConfigsResponse as u32, "AlterConfigsResponse");
+        h.insert(ParseTag::AlterConfigsResourceStatus as u32, "AlterConfigsResourceStatus");
+
+        h.insert(ParseTag::SaslAuthenticateResponse as u32, "SaslAuthenticateResponse");
         h
     };
 }
@@ -260,11 +322,46 @@ named!(pub parse_string<String>,
     )
 );
 
+thread_local!(static FRAME: RefCell<Option<Bytes>> = RefCell::new(None));
+
+/// Parse the buffer of frame, the parsed bytes will share the memory of frame instead of being copied.
+///
+/// The huge records could be decoded without multiple copies,
+/// but the whole frame will be kept in memory until all the parsed bytes are dropped.
+pub fn parse_frame<'f, F, T>(frame: &'f Bytes, f: F) -> T
+where
+    F: FnOnce(&'f [u8]) -> T,
+{
+    let parent = FRAME.with(|current| mem::replace(&mut *current.borrow_mut(), Some(frame.clone())));
+    let result = f(&frame[..]);
+
+    FRAME.with(|current| *current.borrow_mut() = parent);
+
+    result
+}
+
+/// Slice the bytes from the frame being parsed, or copy them if they are not in the frame.
+fn slice_frame(data: &[u8]) -> Bytes {
+    FRAME.with(|current| match *current.borrow() {
+        Some(ref frame) => {
+            let base = frame.as_ptr() as usize;
+            let ptr = data.as_ptr() as usize;
+
+            if base <= ptr && ptr + data.len() <= base + frame.len() {
+                frame.slice(ptr - base, ptr - base + data.len())
+            } else {
+                Bytes::from(data)
+            }
+        }
+        None => Bytes::from(data),
+    })
+}
+
 named!(pub parse_bytes<Bytes>,
     parse_tag!(ParseTag::Bytes,
         do_parse!(
             len: be_i32
-         >> s: cond_reduce!(len >= 0, map!(take!(len), Bytes::from))
+         >> s: cond_reduce!(len >= 0, map!(take!(len), slice_frame))
          >> (s)
         )
     )
@@ -274,7 +371,41 @@ named!(pub parse_opt_bytes<Option<Bytes>>,
     parse_tag!(ParseTag::Bytes,
         do_parse!(
             len: be_i32
-         >> s: cond!(len >= 0, map!(take!(len), Bytes::from))
+         >> s: cond!(len >= 0, map!(take!(len), slice_frame))
+         >> (s)
+        )
+    )
+);
+
+/// The max bytes of a zigzag encoded 64-bit integer.
+const MAX_VARLONG_SIZE: usize = 10;
+
+/// Parse a zigzag encoded variable-length integer, which is used by the record batch (v2).
+pub fn parse_varlong(input: &[u8]) -> IResult<&[u8], i64> {
+    let mut value = 0u64;
+
+    for (i, &b) in input.iter().take(MAX_VARLONG_SIZE).enumerate() {
+        value |= u64::from(b & 0x7f) << (7 * i);
+
+        if b & 0x80 == 0 {
+            return IResult::Done(&input[i + 1..], (value >> 1) as i64 ^ -((value & 1) as i64));
+        }
+    }
+
+    if input.len() < MAX_VARLONG_SIZE {
+        IResult::Incomplete(nom::Needed::Unknown)
+    } else {
+        IResult::Error(error_position!(nom::ErrorKind::Custom(ParseTag::Varint as u32), input))
+    }
+}
+
+named!(pub parse_varint<i32>, map!(parse_varlong, |value: i64| value as i32));
+
+named!(pub parse_varint_opt_bytes<Option<Bytes>>,
+    parse_tag!(ParseTag::Bytes,
+        do_parse!(
+            len: parse_varint
+         >> s: cond!(len >= 0, map!(take!(len), slice_frame))
          >> (s)
         )
     )
@@ -359,6 +490,25 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_frame() {
+        let mut data = vec![0, 0, 0, 64];
+
+        data.extend(0..64);
+
+        let frame = Bytes::from(data);
+
+        let (_, parsed) = parse_frame(&frame, |buf| parse_bytes(buf).unwrap());
+
+        assert_eq!(&parsed[..], &frame[4..]);
+        assert_eq!(parsed.as_ptr(), frame[4..].as_ptr());
+
+        let (_, copied) = parse_bytes(&frame[..]).unwrap();
+
+        assert_eq!(copied, parsed);
+        assert_ne!(copied.as_ptr(), frame[4..].as_ptr());
+    }
+
     #[test]
     fn test_parse_opt_bytes() {
         assert_eq!(parse_opt_bytes(b"\0"), IResult::Incomplete(Needed::Size(4)));
@@ -372,4 +522,28 @@ mod tests {
             IResult::Done(&b""[..], Some(Bytes::from(&b"test"[..])))
         );
     }
+
+    #[test]
+    fn test_parse_varlong() {
+        assert_eq!(parse_varlong(b"\x00"), IResult::Done(&b""[..], 0));
+        assert_eq!(parse_varlong(b"\x01"), IResult::Done(&b""[..], -1));
+        assert_eq!(parse_varlong(b"\x02"), IResult::Done(&b""[..], 1));
+        assert_eq!(parse_varlong(b"\x96\x01\xff"), IResult::Done(&b"\xff"[..], 75));
+        assert_eq!(parse_varlong(b"\x96"), IResult::Incomplete(Needed::Unknown));
+        assert_eq!(
+            parse_varlong(b"\xfe\xff\xff\xff\x0f"),
+            IResult::Done(&b""[..], i64::from(i32::max_value()))
+        );
+        assert!(parse_varlong(&[0xff; 11][..]).is_err());
+    }
+
+    #[test]
+    fn test_parse_varint_opt_bytes() {
+        assert_eq!(parse_varint_opt_bytes(b"\x01"), IResult::Done(&b""[..], None));
+        assert_eq!(parse_varint_opt_bytes(b"\x00"), IResult::Done(&b""[..], Some(Bytes::new())));
+        assert_eq!(
+            parse_varint_opt_bytes(b"\x08test"),
+            IResult::Done(&b""[..], Some(Bytes::from(&b"test"[..])))
+        );
+    }
 }
@@ -260,6 +260,7 @@ mod tests {
                                         key: Some(Bytes::from(&b"key"[..])),
                                         value: Some(Bytes::from(&b"value"[..])),
                                         timestamp: Some(MessageTimestamp::CreateTime(456)),
+                                        leader_epoch: None,
                                     },
                                 ],
                             }),
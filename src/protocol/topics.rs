@@ -0,0 +1,364 @@
+use std::borrow::Cow;
+
+use bytes::{BufMut, ByteOrder, BytesMut};
+
+use nom::{IResult, be_i16, be_i32};
+
+use errors::Result;
+use protocol::{parse_response_header, parse_string, ApiVersion, Encodable, ErrorCode, ParseTag, PartitionId, Record,
+               RequestHeader, ResponseHeader, WriteExt, ARRAY_LEN_SIZE, PARTITION_ID_SIZE, STR_LEN_SIZE};
+
+const NUM_PARTITIONS_SIZE: usize = 4;
+const REPLICATION_FACTOR_SIZE: usize = 2;
+const REPLICA_SIZE: usize = 4;
+const TIMEOUT_SIZE: usize = 4;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CreateTopicsRequest<'a> {
+    pub header: RequestHeader<'a>,
+    /// An array of single topic creation requests.
+    pub topics: Vec<CreateTopicsTopic<'a>>,
+    /// The time in ms to wait for a topic to be completely created on the controller node.
+    pub timeout: i32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CreateTopicsTopic<'a> {
+    /// The name of the topic.
+    pub topic_name: Cow<'a, str>,
+    /// Number of partitions to be created, -1 indicates unset.
+    pub num_partitions: i32,
+    /// Replication factor for the topic, -1 indicates unset.
+    pub replication_factor: i16,
+    /// Replica assignment among kafka brokers for this topic partitions.
+    ///
+    /// If this is set num_partitions and replication_factor must be unset.
+    pub replica_assignment: Vec<CreateTopicsReplicaAssignment>,
+    /// Topic level configuration for topic to be set.
+    pub configs: Vec<CreateTopicsConfigEntry<'a>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CreateTopicsReplicaAssignment {
+    /// The partition id.
+    pub partition_id: PartitionId,
+    /// The set of all nodes that should host this partition.
+    pub replicas: Vec<i32>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CreateTopicsConfigEntry<'a> {
+    /// Configuration name
+    pub config_name: Cow<'a, str>,
+    /// Configuration value
+    pub config_value: Option<Cow<'a, str>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CreateTopicsResponse {
+    pub header: ResponseHeader,
+    /// An array of per topic error codes.
+    pub topics: Vec<CreateTopicsTopicStatus>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CreateTopicsTopicStatus {
+    /// The name of the topic.
+    pub topic_name: String,
+    /// Error code.
+    pub error_code: ErrorCode,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeleteTopicsRequest<'a> {
+    pub header: RequestHeader<'a>,
+    /// An array of topics to be deleted.
+    pub topic_names: Vec<Cow<'a, str>>,
+    /// The time in ms to wait for a topic to be completely deleted on the controller node.
+    pub timeout: i32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeleteTopicsResponse {
+    pub header: ResponseHeader,
+    /// An array of per topic error codes.
+    pub topics: Vec<DeleteTopicsTopicStatus>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeleteTopicsTopicStatus {
+    /// The name of the topic.
+    pub topic_name: String,
+    /// Error code.
+    pub error_code: ErrorCode,
+}
+
+impl<'a> Record for CreateTopicsRequest<'a> {
+    fn size(&self, api_version: ApiVersion) -> usize {
+        self.header.size(api_version) + self.topics.iter().fold(ARRAY_LEN_SIZE, |size, topic| {
+            size + STR_LEN_SIZE + topic.topic_name.len() + NUM_PARTITIONS_SIZE + REPLICATION_FACTOR_SIZE
+                + topic
+                    .replica_assignment
+                    .iter()
+                    .fold(ARRAY_LEN_SIZE, |size, assignment| {
+                        size + PARTITION_ID_SIZE + ARRAY_LEN_SIZE + assignment.replicas.len() * REPLICA_SIZE
+                    })
+                + topic.configs.iter().fold(ARRAY_LEN_SIZE, |size, config| {
+                    size + STR_LEN_SIZE + config.config_name.len() + STR_LEN_SIZE
+                        + config.config_value.as_ref().map_or(0, |value| value.len())
+                })
+        }) + TIMEOUT_SIZE
+    }
+}
+
+impl<'a> Encodable for CreateTopicsRequest<'a> {
+    fn encode<T: ByteOrder>(&self, dst: &mut BytesMut) -> Result<()> {
+        self.header.encode::<T>(dst)?;
+
+        dst.put_array::<T, _, _>(&self.topics, |buf, topic| {
+            buf.put_str::<T, _>(Some(topic.topic_name.as_ref()))?;
+            buf.put_i32::<T>(topic.num_partitions);
+            buf.put_i16::<T>(topic.replication_factor);
+            buf.put_array::<T, _, _>(&topic.replica_assignment, |buf, assignment| {
+                buf.put_i32::<T>(assignment.partition_id);
+                buf.put_array::<T, _, _>(&assignment.replicas, |buf, replica| {
+                    buf.put_i32::<T>(*replica);
+                    Ok(())
+                })
+            })?;
+            buf.put_array::<T, _, _>(&topic.configs, |buf, config| {
+                buf.put_str::<T, _>(Some(config.config_name.as_ref()))?;
+                buf.put_str::<T, _>(config.config_value.as_ref())
+            })
+        })?;
+
+        dst.put_i32::<T>(self.timeout);
+
+        Ok(())
+    }
+}
+
+impl<'a> Record for DeleteTopicsRequest<'a> {
+    fn size(&self, api_version: ApiVersion) -> usize {
+        self.header.size(api_version)
+            + self.topic_names
+                .iter()
+                .fold(ARRAY_LEN_SIZE, |size, topic_name| size + STR_LEN_SIZE + topic_name.len())
+            + TIMEOUT_SIZE
+    }
+}
+
+impl<'a> Encodable for DeleteTopicsRequest<'a> {
+    fn encode<T: ByteOrder>(&self, dst: &mut BytesMut) -> Result<()> {
+        self.header.encode::<T>(dst)?;
+
+        dst.put_array::<T, _, _>(&self.topic_names, |buf, topic_name| {
+            buf.put_str::<T, _>(Some(topic_name.as_ref()))
+        })?;
+
+        dst.put_i32::<T>(self.timeout);
+
+        Ok(())
+    }
+}
+
+impl CreateTopicsResponse {
+    pub fn parse(buf: &[u8]) -> IResult<&[u8], Self> {
+        parse_create_topics_response(buf)
+    }
+}
+
+named!(
+    parse_create_topics_response<CreateTopicsResponse>,
+    parse_tag!(
+        ParseTag::CreateTopicsResponse,
+        do_parse!(
+            header: parse_response_header >> topics: length_count!(be_i32, parse_create_topics_topic_status)
+                >> (CreateTopicsResponse { header, topics })
+        )
+    )
+);
+
+named!(
+    parse_create_topics_topic_status<CreateTopicsTopicStatus>,
+    parse_tag!(
+        ParseTag::CreateTopicsTopicStatus,
+        do_parse!(
+            topic_name: parse_string >> error_code: be_i16 >> (CreateTopicsTopicStatus {
+                topic_name,
+                error_code,
+            })
+        )
+    )
+);
+
+impl DeleteTopicsResponse {
+    pub fn parse(buf: &[u8]) -> IResult<&[u8], Self> {
+        parse_delete_topics_response(buf)
+    }
+}
+
+named!(
+    parse_delete_topics_response<DeleteTopicsResponse>,
+    parse_tag!(
+        ParseTag::DeleteTopicsResponse,
+        do_parse!(
+            header: parse_response_header >> topics: length_count!(be_i32, parse_delete_topics_topic_status)
+                >> (DeleteTopicsResponse { header, topics })
+        )
+    )
+);
+
+named!(
+    parse_delete_topics_topic_status<DeleteTopicsTopicStatus>,
+    parse_tag!(
+        ParseTag::DeleteTopicsTopicStatus,
+        do_parse!(
+            topic_name: parse_string >> error_code: be_i16 >> (DeleteTopicsTopicStatus {
+                topic_name,
+                error_code,
+            })
+        )
+    )
+);
+
+#[cfg(test)]
+mod tests {
+    use bytes::BigEndian;
+
+    use nom::IResult;
+
+    use protocol::*;
+
+    use super::*;
+
+    #[test]
+    fn test_encode_create_topics_request() {
+        let req = CreateTopicsRequest {
+            header: RequestHeader {
+                api_key: ApiKeys::CreateTopics as ApiKey,
+                api_version: 0,
+                correlation_id: 123,
+                client_id: Some("client".into()),
+            },
+            topics: vec![
+                CreateTopicsTopic {
+                    topic_name: "topic".into(),
+                    num_partitions: -1,
+                    replication_factor: -1,
+                    replica_assignment: vec![
+                        CreateTopicsReplicaAssignment {
+                            partition_id: 0,
+                            replicas: vec![1, 2],
+                        },
+                    ],
+                    configs: vec![
+                        CreateTopicsConfigEntry {
+                            config_name: "key".into(),
+                            config_value: Some("value".into()),
+                        },
+                    ],
+                },
+            ],
+            timeout: 1000,
+        };
+
+        let data = vec![
+            /* CreateTopicsRequest
+             * RequestHeader */ 0, 19 /* api_key */, 0,
+            0 /* api_version */, 0, 0, 0, 123 /* correlation_id */, 0, 6, b'c', b'l', b'i', b'e', b'n',
+            b't' /* client_id */, /* topics: [CreateTopicsTopic] */ 0, 0, 0, 1, /* CreateTopicsTopic */ 0, 5,
+            b't', b'o', b'p', b'i', b'c' /* topic_name */, 255, 255, 255, 255 /* num_partitions */, 255,
+            255 /* replication_factor */, /* replica_assignment: [CreateTopicsReplicaAssignment] */ 0, 0, 0, 1,
+            0, 0, 0, 0 /* partition_id */, 0, 0, 0, 2, 0, 0, 0, 1, 0, 0, 0, 2 /* replicas */,
+            /* configs: [CreateTopicsConfigEntry] */ 0, 0, 0, 1, 0, 3, b'k', b'e', b'y' /* config_name */, 0, 5,
+            b'v', b'a', b'l', b'u', b'e' /* config_value */, 0, 0, 3, 232 /* timeout */,
+        ];
+
+        let mut buf = BytesMut::with_capacity(128);
+
+        req.encode::<BigEndian>(&mut buf).unwrap();
+
+        assert_eq!(req.size(req.header.api_version), buf.len());
+
+        assert_eq!(&buf[..], &data[..]);
+    }
+
+    #[test]
+    fn test_parse_create_topics_response() {
+        let response = CreateTopicsResponse {
+            header: ResponseHeader { correlation_id: 123 },
+            topics: vec![
+                CreateTopicsTopicStatus {
+                    topic_name: "topic".to_owned(),
+                    error_code: 36,
+                },
+            ],
+        };
+
+        let data = vec![
+            /* ResponseHeader */ 0, 0, 0, 123 /* correlation_id */,
+            /* topics: [CreateTopicsTopicStatus] */ 0, 0, 0, 1, 0, 5, b't', b'o', b'p', b'i',
+            b'c' /* topic_name */, 0, 36 /* error_code */,
+        ];
+
+        assert_eq!(
+            parse_create_topics_response(data.as_slice()),
+            IResult::Done(&[][..], response)
+        );
+    }
+
+    #[test]
+    fn test_encode_delete_topics_request() {
+        let req = DeleteTopicsRequest {
+            header: RequestHeader {
+                api_key: ApiKeys::DeleteTopics as ApiKey,
+                api_version: 0,
+                correlation_id: 123,
+                client_id: Some("client".into()),
+            },
+            topic_names: vec!["topic".into()],
+            timeout: 1000,
+        };
+
+        let data = vec![
+            /* DeleteTopicsRequest
+             * RequestHeader */ 0, 20 /* api_key */, 0,
+            0 /* api_version */, 0, 0, 0, 123 /* correlation_id */, 0, 6, b'c', b'l', b'i', b'e', b'n',
+            b't' /* client_id */, /* topic_names */ 0, 0, 0, 1, 0, 5, b't', b'o', b'p', b'i', b'c', 0, 0, 3,
+            232 /* timeout */,
+        ];
+
+        let mut buf = BytesMut::with_capacity(128);
+
+        req.encode::<BigEndian>(&mut buf).unwrap();
+
+        assert_eq!(req.size(req.header.api_version), buf.len());
+
+        assert_eq!(&buf[..], &data[..]);
+    }
+
+    #[test]
+    fn test_parse_delete_topics_response() {
+        let response = DeleteTopicsResponse {
+            header: ResponseHeader { correlation_id: 123 },
+            topics: vec![
+                DeleteTopicsTopicStatus {
+                    topic_name: "topic".to_owned(),
+                    error_code: 3,
+                },
+            ],
+        };
+
+        let data = vec![
+            /* ResponseHeader */ 0, 0, 0, 123 /* correlation_id */,
+            /* topics: [DeleteTopicsTopicStatus] */ 0, 0, 0, 1, 0, 5, b't', b'o', b'p', b'i',
+            b'c' /* topic_name */, 0, 3 /* error_code */,
+        ];
+
+        assert_eq!(
+            parse_delete_topics_response(data.as_slice()),
+            IResult::Done(&[][..], response)
+        );
+    }
+}
@@ -0,0 +1,211 @@
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+use bytes::{BigEndian, Buf, BufMut};
+
+use avro_rs::{self, Schema};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use futures::Future;
+
+use client::{StaticBoxFuture, ToStaticBoxFuture};
+use errors::{Error, ErrorKind, Result};
+use serialization::{Deserializer, SchemaId, SchemaRegistry, Serializer};
+
+/// The magic byte of the Confluent wire format.
+const MAGIC_BYTE: u8 = 0;
+
+/// The size of magic byte and schema id before the Avro data.
+const HEADER_SIZE: usize = 5;
+
+/// Serialize the typed data as Avro in the Confluent wire format.
+///
+/// The data is prefixed with a magic byte and the schema id, so the consumers could fetch the schema
+/// from the Schema Registry.
+#[derive(Clone, Debug)]
+pub struct AvroSerializer<T> {
+    schema_id: SchemaId,
+    schema: Rc<Schema>,
+    phantom: PhantomData<T>,
+}
+
+impl<T> AvroSerializer<T>
+where
+    T: 'static,
+{
+    /// Construct an `AvroSerializer` with a registered schema.
+    pub fn new(schema_id: SchemaId, schema: Rc<Schema>) -> Self {
+        AvroSerializer {
+            schema_id,
+            schema,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Register the schema under the subject, and construct an `AvroSerializer` with it.
+    pub fn register(registry: &SchemaRegistry, subject: &str, schema: &str) -> StaticBoxFuture<Self> {
+        let registry = registry.clone();
+
+        registry
+            .register(subject, schema)
+            .and_then(move |schema_id| {
+                registry
+                    .schema(schema_id)
+                    .map(|schema| AvroSerializer::new(schema_id, schema))
+                    .ok_or_else(|| ErrorKind::SchemaNotFound(schema_id).into())
+            })
+            .static_boxed()
+    }
+
+    /// The id of the schema
+    pub fn schema_id(&self) -> SchemaId {
+        self.schema_id
+    }
+}
+
+impl<T> Serializer for AvroSerializer<T>
+where
+    T: Serialize,
+{
+    type Item = T;
+    type Error = Error;
+
+    fn serialize_to<B: BufMut>(&self, _topic_name: &str, data: Self::Item, buf: &mut B) -> Result<()> {
+        let value = avro_rs::to_value(data).map_err(|err| ErrorKind::AvroError(err.to_string()))?;
+        let datum = avro_rs::to_avro_datum(&self.schema, value).map_err(|err| ErrorKind::AvroError(err.to_string()))?;
+
+        buf.put_u8(MAGIC_BYTE);
+        buf.put_u32::<BigEndian>(self.schema_id);
+        buf.put_slice(&datum);
+        Ok(())
+    }
+}
+
+/// Deserialize the typed data from Avro in the Confluent wire format.
+///
+/// The writer schema is looked up from the cache of `SchemaRegistry` by the schema id.
+/// The record of an unknown schema fails with `ErrorKind::SchemaNotFound`, and the schema will be fetched
+/// in the background, use `SchemaRegistry::fetch` to load the schemas before consuming.
+#[derive(Clone)]
+pub struct AvroDeserializer<T> {
+    registry: SchemaRegistry,
+    reader_schema: Option<Rc<Schema>>,
+    phantom: PhantomData<T>,
+}
+
+impl<T> AvroDeserializer<T> {
+    pub fn new(registry: SchemaRegistry) -> Self {
+        AvroDeserializer {
+            registry,
+            reader_schema: None,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Resolve the writer schema to the reader schema, for the schema evolution.
+    pub fn with_reader_schema(mut self, schema: Rc<Schema>) -> Self {
+        self.reader_schema = Some(schema);
+        self
+    }
+}
+
+impl<T> Deserializer for AvroDeserializer<T>
+where
+    T: DeserializeOwned,
+{
+    type Item = T;
+    type Error = Error;
+
+    fn deserialize_to<B: Buf>(&self, topic_name: &str, buf: &mut B, data: &mut Self::Item) -> Result<()> {
+        *data = self.deserialize(topic_name, buf)?;
+        Ok(())
+    }
+
+    fn deserialize<B: Buf>(&self, _topic_name: &str, buf: &mut B) -> Result<Self::Item> {
+        if buf.remaining() < HEADER_SIZE {
+            bail!(ErrorKind::ParseError("missing schema id".to_owned()))
+        }
+
+        let magic = buf.get_u8();
+
+        if magic != MAGIC_BYTE {
+            bail!(ErrorKind::ParseError(format!("unknown magic byte {}", magic)))
+        }
+
+        let schema_id = buf.get_u32::<BigEndian>();
+        let schema = match self.registry.schema(schema_id) {
+            Some(schema) => schema,
+            None => {
+                self.registry.prefetch(schema_id);
+
+                bail!(ErrorKind::SchemaNotFound(schema_id))
+            }
+        };
+
+        let len = buf.remaining();
+        let value = {
+            let mut reader = buf.bytes();
+
+            avro_rs::from_avro_datum(&schema, &mut reader, self.reader_schema.as_ref().map(|schema| &**schema))
+                .map_err(|err| ErrorKind::AvroError(err.to_string()))?
+        };
+        buf.advance(len);
+
+        Ok(avro_rs::from_value(&value).map_err(|err| ErrorKind::AvroError(err.to_string()))?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use tokio_core::reactor::Core;
+
+    use super::*;
+
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    struct User {
+        name: String,
+        age: i32,
+    }
+
+    const USER_SCHEMA: &str = r#"{
+        "type": "record",
+        "name": "User",
+        "fields": [
+            {"name": "name", "type": "string"},
+            {"name": "age", "type": "int"}
+        ]
+    }"#;
+
+    #[test]
+    fn test_wire_format() {
+        let core = Core::new().unwrap();
+        let registry = SchemaRegistry::new("http://localhost:8081", &core.handle());
+        let schema = Schema::parse_str(USER_SCHEMA).unwrap();
+        let user = User {
+            name: "Alice".to_owned(),
+            age: 30,
+        };
+
+        let serializer = AvroSerializer::new(123, Rc::new(schema.clone()));
+        let deserializer = AvroDeserializer::<User>::new(registry.clone());
+
+        let buf = serializer.serialize("topic", user.clone()).unwrap();
+
+        assert_eq!(&buf[..HEADER_SIZE], &[0, 0, 0, 0, 123]);
+        assert_eq!(&buf[HEADER_SIZE..], &[10, b'A', b'l', b'i', b'c', b'e', 60]);
+
+        assert!(deserializer.deserialize("topic", &mut Cursor::new(buf.clone())).is_err());
+
+        registry.cache(123, schema);
+
+        assert_eq!(deserializer.deserialize("topic", &mut Cursor::new(buf)).unwrap(), user);
+        assert!(
+            deserializer
+                .deserialize("topic", &mut Cursor::new(vec![1, 0, 0, 0, 123]))
+                .is_err()
+        );
+    }
+}
@@ -4,12 +4,13 @@ use std::str;
 use bytes::{Buf, BufMut};
 
 use serde;
-use serde_json::{self, Value};
+use serde::de::DeserializeOwned;
+use serde_json;
 
 use errors::{Error, Result};
 use serialization::{Deserializer, Serializer};
 
-/// Serialize `String` with UTF-8 encoding
+/// Serialize the typed data as JSON
 #[derive(Clone, Debug, Default)]
 pub struct JsonSerializer<T> {
     pretty: bool,
@@ -17,6 +18,13 @@ pub struct JsonSerializer<T> {
 }
 
 impl<T> JsonSerializer<T> {
+    pub fn new() -> Self {
+        JsonSerializer {
+            pretty: false,
+            phantom: PhantomData,
+        }
+    }
+
     pub fn pretty() -> Self {
         JsonSerializer {
             pretty: true,
@@ -44,25 +52,35 @@ where
     }
 }
 
-/// Deserialize `String` as UTF-8 encoding
+/// Deserialize the typed data from JSON
 #[derive(Clone, Debug, Default)]
 pub struct JsonDeserializer<T> {
     phantom: PhantomData<T>,
 }
 
-impl<'de, T> Deserializer for JsonDeserializer<T>
+impl<T> JsonDeserializer<T> {
+    pub fn new() -> Self {
+        JsonDeserializer { phantom: PhantomData }
+    }
+}
+
+impl<T> Deserializer for JsonDeserializer<T>
 where
-    T: serde::Deserialize<'de>,
+    T: DeserializeOwned,
 {
     type Item = T;
     type Error = Error;
 
-    fn deserialize_to<B: Buf>(&self, _topic_name: &str, buf: &mut B, data: &mut Self::Item) -> Result<()> {
+    fn deserialize_to<B: Buf>(&self, topic_name: &str, buf: &mut B, data: &mut Self::Item) -> Result<()> {
+        *data = self.deserialize(topic_name, buf)?;
+        Ok(())
+    }
+
+    fn deserialize<B: Buf>(&self, _topic_name: &str, buf: &mut B) -> Result<Self::Item> {
         let len = buf.remaining();
-        let v: Value = serde_json::from_slice(buf.bytes())?;
-        *data = T::deserialize(v)?;
+        let data = serde_json::from_slice(buf.bytes())?;
         buf.advance(len);
-        Ok(())
+        Ok(data)
     }
 }
 
@@ -106,4 +124,29 @@ mod tests {
 
         assert_eq!(deserializer.deserialize("topic", &mut cur).unwrap(), d);
     }
+
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+    struct Event {
+        name: String,
+        tags: Vec<String>,
+        parent: Option<Box<Event>>,
+    }
+
+    #[test]
+    fn test_typed_struct() {
+        let event = Event {
+            name: "child".to_owned(),
+            tags: vec!["tag".to_owned()],
+            parent: Some(Box::new(Event {
+                name: "parent".to_owned(),
+                tags: vec![],
+                parent: None,
+            })),
+        };
+
+        let buf = JsonSerializer::new().serialize("topic", event.clone()).unwrap();
+        let mut cur = Cursor::new(buf);
+
+        assert_eq!(JsonDeserializer::<Event>::new().deserialize("topic", &mut cur).unwrap(), event);
+    }
 }
@@ -18,6 +18,16 @@ mod json;
 #[cfg(feature = "json")]
 pub use self::json::{JsonDeserializer, JsonSerializer};
 
+#[cfg(feature = "avro")]
+mod avro;
+#[cfg(feature = "avro")]
+mod registry;
+#[cfg(feature = "avro")]
+pub use self::avro::{AvroDeserializer, AvroSerializer};
+#[cfg(feature = "avro")]
+pub use self::registry::{FetchSchema, RegisterSchema, SchemaId, SchemaRegistry};
+
+use std::fmt;
 use std::mem;
 use std::result::Result;
 
@@ -47,7 +57,7 @@ pub trait Deserializer {
     /// The type of value that this deserializer will deserialize.
     type Item;
     /// The type of error that this deserializer will return if it fails.
-    type Error;
+    type Error: fmt::Display;
 
     /// Deserizalize data of topic from the given buffer
     fn deserialize_to<B: Buf>(&self, topic_name: &str, buf: &mut B, data: &mut Self::Item) -> Result<(), Self::Error>;
@@ -60,3 +70,25 @@ pub trait Deserializer {
         Ok(data)
     }
 }
+
+/// The error of deserializing the key or value of a consumed record.
+///
+/// The record will still be yielded, with the failed key or value as `None`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DeserializeError {
+    /// The reason of failing to deserialize the key
+    pub key: Option<String>,
+    /// The reason of failing to deserialize the value
+    pub value: Option<String>,
+}
+
+impl fmt::Display for DeserializeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.key, &self.value) {
+            (&Some(ref key), &Some(ref value)) => write!(f, "fail to deserialize key, {}, and value, {}", key, value),
+            (&Some(ref key), &None) => write!(f, "fail to deserialize key, {}", key),
+            (&None, &Some(ref value)) => write!(f, "fail to deserialize value, {}", value),
+            (&None, &None) => write!(f, "deserialized"),
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use futures::{future, Future, Stream};
+use hyper::client::HttpConnector;
+use hyper::{Client, Method, Request, Uri};
+use serde_json;
+use tokio_core::reactor::Handle;
+
+use avro_rs::Schema;
+
+use client::{Runtime, StaticBoxFuture, ToStaticBoxFuture};
+use errors::{Error, ErrorKind, Result};
+
+/// The id of a schema registered in the Schema Registry.
+pub type SchemaId = u32;
+
+/// The future of registering a schema.
+pub type RegisterSchema = StaticBoxFuture<SchemaId>;
+
+/// The future of fetching a schema.
+pub type FetchSchema = StaticBoxFuture<Rc<Schema>>;
+
+const SCHEMA_REGISTRY_CONTENT_TYPE: &str = "application/vnd.schemaregistry.v1+json";
+
+#[derive(Serialize)]
+struct SchemaRequest<'a> {
+    schema: &'a str,
+}
+
+#[derive(Deserialize)]
+struct SchemaResponse {
+    schema: String,
+}
+
+#[derive(Deserialize)]
+struct RegisterResponse {
+    id: SchemaId,
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    message: String,
+}
+
+/// An asynchronous client of the Confluent Schema Registry.
+///
+/// The fetched and registered schemas are cached, and shared between the clones.
+#[derive(Clone)]
+pub struct SchemaRegistry {
+    inner: Rc<Inner>,
+}
+
+struct Inner {
+    url: String,
+    runtime: Runtime,
+    client: Client<HttpConnector>,
+    schemas: RefCell<HashMap<SchemaId, Rc<Schema>>>,
+    fetching: RefCell<HashSet<SchemaId>>,
+    subjects: RefCell<HashMap<(String, String), SchemaId>>,
+}
+
+impl SchemaRegistry {
+    /// Construct a `SchemaRegistry` with the base URL, e.g. `http://localhost:8081`
+    pub fn new<S: Into<String>>(url: S, handle: &Handle) -> Self {
+        SchemaRegistry::new_with_runtime(url, handle, Runtime::from(handle.clone()))
+    }
+
+    /// Construct a `SchemaRegistry` which prefetches the schemas in the background with the runtime.
+    ///
+    /// The `Handle` is still used to send the HTTP requests.
+    pub fn new_with_runtime<S: Into<String>>(url: S, handle: &Handle, runtime: Runtime) -> Self {
+        SchemaRegistry {
+            inner: Rc::new(Inner {
+                url: url.into().trim_right_matches('/').to_owned(),
+                runtime,
+                client: Client::new(handle),
+                schemas: RefCell::new(HashMap::new()),
+                fetching: RefCell::new(HashSet::new()),
+                subjects: RefCell::new(HashMap::new()),
+            }),
+        }
+    }
+
+    /// Get the cached schema of the id.
+    pub fn schema(&self, schema_id: SchemaId) -> Option<Rc<Schema>> {
+        self.inner.schemas.borrow().get(&schema_id).cloned()
+    }
+
+    /// Register the schema under the subject, return the id of schema.
+    pub fn register(&self, subject: &str, schema: &str) -> RegisterSchema {
+        let key = (subject.to_owned(), schema.to_owned());
+
+        if let Some(&schema_id) = self.inner.subjects.borrow().get(&key) {
+            return future::ok(schema_id).static_boxed();
+        }
+
+        let parsed = match Schema::parse_str(schema) {
+            Ok(schema) => Rc::new(schema),
+            Err(err) => return future::err(ErrorKind::AvroError(err.to_string()).into()).static_boxed(),
+        };
+        let body = match serde_json::to_vec(&SchemaRequest { schema }) {
+            Ok(body) => body,
+            Err(err) => return future::err(err.into()).static_boxed(),
+        };
+        let inner = self.inner.clone();
+
+        self.send(Method::Post, &format!("/subjects/{}/versions", subject), Some(body))
+            .and_then(|body| serde_json::from_slice::<RegisterResponse>(&body).map_err(Error::from))
+            .map(move |res| {
+                debug!("registered schema #{} under the `{}` subject", res.id, key.0);
+
+                inner.schemas.borrow_mut().insert(res.id, parsed);
+                inner.subjects.borrow_mut().insert(key, res.id);
+
+                res.id
+            })
+            .static_boxed()
+    }
+
+    /// Fetch the schema of the id, or get it from the cache.
+    pub fn fetch(&self, schema_id: SchemaId) -> FetchSchema {
+        if let Some(schema) = self.schema(schema_id) {
+            return future::ok(schema).static_boxed();
+        }
+
+        let inner = self.inner.clone();
+
+        self.send(Method::Get, &format!("/schemas/ids/{}", schema_id), None)
+            .and_then(|body| serde_json::from_slice::<SchemaResponse>(&body).map_err(Error::from))
+            .and_then(move |res| {
+                let schema = Schema::parse_str(&res.schema).map_err(|err| ErrorKind::AvroError(err.to_string()))?;
+                let schema = Rc::new(schema);
+
+                inner.schemas.borrow_mut().insert(schema_id, schema.clone());
+
+                Ok(schema)
+            })
+            .static_boxed()
+    }
+
+    /// Fetch the schema of the id in the background, if it is not cached or being fetched.
+    pub fn prefetch(&self, schema_id: SchemaId) {
+        if self.schema(schema_id).is_some() || !self.inner.fetching.borrow_mut().insert(schema_id) {
+            return;
+        }
+
+        let inner = self.inner.clone();
+
+        self.inner.runtime.spawn(self.fetch(schema_id).then(move |res| {
+            inner.fetching.borrow_mut().remove(&schema_id);
+
+            if let Err(err) = res {
+                warn!("fail to fetch schema #{}, {}", schema_id, err);
+            }
+
+            Ok(())
+        }));
+    }
+
+    fn send(&self, method: Method, path: &str, body: Option<Vec<u8>>) -> StaticBoxFuture<Vec<u8>> {
+        let uri = match format!("{}{}", self.inner.url, path).parse::<Uri>() {
+            Ok(uri) => uri,
+            Err(err) => return future::err(err.into()).static_boxed(),
+        };
+
+        trace!("sending {} request to schema registry {}", method, uri);
+
+        let mut request = Request::new(method, uri);
+
+        request.headers_mut().set_raw("Accept", SCHEMA_REGISTRY_CONTENT_TYPE);
+
+        if let Some(body) = body {
+            request.headers_mut().set_raw("Content-Type", SCHEMA_REGISTRY_CONTENT_TYPE);
+            request.set_body(body);
+        }
+
+        self.inner
+            .client
+            .request(request)
+            .and_then(|res| {
+                let status = res.status();
+
+                res.body().concat2().map(move |body| (status, body.to_vec()))
+            })
+            .from_err()
+            .and_then(|(status, body)| -> Result<Vec<u8>> {
+                if status.is_success() {
+                    Ok(body)
+                } else {
+                    let reason = serde_json::from_slice::<ErrorResponse>(&body)
+                        .map(|res| res.message)
+                        .unwrap_or_else(|_| String::from_utf8_lossy(&body).into_owned());
+
+                    bail!(ErrorKind::SchemaRegistryError(status.as_u16(), reason))
+                }
+            })
+            .static_boxed()
+    }
+
+    #[cfg(test)]
+    pub fn cache(&self, schema_id: SchemaId, schema: Schema) {
+        self.inner.schemas.borrow_mut().insert(schema_id, Rc::new(schema));
+    }
+}
@@ -0,0 +1,4 @@
+mod tailer;
+
+pub use self::tailer::{FileTailer, LoadCheckpoints, TailFiles, DEFAULT_TAIL_MAX_LINES,
+                       DEFAULT_TAIL_POLL_INTERVAL_MILLIS};
@@ -0,0 +1,431 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::str;
+use std::time::Duration;
+
+use futures::future::{self, Either, Loop};
+use futures::{Future, Stream};
+
+use client::{Client, Cluster, KafkaClient, PartitionData, StaticBoxFuture, ToStaticBoxFuture};
+use consumer::{LeaderSelector, DEFAULT_FETCH_MAX_BYTES, DEFAULT_PARTITION_FETCH_BYTES};
+use errors::ErrorKind;
+use network::TopicPartition;
+use producer::{Producer, ProducerRecord};
+use protocol::{FetchOffset, KafkaCode, Offset, PartitionId};
+
+/// The default interval to poll the tailed files.
+pub const DEFAULT_TAIL_POLL_INTERVAL_MILLIS: u64 = 1000;
+
+/// The default maximum number of lines read from a file per poll.
+pub const DEFAULT_TAIL_MAX_LINES: usize = 1000;
+
+/// Tail the files and produce the appended lines as records, at least once.
+///
+/// Each line is sent with the file path as the key.
+/// The byte offset of every file is checkpointed to a compacted topic after its lines were acknowledged,
+/// so a restarted tailer will resume from the last checkpoint, and might resend some lines.
+///
+/// A directory is expanded to the regular files in it, and a file shrinking below its checkpoint
+/// (e.g. rotated by `copytruncate`) will be tailed from the beginning again.
+#[derive(Clone)]
+pub struct FileTailer<'a, P> {
+    client: KafkaClient<'a>,
+    producer: P,
+    topic_name: String,
+    checkpoint_topic: String,
+    paths: Vec<PathBuf>,
+    poll_interval: Duration,
+    max_lines: usize,
+    checkpoints: Rc<RefCell<HashMap<String, u64>>>,
+}
+
+/// The future of loading the checkpoints.
+pub type LoadCheckpoints = StaticBoxFuture<usize>;
+
+/// The future of tailing the files.
+pub type TailFiles = StaticBoxFuture;
+
+impl<'a, P> FileTailer<'a, P>
+where
+    P: Producer<'a, Key = String, Value = String> + Clone,
+    Self: 'static,
+{
+    /// Construct a `FileTailer` producing lines to the topic and checkpoints to the compacted topic.
+    pub fn new<S, I, T>(client: KafkaClient<'a>, producer: P, topic_name: S, checkpoint_topic: S, paths: I) -> Self
+    where
+        S: Into<String>,
+        I: IntoIterator<Item = T>,
+        T: AsRef<Path>,
+    {
+        FileTailer {
+            client,
+            producer,
+            topic_name: topic_name.into(),
+            checkpoint_topic: checkpoint_topic.into(),
+            paths: paths.into_iter().map(|path| path.as_ref().to_owned()).collect(),
+            poll_interval: Duration::from_millis(DEFAULT_TAIL_POLL_INTERVAL_MILLIS),
+            max_lines: DEFAULT_TAIL_MAX_LINES,
+            checkpoints: Rc::new(RefCell::new(HashMap::new())),
+        }
+    }
+
+    /// Sets the interval to poll the tailed files.
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Sets the maximum number of lines read from a file per poll.
+    pub fn with_max_lines(mut self, max_lines: usize) -> Self {
+        self.max_lines = max_lines;
+        self
+    }
+
+    /// The acknowledged byte offset of the tailed files.
+    pub fn checkpoints(&self) -> HashMap<String, u64> {
+        self.checkpoints.borrow().clone()
+    }
+
+    /// Load the last checkpoints from the compacted topic, return the number of the checkpointed files.
+    pub fn load_checkpoints(&self) -> LoadCheckpoints {
+        let client = self.client.clone();
+        let checkpoint_topic = self.checkpoint_topic.clone();
+        let checkpoints = self.checkpoints.clone();
+
+        let positions = {
+            let client = self.client.clone();
+            let checkpoint_topic = self.checkpoint_topic.clone();
+
+            self.client.metadata().and_then(move |metadata| {
+                let partitions: Vec<TopicPartition<'a>> = metadata
+                    .partitions_for_topic(&checkpoint_topic)
+                    .map(|partitions| {
+                        partitions
+                            .into_iter()
+                            .map(|tp| topic_partition!(tp.topic_name.into_owned(), tp.partition_id))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+
+                let earliest = client.list_offsets(
+                    partitions
+                        .clone()
+                        .into_iter()
+                        .map(|tp| (tp, FetchOffset::Earliest)),
+                );
+                let latest = client.list_offsets(partitions.into_iter().map(|tp| (tp, FetchOffset::Latest)));
+
+                earliest.join(latest).map(move |(earliest, latest)| {
+                    let mut positions = HashMap::new();
+
+                    for partition in earliest.get(&checkpoint_topic).into_iter().flat_map(|v| v) {
+                        let end = latest
+                            .get(&checkpoint_topic)
+                            .and_then(|partitions| {
+                                partitions
+                                    .iter()
+                                    .find(|latest| latest.partition_id == partition.partition_id)
+                            })
+                            .and_then(|latest| latest.latest());
+
+                        if let (Some(offset), Some(end)) = (partition.earliest(), end) {
+                            positions.insert(partition.partition_id, (offset, end));
+                        }
+                    }
+
+                    positions
+                })
+            })
+        };
+
+        positions
+            .and_then(move |positions: HashMap<PartitionId, (Offset, Offset)>| {
+                future::loop_fn((positions, HashMap::new()), move |(mut positions, mut loaded)| {
+                    let partitions = positions
+                        .iter()
+                        .filter(|&(_, &(offset, end))| offset < end)
+                        .map(|(&partition_id, &(offset, _))| {
+                            (
+                                topic_partition!(checkpoint_topic.clone(), partition_id),
+                                PartitionData {
+                                    offset,
+                                    max_bytes: Some(DEFAULT_PARTITION_FETCH_BYTES as i32),
+                                },
+                            )
+                        })
+                        .collect::<Vec<_>>();
+
+                    if partitions.is_empty() {
+                        return Either::A(future::ok(Loop::Break(loaded)));
+                    }
+
+                    let checkpoint_topic = checkpoint_topic.clone();
+
+                    Either::B(
+                        client
+                            .fetch_records(
+                                Duration::default(),
+                                0,
+                                DEFAULT_FETCH_MAX_BYTES,
+                                Rc::new(LeaderSelector),
+                                partitions,
+                            )
+                            .and_then(move |(_, mut records)| {
+                                for partition in records.remove(&checkpoint_topic).unwrap_or_default() {
+                                    if partition.error_code != KafkaCode::None {
+                                        bail!(ErrorKind::KafkaError(partition.error_code))
+                                    }
+
+                                    let position = match positions.get_mut(&partition.partition_id) {
+                                        Some(position) => position,
+                                        None => continue,
+                                    };
+
+                                    if partition.messages.is_empty() {
+                                        position.0 = position.1;
+                                    }
+
+                                    for message in partition.messages {
+                                        if message.offset < position.0 {
+                                            continue;
+                                        }
+
+                                        position.0 = message.offset + 1;
+
+                                        let path = match message.key {
+                                            Some(ref key) => String::from_utf8_lossy(key).into_owned(),
+                                            None => continue,
+                                        };
+
+                                        match message.value {
+                                            Some(ref value) => {
+                                                match str::from_utf8(value).ok().and_then(|s| s.parse().ok()) {
+                                                    Some(offset) => {
+                                                        loaded.insert(path, offset);
+                                                    }
+                                                    None => warn!("skip the malformed checkpoint of {}", path),
+                                                }
+                                            }
+                                            None => {
+                                                loaded.remove(&path);
+                                            }
+                                        }
+                                    }
+                                }
+
+                                Ok(Loop::Continue((positions, loaded)))
+                            }),
+                    )
+                })
+            })
+            .map(move |loaded: HashMap<String, u64>| {
+                let n = loaded.len();
+
+                debug!("loaded {} checkpoints of the tailed files", n);
+
+                checkpoints.borrow_mut().extend(loaded);
+
+                n
+            })
+            .static_boxed()
+    }
+
+    /// Produce the lines appended to the files since the last checkpoints,
+    /// and checkpoint the offsets once all the lines were acknowledged.
+    pub fn tail(&self) -> TailFiles {
+        let files = match self.files() {
+            Ok(files) => files,
+            Err(err) => return future::err(err.into()).static_boxed(),
+        };
+
+        let mut producer = self.producer.clone();
+        let mut sends = Vec::new();
+        let mut offsets = Vec::new();
+
+        for path in files {
+            let key = path.to_string_lossy().into_owned();
+            let offset = self.checkpoints.borrow().get(&key).cloned().unwrap_or_default();
+
+            let (lines, next) = match read_lines(&path, offset, self.max_lines) {
+                Ok(read) => read,
+                Err(err) => {
+                    warn!("fail to read the tailed file {:?}, {}", path, err);
+
+                    continue;
+                }
+            };
+
+            if next == offset {
+                continue;
+            }
+
+            trace!("read {} lines from {:?} @ {}", lines.len(), path, offset);
+
+            for line in lines {
+                sends.push(producer.send(ProducerRecord::from_key_value(&self.topic_name, key.clone(), line)));
+            }
+
+            offsets.push((key, next));
+        }
+
+        if offsets.is_empty() {
+            return future::ok(()).static_boxed();
+        }
+
+        let checkpoint_topic = self.checkpoint_topic.clone();
+        let checkpoints = self.checkpoints.clone();
+        let flush = producer.flush();
+
+        future::join_all(sends)
+            .join(flush)
+            .and_then(move |_| {
+                let sends = offsets
+                    .iter()
+                    .map(|&(ref key, offset)| {
+                        producer.send(ProducerRecord::from_key_value(
+                            &checkpoint_topic,
+                            key.clone(),
+                            offset.to_string(),
+                        ))
+                    })
+                    .collect::<Vec<_>>();
+                let flush = producer.flush();
+
+                future::join_all(sends).join(flush).map(move |_| {
+                    checkpoints.borrow_mut().extend(offsets);
+                })
+            })
+            .static_boxed()
+    }
+
+    /// Load the checkpoints, and tail the files periodically.
+    pub fn run(self) -> TailFiles {
+        debug!(
+            "tail {} paths to the `{}` topic per {:?}",
+            self.paths.len(),
+            self.topic_name,
+            self.poll_interval
+        );
+
+        let tailer = self.clone();
+        let runtime = self.client.runtime().clone();
+        let poll_interval = self.poll_interval;
+
+        self.load_checkpoints()
+            .and_then(move |_| {
+                runtime
+                    .interval(Duration::default(), poll_interval)
+                    .for_each(move |_| tailer.tail())
+            })
+            .static_boxed()
+    }
+
+    fn files(&self) -> io::Result<Vec<PathBuf>> {
+        let mut files = Vec::new();
+
+        for path in &self.paths {
+            if path.is_dir() {
+                let mut entries = Vec::new();
+
+                for entry in fs::read_dir(path)? {
+                    let path = entry?.path();
+
+                    if path.is_file() {
+                        entries.push(path);
+                    }
+                }
+
+                entries.sort();
+                files.append(&mut entries);
+            } else if path.exists() {
+                files.push(path.clone());
+            }
+        }
+
+        Ok(files)
+    }
+}
+
+/// Read the complete lines from the offset, return the lines and the offset after them.
+///
+/// The partial line at the end of file is left for the next read.
+fn read_lines(path: &Path, offset: u64, max_lines: usize) -> io::Result<(Vec<String>, u64)> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+
+    let mut offset = if len < offset {
+        info!("tailed file {:?} was truncated, tail it from the beginning", path);
+
+        0
+    } else {
+        offset
+    };
+
+    file.seek(SeekFrom::Start(offset))?;
+
+    let mut reader = BufReader::new(file);
+    let mut lines = Vec::new();
+    let mut buf = Vec::new();
+
+    while lines.len() < max_lines {
+        buf.clear();
+
+        let read = reader.read_until(b'\n', &mut buf)?;
+
+        if read == 0 || buf.last() != Some(&b'\n') {
+            break;
+        }
+
+        offset += read as u64;
+
+        buf.pop();
+
+        if buf.last() == Some(&b'\r') {
+            buf.pop();
+        }
+
+        lines.push(String::from_utf8_lossy(&buf).into_owned());
+    }
+
+    Ok((lines, offset))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::io::Write;
+    use std::process;
+
+    use super::*;
+
+    #[test]
+    fn test_read_lines() {
+        let path = env::temp_dir().join(format!("tokio-kafka-tail-{}", process::id()));
+        let mut file = File::create(&path).unwrap();
+
+        file.write_all(b"first\nsecond\r\nthi").unwrap();
+
+        assert_eq!(
+            read_lines(&path, 0, 10).unwrap(),
+            (vec!["first".to_owned(), "second".to_owned()], 14)
+        );
+        assert_eq!(read_lines(&path, 0, 1).unwrap(), (vec!["first".to_owned()], 6));
+        assert_eq!(read_lines(&path, 14, 10).unwrap(), (vec![], 14));
+
+        file.write_all(b"rd\n").unwrap();
+
+        assert_eq!(read_lines(&path, 14, 10).unwrap(), (vec!["third".to_owned()], 20));
+
+        file.set_len(0).unwrap();
+        file.seek(SeekFrom::Start(0)).unwrap();
+        file.write_all(b"rotated\n").unwrap();
+
+        assert_eq!(read_lines(&path, 20, 10).unwrap(), (vec!["rotated".to_owned()], 8));
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,294 @@
+//! The blocking API surface for the applications without an event loop, e.g. the command line tools.
+//!
+//! The producers and consumers are driven on the dedicated threads with their own event loops,
+//! and the blocking methods park the calling thread until the results arrived or the timeout elapsed.
+
+use std::hash::Hash;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use futures::executor::{self, Notify, Spawn};
+use futures::sync::mpsc;
+use futures::{Async, Future, Sink, Stream};
+use tokio_core::reactor::{Core, Handle};
+
+use errors::{Error, ErrorKind, Result};
+use producer::{Producer, ProducerRecord, RecordMetadata, SharedProducer};
+
+/// The records buffered between the thread of a spawned stream and its consumer.
+const STREAM_BUFFER_SIZE: usize = 16;
+
+/// Unpark the waiting thread when the polled future or stream is ready to make progress.
+struct ThreadNotify {
+    thread: thread::Thread,
+}
+
+impl Notify for ThreadNotify {
+    fn notify(&self, _id: usize) {
+        self.thread.unpark()
+    }
+}
+
+/// Run a stream on a dedicated thread with its own event loop, and return the receiver of its items.
+///
+/// The stream is built with the handle of the thread's event loop, and the thread exits
+/// once the stream was finished or the returned receiver has been dropped.
+pub fn spawn_stream_thread<F, S>(build: F) -> mpsc::Receiver<Result<S::Item>>
+where
+    F: FnOnce(Handle) -> Result<S> + Send + 'static,
+    S: Stream<Error = Error> + 'static,
+    S::Item: Send + 'static,
+{
+    let (tx, rx) = mpsc::channel(STREAM_BUFFER_SIZE);
+
+    let spawned = thread::Builder::new()
+        .name("kafka-stream".to_owned())
+        .spawn(move || {
+            let built = Core::new()
+                .map_err(Error::from)
+                .and_then(|core| build(core.handle()).map(|stream| (core, stream)));
+
+            match built {
+                Ok((mut core, stream)) => {
+                    let forward = stream
+                        .then(|res| Ok::<_, ()>(res))
+                        .forward(tx.sink_map_err(|_| debug!("the spawned stream was dropped")));
+
+                    drop(core.run(forward));
+                }
+                Err(err) => {
+                    drop(tx.send(Err(err)).wait());
+                }
+            }
+        });
+
+    if let Err(err) = spawned {
+        warn!("fail to spawn the stream thread, {}", err);
+    }
+
+    rx
+}
+
+/// Wait for the future in the current thread, and fail with `TimeoutError` once the deadline passed.
+fn wait_until<F>(future: F, deadline: Instant) -> Result<F::Item>
+where
+    F: Future<Error = Error>,
+{
+    let notify = Arc::new(ThreadNotify {
+        thread: thread::current(),
+    });
+    let mut future = executor::spawn(future);
+
+    loop {
+        if let Async::Ready(item) = future.poll_future_notify(&notify, 0)? {
+            return Ok(item);
+        }
+
+        let now = Instant::now();
+
+        if now >= deadline {
+            bail!(ErrorKind::TimeoutError("wait for the blocking call".to_owned()))
+        }
+
+        thread::park_timeout(deadline - now);
+    }
+}
+
+/// A blocking handle of a producer, for the applications without an event loop.
+///
+/// The producer is built and driven on a dedicated thread, see `SharedProducer` for details.
+pub struct BlockingProducer<K, V>
+where
+    K: Hash,
+{
+    producer: SharedProducer<K, V>,
+}
+
+impl<K, V> Clone for BlockingProducer<K, V>
+where
+    K: Hash,
+{
+    fn clone(&self) -> Self {
+        BlockingProducer {
+            producer: self.producer.clone(),
+        }
+    }
+}
+
+impl<K, V> From<SharedProducer<K, V>> for BlockingProducer<K, V>
+where
+    K: Hash,
+{
+    fn from(producer: SharedProducer<K, V>) -> Self {
+        BlockingProducer { producer }
+    }
+}
+
+impl<K, V> BlockingProducer<K, V>
+where
+    K: Hash + Send + 'static,
+    V: Send + 'static,
+{
+    /// Spawn a thread to run the producer, which is built with the handle of the thread's event loop.
+    pub fn spawn<F, P>(build: F) -> Result<Self>
+    where
+        F: FnOnce(Handle) -> Result<P> + Send + 'static,
+        P: Producer<'static, Key = K, Value = V> + 'static,
+    {
+        SharedProducer::spawn(build).map(BlockingProducer::from)
+    }
+
+    /// Send the record, and block until its metadata was returned or the timeout elapsed.
+    ///
+    /// The record may still be delivered after the timeout, since it has been handed over to the producer.
+    pub fn send_sync(&self, record: ProducerRecord<K, V>, timeout: Duration) -> Result<RecordMetadata> {
+        wait_until(self.producer.send(record), Instant::now() + timeout)
+    }
+
+    /// Send the records in a batch, and block until every record was acknowledged or the timeout elapsed.
+    ///
+    /// The metadata of the records are returned in the same order as the records.
+    pub fn send_all_sync(&self, records: Vec<ProducerRecord<K, V>>, timeout: Duration) -> Result<Vec<RecordMetadata>> {
+        wait_until(self.producer.send_all(records), Instant::now() + timeout)
+    }
+
+    /// Flush the accumulated records of the producer, and block until they were sent or the timeout elapsed.
+    pub fn flush_sync(&self, timeout: Duration) -> Result<()> {
+        wait_until(self.producer.flush(), Instant::now() + timeout)
+    }
+
+    /// Returns the shared handle of the producer, e.g. to send the records asynchronously.
+    pub fn shared(&self) -> &SharedProducer<K, V> {
+        &self.producer
+    }
+}
+
+/// A blocking consumer of a stream, e.g. the records of a `KafkaConsumer`,
+/// for the applications without an event loop.
+///
+/// The stream is built and driven on a dedicated thread, the thread exits
+/// once the stream was finished or the `BlockingConsumer` has been dropped.
+pub struct BlockingConsumer<T> {
+    records: Spawn<mpsc::Receiver<Result<T>>>,
+    pending: Option<Error>,
+    finished: bool,
+}
+
+impl<T> BlockingConsumer<T>
+where
+    T: Send + 'static,
+{
+    /// Spawn a thread to run the stream, which is built with the handle of the thread's event loop.
+    pub fn spawn<F, S>(build: F) -> Self
+    where
+        F: FnOnce(Handle) -> Result<S> + Send + 'static,
+        S: Stream<Item = T, Error = Error> + 'static,
+    {
+        BlockingConsumer {
+            records: executor::spawn(spawn_stream_thread(build)),
+            pending: None,
+            finished: false,
+        }
+    }
+
+    /// Block until some records arrived or the timeout elapsed, and return all the received records.
+    ///
+    /// Returns an empty `Vec` if no record arrived before the timeout,
+    /// and fails with `Canceled` once the stream was finished and all its records have been returned.
+    pub fn poll(&mut self, timeout: Duration) -> Result<Vec<T>> {
+        if let Some(err) = self.pending.take() {
+            return Err(err);
+        }
+
+        let deadline = Instant::now() + timeout;
+        let notify = Arc::new(ThreadNotify {
+            thread: thread::current(),
+        });
+        let mut records = Vec::new();
+
+        while !self.finished {
+            match self.records.poll_stream_notify(&notify, 0) {
+                Ok(Async::Ready(Some(Ok(record)))) => records.push(record),
+                Ok(Async::Ready(Some(Err(err)))) => {
+                    if records.is_empty() {
+                        return Err(err);
+                    }
+
+                    self.pending = Some(err);
+
+                    break;
+                }
+                Ok(Async::Ready(None)) | Err(()) => self.finished = true,
+                Ok(Async::NotReady) => {
+                    let now = Instant::now();
+
+                    if !records.is_empty() || now >= deadline {
+                        break;
+                    }
+
+                    thread::park_timeout(deadline - now);
+                }
+            }
+        }
+
+        if records.is_empty() && self.finished {
+            bail!(ErrorKind::Canceled("stream thread exited"))
+        }
+
+        Ok(records)
+    }
+
+    /// Returns `true` if the stream was finished and all its records have been returned.
+    pub fn is_finished(&self) -> bool {
+        self.finished && self.pending.is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{future, stream};
+
+    use super::*;
+
+    #[test]
+    fn test_wait_until() {
+        let deadline = Instant::now() + Duration::from_secs(1);
+
+        assert_eq!(wait_until(future::ok::<_, Error>(123), deadline).unwrap(), 123);
+
+        let err = wait_until(future::empty::<(), Error>(), Instant::now() + Duration::from_millis(10)).unwrap_err();
+
+        match *err.kind() {
+            ErrorKind::TimeoutError(_) => {}
+            _ => panic!("unexpected error: {}", err),
+        }
+    }
+
+    #[test]
+    fn test_blocking_consumer() {
+        let mut consumer = BlockingConsumer::spawn(|_| Ok(stream::iter_ok::<_, Error>(vec![1, 2, 3])));
+        let mut records = Vec::new();
+
+        while !consumer.is_finished() {
+            match consumer.poll(Duration::from_secs(1)) {
+                Ok(batch) => records.extend(batch),
+                Err(_) => break,
+            }
+        }
+
+        assert_eq!(records, vec![1, 2, 3]);
+        assert!(consumer.poll(Duration::from_millis(10)).is_err());
+
+        let mut failed = BlockingConsumer::spawn(|_| -> Result<stream::Empty<u32, Error>> {
+            bail!(ErrorKind::ConfigError("missed handle"))
+        });
+
+        let err = failed.poll(Duration::from_secs(1)).unwrap_err();
+
+        match *err.kind() {
+            ErrorKind::ConfigError(_) => {}
+            _ => panic!("unexpected error: {}", err),
+        }
+    }
+}
@@ -57,7 +57,7 @@ where
 
     let mut core = Core::new()?;
 
-    let client = KafkaClient::new(config, core.handle());
+    let client = KafkaClient::new(config, core.handle())?;
 
     let work = op(client).map_err(|err| err.into());
 